@@ -85,7 +85,7 @@ impl Database {
             .map_err(|err| into_napi_error_with_message("SQLITE_CANTOPEN".to_owned(), err))?;
 
         let db_file = Arc::new(DatabaseFile::new(file));
-        let db = turso_core::Database::open(io.clone(), &path, db_file, false, false)
+        let db = turso_core::Database::open(io.clone(), &path, db_file, false, true)
             .map_err(into_napi_sqlite_error)?;
         let conn = db.connect().map_err(into_napi_sqlite_error)?;
 
@@ -698,6 +698,10 @@ impl turso_core::DatabaseStorage for DatabaseFile {
     fn size(&self) -> turso_core::Result<u64> {
         self.file.size()
     }
+
+    fn truncate(&self, len: u64) -> turso_core::Result<()> {
+        self.file.truncate(len)
+    }
 }
 
 #[inline]
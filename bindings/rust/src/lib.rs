@@ -83,30 +83,18 @@ impl Builder {
         match self.path.as_str() {
             ":memory:" => {
                 let io: Arc<dyn turso_core::IO> = Arc::new(turso_core::MemoryIO::new());
-                let db = turso_core::Database::open_file(
-                    io,
-                    self.path.as_str(),
-                    false,
-                    indexes_enabled(),
-                )?;
+                let db = turso_core::Database::open_file(io, self.path.as_str(), false, true)?;
                 Ok(Database { inner: db })
             }
             path => {
                 let io: Arc<dyn turso_core::IO> = Arc::new(turso_core::PlatformIO::new()?);
-                let db = turso_core::Database::open_file(io, path, false, indexes_enabled())?;
+                let db = turso_core::Database::open_file(io, path, false, true)?;
                 Ok(Database { inner: db })
             }
         }
     }
 }
 
-fn indexes_enabled() -> bool {
-    #[cfg(feature = "experimental_indexes")]
-    return true;
-    #[cfg(not(feature = "experimental_indexes"))]
-    return false;
-}
-
 /// A database.
 ///
 /// The `Database` object points to a database and allows you to connect to it
@@ -22,7 +22,7 @@ impl Database {
         let io: Arc<dyn turso_core::IO> = Arc::new(PlatformIO { vfs: VFS::new() });
         let file = io.open_file(path, OpenFlags::Create, false).unwrap();
         let db_file = Arc::new(DatabaseFile::new(file));
-        let db = turso_core::Database::open(io, path, db_file, false, false).unwrap();
+        let db = turso_core::Database::open(io, path, db_file, false, true).unwrap();
         let conn = db.connect().unwrap();
         Database { db, conn }
     }
@@ -256,6 +256,11 @@ impl turso_core::File for File {
     fn size(&self) -> Result<u64> {
         Ok(self.vfs.size(self.fd))
     }
+
+    fn truncate(&self, _len: u64) -> Result<()> {
+        // TODO
+        Ok(())
+    }
 }
 
 pub struct PlatformIO {
@@ -375,6 +380,10 @@ impl turso_core::DatabaseStorage for DatabaseFile {
     fn size(&self) -> Result<u64> {
         self.file.size()
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        self.file.truncate(len)
+    }
 }
 
 #[cfg(all(feature = "web", not(feature = "nodejs")))]
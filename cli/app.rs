@@ -27,7 +27,9 @@ use std::{
 use tracing::level_filters::LevelFilter;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use turso_core::{Connection, Database, LimboError, OpenFlags, Statement, StepResult, Value};
+use turso_core::{
+    Connection, Database, LimboError, OpenFlags, QueryMode, Statement, StepResult, Value,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "Turso")]
@@ -60,7 +62,11 @@ pub struct Opts {
     pub readonly: bool,
     #[clap(long, help = "Enable experimental MVCC feature")]
     pub experimental_mvcc: bool,
-    #[clap(long, help = "Enable experimental indexing feature")]
+    #[clap(
+        long,
+        hide = true,
+        help = "Deprecated and ignored: indexes are always enabled"
+    )]
     pub experimental_indexes: bool,
     #[clap(short = 't', long, help = "specify output file for log traces")]
     pub tracing_output: Option<String>,
@@ -369,7 +375,7 @@ impl Limbo {
             };
             (
                 io.clone(),
-                Database::open_file(io.clone(), path, false, false)?,
+                Database::open_file(io.clone(), path, false, true)?,
             )
         };
         self.io = io;
@@ -446,6 +452,9 @@ impl Limbo {
             .unwrap_or(false)
         {
             match self.conn.query(input) {
+                Ok(Some(mut stmt)) if stmt.query_mode() == QueryMode::ExplainQueryPlan => {
+                    let _ = self.print_query_plan(&mut stmt);
+                }
                 Ok(Some(stmt)) => {
                     let _ = self.writeln(stmt.explain().as_bytes());
                 }
@@ -692,6 +701,9 @@ impl Limbo {
         mut statistics: Option<&mut QueryStatistics>,
     ) -> anyhow::Result<()> {
         match output {
+            Ok(Some(ref mut rows)) if rows.query_mode() == QueryMode::ExplainQueryPlan => {
+                self.print_query_plan(rows)?;
+            }
             Ok(Some(ref mut rows)) => match self.opts.output_mode {
                 OutputMode::List => {
                     let mut headers_printed = false;
@@ -878,6 +890,51 @@ impl Limbo {
         Ok(())
     }
 
+    /// Prints the rows of an EXPLAIN QUERY PLAN as a tree, like the SQLite shell does.
+    fn print_query_plan(&mut self, rows: &mut Statement) -> anyhow::Result<()> {
+        let mut plan = Vec::new();
+        loop {
+            match rows.step()? {
+                StepResult::Row => {
+                    let row = rows.row().unwrap();
+                    plan.push((
+                        row.get::<i64>(0)?,
+                        row.get::<i64>(1)?,
+                        row.get::<String>(3)?,
+                    ));
+                }
+                StepResult::IO => rows.run_once()?,
+                StepResult::Interrupt | StepResult::Done | StepResult::Busy => break,
+            }
+        }
+        if !plan.is_empty() {
+            let _ = self.writeln("QUERY PLAN");
+            self.print_query_plan_children(&plan, 0, &mut String::new());
+        }
+        Ok(())
+    }
+
+    fn print_query_plan_children(
+        &mut self,
+        plan: &[(i64, i64, String)],
+        parent: i64,
+        prefix: &mut String,
+    ) {
+        let children = plan
+            .iter()
+            .filter(|(_, row_parent, _)| *row_parent == parent)
+            .collect::<Vec<_>>();
+        for (i, (id, _, detail)) in children.iter().enumerate() {
+            let is_last = i == children.len() - 1;
+            let branch = if is_last { "`--" } else { "|--" };
+            let _ = self.writeln(format!("{prefix}{branch}{detail}"));
+            let prefix_len = prefix.len();
+            prefix.push_str(if is_last { "   " } else { "|  " });
+            self.print_query_plan_children(plan, *id, prefix);
+            prefix.truncate(prefix_len);
+        }
+    }
+
     pub fn init_tracing(&mut self) -> Result<WorkerGuard, std::io::Error> {
         let ((non_blocking, guard), should_emit_ansi) =
             if let Some(file) = &self.opts.tracing_output {
@@ -19,7 +19,7 @@ fn bench_open(criterion: &mut Criterion) {
     if !std::fs::exists("../testing/schema_5k.db").unwrap() {
         #[allow(clippy::arc_with_non_send_sync)]
         let io = Arc::new(PlatformIO::new().unwrap());
-        let db = Database::open_file(io.clone(), "../testing/schema_5k.db", false, false).unwrap();
+        let db = Database::open_file(io.clone(), "../testing/schema_5k.db", false, true).unwrap();
         let conn = db.connect().unwrap();
 
         for i in 0..5000 {
@@ -36,7 +36,7 @@ fn bench_open(criterion: &mut Criterion) {
             #[allow(clippy::arc_with_non_send_sync)]
             let io = Arc::new(PlatformIO::new().unwrap());
             let db =
-                Database::open_file(io.clone(), "../testing/schema_5k.db", false, false).unwrap();
+                Database::open_file(io.clone(), "../testing/schema_5k.db", false, true).unwrap();
             black_box(db.connect().unwrap());
         });
     });
@@ -59,7 +59,7 @@ fn bench_prepare_query(criterion: &mut Criterion) {
 
     #[allow(clippy::arc_with_non_send_sync)]
     let io = Arc::new(PlatformIO::new().unwrap());
-    let db = Database::open_file(io.clone(), "../testing/testing.db", false, false).unwrap();
+    let db = Database::open_file(io.clone(), "../testing/testing.db", false, true).unwrap();
     let limbo_conn = db.connect().unwrap();
 
     let queries = [
@@ -106,7 +106,7 @@ fn bench_execute_select_rows(criterion: &mut Criterion) {
 
     #[allow(clippy::arc_with_non_send_sync)]
     let io = Arc::new(PlatformIO::new().unwrap());
-    let db = Database::open_file(io.clone(), "../testing/testing.db", false, false).unwrap();
+    let db = Database::open_file(io.clone(), "../testing/testing.db", false, true).unwrap();
     let limbo_conn = db.connect().unwrap();
 
     let mut group = criterion.benchmark_group("Execute `SELECT * FROM users LIMIT ?`");
@@ -174,7 +174,7 @@ fn bench_execute_select_1(criterion: &mut Criterion) {
 
     #[allow(clippy::arc_with_non_send_sync)]
     let io = Arc::new(PlatformIO::new().unwrap());
-    let db = Database::open_file(io.clone(), "../testing/testing.db", false, false).unwrap();
+    let db = Database::open_file(io.clone(), "../testing/testing.db", false, true).unwrap();
     let limbo_conn = db.connect().unwrap();
 
     let mut group = criterion.benchmark_group("Execute `SELECT 1`");
@@ -226,7 +226,7 @@ fn bench_execute_select_count(criterion: &mut Criterion) {
 
     #[allow(clippy::arc_with_non_send_sync)]
     let io = Arc::new(PlatformIO::new().unwrap());
-    let db = Database::open_file(io.clone(), "../testing/testing.db", false, false).unwrap();
+    let db = Database::open_file(io.clone(), "../testing/testing.db", false, true).unwrap();
     let limbo_conn = db.connect().unwrap();
 
     let mut group = criterion.benchmark_group("Execute `SELECT count() FROM users`");
@@ -284,7 +284,7 @@ fn bench_insert_rows(criterion: &mut Criterion) {
 
         #[allow(clippy::arc_with_non_send_sync)]
         let io = Arc::new(PlatformIO::new().unwrap());
-        let db = Database::open_file(io.clone(), db_path.to_str().unwrap(), false, false).unwrap();
+        let db = Database::open_file(io.clone(), db_path.to_str().unwrap(), false, true).unwrap();
         let limbo_conn = db.connect().unwrap();
 
         let mut stmt = limbo_conn
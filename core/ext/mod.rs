@@ -107,7 +107,7 @@ impl Database {
                 }
             },
         };
-        let db = Self::open_file(io.clone(), path, false, false)?;
+        let db = Self::open_file(io.clone(), path, false, true)?;
         Ok((io, db))
     }
 }
@@ -151,7 +151,7 @@ impl Connection {
             .insert(name.to_string(), vmodule.into());
         if kind == VTabKind::TableValuedFunction {
             if let Ok(vtab) = VirtualTable::function(name, &self.syms.borrow()) {
-                self.schema.borrow_mut().add_virtual_table(vtab);
+                self.schema_mut().add_virtual_table(vtab);
             } else {
                 return ResultCode::Error;
             }
@@ -267,6 +267,72 @@ impl AggFunc {
     }
 }
 
+/// Built-in functions that can only be used as window functions, e.g. `rank() OVER (ORDER BY x)`.
+/// Aggregate functions can be used as window functions too, see [AggFunc].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFunc {
+    RowNumber,
+    Rank,
+    DenseRank,
+    PercentRank,
+    CumeDist,
+    Ntile,
+    Lag,
+    Lead,
+    FirstValue,
+    LastValue,
+    NthValue,
+}
+
+impl WindowFunc {
+    /// Resolves a window function by name. Returns `Ok(None)` if the name is not a window function.
+    pub fn resolve_function(name: &str, arg_count: usize) -> Result<Option<Self>, LimboError> {
+        let normalized_name = crate::util::normalize_ident(name);
+        let (func, valid_arg_count) = match normalized_name.as_str() {
+            "row_number" => (Self::RowNumber, arg_count == 0),
+            "rank" => (Self::Rank, arg_count == 0),
+            "dense_rank" => (Self::DenseRank, arg_count == 0),
+            "percent_rank" => (Self::PercentRank, arg_count == 0),
+            "cume_dist" => (Self::CumeDist, arg_count == 0),
+            "ntile" => (Self::Ntile, arg_count == 1),
+            "lag" => (Self::Lag, (1..=3).contains(&arg_count)),
+            "lead" => (Self::Lead, (1..=3).contains(&arg_count)),
+            "first_value" => (Self::FirstValue, arg_count == 1),
+            "last_value" => (Self::LastValue, arg_count == 1),
+            "nth_value" => (Self::NthValue, arg_count == 2),
+            _ => return Ok(None),
+        };
+        if !valid_arg_count {
+            crate::bail_parse_error!("wrong number of arguments to function {}()", name)
+        }
+        Ok(Some(func))
+    }
+
+    /// Whether the result of the function depends on the frame of the window.
+    pub fn uses_frame(&self) -> bool {
+        matches!(self, Self::FirstValue | Self::LastValue | Self::NthValue)
+    }
+}
+
+impl Display for WindowFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Self::RowNumber => "row_number",
+            Self::Rank => "rank",
+            Self::DenseRank => "dense_rank",
+            Self::PercentRank => "percent_rank",
+            Self::CumeDist => "cume_dist",
+            Self::Ntile => "ntile",
+            Self::Lag => "lag",
+            Self::Lead => "lead",
+            Self::FirstValue => "first_value",
+            Self::LastValue => "last_value",
+            Self::NthValue => "nth_value",
+        };
+        write!(f, "{str}")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ScalarFunc {
     Cast,
@@ -128,6 +128,11 @@ impl File for GenericFile {
         let file = self.file.borrow();
         Ok(file.metadata().unwrap().len())
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let file = self.file.borrow();
+        file.set_len(len).map_err(LimboError::IOError)
+    }
 }
 
 impl Drop for GenericFile {
@@ -176,6 +176,24 @@ impl File for MemoryFile {
     fn size(&self) -> Result<u64> {
         Ok(self.size.get() as u64)
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        let len = len as usize;
+        if len >= self.size.get() {
+            return Ok(());
+        }
+        let pages = unsafe { &mut *self.pages.get() };
+        // Drop the pages that start past the new end and zero the tail of the last one,
+        // so that growing the file again reads zeroes like a real file would.
+        pages.retain(|page_no, _| page_no * PAGE_SIZE < len);
+        if len % PAGE_SIZE != 0 {
+            if let Some(page) = pages.get_mut(&(len / PAGE_SIZE)) {
+                page[len % PAGE_SIZE..].fill(0);
+            }
+        }
+        self.size.set(len);
+        Ok(())
+    }
 }
 
 impl Drop for MemoryFile {
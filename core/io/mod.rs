@@ -23,6 +23,7 @@ pub trait File: Send + Sync {
     ) -> Result<Arc<Completion>>;
     fn sync(&self, c: Completion) -> Result<Arc<Completion>>;
     fn size(&self) -> Result<u64>;
+    fn truncate(&self, len: u64) -> Result<()>;
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
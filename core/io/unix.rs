@@ -424,6 +424,13 @@ impl File for UnixFile<'_> {
         let file = self.file.borrow();
         Ok(file.metadata()?.len())
     }
+
+    #[instrument(err, skip_all, level = Level::INFO)]
+    fn truncate(&self, len: u64) -> Result<()> {
+        let file = self.file.borrow();
+        file.set_len(len)?;
+        Ok(())
+    }
 }
 
 impl Drop for UnixFile<'_> {
@@ -166,6 +166,11 @@ impl File for VfsFileImpl {
             Ok(result as u64)
         }
     }
+
+    fn truncate(&self, _len: u64) -> Result<()> {
+        // The extension VFS API has no way to shrink a file, so it keeps its size.
+        Ok(())
+    }
 }
 
 impl Drop for VfsMod {
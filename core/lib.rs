@@ -43,9 +43,8 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 use crate::storage::sqlite3_ondisk::is_valid_page_size;
 use crate::storage::{header_accessor, wal::DummyWAL};
-use crate::translate::optimizer::optimize_plan;
 use crate::translate::pragma::TURSO_CDC_DEFAULT_TABLE_NAME;
-use crate::util::{OpenMode, OpenOptions};
+use crate::util::{normalize_ident, OpenMode, OpenOptions};
 use crate::vtab::VirtualTable;
 use core::str;
 pub use error::LimboError;
@@ -65,12 +64,11 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 use std::{
     borrow::Cow,
-    cell::{Cell, RefCell, UnsafeCell},
+    cell::{Cell, OnceCell, RefCell, RefMut, UnsafeCell},
     collections::HashMap,
     fmt::Display,
     io::Write,
     num::NonZero,
-    ops::Deref,
     rc::Rc,
     sync::Arc,
 };
@@ -86,14 +84,12 @@ pub use storage::{
     wal::{CheckpointMode, CheckpointResult, Wal, WalFile, WalFileShared},
 };
 use tracing::{instrument, Level};
-use translate::select::prepare_select_plan;
 use turso_sqlite3_parser::{ast, ast::Cmd, lexer::sql::Parser};
 use types::IOResult;
 pub use types::RefValue;
 pub use types::Value;
 use util::parse_schema_rows;
-use vdbe::builder::QueryMode;
-use vdbe::builder::TableRefIdCounter;
+pub use vdbe::builder::QueryMode;
 
 pub type Result<T, E = LimboError> = std::result::Result<T, E>;
 
@@ -104,6 +100,31 @@ enum TransactionState {
     None,
 }
 
+/// A savepoint opened with `SAVEPOINT name`.
+/// The pages it journals are tracked by the [Pager], at the same depth in its savepoint stack.
+#[derive(Debug)]
+struct Savepoint {
+    name: String,
+    /// Schema when the savepoint was opened, restored by `ROLLBACK TO`.
+    schema: Schema,
+    /// Whether the savepoint started the transaction, in which case releasing it commits.
+    starts_transaction: bool,
+    /// Deferred foreign key violations when the savepoint was opened, restored by `ROLLBACK TO`.
+    deferred_fk_violations: i64,
+}
+
+/// A database attached with `ATTACH`.
+/// Statements access it through an internal connection, which holds its pager, schema and
+/// transaction state.
+struct AttachedDatabase {
+    name: String,
+    path: String,
+    conn: Arc<Connection>,
+}
+
+/// Maximum number of databases that can be attached to a connection.
+const MAX_ATTACHED: usize = 10;
+
 pub(crate) type MvStore = mvcc::MvStore<mvcc::LocalClock>;
 
 pub(crate) type MvCursor = mvcc::cursor::ScanCursor<mvcc::LocalClock>;
@@ -168,6 +189,9 @@ impl Database {
         )
     }
 
+    /// `_enable_indexes` is ignored, as indexes are always enabled. It is deprecated and kept
+    /// so the constructors keep their signatures for embedders; it will be removed in a future
+    /// release.
     #[allow(clippy::arc_with_non_send_sync)]
     pub fn open_with_flags(
         io: Arc<dyn IO>,
@@ -175,7 +199,7 @@ impl Database {
         db_file: Arc<dyn DatabaseStorage>,
         flags: OpenFlags,
         enable_mvcc: bool,
-        enable_indexes: bool,
+        _enable_indexes: bool,
     ) -> Result<Arc<Database>> {
         let wal_path = format!("{path}-wal");
         let maybe_shared_wal = WalFileShared::open_shared_if_exists(&io, wal_path.as_str())?;
@@ -197,7 +221,7 @@ impl Database {
         };
 
         let shared_page_cache = Arc::new(RwLock::new(DumbLruPageCache::default()));
-        let schema = Arc::new(RwLock::new(Schema::new(enable_indexes)));
+        let schema = Arc::new(RwLock::new(Schema::new()));
         let db = Database {
             mv_store,
             path: path.to_string(),
@@ -247,6 +271,7 @@ impl Database {
             _db: self.clone(),
             pager: RefCell::new(Rc::new(pager)),
             schema: RefCell::new(self.schema.read().clone()),
+            translation_schema: RefCell::new(None),
             auto_commit: Cell::new(true),
             mv_transactions: RefCell::new(Vec::new()),
             transaction_state: Cell::new(TransactionState::None),
@@ -260,6 +285,14 @@ impl Database {
             readonly: Cell::new(false),
             wal_checkpoint_disabled: Cell::new(false),
             capture_data_changes: RefCell::new(CaptureDataChangesMode::Off),
+            recursive_triggers: Cell::new(false),
+            automatic_index: Cell::new(true),
+            ignore_check_constraints: Cell::new(false),
+            foreign_keys: Cell::new(false),
+            deferred_fk_violations: Cell::new(0),
+            savepoints: RefCell::new(Vec::new()),
+            temp: RefCell::new(None),
+            attached: RefCell::new(Vec::new()),
             closed: Cell::new(false),
         });
 
@@ -493,6 +526,9 @@ pub struct Connection {
     _db: Arc<Database>,
     pager: RefCell<Rc<Pager>>,
     schema: RefCell<Schema>,
+    /// The schema statements are translated with, see [Connection::translation_schema]. Dropped
+    /// whenever [Connection::schema] changes.
+    translation_schema: RefCell<Option<Rc<Schema>>>,
     /// Whether to automatically commit transaction
     auto_commit: Cell<bool>,
     mv_transactions: RefCell<Vec<crate::mvcc::database::TxID>>,
@@ -509,6 +545,23 @@ pub struct Connection {
     readonly: Cell<bool>,
     wal_checkpoint_disabled: Cell<bool>,
     capture_data_changes: RefCell<CaptureDataChangesMode>,
+    /// Whether triggers may fire other triggers (including themselves) recursively
+    recursive_triggers: Cell<bool>,
+    /// Whether queries may build automatic indexes on the tables they join
+    automatic_index: Cell<bool>,
+    /// Whether CHECK constraints are not enforced
+    ignore_check_constraints: Cell<bool>,
+    /// Whether foreign key constraints are enforced
+    foreign_keys: Cell<bool>,
+    /// Number of violations of deferred foreign key constraints in the current transaction,
+    /// checked at COMMIT.
+    deferred_fk_violations: Cell<i64>,
+    /// Open savepoints, outermost first.
+    savepoints: RefCell<Vec<Savepoint>>,
+    /// Internal connection of the in-memory temp database, opened the first time it is used.
+    temp: RefCell<Option<Arc<Connection>>>,
+    /// Attached databases, in attach order.
+    attached: RefCell<Vec<AttachedDatabase>>,
     closed: Cell<bool>,
 }
 
@@ -536,22 +589,23 @@ impl Connection {
             .trim();
         self.maybe_update_schema();
         let pager = self.pager.borrow().clone();
-        match cmd {
-            Cmd::Stmt(stmt) => {
-                let program = Rc::new(translate::translate(
-                    self.schema.borrow().deref(),
-                    stmt,
-                    pager.clone(),
-                    self.clone(),
-                    &syms,
-                    QueryMode::Normal,
-                    input,
-                )?);
-                Ok(Statement::new(program, self._db.mv_store.clone(), pager))
-            }
+        let (stmt, query_mode) = match cmd {
+            Cmd::Stmt(stmt) => (stmt, QueryMode::Normal),
             Cmd::Explain(_stmt) => todo!(),
-            Cmd::ExplainQueryPlan(_stmt) => todo!(),
-        }
+            Cmd::ExplainQueryPlan(stmt) => (stmt, QueryMode::ExplainQueryPlan),
+        };
+        let program = Rc::new(self.with_translation_schema(|schema| {
+            translate::translate(
+                schema,
+                stmt,
+                pager.clone(),
+                self.clone(),
+                &syms,
+                query_mode,
+                input,
+            )
+        })?);
+        Ok(Statement::new(program, self._db.mv_store.clone(), pager))
     }
 
     #[instrument(skip_all, level = Level::INFO)]
@@ -585,38 +639,22 @@ impl Connection {
         let syms = self.syms.borrow();
         let pager = self.pager.borrow().clone();
         match cmd {
-            Cmd::Stmt(ref stmt) | Cmd::Explain(ref stmt) => {
-                let program = translate::translate(
-                    self.schema.borrow().deref(),
-                    stmt.clone(),
-                    pager.clone(),
-                    self.clone(),
-                    &syms,
-                    cmd.into(),
-                    input,
-                )?;
+            Cmd::Stmt(ref stmt) | Cmd::Explain(ref stmt) | Cmd::ExplainQueryPlan(ref stmt) => {
+                let stmt = stmt.clone();
+                let program = self.with_translation_schema(|schema| {
+                    translate::translate(
+                        schema,
+                        stmt,
+                        pager.clone(),
+                        self.clone(),
+                        &syms,
+                        cmd.into(),
+                        input,
+                    )
+                })?;
                 let stmt = Statement::new(program.into(), self._db.mv_store.clone(), pager);
                 Ok(Some(stmt))
             }
-            Cmd::ExplainQueryPlan(stmt) => {
-                let mut table_ref_counter = TableRefIdCounter::new();
-                match stmt {
-                    ast::Stmt::Select(select) => {
-                        let mut plan = prepare_select_plan(
-                            self.schema.borrow().deref(),
-                            *select,
-                            &syms,
-                            &[],
-                            &mut table_ref_counter,
-                            translate::plan::QueryDestination::ResultRows,
-                        )?;
-                        optimize_plan(&mut plan, self.schema.borrow().deref())?;
-                        let _ = std::io::stdout().write_all(plan.to_string().as_bytes());
-                    }
-                    _ => todo!(),
-                }
-                Ok(None)
-            }
         }
     }
 
@@ -633,38 +671,55 @@ impl Connection {
         }
         let sql = sql.as_ref();
         let mut parser = Parser::new(sql.as_bytes());
+        let mut byte_offset_start = 0;
         while let Some(cmd) = parser.next()? {
             let syms = self.syms.borrow();
             let pager = self.pager.borrow().clone();
             let byte_offset_end = parser.offset();
-            let input = str::from_utf8(&sql.as_bytes()[..byte_offset_end])
-                .unwrap()
-                .trim();
+            let input = sql[byte_offset_start..byte_offset_end].trim();
+            byte_offset_start = byte_offset_end;
             self.maybe_update_schema();
             match cmd {
                 Cmd::Explain(stmt) => {
-                    let program = translate::translate(
-                        self.schema.borrow().deref(),
-                        stmt,
-                        pager,
-                        self.clone(),
-                        &syms,
-                        QueryMode::Explain,
-                        input,
-                    )?;
+                    let program = self.with_translation_schema(|schema| {
+                        translate::translate(
+                            schema,
+                            stmt,
+                            pager,
+                            self.clone(),
+                            &syms,
+                            QueryMode::Explain,
+                            input,
+                        )
+                    })?;
                     let _ = std::io::stdout().write_all(program.explain().as_bytes());
                 }
-                Cmd::ExplainQueryPlan(_stmt) => todo!(),
+                Cmd::ExplainQueryPlan(stmt) => {
+                    // Like the rows of any other statement, the rows of the plan are discarded
+                    self.with_translation_schema(|schema| {
+                        translate::translate(
+                            schema,
+                            stmt,
+                            pager,
+                            self.clone(),
+                            &syms,
+                            QueryMode::ExplainQueryPlan,
+                            input,
+                        )
+                    })?;
+                }
                 Cmd::Stmt(stmt) => {
-                    let program = translate::translate(
-                        self.schema.borrow().deref(),
-                        stmt,
-                        pager.clone(),
-                        self.clone(),
-                        &syms,
-                        QueryMode::Normal,
-                        input,
-                    )?;
+                    let program = self.with_translation_schema(|schema| {
+                        translate::translate(
+                            schema,
+                            stmt,
+                            pager.clone(),
+                            self.clone(),
+                            &syms,
+                            QueryMode::Normal,
+                            input,
+                        )
+                    })?;
 
                     let mut state =
                         vdbe::ProgramState::new(program.max_registers, program.cursor_ref.len());
@@ -732,7 +787,7 @@ impl Connection {
             && current_schema_version < self._db.schema.read().schema_version
         {
             let new_schema = self._db.schema.read();
-            self.schema.replace(new_schema.clone());
+            *self.schema_mut() = new_schema.clone();
         }
     }
 
@@ -784,6 +839,13 @@ impl Connection {
             return Ok(());
         }
         self.closed.set(true);
+        // The temp database goes away with the connection
+        if let Some(temp) = self.temp.take() {
+            temp.close()?;
+        }
+        for attached in self.attached.borrow().iter() {
+            attached.conn.close()?;
+        }
         self.pager
             .borrow()
             .checkpoint_shutdown(self.wal_checkpoint_disabled.get())
@@ -822,12 +884,351 @@ impl Connection {
         self.cache_size.set(size);
     }
 
+    /// Returns the memory that `PRAGMA cache_size` allows for caching pages, in bytes. It is
+    /// also the memory a hash join may use before its hash table spills to a temporary file.
+    pub fn get_cache_size_in_bytes(&self) -> usize {
+        let cache_size = self.get_cache_size();
+        if cache_size < 0 {
+            cache_size.unsigned_abs() as usize * 1024
+        } else {
+            cache_size as usize * self.get_page_size() as usize
+        }
+    }
+
     pub fn get_capture_data_changes(&self) -> std::cell::Ref<'_, CaptureDataChangesMode> {
         self.capture_data_changes.borrow()
     }
     pub fn set_capture_data_changes(&self, opts: CaptureDataChangesMode) {
         self.capture_data_changes.replace(opts);
     }
+    /// Attaches the database file at `path` under the schema name `name`.
+    pub(crate) fn attach_database(&self, path: &str, name: &str) -> Result<()> {
+        if !self.auto_commit.get() {
+            return Err(LimboError::TxError(
+                "cannot ATTACH database within transaction".to_string(),
+            ));
+        }
+        let name = normalize_ident(name);
+        let mut attached = self.attached.borrow_mut();
+        if name == "main" || name == "temp" || attached.iter().any(|attached| attached.name == name)
+        {
+            return Err(LimboError::InvalidArgument(format!(
+                "database {name} is already in use"
+            )));
+        }
+        if attached.len() >= MAX_ATTACHED {
+            return Err(LimboError::InvalidArgument(format!(
+                "too many attached databases - max {MAX_ATTACHED}"
+            )));
+        }
+        let conn = self.open_database(path)?.connect()?;
+        attached.push(AttachedDatabase {
+            name,
+            path: path.to_string(),
+            conn,
+        });
+        Ok(())
+    }
+
+    /// Opens the database at `path` alongside this one, for ATTACH and VACUUM INTO.
+    /// An empty path or `:memory:` opens a new in-memory database.
+    fn open_database(&self, path: &str) -> Result<Arc<Database>> {
+        if path.is_empty() || path == util::MEMORY_PATH {
+            let io: Arc<dyn IO> = self._db.io.get_memory_io();
+            let file = io.open_file(path, OpenFlags::Create, false)?;
+            let db_file = Arc::new(storage::database::FileMemoryStorage::new(file));
+            return Database::open(io, path, db_file, false, true);
+        }
+        Self::open_database_file(path)
+    }
+
+    /// Files get their own platform IO, as the IO of this database may be an in-memory one.
+    #[cfg(feature = "fs")]
+    fn open_database_file(path: &str) -> Result<Arc<Database>> {
+        let (_, db) = Database::open_new(path, None::<&str>, OpenFlags::default(), true, false)?;
+        Ok(db)
+    }
+
+    #[cfg(not(feature = "fs"))]
+    fn open_database_file(path: &str) -> Result<Arc<Database>> {
+        Err(LimboError::InvalidArgument(format!(
+            "unable to open database: {path}"
+        )))
+    }
+
+    /// Detaches the database attached under the schema name `name`.
+    pub(crate) fn detach_database(&self, name: &str) -> Result<()> {
+        let name = normalize_ident(name);
+        let mut attached = self.attached.borrow_mut();
+        let Some(position) = attached.iter().position(|attached| attached.name == name) else {
+            return Err(LimboError::InvalidArgument(format!(
+                "no such database: {name}"
+            )));
+        };
+        if !self.auto_commit.get()
+            || attached[position].conn.transaction_state.get() != TransactionState::None
+        {
+            return Err(LimboError::TxError(format!("database {name} is locked")));
+        }
+        let detached = attached.remove(position);
+        detached.conn.close()
+    }
+
+    /// Rebuilds the main database from scratch, leaving no free pages behind.
+    ///
+    /// The database is copied into a temporary in-memory database, whose pages then replace the
+    /// ones of the main database in a single write transaction. The database file shrinks once
+    /// the WAL is checkpointed, which is done right away.
+    pub(crate) fn vacuum(self: &Arc<Connection>) -> Result<()> {
+        self.check_vacuum_allowed()?;
+        let pager = self.pager.borrow().clone();
+        storage::vacuum::begin_tx(&pager, true)?;
+        self.transaction_state.set(TransactionState::Write {
+            schema_did_change: true,
+        });
+        let wal_checkpoint_disabled = self.wal_checkpoint_disabled.get();
+        if let Err(err) = self.vacuum_in_write_tx(&pager) {
+            pager.rollback(true, self)?;
+            pager.end_tx(true, true, self, wal_checkpoint_disabled)?;
+            self.transaction_state.set(TransactionState::None);
+            return Err(err);
+        }
+        let result = storage::vacuum::run_until_done(&pager, || {
+            pager.end_tx(false, true, self, wal_checkpoint_disabled)
+        });
+        self.transaction_state.set(TransactionState::None);
+        result?;
+        self.checkpoint()?;
+        Ok(())
+    }
+
+    fn vacuum_in_write_tx(self: &Arc<Connection>, pager: &Rc<Pager>) -> Result<()> {
+        let temp = self.open_database(util::MEMORY_PATH)?.connect()?;
+        self.vacuum_copy_into(pager, &temp)?;
+        let temp_pager = temp.pager.borrow().clone();
+        storage::vacuum::begin_tx(&temp_pager, false)?;
+        let result = storage::vacuum::overwrite_database(&temp_pager, pager);
+        temp_pager.end_read_tx()?;
+        result?;
+
+        // Root pages moved, load the schema back from the rebuilt sqlite_schema.
+        let mut schema = Schema::new();
+        schema.schema_version = header_accessor::get_schema_cookie(pager)?;
+        let previous_auto_commit = self.auto_commit.replace(false);
+        let result = self
+            .prepare("SELECT * FROM sqlite_schema")
+            .and_then(|stmt| parse_schema_rows(Some(stmt), &mut schema, &self.syms.borrow(), None));
+        self.auto_commit.set(previous_auto_commit);
+        result?;
+        *self.schema_mut() = schema;
+        Ok(())
+    }
+
+    /// Writes a vacuumed copy of the main database to the new database file at `path`.
+    pub(crate) fn vacuum_into(self: &Arc<Connection>, path: &str) -> Result<()> {
+        self.check_vacuum_allowed()?;
+        let db = self.open_database(path)?;
+        if db.db_file.size()? > 0 {
+            return Err(LimboError::InvalidArgument(
+                "output file already exists".to_string(),
+            ));
+        }
+        let dest = db.connect()?;
+        let pager = self.pager.borrow().clone();
+        storage::vacuum::begin_tx(&pager, false)?;
+        let result = self.vacuum_copy_into(&pager, &dest);
+        pager.end_read_tx()?;
+        result?;
+        dest.close()
+    }
+
+    /// Copies the main database into the empty database of `dest` and commits it.
+    /// `pager` is the pager of this connection, already in a transaction.
+    fn vacuum_copy_into(&self, pager: &Rc<Pager>, dest: &Arc<Connection>) -> Result<()> {
+        dest.reset_page_size(header_accessor::get_page_size(pager)?)?;
+        let dest_pager = dest.pager.borrow().clone();
+        storage::vacuum::begin_tx(&dest_pager, true)?;
+        let schema = self.schema.borrow().clone();
+        if let Err(err) = storage::vacuum::copy_database(&schema, pager, &dest_pager) {
+            dest_pager.rollback(false, dest)?;
+            dest_pager.end_tx(true, false, dest, false)?;
+            return Err(err);
+        }
+        storage::vacuum::run_until_done(&dest_pager, || {
+            dest_pager.end_tx(false, false, dest, false)
+        })?;
+        Ok(())
+    }
+
+    fn check_vacuum_allowed(&self) -> Result<()> {
+        if self.closed.get() {
+            return Err(LimboError::InternalError("Connection closed".to_string()));
+        }
+        if !self.auto_commit.get() {
+            return Err(LimboError::TxError(
+                "cannot VACUUM from within a transaction".to_string(),
+            ));
+        }
+        if self._db.mv_store.is_some() {
+            return Err(LimboError::ParseError(
+                "VACUUM is not supported with MVCC".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the connection used to access the database at index `db`: this connection for
+    /// `main`, the internal connection of the database for `temp` and attached databases.
+    pub(crate) fn database_connection(
+        self: &Arc<Connection>,
+        db: usize,
+    ) -> Result<Arc<Connection>> {
+        match db {
+            0 => Ok(self.clone()),
+            1 => self.temp_connection(),
+            _ => self
+                .attached
+                .borrow()
+                .get(db - 2)
+                .map(|attached| attached.conn.clone())
+                .ok_or_else(|| LimboError::InternalError(format!("no database at index {db}"))),
+        }
+    }
+
+    /// Returns the internal connection of the temp database, opening the database if this is
+    /// the first time it is used.
+    fn temp_connection(&self) -> Result<Arc<Connection>> {
+        if let Some(temp) = self.temp.borrow().as_ref() {
+            return Ok(temp.clone());
+        }
+        let temp = self.open_database(util::MEMORY_PATH)?.connect()?;
+        self.temp.replace(Some(temp.clone()));
+        Ok(temp)
+    }
+
+    /// Returns the internal connections of the temp database, if it was opened, and of the
+    /// attached databases, in attach order.
+    pub(crate) fn internal_connections(&self) -> Vec<Arc<Connection>> {
+        self.temp
+            .borrow()
+            .iter()
+            .cloned()
+            .chain(
+                self.attached
+                    .borrow()
+                    .iter()
+                    .map(|attached| attached.conn.clone()),
+            )
+            .collect()
+    }
+
+    /// Returns the name and file of each database of the connection, with its index. The file
+    /// of an in-memory database is empty.
+    pub(crate) fn database_list(&self) -> Vec<(usize, String, String)> {
+        let file = |path: &str| match path {
+            ":memory:" => String::new(),
+            path => path.to_string(),
+        };
+        let mut databases = vec![(0, "main".to_string(), file(&self._db.path))];
+        if self.temp.borrow().is_some() {
+            databases.push((1, "temp".to_string(), String::new()));
+        }
+        databases.extend(
+            self.attached
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(i, attached)| (i + 2, attached.name.clone(), file(&attached.path))),
+        );
+        databases
+    }
+
+    /// Mutably borrows the schema of this connection, dropping the translation schema built from
+    /// it.
+    pub(crate) fn schema_mut(&self) -> RefMut<'_, Schema> {
+        self.translation_schema.take();
+        self.schema.borrow_mut()
+    }
+
+    /// Runs `f` with the schema statements are translated with: the schema of `main`, along with
+    /// the schemas of the temp and attached databases if there are any.
+    fn with_translation_schema<T>(&self, f: impl FnOnce(&Schema) -> T) -> T {
+        if self.temp.borrow().is_none() && self.attached.borrow().is_empty() {
+            return f(&self.schema.borrow());
+        }
+        let schema = self.translation_schema();
+        f(&schema)
+    }
+
+    /// Returns a copy of the schema of this connection holding the schemas of its temp and
+    /// attached databases. The copy is kept until one of these schemas changes.
+    fn translation_schema(&self) -> Rc<Schema> {
+        let temp = self
+            .temp
+            .borrow()
+            .as_ref()
+            .map(|temp| temp.translation_schema());
+        let attached = self
+            .attached
+            .borrow()
+            .iter()
+            .map(|attached| {
+                attached.conn.maybe_update_schema();
+                (attached.name.clone(), attached.conn.translation_schema())
+            })
+            .collect::<Vec<_>>();
+        if let Some(schema) = self.translation_schema.borrow().as_ref() {
+            let same_temp = match (schema.temp.get(), &temp) {
+                (None, None) => true,
+                (Some(cached), Some(temp)) => Rc::ptr_eq(cached, temp),
+                _ => false,
+            };
+            let same_attached = schema.attached.len() == attached.len()
+                && schema.attached.iter().zip(&attached).all(
+                    |((cached_name, cached), (name, attached))| {
+                        cached_name == name && Rc::ptr_eq(cached, attached)
+                    },
+                );
+            if same_temp && same_attached {
+                return schema.clone();
+            }
+        }
+        let schema = Rc::new(Schema {
+            temp: temp.map_or_else(OnceCell::new, OnceCell::from),
+            attached,
+            ..self.schema.borrow().clone()
+        });
+        self.translation_schema.replace(Some(schema.clone()));
+        schema
+    }
+
+    pub fn get_recursive_triggers(&self) -> bool {
+        self.recursive_triggers.get()
+    }
+    pub fn set_recursive_triggers(&self, enabled: bool) {
+        self.recursive_triggers.set(enabled);
+    }
+    pub fn get_automatic_index(&self) -> bool {
+        self.automatic_index.get()
+    }
+    pub fn set_automatic_index(&self, enabled: bool) {
+        self.automatic_index.set(enabled);
+    }
+    pub fn get_ignore_check_constraints(&self) -> bool {
+        self.ignore_check_constraints.get()
+    }
+    pub fn set_ignore_check_constraints(&self, enabled: bool) {
+        self.ignore_check_constraints.set(enabled);
+    }
+    pub fn get_foreign_keys(&self) -> bool {
+        self.foreign_keys.get()
+    }
+    /// Like SQLite, enabling or disabling foreign keys is a no-op within a transaction.
+    pub fn set_foreign_keys(&self, enabled: bool) {
+        if self.auto_commit.get() {
+            self.foreign_keys.set(enabled);
+        }
+    }
     pub fn get_page_size(&self) -> u32 {
         self.page_size.get()
     }
@@ -887,7 +1288,7 @@ impl Connection {
             return Err(LimboError::InternalError("Connection closed".to_string()));
         }
         let rows = self.query("SELECT * FROM sqlite_schema")?;
-        let mut schema = self.schema.borrow_mut();
+        let mut schema = self.schema_mut();
         {
             let syms = self.syms.borrow();
             if let Err(LimboError::ExtensionError(e)) =
@@ -1073,6 +1474,11 @@ impl Statement {
     pub fn explain(&self) -> String {
         self.program.explain()
     }
+
+    /// Whether the statement is an EXPLAIN or EXPLAIN QUERY PLAN statement.
+    pub fn query_mode(&self) -> QueryMode {
+        self.program.query_mode
+    }
 }
 
 pub type Row = vdbe::Row;
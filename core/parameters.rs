@@ -70,6 +70,11 @@ impl Parameters {
             .copied()
     }
 
+    /// Returns the index the next anonymous parameter will get, without allocating it.
+    pub fn peek_next_index(&self) -> NonZero<usize> {
+        self.index
+    }
+
     pub fn next_index(&mut self) -> NonZero<usize> {
         let index = self.index;
         self.index = self.index.checked_add(1).unwrap();
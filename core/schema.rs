@@ -1,42 +1,71 @@
 use crate::result::LimboResult;
 use crate::storage::btree::BTreeCursor;
 use crate::translate::collate::CollationSeq;
-use crate::translate::plan::SelectPlan;
+use crate::translate::plan::{
+    CompoundSubquery, CteDefinition, QueryDestination, RecursiveCte, SelectPlan,
+};
 use crate::types::IOResult;
-use crate::util::{module_args_from_sql, module_name_from_sql, UnparsedFromSqlIndex};
+use crate::util::{
+    module_args_from_sql, module_name_from_sql, UnparsedFromSqlIndex,
+    PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
+};
 use crate::{util::normalize_ident, Result};
 use crate::{LimboError, MvCursor, Pager, RefValue, SymbolTable, VirtualTable};
 use core::fmt;
 use fallible_iterator::FallibleIterator;
-use std::cell::RefCell;
+use std::cell::{OnceCell, RefCell};
 use std::collections::hash_map::Entry;
 use std::collections::{BTreeSet, HashMap};
 use std::rc::Rc;
 use std::sync::Arc;
 use tracing::trace;
-use turso_sqlite3_parser::ast::{self, ColumnDefinition, Expr, Literal, SortOrder, TableOptions};
+use turso_sqlite3_parser::ast::{
+    self, ColumnDefinition, Expr, Literal, RefAct, ResolveType, SortOrder, TableOptions,
+};
 use turso_sqlite3_parser::{
     ast::{Cmd, CreateTableBody, QualifiedName, ResultColumn, Stmt},
-    lexer::sql::Parser,
+    dialect::TokenType,
+    lexer::{
+        sql::{Parser, Tokenizer},
+        Scanner,
+    },
 };
 
 const SCHEMA_TABLE_NAME: &str = "sqlite_schema";
 const SCHEMA_TABLE_NAME_ALT: &str = "sqlite_master";
+/// Names of the schema table of the temp database when not qualified with `temp`.
+const TEMP_SCHEMA_TABLE_NAMES: [&str; 2] = ["sqlite_temp_schema", "sqlite_temp_master"];
 
 #[derive(Debug, Clone)]
 pub struct Schema {
     pub tables: HashMap<String, Arc<Table>>,
     /// table_name to list of indexes for the table
     pub indexes: HashMap<String, Vec<Arc<Index>>>,
-    pub has_indexes: std::collections::HashSet<String>,
-    pub indexes_enabled: bool,
     pub schema_version: u32,
+    /// table_name to list of triggers on the table, in creation order
+    pub triggers: HashMap<String, Vec<Arc<Trigger>>>,
+    pub views: HashMap<String, Arc<View>>,
+    /// table_name to the statistics of the table and its indexes gathered by ANALYZE
+    pub stats: HashMap<String, TableStats>,
+    /// Schema of the temp database.
+    /// Only populated on the schema a statement is translated with, and only once the connection
+    /// opened the temp database. Until then the temp database is empty.
+    pub temp: OnceCell<Rc<Schema>>,
+    /// Schemas of the databases attached to the connection, in attach order.
+    /// Only populated on the schema a statement is translated with, the database at position `i`
+    /// has index `i + 2` (0 is `main`, 1 is reserved for `temp`).
+    pub attached: Vec<(String, Rc<Schema>)>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Schema {
-    pub fn new(indexes_enabled: bool) -> Self {
+    pub fn new() -> Self {
         let mut tables: HashMap<String, Arc<Table>> = HashMap::new();
-        let has_indexes = std::collections::HashSet::new();
         let indexes: HashMap<String, Vec<Arc<Index>>> = HashMap::new();
         #[allow(clippy::arc_with_non_send_sync)]
         tables.insert(
@@ -49,12 +78,86 @@ impl Schema {
         Self {
             tables,
             indexes,
-            has_indexes,
-            indexes_enabled,
             schema_version: 0,
+            triggers: HashMap::new(),
+            views: HashMap::new(),
+            stats: HashMap::new(),
+            temp: OnceCell::new(),
+            attached: Vec::new(),
+        }
+    }
+
+    /// Returns the index of the database named `name`.
+    pub fn database_index(&self, name: &str) -> Result<usize> {
+        let name = normalize_ident(name);
+        if name == "main" {
+            return Ok(0);
+        }
+        if name == "temp" {
+            return Ok(1);
+        }
+        match self
+            .attached
+            .iter()
+            .position(|(attached_name, _)| *attached_name == name)
+        {
+            Some(position) => Ok(position + 2),
+            None => crate::bail_parse_error!("unknown database {}", name),
+        }
+    }
+
+    /// Returns the schema of the database at index `database_id`.
+    pub fn database_schema(&self, database_id: usize) -> &Schema {
+        match database_id {
+            0 => self,
+            1 => self.temp.get_or_init(|| Rc::new(Schema::new())),
+            _ => &self.attached[database_id - 2].1,
         }
     }
 
+    /// Returns the name of the database at index `database_id`.
+    pub fn database_name(&self, database_id: usize) -> &str {
+        match database_id {
+            0 => "main",
+            1 => "temp",
+            _ => &self.attached[database_id - 2].0,
+        }
+    }
+
+    /// Returns the index of the database a possibly schema-qualified table or view name refers to.
+    /// Unqualified names are searched in `temp`, then in `main`, then in the attached databases in
+    /// attach order.
+    pub fn locate_database(&self, name: &QualifiedName) -> Result<usize> {
+        if let Some(db_name) = &name.db_name {
+            return self.database_index(&db_name.0);
+        }
+        let table_name = normalize_ident(&name.name.0);
+        if TEMP_SCHEMA_TABLE_NAMES.contains(&table_name.as_str()) {
+            return Ok(1);
+        }
+        let has_object = |schema: &Schema| {
+            schema.get_table(&table_name).is_some() || schema.get_view(&table_name).is_some()
+        };
+        // Every schema has the schema table and the built-in table-valued functions, only the
+        // tables created in the temp database shadow the other databases
+        let has_temp_object = |temp: &Schema| {
+            temp.get_btree_table(&table_name)
+                .is_some_and(|table| table.name != SCHEMA_TABLE_NAME)
+                || temp.get_view(&table_name).is_some()
+        };
+        if self.temp.get().map(Rc::as_ref).is_some_and(has_temp_object) {
+            return Ok(1);
+        }
+        if has_object(self) {
+            return Ok(0);
+        }
+        Ok(self
+            .attached
+            .iter()
+            .position(|(_, schema)| has_object(schema))
+            .map_or(0, |position| position + 2))
+    }
+
     pub fn is_unique_idx_name(&self, name: &str) -> bool {
         !self
             .indexes
@@ -74,7 +177,9 @@ impl Schema {
 
     pub fn get_table(&self, name: &str) -> Option<Arc<Table>> {
         let name = normalize_ident(name);
-        let name = if name.eq_ignore_ascii_case(SCHEMA_TABLE_NAME_ALT) {
+        let name = if name.eq_ignore_ascii_case(SCHEMA_TABLE_NAME_ALT)
+            || TEMP_SCHEMA_TABLE_NAMES.contains(&name.as_str())
+        {
             SCHEMA_TABLE_NAME
         } else {
             &name
@@ -85,6 +190,7 @@ impl Schema {
     pub fn remove_table(&mut self, table_name: &str) {
         let name = normalize_ident(table_name);
         self.tables.remove(&name);
+        self.stats.remove(&name);
     }
 
     pub fn get_btree_table(&self, name: &str) -> Option<Rc<BTreeTable>> {
@@ -96,6 +202,38 @@ impl Schema {
         }
     }
 
+    /// Returns the foreign keys whose parent table is `table_name`, with the tables they are
+    /// declared on, ordered by table name.
+    pub fn get_referencing_foreign_keys(
+        &self,
+        table_name: &str,
+    ) -> Vec<(Rc<BTreeTable>, ForeignKey)> {
+        let table_name = normalize_ident(table_name);
+        let mut children = self
+            .tables
+            .values()
+            .filter_map(|table| table.btree())
+            .filter(|table| {
+                table
+                    .foreign_keys
+                    .iter()
+                    .any(|fk| fk.parent_table == table_name)
+            })
+            .collect::<Vec<_>>();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        children
+            .into_iter()
+            .flat_map(|child| {
+                child
+                    .foreign_keys
+                    .iter()
+                    .filter(|fk| fk.parent_table == table_name)
+                    .map(|fk| (child.clone(), fk.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
     pub fn add_index(&mut self, index: Arc<Index>) {
         let table_name = normalize_ident(&index.table_name);
         self.indexes
@@ -130,18 +268,76 @@ impl Schema {
             .get_mut(&name)
             .expect("Must have the index")
             .retain_mut(|other_idx| other_idx.name != idx.name);
+        if let Some(stats) = self.stats.get_mut(&name) {
+            stats.indexes.remove(&normalize_ident(&idx.name));
+        }
+    }
+
+    /// Records a row of `sqlite_stat1`. Rows whose `stat` does not start with a number are ignored,
+    /// like SQLite does, as are the flags that may follow the numbers.
+    pub fn add_stat1_row(&mut self, tbl: &str, idx: Option<&str>, stat: &str) {
+        let values: Vec<u64> = stat
+            .split_ascii_whitespace()
+            .map_while(|value| value.parse().ok())
+            .collect();
+        if values.is_empty() {
+            return;
+        }
+        let stats = self.stats.entry(normalize_ident(tbl)).or_default();
+        match idx {
+            Some(idx) => {
+                stats.indexes.insert(normalize_ident(idx), values);
+            }
+            None => stats.row_count = Some(values[0]),
+        }
+    }
+
+    pub fn add_trigger(&mut self, trigger: Arc<Trigger>) {
+        let table_name = normalize_ident(&trigger.table_name);
+        self.triggers.entry(table_name).or_default().push(trigger);
+    }
+
+    pub fn get_triggers(&self, table_name: &str) -> &[Arc<Trigger>] {
+        let name = normalize_ident(table_name);
+        self.triggers
+            .get(&name)
+            .map_or_else(|| &[] as &[Arc<Trigger>], |v| v.as_slice())
+    }
+
+    pub fn get_trigger(&self, trigger_name: &str) -> Option<&Arc<Trigger>> {
+        let name = normalize_ident(trigger_name);
+        self.triggers
+            .values()
+            .flat_map(|triggers| triggers.iter())
+            .find(|trigger| trigger.name == name)
+    }
+
+    pub fn remove_trigger(&mut self, trigger_name: &str) {
+        let name = normalize_ident(trigger_name);
+        for triggers in self.triggers.values_mut() {
+            triggers.retain(|trigger| trigger.name != name);
+        }
+        self.triggers.retain(|_, triggers| !triggers.is_empty());
     }
 
-    pub fn table_has_indexes(&self, table_name: &str) -> bool {
-        self.has_indexes.contains(table_name)
+    pub fn remove_triggers_for_table(&mut self, table_name: &str) {
+        let name = normalize_ident(table_name);
+        self.triggers.remove(&name);
     }
 
-    pub fn table_set_has_index(&mut self, table_name: &str) {
-        self.has_indexes.insert(table_name.to_string());
+    pub fn add_view(&mut self, view: Arc<View>) {
+        let name = normalize_ident(&view.name);
+        self.views.insert(name, view);
     }
 
-    pub fn indexes_enabled(&self) -> bool {
-        self.indexes_enabled
+    pub fn get_view(&self, name: &str) -> Option<Arc<View>> {
+        let name = normalize_ident(name);
+        self.views.get(&name).cloned()
+    }
+
+    pub fn remove_view(&mut self, view_name: &str) {
+        let name = normalize_ident(view_name);
+        self.views.remove(&name);
     }
 
     /// Update [Schema] by scanning the first root page (sqlite_schema)
@@ -280,6 +476,22 @@ impl Schema {
                         }
                     }
                 }
+                "trigger" => {
+                    let sql_value = record_cursor.get_value(&row, 4)?;
+                    let RefValue::Text(sql_text) = sql_value else {
+                        return Err(LimboError::ConversionError("Expected text value".into()));
+                    };
+                    let trigger = Trigger::from_sql(sql_text.as_str())?;
+                    self.add_trigger(Arc::new(trigger));
+                }
+                "view" => {
+                    let sql_value = record_cursor.get_value(&row, 4)?;
+                    let RefValue::Text(sql_text) = sql_value else {
+                        return Err(LimboError::ConversionError("Expected text value".into()));
+                    };
+                    let view = View::from_sql(sql_text.as_str())?;
+                    self.add_view(Arc::new(view));
+                }
                 _ => {}
             };
             drop(record_cursor);
@@ -290,43 +502,122 @@ impl Schema {
             };
         }
 
+        if let Some(stat1) = self.get_btree_table(STAT1_TABLE_NAME) {
+            self.load_stats_from_btree(&pager, stat1.root_page)?;
+        }
+
         pager.end_read_tx()?;
 
         for unparsed_sql_from_index in from_sql_indexes {
-            if !self.indexes_enabled() {
-                self.table_set_has_index(&unparsed_sql_from_index.table_name);
-            } else {
-                let table = self
-                    .get_btree_table(&unparsed_sql_from_index.table_name)
-                    .unwrap();
-                let index = Index::from_sql(
-                    &unparsed_sql_from_index.sql,
-                    unparsed_sql_from_index.root_page,
-                    table.as_ref(),
-                )?;
+            let table = self
+                .get_btree_table(&unparsed_sql_from_index.table_name)
+                .unwrap();
+            let index = Index::from_sql(
+                &unparsed_sql_from_index.sql,
+                unparsed_sql_from_index.root_page,
+                table.as_ref(),
+            )?;
+            self.add_index(Arc::new(index));
+        }
+
+        for automatic_index in automatic_indices {
+            let table = self.get_btree_table(&automatic_index.0).unwrap();
+            let ret_index =
+                Index::automatic_from_primary_key_and_unique(table.as_ref(), automatic_index.1)?;
+            for index in ret_index {
                 self.add_index(Arc::new(index));
             }
         }
 
-        for automatic_index in automatic_indices {
-            if !self.indexes_enabled() {
-                self.table_set_has_index(&automatic_index.0);
-            } else {
-                let table = self.get_btree_table(&automatic_index.0).unwrap();
-                let ret_index = Index::automatic_from_primary_key_and_unique(
-                    table.as_ref(),
-                    automatic_index.1,
-                )?;
-                for index in ret_index {
-                    self.add_index(Arc::new(index));
-                }
+        Ok(())
+    }
+
+    /// Load the statistics gathered by ANALYZE from the `sqlite_stat1` table at `root_page`.
+    fn load_stats_from_btree(&mut self, pager: &Rc<Pager>, root_page: usize) -> Result<()> {
+        let mut cursor = BTreeCursor::new_table(None, pager.clone(), root_page, 3);
+        loop {
+            match cursor.rewind()? {
+                IOResult::Done(_) => break,
+                IOResult::IO => pager.io.run_once()?,
             }
         }
+        loop {
+            let Some(row) = (loop {
+                match cursor.record()? {
+                    IOResult::Done(v) => break v,
+                    IOResult::IO => pager.io.run_once()?,
+                }
+            }) else {
+                break;
+            };
 
+            let mut record_cursor = cursor.record_cursor.borrow_mut();
+            if let (RefValue::Text(tbl), idx, RefValue::Text(stat)) = (
+                record_cursor.get_value(&row, 0)?,
+                record_cursor.get_value(&row, 1)?,
+                record_cursor.get_value(&row, 2)?,
+            ) {
+                let idx = match &idx {
+                    RefValue::Text(idx) => Some(idx.as_str()),
+                    _ => None,
+                };
+                self.add_stat1_row(tbl.as_str(), idx, stat.as_str());
+            }
+            drop(record_cursor);
+            drop(row);
+
+            loop {
+                match cursor.next()? {
+                    IOResult::Done(_) => break,
+                    IOResult::IO => pager.io.run_once()?,
+                }
+            }
+        }
         Ok(())
     }
 }
 
+/// Name of the table ANALYZE stores its statistics in.
+pub const STAT1_TABLE_NAME: &str = "sqlite_stat1";
+/// Definition of the `sqlite_stat1` table, as created by SQLite.
+pub const STAT1_TABLE_SQL: &str = "CREATE TABLE sqlite_stat1(tbl,idx,stat)";
+
+/// Statistics of a table and its indexes gathered by ANALYZE, as stored in `sqlite_stat1`.
+#[derive(Clone, Debug, Default)]
+pub struct TableStats {
+    /// Number of rows of the table. ANALYZE only records it separately when the table has no
+    /// index that covers every row, otherwise it is the number of rows of such an index.
+    pub row_count: Option<u64>,
+    /// index name to the number of rows of the index, followed by the average number of rows
+    /// sharing the same values of its first 1, 2, ... columns
+    pub indexes: HashMap<String, Vec<u64>>,
+}
+
+impl TableStats {
+    /// Estimated number of rows of the table.
+    pub fn estimated_row_count(&self) -> Option<u64> {
+        self.row_count
+            .or_else(|| self.indexes.values().map(|stat| stat[0]).max())
+    }
+
+    /// Statistics of `index` of `table`, see [TableStats::indexes].
+    pub fn index(&self, table: &BTreeTable, index: &Index) -> Option<&[u64]> {
+        self.indexes
+            .get(&normalize_ident(stat1_index_name(table, index)))
+            .map(Vec::as_slice)
+    }
+}
+
+/// Name of `index` in the `idx` column of `sqlite_stat1`. Like SQLite, the PRIMARY KEY index of a
+/// WITHOUT ROWID table, which is the table b-tree itself, goes by the name of the table.
+pub fn stat1_index_name<'a>(table: &'a BTreeTable, index: &'a Index) -> &'a str {
+    if !table.has_rowid && index.root_page == table.root_page {
+        &table.name
+    } else {
+        &index.name
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Table {
     BTree(Rc<BTreeTable>),
@@ -400,10 +691,130 @@ pub struct BTreeTable {
     pub root_page: usize,
     pub name: String,
     pub primary_key_columns: Vec<(String, SortOrder)>,
+    /// Conflict resolution of the PRIMARY KEY constraint, from its `ON CONFLICT` clause.
+    pub primary_key_conflict: Option<ResolveType>,
     pub columns: Vec<Column>,
     pub has_rowid: bool,
     pub is_strict: bool,
-    pub unique_sets: Option<Vec<Vec<(String, SortOrder)>>>,
+    pub unique_sets: Option<Vec<UniqueSet>>,
+    /// CHECK constraints of the table, both column and table level, in declaration order.
+    pub check_constraints: Vec<CheckConstraint>,
+    /// FOREIGN KEY constraints of the table, the column level ones first, like SQLite.
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// A table-level UNIQUE constraint.
+#[derive(Clone, Debug)]
+pub struct UniqueSet {
+    pub columns: Vec<(String, SortOrder)>,
+    /// Conflict resolution of the constraint, from its `ON CONFLICT` clause.
+    pub on_conflict: Option<ResolveType>,
+}
+
+/// A CHECK constraint, column or table level.
+#[derive(Clone, Debug)]
+pub struct CheckConstraint {
+    /// Name of the constraint, from its `CONSTRAINT name` prefix.
+    pub name: Option<String>,
+    pub expr: Expr,
+    /// Source text of the expression in the CREATE TABLE statement.
+    pub source: String,
+}
+
+/// A FOREIGN KEY constraint, column or table level.
+#[derive(Clone, Debug)]
+pub struct ForeignKey {
+    /// Columns of the child table, the one the constraint is declared on.
+    pub child_columns: Vec<String>,
+    pub parent_table: String,
+    /// Columns of the parent key, empty if it is the PRIMARY KEY of the parent table.
+    pub parent_columns: Vec<String>,
+    pub on_delete: RefAct,
+    pub on_update: RefAct,
+    /// Whether the constraint is `DEFERRABLE INITIALLY DEFERRED`, i.e. checked at COMMIT.
+    pub deferred: bool,
+}
+
+impl ForeignKey {
+    fn new(
+        child_columns: Vec<String>,
+        clause: ast::ForeignKeyClause,
+        defer_clause: Option<ast::DeferSubclause>,
+    ) -> Self {
+        let mut on_delete = RefAct::NoAction;
+        let mut on_update = RefAct::NoAction;
+        for arg in clause.args {
+            match arg {
+                ast::RefArg::OnDelete(action) => on_delete = action,
+                ast::RefArg::OnUpdate(action) => on_update = action,
+                ast::RefArg::OnInsert(_) | ast::RefArg::Match(_) => {}
+            }
+        }
+        Self {
+            child_columns,
+            parent_table: normalize_ident(&clause.tbl_name.0),
+            parent_columns: clause
+                .columns
+                .unwrap_or_default()
+                .iter()
+                .map(|column| normalize_ident(&column.col_name.0))
+                .collect(),
+            on_delete,
+            on_update,
+            deferred: defer_clause.as_ref().is_some_and(Self::is_deferred),
+        }
+    }
+
+    fn is_deferred(defer_clause: &ast::DeferSubclause) -> bool {
+        defer_clause.deferrable
+            && defer_clause.init_deferred == Some(ast::InitDeferredPred::InitiallyDeferred)
+    }
+}
+
+/// Returns the source text of the expressions of the CHECK constraints in the CREATE TABLE
+/// statement `sql`, in the order they appear.
+fn check_constraint_sources(sql: &str) -> Vec<String> {
+    let input = sql.as_bytes();
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut sources = Vec::new();
+    // Where the expression of the current CHECK starts and the depth of its parentheses
+    let mut current: Option<(Option<usize>, usize)> = None;
+    loop {
+        let Ok((token_start, Some((_, token_type)), token_end)) = scanner.scan(input) else {
+            return sources;
+        };
+        match (&mut current, token_type) {
+            (None, TokenType::TK_CHECK) => current = Some((None, 0)),
+            (Some((start @ None, depth)), TokenType::TK_LP) => {
+                *start = Some(token_end);
+                *depth = 1;
+            }
+            (Some((_, depth)), TokenType::TK_LP) => *depth += 1,
+            (Some((Some(start), depth)), TokenType::TK_RP) => {
+                *depth -= 1;
+                if *depth == 0 {
+                    sources.push(
+                        String::from_utf8_lossy(&input[*start..token_start])
+                            .trim()
+                            .to_string(),
+                    );
+                    current = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl CheckConstraint {
+    /// How a violation of the constraint is reported: its name, or else the source text of its
+    /// expression.
+    pub fn description(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.source.clone(),
+        }
+    }
 }
 
 impl BTreeTable {
@@ -434,18 +845,102 @@ impl BTreeTable {
             .find(|(_, column)| column.name.as_ref() == Some(&name))
     }
 
+    /// Returns the position of column `column` in the table record, which has no slot
+    /// for `VIRTUAL` generated columns.
+    /// The record of a WITHOUT ROWID table starts with the PRIMARY KEY columns, in PRIMARY KEY
+    /// order, followed by the other columns in declaration order.
+    pub fn storage_column_index(&self, column: usize) -> usize {
+        if !self.has_rowid {
+            if let Some(pk_pos) = self.primary_key_position(column) {
+                return pk_pos;
+            }
+            return self.primary_key_columns.len()
+                + self.columns[..column]
+                    .iter()
+                    .filter(|column| !column.is_virtual() && !column.primary_key)
+                    .count();
+        }
+        self.columns[..column]
+            .iter()
+            .filter(|column| !column.is_virtual())
+            .count()
+    }
+
+    /// Returns the position of column `column` in the PRIMARY KEY, if it is part of it.
+    pub fn primary_key_position(&self, column: usize) -> Option<usize> {
+        self.primary_key_columns.iter().position(|(name, _)| {
+            self.get_column(name)
+                .is_some_and(|(pos_in_table, _)| pos_in_table == column)
+        })
+    }
+
+    /// The columns of the table in the order they are stored in the table record,
+    /// `VIRTUAL` generated columns left out.
+    pub fn storage_columns(&self) -> Vec<usize> {
+        let mut columns = (0..self.columns.len())
+            .filter(|&column| !self.columns[column].is_virtual())
+            .collect::<Vec<_>>();
+        if !self.has_rowid {
+            columns.sort_by_key(|&column| self.storage_column_index(column));
+        }
+        columns
+    }
+
+    /// The index a WITHOUT ROWID table is stored as: its b-tree is keyed by the PRIMARY KEY
+    /// and holds the whole row. Returns `None` for tables with a rowid.
+    pub fn clustered_index(&self) -> Option<Arc<Index>> {
+        if self.has_rowid {
+            return None;
+        }
+        let columns = self
+            .primary_key_columns
+            .iter()
+            .map(|(name, order)| {
+                let (pos_in_table, column) = self
+                    .get_column(name)
+                    .expect("primary key column should be in the table");
+                IndexColumn {
+                    name: normalize_ident(name),
+                    order: *order,
+                    pos_in_table,
+                    collation: column.collation,
+                    default: column.default.clone(),
+                    expr: None,
+                }
+            })
+            .collect();
+        Some(Arc::new(Index {
+            name: format!("{}{}_1", PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX, self.name),
+            table_name: self.name.clone(),
+            root_page: self.root_page,
+            columns,
+            unique: true,
+            ephemeral: false,
+            has_rowid: false,
+            on_conflict: self.primary_key_conflict,
+            where_clause: None,
+            pk_suffix_len: 0,
+        }))
+    }
+
+    pub fn has_virtual_columns(&self) -> bool {
+        self.columns.iter().any(|column| column.is_virtual())
+    }
+
     pub fn from_sql(sql: &str, root_page: usize) -> Result<BTreeTable> {
         let mut parser = Parser::new(sql.as_bytes());
         let cmd = parser.next()?;
         match cmd {
             Some(Cmd::Stmt(Stmt::CreateTable { tbl_name, body, .. })) => {
-                create_table(tbl_name, *body, root_page)
+                create_table(tbl_name, *body, root_page, sql)
             }
             _ => todo!("Expected CREATE TABLE statement"),
         }
     }
 
     pub fn to_sql(&self) -> String {
+        // The key order of a WITHOUT ROWID table is part of how it is stored
+        let table_primary_key = self.primary_key_columns.len() > 1 || !self.has_rowid;
         let mut sql = format!("CREATE TABLE {} (", self.name);
         for (i, column) in self.columns.iter().enumerate() {
             if i > 0 {
@@ -462,7 +957,7 @@ impl BTreeTable {
                 sql.push_str(" UNIQUE");
             }
 
-            if column.primary_key {
+            if column.primary_key && !table_primary_key {
                 sql.push_str(" PRIMARY KEY");
             }
 
@@ -470,8 +965,32 @@ impl BTreeTable {
                 sql.push_str(" DEFAULT ");
                 sql.push_str(&default.to_string());
             }
+
+            if let Some(generated) = &column.generated {
+                sql.push_str(" GENERATED ALWAYS AS (");
+                sql.push_str(&generated.expr.to_string());
+                sql.push_str(if generated.stored {
+                    ") STORED"
+                } else {
+                    ") VIRTUAL"
+                });
+            }
+        }
+        if table_primary_key {
+            let columns = self
+                .primary_key_columns
+                .iter()
+                .map(|(name, order)| match order {
+                    SortOrder::Asc => name.clone(),
+                    SortOrder::Desc => format!("{name} DESC"),
+                })
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(", PRIMARY KEY ({})", columns.join(", ")));
         }
         sql.push(')');
+        if !self.has_rowid {
+            sql.push_str(" WITHOUT ROWID");
+        }
         sql
     }
 
@@ -509,6 +1028,41 @@ pub struct FromClauseSubquery {
     /// The start register for the result columns of the derived table;
     /// must be set before data is read from it.
     pub result_columns_start_reg: Option<usize>,
+    /// The recursive part of a recursive CTE, in which case `plan` is its initial part.
+    pub recursive: Option<Box<RecursiveCte>>,
+    /// The other SELECTs of a compound SELECT, in which case `plan` is its rightmost SELECT.
+    pub compound: Option<Box<CompoundSubquery>>,
+    /// Whether this is the reference of a recursive CTE to itself in its recursive part.
+    /// It has a single row, the row of the CTE the recursive part is run for, which is read
+    /// from `result_columns_start_reg`.
+    pub is_recursive_reference: bool,
+    /// The CTE this derived table was planned from, if any.
+    pub cte: Option<Rc<CteDefinition>>,
+}
+
+impl FromClauseSubquery {
+    /// The destination of the rows of the derived table, from which the parent query reads them.
+    pub fn query_destination(&self) -> &QueryDestination {
+        match &self.recursive {
+            Some(recursive) => &recursive.query_destination,
+            None => &self.plan.query_destination,
+        }
+    }
+
+    /// The SELECTs of the derived table, which are several for a compound SELECT.
+    pub fn selects(&self) -> impl Iterator<Item = &SelectPlan> {
+        self.compound
+            .iter()
+            .flat_map(|compound| compound.left.iter().map(|(plan, _)| plan))
+            .chain(std::iter::once(self.plan.as_ref()))
+    }
+
+    pub fn selects_mut(&mut self) -> impl Iterator<Item = &mut SelectPlan> {
+        self.compound
+            .iter_mut()
+            .flat_map(|compound| compound.left.iter_mut().map(|(plan, _)| plan))
+            .chain(std::iter::once(self.plan.as_mut()))
+    }
 }
 
 #[derive(Debug, Eq)]
@@ -539,15 +1093,21 @@ fn create_table(
     tbl_name: QualifiedName,
     body: CreateTableBody,
     root_page: usize,
+    sql: &str,
 ) -> Result<BTreeTable> {
     let table_name = normalize_ident(&tbl_name.name.0);
     trace!("Creating table {}", table_name);
     let mut has_rowid = true;
     let mut primary_key_columns = vec![];
+    let mut primary_key_conflict = None;
     let mut cols = vec![];
     let is_strict: bool;
     // BtreeSet here to preserve order of inserted keys
-    let mut unique_sets: Vec<BTreeSet<UniqueColumnProps>> = vec![];
+    let mut unique_sets: Vec<(BTreeSet<UniqueColumnProps>, Option<ResolveType>)> = vec![];
+    let mut check_constraints = vec![];
+    let mut table_check_constraints = vec![];
+    let mut foreign_keys = vec![];
+    let mut table_foreign_keys = vec![];
     match body {
         CreateTableBody::ColumnsAndConstraints {
             columns,
@@ -558,9 +1118,12 @@ fn create_table(
             if let Some(constraints) = constraints {
                 for c in constraints {
                     if let turso_sqlite3_parser::ast::TableConstraint::PrimaryKey {
-                        columns, ..
+                        columns,
+                        conflict_clause,
+                        ..
                     } = c.constraint
                     {
+                        primary_key_conflict = conflict_clause;
                         for column in columns {
                             let col_name = match column.expr {
                                 Expr::Id(id) => normalize_ident(&id.0),
@@ -579,9 +1142,6 @@ fn create_table(
                         conflict_clause,
                     } = c.constraint
                     {
-                        if conflict_clause.is_some() {
-                            unimplemented!("ON CONFLICT not implemented");
-                        }
                         let unique_set = columns
                             .into_iter()
                             .map(|column| {
@@ -597,7 +1157,30 @@ fn create_table(
                                 }
                             })
                             .collect();
-                        unique_sets.push(unique_set);
+                        unique_sets.push((unique_set, conflict_clause));
+                    } else if let turso_sqlite3_parser::ast::TableConstraint::Check(expr) =
+                        c.constraint
+                    {
+                        table_check_constraints.push(CheckConstraint {
+                            name: c.name.map(|name| normalize_ident(&name.0)),
+                            source: expr.to_string(),
+                            expr,
+                        });
+                    } else if let turso_sqlite3_parser::ast::TableConstraint::ForeignKey {
+                        columns,
+                        clause,
+                        deref_clause,
+                    } = c.constraint
+                    {
+                        let child_columns = columns
+                            .iter()
+                            .map(|column| normalize_ident(&column.col_name.0))
+                            .collect();
+                        table_foreign_keys.push(ForeignKey::new(
+                            child_columns,
+                            clause,
+                            deref_clause,
+                        ));
                     }
                 }
             }
@@ -659,35 +1242,70 @@ fn create_table(
                 let mut notnull = false;
                 let mut order = SortOrder::Asc;
                 let mut unique = false;
+                let mut notnull_conflict = None;
+                let mut unique_conflict = None;
                 let mut collation = None;
+                let mut generated = None;
                 for c_def in col_def.constraints {
                     match c_def.constraint {
                         turso_sqlite3_parser::ast::ColumnConstraint::PrimaryKey {
                             order: o,
+                            conflict_clause,
                             ..
                         } => {
                             primary_key = true;
+                            primary_key_conflict = conflict_clause;
                             if let Some(o) = o {
                                 order = o;
                             }
                         }
-                        turso_sqlite3_parser::ast::ColumnConstraint::NotNull { .. } => {
-                            notnull = true;
+                        turso_sqlite3_parser::ast::ColumnConstraint::NotNull {
+                            nullable,
+                            conflict_clause,
+                        } => {
+                            // `NULL` is accepted as a column constraint, and is a no-op
+                            if !nullable {
+                                notnull = true;
+                                notnull_conflict = conflict_clause;
+                            }
                         }
                         turso_sqlite3_parser::ast::ColumnConstraint::Default(expr) => {
                             default = Some(expr)
                         }
-                        // TODO: for now we don't check Resolve type of unique
                         turso_sqlite3_parser::ast::ColumnConstraint::Unique(on_conflict) => {
-                            if on_conflict.is_some() {
-                                unimplemented!("ON CONFLICT not implemented");
-                            }
                             unique = true;
+                            unique_conflict = on_conflict;
                         }
                         turso_sqlite3_parser::ast::ColumnConstraint::Collate { collation_name } => {
                             collation = Some(CollationSeq::new(collation_name.0.as_str())?);
                         }
-                        _ => {}
+                        turso_sqlite3_parser::ast::ColumnConstraint::Check(expr) => {
+                            check_constraints.push(CheckConstraint {
+                                name: c_def.name.map(|name| normalize_ident(&name.0)),
+                                source: expr.to_string(),
+                                expr,
+                            });
+                        }
+                        turso_sqlite3_parser::ast::ColumnConstraint::ForeignKey {
+                            clause,
+                            deref_clause,
+                        } => {
+                            foreign_keys.push(ForeignKey::new(
+                                vec![normalize_ident(&name)],
+                                clause,
+                                deref_clause,
+                            ));
+                        }
+                        turso_sqlite3_parser::ast::ColumnConstraint::Generated { expr, typ } => {
+                            generated = Some(GeneratedColumn::new(expr, typ));
+                        }
+                        // The DEFERRABLE clause of a column's REFERENCES is parsed as a
+                        // constraint of its own, which applies to the last foreign key.
+                        turso_sqlite3_parser::ast::ColumnConstraint::Defer(defer_clause) => {
+                            if let Some(foreign_key) = foreign_keys.last_mut() {
+                                foreign_key.deferred = ForeignKey::is_deferred(&defer_clause);
+                            }
+                        }
                     }
                 }
 
@@ -711,14 +1329,29 @@ fn create_table(
                     unique,
                     collation,
                     hidden: false,
+                    notnull_conflict,
+                    unique_conflict,
+                    generated,
                 });
             }
             if options.contains(TableOptions::WITHOUT_ROWID) {
                 has_rowid = false;
             }
         }
-        CreateTableBody::AsSelect(_) => todo!(),
+        CreateTableBody::AsSelect(_) => {
+            // CREATE TABLE ... AS SELECT is stored with the columns of its SELECT
+            crate::bail_parse_error!("CREATE TABLE AS SELECT has no column definitions")
+        }
     };
+    foreign_keys.extend(table_foreign_keys);
+    // The table constraints come after the column definitions, like in the statement
+    check_constraints.extend(table_check_constraints);
+    let check_sources = check_constraint_sources(sql);
+    if check_sources.len() == check_constraints.len() {
+        for (check, source) in check_constraints.iter_mut().zip(check_sources) {
+            check.source = source;
+        }
+    }
     // flip is_rowid_alias back to false if the table has multiple primary keys
     // or if the table has no rowid
     if !has_rowid || primary_key_columns.len() > 1 {
@@ -726,25 +1359,36 @@ fn create_table(
             col.is_rowid_alias = false;
         }
     }
+    // the PRIMARY KEY of a WITHOUT ROWID table is the key of its b-tree, so it cannot be NULL
+    if !has_rowid {
+        for col in cols.iter_mut().filter(|col| col.primary_key) {
+            col.notnull = true;
+        }
+    }
     Ok(BTreeTable {
         root_page,
         name: table_name,
         has_rowid,
         primary_key_columns,
+        primary_key_conflict,
         columns: cols,
         is_strict,
+        check_constraints,
+        foreign_keys,
         unique_sets: if unique_sets.is_empty() {
             None
         } else {
             // Sort first so that dedup operation removes all duplicates
-            unique_sets.dedup();
+            unique_sets.dedup_by(|(a, _), (b, _)| a == b);
             Some(
                 unique_sets
                     .into_iter()
-                    .map(|set| {
-                        set.into_iter()
+                    .map(|(set, on_conflict)| UniqueSet {
+                        columns: set
+                            .into_iter()
                             .map(|UniqueColumnProps { column_name, order }| (column_name, order))
-                            .collect()
+                            .collect(),
+                        on_conflict,
                     })
                     .collect(),
             )
@@ -783,12 +1427,43 @@ pub struct Column {
     pub unique: bool,
     pub collation: Option<CollationSeq>,
     pub hidden: bool,
+    /// Conflict resolution of the NOT NULL constraint, from its `ON CONFLICT` clause.
+    pub notnull_conflict: Option<ResolveType>,
+    /// Conflict resolution of the UNIQUE constraint, from its `ON CONFLICT` clause.
+    pub unique_conflict: Option<ResolveType>,
+    /// The `GENERATED ALWAYS AS (expr)` clause, if this is a generated column.
+    pub generated: Option<GeneratedColumn>,
+}
+
+/// The expression of a generated column and how its value is kept.
+#[derive(Debug, Clone)]
+pub struct GeneratedColumn {
+    pub expr: Expr,
+    /// `STORED` columns are computed on write and kept in the table record,
+    /// `VIRTUAL` ones are left out of the record and computed when read.
+    pub stored: bool,
+}
+
+impl GeneratedColumn {
+    fn new(expr: Expr, typ: Option<ast::Id>) -> Self {
+        let stored = typ.is_some_and(|ast::Id(typ)| typ.eq_ignore_ascii_case("stored"));
+        Self { expr, stored }
+    }
 }
 
 impl Column {
     pub fn affinity(&self) -> Affinity {
         affinity(&self.ty_str)
     }
+
+    pub fn is_generated(&self) -> bool {
+        self.generated.is_some()
+    }
+
+    /// Whether this is a `VIRTUAL` generated column, which has no slot in the table record.
+    pub fn is_virtual(&self) -> bool {
+        self.generated.as_ref().is_some_and(|g| !g.stored)
+    }
 }
 
 // TODO: This might replace some of util::columns_from_create_table_body
@@ -801,12 +1476,24 @@ impl From<ColumnDefinition> for Column {
         let mut primary_key = false;
         let mut unique = false;
         let mut collation = None;
+        let mut notnull_conflict = None;
+        let mut unique_conflict = None;
+        let mut generated = None;
 
         for ast::NamedColumnConstraint { constraint, .. } in value.constraints {
             match constraint {
                 ast::ColumnConstraint::PrimaryKey { .. } => primary_key = true,
-                ast::ColumnConstraint::NotNull { .. } => notnull = true,
-                ast::ColumnConstraint::Unique(..) => unique = true,
+                ast::ColumnConstraint::NotNull {
+                    nullable: false,
+                    conflict_clause,
+                } => {
+                    notnull = true;
+                    notnull_conflict = conflict_clause;
+                }
+                ast::ColumnConstraint::Unique(on_conflict) => {
+                    unique = true;
+                    unique_conflict = on_conflict;
+                }
                 ast::ColumnConstraint::Default(expr) => {
                     default.replace(expr);
                 }
@@ -816,6 +1503,9 @@ impl From<ColumnDefinition> for Column {
                             .expect("collation should have been set correctly in create table"),
                     );
                 }
+                ast::ColumnConstraint::Generated { expr, typ } => {
+                    generated = Some(GeneratedColumn::new(expr, typ));
+                }
                 _ => {}
             };
         }
@@ -862,6 +1552,9 @@ impl From<ColumnDefinition> for Column {
             unique,
             collation,
             hidden: false,
+            notnull_conflict,
+            unique_conflict,
+            generated,
         }
     }
 }
@@ -881,24 +1574,25 @@ impl From<ColumnDefinition> for Column {
 ///
 /// Note that the order of the rules for determining column affinity is important. A column whose declared type is "CHARINT" will match both rules 1 and 2 but the first rule takes precedence and so the column affinity will be INTEGER.
 pub fn affinity(datatype: &str) -> Affinity {
-    // Note: callers of this function must ensure that the datatype is uppercase.
+    // Type names are case-insensitive.
+    let contains = |needle: &str| contains_ignore_ascii_case(datatype, needle);
     // Rule 1: INT -> INTEGER affinity
-    if datatype.contains("INT") {
+    if contains("INT") {
         return Affinity::Integer;
     }
 
     // Rule 2: CHAR/CLOB/TEXT -> TEXT affinity
-    if datatype.contains("CHAR") || datatype.contains("CLOB") || datatype.contains("TEXT") {
+    if contains("CHAR") || contains("CLOB") || contains("TEXT") {
         return Affinity::Text;
     }
 
     // Rule 3: BLOB or empty -> BLOB affinity (historically called NONE)
-    if datatype.contains("BLOB") || datatype.is_empty() || datatype.contains("ANY") {
+    if contains("BLOB") || datatype.is_empty() || contains("ANY") {
         return Affinity::Blob;
     }
 
     // Rule 4: REAL/FLOA/DOUB -> REAL affinity
-    if datatype.contains("REAL") || datatype.contains("FLOA") || datatype.contains("DOUB") {
+    if contains("REAL") || contains("FLOA") || contains("DOUB") {
         return Affinity::Real;
     }
 
@@ -906,6 +1600,14 @@ pub fn affinity(datatype: &str) -> Affinity {
     Affinity::Numeric
 }
 
+/// Whether `haystack` contains `needle`, comparing ASCII letters case-insensitively.
+fn contains_ignore_ascii_case(haystack: &str, needle: &str) -> bool {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Type {
     Null,
@@ -1065,7 +1767,10 @@ pub fn sqlite_schema_table() -> BTreeTable {
         name: "sqlite_schema".to_string(),
         has_rowid: true,
         is_strict: false,
+        check_constraints: vec![],
+        foreign_keys: vec![],
         primary_key_columns: vec![],
+        primary_key_conflict: None,
         columns: vec![
             Column {
                 name: Some("type".to_string()),
@@ -1078,6 +1783,9 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             },
             Column {
                 name: Some("name".to_string()),
@@ -1090,6 +1798,9 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             },
             Column {
                 name: Some("tbl_name".to_string()),
@@ -1102,6 +1813,9 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             },
             Column {
                 name: Some("rootpage".to_string()),
@@ -1114,6 +1828,9 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             },
             Column {
                 name: Some("sql".to_string()),
@@ -1126,6 +1843,9 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             },
         ],
         unique_sets: None,
@@ -1144,11 +1864,22 @@ pub struct Index {
     /// Does the index have a rowid as the last column?
     /// This is the case for btree indexes (persistent or ephemeral) that
     /// have been created based on a table with a rowid.
-    /// For example, WITHOUT ROWID tables (not supported in Limbo yet),
+    /// For example, indexes of WITHOUT ROWID tables
     /// and  SELECT DISTINCT ephemeral indexes will not have a rowid.
     pub has_rowid: bool,
+    /// Conflict resolution of the constraint the index enforces, from its `ON CONFLICT` clause.
+    /// Only automatic indexes of PRIMARY KEY and UNIQUE constraints can have one.
+    pub on_conflict: Option<ResolveType>,
+    /// The `WHERE` clause of a partial index. Only the rows satisfying it are in the index.
+    pub where_clause: Option<Box<Expr>>,
+    /// Number of trailing [Index::columns] that are PRIMARY KEY columns appended to the key
+    /// of an index of a WITHOUT ROWID table, which point to the row in place of a rowid.
+    pub pk_suffix_len: usize,
 }
 
+/// The [IndexColumn::pos_in_table] of an index column on an expression rather than a column.
+pub const EXPR_INDEX_SENTINEL: usize = usize::MAX;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct IndexColumn {
@@ -1159,9 +1890,13 @@ pub struct IndexColumn {
     /// CREATE TABLE t(a,b,c)
     /// CREATE INDEX idx ON t(b)
     /// b.pos_in_table == 1
+    /// [EXPR_INDEX_SENTINEL] for an index column on an expression.
     pub pos_in_table: usize,
     pub collation: Option<CollationSeq>,
     pub default: Option<Expr>,
+    /// The indexed expression, if the index column is on an expression rather than a column.
+    /// Its column references are unqualified names of the table columns.
+    pub expr: Option<Box<Expr>>,
 }
 
 impl Index {
@@ -1174,12 +1909,31 @@ impl Index {
                 tbl_name,
                 columns,
                 unique,
+                where_clause,
                 ..
             })) => {
                 let index_name = normalize_ident(&idx_name.name.0);
                 let mut index_columns = Vec::with_capacity(columns.len());
                 for col in columns.into_iter() {
-                    let name = normalize_ident(&col.expr.to_string());
+                    let order = col.order.unwrap_or(SortOrder::Asc);
+                    let (expr, collation) = match col.expr {
+                        Expr::Collate(expr, collation) => {
+                            (*expr, Some(CollationSeq::new(&collation)?))
+                        }
+                        expr => (expr, None),
+                    };
+                    if !matches!(expr, Expr::Id(_) | Expr::Name(_)) {
+                        index_columns.push(IndexColumn {
+                            name: expr.to_string(),
+                            order,
+                            pos_in_table: EXPR_INDEX_SENTINEL,
+                            collation,
+                            default: None,
+                            expr: Some(Box::new(expr)),
+                        });
+                        continue;
+                    }
+                    let name = normalize_ident(&expr.to_string());
                     let Some((pos_in_table, _)) = table.get_column(&name) else {
                         return Err(crate::LimboError::InternalError(format!(
                             "Column {} is in index {} but not found in table {}",
@@ -1189,10 +1943,11 @@ impl Index {
                     let (_, column) = table.get_column(&name).unwrap();
                     index_columns.push(IndexColumn {
                         name,
-                        order: col.order.unwrap_or(SortOrder::Asc),
+                        order,
                         pos_in_table,
-                        collation: column.collation,
+                        collation: collation.or(column.collation),
                         default: column.default.clone(),
+                        expr: None,
                     });
                 }
                 Ok(Index {
@@ -1203,7 +1958,11 @@ impl Index {
                     unique,
                     ephemeral: false,
                     has_rowid: table.has_rowid,
-                })
+                    on_conflict: None,
+                    where_clause,
+                    pk_suffix_len: 0,
+                }
+                .with_primary_key_suffix(table))
             }
             _ => todo!("Expected create index statement"),
         }
@@ -1229,8 +1988,10 @@ impl Index {
         // I wanted to just chain the iterator above but Rust type system get's messy with Iterators.
         // It would not allow me chain them even by using a core::iter::empty()
         // To circumvent this, I'm having to allocate a second Vec, and extend the other from it.
-        let has_primary_key_index =
+        let has_primary_key =
             table.get_rowid_alias_column().is_none() && !table.primary_key_columns.is_empty();
+        // The PRIMARY KEY of a WITHOUT ROWID table is the table b-tree itself
+        let has_primary_key_index = has_primary_key && table.has_rowid;
         if has_primary_key_index {
             let (index_name, root_page) = auto_indices.next().expect(
                 "number of auto_indices in schema should be same number of indices calculated",
@@ -1257,6 +2018,7 @@ impl Index {
                         pos_in_table,
                         collation: column.collation,
                         default: column.default.clone(),
+                        expr: None,
                     }
                 })
                 .collect::<Vec<_>>();
@@ -1269,6 +2031,9 @@ impl Index {
                 unique: true,
                 ephemeral: false,
                 has_rowid: table.has_rowid,
+                on_conflict: table.primary_key_conflict,
+                where_clause: None,
+                pk_suffix_len: 0,
             });
         }
 
@@ -1281,7 +2046,7 @@ impl Index {
                 if col.unique {
                     // Unique columns in Table should always be named
                     let col_name = col.name.as_ref().unwrap();
-                    if has_primary_key_index
+                    if has_primary_key
                         && table.primary_key_columns.len() == 1
                         && &table.primary_key_columns.first().as_ref().unwrap().0 == col_name {
                             // skip unique columns that are satisfied with pk constraint
@@ -1299,11 +2064,16 @@ impl Index {
                             pos_in_table,
                             collation: column.collation,
                             default: column.default.clone(),
+                            expr: None,
                         }],
                         unique: true,
                         ephemeral: false,
                         has_rowid: table.has_rowid,
-                    })
+                        on_conflict: col.unique_conflict,
+                        where_clause: None,
+                        pk_suffix_len: 0,
+                    }
+                    .with_primary_key_suffix(table))
                 } else {
                     None
                 }
@@ -1334,12 +2104,12 @@ impl Index {
             let unique_set_indices = unique_sets
                 .iter()
                 .filter(|set| {
-                    if has_primary_key_index
-                        && table.primary_key_columns.len() == set.len()
+                    if has_primary_key
+                        && table.primary_key_columns.len() == set.columns.len()
                         && table
                             .primary_key_columns
                             .iter()
-                            .all(|col| set.contains(col))
+                            .all(|col| set.columns.contains(col))
                     {
                         // skip unique columns that are satisfied with pk constraint
                         false
@@ -1352,7 +2122,7 @@ impl Index {
                     "number of auto_indices in schema should be same number of indices calculated",
                 );
 
-                    let index_cols = set.iter().map(|(col_name, order)| {
+                    let index_cols = set.columns.iter().map(|(col_name, order)| {
                         let Some((pos_in_table, _)) = table.get_column(col_name) else {
                             // This is clearly an invariant that should be maintained, so a panic seems more correct here
                             panic!(
@@ -1367,6 +2137,7 @@ impl Index {
                             pos_in_table,
                             collation: column.collation,
                             default: column.default.clone(),
+                            expr: None,
                         }
                     });
                     Index {
@@ -1377,7 +2148,11 @@ impl Index {
                         unique: true,
                         ephemeral: false,
                         has_rowid: table.has_rowid,
+                        on_conflict: set.on_conflict,
+                        where_clause: None,
+                        pk_suffix_len: 0,
                     }
+                    .with_primary_key_suffix(table)
                 });
             indices.extend(unique_set_indices);
         }
@@ -1389,6 +2164,52 @@ impl Index {
         Ok(indices)
     }
 
+    /// Appends to the key of an index of a WITHOUT ROWID table the PRIMARY KEY columns it does
+    /// not already have, so that every entry points to its row.
+    fn with_primary_key_suffix(mut self, table: &BTreeTable) -> Self {
+        let Some(clustered_index) = table.clustered_index() else {
+            return self;
+        };
+        for pk_column in clustered_index.columns.iter() {
+            if self.position_of_key_column(pk_column).is_none() {
+                self.columns.push(pk_column.clone());
+                self.pk_suffix_len += 1;
+            }
+        }
+        self
+    }
+
+    /// Returns the position in the index of a column of the same table with the same collation.
+    fn position_of_key_column(&self, column: &IndexColumn) -> Option<usize> {
+        self.columns.iter().position(|c| {
+            c.expr.is_none()
+                && c.pos_in_table == column.pos_in_table
+                && c.collation.unwrap_or_default() == column.collation.unwrap_or_default()
+        })
+    }
+
+    /// The positions in the index of the PRIMARY KEY columns of a WITHOUT ROWID table, in
+    /// PRIMARY KEY order: reading them from an index entry gives the key of its row.
+    pub fn primary_key_positions(&self, table: &BTreeTable) -> Vec<usize> {
+        let clustered_index = table
+            .clustered_index()
+            .expect("only WITHOUT ROWID tables have a clustered index");
+        clustered_index
+            .columns
+            .iter()
+            .map(|pk_column| {
+                self.position_of_key_column(pk_column)
+                    .expect("index of a WITHOUT ROWID table should contain the PRIMARY KEY")
+            })
+            .collect()
+    }
+
+    /// The columns the index is declared on, without the PRIMARY KEY columns appended to the
+    /// key of an index of a WITHOUT ROWID table. These are the columns a UNIQUE index constrains.
+    pub fn key_columns(&self) -> &[IndexColumn] {
+        &self.columns[..self.columns.len() - self.pk_suffix_len]
+    }
+
     /// Given a column position in the table, return the position in the index.
     /// Returns None if the column is not found in the index.
     /// For example, given:
@@ -1402,6 +2223,92 @@ impl Index {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub table_name: String,
+    pub time: ast::TriggerTime,
+    pub event: ast::TriggerEvent,
+    pub when_clause: Option<Expr>,
+    pub commands: Vec<ast::TriggerCmd>,
+}
+
+impl Trigger {
+    pub fn from_sql(sql: &str) -> Result<Trigger> {
+        let mut parser = Parser::new(sql.as_bytes());
+        let cmd = parser.next()?;
+        match cmd {
+            Some(Cmd::Stmt(Stmt::CreateTrigger(create_trigger))) => Ok(Self::new(*create_trigger)),
+            _ => Err(LimboError::ParseError(format!(
+                "Expected CREATE TRIGGER statement, got: {sql}"
+            ))),
+        }
+    }
+
+    pub fn new(create_trigger: ast::CreateTrigger) -> Trigger {
+        Trigger {
+            name: normalize_ident(&create_trigger.trigger_name.name.0),
+            table_name: normalize_ident(&create_trigger.tbl_name.name.0),
+            time: create_trigger.time.unwrap_or(ast::TriggerTime::Before),
+            event: create_trigger.event,
+            when_clause: create_trigger.when_clause,
+            commands: create_trigger.commands,
+        }
+    }
+
+    /// Whether this trigger fires for `event`. `updated_columns` are the names of the
+    /// columns assigned by an UPDATE, used to match `UPDATE OF col, ...` triggers.
+    pub fn fires_on(&self, event: &ast::TriggerEvent, updated_columns: &[String]) -> bool {
+        match (&self.event, event) {
+            (ast::TriggerEvent::Insert, ast::TriggerEvent::Insert) => true,
+            (ast::TriggerEvent::Delete, ast::TriggerEvent::Delete) => true,
+            (ast::TriggerEvent::Update, ast::TriggerEvent::Update) => true,
+            (ast::TriggerEvent::UpdateOf(columns), ast::TriggerEvent::Update) => {
+                columns.iter().any(|column| {
+                    let column = normalize_ident(&column.0);
+                    updated_columns.contains(&column)
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct View {
+    pub name: String,
+    pub select: ast::Select,
+    /// Column names given in `CREATE VIEW v(a, b) AS ...`, if any
+    pub columns: Option<Vec<String>>,
+}
+
+impl View {
+    pub fn from_sql(sql: &str) -> Result<View> {
+        let mut parser = Parser::new(sql.as_bytes());
+        let cmd = parser.next()?;
+        match cmd {
+            Some(Cmd::Stmt(Stmt::CreateView {
+                view_name,
+                columns,
+                select,
+                ..
+            })) => Ok(View {
+                name: normalize_ident(&view_name.name.0),
+                select: *select,
+                columns: columns.map(|columns| {
+                    columns
+                        .iter()
+                        .map(|column| normalize_ident(&column.col_name.0))
+                        .collect()
+                }),
+            }),
+            _ => Err(LimboError::ParseError(format!(
+                "Expected CREATE VIEW statement, got: {sql}"
+            ))),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1728,7 +2635,10 @@ mod tests {
             name: "t1".to_string(),
             has_rowid: true,
             is_strict: false,
+            check_constraints: vec![],
+            foreign_keys: vec![],
             primary_key_columns: vec![("nonexistent".to_string(), SortOrder::Asc)],
+            primary_key_conflict: None,
             columns: vec![Column {
                 name: Some("a".to_string()),
                 ty: Type::Integer,
@@ -1740,6 +2650,9 @@ mod tests {
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             }],
             unique_sets: None,
         };
@@ -1946,4 +2859,14 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    pub fn test_affinity_is_case_insensitive() {
+        assert_eq!(affinity("integer"), Affinity::Integer);
+        assert_eq!(affinity("VarChar(10)"), Affinity::Text);
+        assert_eq!(affinity("blob"), Affinity::Blob);
+        assert_eq!(affinity(""), Affinity::Blob);
+        assert_eq!(affinity("double precision"), Affinity::Real);
+        assert_eq!(affinity("decimal"), Affinity::Numeric);
+    }
 }
@@ -191,19 +191,27 @@ enum DeleteState {
     },
     InteriorNodeReplacement {
         cell_idx: usize,
+        /// Position of the interior page in the page stack.
+        cell_depth: usize,
         original_child_pointer: Option<u32>,
         post_balancing_seek_key: Option<DeleteSavepoint>,
     },
     CheckNeedsBalancing {
         rightmost_cell_was_dropped: bool,
+        /// Position of the interior page whose cell was replaced, which must be balanced after the leaf.
+        interior_cell_depth: Option<usize>,
         post_balancing_seek_key: Option<DeleteSavepoint>,
     },
     WaitForBalancingToComplete {
         target_key: DeleteSavepoint,
+        interior_cell_depth: Option<usize>,
     },
     SeekAfterBalancing {
         target_key: DeleteSavepoint,
     },
+    /// The seek after balancing ended up at the start of a leaf page, the row before the deleted
+    /// one is in a page to the left.
+    RetreatAfterBalancing,
 }
 
 #[derive(Clone)]
@@ -457,16 +465,16 @@ pub enum CursorSeekState {
 }
 
 #[derive(Debug)]
-struct FindCellState(Option<(usize, usize)>); // low, high
+struct FindCellState(Option<(usize, usize, usize)>); // low, high, result_index
 
 impl FindCellState {
     #[inline]
-    fn set(&mut self, lowhigh: (usize, usize)) {
-        self.0 = Some(lowhigh);
+    fn set(&mut self, state: (usize, usize, usize)) {
+        self.0 = Some(state);
     }
 
     #[inline]
-    fn get_state(&mut self) -> (usize, usize) {
+    fn get_state(&mut self) -> (usize, usize, usize) {
         self.0.expect("get can only be called after a set")
     }
 
@@ -604,7 +612,17 @@ impl BTreeCursor {
     pub fn has_rowid(&self) -> bool {
         match &self.index_key_info {
             Some(index_key_info) => index_key_info.has_rowid,
-            None => true, // currently we don't support WITHOUT ROWID tables
+            None => true,
+        }
+    }
+
+    /// Number of leading columns of a record of `record_len` columns that identify an entry of
+    /// an index b-tree. The b-tree of a WITHOUT ROWID table is keyed by its PRIMARY KEY, and the
+    /// rest of the record is the payload of the row.
+    fn index_key_len(&self, record_len: usize) -> usize {
+        match &self.index_key_info {
+            Some(index_key_info) => index_key_info.num_cols.min(record_len),
+            None => record_len,
         }
     }
 
@@ -2143,12 +2161,13 @@ impl BTreeCursor {
                             }
                             BTreeCell::IndexLeafCell(..) => {
                                 // Not necessary to read record again here, as find_cell already does that for us
+                                let key_len = self.index_key_len(record_values.len());
                                 let cmp = compare_immutable(
-                                    record_values.as_slice(),
-                                    self.get_immutable_record()
+                                    &record_values[..key_len],
+                                    &self.get_immutable_record()
                                         .as_ref()
                                         .unwrap()
-                                        .get_values().as_slice(),
+                                        .get_values()[..key_len],
                                         self.key_sort_order(),
                                         &self.collations,
                                 );
@@ -2331,7 +2350,9 @@ impl BTreeCursor {
                 // If `move_to` moved to rightmost page, cell index will be out of bounds. Meaning cell_count+1.
                 // In any other case, `move_to` will stay in the correct index.
                 if self.stack.current_cell_index() as usize
-                    == parent_page.get_contents().cell_count() + 1
+                    == parent_page.get_contents().cell_count()
+                        + parent_page.get_contents().overflow_cells.len()
+                        + 1
                 {
                     self.stack.retreat();
                 } else if self.stack.current_cell_index() == -1 {
@@ -2362,12 +2383,16 @@ impl BTreeCursor {
                 let number_of_cells_in_parent =
                     parent_contents.cell_count() + parent_contents.overflow_cells.len();
 
+                // The parent can only have overflowed while a cell of an interior page is replaced
+                // in a delete, in which case the overflow cell is the divider of the page to balance.
                 turso_assert!(
-                    parent_contents.overflow_cells.is_empty(),
+                    parent_contents.overflow_cells.is_empty()
+                        || (parent_contents.overflow_cells.len() == 1
+                            && parent_contents.overflow_cells[0].index == page_to_balance_idx),
                     "balancing child page with overflowed parent not yet implemented"
                 );
                 turso_assert!(
-                    page_to_balance_idx <= parent_contents.cell_count(),
+                    page_to_balance_idx <= number_of_cells_in_parent,
                     "page_to_balance_idx={page_to_balance_idx} is out of bounds for parent cell count {number_of_cells_in_parent}"
                 );
                 // As there will be at maximum 3 pages used to balance:
@@ -2402,8 +2427,9 @@ impl BTreeCursor {
                 let right_pointer = if last_sibling_is_right_pointer {
                     parent_contents.rightmost_pointer_raw().unwrap()
                 } else {
+                    // The overflow cell of the parent comes before the last divider, see above.
                     let (start_of_cell, _) = parent_contents.cell_get_raw_region(
-                        first_cell_divider + sibling_pointer,
+                        first_cell_divider + sibling_pointer - parent_contents.overflow_cells.len(),
                         self.usable_space(),
                     );
                     let buf = parent_contents.as_ptr().as_mut_ptr();
@@ -2431,14 +2457,27 @@ impl BTreeCursor {
                         );
                     }
                     pages_to_balance[i].replace(page);
-                    turso_assert!(
-                        parent_contents.overflow_cells.is_empty(),
-                        "overflow in parent is not yet implented while balancing it"
-                    );
                     if i == 0 {
                         break;
                     }
                     let next_cell_divider = i + first_cell_divider - 1;
+                    if let Some(overflow_cell) = parent_contents
+                        .overflow_cells
+                        .first()
+                        .filter(|overflow_cell| overflow_cell.index == next_cell_divider)
+                    {
+                        pgno = read_u32(&overflow_cell.payload, 0);
+                        continue;
+                    }
+                    let next_cell_divider = if parent_contents
+                        .overflow_cells
+                        .first()
+                        .is_some_and(|overflow_cell| overflow_cell.index < next_cell_divider)
+                    {
+                        next_cell_divider - 1
+                    } else {
+                        next_cell_divider
+                    };
                     pgno = match parent_contents.cell_get(next_cell_divider, self.usable_space())? {
                         BTreeCell::TableInteriorCell(TableInteriorCell {
                             left_child_page, ..
@@ -2502,7 +2541,7 @@ impl BTreeCursor {
                 let parent_is_root = !self.stack.has_parent();
 
                 turso_assert!(
-                    parent_contents.overflow_cells.is_empty(),
+                    parent_contents.overflow_cells.len() <= 1,
                     "overflow parent not yet implemented"
                 );
 
@@ -2529,6 +2568,21 @@ impl BTreeCursor {
                     }
                     // Since we know we have a left sibling, take the divider that points to left sibling of this page
                     let cell_idx = balance_info.first_divider_cell + i;
+                    if parent_contents
+                        .overflow_cells
+                        .first()
+                        .is_some_and(|overflow_cell| overflow_cell.index == cell_idx)
+                    {
+                        // The divider overflowed, so it is not in the parent and there is nothing to drop.
+                        let overflow_cell = parent_contents.overflow_cells.pop().unwrap();
+                        total_cells_to_redistribute += 1;
+                        balance_info.divider_cell_payloads[i]
+                            .replace(Pin::into_inner(overflow_cell.payload));
+                        continue;
+                    }
+                    // Dividers are visited right to left, so the ones after the overflow cell sit one
+                    // position to the left in the parent.
+                    let cell_idx = cell_idx - parent_contents.overflow_cells.len();
                     let (cell_start, cell_len) =
                         parent_contents.cell_get_raw_region(cell_idx, self.usable_space());
                     let buf = parent_contents.as_ptr();
@@ -3847,12 +3901,12 @@ impl BTreeCursor {
         let mut high = if cell_count > 0 { cell_count - 1 } else { 0 };
         let mut result_index = cell_count;
         if self.find_cell_state.0.is_some() {
-            (low, high) = self.find_cell_state.get_state();
+            (low, high, result_index) = self.find_cell_state.get_state();
         }
 
         while low <= high && cell_count > 0 {
             let mid = low + (high - low) / 2;
-            self.find_cell_state.set((low, high));
+            self.find_cell_state.set((low, high, result_index));
             let cell = page.cell_get(mid, self.usable_space())?;
 
             let comparison_result = match cell {
@@ -3879,11 +3933,12 @@ impl BTreeCursor {
                     ));
 
                     let key_values = key.to_index_key_values();
+                    let key_len = self.index_key_len(key_values.len());
                     let record = self.get_immutable_record();
                     let record = record.as_ref().unwrap();
-                    let record_same_number_cols = &record.get_values()[..key_values.len()];
+                    let record_same_number_cols = &record.get_values()[..key_len];
                     compare_immutable(
-                        key_values.as_slice(),
+                        &key_values[..key_len],
                         record_same_number_cols,
                         self.key_sort_order(),
                         &self.collations,
@@ -3967,6 +4022,8 @@ impl BTreeCursor {
 
     #[instrument(skip_all, level = Level::INFO)]
     pub fn rewind(&mut self) -> Result<IOResult<()>> {
+        // The cursor might have been set to a NULL row by an earlier unmatched outer join row.
+        self.set_null_flag(false);
         if self.mv_cursor.is_some() {
             let cursor_has_record = return_if_io!(self.get_next_record());
             self.invalidate_record();
@@ -3984,6 +4041,7 @@ impl BTreeCursor {
     #[instrument(skip_all, level = Level::INFO)]
     pub fn last(&mut self) -> Result<IOResult<()>> {
         assert!(self.mv_cursor.is_none());
+        self.set_null_flag(false);
         let cursor_has_record = return_if_io!(self.move_to_rightmost());
         self.has_record.replace(cursor_has_record);
         self.invalidate_record();
@@ -4343,6 +4401,7 @@ impl BTreeCursor {
                     if !contents.is_leaf() {
                         delete_info.state = DeleteState::InteriorNodeReplacement {
                             cell_idx,
+                            cell_depth: self.stack.current(),
                             original_child_pointer,
                             post_balancing_seek_key,
                         };
@@ -4353,6 +4412,7 @@ impl BTreeCursor {
                         let delete_info = self.state.mut_delete_info().unwrap();
                         delete_info.state = DeleteState::CheckNeedsBalancing {
                             rightmost_cell_was_dropped: is_last_cell,
+                            interior_cell_depth: None,
                             post_balancing_seek_key,
                         };
                     }
@@ -4360,6 +4420,7 @@ impl BTreeCursor {
 
                 DeleteState::InteriorNodeReplacement {
                     cell_idx,
+                    cell_depth,
                     original_child_pointer,
                     post_balancing_seek_key,
                 } => {
@@ -4409,7 +4470,9 @@ impl BTreeCursor {
                         (cell_payload, leaf_cell_idx)
                     };
 
-                    let parent_page = self.stack.parent_page().unwrap();
+                    // The interior page is not necessarily the parent of the leaf, the predecessor is
+                    // the last cell of the rightmost leaf of the left subtree.
+                    let parent_page = self.stack.page_at(cell_depth);
                     let leaf_page = self.stack.top();
 
                     parent_page.get().set_dirty();
@@ -4440,15 +4503,27 @@ impl BTreeCursor {
                         drop_cell(leaf_contents, leaf_cell_idx, self.usable_space() as u16)?;
                     }
 
+                    // Point the pages of the stack at the children leading to the leaf, balancing
+                    // uses them to find the siblings. prev() leaves the interior page one cell before
+                    // the replaced cell and the pages below it past their last cell.
+                    self.stack.set_cell_index_at(cell_depth, cell_idx as i32);
+                    for depth in cell_depth + 1..self.stack.current() {
+                        let page = self.stack.page_at(depth);
+                        let cell_count = page.get().get_contents().cell_count();
+                        self.stack.set_cell_index_at(depth, cell_count as i32);
+                    }
+
                     let delete_info = self.state.mut_delete_info().unwrap();
                     delete_info.state = DeleteState::CheckNeedsBalancing {
                         rightmost_cell_was_dropped: false,
+                        interior_cell_depth: Some(cell_depth),
                         post_balancing_seek_key,
                     };
                 }
 
                 DeleteState::CheckNeedsBalancing {
                     rightmost_cell_was_dropped,
+                    interior_cell_depth,
                     post_balancing_seek_key,
                 } => {
                     let page = self.stack.top();
@@ -4457,8 +4532,11 @@ impl BTreeCursor {
                     let page = page.get();
                     let contents = page.get().contents.as_ref().unwrap();
                     let free_space = compute_free_space(contents, self.usable_space() as u16);
-                    let needs_balancing = self.stack.has_parent()
-                        && free_space as usize * 3 > self.usable_space() * 2;
+                    // A cell taken from the leaf to replace a cell of an interior page always goes
+                    // through balancing, which checks the interior page too and repositions the cursor.
+                    let needs_balancing = interior_cell_depth.is_some()
+                        || (self.stack.has_parent()
+                            && free_space as usize * 3 > self.usable_space() * 2);
 
                     if rightmost_cell_was_dropped {
                         // If we drop a cell in the middle, e.g. our current index is 2 and we drop 'c' from [a,b,c,d,e], then we don't need to retreat index,
@@ -4478,10 +4556,9 @@ impl BTreeCursor {
                         }
                         delete_info.state = DeleteState::WaitForBalancingToComplete {
                             target_key: post_balancing_seek_key.unwrap(),
+                            interior_cell_depth,
                         }
                     } else {
-                        // FIXME: if we deleted something from an interior page, this is now the leaf page from where a replacement cell
-                        // was taken in InteriorNodeReplacement. We must also check if the parent needs balancing!!!
                         self.stack.retreat();
                         self.state = CursorState::None;
                         return Ok(IOResult::Done(()));
@@ -4490,7 +4567,10 @@ impl BTreeCursor {
                     // self.save_context();
                 }
 
-                DeleteState::WaitForBalancingToComplete { target_key } => {
+                DeleteState::WaitForBalancingToComplete {
+                    target_key,
+                    interior_cell_depth,
+                } => {
                     let delete_info = self.state.mut_delete_info().unwrap();
 
                     // Switch the CursorState to Write state for balancing
@@ -4501,14 +4581,29 @@ impl BTreeCursor {
                         // TODO(Krishna): Add second balance in the case where deletion causes cursor to end up
                         // a level deeper.
                         IOResult::Done(()) => {
-                            let write_info = match &self.state {
+                            let mut write_info = match &self.state {
                                 CursorState::Write(wi) => wi.clone(),
                                 _ => unreachable!("Balance operation changed cursor state"),
                             };
 
-                            // Move to seek state
+                            let state = match interior_cell_depth {
+                                // Balancing the leaf stopped below the interior page whose cell was
+                                // replaced, which may have overflowed or underflowed, so balance it too.
+                                Some(depth) if self.stack.current() > depth => {
+                                    while self.stack.current() > depth {
+                                        self.stack.pop();
+                                    }
+                                    write_info.state = WriteState::BalanceStart;
+                                    DeleteState::WaitForBalancingToComplete {
+                                        target_key,
+                                        interior_cell_depth: None,
+                                    }
+                                }
+                                // Move to seek state
+                                _ => DeleteState::SeekAfterBalancing { target_key },
+                            };
                             self.state = CursorState::Delete(DeleteInfo {
-                                state: DeleteState::SeekAfterBalancing { target_key },
+                                state,
                                 balance_write_info: Some(write_info),
                             });
                         }
@@ -4522,7 +4617,10 @@ impl BTreeCursor {
                             };
 
                             self.state = CursorState::Delete(DeleteInfo {
-                                state: DeleteState::WaitForBalancingToComplete { target_key },
+                                state: DeleteState::WaitForBalancingToComplete {
+                                    target_key,
+                                    interior_cell_depth,
+                                },
                                 balance_write_info: Some(write_info),
                             });
                             return Ok(IOResult::IO);
@@ -4539,7 +4637,23 @@ impl BTreeCursor {
                     };
                     // We want to end up pointing at the row to the left of the position of the row we deleted, so
                     // that after we call next() in the loop,the next row we delete will again be the same position as this one.
-                    return_if_io!(self.seek(key, SeekOp::LT));
+                    let seek_result = return_if_io!(self.seek(key, SeekOp::LT));
+                    if matches!(seek_result, SeekResult::TryAdvance) {
+                        let delete_info = self.state.mut_delete_info().unwrap();
+                        delete_info.state = DeleteState::RetreatAfterBalancing;
+                        continue;
+                    }
+
+                    self.state = CursorState::None;
+                    return Ok(IOResult::Done(()));
+                }
+
+                DeleteState::RetreatAfterBalancing => {
+                    if !return_if_io!(self.prev()) {
+                        // The deleted row was the first one: move before the new first row, as
+                        // rewind() does, so that next() returns it.
+                        self.move_to_root()?;
+                    }
 
                     self.state = CursorState::None;
                     return Ok(IOResult::Done(()));
@@ -5536,21 +5650,21 @@ impl PageStack {
         self.current_page.get() > 0
     }
 
+    /// Get the page at the given position of the stack, the root page being at 0.
+    fn page_at(&self, depth: usize) -> BTreePage {
+        assert!(depth <= self.current());
+        self.stack.borrow()[depth].as_ref().unwrap().clone()
+    }
+
+    /// Set the cell index of the page at the given position of the stack.
+    fn set_cell_index_at(&self, depth: usize, idx: i32) {
+        assert!(depth <= self.current());
+        self.cell_indices.borrow_mut()[depth] = idx;
+    }
+
     fn clear(&self) {
         self.current_page.set(-1);
     }
-    pub fn parent_page(&self) -> Option<BTreePage> {
-        if self.current_page.get() > 0 {
-            Some(
-                self.stack.borrow()[self.current() - 1]
-                    .as_ref()
-                    .unwrap()
-                    .clone(),
-            )
-        } else {
-            None
-        }
-    }
 }
 
 /// Used for redistributing cells during a balance operation.
@@ -5677,7 +5791,10 @@ fn edit_page(
         number_new_cells,
         cell_array.cell_payloads.len()
     );
-    let end_old_cells = start_old_cells + page.cell_count() + page.overflow_cells.len();
+    // The overflow cells are written back into the page below, take them out so that they are not
+    // confused with the cells inserted by this edit.
+    let overflow_cells = std::mem::take(&mut page.overflow_cells);
+    let end_old_cells = start_old_cells + page.cell_count() + overflow_cells.len();
     let end_new_cells = start_new_cells + number_new_cells;
     let mut count_cells = page.cell_count();
     if start_old_cells < start_new_cells {
@@ -5717,8 +5834,7 @@ fn edit_page(
     }
     // TODO: overflow cells
     debug_validate_cells!(page, usable_space);
-    for i in 0..page.overflow_cells.len() {
-        let overflow_cell = &page.overflow_cells[i];
+    for overflow_cell in &overflow_cells {
         // cell index in context of new list of cells that should be in the page
         if start_old_cells + overflow_cell.index >= start_new_cells {
             let cell_idx = start_old_cells + overflow_cell.index - start_new_cells;
@@ -6087,7 +6203,9 @@ fn insert_into_cell(
     );
     let free = compute_free_space(page, usable_space);
     let enough_space = payload.len() + CELL_PTR_SIZE_BYTES <= free as usize;
-    if !enough_space {
+    // Once a page has an overflow cell, the indexes of the cells after it no longer match their
+    // position in the page, so the cells inserted after it are overflow cells too.
+    if !enough_space || !page.overflow_cells.is_empty() {
         // add to overflow cell
         page.overflow_cells.push(OverflowCell {
             index: cell_idx,
@@ -6457,7 +6575,7 @@ mod tests {
                 .unwrap();
         }
         let io: Arc<dyn IO> = Arc::new(PlatformIO::new().unwrap());
-        let db = Database::open_file(io.clone(), path.to_str().unwrap(), false, false).unwrap();
+        let db = Database::open_file(io.clone(), path.to_str().unwrap(), false, true).unwrap();
 
         db
     }
@@ -6720,7 +6838,7 @@ mod tests {
     fn empty_btree() -> (Rc<Pager>, usize, Arc<Database>, Arc<Connection>) {
         #[allow(clippy::arc_with_non_send_sync)]
         let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
-        let db = Database::open_file(io.clone(), "test.db", false, false).unwrap();
+        let db = Database::open_file(io.clone(), "test.db", false, true).unwrap();
         let conn = db.connect().unwrap();
         let pager = conn.pager.borrow().clone();
 
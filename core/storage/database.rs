@@ -19,6 +19,8 @@ pub trait DatabaseStorage: Send + Sync {
     ) -> Result<()>;
     fn sync(&self, c: Completion) -> Result<()>;
     fn size(&self) -> Result<u64>;
+    /// Shrinks the storage to `len` bytes, dropping the pages past it.
+    fn truncate(&self, len: u64) -> Result<()>;
 }
 
 #[cfg(feature = "fs")]
@@ -73,6 +75,11 @@ impl DatabaseStorage for DatabaseFile {
     fn size(&self) -> Result<u64> {
         self.file.size()
     }
+
+    #[instrument(skip_all, level = Level::INFO)]
+    fn truncate(&self, len: u64) -> Result<()> {
+        self.file.truncate(len)
+    }
 }
 
 #[cfg(feature = "fs")]
@@ -132,6 +139,11 @@ impl DatabaseStorage for FileMemoryStorage {
     fn size(&self) -> Result<u64> {
         self.file.size()
     }
+
+    #[instrument(skip_all, level = Level::INFO)]
+    fn truncate(&self, len: u64) -> Result<()> {
+        self.file.truncate(len)
+    }
 }
 
 impl FileMemoryStorage {
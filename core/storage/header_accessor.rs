@@ -19,7 +19,7 @@ const HEADER_OFFSET_MAX_EMBED_FRAC: usize = 21;
 const HEADER_OFFSET_MIN_EMBED_FRAC: usize = 22;
 const HEADER_OFFSET_MIN_LEAF_FRAC: usize = 23;
 const HEADER_OFFSET_CHANGE_COUNTER: usize = 24;
-const HEADER_OFFSET_DATABASE_SIZE: usize = 28;
+pub(crate) const HEADER_OFFSET_DATABASE_SIZE: usize = 28;
 const HEADER_OFFSET_FREELIST_TRUNK_PAGE: usize = 32;
 const HEADER_OFFSET_FREELIST_PAGES: usize = 36;
 const HEADER_OFFSET_SCHEMA_COOKIE: usize = 40;
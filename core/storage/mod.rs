@@ -18,6 +18,7 @@ pub(crate) mod page_cache;
 #[allow(clippy::arc_with_non_send_sync)]
 pub(crate) mod pager;
 pub(crate) mod sqlite3_ondisk;
+pub(crate) mod vacuum;
 #[allow(clippy::arc_with_non_send_sync)]
 pub(crate) mod wal;
 
@@ -6,11 +6,11 @@ use crate::storage::header_accessor;
 use crate::storage::sqlite3_ondisk::{self, DatabaseHeader, PageContent, PageType};
 use crate::storage::wal::{CheckpointResult, Wal};
 use crate::types::IOResult;
-use crate::{return_if_io, Completion};
+use crate::{return_if_io, turso_assert, Completion};
 use crate::{Buffer, Connection, LimboError, Result};
 use parking_lot::RwLock;
 use std::cell::{Cell, OnceCell, RefCell, UnsafeCell};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
@@ -183,6 +183,13 @@ struct FlushInfo {
     in_flight_writes: Rc<RefCell<usize>>,
 }
 
+/// Journal of a savepoint: the contents of the pages that were already dirty when it was opened.
+/// Dirty pages are only appended to the WAL on commit, so rolling back to a savepoint restores
+/// these pages and drops the pages dirtied since, without touching the WAL.
+struct SavepointJournal {
+    pages: HashMap<usize, Vec<u8>>,
+}
+
 /// Track the state of the auto-vacuum mode.
 #[derive(Clone, Copy, Debug)]
 pub enum AutoVacuumMode {
@@ -209,6 +216,8 @@ pub struct Pager {
     /// I/O interface for input/output operations.
     pub io: Arc<dyn crate::io::IO>,
     dirty_pages: Rc<RefCell<HashSet<usize>>>,
+    /// Journals of the open savepoints, outermost first.
+    savepoints: RefCell<Vec<SavepointJournal>>,
 
     flush_info: RefCell<FlushInfo>,
     checkpoint_state: RefCell<CheckpointState>,
@@ -271,6 +280,7 @@ impl Pager {
             page_cache,
             io,
             dirty_pages: Rc::new(RefCell::new(HashSet::new())),
+            savepoints: RefCell::new(Vec::new()),
             flush_info: RefCell::new(FlushInfo {
                 state: FlushState::Start,
                 in_flight_writes: Rc::new(RefCell::new(0)),
@@ -760,6 +770,47 @@ impl Pager {
         dirty_pages.insert(page_id);
     }
 
+    /// Returns true once the dirty pages take up half of the page cache. Pages can't be evicted
+    /// while they are dirty, so a transaction that keeps writing past this point has to
+    /// [spill](Self::spill_dirty_pages) them or it will run out of cache.
+    pub fn should_spill(&self) -> bool {
+        self.dirty_pages.borrow().len() >= self.page_cache.read().capacity() / 2
+    }
+
+    /// Writes the dirty pages to the WAL as frames of the ongoing write transaction, so that the
+    /// page cache can evict them. The frames are only visible to this connection until
+    /// [Self::cacheflush] appends the commit frame, and are dropped by a rollback.
+    ///
+    /// Savepoint journals are keyed by the pages dirtied after the savepoint was opened, so
+    /// pages can't be spilled while a savepoint is open.
+    pub fn spill_dirty_pages(&self) -> Result<IOResult<()>> {
+        turso_assert!(
+            self.savepoints.borrow().is_empty(),
+            "cannot spill dirty pages with open savepoints"
+        );
+        let in_flight_writes = self.flush_info.borrow().in_flight_writes.clone();
+        let dirty_pages = std::mem::take(&mut *self.dirty_pages.borrow_mut());
+        if dirty_pages.is_empty() {
+            // Wait for the frames of the previous call to be written.
+            if *in_flight_writes.borrow() > 0 {
+                return Ok(IOResult::IO);
+            }
+            return Ok(IOResult::Done(()));
+        }
+        let mut cache = self.page_cache.write();
+        for page_id in dirty_pages {
+            let page = cache
+                .get(&PageCacheKey::new(page_id))
+                .expect("dirty page must be in the page cache");
+            // A db_size of 0 marks the frame as not being a commit frame.
+            self.wal
+                .borrow_mut()
+                .append_frame(page.clone(), 0, in_flight_writes.clone())?;
+            page.clear_dirty();
+        }
+        Ok(IOResult::IO)
+    }
+
     pub fn wal_frame_count(&self) -> Result<u64> {
         Ok(self.wal.borrow().get_max_frame_in_wal())
     }
@@ -801,6 +852,7 @@ impl Pager {
                         cache.clear().unwrap();
                     }
                     self.dirty_pages.borrow_mut().clear();
+                    self.savepoints.borrow_mut().clear();
                     self.flush_info.borrow_mut().state = FlushState::WaitAppendFrames;
                     return Ok(IOResult::IO);
                 }
@@ -1208,6 +1260,79 @@ impl Pager {
         (page_size - reserved_space) as usize
     }
 
+    /// Opens a savepoint, journaling the pages dirtied so far by the current transaction.
+    pub fn open_savepoint(&self) {
+        let mut cache = self.page_cache.write();
+        let pages = self
+            .dirty_pages
+            .borrow()
+            .iter()
+            .map(|page_id| {
+                let page = cache
+                    .peek(&PageCacheKey::new(*page_id), false)
+                    .expect("dirty page must be in the page cache");
+                let contents = page.get().contents.as_ref().unwrap().as_ptr().to_vec();
+                (*page_id, contents)
+            })
+            .collect();
+        self.savepoints
+            .borrow_mut()
+            .push(SavepointJournal { pages });
+    }
+
+    /// Releases the savepoint at `depth` and all the savepoints opened after it.
+    pub fn release_savepoint(&self, depth: usize) {
+        self.savepoints.borrow_mut().truncate(depth);
+    }
+
+    /// Undoes the changes made to pages since the savepoint at `depth` was opened.
+    /// The savepoint stays open, the savepoints opened after it are released.
+    #[instrument(skip_all, level = Level::INFO)]
+    pub fn rollback_to_savepoint(&self, depth: usize) -> Result<()> {
+        let mut savepoints = self.savepoints.borrow_mut();
+        savepoints.truncate(depth + 1);
+        let Some(savepoint) = savepoints.get(depth) else {
+            return Err(LimboError::InternalError(format!(
+                "no journal for savepoint at depth {depth}"
+            )));
+        };
+        let mut cache = self.page_cache.write();
+        let mut dirty_pages = self.dirty_pages.borrow_mut();
+        let mut dropped_pages = Vec::new();
+        for page_id in dirty_pages.iter() {
+            let page_key = PageCacheKey::new(*page_id);
+            let page = cache
+                .peek(&page_key, false)
+                .expect("dirty page must be in the page cache");
+            match savepoint.pages.get(page_id) {
+                Some(contents) => {
+                    // The page was already dirty when the savepoint was opened: restore it.
+                    page.get()
+                        .contents
+                        .as_ref()
+                        .unwrap()
+                        .as_ptr()
+                        .copy_from_slice(contents);
+                }
+                None => {
+                    // The page was dirtied after the savepoint was opened: drop it so that
+                    // it is read again from the WAL or the database file.
+                    page.clear_dirty();
+                    cache.delete(page_key).map_err(|e| {
+                        LimboError::InternalError(format!(
+                            "failed to drop page {page_id} from cache: {e:?}"
+                        ))
+                    })?;
+                    dropped_pages.push(*page_id);
+                }
+            }
+        }
+        for page_id in dropped_pages {
+            dirty_pages.remove(&page_id);
+        }
+        Ok(())
+    }
+
     #[instrument(skip_all, level = Level::INFO)]
     pub fn rollback(
         &self,
@@ -1216,12 +1341,14 @@ impl Pager {
     ) -> Result<(), LimboError> {
         tracing::debug!(schema_did_change);
         self.dirty_pages.borrow_mut().clear();
+        self.savepoints.borrow_mut().clear();
+        connection.savepoints.borrow_mut().clear();
         let mut cache = self.page_cache.write();
         cache.unset_dirty_all_pages();
         cache.clear().expect("failed to clear page cache");
         if schema_did_change {
             let prev_schema = connection._db.schema.read().clone();
-            connection.schema.replace(prev_schema);
+            *connection.schema_mut() = prev_schema;
         }
         self.wal.borrow_mut().rollback()?;
 
@@ -0,0 +1,350 @@
+//! VACUUM support.
+//!
+//! A database is vacuumed by rebuilding every b-tree listed in `sqlite_schema` into a fresh,
+//! empty database: entries are read in order from the source b-tree and inserted into a newly
+//! created one through the regular [BTreeCursor] insert path. The resulting database has an empty
+//! freelist and densely packed b-trees.
+//!
+//! More info: https://www.sqlite.org/lang_vacuum.html
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::result::LimboResult;
+use crate::schema::{BTreeTable, Index, Schema};
+use crate::storage::btree::{BTreeCursor, BTreeKey};
+use crate::storage::header_accessor;
+use crate::storage::pager::{CreateBTreeFlags, PageRef, Pager};
+use crate::storage::sqlite3_ondisk::DATABASE_HEADER_PAGE_ID;
+use crate::types::{IOResult, ImmutableRecord, Value};
+use crate::util::normalize_ident;
+use crate::{turso_assert, LimboError, Result};
+
+/// Number of columns of `sqlite_schema`: type, name, tbl_name, rootpage and sql.
+const SCHEMA_NUM_COLUMNS: usize = 5;
+const SCHEMA_ROOT_PAGE_COLUMN: usize = 3;
+
+/// A row of `sqlite_schema`.
+struct SchemaEntry {
+    rowid: i64,
+    values: Vec<Value>,
+}
+
+impl SchemaEntry {
+    fn text(&self, column: usize) -> Option<&str> {
+        match self.values.get(column) {
+            Some(Value::Text(text)) => Some(text.as_str()),
+            _ => None,
+        }
+    }
+
+    fn kind(&self) -> &str {
+        self.text(0).unwrap_or_default()
+    }
+
+    fn name(&self) -> &str {
+        self.text(1).unwrap_or_default()
+    }
+
+    fn tbl_name(&self) -> &str {
+        self.text(2).unwrap_or_default()
+    }
+
+    fn root_page(&self) -> usize {
+        match self.values.get(SCHEMA_ROOT_PAGE_COLUMN) {
+            Some(Value::Integer(root_page)) if *root_page > 0 => *root_page as usize,
+            _ => 0,
+        }
+    }
+
+    fn sql(&self) -> Option<&str> {
+        self.text(4)
+    }
+}
+
+/// Copies the content of the database behind `src` into the empty database behind `dest`.
+///
+/// `src` must be in a read transaction and `dest` in a write transaction. `schema` is the schema
+/// of the source database, used to find out the key layout of its indexes. Every entry of
+/// `sqlite_schema` keeps its rowid, only the root pages change. The schema cookie of `dest` is
+/// set one past the source's so that connections holding the source schema reload it.
+pub(crate) fn copy_database(schema: &Schema, src: &Rc<Pager>, dest: &Rc<Pager>) -> Result<()> {
+    let entries = read_schema_entries(src)?;
+    let indexes = source_indexes(schema, &entries)?;
+    let mut schema_cursor = BTreeCursor::new_table(
+        None,
+        dest.clone(),
+        DATABASE_HEADER_PAGE_ID,
+        SCHEMA_NUM_COLUMNS,
+    );
+    for mut entry in entries {
+        let src_root_page = entry.root_page();
+        if src_root_page != 0 {
+            let index = match entry.kind() {
+                "index" => Some(indexes.get(&normalize_ident(entry.name())).ok_or_else(|| {
+                    LimboError::Corrupt(format!("no such index: {}", entry.name()))
+                })?),
+                _ => None,
+            };
+            let flags = match index {
+                Some(_) => CreateBTreeFlags::new_index(),
+                None => CreateBTreeFlags::new_table(),
+            };
+            let dest_root_page = run_until_done(dest, || dest.btree_create(&flags))? as usize;
+            copy_btree(
+                src,
+                src_root_page,
+                dest,
+                dest_root_page,
+                index.map(|index| index.as_ref()),
+            )?;
+            entry.values[SCHEMA_ROOT_PAGE_COLUMN] = Value::Integer(dest_root_page as i64);
+        }
+        let record = ImmutableRecord::from_values(&entry.values, entry.values.len());
+        let key = BTreeKey::new_table_rowid(entry.rowid, Some(&record));
+        insert(dest, &mut schema_cursor, &key)?;
+    }
+    copy_header(src, dest)
+}
+
+/// Overwrites the database behind `dest` with the pages of the database behind `src`.
+///
+/// `src` must be in a read transaction and `dest` in a write transaction. Pages are spilled to the
+/// WAL of `dest` as they are copied, the copy becomes visible when the transaction commits. Pages
+/// of `dest` past the size of `src` are left as they are: they are no longer part of the database once the new
+/// header is committed, and are cut off the file by the checkpoint that backfills it.
+pub(crate) fn overwrite_database(src: &Pager, dest: &Pager) -> Result<()> {
+    let src_size = header_accessor::get_database_size(src)? as usize;
+    let dest_size = header_accessor::get_database_size(dest)? as usize;
+    // The header goes last, as allocating pages past the end of `dest` reads its database size.
+    for page_id in (2..=src_size).chain(std::iter::once(DATABASE_HEADER_PAGE_ID)) {
+        let src_page = read_loaded_page(src, page_id)?;
+        let dest_page = if page_id <= dest_size {
+            read_loaded_page(dest, page_id)?
+        } else {
+            let page = dest.allocate_page()?;
+            turso_assert!(
+                page.get().id == page_id,
+                "allocated page {} while overwriting page {page_id}",
+                page.get().id
+            );
+            page
+        };
+        dest_page
+            .get_contents()
+            .as_ptr()
+            .copy_from_slice(src_page.get_contents().as_ptr());
+        dest_page.set_dirty();
+        dest.add_dirty(page_id);
+        spill_if_needed(dest)?;
+    }
+    Ok(())
+}
+
+fn read_loaded_page(pager: &Pager, page_id: usize) -> Result<PageRef> {
+    let page = pager.read_page(page_id)?;
+    while page.is_locked() {
+        pager.io.run_once()?;
+    }
+    Ok(page)
+}
+
+/// Begins a read transaction on `pager`, and a write transaction too if `write` is set.
+pub(crate) fn begin_tx(pager: &Pager, write: bool) -> Result<()> {
+    if let LimboResult::Busy = run_until_done(pager, || pager.begin_read_tx())? {
+        return Err(LimboError::Busy);
+    }
+    if write {
+        if let LimboResult::Busy = run_until_done(pager, || pager.begin_write_tx())? {
+            pager.end_read_tx()?;
+            return Err(LimboError::Busy);
+        }
+    }
+    Ok(())
+}
+
+fn read_schema_entries(src: &Rc<Pager>) -> Result<Vec<SchemaEntry>> {
+    let mut cursor = BTreeCursor::new_table(
+        None,
+        src.clone(),
+        DATABASE_HEADER_PAGE_ID,
+        SCHEMA_NUM_COLUMNS,
+    );
+    let mut entries = Vec::new();
+    run_until_done(src, || cursor.rewind())?;
+    while !cursor.is_empty() {
+        let rowid = run_until_done(src, || cursor.rowid())?
+            .ok_or_else(|| LimboError::Corrupt("sqlite_schema row without rowid".to_string()))?;
+        let record = current_record(src, &cursor)?;
+        let values = record
+            .get_values()
+            .iter()
+            .map(|value| value.to_owned())
+            .collect();
+        entries.push(SchemaEntry { rowid, values });
+        run_until_done(src, || cursor.next())?;
+    }
+    Ok(entries)
+}
+
+/// Returns the indexes of the source database by normalized name.
+///
+/// Indexes are taken from `schema` when it has them, and built from their `sqlite_schema` entry
+/// otherwise (e.g. when indexes are disabled), as cursors need the key layout to insert into an
+/// index b-tree.
+fn source_indexes(schema: &Schema, entries: &[SchemaEntry]) -> Result<HashMap<String, Arc<Index>>> {
+    let mut indexes = HashMap::new();
+    let mut automatic_indexes: HashMap<&str, Vec<(String, usize)>> = HashMap::new();
+    for entry in entries {
+        if entry.kind() != "index" || entry.root_page() == 0 {
+            continue;
+        }
+        let name = normalize_ident(entry.name());
+        if let Some(index) = schema.get_index(entry.tbl_name(), &name) {
+            indexes.insert(name, index.clone());
+            continue;
+        }
+        match entry.sql() {
+            Some(sql) => {
+                let table = source_table(schema, entry.tbl_name())?;
+                let index = Index::from_sql(sql, entry.root_page(), &table)?;
+                indexes.insert(name, Arc::new(index));
+            }
+            None => automatic_indexes
+                .entry(entry.tbl_name())
+                .or_default()
+                .push((entry.name().to_string(), entry.root_page())),
+        }
+    }
+    for (table_name, automatic_indexes) in automatic_indexes {
+        let table = source_table(schema, table_name)?;
+        for index in Index::automatic_from_primary_key_and_unique(&table, automatic_indexes)? {
+            indexes.insert(index.name.clone(), Arc::new(index));
+        }
+    }
+    Ok(indexes)
+}
+
+fn source_table(schema: &Schema, table_name: &str) -> Result<Rc<BTreeTable>> {
+    schema
+        .get_btree_table(table_name)
+        .ok_or_else(|| LimboError::Corrupt(format!("no such table: {table_name}")))
+}
+
+/// Inserts every entry of the b-tree rooted at `src_root_page` into the empty b-tree rooted at
+/// `dest_root_page`. Entries are visited in key order, so the destination is filled left to right.
+fn copy_btree(
+    src: &Rc<Pager>,
+    src_root_page: usize,
+    dest: &Rc<Pager>,
+    dest_root_page: usize,
+    index: Option<&Index>,
+) -> Result<()> {
+    let (mut src_cursor, mut dest_cursor) = match index {
+        Some(index) => {
+            let collations: Vec<_> = index
+                .columns
+                .iter()
+                .map(|column| column.collation.unwrap_or_default())
+                .collect();
+            let num_columns = index.columns.len() + index.has_rowid as usize;
+            (
+                BTreeCursor::new_index(
+                    None,
+                    src.clone(),
+                    src_root_page,
+                    index,
+                    collations.clone(),
+                    num_columns,
+                ),
+                BTreeCursor::new_index(
+                    None,
+                    dest.clone(),
+                    dest_root_page,
+                    index,
+                    collations,
+                    num_columns,
+                ),
+            )
+        }
+        // Table records are copied whole and never parsed, no column count needed.
+        None => (
+            BTreeCursor::new_table(None, src.clone(), src_root_page, 0),
+            BTreeCursor::new_table(None, dest.clone(), dest_root_page, 0),
+        ),
+    };
+    run_until_done(src, || src_cursor.rewind())?;
+    while !src_cursor.is_empty() {
+        let record = current_record(src, &src_cursor)?;
+        match index {
+            Some(_) => insert(dest, &mut dest_cursor, &BTreeKey::new_index_key(&record))?,
+            None => {
+                let rowid = run_until_done(src, || src_cursor.rowid())?.ok_or_else(|| {
+                    LimboError::Corrupt(format!("row without rowid in page {src_root_page}"))
+                })?;
+                insert(
+                    dest,
+                    &mut dest_cursor,
+                    &BTreeKey::new_table_rowid(rowid, Some(&record)),
+                )?;
+            }
+        }
+        run_until_done(src, || src_cursor.next())?;
+    }
+    Ok(())
+}
+
+/// Inserts `key` with `cursor`, spilling the dirty pages of `pager` once there are too many of
+/// them: the whole copy happens in a single transaction, which may be larger than the page cache.
+fn insert(pager: &Pager, cursor: &mut BTreeCursor, key: &BTreeKey) -> Result<()> {
+    // Seek only on the first call, the following ones resume the insert that returned IO.
+    run_until_done(pager, || cursor.insert(key, cursor.is_write_in_progress()))?;
+    spill_if_needed(pager)
+}
+
+fn spill_if_needed(pager: &Pager) -> Result<()> {
+    if pager.should_spill() {
+        run_until_done(pager, || pager.spill_dirty_pages())?;
+    }
+    Ok(())
+}
+
+fn current_record(pager: &Pager, cursor: &BTreeCursor) -> Result<ImmutableRecord> {
+    loop {
+        match cursor.record()? {
+            IOResult::Done(Some(record)) => return Ok(record.clone()),
+            IOResult::Done(None) => {
+                return Err(LimboError::Corrupt(format!(
+                    "missing record in page {}",
+                    cursor.root_page()
+                )))
+            }
+            IOResult::IO => pager.io.run_once()?,
+        }
+    }
+}
+
+/// Carries over the persistent header fields that are not derived from the content.
+fn copy_header(src: &Pager, dest: &Pager) -> Result<()> {
+    header_accessor::set_schema_cookie(dest, header_accessor::get_schema_cookie(src)? + 1)?;
+    header_accessor::set_user_version(dest, header_accessor::get_user_version(src)?)?;
+    header_accessor::set_application_id(dest, header_accessor::get_application_id(src)?)?;
+    header_accessor::set_default_page_cache_size(
+        dest,
+        header_accessor::get_default_page_cache_size(src)?,
+    )?;
+    Ok(())
+}
+
+pub(crate) fn run_until_done<T>(
+    pager: &Pager,
+    mut f: impl FnMut() -> Result<IOResult<T>>,
+) -> Result<T> {
+    loop {
+        match f()? {
+            IOResult::Done(value) => return Ok(value),
+            IOResult::IO => pager.io.run_once()?,
+        }
+    }
+}
@@ -30,6 +30,7 @@ use crate::{Completion, Page};
 use self::sqlite3_ondisk::{checksum_wal, PageContent, WAL_MAGIC_BE, WAL_MAGIC_LE};
 
 use super::buffer_pool::BufferPool;
+use super::header_accessor::HEADER_OFFSET_DATABASE_SIZE;
 use super::pager::{PageRef, Pager};
 use super::sqlite3_ondisk::{self, begin_write_btree_page, WalHeader};
 
@@ -379,6 +380,9 @@ struct OngoingCheckpoint {
     min_frame: u64,
     max_frame: u64,
     current_page: u64,
+    /// Database size in pages recorded in the header of the last page 1 backfilled by this
+    /// checkpoint, if any.
+    db_size: Option<u32>,
 }
 
 impl fmt::Debug for OngoingCheckpoint {
@@ -388,6 +392,7 @@ impl fmt::Debug for OngoingCheckpoint {
             .field("min_frame", &self.min_frame)
             .field("max_frame", &self.max_frame)
             .field("current_page", &self.current_page)
+            .field("db_size", &self.db_size)
             .finish()
     }
 }
@@ -750,6 +755,7 @@ impl Wal for WalFile {
                     }
                     self.ongoing_checkpoint.max_frame = max_safe_frame;
                     self.ongoing_checkpoint.current_page = 0;
+                    self.ongoing_checkpoint.db_size = None;
                     self.ongoing_checkpoint.state = CheckpointState::ReadFrame;
                     tracing::trace!(
                         "checkpoint_start(min_frame={}, max_frame={})",
@@ -805,6 +811,13 @@ impl Wal for WalFile {
                     }
                 }
                 CheckpointState::WritePage => {
+                    if self.ongoing_checkpoint.page.get().id
+                        == sqlite3_ondisk::DATABASE_HEADER_PAGE_ID
+                    {
+                        let contents = self.ongoing_checkpoint.page.get_contents();
+                        self.ongoing_checkpoint.db_size =
+                            Some(contents.read_u32_no_offset(HEADER_OFFSET_DATABASE_SIZE));
+                    }
                     self.ongoing_checkpoint.page.set_dirty();
                     begin_write_btree_page(
                         pager,
@@ -848,8 +861,15 @@ impl Wal for WalFile {
                     let everything_backfilled = shared.max_frame.load(Ordering::SeqCst)
                         == self.ongoing_checkpoint.max_frame;
                     if everything_backfilled {
-                        // TODO: Even in Passive mode, if everything was backfilled we should
-                        // truncate and fsync the *db file*
+                        // The db file now holds the latest version of every page, so whatever lies
+                        // past the last committed database size (e.g. after a VACUUM) can be given
+                        // back to the OS. The caller fsyncs the db file after the checkpoint.
+                        if let Some(db_size) = self.ongoing_checkpoint.db_size {
+                            let len = db_size as u64 * self.page_size() as u64;
+                            if pager.db_file.size()? > len {
+                                pager.db_file.truncate(len)?;
+                            }
+                        }
 
                         // To properly reset the *wal file* we will need restart and/or truncate mode.
                         // Currently, it will grow the WAL file indefinetly, but don't resetting is better than breaking.
@@ -986,6 +1006,7 @@ impl WalFile {
                 min_frame: 0,
                 max_frame: 0,
                 current_page: 0,
+                db_size: None,
             },
             checkpoint_threshold: 1000,
             buffer_pool,
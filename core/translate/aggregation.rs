@@ -13,9 +13,17 @@ use super::{
     emitter::{Resolver, TranslateCtx},
     expr::translate_expr,
     plan::{Aggregate, Distinctness, SelectPlan, TableReferences},
+    planner::table_mask_from_expr,
     result_row::emit_select_result,
 };
 
+/// Whether a non-aggregate result column of an aggregate query without GROUP BY reads the
+/// current row of the loop. Columns that don't are computed after the loop instead, so that
+/// they have a value even if there are no rows, e.g. `SELECT NEW.x, count(*) FROM t` in a trigger.
+pub fn nonagg_column_reads_row(expr: &ast::Expr, tables: &TableReferences) -> bool {
+    table_mask_from_expr(expr, tables).map_or(true, |mask| !mask.is_empty())
+}
+
 /// Emits the bytecode for processing an aggregate without a GROUP BY clause.
 /// This is called when the main query execution loop has finished processing,
 /// and we can now materialize the aggregate results.
@@ -43,6 +51,19 @@ pub fn emit_ungrouped_aggregation<'a>(
     }
     t_ctx.resolver.enable_expr_to_reg_cache();
 
+    let col_start = t_ctx.reg_result_cols_start.unwrap();
+    for (i, rc) in plan.result_columns.iter().enumerate().filter(|(_, rc)| {
+        !rc.contains_aggregates && !nonagg_column_reads_row(&rc.expr, &plan.table_references)
+    }) {
+        translate_expr(
+            program,
+            Some(&plan.table_references),
+            &rc.expr,
+            col_start + i,
+            &t_ctx.resolver,
+        )?;
+    }
+
     // This always emits a ResultRow because currently it can only be used for a single row result
     // Limit is None because we early exit on limit 0 and the max rows here is 1
     emit_select_result(
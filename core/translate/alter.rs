@@ -13,7 +13,8 @@ use crate::{
 };
 
 use super::{
-    emitter::TransactionMode, schema::SQLITE_TABLEID, update::translate_update_with_after,
+    emitter::TransactionMode, generated::check_generated_columns, index::index_uses_columns,
+    schema::SQLITE_TABLEID, update::translate_update_with_after,
 };
 
 pub fn translate_alter_table(
@@ -24,14 +25,6 @@ pub fn translate_alter_table(
 ) -> Result<ProgramBuilder> {
     let (table_name, alter_table) = alter;
     let ast::Name(table_name) = table_name.name;
-    if schema.table_has_indexes(&table_name) && !schema.indexes_enabled() {
-        // Let's disable altering a table with indices altogether instead of checking column by
-        // column to be extra safe.
-        crate::bail_parse_error!(
-            "ALTER TABLE for table with indexes is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
-    }
-
     let Some(original_btree) = schema
         .get_table(&table_name)
         .and_then(|table| table.btree())
@@ -66,10 +59,17 @@ pub fn translate_alter_table(
                 )));
             }
 
+            if !btree.has_rowid {
+                return Err(LimboError::ParseError(
+                    "DROP COLUMN is not supported for WITHOUT ROWID tables".to_string(),
+                ));
+            }
+
             if column.unique
                 || btree.unique_sets.as_ref().is_some_and(|set| {
                     set.iter().any(|set| {
-                        set.iter()
+                        set.columns
+                            .iter()
                             .any(|(name, _)| name == &normalize_ident(&column_name))
                     })
                 })
@@ -79,9 +79,31 @@ pub fn translate_alter_table(
                 )));
             }
 
+            if let Some(index) = schema
+                .get_indices(&table_name)
+                .iter()
+                .find(|index| index_uses_columns(&original_btree, index, &[dropped_index]))
+            {
+                return Err(LimboError::ParseError(format!(
+                    "error in index {} after drop column: no such column: {column_name}",
+                    index.name
+                )));
+            }
+
             btree.columns.remove(dropped_index);
 
-            let sql = btree.to_sql();
+            if btree.columns.iter().all(|column| column.is_generated()) {
+                return Err(LimboError::ParseError(format!(
+                    "error in table {table_name} after drop column: must have at least one non-generated column"
+                )));
+            }
+            if let Err(LimboError::ParseError(err)) = check_generated_columns(&btree) {
+                return Err(LimboError::ParseError(format!(
+                    "error in table {table_name} after drop column: {err}"
+                )));
+            }
+
+            let sql = btree.to_sql().replace('\'', "''");
 
             let stmt = format!(
                 r#"
@@ -101,13 +123,15 @@ pub fn translate_alter_table(
                 let root_page = btree.root_page;
                 let table_name = btree.name.clone();
 
-                let cursor_id = program
-                    .alloc_cursor_id(crate::vdbe::builder::CursorType::BTreeTable(original_btree));
+                let cursor_id = program.alloc_cursor_id(
+                    crate::vdbe::builder::CursorType::BTreeTable(original_btree.clone()),
+                );
 
                 program.emit_insn(Insn::OpenWrite {
                     cursor_id,
                     root_page: RegisterOrLiteral::Literal(root_page),
                     name: table_name.clone(),
+                    db: 0,
                 });
 
                 program.cursor_loop(cursor_id, |program, rowid| {
@@ -116,7 +140,8 @@ pub fn translate_alter_table(
                     let mut iter = first_column;
 
                     for i in 0..(column_count + 1) {
-                        if i == dropped_index {
+                        // VIRTUAL generated columns have no slot in the record
+                        if i == dropped_index || original_btree.columns[i].is_virtual() {
                             continue;
                         }
 
@@ -129,7 +154,7 @@ pub fn translate_alter_table(
 
                     program.emit_insn(Insn::MakeRecord {
                         start_reg: first_column,
-                        count: column_count,
+                        count: iter - first_column,
                         dest_reg: record,
                         index_name: None,
                     });
@@ -150,7 +175,7 @@ pub fn translate_alter_table(
                 });
 
                 program.emit_insn(Insn::ParseSchema {
-                    db: usize::MAX, // TODO: This value is unused, change when we do something with it
+                    db: 0,
                     where_clause: None,
                 })
             })?
@@ -158,6 +183,13 @@ pub fn translate_alter_table(
         ast::AlterTableBody::AddColumn(col_def) => {
             let column = Column::from(col_def);
 
+            if column.generated.as_ref().is_some_and(|g| g.stored) {
+                // TODO: sqlite only fails this at runtime, when the table is not empty.
+                return Err(LimboError::ParseError(
+                    "cannot add a STORED column".to_string(),
+                ));
+            }
+
             if let Some(default) = &column.default {
                 if !matches!(
                     default,
@@ -177,6 +209,7 @@ pub fn translate_alter_table(
             }
 
             btree.columns.push(column);
+            check_generated_columns(&btree)?;
 
             let sql = btree.to_sql();
             let mut escaped = String::with_capacity(sql.len());
@@ -209,7 +242,7 @@ pub fn translate_alter_table(
                     p5: 0,
                 });
                 program.emit_insn(Insn::ParseSchema {
-                    db: usize::MAX, // TODO: This value is unused, change when we do something with it
+                    db: 0,
                     where_clause: None,
                 });
             })?
@@ -242,6 +275,7 @@ pub fn translate_alter_table(
                 cursor_id,
                 root_page: RegisterOrLiteral::Literal(sqlite_schema.root_page),
                 name: sqlite_schema.name.clone(),
+                db: 0,
             });
 
             program.cursor_loop(cursor_id, |program, rowid| {
@@ -300,7 +334,7 @@ pub fn translate_alter_table(
                 p5: 0,
             });
             program.emit_insn(Insn::ParseSchema {
-                db: usize::MAX, // TODO: This value is unused, change when we do something with it
+                db: 0,
                 where_clause: None,
             });
 
@@ -329,6 +363,7 @@ pub fn translate_alter_table(
                 cursor_id,
                 root_page: RegisterOrLiteral::Literal(sqlite_schema.root_page),
                 name: sqlite_schema.name.clone(),
+                db: 0,
             });
 
             program.cursor_loop(cursor_id, |program, rowid| {
@@ -385,7 +420,7 @@ pub fn translate_alter_table(
             });
 
             program.emit_insn(Insn::ParseSchema {
-                db: usize::MAX, // TODO: This value is unused, change when we do something with it
+                db: 0,
                 where_clause: None,
             });
 
@@ -0,0 +1,541 @@
+//! VDBE bytecode generation for ANALYZE.
+//!
+//! ANALYZE records in the `sqlite_stat1` table of a database the number of rows of its tables and
+//! how many rows share the values of the leading columns of their indexes, in the same format as
+//! SQLite. The optimizer uses them in place of its fixed estimates.
+//!
+//! More info: https://www.sqlite.org/lang_analyze.html
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast;
+
+use crate::schema::{
+    stat1_index_name, BTreeTable, Index, Schema, STAT1_TABLE_NAME, STAT1_TABLE_SQL,
+};
+use crate::storage::pager::CreateBTreeFlags;
+use crate::translate::collate::CollationSeq;
+use crate::translate::emitter::TransactionMode;
+use crate::translate::schema::{emit_schema_entry, SchemaEntryType, SQLITE_TABLEID};
+use crate::util::normalize_ident;
+use crate::vdbe::builder::{CursorType, ProgramBuilder, ProgramBuilderOpts};
+use crate::vdbe::insn::{CmpInsFlags, Cookie, InsertFlags, Insn, RegisterOrLiteral};
+use crate::vdbe::CursorID;
+use crate::{bail_parse_error, Result};
+
+/// The rows of `sqlite_stat1` that an ANALYZE replaces, or a DROP deletes.
+pub(crate) enum Stat1Rows {
+    /// All of them, when the whole database is analyzed.
+    All,
+    /// The rows of the table with this name.
+    Table(String),
+    /// The row of the index with this name.
+    Index(String),
+}
+
+/// What an ANALYZE gathers statistics for in one database.
+struct AnalyzeTarget {
+    database_id: usize,
+    /// The tables to analyze, with the only index to analyze if not all of them are.
+    tables: Vec<(Rc<BTreeTable>, Option<Arc<Index>>)>,
+    replaced_rows: Stat1Rows,
+}
+
+pub fn translate_analyze(
+    name: Option<&ast::QualifiedName>,
+    schema: &Schema,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let targets = match name {
+        // Like SQLite, the temp database is only analyzed when it is named
+        None => std::iter::once(0)
+            .chain(2..schema.attached.len() + 2)
+            .map(|database_id| database_target(schema, database_id))
+            .collect(),
+        Some(name) => vec![resolve_target(schema, name)?],
+    };
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 3,
+        approx_num_insns: 40,
+        approx_num_labels: 5,
+    });
+    for target in targets {
+        emit_analyze(
+            &mut program,
+            schema.database_schema(target.database_id),
+            target,
+        );
+    }
+    program.epilogue(TransactionMode::Write);
+    Ok(program)
+}
+
+/// Analyze every table of the database `database_id`.
+fn database_target(schema: &Schema, database_id: usize) -> AnalyzeTarget {
+    let mut tables = schema
+        .database_schema(database_id)
+        .tables
+        .values()
+        .filter_map(|table| table.btree())
+        .map(|table| (table, None))
+        .collect::<Vec<_>>();
+    tables.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+    AnalyzeTarget {
+        database_id,
+        tables,
+        replaced_rows: Stat1Rows::All,
+    }
+}
+
+/// Resolves the argument of ANALYZE, which names a database, a table or an index.
+fn resolve_target(schema: &Schema, name: &ast::QualifiedName) -> Result<AnalyzeTarget> {
+    let database_ids = match &name.db_name {
+        Some(db_name) => vec![schema.database_index(&db_name.0)?],
+        None => {
+            if let Ok(database_id) = schema.database_index(&name.name.0) {
+                return Ok(database_target(schema, database_id));
+            }
+            // Unqualified names are searched in the same order as tables are
+            let temp = schema.temp.get().map(|_| 1);
+            temp.into_iter()
+                .chain(std::iter::once(0))
+                .chain(2..schema.attached.len() + 2)
+                .collect()
+        }
+    };
+    let object_name = normalize_ident(&name.name.0);
+    for database_id in database_ids {
+        let database_schema = schema.database_schema(database_id);
+        if let Some(table) = database_schema.get_table(&object_name) {
+            return Ok(AnalyzeTarget {
+                database_id,
+                // Views and virtual tables have nothing to analyze
+                tables: table
+                    .btree()
+                    .map(|table| (table, None))
+                    .into_iter()
+                    .collect(),
+                replaced_rows: Stat1Rows::Table(object_name),
+            });
+        }
+        if database_schema.get_view(&object_name).is_some() {
+            return Ok(AnalyzeTarget {
+                database_id,
+                tables: Vec::new(),
+                replaced_rows: Stat1Rows::Table(object_name),
+            });
+        }
+        let index = database_schema
+            .indexes
+            .values()
+            .flatten()
+            .find(|index| index.name == object_name);
+        if let Some(index) = index {
+            let table = database_schema
+                .get_btree_table(&index.table_name)
+                .expect("index should belong to a table");
+            return Ok(AnalyzeTarget {
+                database_id,
+                tables: vec![(table, Some(index.clone()))],
+                replaced_rows: Stat1Rows::Index(object_name),
+            });
+        }
+    }
+    match &name.db_name {
+        Some(db_name) => bail_parse_error!("no such table: {}.{}", db_name.0, name.name.0),
+        None => bail_parse_error!("no such table: {}", name.name.0),
+    }
+}
+
+/// Emits the bytecode that replaces the rows of `sqlite_stat1` for `target`, creating the table if
+/// it does not exist yet, and then loads the new statistics into the schema.
+fn emit_analyze(program: &mut ProgramBuilder, schema: &Schema, target: AnalyzeTarget) {
+    let database_id = target.database_id;
+    let stat1_table = match schema.get_btree_table(STAT1_TABLE_NAME) {
+        Some(table) => table,
+        None => Rc::new(BTreeTable::from_sql(STAT1_TABLE_SQL, 0).expect("valid sqlite_stat1 SQL")),
+    };
+    let stat1_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(stat1_table.clone()));
+    if stat1_table.root_page == 0 {
+        let root_reg = emit_create_stat1_table(program, schema, database_id);
+        program.emit_insn(Insn::OpenWrite {
+            cursor_id: stat1_cursor_id,
+            root_page: RegisterOrLiteral::Register(root_reg),
+            name: STAT1_TABLE_NAME.to_string(),
+            db: database_id,
+        });
+    } else {
+        program.emit_insn(Insn::OpenWrite {
+            cursor_id: stat1_cursor_id,
+            root_page: RegisterOrLiteral::Literal(stat1_table.root_page),
+            name: STAT1_TABLE_NAME.to_string(),
+            db: database_id,
+        });
+        emit_delete_stat1_rows(program, stat1_cursor_id, &target.replaced_rows);
+    }
+
+    for (table, index) in target.tables {
+        // Like SQLite, there are no statistics for the internal tables
+        if table.name.starts_with("sqlite_") {
+            continue;
+        }
+        let record_reg = program.alloc_registers(3);
+        program.emit_string8(table.name.clone(), record_reg);
+        let indexes = match index {
+            Some(index) => vec![index],
+            None => table
+                .clustered_index()
+                .into_iter()
+                .chain(schema.get_indices(&table.name).iter().cloned())
+                .collect(),
+        };
+        for index in indexes.iter() {
+            emit_index_stat(
+                program,
+                database_id,
+                stat1_cursor_id,
+                record_reg,
+                &table,
+                index,
+            );
+        }
+        // The number of rows of the table is the number of rows of any index that is not
+        // partial, it is only recorded on its own when there is none
+        let all_partial = indexes.iter().all(|index| index.where_clause.is_some());
+        if matches!(target.replaced_rows, Stat1Rows::Index(_)) || !all_partial {
+            continue;
+        }
+        let table_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+        program.emit_insn(Insn::OpenRead {
+            cursor_id: table_cursor_id,
+            root_page: table.root_page,
+            db: database_id,
+        });
+        let count_reg = program.alloc_register();
+        program.emit_insn(Insn::Count {
+            cursor_id: table_cursor_id,
+            target_reg: count_reg,
+            exact: true,
+        });
+        // Empty tables have no statistics
+        let skip_label = program.allocate_label();
+        program.emit_insn(Insn::IfNot {
+            reg: count_reg,
+            target_pc: skip_label,
+            jump_if_null: true,
+        });
+        program.emit_null(record_reg + 1, None);
+        // The statistics are text, like the ones of the indexes
+        let empty_reg = program.emit_string8_new_reg(String::new());
+        program.emit_insn(Insn::Concat {
+            lhs: count_reg,
+            rhs: empty_reg,
+            dest: record_reg + 2,
+        });
+        emit_stat1_row(program, stat1_cursor_id, record_reg);
+        program.preassign_label_to_next_insn(skip_label);
+    }
+
+    program.emit_insn(Insn::LoadAnalysis { db: database_id });
+}
+
+/// Creates the `sqlite_stat1` table, returning the register that holds its root page.
+fn emit_create_stat1_table(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+) -> usize {
+    let root_reg = program.alloc_register();
+    program.emit_insn(Insn::CreateBtree {
+        db: database_id,
+        root: root_reg,
+        flags: CreateBTreeFlags::new_table(),
+    });
+    let sqlite_schema = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+    let sqlite_schema_cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(sqlite_schema.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: sqlite_schema_cursor_id,
+        root_page: RegisterOrLiteral::Literal(sqlite_schema.root_page),
+        name: sqlite_schema.name.clone(),
+        db: database_id,
+    });
+    emit_schema_entry(
+        program,
+        sqlite_schema_cursor_id,
+        SchemaEntryType::Table,
+        STAT1_TABLE_NAME,
+        STAT1_TABLE_NAME,
+        root_reg,
+        Some(STAT1_TABLE_SQL.to_string()),
+    );
+    program.emit_insn(Insn::SetCookie {
+        db: database_id,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+    program.emit_insn(Insn::ParseSchema {
+        db: database_id,
+        where_clause: Some(format!("tbl_name = '{STAT1_TABLE_NAME}'")),
+    });
+    root_reg
+}
+
+/// Deletes the statistics of a table or index being dropped, like SQLite. `schema` is the schema
+/// of the database of the table or index.
+pub(crate) fn emit_drop_stat1_rows(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    rows: &Stat1Rows,
+) {
+    let Some(stat1_table) = schema.get_btree_table(STAT1_TABLE_NAME) else {
+        return;
+    };
+    let stat1_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(stat1_table.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: stat1_cursor_id,
+        root_page: RegisterOrLiteral::Literal(stat1_table.root_page),
+        name: STAT1_TABLE_NAME.to_string(),
+        db: database_id,
+    });
+    emit_delete_stat1_rows(program, stat1_cursor_id, rows);
+}
+
+/// Deletes `rows` from the `sqlite_stat1` table open in `cursor_id`.
+fn emit_delete_stat1_rows(program: &mut ProgramBuilder, cursor_id: CursorID, rows: &Stat1Rows) {
+    let filter = match rows {
+        Stat1Rows::All => None,
+        Stat1Rows::Table(name) => Some((0, name)),
+        Stat1Rows::Index(name) => Some((1, name)),
+    };
+    let name_reg = filter.map(|(_, name)| program.emit_string8_new_reg(name.clone()));
+    let column_reg = program.alloc_register();
+    let loop_start_label = program.allocate_label();
+    let loop_end_label = program.allocate_label();
+    let next_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: loop_end_label,
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+    if let (Some((column, _)), Some(name_reg)) = (filter, name_reg) {
+        program.emit_column(cursor_id, column, column_reg);
+        program.emit_insn(Insn::Ne {
+            lhs: column_reg,
+            rhs: name_reg,
+            target_pc: next_label,
+            flags: CmpInsFlags::default().jump_if_null(),
+            // Names are case insensitive, SQLite stores them as they were declared
+            collation: Some(CollationSeq::NoCase),
+        });
+    }
+    program.emit_insn(Insn::Delete { cursor_id });
+    program.preassign_label_to_next_insn(next_label);
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(loop_end_label);
+}
+
+/// Scans `index` and records in `sqlite_stat1` its number of rows followed by the average number
+/// of rows sharing the values of its first 1, 2, ... columns, rounded up. Empty indexes have no
+/// statistics. `record_reg` holds the name of the table, followed by two free registers.
+fn emit_index_stat(
+    program: &mut ProgramBuilder,
+    database_id: usize,
+    stat1_cursor_id: CursorID,
+    record_reg: usize,
+    table: &BTreeTable,
+    index: &Arc<Index>,
+) {
+    let columns = index.key_columns();
+    let index_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+    program.emit_insn(Insn::OpenRead {
+        cursor_id: index_cursor_id,
+        root_page: index.root_page,
+        db: database_id,
+    });
+    let one_reg = program.alloc_register();
+    program.emit_int(1, one_reg);
+    let row_count_reg = program.alloc_register();
+    program.emit_int(0, row_count_reg);
+    // The number of distinct values of the first 1, 2, ... columns
+    let distinct_regs = program.alloc_registers(columns.len());
+    for i in 0..columns.len() {
+        program.emit_int(0, distinct_regs + i);
+    }
+    // The values of the columns in the previous row
+    let prev_regs = program.alloc_registers(columns.len());
+    let column_reg = program.alloc_register();
+
+    let loop_start_label = program.allocate_label();
+    let next_row_label = program.allocate_label();
+    let loop_end_label = program.allocate_label();
+    let changed_labels = columns
+        .iter()
+        .map(|_| program.allocate_label())
+        .collect::<Vec<_>>();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: index_cursor_id,
+        pc_if_empty: loop_end_label,
+    });
+    // The first row starts a new group of values for every prefix
+    program.emit_insn(Insn::Goto {
+        target_pc: changed_labels[0],
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+    for (i, column) in columns.iter().enumerate() {
+        program.emit_column(index_cursor_id, i, column_reg);
+        program.emit_insn(Insn::Ne {
+            lhs: column_reg,
+            rhs: prev_regs + i,
+            target_pc: changed_labels[i],
+            flags: CmpInsFlags::default().null_eq(),
+            collation: column.collation,
+        });
+    }
+    program.emit_insn(Insn::Goto {
+        target_pc: next_row_label,
+    });
+    // When a column changes, so do all the prefixes that include it
+    for (i, changed_label) in changed_labels.into_iter().enumerate() {
+        program.preassign_label_to_next_insn(changed_label);
+        program.emit_insn(Insn::Add {
+            lhs: distinct_regs + i,
+            rhs: one_reg,
+            dest: distinct_regs + i,
+        });
+        program.emit_column(index_cursor_id, i, prev_regs + i);
+    }
+    program.preassign_label_to_next_insn(next_row_label);
+    program.emit_insn(Insn::Add {
+        lhs: row_count_reg,
+        rhs: one_reg,
+        dest: row_count_reg,
+    });
+    program.emit_insn(Insn::Next {
+        cursor_id: index_cursor_id,
+        pc_if_next: loop_start_label,
+    });
+
+    let stat_reg = record_reg + 2;
+    let space_reg = program.emit_string8_new_reg(" ".to_string());
+    program.emit_string8(String::new(), stat_reg);
+    program.emit_insn(Insn::Concat {
+        lhs: stat_reg,
+        rhs: row_count_reg,
+        dest: stat_reg,
+    });
+    let avg_reg = program.alloc_register();
+    let tmp_reg = program.alloc_register();
+    let limit_reg = program.alloc_register();
+    for i in 0..columns.len() {
+        emit_rows_per_distinct_value(
+            program,
+            row_count_reg,
+            distinct_regs + i,
+            one_reg,
+            avg_reg,
+            (tmp_reg, limit_reg),
+        );
+        program.emit_insn(Insn::Concat {
+            lhs: stat_reg,
+            rhs: space_reg,
+            dest: stat_reg,
+        });
+        program.emit_insn(Insn::Concat {
+            lhs: stat_reg,
+            rhs: avg_reg,
+            dest: stat_reg,
+        });
+    }
+    program.emit_string8(stat1_index_name(table, index).to_string(), record_reg + 1);
+    emit_stat1_row(program, stat1_cursor_id, record_reg);
+    program.preassign_label_to_next_insn(loop_end_label);
+}
+
+/// Computes into `dest` the average number of rows per distinct value like SQLite does: rounded
+/// up, except that it is 1 rather than 2 when at most one row in ten shares its value.
+fn emit_rows_per_distinct_value(
+    program: &mut ProgramBuilder,
+    row_count_reg: usize,
+    distinct_reg: usize,
+    one_reg: usize,
+    dest: usize,
+    (tmp_reg, limit_reg): (usize, usize),
+) {
+    program.emit_insn(Insn::Add {
+        lhs: row_count_reg,
+        rhs: distinct_reg,
+        dest,
+    });
+    program.emit_insn(Insn::Subtract {
+        lhs: dest,
+        rhs: one_reg,
+        dest,
+    });
+    program.emit_insn(Insn::Divide {
+        lhs: dest,
+        rhs: distinct_reg,
+        dest,
+    });
+    let done_label = program.allocate_label();
+    program.emit_int(2, tmp_reg);
+    program.emit_insn(Insn::Ne {
+        lhs: dest,
+        rhs: tmp_reg,
+        target_pc: done_label,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_int(10, tmp_reg);
+    program.emit_insn(Insn::Multiply {
+        lhs: row_count_reg,
+        rhs: tmp_reg,
+        dest: tmp_reg,
+    });
+    program.emit_int(11, limit_reg);
+    program.emit_insn(Insn::Multiply {
+        lhs: distinct_reg,
+        rhs: limit_reg,
+        dest: limit_reg,
+    });
+    program.emit_insn(Insn::Gt {
+        lhs: tmp_reg,
+        rhs: limit_reg,
+        target_pc: done_label,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_int(1, dest);
+    program.preassign_label_to_next_insn(done_label);
+}
+
+/// Inserts the `tbl`, `idx` and `stat` values in the registers starting at `record_reg` as a new
+/// row of `sqlite_stat1`.
+fn emit_stat1_row(program: &mut ProgramBuilder, stat1_cursor_id: CursorID, record_reg: usize) {
+    let record = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: record_reg,
+        count: 3,
+        dest_reg: record,
+        index_name: None,
+    });
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: stat1_cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: stat1_cursor_id,
+        key_reg: rowid_reg,
+        record_reg: record,
+        flag: InsertFlags::new(),
+        table_name: STAT1_TABLE_NAME.to_string(),
+    });
+}
@@ -0,0 +1,78 @@
+//! VDBE bytecode generation for ATTACH and DETACH.
+//!
+//! Attached databases are accessed by their schema name, e.g. `SELECT * FROM aux.t`.
+//! More info: https://www.sqlite.org/lang_attach.html
+
+use turso_sqlite3_parser::ast;
+
+use crate::schema::Schema;
+use crate::translate::emitter::{Resolver, TransactionMode};
+use crate::translate::expr::translate_expr;
+use crate::util::dequote;
+use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
+use crate::vdbe::insn::Insn;
+use crate::{bail_parse_error, Result, SymbolTable};
+
+pub fn translate_attach(
+    expr: &ast::Expr,
+    db_name: &ast::Expr,
+    key: Option<&ast::Expr>,
+    schema: &Schema,
+    syms: &SymbolTable,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    if key.is_some() {
+        bail_parse_error!("ATTACH with a KEY is not supported");
+    }
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 0,
+        approx_num_insns: 4,
+        approx_num_labels: 0,
+    });
+    let resolver = Resolver::new(schema, syms);
+    let path = translate_name_expr(&mut program, expr, &resolver)?;
+    let name = translate_name_expr(&mut program, db_name, &resolver)?;
+    program.emit_insn(Insn::Attach { path, name });
+    program.epilogue(TransactionMode::None);
+    Ok(program)
+}
+
+pub fn translate_detach(
+    db_name: &ast::Expr,
+    schema: &Schema,
+    syms: &SymbolTable,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 0,
+        approx_num_insns: 3,
+        approx_num_labels: 0,
+    });
+    let resolver = Resolver::new(schema, syms);
+    let name = translate_name_expr(&mut program, db_name, &resolver)?;
+    program.emit_insn(Insn::Detach { name });
+    program.epilogue(TransactionMode::None);
+    Ok(program)
+}
+
+/// Evaluates a file or schema name of ATTACH/DETACH into a new register.
+/// Like in SQLite, a bare identifier is taken as a string rather than a column reference.
+fn translate_name_expr(
+    program: &mut ProgramBuilder,
+    expr: &ast::Expr,
+    resolver: &Resolver,
+) -> Result<usize> {
+    let identifier = match expr {
+        ast::Expr::Id(id) => Some(&id.0),
+        ast::Expr::Name(name) => Some(&name.0),
+        _ => None,
+    };
+    match identifier {
+        Some(identifier) => Ok(program.emit_string8_new_reg(dequote(identifier).to_string())),
+        None => {
+            let reg = program.alloc_register();
+            translate_expr(program, None, expr, reg, resolver)?;
+            Ok(reg)
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use crate::schema::{Index, IndexColumn, Schema};
-use crate::translate::emitter::{emit_query, LimitCtx, TransactionMode, TranslateCtx};
+use crate::schema::{FromClauseSubquery, Index, IndexColumn, PseudoCursorType, Schema};
+use crate::translate::emitter::{emit_query, LimitCtx, Resolver, TransactionMode, TranslateCtx};
+use crate::translate::order_by::sorter_insert;
 use crate::translate::plan::{Plan, QueryDestination, SelectPlan};
 use crate::vdbe::builder::{CursorType, ProgramBuilder};
 use crate::vdbe::insn::Insn;
@@ -19,10 +20,7 @@ pub fn emit_program_for_compound_select(
     syms: &SymbolTable,
 ) -> crate::Result<()> {
     let Plan::CompoundSelect {
-        left: _left,
-        right_most,
-        limit,
-        ..
+        right_most, limit, ..
     } = &plan
     else {
         crate::bail_parse_error!("expected compound select plan");
@@ -39,20 +37,9 @@ pub fn emit_program_for_compound_select(
         }
     }
 
-    // Each subselect shares the same limit_ctx, because the LIMIT applies to the entire compound select,
-    // not just a single subselect.
-    let limit_ctx = limit.map(|limit| {
-        let reg = program.alloc_register();
-        program.emit_insn(Insn::Integer {
-            value: limit as i64,
-            dest: reg,
-        });
-        LimitCtx::new_shared(reg)
-    });
-
     // When a compound SELECT is part of a query that yields results to a coroutine (e.g. within an INSERT clause),
     // we must allocate registers for the result columns to be yielded. Each subselect will then yield to
-    // the coroutine using the same set of registers.
+    // the coroutine using the same set of registers, right after the yield register.
     let (yield_reg, reg_result_cols_start) = match right_most.query_destination {
         QueryDestination::CoroutineYield { yield_reg, .. } => {
             let start_reg = program.alloc_registers(right_most.result_columns.len());
@@ -61,12 +48,11 @@ pub fn emit_program_for_compound_select(
         _ => (None, None),
     };
 
-    emit_compound_select(
+    emit_compound_query(
         program,
         plan,
         schema,
         syms,
-        limit_ctx,
         yield_reg,
         reg_result_cols_start,
     )?;
@@ -78,6 +64,355 @@ pub fn emit_program_for_compound_select(
     Ok(())
 }
 
+/// Emits a compound SELECT used as a FROM clause subquery as a coroutine, and returns the start register
+/// of its result columns, like [crate::translate::subquery::emit_subquery] does for a single SELECT.
+/// Every SELECT of the compound yields its rows in the registers right after the yield register.
+pub fn emit_compound_subquery(
+    program: &mut ProgramBuilder,
+    subquery: &mut FromClauseSubquery,
+    resolver: &Resolver,
+) -> crate::Result<usize> {
+    let compound = subquery
+        .compound
+        .as_ref()
+        .expect("subquery must be a compound SELECT");
+    let yield_reg = program.alloc_register();
+    let reg_result_cols_start = program.alloc_registers(subquery.columns.len());
+    let label_coroutine_start = program.allocate_label();
+    let label_coroutine_end = program.allocate_label();
+    let query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start: label_coroutine_start,
+    };
+    // The parent query reads the rows through the query destination of the rightmost SELECT.
+    subquery.plan.query_destination = query_destination.clone();
+    let mut left = compound.left.clone();
+    for (plan, _) in left.iter_mut() {
+        plan.query_destination = query_destination.clone();
+    }
+    let plan = Plan::CompoundSelect {
+        left,
+        right_most: subquery.plan.as_ref().clone(),
+        limit: compound.limit,
+        offset: compound.offset,
+        order_by: compound.order_by.clone(),
+    };
+
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: label_coroutine_end,
+        start_offset: label_coroutine_start,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_start);
+    if compound.limit != Some(0) {
+        emit_compound_query(
+            program,
+            plan,
+            resolver.schema,
+            resolver.symbol_table,
+            Some(yield_reg),
+            Some(reg_result_cols_start),
+        )?;
+    }
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(label_coroutine_end);
+    Ok(reg_result_cols_start)
+}
+
+// Emits the bytecode that produces the rows of a compound SELECT, either as result rows or, with a
+// `yield_reg`, by yielding them in the registers starting at `reg_result_cols_start`.
+fn emit_compound_query(
+    program: &mut ProgramBuilder,
+    plan: Plan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    yield_reg: Option<usize>,
+    reg_result_cols_start: Option<usize>,
+) -> crate::Result<()> {
+    let Plan::CompoundSelect {
+        limit,
+        offset,
+        order_by,
+        ..
+    } = &plan
+    else {
+        unreachable!()
+    };
+
+    // Each subselect shares the same limit_ctx, because the LIMIT applies to the entire compound select,
+    // not just a single subselect.
+    let limit_ctx = limit.map(|limit| {
+        let reg = program.alloc_register();
+        program.emit_insn(Insn::Integer {
+            value: limit as i64,
+            dest: reg,
+        });
+        LimitCtx::new_shared(reg)
+    });
+
+    program.emit_explain(true, "COMPOUND QUERY".to_string());
+    if order_by.is_some() || offset.is_some_and(|offset| offset > 0) {
+        emit_ordered_compound_select(
+            program,
+            plan,
+            schema,
+            syms,
+            limit_ctx,
+            yield_reg,
+            reg_result_cols_start,
+        )?;
+    } else {
+        emit_compound_select(
+            program,
+            plan,
+            schema,
+            syms,
+            limit_ctx,
+            yield_reg,
+            reg_result_cols_start,
+        )?;
+    }
+    program.pop_explain();
+
+    Ok(())
+}
+
+// Emits bytecode for a compound SELECT statement with an ORDER BY or OFFSET clause, which apply to the
+// rows of the whole compound SELECT. The compound SELECT is run as a coroutine, and the loop reading its
+// rows either inserts them into an ORDER BY sorter, whose rows are then emitted, or emits them directly.
+// The OFFSET and LIMIT are applied to the emitted rows.
+fn emit_ordered_compound_select(
+    program: &mut ProgramBuilder,
+    plan: Plan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    limit_ctx: Option<LimitCtx>,
+    yield_reg: Option<usize>,
+    reg_result_cols_start: Option<usize>,
+) -> crate::Result<()> {
+    let Plan::CompoundSelect {
+        mut left,
+        mut right_most,
+        offset,
+        order_by,
+        ..
+    } = plan
+    else {
+        unreachable!()
+    };
+    let column_count = right_most.result_columns.len();
+
+    // The rows of the compound SELECT are yielded in the registers right after the yield register.
+    let compound_yield_reg = program.alloc_register();
+    let reg_compound_cols_start = program.alloc_registers(column_count);
+    let label_coroutine_start = program.allocate_label();
+    let label_coroutine_end = program.allocate_label();
+    let compound_destination = QueryDestination::CoroutineYield {
+        yield_reg: compound_yield_reg,
+        coroutine_implementation_start: label_coroutine_start,
+    };
+    for (plan, _) in left.iter_mut() {
+        plan.query_destination = compound_destination.clone();
+    }
+    right_most.query_destination = compound_destination;
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg: compound_yield_reg,
+        jump_on_definition: label_coroutine_end,
+        start_offset: label_coroutine_start,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_start);
+    emit_compound_select(
+        program,
+        Plan::CompoundSelect {
+            left,
+            right_most,
+            limit: None,
+            offset: None,
+            order_by: None,
+        },
+        schema,
+        syms,
+        None,
+        Some(compound_yield_reg),
+        Some(reg_compound_cols_start),
+    )?;
+    program.emit_insn(Insn::EndCoroutine {
+        yield_reg: compound_yield_reg,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_end);
+    if order_by.is_some() {
+        program.emit_explain(false, "USE TEMP B-TREE FOR ORDER BY".to_string());
+    }
+
+    let reg_offset = offset.filter(|offset| *offset > 0).map(|offset| {
+        let reg = program.alloc_register();
+        program.emit_insn(Insn::Integer {
+            value: offset as i64,
+            dest: reg,
+        });
+        reg
+    });
+    let sort_cursor = order_by.as_ref().map(|order_by| {
+        let sort_cursor = program.alloc_cursor_id(CursorType::Sorter);
+        program.emit_insn(Insn::SorterOpen {
+            cursor_id: sort_cursor,
+            columns: order_by.len(),
+            order: order_by.iter().map(|(_, order, _)| *order).collect(),
+            collations: order_by
+                .iter()
+                .map(|(_, _, collation)| *collation)
+                .collect(),
+        });
+        sort_cursor
+    });
+    let label_end = program.allocate_label();
+    let label_compound_loop_start = program.allocate_label();
+    program.preassign_label_to_next_insn(label_compound_loop_start);
+    program.emit_insn(Insn::Yield {
+        yield_reg: compound_yield_reg,
+        end_offset: label_end,
+    });
+
+    let (Some(order_by), Some(sort_cursor)) = (order_by, sort_cursor) else {
+        emit_compound_row(
+            program,
+            reg_compound_cols_start,
+            column_count,
+            reg_offset,
+            label_compound_loop_start,
+            limit_ctx,
+            label_end,
+            yield_reg,
+            reg_result_cols_start,
+        );
+        program.emit_insn(Insn::Goto {
+            target_pc: label_compound_loop_start,
+        });
+        program.preassign_label_to_next_insn(label_end);
+        return Ok(());
+    };
+
+    // The ORDER BY sorter has the sort keys first, then the result columns.
+    let sorter_column_count = order_by.len() + column_count;
+    let reg_sorter_cols_start = program.alloc_registers(sorter_column_count);
+    for (i, (column_idx, _, _)) in order_by.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_compound_cols_start + column_idx,
+            dst_reg: reg_sorter_cols_start + i,
+            extra_amount: 0,
+        });
+    }
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_compound_cols_start,
+        dst_reg: reg_sorter_cols_start + order_by.len(),
+        extra_amount: column_count - 1,
+    });
+    let reg_sorter_record = program.alloc_register();
+    sorter_insert(
+        program,
+        reg_sorter_cols_start,
+        sorter_column_count,
+        sort_cursor,
+        reg_sorter_record,
+    );
+    program.emit_insn(Insn::Goto {
+        target_pc: label_compound_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_end);
+
+    let label_sorter_loop_start = program.allocate_label();
+    let label_sorter_loop_next = program.allocate_label();
+    let label_sorter_loop_end = program.allocate_label();
+
+    let reg_sorter_data = program.alloc_register();
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: sorter_column_count,
+    }));
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: sorter_column_count,
+    });
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sort_cursor,
+        pc_if_empty: label_sorter_loop_end,
+    });
+    program.preassign_label_to_next_insn(label_sorter_loop_start);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sort_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+    let reg_sorted_cols_start = program.alloc_registers(column_count);
+    for i in 0..column_count {
+        program.emit_column(pseudo_cursor, order_by.len() + i, reg_sorted_cols_start + i);
+    }
+    emit_compound_row(
+        program,
+        reg_sorted_cols_start,
+        column_count,
+        reg_offset,
+        label_sorter_loop_next,
+        limit_ctx,
+        label_sorter_loop_end,
+        yield_reg,
+        reg_result_cols_start,
+    );
+    program.preassign_label_to_next_insn(label_sorter_loop_next);
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sort_cursor,
+        pc_if_next: label_sorter_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_sorter_loop_end);
+    Ok(())
+}
+
+/// Emits a row of a compound SELECT with an ORDER BY or OFFSET clause, which is in the registers
+/// starting at `cols_start_reg`, unless the row is skipped by the OFFSET.
+#[allow(clippy::too_many_arguments)]
+fn emit_compound_row(
+    program: &mut ProgramBuilder,
+    cols_start_reg: usize,
+    column_count: usize,
+    reg_offset: Option<usize>,
+    label_next_row: BranchOffset,
+    limit_ctx: Option<LimitCtx>,
+    label_limit_reached: BranchOffset,
+    yield_reg: Option<usize>,
+    reg_result_cols_start: Option<usize>,
+) {
+    if let Some(reg_offset) = reg_offset {
+        program.add_comment(program.offset(), "OFFSET");
+        program.emit_insn(Insn::IfPos {
+            reg: reg_offset,
+            target_pc: label_next_row,
+            decrement_by: 1,
+        });
+    }
+    if let (Some(yield_reg), Some(reg_result_cols_start)) = (yield_reg, reg_result_cols_start) {
+        program.emit_insn(Insn::Copy {
+            src_reg: cols_start_reg,
+            dst_reg: reg_result_cols_start,
+            extra_amount: column_count - 1,
+        });
+        program.emit_insn(Insn::Yield {
+            yield_reg,
+            end_offset: BranchOffset::Offset(0),
+        });
+    } else {
+        program.emit_insn(Insn::ResultRow {
+            start_reg: cols_start_reg,
+            count: column_count,
+        });
+    }
+    if let Some(limit_ctx) = limit_ctx {
+        program.emit_insn(Insn::DecrJumpZero {
+            reg: limit_ctx.reg_limit,
+            target_pc: label_limit_reached,
+        });
+    }
+}
+
 // Emits bytecode for a compound SELECT statement. This function processes the rightmost part of
 // the compound SELECT and handles the left parts recursively based on the compound operator type.
 fn emit_compound_select(
@@ -107,7 +442,21 @@ fn emit_compound_select(
         right_most.table_references.joined_tables().len(),
         right_most.result_columns.len(),
     );
-    right_most_ctx.reg_result_cols_start = reg_result_cols_start;
+    // Only the SELECTs that yield their rows directly write them to the registers after the yield
+    // register; the rows of the others are read back from an ephemeral index.
+    if matches!(
+        right_most.query_destination,
+        QueryDestination::CoroutineYield { .. }
+    ) {
+        right_most_ctx.reg_result_cols_start = reg_result_cols_start;
+    }
+    let explain_detail = match left.last() {
+        Some((_, CompoundOperator::UnionAll)) => "UNION ALL",
+        Some((_, CompoundOperator::Union)) => "UNION USING TEMP B-TREE",
+        Some((_, CompoundOperator::Intersect)) => "INTERSECT USING TEMP B-TREE",
+        Some((_, CompoundOperator::Except)) => "EXCEPT USING TEMP B-TREE",
+        None => "LEFT-MOST SUBQUERY",
+    };
     match left.pop() {
         Some((mut plan, operator)) => match operator {
             CompoundOperator::UnionAll => {
@@ -144,7 +493,12 @@ fn emit_compound_select(
                     right_most.limit = limit;
                     right_most_ctx.limit_ctx = Some(limit_ctx);
                 }
-                emit_query(program, &mut right_most, &mut right_most_ctx)?;
+                emit_compound_part(
+                    program,
+                    &mut right_most,
+                    &mut right_most_ctx,
+                    explain_detail,
+                )?;
                 program.preassign_label_to_next_insn(label_next_select);
             }
             CompoundOperator::Union => {
@@ -155,7 +509,7 @@ fn emit_compound_select(
                     } => (cursor_id, index.clone()),
                     _ => {
                         new_dedupe_index = true;
-                        create_dedupe_index(program, &right_most, schema)?
+                        create_dedupe_index(program, &right_most)?
                     }
                 };
                 plan.query_destination = QueryDestination::EphemeralIndex {
@@ -180,12 +534,18 @@ fn emit_compound_select(
                     reg_result_cols_start,
                 )?;
 
+                right_most_ctx.reg_result_cols_start = None;
                 right_most.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: dedupe_index.0,
                     index: dedupe_index.1.clone(),
                     is_delete: false,
                 };
-                emit_query(program, &mut right_most, &mut right_most_ctx)?;
+                emit_compound_part(
+                    program,
+                    &mut right_most,
+                    &mut right_most_ctx,
+                    explain_detail,
+                )?;
 
                 if new_dedupe_index {
                     read_deduplicated_union_or_except_rows(
@@ -205,8 +565,7 @@ fn emit_compound_select(
                     target_cursor_id = Some(cursor_id);
                 }
 
-                let (left_cursor_id, left_index) =
-                    create_dedupe_index(program, &right_most, schema)?;
+                let (left_cursor_id, left_index) = create_dedupe_index(program, &right_most)?;
                 plan.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: left_cursor_id,
                     index: left_index.clone(),
@@ -229,14 +588,19 @@ fn emit_compound_select(
                     reg_result_cols_start,
                 )?;
 
-                let (right_cursor_id, right_index) =
-                    create_dedupe_index(program, &right_most, schema)?;
+                let (right_cursor_id, right_index) = create_dedupe_index(program, &right_most)?;
+                right_most_ctx.reg_result_cols_start = None;
                 right_most.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: right_cursor_id,
                     index: right_index,
                     is_delete: false,
                 };
-                emit_query(program, &mut right_most, &mut right_most_ctx)?;
+                emit_compound_part(
+                    program,
+                    &mut right_most,
+                    &mut right_most_ctx,
+                    explain_detail,
+                )?;
                 read_intersect_rows(
                     program,
                     left_cursor_id,
@@ -255,7 +619,7 @@ fn emit_compound_select(
                     } => (cursor_id, index),
                     _ => {
                         new_index = true;
-                        create_dedupe_index(program, &right_most, schema)?
+                        create_dedupe_index(program, &right_most)?
                     }
                 };
                 plan.query_destination = QueryDestination::EphemeralIndex {
@@ -279,12 +643,18 @@ fn emit_compound_select(
                     yield_reg,
                     reg_result_cols_start,
                 )?;
+                right_most_ctx.reg_result_cols_start = None;
                 right_most.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id,
                     index: index.clone(),
                     is_delete: true,
                 };
-                emit_query(program, &mut right_most, &mut right_most_ctx)?;
+                emit_compound_part(
+                    program,
+                    &mut right_most,
+                    &mut right_most_ctx,
+                    explain_detail,
+                )?;
                 if new_index {
                     read_deduplicated_union_or_except_rows(
                         program, cursor_id, &index, limit_ctx, yield_reg,
@@ -297,23 +667,37 @@ fn emit_compound_select(
                 right_most_ctx.limit_ctx = Some(limit_ctx);
                 right_most.limit = limit;
             }
-            emit_query(program, &mut right_most, &mut right_most_ctx)?;
+            emit_compound_part(
+                program,
+                &mut right_most,
+                &mut right_most_ctx,
+                explain_detail,
+            )?;
         }
     }
 
     Ok(())
 }
 
+// Emits one of the SELECTs of a compound SELECT, whose rows are nested in the EXPLAIN QUERY PLAN
+// row `explain_detail`.
+fn emit_compound_part<'a>(
+    program: &mut ProgramBuilder,
+    plan: &'a mut SelectPlan,
+    t_ctx: &mut TranslateCtx<'a>,
+    explain_detail: &str,
+) -> crate::Result<()> {
+    program.emit_explain(true, explain_detail.to_string());
+    emit_query(program, plan, t_ctx)?;
+    program.pop_explain();
+    Ok(())
+}
+
 // Creates an ephemeral index that will be used to deduplicate the results of any sub-selects
 fn create_dedupe_index(
     program: &mut ProgramBuilder,
     select: &SelectPlan,
-    schema: &Schema,
 ) -> crate::Result<(usize, Arc<Index>)> {
-    if !schema.indexes_enabled {
-        crate::bail_parse_error!("UNION OR INTERSECT is not supported without indexes");
-    }
-
     let dedupe_index = Arc::new(Index {
         columns: select
             .result_columns
@@ -327,6 +711,7 @@ fn create_dedupe_index(
                 pos_in_table: 0,
                 default: None,
                 collation: None, // FIXME: this should be inferred
+                expr: None,
             })
             .collect(),
         name: "compound_dedupe".to_string(),
@@ -335,6 +720,9 @@ fn create_dedupe_index(
         table_name: String::new(),
         unique: true,
         has_rowid: false,
+        on_conflict: None,
+        where_clause: None,
+        pk_suffix_len: 0,
     });
     let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(dedupe_index.clone()));
     program.emit_insn(Insn::OpenEphemeral {
@@ -2,10 +2,11 @@ use crate::schema::Table;
 use crate::translate::emitter::emit_program;
 use crate::translate::optimizer::optimize_plan;
 use crate::translate::plan::{DeletePlan, Operation, Plan};
-use crate::translate::planner::{parse_limit, parse_where};
+use crate::translate::planner::{parse_limit, parse_returning, parse_where};
+use crate::translate::trigger::{translate_view_write, ViewWrite};
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts, TableRefIdCounter};
 use crate::{schema::Schema, Result, SymbolTable};
-use turso_sqlite3_parser::ast::{Expr, Limit, QualifiedName};
+use turso_sqlite3_parser::ast::{Expr, Limit, QualifiedName, ResultColumn};
 
 use super::plan::{ColumnUsedMask, IterationDirection, JoinedTable, TableReferences};
 
@@ -14,24 +15,34 @@ pub fn translate_delete(
     tbl_name: &QualifiedName,
     where_clause: Option<Box<Expr>>,
     limit: Option<Box<Limit>>,
+    returning: Option<Vec<ResultColumn>>,
     syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
-    if schema.table_has_indexes(&tbl_name.name.to_string()) && !schema.indexes_enabled() {
-        // Let's disable altering a table with indices altogether instead of checking column by
-        // column to be extra safe.
-        crate::bail_parse_error!(
-            "DELETE for table with indexes is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
+    let database_id = schema.locate_database(tbl_name)?;
+    let database_schema = schema.database_schema(database_id);
+    let table_name = tbl_name.name.0.as_str();
+    if database_schema.get_table(table_name).is_none() {
+        if let Some(view) = database_schema.get_view(table_name) {
+            if returning.is_some() {
+                crate::bail_parse_error!("RETURNING is not supported for views");
+            }
+            if limit.is_some() {
+                crate::bail_parse_error!("DELETE of a view does not support LIMIT");
+            }
+            let write = ViewWrite::Delete { where_clause };
+            return translate_view_write(schema, syms, database_id, &view, write, program);
+        }
     }
     let mut delete_plan = prepare_delete_plan(
         schema,
         tbl_name,
         where_clause,
         limit,
+        returning,
         &mut program.table_reference_counter,
     )?;
-    optimize_plan(&mut delete_plan, schema)?;
+    optimize_plan(&mut delete_plan, schema, program.optimizer_options())?;
     let Plan::Delete(ref delete) = delete_plan else {
         panic!("delete_plan is not a DeletePlan");
     };
@@ -50,10 +61,16 @@ pub fn prepare_delete_plan(
     tbl_name: &QualifiedName,
     where_clause: Option<Box<Expr>>,
     limit: Option<Box<Limit>>,
+    returning: Option<Vec<ResultColumn>>,
     table_ref_counter: &mut TableRefIdCounter,
 ) -> Result<Plan> {
-    let table = match schema.get_table(tbl_name.name.0.as_str()) {
+    let database_id = schema.locate_database(tbl_name)?;
+    let database_schema = schema.database_schema(database_id);
+    let table = match database_schema.get_table(tbl_name.name.0.as_str()) {
         Some(table) => table,
+        None if database_schema.get_view(tbl_name.name.0.as_str()).is_some() => {
+            crate::bail_parse_error!("cannot modify {} because it is a view", tbl_name)
+        }
         None => crate::bail_parse_error!("no such table: {}", tbl_name),
     };
     let table = if let Some(table) = table.virtual_table() {
@@ -63,8 +80,11 @@ pub fn prepare_delete_plan(
     } else {
         crate::bail_parse_error!("Table is neither a virtual table nor a btree table");
     };
+    if returning.is_some() && table.virtual_table().is_some() {
+        crate::bail_parse_error!("RETURNING is not supported for virtual tables");
+    }
     let name = tbl_name.name.0.as_str().to_string();
-    let indexes = schema.get_indices(table.get_name()).to_vec();
+    let indexes = database_schema.get_indices(table.get_name()).to_vec();
     let joined_tables = vec![JoinedTable {
         table,
         identifier: name,
@@ -75,6 +95,7 @@ pub fn prepare_delete_plan(
         },
         join_info: None,
         col_used_mask: ColumnUsedMask::default(),
+        database_id,
     }];
     let mut table_references = TableReferences::new(joined_tables, vec![]);
 
@@ -86,14 +107,17 @@ pub fn prepare_delete_plan(
         &mut table_references,
         None,
         &mut where_predicates,
+        schema,
     )?;
 
+    let result_columns = parse_returning(returning, &mut table_references, schema)?;
+
     // Parse the LIMIT/OFFSET clause
     let (resolved_limit, resolved_offset) = limit.map_or(Ok((None, None)), |l| parse_limit(&l))?;
 
     let plan = DeletePlan {
         table_references,
-        result_columns: vec![],
+        result_columns,
         where_clause: where_predicates,
         order_by: None,
         limit: resolved_limit,
@@ -2,11 +2,14 @@ use core::fmt;
 use std::fmt::{Display, Formatter};
 
 use turso_sqlite3_parser::{
-    ast::{SortOrder, TableInternalId},
+    ast::{ResolveType, SortOrder, TableInternalId},
     to_sql_string::{ToSqlContext, ToSqlString},
 };
 
-use crate::{schema::Table, translate::plan::TableReferences};
+use crate::{
+    schema::{FromClauseSubquery, Table},
+    translate::plan::TableReferences,
+};
 
 use super::plan::{
     Aggregate, DeletePlan, JoinedTable, Operation, Plan, Search, SelectPlan, UpdatePlan,
@@ -24,7 +27,7 @@ impl Display for Aggregate {
     }
 }
 
-/// For EXPLAIN QUERY PLAN
+/// A description of the plan, for debugging
 impl Display for Plan {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -49,11 +52,11 @@ impl Display for Plan {
                 }
                 if let Some(order_by) = order_by {
                     writeln!(f, "ORDER BY:")?;
-                    for (expr, dir) in order_by {
+                    for (column_idx, dir, _) in order_by {
                         writeln!(
                             f,
                             "  - {} {}",
-                            expr,
+                            column_idx + 1,
                             if *dir == SortOrder::Asc {
                                 "ASC"
                             } else {
@@ -114,6 +117,20 @@ impl Display for SelectPlan {
                             indent, reference.identifier, index.name
                         )?;
                     }
+                    Search::MultiIndexOr { .. } => {
+                        writeln!(
+                            f,
+                            "{}SEARCH {} USING MULTI-INDEX OR",
+                            indent, reference.identifier
+                        )?;
+                    }
+                    Search::HashJoin { .. } => {
+                        writeln!(
+                            f,
+                            "{}SEARCH {} USING HASH JOIN",
+                            indent, reference.identifier
+                        )?;
+                    }
                 },
             }
         }
@@ -195,6 +212,20 @@ impl fmt::Display for UpdatePlan {
                             indent, reference.identifier, index.name
                         )?;
                     }
+                    Search::MultiIndexOr { .. } => {
+                        writeln!(
+                            f,
+                            "{}SEARCH {} USING MULTI-INDEX OR",
+                            indent, reference.identifier
+                        )?;
+                    }
+                    Search::HashJoin { .. } => {
+                        writeln!(
+                            f,
+                            "{}SEARCH {} USING HASH JOIN",
+                            indent, reference.identifier
+                        )?;
+                    }
                 },
             }
         }
@@ -291,11 +322,7 @@ impl ToSqlString for Plan {
                         "ORDER BY {}",
                         order_by
                             .iter()
-                            .map(|(expr, order)| format!(
-                                "{} {}",
-                                expr.to_sql_string(context),
-                                order
-                            ))
+                            .map(|(column_idx, order, _)| format!("{} {}", column_idx + 1, order))
                             .collect::<Vec<_>>()
                             .join(", ")
                     ));
@@ -322,6 +349,20 @@ impl ToSqlString for JoinedTable {
         let table_or_subquery =
             match &self.table {
                 Table::BTree(..) | Table::Virtual(..) => self.table.get_name().to_string(),
+                Table::FromClauseSubquery(FromClauseSubquery {
+                    plan,
+                    compound: Some(compound),
+                    ..
+                }) => {
+                    let compound_select = Plan::CompoundSelect {
+                        left: compound.left.clone(),
+                        right_most: plan.as_ref().clone(),
+                        limit: compound.limit,
+                        offset: compound.offset,
+                        order_by: compound.order_by.clone(),
+                    };
+                    format!("({})", compound_select.to_sql_string(_context))
+                }
                 Table::FromClauseSubquery(from_clause_subquery) => {
                     // Could possibly merge the contexts together here
                     format!(
@@ -513,9 +554,19 @@ impl ToSqlString for UpdatePlan {
         let context = &PlanContext(&context);
         let mut ret = Vec::new();
 
-        // TODO: we don't work with conflict clauses yet
-
-        ret.push(format!("UPDATE {} SET", table.table.get_name()));
+        let or_conflict = match self.or_conflict {
+            Some(ResolveType::Rollback) => " OR ROLLBACK",
+            Some(ResolveType::Abort) => " OR ABORT",
+            Some(ResolveType::Fail) => " OR FAIL",
+            Some(ResolveType::Ignore) => " OR IGNORE",
+            Some(ResolveType::Replace) => " OR REPLACE",
+            None => "",
+        };
+        ret.push(format!(
+            "UPDATE{} {} SET",
+            or_conflict,
+            table.table.get_name()
+        ));
 
         // TODO: does not support column_name_list yet
         ret.push(
@@ -7,22 +7,32 @@ use tracing::{instrument, Level};
 use turso_sqlite3_parser::ast::{self, Expr};
 
 use super::aggregation::emit_ungrouped_aggregation;
-use super::expr::translate_expr;
+use super::expr::{translate_expr, walk_expr_mut};
+use super::foreign_key::{emit_foreign_key_checks, has_foreign_keys};
+use super::generated::{columns_changed_by_update, emit_generated_columns, emit_table_record};
 use super::group_by::{
     group_by_agg_phase, group_by_emit_row_phase, init_group_by, GroupByMetadata, GroupByRowSource,
 };
+use super::index::{
+    emit_index_key, emit_partial_index_check, unique_index_violation, IndexKeySource,
+};
+use super::insert::{emit_check_constraints, emit_notnull_check, emit_replace_delete};
 use super::main_loop::{
     close_loop, emit_loop, init_distinct, init_loop, open_loop, LeftJoinMetadata, LoopLabels,
+    RightJoinMetadata, SearchLoopMetadata,
 };
 use super::order_by::{emit_order_by, init_order_by, SortMetadata};
 use super::plan::{
-    Distinctness, JoinOrderMember, Operation, SelectPlan, TableReferences, UpdatePlan,
+    Distinctness, JoinOrderMember, Operation, ResultSetColumn, SelectPlan, TableReferences,
+    UpdatePlan,
 };
 use super::select::emit_simple_count;
 use super::subquery::emit_subqueries;
+use super::trigger::{emit_triggers, triggers_to_fire, TriggerRowRegisters};
+use super::window::{emit_window, init_window, WindowMetadata};
 use crate::error::SQLITE_CONSTRAINT_PRIMARYKEY;
 use crate::function::Func;
-use crate::schema::{Schema, Table};
+use crate::schema::{BTreeTable, Index, Schema, Table};
 use crate::translate::compound_select::emit_program_for_compound_select;
 use crate::translate::plan::{DeletePlan, Plan, QueryDestination, Search};
 use crate::translate::values::emit_values;
@@ -128,9 +138,17 @@ pub struct TranslateCtx<'a> {
     pub meta_group_by: Option<GroupByMetadata>,
     // metadata for the order by operator
     pub meta_sort: Option<SortMetadata>,
+    // metadata for computing window functions
+    pub meta_window: Option<WindowMetadata>,
     /// mapping between table loop index and associated metadata (for left joins only)
     /// this metadata exists for the right table in a given left join
     pub meta_left_joins: Vec<Option<LeftJoinMetadata>>,
+    /// mapping between table loop index and associated metadata (for right joins only)
+    /// this metadata exists for the right table in a given right join
+    pub meta_right_joins: Vec<Option<RightJoinMetadata>>,
+    /// mapping between table loop index and the loops its search runs within
+    /// (for IN lists and multi-index ORs only)
+    pub meta_search_loops: Vec<SearchLoopMetadata>,
     // We need to emit result columns in the order they are present in the SELECT, but they may not be in the same order in the ORDER BY sorter.
     // This vector holds the indexes of the result columns in the ORDER BY sorter.
     pub result_column_indexes_in_orderby_sorter: Vec<usize>,
@@ -172,7 +190,10 @@ impl<'a> TranslateCtx<'a> {
             reg_result_cols_start: None,
             meta_group_by: None,
             meta_left_joins: (0..table_count).map(|_| None).collect(),
+            meta_right_joins: (0..table_count).map(|_| None).collect(),
+            meta_search_loops: (0..table_count).map(|_| Default::default()).collect(),
             meta_sort: None,
+            meta_window: None,
             result_column_indexes_in_orderby_sorter: (0..result_column_count).collect(),
             result_columns_to_skip_in_orderby_sorter: None,
             resolver: Resolver::new(schema, syms),
@@ -265,10 +286,21 @@ pub fn emit_query<'a>(
     t_ctx: &mut TranslateCtx<'a>,
 ) -> Result<usize> {
     if !plan.values.is_empty() {
-        let reg_result_cols_start = emit_values(program, plan, &t_ctx.resolver)?;
+        let reg_result_cols_start =
+            emit_values(program, plan, &t_ctx.resolver, t_ctx.reg_result_cols_start)?;
         return Ok(reg_result_cols_start);
     }
 
+    // The results yielded to a coroutine are read from the registers right after its yield
+    // register, so they are allocated before anything else.
+    if matches!(
+        plan.query_destination,
+        QueryDestination::CoroutineYield { .. }
+    ) && t_ctx.reg_result_cols_start.is_none()
+    {
+        t_ctx.reg_result_cols_start = Some(program.alloc_registers(plan.result_columns.len()));
+    }
+
     // Emit subqueries first so the results can be read in the main query loop.
     emit_subqueries(program, t_ctx, &mut plan.table_references)?;
 
@@ -307,6 +339,10 @@ pub fn emit_query<'a>(
         init_order_by(program, t_ctx, order_by, &plan.table_references)?;
     }
 
+    if let Some(ref window) = plan.window {
+        init_window(program, t_ctx, window, &plan.table_references)?;
+    }
+
     if let Some(ref group_by) = plan.group_by {
         init_group_by(
             program,
@@ -345,6 +381,13 @@ pub fn emit_query<'a>(
         return Ok(t_ctx.reg_result_cols_start.unwrap());
     }
 
+    if plan.table_references.joined_tables().is_empty() {
+        program.emit_explain(false, "SCAN CONSTANT ROW".to_string());
+    }
+    if plan.group_by.is_none() {
+        emit_explain_distinct_aggregates(program, plan);
+    }
+
     // Set up main query execution loop
     open_loop(
         program,
@@ -372,6 +415,19 @@ pub fn emit_query<'a>(
     let mut order_by_necessary = plan.order_by.is_some() && !plan.contains_constant_false_condition;
     let order_by = plan.order_by.as_ref();
 
+    if let Some(meta_group_by) = &t_ctx.meta_group_by {
+        if matches!(meta_group_by.row_source, GroupByRowSource::Sorter { .. }) {
+            program.emit_explain(false, "USE TEMP B-TREE FOR GROUP BY".to_string());
+        }
+        emit_explain_distinct_aggregates(program, plan);
+    }
+    if plan.distinctness.is_distinct() {
+        program.emit_explain(false, "USE TEMP B-TREE FOR DISTINCT".to_string());
+    }
+    if order_by_necessary && (plan.group_by.is_some() || plan.aggregates.is_empty()) {
+        program.emit_explain(false, "USE TEMP B-TREE FOR ORDER BY".to_string());
+    }
+
     // Handle GROUP BY and aggregation processing
     if plan.group_by.is_some() {
         let row_source = &t_ctx
@@ -388,6 +444,8 @@ pub fn emit_query<'a>(
         emit_ungrouped_aggregation(program, t_ctx, plan)?;
         // Single row result for aggregates without GROUP BY, so ORDER BY not needed
         order_by_necessary = false;
+    } else if plan.window.is_some() {
+        emit_window(program, t_ctx, plan)?;
     }
 
     // Process ORDER BY results if needed
@@ -398,6 +456,19 @@ pub fn emit_query<'a>(
     Ok(t_ctx.reg_result_cols_start.unwrap())
 }
 
+/// Explains the ephemeral indexes that deduplicate the arguments of DISTINCT aggregates.
+fn emit_explain_distinct_aggregates(program: &mut ProgramBuilder, plan: &SelectPlan) {
+    for aggregate in plan.aggregates.iter().filter(|agg| agg.is_distinct()) {
+        program.emit_explain(
+            false,
+            format!(
+                "USE TEMP B-TREE FOR {}(DISTINCT)",
+                aggregate.func.to_string()
+            ),
+        );
+    }
+}
+
 #[instrument(skip_all, level = Level::INFO)]
 fn emit_program_for_delete(
     program: &mut ProgramBuilder,
@@ -432,6 +503,13 @@ fn emit_program_for_delete(
         });
     }
 
+    // The WHERE terms are placed in the loop of the table by its id, which isn't the default
+    // one when the statement is nested in another, e.g. by a trigger.
+    let join_order = [JoinOrderMember {
+        table_id: plan.table_references.joined_tables()[0].internal_id,
+        ..Default::default()
+    }];
+
     // Initialize cursors and other resources needed for query execution
     init_loop(
         program,
@@ -448,19 +526,24 @@ fn emit_program_for_delete(
         program,
         &mut t_ctx,
         &plan.table_references,
-        &[JoinOrderMember::default()],
+        &join_order,
         &plan.where_clause,
         None,
     )?;
 
-    emit_delete_insns(program, &mut t_ctx, &plan.table_references)?;
+    emit_delete_insns(
+        program,
+        &mut t_ctx,
+        &plan.table_references,
+        &plan.result_columns,
+    )?;
 
     // Clean up and close the main execution loop
     close_loop(
         program,
         &mut t_ctx,
         &plan.table_references,
-        &[JoinOrderMember::default()],
+        &join_order,
         None,
     )?;
     program.preassign_label_to_next_insn(after_main_loop_label);
@@ -476,6 +559,7 @@ fn emit_delete_insns(
     program: &mut ProgramBuilder,
     t_ctx: &mut TranslateCtx,
     table_references: &TableReferences,
+    returning: &[ResultSetColumn],
 ) -> Result<()> {
     let table_reference = table_references.joined_tables().first().unwrap();
     let cursor_id = match &table_reference.op {
@@ -483,9 +567,12 @@ fn emit_delete_insns(
             program.resolve_cursor_id(&CursorKey::table(table_reference.internal_id))
         }
         Operation::Search(search) => match search {
-            Search::RowidEq { .. } | Search::Seek { index: None, .. } => {
+            Search::RowidEq { .. }
+            | Search::Seek { index: None, .. }
+            | Search::MultiIndexOr { .. } => {
                 program.resolve_cursor_id(&CursorKey::table(table_reference.internal_id))
             }
+            Search::HashJoin { .. } => unreachable!("DELETE does not join tables"),
             Search::Seek {
                 index: Some(index), ..
             } => program.resolve_cursor_id(&CursorKey::index(
@@ -520,12 +607,77 @@ fn emit_delete_insns(
             conflict_action,
         });
     } else {
+        let btree_table = table_reference.btree().unwrap();
+        let schema = t_ctx.resolver.schema;
+        let table_schema = schema.database_schema(table_reference.database_id);
+        let (before_triggers, after_triggers) = if program.disable_triggers() {
+            (vec![], vec![])
+        } else {
+            (
+                triggers_to_fire(
+                    schema,
+                    table_reference.database_id,
+                    &btree_table.name,
+                    &ast::TriggerEvent::Delete,
+                    ast::TriggerTime::Before,
+                    &[],
+                ),
+                triggers_to_fire(
+                    schema,
+                    table_reference.database_id,
+                    &btree_table.name,
+                    &ast::TriggerEvent::Delete,
+                    ast::TriggerTime::After,
+                    &[],
+                ),
+            )
+        };
+        // RAISE(IGNORE) skips to the next row
+        let row_done_label = t_ctx.labels_main_loop.first().unwrap().next;
+        let old_row = if before_triggers.is_empty()
+            && after_triggers.is_empty()
+            && returning.is_empty()
+            && !has_foreign_keys(program, table_schema, &btree_table)
+        {
+            None
+        } else {
+            // Load the OLD row, it must outlive the deletion for AFTER triggers, foreign key
+            // checks and RETURNING
+            let columns_start_reg = program.alloc_registers(btree_table.columns.len());
+            for idx in 0..btree_table.columns.len() {
+                program.emit_column(main_table_cursor_id, idx, columns_start_reg + idx);
+            }
+            let old_row = TriggerRowRegisters {
+                rowid_reg: key_reg,
+                columns_start_reg,
+            };
+            emit_generated_columns(program, &t_ctx.resolver, &btree_table, old_row, true)?;
+            Some(old_row)
+        };
+
+        if !before_triggers.is_empty() {
+            emit_triggers(
+                program,
+                schema,
+                t_ctx.resolver.symbol_table,
+                &before_triggers,
+                &btree_table,
+                None,
+                old_row,
+                row_done_label,
+            )?;
+            // The trigger body may have moved the cursor or deleted the row itself
+            emit_seek_row(
+                program,
+                &btree_table,
+                main_table_cursor_id,
+                old_row.expect("the OLD row is loaded for BEFORE triggers"),
+                row_done_label,
+            );
+        }
+
         // Delete from all indexes before deleting from the main table.
-        let indexes = t_ctx
-            .resolver
-            .schema
-            .indexes
-            .get(table_reference.table.get_name());
+        let indexes = table_schema.indexes.get(table_reference.table.get_name());
         let index_refs_opt = indexes.map(|indexes| {
             indexes
                 .iter()
@@ -542,31 +694,35 @@ fn emit_delete_insns(
         });
 
         if let Some(index_refs) = index_refs_opt {
+            let source = IndexKeySource::Cursor(main_table_cursor_id);
             for (index, index_cursor_id) in index_refs {
-                let num_regs = index.columns.len() + 1;
+                let skip_label = program.allocate_label();
+                emit_partial_index_check(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    &index,
+                    source,
+                    skip_label,
+                )?;
+                let num_regs = index.columns.len() + index.has_rowid as usize;
                 let start_reg = program.alloc_registers(num_regs);
                 // Emit columns that are part of the index
-                index
-                    .columns
-                    .iter()
-                    .enumerate()
-                    .for_each(|(reg_offset, column_index)| {
-                        program.emit_column(
-                            main_table_cursor_id,
-                            column_index.pos_in_table,
-                            start_reg + reg_offset,
-                        );
-                    });
-                program.emit_insn(Insn::RowId {
-                    cursor_id: main_table_cursor_id,
-                    dest: start_reg + num_regs - 1,
-                });
+                emit_index_key(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    &index,
+                    source,
+                    start_reg,
+                )?;
                 program.emit_insn(Insn::IdxDelete {
                     start_reg,
                     num_regs,
                     cursor_id: index_cursor_id,
                     raise_error_if_no_matching_entry: true,
                 });
+                program.preassign_label_to_next_insn(skip_label);
             }
         }
 
@@ -603,6 +759,34 @@ fn emit_delete_insns(
         program.emit_insn(Insn::Delete {
             cursor_id: main_table_cursor_id,
         });
+
+        if let Some(old_row) = old_row {
+            emit_foreign_key_checks(
+                program,
+                &t_ctx.resolver,
+                table_reference.database_id,
+                &btree_table,
+                None,
+                Some(old_row),
+                None,
+                row_done_label,
+            )?;
+        }
+
+        emit_triggers(
+            program,
+            schema,
+            t_ctx.resolver.symbol_table,
+            &after_triggers,
+            &btree_table,
+            None,
+            old_row,
+            row_done_label,
+        )?;
+
+        if let Some(old_row) = old_row {
+            emit_returning_row(program, &t_ctx.resolver, returning, old_row)?;
+        }
     }
     if let Some(limit_ctx) = t_ctx.limit_ctx {
         program.emit_insn(Insn::DecrJumpZero {
@@ -648,16 +832,20 @@ fn emit_program_for_update(
     }
 
     let ephemeral_plan = plan.ephemeral_plan.take();
-    let temp_cursor_id = ephemeral_plan.as_ref().map(|plan| {
-        let QueryDestination::EphemeralTable { cursor_id, .. } = &plan.query_destination else {
-            unreachable!()
-        };
-        *cursor_id
-    });
+    let temp_cursor_id = ephemeral_plan
+        .as_ref()
+        .map(|plan| match &plan.query_destination {
+            QueryDestination::EphemeralTable { cursor_id, .. }
+            | QueryDestination::EphemeralIndex { cursor_id, .. } => *cursor_id,
+            _ => unreachable!(),
+        });
     if let Some(ephemeral_plan) = ephemeral_plan {
         program.emit_insn(Insn::OpenEphemeral {
             cursor_id: temp_cursor_id.unwrap(),
-            is_table: true,
+            is_table: matches!(
+                ephemeral_plan.query_destination,
+                QueryDestination::EphemeralTable { .. }
+            ),
         });
         program.incr_nesting();
         emit_program_for_select(program, ephemeral_plan, schema, syms)?;
@@ -676,16 +864,13 @@ fn emit_program_for_update(
     )?;
 
     // Prepare index cursors
+    let target_table = plan.table_references.joined_tables().first().unwrap();
+    let (target_table_id, database_id) = (target_table.internal_id, target_table.database_id);
     let mut index_cursors = Vec::with_capacity(plan.indexes_to_update.len());
     for index in &plan.indexes_to_update {
-        let index_cursor = if let Some(cursor) = program.resolve_cursor_id_safe(&CursorKey::index(
-            plan.table_references
-                .joined_tables()
-                .first()
-                .unwrap()
-                .internal_id,
-            index.clone(),
-        )) {
+        let index_cursor = if let Some(cursor) =
+            program.resolve_cursor_id_safe(&CursorKey::index(target_table_id, index.clone()))
+        {
             cursor
         } else {
             let cursor = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
@@ -693,6 +878,7 @@ fn emit_program_for_update(
                 cursor_id: cursor,
                 root_page: RegisterOrLiteral::Literal(index.root_page),
                 name: index.name.clone(),
+                db: database_id,
             });
             cursor
         };
@@ -700,12 +886,17 @@ fn emit_program_for_update(
         index_cursors.push((index_cursor, record_reg));
     }
 
+    let join_order = [JoinOrderMember {
+        table_id: target_table_id,
+        ..Default::default()
+    }];
+
     // Open the main loop
     open_loop(
         program,
         &mut t_ctx,
         &plan.table_references,
-        &[JoinOrderMember::default()],
+        &join_order,
         &plan.where_clause,
         temp_cursor_id,
     )?;
@@ -718,7 +909,7 @@ fn emit_program_for_update(
         program,
         &mut t_ctx,
         &plan.table_references,
-        &[JoinOrderMember::default()],
+        &join_order,
         temp_cursor_id,
     )?;
 
@@ -756,7 +947,10 @@ fn emit_update_insns(
             table_ref.virtual_table().is_some(),
         ),
         Operation::Search(search) => match search {
-            &Search::RowidEq { .. } | Search::Seek { index: None, .. } => (None, false),
+            &Search::RowidEq { .. }
+            | Search::Seek { index: None, .. }
+            | Search::MultiIndexOr { .. } => (None, false),
+            Search::HashJoin { .. } => unreachable!("UPDATE does not join tables"),
             Search::Seek {
                 index: Some(index), ..
             } => (
@@ -778,9 +972,42 @@ fn emit_update_insns(
                 1 // rowid reg
             },
     );
-    program.emit_insn(Insn::RowId {
-        cursor_id: temp_cursor_id.unwrap_or(cursor_id),
-        dest: beg,
+    // The key of the row being updated: its rowid, or the PRIMARY KEY of a WITHOUT ROWID table,
+    // which has no rowid to put in `beg`
+    let without_rowid_table = table_ref
+        .btree()
+        .filter(|btree_table| !btree_table.has_rowid);
+    let row_key_reg = match &without_rowid_table {
+        Some(btree_table) => {
+            program.emit_null(beg, None);
+            let clustered_index = btree_table
+                .clustered_index()
+                .expect("a WITHOUT ROWID table has a PRIMARY KEY");
+            let key_reg = program.alloc_registers(clustered_index.columns.len());
+            if let Some(temp_cursor_id) = temp_cursor_id {
+                for i in 0..clustered_index.columns.len() {
+                    program.emit_column(temp_cursor_id, i, key_reg + i);
+                }
+                emit_seek_row_key(program, btree_table, cursor_id, key_reg, loop_labels.next);
+            } else {
+                for (i, column) in clustered_index.columns.iter().enumerate() {
+                    program.emit_column(cursor_id, column.pos_in_table, key_reg + i);
+                }
+            }
+            key_reg
+        }
+        None => {
+            program.emit_insn(Insn::RowId {
+                cursor_id: temp_cursor_id.unwrap_or(cursor_id),
+                dest: beg,
+            });
+            beg
+        }
+    };
+    let primary_key_updated = without_rowid_table.as_ref().is_some_and(|btree_table| {
+        plan.set_clauses
+            .iter()
+            .any(|(idx, _)| btree_table.columns[*idx].primary_key)
     });
 
     // Check if rowid was provided (through INTEGER PRIMARY KEY as a rowid alias)
@@ -812,7 +1039,14 @@ fn emit_update_insns(
             rowid_reg: beg,
             target_pc: check_rowid_not_exists_label.unwrap(),
         });
-    } else {
+    } else if without_rowid_table.is_none() && temp_cursor_id.is_some() {
+        // The rowids were collected before the loop, so the table cursor has to be moved to the row
+        program.emit_insn(Insn::NotExists {
+            cursor: cursor_id,
+            rowid_reg: beg,
+            target_pc: loop_labels.next,
+        });
+    } else if without_rowid_table.is_none() {
         // if no rowid, we're done
         program.emit_insn(Insn::IsNull {
             reg: beg,
@@ -836,6 +1070,66 @@ fn emit_update_insns(
         });
     }
 
+    let (before_triggers, after_triggers) = match table_ref.btree() {
+        Some(btree_table) => {
+            let updated_columns = plan
+                .set_clauses
+                .iter()
+                .filter_map(|(idx, _)| btree_table.columns[*idx].name.clone())
+                .collect::<Vec<_>>();
+            let event = ast::TriggerEvent::Update;
+            let schema = t_ctx.resolver.schema;
+            (
+                triggers_to_fire(
+                    schema,
+                    table_ref.database_id,
+                    &btree_table.name,
+                    &event,
+                    ast::TriggerTime::Before,
+                    &updated_columns,
+                ),
+                triggers_to_fire(
+                    schema,
+                    table_ref.database_id,
+                    &btree_table.name,
+                    &event,
+                    ast::TriggerTime::After,
+                    &updated_columns,
+                ),
+            )
+        }
+        None => (vec![], vec![]),
+    };
+    let has_foreign_keys = table_ref.btree().is_some_and(|btree_table| {
+        has_foreign_keys(
+            program,
+            t_ctx.resolver.schema.database_schema(table_ref.database_id),
+            &btree_table,
+        )
+    });
+    // Load the OLD row before it gets overwritten
+    let old_row = if before_triggers.is_empty() && after_triggers.is_empty() && !has_foreign_keys {
+        None
+    } else {
+        let num_cols = table_ref.columns().len();
+        let columns_start_reg = program.alloc_registers(num_cols);
+        for idx in 0..num_cols {
+            program.emit_column(cursor_id, idx, columns_start_reg + idx);
+        }
+        let old_row = TriggerRowRegisters {
+            rowid_reg: beg,
+            columns_start_reg,
+        };
+        emit_generated_columns(
+            program,
+            &t_ctx.resolver,
+            &table_ref.btree().unwrap(),
+            old_row,
+            true,
+        )?;
+        Some(old_row)
+    };
+
     // we scan a column at a time, loading either the column's values, or the new value
     // from the Set expression, into registers so we can emit a MakeRecord and update the row.
     let start = if is_virtual { beg + 2 } else { beg + 1 };
@@ -869,11 +1163,15 @@ fn emit_update_insns(
                     &t_ctx.resolver,
                 )?;
                 if table_column.notnull {
-                    use crate::error::SQLITE_CONSTRAINT_NOTNULL;
-                    program.emit_insn(Insn::HaltIfNull {
+                    emit_notnull_check(
+                        program,
+                        &t_ctx.resolver,
                         target_reg,
-                        err_code: SQLITE_CONSTRAINT_NOTNULL,
-                        description: format!(
+                        plan.or_conflict
+                            .or(table_column.notnull_conflict)
+                            .unwrap_or(ast::ResolveType::Abort),
+                        table_column.default.as_ref(),
+                        format!(
                             "{}.{}",
                             table_ref.table.get_name(),
                             table_column
@@ -881,7 +1179,8 @@ fn emit_update_insns(
                                 .as_ref()
                                 .expect("Column name must be present")
                         ),
-                    });
+                        loop_labels.next,
+                    )?;
                 }
             }
         } else {
@@ -917,10 +1216,69 @@ fn emit_update_insns(
         }
     }
 
+    let new_row = TriggerRowRegisters {
+        rowid_reg: rowid_set_clause_reg.unwrap_or(beg),
+        columns_start_reg: start,
+    };
+    // The columns the UPDATE may change, which include the generated columns depending on them
+    let mut updated_columns = plan
+        .set_clauses
+        .iter()
+        .map(|(idx, _)| *idx)
+        .collect::<Vec<_>>();
+    if let Some(btree_table) = table_ref.btree() {
+        emit_generated_columns(program, &t_ctx.resolver, &btree_table, new_row, false)?;
+        updated_columns = columns_changed_by_update(&btree_table, &updated_columns);
+    }
+    if !before_triggers.is_empty() {
+        let btree_table = table_ref.btree().unwrap();
+        emit_triggers(
+            program,
+            t_ctx.resolver.schema,
+            t_ctx.resolver.symbol_table,
+            &before_triggers,
+            &btree_table,
+            Some(new_row),
+            old_row,
+            loop_labels.next,
+        )?;
+        // The trigger body may have moved the cursor or deleted the row itself
+        emit_seek_row_key(
+            program,
+            &btree_table,
+            cursor_id,
+            row_key_reg,
+            loop_labels.next,
+        );
+    }
+
+    if let Some(btree_table) = table_ref.btree() {
+        emit_check_constraints(
+            program,
+            &t_ctx.resolver,
+            &btree_table,
+            new_row,
+            Some(&updated_columns),
+            plan.or_conflict.unwrap_or(ast::ResolveType::Abort),
+            loop_labels.next,
+        )?;
+    }
+
+    // Every index of the table when a conflict may be resolved with REPLACE, see prepare_update_plan()
+    let replace_indexes = plan
+        .indexes_to_update
+        .iter()
+        .cloned()
+        .zip(
+            index_cursors
+                .iter()
+                .map(|(idx_cursor_id, _)| *idx_cursor_id),
+        )
+        .collect::<Vec<_>>();
     for (index, (idx_cursor_id, record_reg)) in plan.indexes_to_update.iter().zip(&index_cursors) {
-        let num_cols = index.columns.len();
+        let num_cols = index.columns.len() + index.has_rowid as usize;
         // allocate scratch registers for the index columns plus rowid
-        let idx_start_reg = program.alloc_registers(num_cols + 1);
+        let idx_start_reg = program.alloc_registers(num_cols);
 
         // Use the new rowid value (if the UPDATE statement sets the rowid alias),
         // otherwise keep using the original rowid. This guarantees that any
@@ -932,29 +1290,23 @@ fn emit_update_insns(
         } else {
             beg
         };
-        let idx_cols_start_reg = beg + 1;
-
-        // copy each index column from the table's column registers into these scratch regs
-        for (i, col) in index.columns.iter().enumerate() {
-            // copy from the table's column register over to the index's scratch register
-
-            program.emit_insn(Insn::Copy {
-                src_reg: idx_cols_start_reg + col.pos_in_table,
-                dst_reg: idx_start_reg + i,
-                extra_amount: 0,
-            });
-        }
-        // last register is the rowid
-        program.emit_insn(Insn::Copy {
-            src_reg: rowid_reg,
-            dst_reg: idx_start_reg + num_cols,
-            extra_amount: 0,
+        let source = IndexKeySource::Registers(TriggerRowRegisters {
+            rowid_reg,
+            columns_start_reg: start,
         });
+        emit_index_key(
+            program,
+            &t_ctx.resolver,
+            &table_ref.btree().unwrap(),
+            index,
+            source,
+            idx_start_reg,
+        )?;
 
         // this record will be inserted into the index later
         program.emit_insn(Insn::MakeRecord {
             start_reg: idx_start_reg,
-            count: num_cols + 1,
+            count: num_cols,
             dest_reg: *record_reg,
             index_name: Some(index.name.clone()),
         });
@@ -965,45 +1317,78 @@ fn emit_update_insns(
 
         // check if the record already exists in the index for unique indexes and abort if so
         let constraint_check = program.allocate_label();
+        emit_partial_index_check(
+            program,
+            &t_ctx.resolver,
+            &table_ref.btree().unwrap(),
+            index,
+            source,
+            constraint_check,
+        )?;
         program.emit_insn(Insn::NoConflict {
             cursor_id: *idx_cursor_id,
             target_pc: constraint_check,
             record_reg: idx_start_reg,
-            num_regs: num_cols,
+            num_regs: index.key_columns().len(),
         });
 
-        let column_names = index.columns.iter().enumerate().fold(
-            String::with_capacity(50),
-            |mut accum, (idx, col)| {
-                if idx > 0 {
-                    accum.push_str(", ");
-                }
-                accum.push_str(table_ref.table.get_name());
-                accum.push('.');
-                accum.push_str(&col.name);
+        let column_names = unique_index_violation(table_ref.table.get_name(), index);
 
-                accum
-            },
+        let btree_table = table_ref.btree().unwrap();
+        let conflict_key_reg =
+            emit_index_entry_row_key(program, &btree_table, index, *idx_cursor_id);
+        // The entry of the row being updated itself, under its rowid before the UPDATE, is no
+        // conflict
+        emit_row_keys_eq(
+            program,
+            &btree_table,
+            row_key_reg,
+            conflict_key_reg,
+            constraint_check,
         );
 
-        let idx_rowid_reg = program.alloc_register();
-        program.emit_insn(Insn::IdxRowId {
-            cursor_id: *idx_cursor_id,
-            dest: idx_rowid_reg,
-        });
-
-        program.emit_insn(Insn::Eq {
-            lhs: rowid_reg,
-            rhs: idx_rowid_reg,
-            target_pc: constraint_check,
-            flags: CmpInsFlags::default(), // TODO: not sure what type of comparison flag is needed
-            collation: program.curr_collation(),
-        });
-
-        program.emit_insn(Insn::Halt {
-            err_code: SQLITE_CONSTRAINT_PRIMARYKEY, // TODO: distinct between primary key and unique index for error code
-            description: column_names,
-        });
+        match plan
+            .or_conflict
+            .or(index.on_conflict)
+            .unwrap_or(ast::ResolveType::Abort)
+        {
+            ast::ResolveType::Replace => {
+                emit_seek_row_key(
+                    program,
+                    &btree_table,
+                    cursor_id,
+                    conflict_key_reg,
+                    constraint_check,
+                );
+                emit_replace_delete(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    cursor_id,
+                    &replace_indexes,
+                )?;
+                // Move back to the row being updated
+                emit_seek_row_key(
+                    program,
+                    &btree_table,
+                    cursor_id,
+                    row_key_reg,
+                    loop_labels.next,
+                );
+            }
+            ast::ResolveType::Ignore => {
+                program.emit_insn(Insn::Goto {
+                    target_pc: loop_labels.next,
+                });
+            }
+            on_error => {
+                program.emit_insn(Insn::Halt {
+                    err_code: SQLITE_CONSTRAINT_PRIMARYKEY, // TODO: distinct between primary key and unique index for error code
+                    description: column_names,
+                    on_error,
+                });
+            }
+        }
 
         program.preassign_label_to_next_insn(constraint_check);
     }
@@ -1036,31 +1421,100 @@ fn emit_update_insns(
                 target_pc: record_label,
             });
 
-            program.emit_insn(Insn::Halt {
-                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
-                description: format!(
-                    "{}.{}",
-                    table_ref.table.get_name(),
-                    &table_ref
-                        .columns()
-                        .get(idx)
-                        .unwrap()
-                        .name
-                        .as_ref()
-                        .map_or("", |v| v)
-                ),
-            });
+            match plan
+                .or_conflict
+                .or(btree_table.primary_key_conflict)
+                .unwrap_or(ast::ResolveType::Abort)
+            {
+                // The cursor is moved back to the row being updated below
+                ast::ResolveType::Replace => emit_replace_delete(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    cursor_id,
+                    &replace_indexes,
+                )?,
+                ast::ResolveType::Ignore => {
+                    program.emit_insn(Insn::Goto {
+                        target_pc: loop_labels.next,
+                    });
+                }
+                on_error => {
+                    program.emit_insn(Insn::Halt {
+                        err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                        description: format!(
+                            "{}.{}",
+                            table_ref.table.get_name(),
+                            &table_ref
+                                .columns()
+                                .get(idx)
+                                .unwrap()
+                                .name
+                                .as_ref()
+                                .map_or("", |v| v)
+                        ),
+                        on_error,
+                    });
+                }
+            }
 
             program.preassign_label_to_next_insn(record_label);
         }
 
+        // A new PRIMARY KEY of a WITHOUT ROWID table must not belong to another row
+        if primary_key_updated {
+            let clustered_index = btree_table
+                .clustered_index()
+                .expect("a WITHOUT ROWID table has a PRIMARY KEY");
+            let record_label = program.allocate_label();
+            let new_key_reg = emit_row_key(program, &btree_table, new_row);
+            emit_row_keys_eq(
+                program,
+                &btree_table,
+                row_key_reg,
+                new_key_reg,
+                record_label,
+            );
+            program.emit_insn(Insn::NoConflict {
+                cursor_id,
+                target_pc: record_label,
+                record_reg: new_key_reg,
+                num_regs: clustered_index.columns.len(),
+            });
+            match plan
+                .or_conflict
+                .or(btree_table.primary_key_conflict)
+                .unwrap_or(ast::ResolveType::Abort)
+            {
+                // The cursor is moved back to the row being updated below
+                ast::ResolveType::Replace => emit_replace_delete(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    cursor_id,
+                    &replace_indexes,
+                )?,
+                ast::ResolveType::Ignore => {
+                    program.emit_insn(Insn::Goto {
+                        target_pc: loop_labels.next,
+                    });
+                }
+                on_error => {
+                    program.emit_insn(Insn::Halt {
+                        err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                        description: unique_index_violation(
+                            table_ref.table.get_name(),
+                            &clustered_index,
+                        ),
+                        on_error,
+                    });
+                }
+            }
+            program.preassign_label_to_next_insn(record_label);
+        }
+
         let record_reg = program.alloc_register();
-        program.emit_insn(Insn::MakeRecord {
-            start_reg: start,
-            count: table_ref.columns().len(),
-            dest_reg: record_reg,
-            index_name: None,
-        });
+        emit_table_record(program, &btree_table, start, record_reg);
 
         if has_user_provided_rowid {
             program.emit_insn(Insn::NotExists {
@@ -1068,47 +1522,67 @@ fn emit_update_insns(
                 rowid_reg: beg,
                 target_pc: check_rowid_not_exists_label.unwrap(),
             });
+        } else if primary_key_updated {
+            emit_seek_row_key(
+                program,
+                &btree_table,
+                cursor_id,
+                row_key_reg,
+                loop_labels.next,
+            );
         }
 
         // For each index -> insert
         for (index, (idx_cursor_id, record_reg)) in plan.indexes_to_update.iter().zip(index_cursors)
         {
-            let num_regs = index.columns.len() + 1;
+            let num_regs = index.columns.len() + index.has_rowid as usize;
             let start_reg = program.alloc_registers(num_regs);
 
             // Delete existing index key
-            index
-                .columns
-                .iter()
-                .enumerate()
-                .for_each(|(reg_offset, column_index)| {
-                    program.emit_column(
-                        cursor_id,
-                        column_index.pos_in_table,
-                        start_reg + reg_offset,
-                    );
-                });
-
-            program.emit_insn(Insn::RowId {
-                cursor_id,
-                dest: start_reg + num_regs - 1,
-            });
-
+            let old_key_label = program.allocate_label();
+            let source = IndexKeySource::Cursor(cursor_id);
+            emit_partial_index_check(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                index,
+                source,
+                old_key_label,
+            )?;
+            emit_index_key(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                index,
+                source,
+                start_reg,
+            )?;
             program.emit_insn(Insn::IdxDelete {
                 start_reg,
                 num_regs,
                 cursor_id: idx_cursor_id,
                 raise_error_if_no_matching_entry: true,
             });
+            program.preassign_label_to_next_insn(old_key_label);
 
             // Insert new index key (filled further above with values from set_clauses)
+            let new_key_label = program.allocate_label();
+            emit_partial_index_check(
+                program,
+                &t_ctx.resolver,
+                &btree_table,
+                index,
+                IndexKeySource::Registers(new_row),
+                new_key_label,
+            )?;
             program.emit_insn(Insn::IdxInsert {
                 cursor_id: idx_cursor_id,
                 record_reg,
                 unpacked_start: Some(start),
-                unpacked_count: Some((index.columns.len() + 1) as u16),
+                unpacked_count: Some(num_regs as u16),
                 flags: IdxInsertFlags::new(),
             });
+            program.preassign_label_to_next_insn(new_key_label);
         }
 
         // create alias for CDC rowid after the change (will differ from cdc_rowid_before_reg only in case of UPDATE with change in rowid alias)
@@ -1149,13 +1623,26 @@ fn emit_update_insns(
             program.emit_insn(Insn::Delete { cursor_id });
         }
 
-        program.emit_insn(Insn::Insert {
-            cursor: cursor_id,
-            key_reg: rowid_set_clause_reg.unwrap_or(beg),
-            record_reg,
-            flag: InsertFlags::new().update(true),
-            table_name: table_ref.identifier.clone(),
-        });
+        if btree_table.has_rowid {
+            program.emit_insn(Insn::Insert {
+                cursor: cursor_id,
+                key_reg: rowid_set_clause_reg.unwrap_or(beg),
+                record_reg,
+                flag: InsertFlags::new().update(true),
+                table_name: table_ref.identifier.clone(),
+            });
+        } else {
+            // The row of a WITHOUT ROWID table is an index entry, whose key may be in an interior
+            // cell that an insert cannot overwrite, so the old row is always deleted first
+            program.emit_insn(Insn::Delete { cursor_id });
+            program.emit_insn(Insn::IdxInsert {
+                cursor_id,
+                record_reg,
+                unpacked_start: None,
+                unpacked_count: None,
+                flags: IdxInsertFlags::new(),
+            });
+        }
 
         // create full CDC record after update if necessary
         let cdc_after_reg = if program.capture_data_changes_mode().has_after() {
@@ -1208,13 +1695,42 @@ fn emit_update_insns(
                 )?;
             }
         }
+
+        if has_foreign_keys {
+            emit_foreign_key_checks(
+                program,
+                &t_ctx.resolver,
+                table_ref.database_id,
+                &btree_table,
+                Some(new_row),
+                old_row,
+                Some(&updated_columns),
+                loop_labels.next,
+            )?;
+        }
+
+        emit_triggers(
+            program,
+            t_ctx.resolver.schema,
+            t_ctx.resolver.symbol_table,
+            &after_triggers,
+            &btree_table,
+            Some(new_row),
+            old_row,
+            loop_labels.next,
+        )?;
+
+        if let Some(returning) = &plan.returning {
+            emit_returning_row(program, &t_ctx.resolver, returning, new_row)?;
+        }
     } else if table_ref.virtual_table().is_some() {
         let arg_count = table_ref.columns().len() + 2;
+        let conflict_action = plan.or_conflict.map(|c| c.bit_value()).unwrap_or(0) as u16;
         program.emit_insn(Insn::VUpdate {
             cursor_id,
             arg_count,
             start_reg: beg,
-            conflict_action: 0u16,
+            conflict_action,
         });
     }
 
@@ -1224,7 +1740,6 @@ fn emit_update_insns(
             target_pc: t_ctx.label_main_loop_end.unwrap(),
         })
     }
-    // TODO(pthorpe): handle RETURNING clause
 
     if let Some(label) = check_rowid_not_exists_label {
         program.preassign_label_to_next_insn(label);
@@ -1387,6 +1902,165 @@ pub fn emit_cdc_insns(
     Ok(())
 }
 
+/// Emits a `ResultRow` with the RETURNING clause evaluated against the row held in `row`.
+/// Column references of the modified table are read from the row registers.
+pub fn emit_returning_row(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    returning: &[ResultSetColumn],
+    row: TriggerRowRegisters,
+) -> Result<()> {
+    if returning.is_empty() {
+        return Ok(());
+    }
+    let start_reg = program.alloc_registers(returning.len());
+    for (i, result_column) in returning.iter().enumerate() {
+        let mut expr = result_column.expr.clone();
+        walk_expr_mut(&mut expr, &mut |e: &mut Expr| -> Result<()> {
+            match e {
+                Expr::Column {
+                    column,
+                    is_rowid_alias,
+                    ..
+                } => {
+                    *e = Expr::Register(if *is_rowid_alias {
+                        row.rowid_reg
+                    } else {
+                        row.columns_start_reg + *column
+                    });
+                }
+                Expr::RowId { .. } => *e = Expr::Register(row.rowid_reg),
+                _ => {}
+            }
+            Ok(())
+        })?;
+        translate_expr(program, None, &expr, start_reg + i, resolver)?;
+    }
+    program.emit_insn(Insn::ResultRow {
+        start_reg,
+        count: returning.len(),
+    });
+    Ok(())
+}
+
+/// Emits the key of the row held in `row`: its rowid, or for a WITHOUT ROWID table its
+/// PRIMARY KEY columns copied to consecutive registers. Returns the first register of the key.
+pub(crate) fn emit_row_key(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    row: TriggerRowRegisters,
+) -> usize {
+    if table.has_rowid {
+        return row.rowid_reg;
+    }
+    let pk_columns = (0..table.columns.len())
+        .filter_map(|column| table.primary_key_position(column).map(|pos| (pos, column)))
+        .collect::<Vec<_>>();
+    let key_reg = program.alloc_registers(pk_columns.len());
+    for (pos, column) in pk_columns {
+        program.emit_insn(Insn::Copy {
+            src_reg: row.columns_start_reg + column,
+            dst_reg: key_reg + pos,
+            extra_amount: 0,
+        });
+    }
+    key_reg
+}
+
+/// Emits the key of the row the entry under `index_cursor_id` points to: the rowid of the entry,
+/// or for an index of a WITHOUT ROWID table the PRIMARY KEY columns of the entry.
+/// Returns the first register of the key.
+pub(crate) fn emit_index_entry_row_key(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    index: &Index,
+    index_cursor_id: CursorID,
+) -> usize {
+    if table.has_rowid {
+        let rowid_reg = program.alloc_register();
+        program.emit_insn(Insn::IdxRowId {
+            cursor_id: index_cursor_id,
+            dest: rowid_reg,
+        });
+        return rowid_reg;
+    }
+    let positions = index.primary_key_positions(table);
+    let key_reg = program.alloc_registers(positions.len());
+    for (i, pos) in positions.into_iter().enumerate() {
+        program.emit_column(index_cursor_id, pos, key_reg + i);
+    }
+    key_reg
+}
+
+/// Moves `cursor_id` to the row with the key in `key_reg` (see [emit_row_key]), jumping to
+/// `not_found` if there is no such row.
+pub(crate) fn emit_seek_row_key(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    cursor_id: CursorID,
+    key_reg: usize,
+    not_found: BranchOffset,
+) {
+    if table.has_rowid {
+        program.emit_insn(Insn::NotExists {
+            cursor: cursor_id,
+            rowid_reg: key_reg,
+            target_pc: not_found,
+        });
+    } else {
+        program.emit_insn(Insn::NotFound {
+            cursor_id,
+            target_pc: not_found,
+            record_reg: key_reg,
+            num_regs: table.primary_key_columns.len(),
+        });
+    }
+}
+
+/// Moves `cursor_id` to the row held in `row`, jumping to `not_found` if it no longer exists.
+pub(crate) fn emit_seek_row(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    cursor_id: CursorID,
+    row: TriggerRowRegisters,
+    not_found: BranchOffset,
+) {
+    let key_reg = emit_row_key(program, table, row);
+    emit_seek_row_key(program, table, cursor_id, key_reg, not_found);
+}
+
+/// Jumps to `target_pc` if the row keys in `lhs` and `rhs` (see [emit_row_key]) are equal.
+pub(crate) fn emit_row_keys_eq(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    lhs: usize,
+    rhs: usize,
+    target_pc: BranchOffset,
+) {
+    let Some(clustered_index) = table.clustered_index() else {
+        program.emit_insn(Insn::Eq {
+            lhs,
+            rhs,
+            target_pc,
+            flags: CmpInsFlags::default(),
+            collation: program.curr_collation(),
+        });
+        return;
+    };
+    let not_equal = program.allocate_label();
+    for (i, column) in clustered_index.columns.iter().enumerate() {
+        program.emit_insn(Insn::Ne {
+            lhs: lhs + i,
+            rhs: rhs + i,
+            target_pc: not_equal,
+            flags: CmpInsFlags::default(),
+            collation: column.collation,
+        });
+    }
+    program.emit_insn(Insn::Goto { target_pc });
+    program.preassign_label_to_next_insn(not_equal);
+}
+
 /// Initialize the limit/offset counters and registers.
 /// In case of compound SELECTs, the limit counter is initialized only once,
 /// hence [LimitCtx::initialize_counter] being false in those cases.
@@ -1,16 +1,21 @@
+use std::sync::Arc;
+
 use tracing::{instrument, Level};
-use turso_sqlite3_parser::ast::{self, Expr, UnaryOperator};
+use turso_sqlite3_parser::ast::{self, Expr, TableInternalId, UnaryOperator};
 
 use super::emitter::Resolver;
+use super::generated::emit_table_column;
 use super::optimizer::Optimizable;
 use super::plan::TableReferences;
+use super::subquery::emit_expr_subquery;
+use crate::error::SQLITE_CONSTRAINT_TRIGGER;
 #[cfg(feature = "json")]
 use crate::function::JsonFunc;
 use crate::function::{Func, FuncCtx, MathFuncArity, ScalarFunc, VectorFunc};
 use crate::functions::datetime;
-use crate::schema::{Affinity, Table, Type};
+use crate::schema::{Affinity, Index, Table, Type};
 use crate::util::{exprs_are_equivalent, parse_numeric_literal};
-use crate::vdbe::builder::CursorKey;
+use crate::vdbe::builder::{CursorKey, CursorType};
 use crate::vdbe::{
     builder::ProgramBuilder,
     insn::{CmpInsFlags, Insn},
@@ -203,7 +208,12 @@ pub fn translate_condition_expr(
         | ast::Expr::FunctionCall { .. }
         | ast::Expr::Column { .. }
         | ast::Expr::RowId { .. }
-        | ast::Expr::Case { .. } => {
+        | ast::Expr::Register(_)
+        | ast::Expr::Case { .. }
+        | ast::Expr::Exists(_)
+        | ast::Expr::InSelect { .. }
+        | ast::Expr::Subquery(_)
+        | ast::Expr::SubqueryResult { .. } => {
             let reg = program.alloc_register();
             translate_expr(program, Some(referenced_tables), expr, reg, resolver)?;
             emit_cond_jump(program, condition_metadata, reg);
@@ -404,6 +414,44 @@ pub fn translate_condition_expr(
     Ok(())
 }
 
+/// Returns the index a table of an outer query is read from, and whether it is a covering
+/// index, when a correlated subquery reads the columns of the outer query. The outer query
+/// only has no table cursor open for a table that it reads from a covering index, and only
+/// reads the table cursor of a table on the build side of a hash join to build its hash table.
+fn outer_query_ref_index(
+    program: &ProgramBuilder,
+    table_ref_id: TableInternalId,
+) -> (Option<Arc<Index>>, bool) {
+    let hash_join_index =
+        program
+            .cursor_ref
+            .iter()
+            .find_map(|(key, cursor_type)| match (key, cursor_type) {
+                (Some(key), CursorType::HashTable(index))
+                    if key.table_reference_id == table_ref_id =>
+                {
+                    Some(index.clone())
+                }
+                _ => None,
+            });
+    if hash_join_index.is_some() {
+        return (hash_join_index, true);
+    }
+    if program
+        .resolve_cursor_id_safe(&CursorKey::table(table_ref_id))
+        .is_some()
+    {
+        return (None, false);
+    }
+    let covering_index = program.cursor_ref.iter().find_map(|(key, _)| {
+        key.as_ref()
+            .filter(|key| key.table_reference_id == table_ref_id)
+            .and_then(|key| key.index.clone())
+    });
+    let use_covering_index = covering_index.is_some();
+    (covering_index, use_covering_index)
+}
+
 /// Reason why [translate_expr_no_constant_opt()] was called.
 #[derive(Debug)]
 pub enum NoConstantOptReason {
@@ -670,8 +718,12 @@ pub fn translate_expr(
             program.set_collation(Some((collation, true)));
             Ok(target_register)
         }
-        ast::Expr::DoublyQualified(_, _, _) => todo!(),
-        ast::Expr::Exists(_) => todo!(),
+        ast::Expr::DoublyQualified(_, _, _) => {
+            unreachable!("DoublyQualified should be resolved to a Column before translation")
+        }
+        ast::Expr::Exists(_) => {
+            emit_expr_subquery(program, referenced_tables, expr, target_register, resolver)
+        }
         ast::Expr::FunctionCall {
             name,
             distinctness: _,
@@ -1901,11 +1953,11 @@ pub fn translate_expr(
                     .find_joined_table_by_internal_id(*table_ref_id)
                 {
                     (
-                        table_reference.op.index(),
+                        table_reference.op.index().cloned(),
                         table_reference.utilizes_covering_index(),
                     )
                 } else {
-                    (None, false)
+                    outer_query_ref_index(program, *table_ref_id)
                 }
             };
 
@@ -1924,13 +1976,13 @@ pub fn translate_expr(
             // the table and read the column from the cursor.
             // If we have a covering index, we don't have an open table cursor so we read from the index cursor.
             match &table {
-                Table::BTree(_) => {
+                Table::BTree(btree) => {
                     let table_cursor_id = if use_covering_index {
                         None
                     } else {
                         Some(program.resolve_cursor_id(&CursorKey::table(*table_ref_id)))
                     };
-                    let index_cursor_id = index.map(|index| {
+                    let index_cursor_id = index.as_ref().map(|index| {
                         program.resolve_cursor_id(&CursorKey::index(*table_ref_id, index.clone()))
                     });
                     if *is_rowid_alias {
@@ -1958,7 +2010,7 @@ pub fn translate_expr(
                             )
                         };
                         let column = if use_covering_index {
-                            let index = index.expect(
+                            let index = index.as_ref().expect(
                                 "index cursor should be opened when use_covering_index=true",
                             );
                             index.column_table_pos_to_index_pos(*column).unwrap_or_else(|| {
@@ -1968,7 +2020,18 @@ pub fn translate_expr(
                             *column
                         };
 
-                        program.emit_column(read_cursor, column, target_register);
+                        if use_covering_index {
+                            program.emit_column(read_cursor, column, target_register);
+                        } else {
+                            emit_table_column(
+                                program,
+                                resolver,
+                                btree,
+                                read_cursor,
+                                column,
+                                target_register,
+                            )?;
+                        }
                     }
                     let Some(column) = table.get_column_at(*column) else {
                         crate::bail_parse_error!("column index out of bounds");
@@ -2010,11 +2073,11 @@ pub fn translate_expr(
                     .find_joined_table_by_internal_id(*table_ref_id)
                 {
                     (
-                        table_reference.op.index(),
+                        table_reference.op.index().cloned(),
                         table_reference.utilizes_covering_index(),
                     )
                 } else {
-                    (None, false)
+                    outer_query_ref_index(program, *table_ref_id)
                 }
             };
 
@@ -2036,8 +2099,85 @@ pub fn translate_expr(
             }
             Ok(target_register)
         }
-        ast::Expr::InList { .. } => todo!(),
-        ast::Expr::InSelect { .. } => todo!(),
+        ast::Expr::InList { lhs, not, rhs } => {
+            // `x IN (...)` is 1 if x equals one of the values, NULL if x is NULL or no value equals x
+            // but one of them is NULL, and 0 otherwise. `NOT IN` negates it.
+            let (value_if_found, value_if_not_found) = if *not { (0, 1) } else { (1, 0) };
+            let rhs = rhs.as_deref().unwrap_or_default();
+            if rhs.is_empty() {
+                // An empty list matches nothing, not even NULL
+                program.emit_insn(Insn::Integer {
+                    value: value_if_not_found,
+                    dest: target_register,
+                });
+                return Ok(target_register);
+            }
+            let label_found = program.allocate_label();
+            let label_null = program.allocate_label();
+            let label_done = program.allocate_label();
+            let lhs_reg = program.alloc_register();
+            translate_expr(program, referenced_tables, lhs, lhs_reg, resolver)?;
+            program.emit_insn(Insn::IsNull {
+                reg: lhs_reg,
+                target_pc: label_null,
+            });
+            let has_null_reg = program.alloc_register();
+            program.emit_insn(Insn::Integer {
+                value: 0,
+                dest: has_null_reg,
+            });
+            for expr in rhs.iter() {
+                let rhs_reg = program.alloc_register();
+                translate_expr(program, referenced_tables, expr, rhs_reg, resolver)?;
+                program.emit_insn(Insn::Eq {
+                    lhs: lhs_reg,
+                    rhs: rhs_reg,
+                    target_pc: label_found,
+                    flags: CmpInsFlags::default(),
+                    collation: program.curr_collation(),
+                });
+                let label_not_null = program.allocate_label();
+                program.emit_insn(Insn::NotNull {
+                    reg: rhs_reg,
+                    target_pc: label_not_null,
+                });
+                program.emit_insn(Insn::Integer {
+                    value: 1,
+                    dest: has_null_reg,
+                });
+                program.preassign_label_to_next_insn(label_not_null);
+            }
+            program.emit_insn(Insn::If {
+                reg: has_null_reg,
+                target_pc: label_null,
+                jump_if_null: false,
+            });
+            program.emit_insn(Insn::Integer {
+                value: value_if_not_found,
+                dest: target_register,
+            });
+            program.emit_insn(Insn::Goto {
+                target_pc: label_done,
+            });
+            program.preassign_label_to_next_insn(label_found);
+            program.emit_insn(Insn::Integer {
+                value: value_if_found,
+                dest: target_register,
+            });
+            program.emit_insn(Insn::Goto {
+                target_pc: label_done,
+            });
+            program.preassign_label_to_next_insn(label_null);
+            program.emit_insn(Insn::Null {
+                dest: target_register,
+                dest_end: None,
+            });
+            program.preassign_label_to_next_insn(label_done);
+            Ok(target_register)
+        }
+        ast::Expr::InSelect { .. } => {
+            emit_expr_subquery(program, referenced_tables, expr, target_register, resolver)
+        }
         ast::Expr::InTable { .. } => todo!(),
         ast::Expr::IsNull(expr) => {
             let reg = program.alloc_register();
@@ -2188,8 +2328,53 @@ pub fn translate_expr(
         ast::Expr::Qualified(_, _) => {
             unreachable!("Qualified should be resolved to a Column before translation")
         }
-        ast::Expr::Raise(_, _) => todo!(),
-        ast::Expr::Subquery(_) => todo!(),
+        ast::Expr::Raise(resolve_type, message) => {
+            if !program.is_trigger_program() {
+                crate::bail_parse_error!("RAISE() may only be used within a trigger-program");
+            }
+            match resolve_type {
+                // Returns from the trigger program, see [Insn::Program]
+                ast::ResolveType::Ignore => {
+                    program.emit_insn(Insn::Halt {
+                        err_code: 0,
+                        description: String::new(),
+                        on_error: ast::ResolveType::Ignore,
+                    });
+                }
+                on_error @ (ast::ResolveType::Abort
+                | ast::ResolveType::Fail
+                | ast::ResolveType::Rollback) => {
+                    let description = match message.as_deref() {
+                        Some(ast::Expr::Literal(ast::Literal::String(s))) => sanitize_string(s),
+                        _ => crate::bail_parse_error!(
+                            "RAISE() error message must be a string literal"
+                        ),
+                    };
+                    program.emit_insn(Insn::Halt {
+                        err_code: SQLITE_CONSTRAINT_TRIGGER,
+                        description,
+                        on_error: *on_error,
+                    });
+                }
+                ast::ResolveType::Replace => {
+                    unreachable!("RAISE(REPLACE) is rejected by the parser")
+                }
+            }
+            // RAISE() evaluates to NULL when it does not halt the program
+            program.emit_null(target_register, None);
+            Ok(target_register)
+        }
+        ast::Expr::Register(src_reg) => {
+            program.emit_insn(Insn::Copy {
+                src_reg: *src_reg,
+                dst_reg: target_register,
+                extra_amount: 0,
+            });
+            Ok(target_register)
+        }
+        ast::Expr::Subquery(_) | ast::Expr::SubqueryResult { .. } => {
+            emit_expr_subquery(program, referenced_tables, expr, target_register, resolver)
+        }
         ast::Expr::Unary(op, expr) => match (op, expr.as_ref()) {
             (UnaryOperator::Positive, expr) => {
                 translate_expr(program, referenced_tables, expr, target_register, resolver)
@@ -2792,6 +2977,11 @@ where
                 ast::Expr::Exists(_select) | ast::Expr::Subquery(_select) => {
                     // TODO: Walk through select statements if needed
                 }
+                ast::Expr::SubqueryResult { expr, .. } => {
+                    if let ast::Expr::InSelect { lhs, .. } = expr.as_ref() {
+                        walk_expr(lhs, func)?;
+                    }
+                }
                 ast::Expr::FunctionCall {
                     args,
                     order_by,
@@ -2915,6 +3105,7 @@ where
                 ast::Expr::Id(_)
                 | ast::Expr::Column { .. }
                 | ast::Expr::RowId { .. }
+                | ast::Expr::Register(_)
                 | ast::Expr::Literal(_)
                 | ast::Expr::DoublyQualified(..)
                 | ast::Expr::Name(_)
@@ -2988,6 +3179,11 @@ where
         ast::Expr::Exists(_) | ast::Expr::Subquery(_) => {
             // TODO: Walk through select statements if needed
         }
+        ast::Expr::SubqueryResult { expr, .. } => {
+            if let ast::Expr::InSelect { lhs, .. } = expr.as_mut() {
+                walk_expr_mut(lhs, func)?;
+            }
+        }
         ast::Expr::FunctionCall {
             args,
             order_by,
@@ -3111,6 +3307,7 @@ where
         ast::Expr::Id(_)
         | ast::Expr::Column { .. }
         | ast::Expr::RowId { .. }
+        | ast::Expr::Register(_)
         | ast::Expr::Literal(_)
         | ast::Expr::DoublyQualified(..)
         | ast::Expr::Name(_)
@@ -3185,6 +3382,32 @@ pub fn comparison_affinity(
     }
 }
 
+/// Returns the collating sequence of a comparison between two expressions: an explicit COLLATE
+/// takes precedence over the collation of a column, and the left operand over the right one.
+/// This is the collation [translate_expr] gives to a binary comparison.
+pub fn comparison_collation(
+    lhs_expr: &ast::Expr,
+    rhs_expr: &ast::Expr,
+    referenced_tables: &TableReferences,
+) -> Result<CollationSeq> {
+    for expr in [lhs_expr, rhs_expr] {
+        if let ast::Expr::Collate(_, collation_name) = expr {
+            return CollationSeq::new(collation_name);
+        }
+    }
+    for expr in [lhs_expr, rhs_expr] {
+        if let ast::Expr::Column { table, column, .. } = expr {
+            if let Some(table_column) = referenced_tables
+                .find_table_by_internal_id(*table)
+                .and_then(|table| table.get_column_at(*column))
+            {
+                return Ok(table_column.collation.unwrap_or_default());
+            }
+        }
+    }
+    Ok(CollationSeq::default())
+}
+
 pub fn compare_affinity(
     expr: &ast::Expr,
     other_affinity: Affinity,
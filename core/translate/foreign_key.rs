@@ -0,0 +1,946 @@
+//! VDBE bytecode generation for foreign key constraints.
+//!
+//! Like SQLite, we do not check a foreign key constraint by looking at the whole child table,
+//! but count the violations a statement introduces and resolves as it writes rows: a child row
+//! without a parent increments the counter, and a parent row appearing for orphaned child rows
+//! decrements it. Immediate constraints must have no violations left when the statement halts,
+//! deferred ones when the transaction commits.
+//!
+//! The `ON DELETE` / `ON UPDATE` actions are translated as nested `DELETE` / `UPDATE`
+//! statements on the child table, in sub-programs run each time they fire, like trigger bodies.
+//! More info: https://www.sqlite.org/foreignkeys.html
+
+use std::num::NonZeroUsize;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, Expr, Name, QualifiedName, RefAct};
+
+use crate::error::SQLITE_CONSTRAINT_FOREIGNKEY;
+use crate::schema::{Affinity, BTreeTable, ForeignKey, Index, Schema};
+use crate::translate::collate::CollationSeq;
+use crate::translate::delete::translate_delete;
+use crate::translate::emitter::Resolver;
+use crate::translate::trigger::{translate_nested, TriggerRowRegisters};
+use crate::translate::update::translate_update;
+use crate::vdbe::builder::{CursorType, ProgramBuilder, SubProgramKey};
+use crate::vdbe::insn::{CmpInsFlags, Insn};
+use crate::vdbe::BranchOffset;
+use crate::{bail_parse_error, Result, SymbolTable};
+
+/// Returns whether writing to `table` requires foreign key checks: foreign keys are enforced
+/// (`PRAGMA foreign_keys`) and the table is the child or the parent of a foreign key.
+/// `schema` is the schema of the database of the table.
+pub(crate) fn has_foreign_keys(
+    program: &ProgramBuilder,
+    schema: &Schema,
+    table: &BTreeTable,
+) -> bool {
+    program.foreign_keys()
+        && (!table.foreign_keys.is_empty()
+            || !schema.get_referencing_foreign_keys(&table.name).is_empty())
+}
+
+/// Emits the foreign key checks and actions for a row of `table` that was just written:
+/// inserted if only `new` is given, deleted if only `old` is, updated otherwise.
+/// `updated_columns` are the positions of the columns assigned by an UPDATE, only the
+/// constraints involving one of them are checked.
+/// `ignore_label` is where `RAISE(IGNORE)` jumps to in the triggers fired by the actions.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn emit_foreign_key_checks(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    database_id: usize,
+    table: &BTreeTable,
+    new: Option<TriggerRowRegisters>,
+    old: Option<TriggerRowRegisters>,
+    updated_columns: Option<&[usize]>,
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    let schema = resolver.schema.database_schema(database_id);
+    if !has_foreign_keys(program, schema, table) {
+        return Ok(());
+    }
+    let is_updated = |columns: &[usize]| {
+        updated_columns.is_none_or(|updated| columns.iter().any(|idx| updated.contains(idx)))
+    };
+
+    // The row as a child: its parent must exist
+    for fk in &table.foreign_keys {
+        let child_columns = child_columns(table, fk)?;
+        if !is_updated(&child_columns) {
+            continue;
+        }
+        let parent_key = ParentKey::resolve(resolver.schema, database_id, table, fk)?;
+        if let Some(old) = old {
+            emit_child_check(
+                program,
+                database_id,
+                table,
+                fk,
+                &child_columns,
+                &parent_key,
+                old,
+                -1,
+            );
+        }
+        if let Some(new) = new {
+            emit_child_check(
+                program,
+                database_id,
+                table,
+                fk,
+                &child_columns,
+                &parent_key,
+                new,
+                1,
+            );
+        }
+    }
+
+    // The row as a parent: the rows of the child tables that reference it
+    for (child, fk) in schema.get_referencing_foreign_keys(&table.name) {
+        let parent_key = ParentKey::resolve(resolver.schema, database_id, &child, &fk)?;
+        if !is_updated(&parent_key.columns) {
+            continue;
+        }
+        let child_columns = child_columns(&child, &fk)?;
+        let old_key = old.map(|old| parent_key.key_registers(old));
+        let new_key = new.map(|new| parent_key.key_registers(new));
+
+        // An UPDATE that leaves the parent key as it was neither orphans nor adopts children
+        let key_unchanged_label = program.allocate_label();
+        if let (Some(old_key), Some(new_key)) = (&old_key, &new_key) {
+            let key_changed_label = program.allocate_label();
+            for (old_reg, new_reg) in old_key.iter().zip(new_key) {
+                program.emit_insn(Insn::Ne {
+                    lhs: *old_reg,
+                    rhs: *new_reg,
+                    target_pc: key_changed_label,
+                    flags: CmpInsFlags::default().null_eq(),
+                    collation: None,
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: key_unchanged_label,
+            });
+            program.preassign_label_to_next_insn(key_changed_label);
+        }
+
+        let action = if new.is_some() {
+            fk.on_update
+        } else {
+            fk.on_delete
+        };
+        if let Some(old_key) = &old_key {
+            // The children of the old key are orphaned, unless an action takes care of them
+            emit_children_scan(
+                program,
+                database_id,
+                &child,
+                &child_columns,
+                &parent_key,
+                old_key,
+                None,
+                |program| {
+                    if action == RefAct::Restrict {
+                        emit_fk_halt(program);
+                    } else {
+                        program.emit_insn(Insn::FkCounter {
+                            deferred: fk.deferred,
+                            increment_value: 1,
+                        });
+                    }
+                },
+            );
+        }
+        if let Some(new_key) = &new_key {
+            // The children of the new key are adopted. An inserted row is not its own child,
+            // SQLite checks inserted rows before writing them.
+            let skip_rowid_reg = match old {
+                None if child.name == table.name => new.map(|new| new.rowid_reg),
+                _ => None,
+            };
+            emit_children_scan(
+                program,
+                database_id,
+                &child,
+                &child_columns,
+                &parent_key,
+                new_key,
+                skip_rowid_reg,
+                |program| emit_fk_decrement(program, fk.deferred),
+            );
+        }
+        if let Some(old_key) = &old_key {
+            emit_action(
+                program,
+                resolver,
+                database_id,
+                &child,
+                &child_columns,
+                &parent_key,
+                action,
+                old_key,
+                new_key.as_deref(),
+                ignore_label,
+            )?;
+        }
+        program.preassign_label_to_next_insn(key_unchanged_label);
+    }
+    Ok(())
+}
+
+/// Emits the implicit `DELETE FROM` that precedes the drop of `table` when foreign keys are
+/// enforced, like SQLite: the actions of the foreign keys referencing it apply to their child
+/// rows, and the `DROP TABLE` fails if any is left without a parent. The triggers of the table
+/// do not fire, they are dropped with it.
+pub(crate) fn emit_drop_table_foreign_keys(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    database_id: usize,
+    table: &BTreeTable,
+) -> Result<()> {
+    if !program.foreign_keys() {
+        return Ok(());
+    }
+    let database_schema = schema.database_schema(database_id);
+    let skip_label = if database_schema
+        .get_referencing_foreign_keys(&table.name)
+        .is_empty()
+    {
+        // Deleting the rows of a child table can only resolve the violations of its deferred
+        // constraints, there is nothing to do if the transaction has none
+        if !table.foreign_keys.iter().any(|fk| fk.deferred) {
+            return Ok(());
+        }
+        let skip_label = program.allocate_label();
+        program.emit_insn(Insn::FkIfZero {
+            deferred: true,
+            target_pc: skip_label,
+        });
+        Some(skip_label)
+    } else {
+        None
+    };
+
+    let database_name = schema.database_name(database_id).to_string();
+    let tbl_name = QualifiedName::fullname(Name(database_name), Name(table.name.clone()));
+    program.set_disable_triggers(true);
+    translate_nested(program, |program| {
+        translate_delete(schema, &tbl_name, None, None, None, syms, program)
+    })?;
+    program.set_disable_triggers(false);
+
+    let ok_label = program.allocate_label();
+    program.emit_insn(Insn::FkIfZero {
+        deferred: false,
+        target_pc: ok_label,
+    });
+    emit_fk_halt(program);
+    program.preassign_label_to_next_insn(ok_label);
+    if let Some(skip_label) = skip_label {
+        program.preassign_label_to_next_insn(skip_label);
+    }
+    Ok(())
+}
+
+/// Emits the `foreign_key_check` pragma for the table named `table_name`, or for every table
+/// of the database: one row for each row of a child table whose parent does not exist.
+pub(crate) fn emit_foreign_key_check(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    table_name: Option<&str>,
+) -> Result<()> {
+    let database_schema = schema.database_schema(database_id);
+    let tables = match table_name {
+        Some(table_name) => match database_schema
+            .get_table(table_name)
+            .and_then(|table| table.btree())
+        {
+            Some(table) => vec![table],
+            None => bail_parse_error!("no such table: {}", table_name),
+        },
+        None => {
+            let mut tables = database_schema
+                .tables
+                .values()
+                .filter_map(|table| table.btree())
+                .collect::<Vec<_>>();
+            tables.sort_by(|a, b| a.name.cmp(&b.name));
+            tables
+        }
+    };
+
+    let result_reg = program.alloc_registers(4);
+    for table in tables {
+        // Foreign keys are numbered like in `foreign_key_list`: the last declared one is 0
+        for (fkid, fk) in table.foreign_keys.iter().rev().enumerate() {
+            let child_columns = child_columns(&table, fk)?;
+            let parent_key = match database_schema.get_table(&fk.parent_table) {
+                Some(_) => Some(ParentKey::resolve(schema, database_id, &table, fk)?),
+                // Every child row is an orphan
+                None => None,
+            };
+
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: table.root_page,
+                db: database_id,
+            });
+            let loop_start_label = program.allocate_label();
+            let next_label = program.allocate_label();
+            let loop_end_label = program.allocate_label();
+            program.emit_insn(Insn::Rewind {
+                cursor_id,
+                pc_if_empty: loop_end_label,
+            });
+            program.preassign_label_to_next_insn(loop_start_label);
+            let key_regs = program.alloc_registers(child_columns.len());
+            for (i, idx) in child_columns.iter().enumerate() {
+                emit_table_column(program, cursor_id, &table, *idx, key_regs + i);
+                program.emit_insn(Insn::IsNull {
+                    reg: key_regs + i,
+                    target_pc: next_label,
+                });
+            }
+            if let Some(parent_key) = &parent_key {
+                let key_regs = (key_regs..key_regs + child_columns.len()).collect::<Vec<_>>();
+                parent_key.emit_lookup(program, database_id, &key_regs, next_label);
+            }
+            program.emit_string8(table.name.clone(), result_reg);
+            program.emit_insn(Insn::RowId {
+                cursor_id,
+                dest: result_reg + 1,
+            });
+            program.emit_string8(fk.parent_table.clone(), result_reg + 2);
+            program.emit_int(fkid as i64, result_reg + 3);
+            program.emit_result_row(result_reg, 4);
+            program.preassign_label_to_next_insn(next_label);
+            program.emit_insn(Insn::Next {
+                cursor_id,
+                pc_if_next: loop_start_label,
+            });
+            program.preassign_label_to_next_insn(loop_end_label);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of a referential action, as shown by `PRAGMA foreign_key_list`.
+pub(crate) fn ref_act_name(action: RefAct) -> &'static str {
+    match action {
+        RefAct::SetNull => "SET NULL",
+        RefAct::SetDefault => "SET DEFAULT",
+        RefAct::Cascade => "CASCADE",
+        RefAct::Restrict => "RESTRICT",
+        RefAct::NoAction => "NO ACTION",
+    }
+}
+
+/// The parent key of a foreign key, and how parent rows are looked up by it.
+struct ParentKey {
+    table: Rc<BTreeTable>,
+    /// Positions of the parent key columns in the parent table, in child column order.
+    columns: Vec<usize>,
+    lookup: ParentKeyLookup,
+}
+
+enum ParentKeyLookup {
+    /// The parent key is the rowid of the parent table.
+    Rowid,
+    /// The parent key is the key of a UNIQUE index.
+    Index(Arc<Index>),
+    /// No index on the parent key: the parent table is scanned.
+    Scan,
+}
+
+impl ParentKey {
+    /// Resolves the parent key of `fk`, declared on `child` in the database at `database_id`.
+    /// Like SQLite, it must be the PRIMARY KEY of the parent table or have a UNIQUE constraint.
+    fn resolve(
+        schema: &Schema,
+        database_id: usize,
+        child: &BTreeTable,
+        fk: &ForeignKey,
+    ) -> Result<Self> {
+        let database_schema = schema.database_schema(database_id);
+        let Some(table) = database_schema
+            .get_table(&fk.parent_table)
+            .and_then(|table| table.btree())
+        else {
+            bail_parse_error!(
+                "no such table: {}.{}",
+                schema.database_name(database_id),
+                fk.parent_table
+            );
+        };
+        let mismatch = || {
+            crate::LimboError::ParseError(format!(
+                "foreign key mismatch - \"{}\" referencing \"{}\"",
+                child.name, table.name
+            ))
+        };
+
+        let names = if fk.parent_columns.is_empty() {
+            table
+                .primary_key_columns
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        } else {
+            fk.parent_columns.clone()
+        };
+        if names.is_empty() || names.len() != fk.child_columns.len() {
+            return Err(mismatch());
+        }
+        let columns = names
+            .iter()
+            .map(|name| table.get_column(name).map(|(idx, _)| idx))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(mismatch)?;
+
+        let same_set = |other: &[String]| {
+            other.len() == names.len()
+                && other
+                    .iter()
+                    .all(|name| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        };
+        if let [idx] = columns[..] {
+            if table.columns[idx].is_rowid_alias {
+                return Ok(Self {
+                    table,
+                    columns,
+                    lookup: ParentKeyLookup::Rowid,
+                });
+            }
+        }
+        // The PRIMARY KEY of a WITHOUT ROWID table is the key of the table b-tree itself
+        let index = table
+            .clustered_index()
+            .iter()
+            .chain(database_schema.get_indices(&table.name))
+            .find(|index| {
+                index.unique
+                    && index.where_clause.is_none()
+                    && same_set(
+                        &index
+                            .key_columns()
+                            .iter()
+                            .map(|column| column.name.clone())
+                            .collect::<Vec<_>>(),
+                    )
+            })
+            .cloned();
+        if let Some(index) = index {
+            return Ok(Self {
+                table,
+                columns,
+                lookup: ParentKeyLookup::Index(index),
+            });
+        }
+        let primary_key = table
+            .primary_key_columns
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let is_unique = same_set(&primary_key)
+            || table.unique_sets.iter().flatten().any(|unique_set| {
+                same_set(
+                    &unique_set
+                        .columns
+                        .iter()
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            || matches!(columns[..], [idx] if table.columns[idx].unique);
+        if !is_unique {
+            return Err(mismatch());
+        }
+        Ok(Self {
+            table,
+            columns,
+            lookup: ParentKeyLookup::Scan,
+        })
+    }
+
+    /// Returns the registers holding the parent key of `row`, a row of the parent table.
+    fn key_registers(&self, row: TriggerRowRegisters) -> Vec<usize> {
+        self.columns
+            .iter()
+            .map(|idx| row_column_register(&self.table, row, *idx))
+            .collect()
+    }
+
+    /// Returns how values are compared to each column of the parent key: with the affinity
+    /// and the collation of the parent key column, like SQLite.
+    fn comparisons(&self) -> Vec<(Affinity, Option<CollationSeq>)> {
+        self.columns
+            .iter()
+            .map(|idx| {
+                let column = &self.table.columns[*idx];
+                (column.affinity(), column.collation)
+            })
+            .collect()
+    }
+
+    /// Emits a lookup of the parent row with the key in `key_regs`, which jumps to
+    /// `found_label` if it exists and falls through otherwise.
+    fn emit_lookup(
+        &self,
+        program: &mut ProgramBuilder,
+        database_id: usize,
+        key_regs: &[usize],
+        found_label: BranchOffset,
+    ) {
+        match &self.lookup {
+            ParentKeyLookup::Rowid => {
+                let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(self.table.clone()));
+                program.emit_insn(Insn::OpenRead {
+                    cursor_id,
+                    root_page: self.table.root_page,
+                    db: database_id,
+                });
+                let missing_label = program.allocate_label();
+                program.emit_insn(Insn::SeekRowid {
+                    cursor_id,
+                    src_reg: key_regs[0],
+                    target_pc: missing_label,
+                });
+                program.emit_insn(Insn::Goto {
+                    target_pc: found_label,
+                });
+                program.preassign_label_to_next_insn(missing_label);
+            }
+            ParentKeyLookup::Index(index) => {
+                let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+                program.emit_insn(Insn::OpenRead {
+                    cursor_id,
+                    root_page: index.root_page,
+                    db: database_id,
+                });
+                // The key is copied in index column order, converted to the parent affinities
+                let num_regs = index.key_columns().len();
+                let record_reg = program.alloc_registers(num_regs);
+                let mut affinities = String::with_capacity(num_regs);
+                for (i, index_column) in index.key_columns().iter().enumerate() {
+                    let pos = self
+                        .columns
+                        .iter()
+                        .position(|idx| *idx == index_column.pos_in_table)
+                        .expect("index columns are the parent key columns");
+                    program.emit_insn(Insn::Copy {
+                        src_reg: key_regs[pos],
+                        dst_reg: record_reg + i,
+                        extra_amount: 0,
+                    });
+                    affinities.push(
+                        self.table.columns[index_column.pos_in_table]
+                            .affinity()
+                            .aff_mask(),
+                    );
+                }
+                program.emit_insn(Insn::Affinity {
+                    start_reg: record_reg,
+                    count: NonZeroUsize::new(num_regs).expect("index has columns"),
+                    affinities,
+                });
+                program.emit_insn(Insn::Found {
+                    cursor_id,
+                    target_pc: found_label,
+                    record_reg,
+                    num_regs,
+                });
+            }
+            ParentKeyLookup::Scan => emit_scan(
+                program,
+                database_id,
+                &self.table,
+                &self.columns,
+                &self.comparisons(),
+                key_regs,
+                None,
+                |program| {
+                    program.emit_insn(Insn::Goto {
+                        target_pc: found_label,
+                    })
+                },
+            ),
+        }
+    }
+}
+
+/// Returns the positions in `child` of the child columns of `fk`.
+fn child_columns(child: &BTreeTable, fk: &ForeignKey) -> Result<Vec<usize>> {
+    fk.child_columns
+        .iter()
+        .map(|name| match child.get_column(name) {
+            Some((idx, _)) => Ok(idx),
+            None => bail_parse_error!("unknown column \"{}\" in foreign key definition", name),
+        })
+        .collect()
+}
+
+/// Returns the register holding the column at `idx` of `row`.
+/// The rowid alias column is not stored, its value is the rowid.
+fn row_column_register(table: &BTreeTable, row: TriggerRowRegisters, idx: usize) -> usize {
+    if table.columns[idx].is_rowid_alias {
+        row.rowid_reg
+    } else {
+        row.columns_start_reg + idx
+    }
+}
+
+/// Reads the column at `idx` of the row `cursor_id` points to into `dest`.
+fn emit_table_column(
+    program: &mut ProgramBuilder,
+    cursor_id: usize,
+    table: &BTreeTable,
+    idx: usize,
+    dest: usize,
+) {
+    if table.columns[idx].is_rowid_alias {
+        program.emit_insn(Insn::RowId { cursor_id, dest });
+    } else {
+        program.emit_column(cursor_id, idx, dest);
+    }
+}
+
+/// Emits the check of `row` of the child table against its parent: adds `increment_value`
+/// to the violations if the parent does not exist. A key with a NULL is never a violation.
+#[allow(clippy::too_many_arguments)]
+fn emit_child_check(
+    program: &mut ProgramBuilder,
+    database_id: usize,
+    child: &BTreeTable,
+    fk: &ForeignKey,
+    child_columns: &[usize],
+    parent_key: &ParentKey,
+    row: TriggerRowRegisters,
+    increment_value: i64,
+) {
+    let ok_label = program.allocate_label();
+    let key_regs = child_columns
+        .iter()
+        .map(|idx| row_column_register(child, row, *idx))
+        .collect::<Vec<_>>();
+    for reg in &key_regs {
+        program.emit_insn(Insn::IsNull {
+            reg: *reg,
+            target_pc: ok_label,
+        });
+    }
+    if increment_value < 0 {
+        program.emit_insn(Insn::FkIfZero {
+            deferred: fk.deferred,
+            target_pc: ok_label,
+        });
+        // The old row is checked after it was written, so a row that was its own parent
+        // looks like an orphan, when it was not one
+        if parent_key.table.name == child.name {
+            let not_own_parent_label = program.allocate_label();
+            for (key_reg, idx) in key_regs.iter().zip(&parent_key.columns) {
+                program.emit_insn(Insn::Ne {
+                    lhs: *key_reg,
+                    rhs: row_column_register(child, row, *idx),
+                    target_pc: not_own_parent_label,
+                    flags: CmpInsFlags::default().jump_if_null(),
+                    collation: None,
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: ok_label,
+            });
+            program.preassign_label_to_next_insn(not_own_parent_label);
+        }
+    }
+    parent_key.emit_lookup(program, database_id, &key_regs, ok_label);
+    program.emit_insn(Insn::FkCounter {
+        deferred: fk.deferred,
+        increment_value,
+    });
+    program.preassign_label_to_next_insn(ok_label);
+}
+
+/// Emits `emit_body` for each row of `child` that references the parent key in `key_regs`.
+#[allow(clippy::too_many_arguments)]
+fn emit_children_scan(
+    program: &mut ProgramBuilder,
+    database_id: usize,
+    child: &Rc<BTreeTable>,
+    child_columns: &[usize],
+    parent_key: &ParentKey,
+    key_regs: &[usize],
+    skip_rowid_reg: Option<usize>,
+    emit_body: impl FnOnce(&mut ProgramBuilder),
+) {
+    emit_scan(
+        program,
+        database_id,
+        child,
+        child_columns,
+        &parent_key.comparisons(),
+        key_regs,
+        skip_rowid_reg,
+        emit_body,
+    );
+}
+
+/// Emits a full scan of `table` that runs `emit_body` for each row whose `columns` are equal
+/// to the values in `key_regs`, skipping the row with the rowid in `skip_rowid_reg`, if any.
+#[allow(clippy::too_many_arguments)]
+fn emit_scan(
+    program: &mut ProgramBuilder,
+    database_id: usize,
+    table: &Rc<BTreeTable>,
+    columns: &[usize],
+    comparisons: &[(Affinity, Option<CollationSeq>)],
+    key_regs: &[usize],
+    skip_rowid_reg: Option<usize>,
+    emit_body: impl FnOnce(&mut ProgramBuilder),
+) {
+    let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    program.emit_insn(Insn::OpenRead {
+        cursor_id,
+        root_page: table.root_page,
+        db: database_id,
+    });
+    let loop_start_label = program.allocate_label();
+    let next_label = program.allocate_label();
+    let loop_end_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: loop_end_label,
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+    let column_reg = program.alloc_register();
+    for ((idx, key_reg), (affinity, collation)) in columns.iter().zip(key_regs).zip(comparisons) {
+        emit_table_column(program, cursor_id, table, *idx, column_reg);
+        program.emit_insn(Insn::Ne {
+            lhs: *key_reg,
+            rhs: column_reg,
+            target_pc: next_label,
+            flags: CmpInsFlags::default()
+                .jump_if_null()
+                .with_affinity(*affinity),
+            collation: *collation,
+        });
+    }
+    if let Some(skip_rowid_reg) = skip_rowid_reg {
+        program.emit_insn(Insn::RowId {
+            cursor_id,
+            dest: column_reg,
+        });
+        program.emit_insn(Insn::Eq {
+            lhs: column_reg,
+            rhs: skip_rowid_reg,
+            target_pc: next_label,
+            flags: CmpInsFlags::default(),
+            collation: None,
+        });
+    }
+    emit_body(program);
+    program.preassign_label_to_next_insn(next_label);
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(loop_end_label);
+}
+
+/// Removes a violation, if there is any left: the counter never goes below zero.
+fn emit_fk_decrement(program: &mut ProgramBuilder, deferred: bool) {
+    let done_label = program.allocate_label();
+    program.emit_insn(Insn::FkIfZero {
+        deferred,
+        target_pc: done_label,
+    });
+    program.emit_insn(Insn::FkCounter {
+        deferred,
+        increment_value: -1,
+    });
+    program.preassign_label_to_next_insn(done_label);
+}
+
+fn emit_fk_halt(program: &mut ProgramBuilder) {
+    program.emit_insn(Insn::Halt {
+        err_code: SQLITE_CONSTRAINT_FOREIGNKEY,
+        description: String::new(),
+        on_error: ast::ResolveType::Abort,
+    });
+}
+
+/// Emits the `ON DELETE` / `ON UPDATE` action of a foreign key for the children of the parent
+/// key in `old_key`, which was deleted, or changed to the one in `new_key`.
+#[allow(clippy::too_many_arguments)]
+fn emit_action(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    database_id: usize,
+    child: &Rc<BTreeTable>,
+    child_columns: &[usize],
+    parent_key: &ParentKey,
+    action: RefAct,
+    old_key: &[usize],
+    new_key: Option<&[usize]>,
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    if matches!(action, RefAct::NoAction | RefAct::Restrict) {
+        return Ok(());
+    }
+    let update = new_key.is_some();
+    // The arguments of the action program: the old key, then the new one for ON UPDATE CASCADE
+    let new_key = new_key.filter(|_| action == RefAct::Cascade);
+    let num_args = old_key.len() + new_key.map_or(0, |key| key.len());
+    let args_reg = program.alloc_registers(num_args);
+    for (i, reg) in old_key
+        .iter()
+        .chain(new_key.unwrap_or_default())
+        .enumerate()
+    {
+        program.emit_insn(Insn::Copy {
+            src_reg: *reg,
+            dst_reg: args_reg + i,
+            extra_amount: 0,
+        });
+    }
+    let key = SubProgramKey::ForeignKeyAction {
+        database_id,
+        child_table: child.name.clone(),
+        parent_table: parent_key.table.name.clone(),
+        child_columns: child_columns.to_vec(),
+        update,
+    };
+    let sub_program = match program.sub_program(&key) {
+        Some(index) => index,
+        None => {
+            let (index, mut sub_program) = program.begin_sub_program(key, false);
+            let args_reg = sub_program.alloc_registers(num_args);
+            let old_key = (args_reg..args_reg + old_key.len()).collect::<Vec<_>>();
+            let new_key = new_key
+                .map(|_| (args_reg + old_key.len()..args_reg + num_args).collect::<Vec<_>>());
+            translate_action(
+                &mut sub_program,
+                resolver,
+                database_id,
+                child,
+                child_columns,
+                action,
+                &old_key,
+                new_key.as_deref(),
+            )?;
+            program.end_sub_program(index, sub_program);
+            index
+        }
+    };
+    // Actions can cascade through self-referencing tables, so they always run recursively
+    program.emit_insn(Insn::Program {
+        sub_program,
+        args_reg,
+        num_args,
+        ignore_target: ignore_label,
+        skip_if_active: false,
+        description: format!("-- FOREIGN KEY ACTION {}", child.name),
+    });
+    Ok(())
+}
+
+/// Translates the action program of [emit_action], with the keys in its argument registers.
+#[allow(clippy::too_many_arguments)]
+fn translate_action(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    database_id: usize,
+    child: &Rc<BTreeTable>,
+    child_columns: &[usize],
+    action: RefAct,
+    old_key: &[usize],
+    new_key: Option<&[usize]>,
+) -> Result<()> {
+    let column_name = |idx: &usize| {
+        child.columns[*idx]
+            .name
+            .clone()
+            .expect("Column name must be present")
+    };
+    let new_values = match (action, new_key) {
+        (RefAct::NoAction | RefAct::Restrict, _) => return Ok(()),
+        (RefAct::Cascade, None) => None,
+        (RefAct::Cascade, Some(new_key)) => Some(
+            new_key
+                .iter()
+                .map(|reg| Expr::Register(*reg))
+                .collect::<Vec<_>>(),
+        ),
+        (RefAct::SetNull, _) => Some(vec![Expr::Literal(ast::Literal::Null); child_columns.len()]),
+        (RefAct::SetDefault, _) => Some(
+            child_columns
+                .iter()
+                .map(|idx| {
+                    child.columns[*idx]
+                        .default
+                        .clone()
+                        .unwrap_or(Expr::Literal(ast::Literal::Null))
+                })
+                .collect(),
+        ),
+    };
+
+    let where_clause = child_columns
+        .iter()
+        .zip(old_key)
+        .map(|(idx, reg)| {
+            Expr::Binary(
+                Box::new(Expr::Id(ast::Id(column_name(idx)))),
+                ast::Operator::Equals,
+                Box::new(Expr::Register(*reg)),
+            )
+        })
+        .reduce(|lhs, rhs| Expr::Binary(Box::new(lhs), ast::Operator::And, Box::new(rhs)))
+        .expect("foreign key has columns");
+    let database_name = resolver.schema.database_name(database_id).to_string();
+    let tbl_name = QualifiedName::fullname(Name(database_name), Name(child.name.clone()));
+
+    match new_values {
+        None => translate_nested(program, |program| {
+            translate_delete(
+                resolver.schema,
+                &tbl_name,
+                Some(Box::new(where_clause)),
+                None,
+                None,
+                resolver.symbol_table,
+                program,
+            )
+        }),
+        Some(new_values) => {
+            let sets = child_columns
+                .iter()
+                .zip(new_values)
+                .map(|(idx, expr)| ast::Set {
+                    col_names: ast::DistinctNames::single(Name(column_name(idx))),
+                    expr,
+                })
+                .collect();
+            let mut update = ast::Update {
+                with: None,
+                or_conflict: None,
+                tbl_name,
+                indexed: None,
+                sets,
+                from: None,
+                where_clause: Some(Box::new(where_clause)),
+                returning: None,
+                order_by: None,
+                limit: None,
+            };
+            translate_nested(program, |program| {
+                translate_update(resolver.schema, &mut update, resolver.symbol_table, program)
+            })
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! VDBE bytecode generation for generated columns.
+//!
+//! Like SQLite, a `STORED` generated column is computed whenever a row is written and kept in the
+//! table record, while a `VIRTUAL` one has no slot in the record and is computed whenever it is
+//! read. A generated column expression can only refer to the columns of its own row, which we
+//! substitute with the registers or cursor reads holding them.
+//! More info: https://www.sqlite.org/gencol.html
+
+use std::num::NonZeroUsize;
+
+use turso_sqlite3_parser::ast::Expr;
+
+use crate::schema::{Affinity, BTreeTable, Column};
+use crate::translate::emitter::Resolver;
+use crate::translate::expr::{translate_expr, walk_expr, walk_expr_mut, WalkControl};
+use crate::translate::trigger::TriggerRowRegisters;
+use crate::util::normalize_ident;
+use crate::vdbe::builder::ProgramBuilder;
+use crate::vdbe::insn::Insn;
+use crate::vdbe::CursorID;
+use crate::{bail_parse_error, Result};
+
+/// Validates the generated columns of a table being created.
+pub(crate) fn check_generated_columns(table: &BTreeTable) -> Result<()> {
+    for column in table.columns.iter() {
+        let Some(generated) = &column.generated else {
+            continue;
+        };
+        if column.default.is_some() {
+            bail_parse_error!("cannot use DEFAULT on a generated column");
+        }
+        if column.primary_key {
+            bail_parse_error!("generated columns cannot be part of the PRIMARY KEY");
+        }
+        walk_expr(&generated.expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            match expr {
+                Expr::Id(name) if table.get_column(&name.0).is_none() => {
+                    bail_parse_error!("no such column: {}", name.0)
+                }
+                Expr::Qualified(..) | Expr::DoublyQualified(..) => {
+                    bail_parse_error!("the \".\" operator prohibited in generated columns")
+                }
+                _ => Ok(WalkControl::Continue),
+            }
+        })?;
+    }
+    generated_column_order(table)?;
+    Ok(())
+}
+
+/// Returns the columns of `table` referenced by `expr`.
+fn referenced_columns(table: &BTreeTable, expr: &Expr) -> Vec<usize> {
+    let mut columns = Vec::new();
+    let _ = walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        if let Expr::Id(name) = expr {
+            columns.extend(table.get_column(&name.0).map(|(idx, _)| idx));
+        }
+        Ok(WalkControl::Continue)
+    });
+    columns
+}
+
+/// Returns the generated columns of `table`, each one after the generated columns it refers to.
+fn generated_column_order(table: &BTreeTable) -> Result<Vec<usize>> {
+    fn visit(
+        table: &BTreeTable,
+        idx: usize,
+        visiting: &mut [bool],
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        if order.contains(&idx) {
+            return Ok(());
+        }
+        let column = &table.columns[idx];
+        let Some(generated) = &column.generated else {
+            return Ok(());
+        };
+        visiting[idx] = true;
+        for referenced in referenced_columns(table, &generated.expr) {
+            if visiting[referenced] {
+                bail_parse_error!(
+                    "generated column loop on \"{}\"",
+                    column.name.as_deref().unwrap_or_default()
+                );
+            }
+            visit(table, referenced, visiting, order)?;
+        }
+        visiting[idx] = false;
+        order.push(idx);
+        Ok(())
+    }
+
+    let mut visiting = vec![false; table.columns.len()];
+    let mut order = Vec::new();
+    for idx in 0..table.columns.len() {
+        visit(table, idx, &mut visiting, &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Returns `updated_columns` along with the generated columns whose value depends on them.
+pub(crate) fn columns_changed_by_update(
+    table: &BTreeTable,
+    updated_columns: &[usize],
+) -> Vec<usize> {
+    let mut changed = updated_columns.to_vec();
+    // The order puts a generated column after the ones it depends on, so one pass is enough
+    for idx in generated_column_order(table).unwrap_or_default() {
+        let expr = &table.columns[idx].generated.as_ref().unwrap().expr;
+        if !changed.contains(&idx)
+            && referenced_columns(table, expr)
+                .iter()
+                .any(|referenced| changed.contains(referenced))
+        {
+            changed.push(idx);
+        }
+    }
+    changed
+}
+
+/// Replaces the column references of `expr`, an expression on the columns of a row of `table`
+/// like the one of a generated column, with the registers returned by `column_reg`.
+pub(crate) fn rewrite_column_references(
+    table: &BTreeTable,
+    expr: &mut Expr,
+    column_reg: &mut impl FnMut(usize) -> Result<usize>,
+) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+        if let Expr::Id(name) = expr {
+            let column_name = normalize_ident(&name.0);
+            let Some((idx, _)) = table.get_column(&column_name) else {
+                bail_parse_error!("no such column: {}", column_name);
+            };
+            *expr = Expr::Register(column_reg(idx)?);
+        }
+        Ok(())
+    })
+}
+
+/// Applies the affinity of `column` to the value computed for it in `reg`.
+fn emit_generated_affinity(program: &mut ProgramBuilder, column: &Column, reg: usize) {
+    let affinity = column.affinity();
+    if affinity != Affinity::Blob {
+        program.emit_insn(Insn::Affinity {
+            start_reg: reg,
+            count: NonZeroUsize::new(1).unwrap(),
+            affinities: affinity.aff_mask().to_string(),
+        });
+    }
+}
+
+/// Computes the generated columns of `table` into the registers of `row`.
+/// With `virtual_only`, only the `VIRTUAL` columns are computed, for a row read from the table
+/// where the `STORED` ones already hold their value.
+pub(crate) fn emit_generated_columns(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    row: TriggerRowRegisters,
+    virtual_only: bool,
+) -> Result<()> {
+    for idx in generated_column_order(table)? {
+        let column = &table.columns[idx];
+        if virtual_only && !column.is_virtual() {
+            continue;
+        }
+        let mut expr = column.generated.as_ref().unwrap().expr.clone();
+        rewrite_column_references(table, &mut expr, &mut |idx| {
+            Ok(if table.columns[idx].is_rowid_alias {
+                row.rowid_reg
+            } else {
+                row.columns_start_reg + idx
+            })
+        })?;
+        let reg = row.columns_start_reg + idx;
+        translate_expr(program, None, &expr, reg, resolver)?;
+        emit_generated_affinity(program, column, reg);
+    }
+    Ok(())
+}
+
+/// Reads column `column` of the row the table cursor `cursor_id` points at into `dest`,
+/// computing it from the other columns if it is a `VIRTUAL` generated column.
+pub(crate) fn emit_table_column(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    cursor_id: CursorID,
+    column: usize,
+    dest: usize,
+) -> Result<()> {
+    let table_column = &table.columns[column];
+    let Some(generated) = table_column.generated.as_ref().filter(|g| !g.stored) else {
+        program.emit_column(cursor_id, column, dest);
+        return Ok(());
+    };
+    let mut expr = generated.expr.clone();
+    rewrite_column_references(table, &mut expr, &mut |idx| {
+        let reg = program.alloc_register();
+        if table.columns[idx].is_rowid_alias {
+            program.emit_insn(Insn::RowId {
+                cursor_id,
+                dest: reg,
+            });
+        } else {
+            emit_table_column(program, resolver, table, cursor_id, idx, reg)?;
+        }
+        Ok(reg)
+    })?;
+    translate_expr(program, None, &expr, dest, resolver)?;
+    emit_generated_affinity(program, table_column, dest);
+    Ok(())
+}
+
+/// Emits a `MakeRecord` of the row whose columns are in the registers starting at
+/// `columns_start_reg`, leaving out the `VIRTUAL` generated columns and putting the PRIMARY KEY
+/// columns of a WITHOUT ROWID table first.
+pub(crate) fn emit_table_record(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    columns_start_reg: usize,
+    dest_reg: usize,
+) {
+    if table.has_rowid && !table.has_virtual_columns() {
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: columns_start_reg,
+            count: table.columns.len(),
+            dest_reg,
+            index_name: None,
+        });
+        return;
+    }
+    let stored_columns = table.storage_columns();
+    let start_reg = program.alloc_registers(stored_columns.len());
+    for (i, idx) in stored_columns.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: columns_start_reg + idx,
+            dst_reg: start_reg + i,
+            extra_amount: 0,
+        });
+    }
+    program.emit_insn(Insn::MakeRecord {
+        start_reg,
+        count: stored_columns.len(),
+        dest_reg,
+        index_name: None,
+    });
+}
@@ -284,6 +284,15 @@ fn collect_result_columns<'a>(
                     result_columns.push(expr);
                 }
             }
+            // A subquery is evaluated for each row of the main loop, since it may read the
+            // columns of any table of this query
+            ast::Expr::Exists(_)
+            | ast::Expr::Subquery(_)
+            | ast::Expr::InSelect { .. }
+            | ast::Expr::SubqueryResult { .. } => {
+                result_columns.push(expr);
+                return Ok(WalkControl::SkipChildren);
+            }
             _ => {
                 if plan.aggregates.iter().any(|a| a.original_expr == *expr) {
                     return Ok(WalkControl::SkipChildren);
@@ -863,7 +872,7 @@ pub fn group_by_emit_row_phase<'a>(
     program.emit_insn(Insn::Null {
         dest: start_reg,
         dest_end: Some(
-            start_reg + t_ctx.non_aggregate_expressions.len() + plan.agg_args_count() - 1,
+            start_reg + t_ctx.non_aggregate_expressions.len() + plan.aggregates.len() - 1,
         ),
     });
 
@@ -1,8 +1,11 @@
 use std::sync::Arc;
 
+use crate::function::Func;
 use crate::vdbe::insn::{CmpInsFlags, Cookie};
+use crate::vdbe::{BranchOffset, CursorID};
+use crate::SymbolTable;
 use crate::{
-    schema::{BTreeTable, Column, Index, IndexColumn, PseudoCursorType, Schema},
+    schema::{BTreeTable, Index, IndexColumn, PseudoCursorType, Schema},
     storage::pager::CreateBTreeFlags,
     util::normalize_ident,
     vdbe::{
@@ -12,21 +15,25 @@ use crate::{
 };
 use turso_sqlite3_parser::ast::{self, Expr, Id, SortOrder, SortedColumn};
 
+use super::analyze::{emit_drop_stat1_rows, Stat1Rows};
+use super::collate::CollationSeq;
+use super::emitter::Resolver;
+use super::expr::{translate_expr, walk_expr, WalkControl};
+use super::generated::{emit_table_column, rewrite_column_references};
 use super::schema::{emit_schema_entry, SchemaEntryType, SQLITE_TABLEID};
+use super::trigger::TriggerRowRegisters;
 
+#[allow(clippy::too_many_arguments)]
 pub fn translate_create_index(
     unique_if_not_exists: (bool, bool),
     idx_name: &str,
     tbl_name: &str,
     columns: &[SortedColumn],
+    where_clause: Option<&Expr>,
     schema: &Schema,
+    syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> crate::Result<ProgramBuilder> {
-    if !schema.indexes_enabled() {
-        crate::bail_parse_error!(
-            "CREATE INDEX is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
-    }
     let idx_name = normalize_ident(idx_name);
     let tbl_name = normalize_ident(tbl_name);
     let opts = crate::vdbe::builder::ProgramBuilderOpts {
@@ -41,32 +48,31 @@ pub fn translate_create_index(
     if !schema.is_unique_idx_name(&idx_name) {
         crate::bail_parse_error!("Error: index with name '{idx_name}' already exists.");
     }
+    let table_qualified_name = ast::QualifiedName::single(ast::Name(tbl_name.clone()));
+    if schema.locate_database(&table_qualified_name)? == 1 {
+        crate::bail_parse_error!("CREATE INDEX on temporary tables is not supported yet");
+    }
     let Some(tbl) = schema.tables.get(&tbl_name) else {
         crate::bail_parse_error!("Error: table '{tbl_name}' does not exist.");
     };
     let Some(tbl) = tbl.btree() else {
         crate::bail_parse_error!("Error: table '{tbl_name}' is not a b-tree table.");
     };
-    let columns = resolve_sorted_columns(&tbl, columns)?;
-
-    let idx = Arc::new(Index {
-        name: idx_name.clone(),
-        table_name: tbl.name.clone(),
-        root_page: 0, //  we dont have access till its created, after we parse the schema table
-        columns: columns
-            .iter()
-            .map(|((pos_in_table, col), order)| IndexColumn {
-                name: col.name.as_ref().unwrap().clone(),
-                order: *order,
-                pos_in_table: *pos_in_table,
-                collation: col.collation,
-                default: col.default.clone(),
-            })
-            .collect(),
-        unique: unique_if_not_exists.0,
-        ephemeral: false,
-        has_rowid: tbl.has_rowid,
-    });
+    let resolver = Resolver::new(schema, syms);
+    let columns = resolve_sorted_columns(&tbl, columns, &resolver)?;
+    if let Some(where_clause) = where_clause {
+        check_index_expr(&tbl, where_clause, &resolver, "partial index WHERE clauses")?;
+    }
+    let sql = create_idx_stmt_to_sql(
+        &tbl_name,
+        &idx_name,
+        unique_if_not_exists,
+        &columns,
+        where_clause,
+    );
+    // The root page is not known until the btree is created, the index is reloaded with it when
+    // the schema table is parsed.
+    let idx = Arc::new(Index::from_sql(&sql, 0, &tbl)?);
 
     // Allocate the necessary cursors:
     //
@@ -98,8 +104,8 @@ pub fn translate_create_index(
         cursor_id: sqlite_schema_cursor_id,
         root_page: RegisterOrLiteral::Literal(sqlite_table.root_page),
         name: sqlite_table.name.clone(),
+        db: 0,
     });
-    let sql = create_idx_stmt_to_sql(&tbl_name, &idx_name, unique_if_not_exists, &columns);
     emit_schema_entry(
         &mut program,
         sqlite_schema_cursor_id,
@@ -115,21 +121,24 @@ pub fn translate_create_index(
     // open the sorter and the pseudo table
     program.emit_insn(Insn::SorterOpen {
         cursor_id: sorter_cursor_id,
-        columns: columns.len(),
+        columns: idx.columns.len(),
         order,
-        collations: tbl.column_collations(),
+        collations: idx.columns.iter().map(|c| c.collation).collect(),
     });
+    // the index key: its columns, followed by the rowid unless the table is WITHOUT ROWID
+    let key_len = idx.columns.len() + idx.has_rowid as usize;
     let content_reg = program.alloc_register();
     program.emit_insn(Insn::OpenPseudo {
         cursor_id: pseudo_cursor_id,
         content_reg,
-        num_fields: columns.len() + 1,
+        num_fields: key_len,
     });
 
     // open the table we are creating the index on for reading
     program.emit_insn(Insn::OpenRead {
         cursor_id: table_cursor_id,
         root_page: tbl.root_page,
+        db: 0,
     });
 
     let loop_start_label = program.allocate_label();
@@ -144,20 +153,16 @@ pub fn translate_create_index(
     // Collect index values into start_reg..rowid_reg
     // emit MakeRecord (index key + rowid) into record_reg.
     //
-    // Then insert the record into the sorter
-    let start_reg = program.alloc_registers(columns.len() + 1);
-    for (i, (col, _)) in columns.iter().enumerate() {
-        program.emit_column(table_cursor_id, col.0, start_reg + i);
-    }
-    let rowid_reg = start_reg + columns.len();
-    program.emit_insn(Insn::RowId {
-        cursor_id: table_cursor_id,
-        dest: rowid_reg,
-    });
+    // Then insert the record into the sorter, unless the row is left out of a partial index
+    let skip_row_label = program.allocate_label();
+    let source = IndexKeySource::Cursor(table_cursor_id);
+    emit_partial_index_check(&mut program, &resolver, &tbl, &idx, source, skip_row_label)?;
+    let start_reg = program.alloc_registers(key_len);
+    emit_index_key(&mut program, &resolver, &tbl, &idx, source, start_reg)?;
     let record_reg = program.alloc_register();
     program.emit_insn(Insn::MakeRecord {
         start_reg,
-        count: columns.len() + 1,
+        count: key_len,
         dest_reg: record_reg,
         index_name: Some(idx_name.clone()),
     });
@@ -165,6 +170,7 @@ pub fn translate_create_index(
         cursor_id: sorter_cursor_id,
         record_reg,
     });
+    program.preassign_label_to_next_insn(skip_row_label);
 
     program.emit_insn(Insn::Next {
         cursor_id: table_cursor_id,
@@ -178,6 +184,7 @@ pub fn translate_create_index(
         cursor_id: btree_cursor_id,
         root_page: RegisterOrLiteral::Register(root_page_reg),
         name: idx_name.clone(),
+        db: 0,
     });
 
     let sorted_loop_start = program.allocate_label();
@@ -228,7 +235,7 @@ pub fn translate_create_index(
     // Parse the schema table to get the index root page and add new index to Schema
     let parse_schema_where_clause = format!("name = '{idx_name}' AND type = 'index'");
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: 0,
         where_clause: Some(parse_schema_where_clause),
     });
     // Close the final sqlite_schema cursor
@@ -242,25 +249,261 @@ pub fn translate_create_index(
     Ok(program)
 }
 
-fn resolve_sorted_columns<'a>(
-    table: &'a BTreeTable,
+/// Where the columns of the row an index key is built for are read from.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum IndexKeySource {
+    /// The row the table cursor points at.
+    Cursor(CursorID),
+    /// The row held in registers, like the new row of an INSERT or UPDATE.
+    Registers(TriggerRowRegisters),
+}
+
+/// Replaces the column references of an indexed expression or partial index `WHERE` clause with
+/// registers holding the columns of the row of `source`.
+fn rewrite_index_expr(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    source: IndexKeySource,
+    expr: &mut Expr,
+) -> crate::Result<()> {
+    rewrite_column_references(table, expr, &mut |idx| match source {
+        IndexKeySource::Registers(row) if table.columns[idx].is_rowid_alias => Ok(row.rowid_reg),
+        IndexKeySource::Registers(row) => Ok(row.columns_start_reg + idx),
+        IndexKeySource::Cursor(cursor_id) => {
+            let reg = program.alloc_register();
+            if table.columns[idx].is_rowid_alias {
+                program.emit_insn(Insn::RowId {
+                    cursor_id,
+                    dest: reg,
+                });
+            } else {
+                emit_table_column(program, resolver, table, cursor_id, idx, reg)?;
+            }
+            Ok(reg)
+        }
+    })
+}
+
+/// Emits the value of `index_column` for the row of `source` into `dest`.
+pub(crate) fn emit_index_column(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index_column: &IndexColumn,
+    source: IndexKeySource,
+    dest: usize,
+) -> crate::Result<()> {
+    let Some(expr) = &index_column.expr else {
+        // The record of the table holds NULL for a rowid alias, the key holds the rowid itself.
+        let is_rowid_alias = table.columns[index_column.pos_in_table].is_rowid_alias;
+        match source {
+            IndexKeySource::Cursor(cursor_id) if is_rowid_alias => {
+                program.emit_insn(Insn::RowId { cursor_id, dest });
+            }
+            IndexKeySource::Registers(row) if is_rowid_alias => program.emit_insn(Insn::Copy {
+                src_reg: row.rowid_reg,
+                dst_reg: dest,
+                extra_amount: 0,
+            }),
+            IndexKeySource::Cursor(cursor_id) => emit_table_column(
+                program,
+                resolver,
+                table,
+                cursor_id,
+                index_column.pos_in_table,
+                dest,
+            )?,
+            IndexKeySource::Registers(row) => program.emit_insn(Insn::Copy {
+                src_reg: row.columns_start_reg + index_column.pos_in_table,
+                dst_reg: dest,
+                extra_amount: 0,
+            }),
+        }
+        return Ok(());
+    };
+    let mut expr = expr.as_ref().clone();
+    rewrite_index_expr(program, resolver, table, source, &mut expr)?;
+    translate_expr(program, None, &expr, dest, resolver)?;
+    Ok(())
+}
+
+/// Emits the key of `index` for the row of `source`, its columns followed by the rowid (unless
+/// the table is WITHOUT ROWID), into the registers starting at `start_reg`.
+pub(crate) fn emit_index_key(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    source: IndexKeySource,
+    start_reg: usize,
+) -> crate::Result<()> {
+    for (i, index_column) in index.columns.iter().enumerate() {
+        emit_index_column(
+            program,
+            resolver,
+            table,
+            index_column,
+            source,
+            start_reg + i,
+        )?;
+    }
+    if !index.has_rowid {
+        return Ok(());
+    }
+    let rowid_reg = start_reg + index.columns.len();
+    match source {
+        IndexKeySource::Cursor(cursor_id) => program.emit_insn(Insn::RowId {
+            cursor_id,
+            dest: rowid_reg,
+        }),
+        IndexKeySource::Registers(row) => program.emit_insn(Insn::Copy {
+            src_reg: row.rowid_reg,
+            dst_reg: rowid_reg,
+            extra_amount: 0,
+        }),
+    }
+    Ok(())
+}
+
+/// Jumps to `skip_label` if `index` is a partial index that leaves out the row of `source`.
+pub(crate) fn emit_partial_index_check(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index: &Index,
+    source: IndexKeySource,
+    skip_label: BranchOffset,
+) -> crate::Result<()> {
+    let Some(where_clause) = &index.where_clause else {
+        return Ok(());
+    };
+    let mut expr = where_clause.as_ref().clone();
+    rewrite_index_expr(program, resolver, table, source, &mut expr)?;
+    let reg = program.alloc_register();
+    translate_expr(program, None, &expr, reg, resolver)?;
+    program.emit_insn(Insn::IfNot {
+        reg,
+        target_pc: skip_label,
+        jump_if_null: true,
+    });
+    Ok(())
+}
+
+/// Returns whether the key of `index` depends on any of `columns`, including through its
+/// indexed expressions and partial index `WHERE` clause.
+pub(crate) fn index_uses_columns(table: &BTreeTable, index: &Index, columns: &[usize]) -> bool {
+    let expr_uses_columns = |expr: &Expr| {
+        let mut uses_columns = false;
+        let _ = walk_expr(expr, &mut |expr: &Expr| -> crate::Result<WalkControl> {
+            if let Expr::Id(name) = expr {
+                uses_columns |= table
+                    .get_column(&name.0)
+                    .is_some_and(|(idx, _)| columns.contains(&idx));
+            }
+            Ok(WalkControl::Continue)
+        });
+        uses_columns
+    };
+    index
+        .columns
+        .iter()
+        .any(|index_column| match &index_column.expr {
+            Some(expr) => expr_uses_columns(expr),
+            None => columns.contains(&index_column.pos_in_table),
+        })
+        || index
+            .where_clause
+            .as_ref()
+            .is_some_and(|where_clause| expr_uses_columns(where_clause))
+}
+
+/// The description of a violation of the uniqueness constraint `index` enforces.
+pub(crate) fn unique_index_violation(table_name: &str, index: &Index) -> String {
+    if index
+        .key_columns()
+        .iter()
+        .any(|column| column.expr.is_some())
+    {
+        return format!("index '{}'", index.name);
+    }
+    index
+        .key_columns()
+        .iter()
+        .map(|column| format!("{table_name}.{}", column.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks that `expr` can be used in an index of `table`: it may only refer to the columns of
+/// the table and must have the same value whenever it is evaluated for a row.
+fn check_index_expr(
+    table: &BTreeTable,
+    expr: &Expr,
+    resolver: &Resolver,
+    context: &str,
+) -> crate::Result<()> {
+    walk_expr(expr, &mut |expr: &Expr| -> crate::Result<WalkControl> {
+        match expr {
+            Expr::Id(name) if table.get_column(&name.0).is_none() => {
+                crate::bail_parse_error!("no such column: {}", name.0)
+            }
+            Expr::Qualified(..) | Expr::DoublyQualified(..) => {
+                crate::bail_parse_error!("the \".\" operator prohibited in {context}")
+            }
+            Expr::Subquery(_) | Expr::Exists(_) | Expr::InSelect { .. } => {
+                crate::bail_parse_error!("subqueries prohibited in {context}")
+            }
+            Expr::Variable(_) => crate::bail_parse_error!("parameters prohibited in {context}"),
+            Expr::FunctionCall { name, args, .. } => {
+                let arg_count = args.as_ref().map_or(0, |args| args.len());
+                match resolver.resolve_function(&name.0, arg_count) {
+                    None => crate::bail_parse_error!("no such function: {}", name.0),
+                    Some(Func::Agg(_)) => {
+                        crate::bail_parse_error!("misuse of aggregate function {}()", name.0)
+                    }
+                    Some(func) if !func.is_deterministic() => crate::bail_parse_error!(
+                        "non-deterministic functions prohibited in {context}"
+                    ),
+                    Some(_) => Ok(WalkControl::Continue),
+                }
+            }
+            Expr::FunctionCallStar { name, .. } => {
+                crate::bail_parse_error!("misuse of aggregate function {}()", name.0)
+            }
+            _ => Ok(WalkControl::Continue),
+        }
+    })?;
+    Ok(())
+}
+
+/// Resolves the indexed columns and expressions of a CREATE INDEX, returning the SQL text of
+/// each one along with its sort order.
+fn resolve_sorted_columns(
+    table: &BTreeTable,
     cols: &[SortedColumn],
-) -> crate::Result<Vec<((usize, &'a Column), SortOrder)>> {
+    resolver: &Resolver,
+) -> crate::Result<Vec<(String, SortOrder)>> {
     let mut resolved = Vec::with_capacity(cols.len());
     for sc in cols {
-        let ident = normalize_ident(match &sc.expr {
-            // SQLite supports indexes on arbitrary expressions, but we don't (yet).
-            // See "How to use indexes on expressions" in https://www.sqlite.org/expridx.html
+        let order = sc.order.unwrap_or(SortOrder::Asc);
+        let col_name = match &sc.expr {
             Expr::Id(Id(col_name)) | Expr::Name(ast::Name(col_name)) => col_name,
-            _ => crate::bail_parse_error!("Error: cannot use expressions in CREATE INDEX"),
-        });
-        let Some(col) = table.get_column(&ident) else {
+            // See "How to use indexes on expressions" in https://www.sqlite.org/expridx.html
+            expr => {
+                check_index_expr(table, expr, resolver, "index expressions")?;
+                resolved.push((expr.to_string(), order));
+                continue;
+            }
+        };
+        let ident = normalize_ident(col_name);
+        let Some((_, col)) = table.get_column(&ident) else {
             crate::bail_parse_error!(
                 "Error: column '{ident}' does not exist in table '{}'",
                 table.name
             );
         };
-        resolved.push((col, sc.order.unwrap_or(SortOrder::Asc)));
+        resolved.push((col.name.as_ref().unwrap().clone(), order));
     }
     Ok(resolved)
 }
@@ -269,7 +512,8 @@ fn create_idx_stmt_to_sql(
     tbl_name: &str,
     idx_name: &str,
     unique_if_not_exists: (bool, bool),
-    cols: &[((usize, &Column), SortOrder)],
+    cols: &[(String, SortOrder)],
+    where_clause: Option<&Expr>,
 ) -> String {
     let mut sql = String::with_capacity(128);
     sql.push_str("CREATE ");
@@ -288,12 +532,16 @@ fn create_idx_stmt_to_sql(
         if i > 0 {
             sql.push_str(", ");
         }
-        sql.push_str(col.1.name.as_ref().unwrap());
+        sql.push_str(col);
         if *order == SortOrder::Desc {
             sql.push_str(" DESC");
         }
     }
     sql.push(')');
+    if let Some(where_clause) = where_clause {
+        sql.push_str(" WHERE ");
+        sql.push_str(&where_clause.to_string());
+    }
     sql
 }
 
@@ -303,11 +551,6 @@ pub fn translate_drop_index(
     schema: &Schema,
     mut program: ProgramBuilder,
 ) -> crate::Result<ProgramBuilder> {
-    if !schema.indexes_enabled() {
-        crate::bail_parse_error!(
-            "DROP INDEX is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
-    }
     let idx_name = normalize_ident(idx_name);
     let opts = crate::vdbe::builder::ProgramBuilderOpts {
         num_cursors: 5,
@@ -367,6 +610,7 @@ pub fn translate_drop_index(
         cursor_id: sqlite_schema_cursor_id,
         root_page: RegisterOrLiteral::Literal(sqlite_table.root_page),
         name: sqlite_table.name.clone(),
+        db: 0,
     });
 
     let loop_start_label = program.allocate_label();
@@ -383,13 +627,14 @@ pub fn translate_drop_index(
 
     // if current column is not index_name then jump to Next
     // skip if sqlite_schema.name != index_name_reg
+    // Names are case insensitive, SQLite stores them as they were declared
     let next_label = program.allocate_label();
     program.emit_insn(Insn::Ne {
         lhs: index_name_reg,
         rhs: dest_reg,
         target_pc: next_label,
         flags: CmpInsFlags::default(),
-        collation: program.curr_collation(),
+        collation: Some(CollationSeq::NoCase),
     });
 
     // read type of table
@@ -434,6 +679,13 @@ pub fn translate_drop_index(
         p5: 0,
     });
 
+    emit_drop_stat1_rows(
+        &mut program,
+        schema,
+        0,
+        &Stat1Rows::Index(maybe_index.unwrap().name.clone()),
+    );
+
     // Destroy index btree
     program.emit_insn(Insn::Destroy {
         root: maybe_index.unwrap().root_page,
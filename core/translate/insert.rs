@@ -1,16 +1,23 @@
 use std::rc::Rc;
+use std::sync::Arc;
 
 use turso_sqlite3_parser::ast::{
-    DistinctNames, Expr, InsertBody, OneSelect, QualifiedName, ResolveType, ResultColumn, With,
+    self, DistinctNames, Expr, InsertBody, OneSelect, QualifiedName, ResolveType, ResultColumn,
+    With,
 };
 
-use crate::error::{SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY};
-use crate::schema::{IndexColumn, Table};
-use crate::translate::emitter::{emit_cdc_insns, emit_cdc_patch_record, OperationMode};
+use crate::error::{
+    SQLITE_CONSTRAINT_CHECK, SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY,
+};
+use crate::schema::{BTreeTable, Index, Table};
+use crate::translate::emitter::{
+    emit_cdc_insns, emit_cdc_patch_record, emit_index_entry_row_key, emit_returning_row,
+    emit_row_key, emit_seek_row_key, OperationMode,
+};
 use crate::util::normalize_ident;
 use crate::vdbe::builder::ProgramBuilderOpts;
 use crate::vdbe::insn::{IdxInsertFlags, InsertFlags, RegisterOrLiteral};
-use crate::vdbe::BranchOffset;
+use crate::vdbe::{BranchOffset, CursorID};
 use crate::{
     schema::{Column, Schema},
     vdbe::{
@@ -21,10 +28,29 @@ use crate::{
 use crate::{Result, SymbolTable, VirtualTable};
 
 use super::emitter::Resolver;
-use super::expr::{translate_expr, translate_expr_no_constant_opt, NoConstantOptReason};
+use super::expr::{
+    translate_expr, translate_expr_no_constant_opt, walk_expr, walk_expr_mut, NoConstantOptReason,
+    WalkControl,
+};
+use super::foreign_key::{emit_foreign_key_checks, has_foreign_keys};
+use super::generated::{emit_generated_columns, emit_table_record};
+use super::index::{
+    emit_index_key, emit_partial_index_check, unique_index_violation, IndexKeySource,
+};
 use super::optimizer::rewrite_expr;
-use super::plan::QueryDestination;
+use super::plan::{
+    ColumnUsedMask, IterationDirection, JoinedTable, Operation, QueryDestination, TableReferences,
+};
+use super::planner::{ensure_no_aggregates, parse_returning};
 use super::select::translate_select;
+use super::trigger::{
+    emit_triggers, translate_view_write, triggers_to_fire, TriggerRowRegisters, ViewWrite,
+    ROWID_NAMES,
+};
+use super::upsert::{
+    emit_upsert_update, resolve_upserts, rewrite_upserts, upsert_for_index, upsert_for_rowid,
+    Upsert, UpsertTable, UpsertTarget,
+};
 
 struct TempTableCtx {
     cursor_id: usize,
@@ -40,7 +66,7 @@ pub fn translate_insert(
     tbl_name: QualifiedName,
     columns: Option<DistinctNames>,
     mut body: InsertBody,
-    _returning: Option<Vec<ResultColumn>>,
+    returning: Option<Vec<ResultColumn>>,
     syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
@@ -53,26 +79,35 @@ pub fn translate_insert(
     if with.is_some() {
         crate::bail_parse_error!("WITH clause is not supported");
     }
-    if on_conflict.is_some() {
-        crate::bail_parse_error!("ON CONFLICT clause is not supported");
-    }
-
-    if schema.table_has_indexes(&tbl_name.name.to_string()) && !schema.indexes_enabled() {
-        // Let's disable altering a table with indices altogether instead of checking column by
-        // column to be extra safe.
-        crate::bail_parse_error!(
-            "INSERT to table with indexes is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
-    }
+    let database_id = schema.locate_database(&tbl_name)?;
+    let table_schema = schema.database_schema(database_id);
     let table_name = &tbl_name.name;
-    let table = match schema.get_table(table_name.0.as_str()) {
+    let table = match table_schema.get_table(table_name.0.as_str()) {
         Some(table) => table,
-        None => crate::bail_parse_error!("no such table: {}", table_name),
+        None => match table_schema.get_view(table_name.0.as_str()) {
+            Some(view) => {
+                if returning.is_some() {
+                    crate::bail_parse_error!("RETURNING is not supported for views");
+                }
+                return translate_view_write(
+                    schema,
+                    syms,
+                    database_id,
+                    &view,
+                    ViewWrite::Insert { columns, body },
+                    program,
+                );
+            }
+            None => crate::bail_parse_error!("no such table: {}", table_name),
+        },
     };
 
     let resolver = Resolver::new(schema, syms);
 
     if let Some(virtual_table) = &table.virtual_table() {
+        if returning.is_some() {
+            crate::bail_parse_error!("RETURNING is not supported for virtual tables");
+        }
         program = translate_virtual_table_insert(
             program,
             virtual_table.clone(),
@@ -88,11 +123,31 @@ pub fn translate_insert(
     let Some(btree_table) = table.btree() else {
         crate::bail_parse_error!("no such table: {}", table_name);
     };
-    if !btree_table.has_rowid {
-        crate::bail_parse_error!("INSERT into WITHOUT ROWID table is not supported");
-    }
+
+    let mut table_references = TableReferences::new(
+        vec![JoinedTable {
+            table: Table::BTree(btree_table.clone()),
+            identifier: table_name.0.clone(),
+            internal_id: program.table_reference_counter.next(),
+            op: Operation::Scan {
+                iter_dir: IterationDirection::Forwards,
+                index: None,
+            },
+            join_info: None,
+            col_used_mask: ColumnUsedMask::default(),
+            database_id,
+        }],
+        vec![],
+    );
+    let mut returning = parse_returning(returning, &mut table_references, schema)?;
 
     let root_page = btree_table.root_page;
+    let indexes = table_schema.get_indices(&table_name.0);
+    let upsert = match &mut body {
+        InsertBody::Select(_, upsert) => upsert.take(),
+        InsertBody::DefaultValues => None,
+    };
+    let mut upserts = resolve_upserts(upsert, &btree_table, indexes)?;
 
     let mut values: Option<Vec<Expr>> = None;
     let inserting_multiple_rows = match &mut body {
@@ -116,9 +171,34 @@ pub fn translate_insert(
 
     let halt_label = program.allocate_label();
     let loop_start_label = program.allocate_label();
+    // Where processing of the current row ends, also the target of RAISE(IGNORE)
+    let row_done_label = program.allocate_label();
+
+    let before_triggers = triggers_to_fire(
+        schema,
+        database_id,
+        &btree_table.name,
+        &ast::TriggerEvent::Insert,
+        ast::TriggerTime::Before,
+        &[],
+    );
+    let after_triggers = triggers_to_fire(
+        schema,
+        database_id,
+        &btree_table.name,
+        &ast::TriggerEvent::Insert,
+        ast::TriggerTime::After,
+        &[],
+    );
+    let has_triggers = !before_triggers.is_empty() || !after_triggers.is_empty();
 
     let cdc_table = program.capture_data_changes_mode().table();
     let cdc_table = if let Some(cdc_table) = cdc_table {
+        if !btree_table.has_rowid {
+            crate::bail_parse_error!(
+                "capturing data changes of WITHOUT ROWID tables is not supported"
+            );
+        }
         if table.get_name() != cdc_table {
             let Some(turso_cdc_table) = schema.get_table(cdc_table) else {
                 crate::bail_parse_error!("no such table: {}", cdc_table);
@@ -140,7 +220,6 @@ pub fn translate_insert(
     let mut yield_reg_opt = None;
     let mut temp_table_ctx = None;
     let (num_values, cursor_id) = match body {
-        // TODO: upsert
         InsertBody::Select(select, _) => {
             // Simple Common case of INSERT INTO <table> VALUES (...)
             if matches!(select.body.select.as_ref(),  OneSelect::Values(values) if values.len() <= 1)
@@ -187,7 +266,7 @@ pub fn translate_insert(
                  ** of the tables being read by the SELECT statement.  Also use a
                  ** temp table in the case of row triggers.
                  */
-                if program.is_table_open(&table) {
+                if program.is_table_open(&table) || has_triggers {
                     let temp_cursor_id =
                         program.alloc_cursor_id(CursorType::BTreeTable(btree_table.clone()));
                     temp_table_ctx = Some(TempTableCtx {
@@ -231,7 +310,7 @@ pub fn translate_insert(
                         cursor: temp_cursor_id,
                         key_reg: rowid_reg,
                         record_reg,
-                        flag: InsertFlags::new(),
+                        flag: InsertFlags::new().ephemeral(true),
                         table_name: "".to_string(),
                     });
 
@@ -246,12 +325,14 @@ pub fn translate_insert(
                         cursor_id,
                         root_page: RegisterOrLiteral::Literal(root_page),
                         name: table_name.0.clone(),
+                        db: database_id,
                     });
                 } else {
                     program.emit_insn(Insn::OpenWrite {
                         cursor_id,
                         root_page: RegisterOrLiteral::Literal(root_page),
                         name: table_name.0.clone(),
+                        db: database_id,
                     });
 
                     // Main loop
@@ -275,18 +356,16 @@ pub fn translate_insert(
     };
 
     // allocate cursor id's for each btree index cursor we'll need to populate the indexes
-    // (idx name, root_page, idx cursor id)
-    let idx_cursors = schema
-        .get_indices(&table_name.0)
+    // (index, idx cursor id)
+    let idx_cursors = indexes
         .iter()
         .map(|idx| {
             (
-                &idx.name,
-                idx.root_page,
+                idx.clone(),
                 program.alloc_cursor_id(CursorType::BTreeIndex(idx.clone())),
             )
         })
-        .collect::<Vec<(&String, usize, usize)>>();
+        .collect::<Vec<(Arc<Index>, usize)>>();
 
     let column_mappings = resolve_columns_for_insert(&table, &columns, num_values)?;
     // Check if rowid was provided (through INTEGER PRIMARY KEY as a rowid alias)
@@ -338,11 +417,13 @@ pub fn translate_insert(
             cursor_id,
             root_page: RegisterOrLiteral::Literal(root_page),
             name: table_name.0.clone(),
+            db: database_id,
         });
 
+        // DEFAULT VALUES has no values, every column takes its default
         populate_column_registers(
             &mut program,
-            &values.unwrap(),
+            &values.unwrap_or_default(),
             &column_mappings,
             column_registers_start,
             rowid_reg,
@@ -355,15 +436,17 @@ pub fn translate_insert(
             cursor_id: *cdc_cursor_id,
             root_page: cdc_btree.root_page.into(),
             name: cdc_btree.name.clone(),
+            db: 0,
         });
     }
 
     // Open all the index btrees for writing
-    for idx_cursor in idx_cursors.iter() {
+    for (index, idx_cursor_id) in idx_cursors.iter() {
         program.emit_insn(Insn::OpenWrite {
-            cursor_id: idx_cursor.2,
-            root_page: idx_cursor.1.into(),
-            name: idx_cursor.0.clone(),
+            cursor_id: *idx_cursor_id,
+            root_page: index.root_page.into(),
+            name: index.name.clone(),
+            db: database_id,
         });
     }
     // Common record insertion logic for both single and multiple rows
@@ -384,6 +467,31 @@ pub fn translate_insert(
             // for the row record, the rowid alias column is always set to NULL
             program.emit_insn(Insn::SoftNull { reg });
         }
+    }
+
+    if rowid_alias_reg.is_none() && !before_triggers.is_empty() {
+        // The rowid is not known yet when BEFORE triggers fire
+        program.emit_null(rowid_reg, None);
+    }
+    let new_row = TriggerRowRegisters {
+        rowid_reg,
+        columns_start_reg: column_registers_start,
+    };
+    if !before_triggers.is_empty() {
+        emit_generated_columns(&mut program, &resolver, &btree_table, new_row, false)?;
+    }
+    emit_triggers(
+        &mut program,
+        schema,
+        syms,
+        &before_triggers,
+        &btree_table,
+        Some(new_row),
+        None,
+        row_done_label,
+    )?;
+
+    if rowid_alias_reg.is_some() {
         // the user provided rowid value might itself be NULL. If it is, we create a new rowid on the next instruction.
         program.emit_insn(Insn::NotNull {
             reg: rowid_reg,
@@ -391,12 +499,17 @@ pub fn translate_insert(
         });
     }
 
-    // Create new rowid if a) not provided by user or b) provided by user but is NULL
-    program.emit_insn(Insn::NewRowid {
-        cursor: cursor_id,
-        rowid_reg,
-        prev_largest_reg: 0,
-    });
+    if btree_table.has_rowid {
+        // Create new rowid if a) not provided by user or b) provided by user but is NULL
+        program.emit_insn(Insn::NewRowid {
+            cursor: cursor_id,
+            rowid_reg,
+            prev_largest_reg: 0,
+        });
+    } else {
+        // The rows of a WITHOUT ROWID table are identified by their PRIMARY KEY
+        program.emit_null(rowid_reg, None);
+    }
 
     if let Some(must_be_int_label) = check_rowid_is_integer_label {
         program.resolve_label(must_be_int_label, program.offset());
@@ -404,34 +517,54 @@ pub fn translate_insert(
         program.emit_insn(Insn::MustBeInt { reg: rowid_reg });
     }
 
-    // Check uniqueness constraint for rowid if it was provided by user.
-    // When the DB allocates it there are no need for separate uniqueness checks.
-    if has_user_provided_rowid {
-        let make_record_label = program.allocate_label();
-        program.emit_insn(Insn::NotExists {
-            cursor: cursor_id,
-            rowid_reg,
-            target_pc: make_record_label,
-        });
-        let rowid_column_name = if let Some(index) = rowid_alias_index {
-            btree_table
-                .columns
-                .get(index)
-                .unwrap()
+    // Computed once the rowid is known, as a generated column may refer to it
+    emit_generated_columns(&mut program, &resolver, &btree_table, new_row, false)?;
+
+    for (i, col) in column_mappings
+        .iter()
+        .enumerate()
+        .filter(|(_, col)| col.column.notnull)
+    {
+        // if this is rowid alias - turso-db will emit NULL as a column value and always use rowid for the row as a column value
+        if col.column.is_rowid_alias {
+            continue;
+        }
+        let target_reg = i + column_registers_start;
+        let description = format!(
+            "{}.{}",
+            table_name,
+            col.column
                 .name
                 .as_ref()
-                .expect("column name is None")
-        } else {
-            "rowid"
-        };
-
-        program.emit_insn(Insn::Halt {
-            err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
-            description: format!("{}.{}", table_name.0, rowid_column_name),
-        });
-        program.preassign_label_to_next_insn(make_record_label);
+                .expect("Column name must be present")
+        );
+        let on_error = on_conflict
+            .or(col.column.notnull_conflict)
+            .unwrap_or(ResolveType::Abort);
+        emit_notnull_check(
+            &mut program,
+            &resolver,
+            target_reg,
+            on_error,
+            col.default_value,
+            description,
+            row_done_label,
+        )?;
     }
 
+    emit_check_constraints(
+        &mut program,
+        &resolver,
+        &btree_table,
+        TriggerRowRegisters {
+            rowid_reg,
+            columns_start_reg: column_registers_start,
+        },
+        None,
+        on_conflict.unwrap_or(ResolveType::Abort),
+        row_done_label,
+    )?;
+
     match table.btree() {
         Some(t) if t.is_strict => {
             program.emit_insn(Insn::TypeCheck {
@@ -444,135 +577,287 @@ pub fn translate_insert(
         _ => (),
     }
 
-    let index_col_mappings = resolve_indicies_for_insert(schema, table.as_ref(), &column_mappings)?;
-    for index_col_mapping in index_col_mappings {
-        // find which cursor we opened earlier for this index
-        let idx_cursor_id = idx_cursors
-            .iter()
-            .find(|(name, _, _)| *name == &index_col_mapping.idx_name)
-            .map(|(_, _, c_id)| *c_id)
-            .expect("no cursor found for index");
-
-        let num_cols = index_col_mapping.columns.len();
+    // Build the key of the new row in every index
+    let mut index_keys = Vec::with_capacity(idx_cursors.len());
+    for (index, idx_cursor_id) in idx_cursors.iter() {
+        let num_cols = index.columns.len() + index.has_rowid as usize;
         // allocate scratch registers for the index columns plus rowid
-        let idx_start_reg = program.alloc_registers(num_cols + 1);
-
-        // copy each index column from the table's column registers into these scratch regs
-        for (i, col) in index_col_mapping.columns.iter().enumerate() {
-            // copy from the table's column register over to the index's scratch register
-
-            program.emit_insn(Insn::Copy {
-                src_reg: column_registers_start + col.0,
-                dst_reg: idx_start_reg + i,
-                extra_amount: 0,
-            });
-        }
-        // last register is the rowid
-        program.emit_insn(Insn::Copy {
-            src_reg: rowid_reg,
-            dst_reg: idx_start_reg + num_cols,
-            extra_amount: 0,
-        });
-
-        let index = schema
-            .get_index(&table_name.0, &index_col_mapping.idx_name)
-            .expect("index should be present");
-
-        let record_reg = program.alloc_register();
-        program.emit_insn(Insn::MakeRecord {
+        let idx_start_reg = program.alloc_registers(num_cols);
+        emit_index_key(
+            &mut program,
+            &resolver,
+            &btree_table,
+            index,
+            IndexKeySource::Registers(new_row),
+            idx_start_reg,
+        )?;
+        index_keys.push(IndexKey {
+            index: index.clone(),
+            cursor_id: *idx_cursor_id,
             start_reg: idx_start_reg,
-            count: num_cols + 1,
-            dest_reg: record_reg,
-            index_name: Some(index_col_mapping.idx_name),
+            num_cols,
+            column_names: unique_index_violation(&btree_table.name, index),
         });
+    }
 
-        if index.unique {
-            let label_idx_insert = program.allocate_label();
-            program.emit_insn(Insn::NoConflict {
-                cursor_id: idx_cursor_id,
-                target_pc: label_idx_insert,
-                record_reg: idx_start_reg,
-                num_regs: num_cols,
-            });
-            let column_names = index_col_mapping.columns.iter().enumerate().fold(
-                String::with_capacity(50),
-                |mut accum, (idx, (index, _))| {
-                    if idx > 0 {
-                        accum.push_str(", ");
-                    }
-
-                    accum.push_str(&btree_table.name);
-                    accum.push('.');
+    // Check the uniqueness constraints, resolving each violation with the upsert clause that
+    // targets the constraint, or else the statement or constraint conflict resolution.
+    // The rowid only needs checking if it was provided by user. When the DB allocates it there
+    // are no need for separate uniqueness checks.
+    let mut param_idx = program.parameters.peek_next_index().get();
+    rewrite_upserts(&mut upserts, &mut param_idx)?;
+    for result_column in returning.iter_mut() {
+        rewrite_expr(&mut result_column.expr, &mut param_idx)?;
+    }
+    let mut unique_checks = Vec::new();
+    if has_user_provided_rowid {
+        let action = match upsert_for_rowid(&upserts) {
+            Some(upsert) => ConflictAction::Upsert(upsert),
+            None => ConflictAction::Resolve(
+                on_conflict
+                    .or(btree_table.primary_key_conflict)
+                    .unwrap_or(ResolveType::Abort),
+            ),
+        };
+        unique_checks.push((UniqueCheck::Rowid, action));
+    }
+    let clustered_index = btree_table.clustered_index();
+    if let Some(clustered_index) = &clustered_index {
+        let action = match upsert_for_index(&upserts, &clustered_index.name) {
+            Some(upsert) => ConflictAction::Upsert(upsert),
+            None => ConflictAction::Resolve(
+                on_conflict
+                    .or(btree_table.primary_key_conflict)
+                    .unwrap_or(ResolveType::Abort),
+            ),
+        };
+        unique_checks.push((UniqueCheck::PrimaryKey, action));
+    }
+    for (i, key) in index_keys.iter().enumerate() {
+        if !key.index.unique {
+            continue;
+        }
+        let action = match upsert_for_index(&upserts, &key.index.name) {
+            Some(upsert) => ConflictAction::Upsert(upsert),
+            None => ConflictAction::Resolve(
+                on_conflict
+                    .or(key.index.on_conflict)
+                    .unwrap_or(ResolveType::Abort),
+            ),
+        };
+        unique_checks.push((UniqueCheck::Index(i), action));
+    }
+    // Like SQLite, check the constraint targeted by an upsert first, and the ones resolved with
+    // REPLACE last, so that no row is deleted when another constraint fails the statement.
+    unique_checks.sort_by_key(|(_, action)| action.check_order());
 
-                    let name = btree_table
+    let upsert_table = UpsertTable {
+        table: &btree_table,
+        database_id,
+        cursor_id,
+        indexes: &idx_cursors,
+        cdc_cursor_id: cdc_table.as_ref().map(|(cdc_cursor_id, _)| *cdc_cursor_id),
+        returning: &returning,
+    };
+    // The PRIMARY KEY of the new row, which is its key in the b-tree of a WITHOUT ROWID table
+    let primary_key_reg = clustered_index
+        .as_ref()
+        .map(|_| emit_row_key(&mut program, &btree_table, new_row));
+    let mut rows_replaced = false;
+    for (check, action) in unique_checks {
+        let constraint_ok_label = program.allocate_label();
+        let description = match check {
+            UniqueCheck::Rowid => {
+                program.emit_insn(Insn::NotExists {
+                    cursor: cursor_id,
+                    rowid_reg,
+                    target_pc: constraint_ok_label,
+                });
+                let rowid_column_name = if let Some(index) = rowid_alias_index {
+                    btree_table
                         .columns
-                        .get(*index)
+                        .get(index)
                         .unwrap()
                         .name
                         .as_ref()
-                        .expect("column name is None");
-                    accum.push_str(name);
-
-                    accum
-                },
-            );
-
-            program.emit_insn(Insn::Halt {
-                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
-                description: column_names,
-            });
-
-            program.resolve_label(label_idx_insert, program.offset());
+                        .expect("column name is None")
+                } else {
+                    "rowid"
+                };
+                format!("{}.{}", table_name.0, rowid_column_name)
+            }
+            UniqueCheck::PrimaryKey => {
+                let clustered_index = clustered_index
+                    .as_ref()
+                    .expect("only WITHOUT ROWID tables check their PRIMARY KEY");
+                program.emit_insn(Insn::NoConflict {
+                    cursor_id,
+                    target_pc: constraint_ok_label,
+                    record_reg: primary_key_reg.expect("PRIMARY KEY must be loaded"),
+                    num_regs: clustered_index.columns.len(),
+                });
+                unique_index_violation(&btree_table.name, clustered_index)
+            }
+            UniqueCheck::Index(i) => {
+                let key = &index_keys[i];
+                // A row left out of a partial index cannot conflict in it
+                emit_partial_index_check(
+                    &mut program,
+                    &resolver,
+                    &btree_table,
+                    &key.index,
+                    IndexKeySource::Registers(new_row),
+                    constraint_ok_label,
+                )?;
+                program.emit_insn(Insn::NoConflict {
+                    cursor_id: key.cursor_id,
+                    target_pc: constraint_ok_label,
+                    record_reg: key.start_reg,
+                    num_regs: key.index.key_columns().len(),
+                });
+                key.column_names.clone()
+            }
+        };
+        let needs_conflict_key = matches!(
+            action,
+            ConflictAction::Resolve(ResolveType::Replace)
+                | ConflictAction::Upsert(Upsert {
+                    do_clause: ast::UpsertDo::Set { .. },
+                    ..
+                })
+        );
+        // The key of the conflicting row: its rowid, or its PRIMARY KEY for WITHOUT ROWID tables
+        let conflict_key_reg = match check {
+            UniqueCheck::Rowid => Some(rowid_reg),
+            UniqueCheck::PrimaryKey => primary_key_reg,
+            UniqueCheck::Index(i) if needs_conflict_key => Some(emit_index_entry_row_key(
+                &mut program,
+                &btree_table,
+                &index_keys[i].index,
+                index_keys[i].cursor_id,
+            )),
+            UniqueCheck::Index(_) => None,
+        };
+        match action {
+            ConflictAction::Resolve(ResolveType::Replace) => {
+                if let UniqueCheck::Index(_) = check {
+                    emit_seek_row_key(
+                        &mut program,
+                        &btree_table,
+                        cursor_id,
+                        conflict_key_reg.expect("conflict key must be loaded"),
+                        constraint_ok_label,
+                    );
+                }
+                emit_replace_delete(
+                    &mut program,
+                    &resolver,
+                    &btree_table,
+                    cursor_id,
+                    &idx_cursors,
+                )?;
+                rows_replaced = true;
+            }
+            ConflictAction::Resolve(ResolveType::Ignore)
+            | ConflictAction::Upsert(Upsert {
+                do_clause: ast::UpsertDo::Nothing,
+                ..
+            }) => {
+                program.emit_insn(Insn::Goto {
+                    target_pc: row_done_label,
+                });
+            }
+            ConflictAction::Resolve(on_error) => {
+                program.emit_insn(Insn::Halt {
+                    err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                    description,
+                    on_error,
+                });
+            }
+            ConflictAction::Upsert(Upsert {
+                do_clause: ast::UpsertDo::Set { sets, where_clause },
+                ..
+            }) => {
+                emit_upsert_update(
+                    &mut program,
+                    &resolver,
+                    &upsert_table,
+                    sets,
+                    where_clause.as_ref(),
+                    conflict_key_reg.expect("conflict key must be loaded"),
+                    new_row,
+                    row_done_label,
+                )?;
+            }
         }
+        program.preassign_label_to_next_insn(constraint_ok_label);
+    }
 
+    for key in index_keys {
+        let skip_label = program.allocate_label();
+        emit_partial_index_check(
+            &mut program,
+            &resolver,
+            &btree_table,
+            &key.index,
+            IndexKeySource::Registers(new_row),
+            skip_label,
+        )?;
+        let record_reg = program.alloc_register();
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: key.start_reg,
+            count: key.num_cols,
+            dest_reg: record_reg,
+            index_name: Some(key.index.name.clone()),
+        });
         // now do the actual index insertion using the unpacked registers
         program.emit_insn(Insn::IdxInsert {
-            cursor_id: idx_cursor_id,
+            cursor_id: key.cursor_id,
             record_reg,
-            unpacked_start: Some(idx_start_reg), // TODO: enable optimization
-            unpacked_count: Some((num_cols + 1) as u16),
+            unpacked_start: Some(key.start_reg), // TODO: enable optimization
+            unpacked_count: Some(key.num_cols as u16),
             // TODO: figure out how to determine whether or not we need to seek prior to insert.
             flags: IdxInsertFlags::new(),
         });
+        program.preassign_label_to_next_insn(skip_label);
     }
 
-    for (i, col) in column_mappings
-        .iter()
-        .enumerate()
-        .filter(|(_, col)| col.column.notnull)
-    {
-        // if this is rowid alias - turso-db will emit NULL as a column value and always use rowid for the row as a column value
-        if col.column.is_rowid_alias {
-            continue;
-        }
-        let target_reg = i + column_registers_start;
-        program.emit_insn(Insn::HaltIfNull {
-            target_reg,
-            err_code: SQLITE_CONSTRAINT_NOTNULL,
-            description: format!(
-                "{}.{}",
-                table_name,
-                col.column
-                    .name
-                    .as_ref()
-                    .expect("Column name must be present")
-            ),
+    if rows_replaced && btree_table.has_rowid {
+        // Deleting the conflicting rows moved the table cursor
+        let positioned_label = program.allocate_label();
+        program.emit_insn(Insn::NotExists {
+            cursor: cursor_id,
+            rowid_reg,
+            target_pc: positioned_label,
         });
+        program.preassign_label_to_next_insn(positioned_label);
     }
+
     // Create and insert the record
-    program.emit_insn(Insn::MakeRecord {
-        start_reg: column_registers_start,
-        count: num_cols,
-        dest_reg: record_register,
-        index_name: None,
-    });
-    program.emit_insn(Insn::Insert {
-        cursor: cursor_id,
-        key_reg: rowid_reg,
-        record_reg: record_register,
-        flag: InsertFlags::new(),
-        table_name: table_name.to_string(),
-    });
+    emit_table_record(
+        &mut program,
+        &btree_table,
+        column_registers_start,
+        record_register,
+    );
+    if btree_table.has_rowid {
+        program.emit_insn(Insn::Insert {
+            cursor: cursor_id,
+            key_reg: rowid_reg,
+            record_reg: record_register,
+            flag: InsertFlags::new(),
+            table_name: table_name.to_string(),
+        });
+    } else {
+        // The row is an entry of the b-tree keyed by the PRIMARY KEY
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id,
+            record_reg: record_register,
+            unpacked_start: None,
+            unpacked_count: None,
+            flags: IdxInsertFlags::new().nchange(true),
+        });
+    }
 
     // Emit update in the CDC table if necessary (after the INSERT updated the table)
     if let Some((cdc_cursor_id, _)) = &cdc_table {
@@ -600,6 +885,32 @@ pub fn translate_insert(
         )?;
     }
 
+    emit_foreign_key_checks(
+        &mut program,
+        &resolver,
+        database_id,
+        &btree_table,
+        Some(new_row),
+        None,
+        None,
+        row_done_label,
+    )?;
+
+    emit_triggers(
+        &mut program,
+        schema,
+        syms,
+        &after_triggers,
+        &btree_table,
+        Some(new_row),
+        None,
+        row_done_label,
+    )?;
+
+    emit_returning_row(&mut program, &resolver, &returning, new_row)?;
+
+    program.preassign_label_to_next_insn(row_done_label);
+
     if inserting_multiple_rows {
         if let Some(temp_table_ctx) = temp_table_ctx {
             program.emit_insn(Insn::Next {
@@ -619,12 +930,285 @@ pub fn translate_insert(
         }
     }
 
+    // A single row is checked against all the constraints before it is written, unless rows
+    // are replaced or something else runs after the insert.
+    if !inserting_multiple_rows
+        && !has_triggers
+        && upserts.is_empty()
+        && !rows_replaced
+        && returning.is_empty()
+        && !has_foreign_keys(&program, table_schema, &btree_table)
+    {
+        program.skip_statement_journal();
+    }
+
     program.resolve_label(halt_label, program.offset());
     program.epilogue(super::emitter::TransactionMode::Write);
+    program.result_columns = returning;
+    program.table_references.extend(table_references);
 
     Ok(program)
 }
 
+/// The key of the new row in an index, built before checking uniqueness constraints.
+struct IndexKey {
+    index: Arc<Index>,
+    cursor_id: CursorID,
+    /// First of the registers holding the index columns followed by the rowid, if it has one.
+    start_reg: usize,
+    num_cols: usize,
+    /// The constrained columns, as reported in constraint violation errors.
+    column_names: String,
+}
+
+/// A uniqueness constraint checked before inserting a row.
+#[derive(Debug, Clone, Copy)]
+enum UniqueCheck {
+    Rowid,
+    /// The PRIMARY KEY of a WITHOUT ROWID table.
+    PrimaryKey,
+    /// The unique index at this position of the index keys.
+    Index(usize),
+}
+
+/// How a violation of a uniqueness constraint is resolved.
+enum ConflictAction<'a> {
+    Resolve(ResolveType),
+    Upsert(&'a Upsert),
+}
+
+impl ConflictAction<'_> {
+    /// Constraints are checked in ascending order of this.
+    fn check_order(&self) -> u8 {
+        match self {
+            ConflictAction::Upsert(upsert) if upsert.target != UpsertTarget::Any => 0,
+            ConflictAction::Resolve(ResolveType::Replace) => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Emits the NOT NULL check of the column value in `reg`, resolving a violation with `on_error`.
+/// REPLACE stores the default value of the column instead, and behaves like ABORT if there is
+/// none. IGNORE jumps to `ignore_label`.
+pub(crate) fn emit_notnull_check(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    reg: usize,
+    on_error: ResolveType,
+    default: Option<&Expr>,
+    description: String,
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    match (on_error, default) {
+        (ResolveType::Replace, Some(default)) => {
+            let not_null_label = program.allocate_label();
+            program.emit_insn(Insn::NotNull {
+                reg,
+                target_pc: not_null_label,
+            });
+            translate_expr_no_constant_opt(
+                program,
+                None,
+                default,
+                reg,
+                resolver,
+                NoConstantOptReason::RegisterReuse,
+            )?;
+            program.preassign_label_to_next_insn(not_null_label);
+        }
+        (ResolveType::Ignore, _) => {
+            program.emit_insn(Insn::IsNull {
+                reg,
+                target_pc: ignore_label,
+            });
+        }
+        (ResolveType::Abort | ResolveType::Replace, _) => {
+            program.emit_insn(Insn::HaltIfNull {
+                target_reg: reg,
+                err_code: SQLITE_CONSTRAINT_NOTNULL,
+                description,
+            });
+        }
+        (on_error, _) => {
+            let not_null_label = program.allocate_label();
+            program.emit_insn(Insn::NotNull {
+                reg,
+                target_pc: not_null_label,
+            });
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_NOTNULL,
+                description,
+                on_error,
+            });
+            program.preassign_label_to_next_insn(not_null_label);
+        }
+    }
+    Ok(())
+}
+
+/// Emits the checks of the CHECK constraints of `table` against `row`, resolving a violation
+/// with `on_error`. IGNORE jumps to `ignore_label`, REPLACE behaves like ABORT.
+/// For an UPDATE, `updated_columns` are the columns it sets, and only the constraints that
+/// reference one of them are checked.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn emit_check_constraints(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    row: TriggerRowRegisters,
+    updated_columns: Option<&[usize]>,
+    on_error: ResolveType,
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    if program.ignore_check_constraints() {
+        return Ok(());
+    }
+    let rowid_alias_column = table.get_rowid_alias_column().map(|(idx, _)| idx);
+    for check in table.check_constraints.iter() {
+        let mut expr = check.expr.clone();
+        let mut referenced_columns = Vec::new();
+        walk_expr_mut(&mut expr, &mut |expr: &mut Expr| -> Result<()> {
+            let column_name = match expr {
+                Expr::Id(name) if is_true_or_false(name) => {
+                    let value = name.0.eq_ignore_ascii_case("true") as i64;
+                    *expr = Expr::Literal(ast::Literal::Numeric(value.to_string()));
+                    return Ok(());
+                }
+                Expr::Id(name) => normalize_ident(&name.0),
+                Expr::Qualified(qualifier, name) | Expr::DoublyQualified(_, qualifier, name)
+                    if normalize_ident(&qualifier.0) == table.name =>
+                {
+                    normalize_ident(&name.0)
+                }
+                _ => return Ok(()),
+            };
+            let (column, reg) = match table.get_column(&column_name) {
+                Some((idx, column)) if column.is_rowid_alias => (Some(idx), row.rowid_reg),
+                Some((idx, _)) => (Some(idx), row.columns_start_reg + idx),
+                None if ROWID_NAMES.contains(&column_name.as_str()) => {
+                    (rowid_alias_column, row.rowid_reg)
+                }
+                None => crate::bail_parse_error!("no such column: {}", column_name),
+            };
+            referenced_columns.extend(column);
+            *expr = Expr::Register(reg);
+            Ok(())
+        })?;
+        if let Some(updated_columns) = updated_columns {
+            if !referenced_columns
+                .iter()
+                .any(|idx| updated_columns.contains(idx))
+            {
+                continue;
+            }
+        }
+
+        let reg = program.alloc_register();
+        translate_expr(program, None, &expr, reg, resolver)?;
+        let check_ok_label = program.allocate_label();
+        // The constraint is satisfied unless the expression is false, NULL satisfies it.
+        program.emit_insn(Insn::If {
+            reg,
+            target_pc: check_ok_label,
+            jump_if_null: true,
+        });
+        match on_error {
+            ResolveType::Ignore => program.emit_insn(Insn::Goto {
+                target_pc: ignore_label,
+            }),
+            on_error => program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_CHECK,
+                description: check.description(),
+                on_error: match on_error {
+                    ResolveType::Replace => ResolveType::Abort,
+                    on_error => on_error,
+                },
+            }),
+        }
+        program.preassign_label_to_next_insn(check_ok_label);
+    }
+    Ok(())
+}
+
+/// Validates the CHECK constraints of a table being created: like in SQLite, they may only refer
+/// to the columns of the table, and may not contain subqueries, parameters or aggregates.
+pub(crate) fn check_check_constraints(table: &BTreeTable) -> Result<()> {
+    let is_column = |name: &str| {
+        table.get_column(name).is_some()
+            || (table.has_rowid && ROWID_NAMES.contains(&normalize_ident(name).as_str()))
+    };
+    for check in table.check_constraints.iter() {
+        walk_expr(&check.expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            match expr {
+                Expr::Id(name) if !is_true_or_false(name) && !is_column(&name.0) => {
+                    crate::bail_parse_error!("no such column: {}", name.0)
+                }
+                Expr::Qualified(qualifier, name)
+                    if normalize_ident(&qualifier.0) != table.name || !is_column(&name.0) =>
+                {
+                    crate::bail_parse_error!("no such column: {}.{}", qualifier.0, name.0)
+                }
+                Expr::DoublyQualified(database, qualifier, name)
+                    if normalize_ident(&qualifier.0) != table.name || !is_column(&name.0) =>
+                {
+                    crate::bail_parse_error!(
+                        "no such column: {}.{}.{}",
+                        database.0,
+                        qualifier.0,
+                        name.0
+                    )
+                }
+                Expr::Subquery(_)
+                | Expr::Exists(_)
+                | Expr::InSelect { .. }
+                | Expr::InTable { .. } => {
+                    crate::bail_parse_error!("subqueries prohibited in CHECK constraints")
+                }
+                Expr::Variable(_) => {
+                    crate::bail_parse_error!("parameters prohibited in CHECK constraints")
+                }
+                _ => Ok(WalkControl::Continue),
+            }
+        })?;
+        ensure_no_aggregates(&check.expr)?;
+    }
+    Ok(())
+}
+
+/// Whether `name` is `TRUE` or `FALSE`, constants rather than columns like in a SELECT.
+fn is_true_or_false(name: &ast::Id) -> bool {
+    name.0.eq_ignore_ascii_case("true") || name.0.eq_ignore_ascii_case("false")
+}
+
+/// Deletes the row the table cursor `cursor_id` points at, along with its entries in `indexes`.
+/// Used to make room for the new row when a uniqueness constraint is resolved with REPLACE.
+pub(crate) fn emit_replace_delete(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    cursor_id: CursorID,
+    indexes: &[(Arc<Index>, CursorID)],
+) -> Result<()> {
+    let source = IndexKeySource::Cursor(cursor_id);
+    for (index, index_cursor_id) in indexes {
+        let skip_label = program.allocate_label();
+        emit_partial_index_check(program, resolver, table, index, source, skip_label)?;
+        let num_regs = index.columns.len() + index.has_rowid as usize;
+        let start_reg = program.alloc_registers(num_regs);
+        emit_index_key(program, resolver, table, index, source, start_reg)?;
+        program.emit_insn(Insn::IdxDelete {
+            start_reg,
+            num_regs,
+            cursor_id: *index_cursor_id,
+            raise_error_if_no_matching_entry: true,
+        });
+        program.preassign_label_to_next_insn(skip_label);
+    }
+    program.emit_insn(Insn::Delete { cursor_id });
+    Ok(())
+}
+
 #[derive(Debug)]
 /// Represents how a column should be populated during an INSERT.
 /// Contains both the column definition and optionally the index into the VALUES tuple.
@@ -651,6 +1235,7 @@ struct ColumnMapping<'a> {
 /// 1. No column list specified (INSERT INTO t VALUES ...):
 ///    - Values are assigned to columns in table definition order
 ///    - If fewer values than columns, remaining columns map to None
+///    - With no values at all (INSERT INTO t DEFAULT VALUES), every column maps to None
 /// 2. Column list specified (INSERT INTO t (col1, col3) VALUES ...):
 ///    - Named columns map to their corresponding value index
 ///    - Unspecified columns map to None
@@ -665,12 +1250,14 @@ fn resolve_columns_for_insert<'a>(
         let mut value_idx = 0;
         let mut column_mappings = Vec::with_capacity(table_columns.len());
         for col in table_columns {
+            // Hidden columns only get a value when named explicitly, generated columns never do
+            let takes_value = num_values > 0 && !col.hidden && !col.is_generated();
             let mapping = ColumnMapping {
                 column: col,
-                value_index: if col.hidden { None } else { Some(value_idx) },
+                value_index: takes_value.then_some(value_idx),
                 default_value: col.default.as_ref(),
             };
-            if !col.hidden {
+            if takes_value {
                 value_idx += 1;
             }
             column_mappings.push(mapping);
@@ -715,75 +1302,19 @@ fn resolve_columns_for_insert<'a>(
             );
         };
 
+        let column = &table_columns[table_index];
+        if column.is_generated() {
+            crate::bail_parse_error!(
+                "cannot INSERT into generated column \"{}\"",
+                column.name.as_deref().unwrap_or_default()
+            );
+        }
         mappings[table_index].value_index = Some(value_index);
     }
 
     Ok(mappings)
 }
 
-/// Represents how a column in an index should be populated during an INSERT.
-/// Similar to ColumnMapping above but includes the index name, as well as multiple
-/// possible value indices for each.
-#[derive(Debug, Default)]
-struct IndexColMapping {
-    idx_name: String,
-    columns: Vec<(usize, IndexColumn)>,
-    value_indicies: Vec<Option<usize>>,
-}
-
-impl IndexColMapping {
-    fn new(name: String) -> Self {
-        IndexColMapping {
-            idx_name: name,
-            ..Default::default()
-        }
-    }
-}
-
-/// Example:
-/// Table 'test': (a, b, c);
-/// Index 'idx': test(a, b);
-///________________________________
-/// Insert (a, c): (2, 3)
-/// Record: (2, NULL, 3)
-/// IndexColMapping: (a, b) = (2, NULL)
-fn resolve_indicies_for_insert(
-    schema: &Schema,
-    table: &Table,
-    columns: &[ColumnMapping<'_>],
-) -> Result<Vec<IndexColMapping>> {
-    let mut index_col_mappings = Vec::new();
-    // Iterate over all indices for this table
-    for index in schema.get_indices(table.get_name()) {
-        let mut idx_map = IndexColMapping::new(index.name.clone());
-        // For each column in the index (in the order defined by the index),
-        // try to find the corresponding column in the insert’s column mapping.
-        for idx_col in &index.columns {
-            let target_name = normalize_ident(idx_col.name.as_str());
-            if let Some((i, col_mapping)) = columns.iter().enumerate().find(|(_, mapping)| {
-                mapping
-                    .column
-                    .name
-                    .as_ref()
-                    .is_some_and(|name| name.eq_ignore_ascii_case(&target_name))
-            }) {
-                idx_map.columns.push((i, idx_col.clone()));
-                idx_map.value_indicies.push(col_mapping.value_index);
-            } else {
-                return Err(crate::LimboError::ParseError(format!(
-                    "Column {} not found in index {}",
-                    target_name, index.name
-                )));
-            }
-        }
-        // Add the mapping if at least one column was found.
-        if !idx_map.columns.is_empty() {
-            index_col_mappings.push(idx_map);
-        }
-    }
-    Ok(index_col_mappings)
-}
-
 fn populate_columns_multiple_rows(
     program: &mut ProgramBuilder,
     column_mappings: &[ColumnMapping],
@@ -802,11 +1333,14 @@ fn populate_columns_multiple_rows(
             // Decrement as we have now seen a value index instead
             other_values_seen -= 1;
             if let Some(temp_table_ctx) = temp_table_ctx {
-                program.emit_column(
-                    temp_table_ctx.cursor_id,
-                    value_index_seen,
-                    column_registers_start + i,
-                );
+                // The temp table holds the values in the order they were supplied, not the
+                // layout of the table record, so read them without remapping.
+                program.emit_insn(Insn::Column {
+                    cursor_id: temp_table_ctx.cursor_id,
+                    column: value_index_seen,
+                    dest: column_registers_start + i,
+                    default: None,
+                });
             } else {
                 program.emit_insn(Insn::Copy {
                     src_reg: yield_reg + value_index_seen,
@@ -1,6 +1,14 @@
 use crate::{
-    schema::Schema,
-    vdbe::{builder::ProgramBuilder, insn::Insn},
+    schema::{Index, Schema},
+    translate::{
+        emitter::Resolver,
+        index::{emit_index_key, emit_partial_index_check, IndexKeySource},
+    },
+    vdbe::{
+        builder::{CursorType, ProgramBuilder},
+        insn::{CmpInsFlags, Insn},
+    },
+    SymbolTable,
 };
 
 /// Maximum number of errors to report with integrity check. If we exceed this number we will short
@@ -9,24 +17,240 @@ const MAX_INTEGRITY_CHECK_ERRORS: usize = 10;
 
 pub fn translate_integrity_check(
     schema: &Schema,
+    syms: &SymbolTable,
     program: &mut ProgramBuilder,
 ) -> crate::Result<()> {
+    let mut tables = schema
+        .tables
+        .values()
+        .filter_map(|table| table.btree())
+        .collect::<Vec<_>>();
+    tables.sort_by_key(|table| table.root_page);
+
     let mut root_pages = Vec::with_capacity(schema.tables.len() + schema.indexes.len());
     // Collect root pages to run integrity check on
-    for table in schema.tables.values() {
-        if let crate::schema::Table::BTree(table) = table.as_ref() {
-            root_pages.push(table.root_page);
-        };
+    for table in &tables {
+        root_pages.push(table.root_page);
+        for index in schema.get_indices(&table.name) {
+            root_pages.push(index.root_page);
+        }
     }
+
+    // Number of errors reported so far, "ok" is only reported if there were none
+    let errors_reg = program.alloc_register();
+    let one_reg = program.alloc_register();
+    program.emit_int(0, errors_reg);
+    program.emit_int(1, one_reg);
+    let ok_reg = program.emit_string8_new_reg("ok".to_string());
+
     let message_register = program.alloc_register();
     program.emit_insn(Insn::IntegrityCk {
         max_errors: MAX_INTEGRITY_CHECK_ERRORS,
         roots: root_pages,
         message_register,
     });
-    program.emit_insn(Insn::ResultRow {
-        start_reg: message_register,
-        count: 1,
+    let structure_ok_label = program.allocate_label();
+    program.emit_insn(Insn::Eq {
+        lhs: message_register,
+        rhs: ok_reg,
+        target_pc: structure_ok_label,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    emit_error_row(program, message_register, errors_reg, one_reg);
+    program.preassign_label_to_next_insn(structure_ok_label);
+
+    // Cross-check every table against its indexes: each row must have its key in every index it
+    // belongs to, with the same values as the row, and no index may have more entries than the rows
+    // that belong to it.
+    let resolver = Resolver::new(schema, syms);
+    for table in &tables {
+        let indexes = schema.get_indices(&table.name);
+        if indexes.is_empty() {
+            continue;
+        }
+        let table_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+        program.emit_insn(Insn::OpenRead {
+            cursor_id: table_cursor_id,
+            root_page: table.root_page,
+            db: 0,
+        });
+        let index_cursors = indexes
+            .iter()
+            .map(|index| {
+                let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+                program.emit_insn(Insn::OpenRead {
+                    cursor_id,
+                    root_page: index.root_page,
+                    db: 0,
+                });
+                let count_reg = program.alloc_register();
+                program.emit_int(0, count_reg);
+                (cursor_id, count_reg)
+            })
+            .collect::<Vec<_>>();
+
+        let loop_start_label = program.allocate_label();
+        let loop_end_label = program.allocate_label();
+        program.emit_insn(Insn::Rewind {
+            cursor_id: table_cursor_id,
+            pc_if_empty: loop_end_label,
+        });
+        program.preassign_label_to_next_insn(loop_start_label);
+        let source = IndexKeySource::Cursor(table_cursor_id);
+        for (index, (index_cursor_id, count_reg)) in indexes.iter().zip(&index_cursors) {
+            let next_index_label = program.allocate_label();
+            emit_partial_index_check(program, &resolver, table, index, source, next_index_label)?;
+            program.emit_insn(Insn::Add {
+                lhs: *count_reg,
+                rhs: one_reg,
+                dest: *count_reg,
+            });
+            let num_regs = index.columns.len() + index.has_rowid as usize;
+            let key_reg = program.alloc_registers(num_regs);
+            emit_index_key(program, &resolver, table, index, source, key_reg)?;
+            let found_label = program.allocate_label();
+            program.emit_insn(Insn::Found {
+                cursor_id: *index_cursor_id,
+                target_pc: found_label,
+                record_reg: key_reg,
+                num_regs,
+            });
+            // "row N missing from index X", where N is the rowid, or the ordinal of the row among
+            // those belonging to the index for WITHOUT ROWID tables
+            let row_reg = if index.has_rowid {
+                key_reg + index.columns.len()
+            } else {
+                *count_reg
+            };
+            emit_row_error(
+                program,
+                row_reg,
+                " missing from index",
+                index,
+                errors_reg,
+                one_reg,
+            );
+            program.emit_insn(Insn::Goto {
+                target_pc: next_index_label,
+            });
+
+            // The entry found compares equal to the key, which is not enough when the columns use
+            // a collation other than BINARY: the entry must hold the exact values of the row.
+            program.preassign_label_to_next_insn(found_label);
+            let differ_label = program.allocate_label();
+            let entry_reg = program.alloc_register();
+            for i in 0..num_regs {
+                program.emit_insn(Insn::Column {
+                    cursor_id: *index_cursor_id,
+                    column: i,
+                    dest: entry_reg,
+                    default: None,
+                });
+                program.emit_insn(Insn::Ne {
+                    lhs: entry_reg,
+                    rhs: key_reg + i,
+                    target_pc: differ_label,
+                    flags: CmpInsFlags::default().null_eq(),
+                    collation: None,
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: next_index_label,
+            });
+            // "row N values differ from index X"
+            program.preassign_label_to_next_insn(differ_label);
+            emit_row_error(
+                program,
+                row_reg,
+                " values differ from index",
+                index,
+                errors_reg,
+                one_reg,
+            );
+            program.preassign_label_to_next_insn(next_index_label);
+        }
+        program.emit_insn(Insn::Next {
+            cursor_id: table_cursor_id,
+            pc_if_next: loop_start_label,
+        });
+        program.preassign_label_to_next_insn(loop_end_label);
+
+        // "wrong # of entries in index X"
+        for (index, (index_cursor_id, count_reg)) in indexes.iter().zip(&index_cursors) {
+            let entries_reg = program.alloc_register();
+            program.emit_insn(Insn::Count {
+                cursor_id: *index_cursor_id,
+                target_reg: entries_reg,
+                exact: true,
+            });
+            let count_ok_label = program.allocate_label();
+            program.emit_insn(Insn::Eq {
+                lhs: entries_reg,
+                rhs: *count_reg,
+                target_pc: count_ok_label,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            let message_reg =
+                program.emit_string8_new_reg(format!("wrong # of entries in index {}", index.name));
+            emit_error_row(program, message_reg, errors_reg, one_reg);
+            program.preassign_label_to_next_insn(count_ok_label);
+        }
+        program.close_cursors(&[table_cursor_id]);
+        let index_cursor_ids = index_cursors
+            .iter()
+            .map(|(cursor_id, _)| *cursor_id)
+            .collect::<Vec<_>>();
+        program.close_cursors(&index_cursor_ids);
+    }
+
+    let done_label = program.allocate_label();
+    program.emit_insn(Insn::IfPos {
+        reg: errors_reg,
+        target_pc: done_label,
+        decrement_by: 0,
     });
+    program.emit_result_row(ok_reg, 1);
+    program.preassign_label_to_next_insn(done_label);
     Ok(())
 }
+
+/// Reports "row N<suffix> X" for the row in `row_reg` and the index X.
+fn emit_row_error(
+    program: &mut ProgramBuilder,
+    row_reg: usize,
+    suffix: &str,
+    index: &Index,
+    errors_reg: usize,
+    one_reg: usize,
+) {
+    let message_reg = program.emit_string8_new_reg("row ".to_string());
+    program.emit_insn(Insn::Concat {
+        lhs: message_reg,
+        rhs: row_reg,
+        dest: message_reg,
+    });
+    let suffix_reg = program.emit_string8_new_reg(format!("{suffix} {}", index.name));
+    program.emit_insn(Insn::Concat {
+        lhs: message_reg,
+        rhs: suffix_reg,
+        dest: message_reg,
+    });
+    emit_error_row(program, message_reg, errors_reg, one_reg);
+}
+
+/// Reports the message in `message_reg` as a row of the result and counts it as an error.
+fn emit_error_row(
+    program: &mut ProgramBuilder,
+    message_reg: usize,
+    errors_reg: usize,
+    one_reg: usize,
+) {
+    program.emit_result_row(message_reg, 1);
+    program.emit_insn(Insn::Add {
+        lhs: errors_reg,
+        rhs: one_reg,
+        dest: errors_reg,
+    });
+}
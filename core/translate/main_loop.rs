@@ -1,6 +1,6 @@
 use turso_sqlite3_parser::ast::{self, SortOrder};
 
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc};
 
 use crate::{
     schema::{Affinity, Index, IndexColumn, Table},
@@ -18,19 +18,22 @@ use crate::{
 };
 
 use super::{
-    aggregation::translate_aggregation_step,
-    emitter::{OperationMode, TranslateCtx},
+    aggregation::{nonagg_column_reads_row, translate_aggregation_step},
+    emitter::{emit_index_entry_row_key, emit_seek_row_key, OperationMode, Resolver, TranslateCtx},
     expr::{
         translate_condition_expr, translate_expr, translate_expr_no_constant_opt,
         ConditionMetadata, NoConstantOptReason,
     },
+    generated::emit_table_column,
     group_by::{group_by_agg_phase, GroupByMetadata, GroupByRowSource},
-    optimizer::Optimizable,
+    optimizer::{constraints::as_in_list_components, Optimizable},
     order_by::{order_by_sorter_insert, sorter_insert},
     plan::{
-        convert_where_to_vtab_constraint, Aggregate, GroupBy, IterationDirection, JoinOrderMember,
-        Operation, QueryDestination, Search, SeekDef, SelectPlan, TableReferences, WhereTerm,
+        convert_where_to_vtab_constraint, Aggregate, GroupBy, HashJoinKey, IterationDirection,
+        JoinOrderMember, JoinedTable, Operation, QueryDestination, Search, SeekDef, SelectPlan,
+        TableReferences, WhereTerm,
     },
+    window::window_sorter_insert,
 };
 
 // Metadata for handling LEFT JOIN operations
@@ -44,6 +47,47 @@ pub struct LeftJoinMetadata {
     pub label_match_flag_check_value: BranchOffset,
 }
 
+// Metadata for handling RIGHT JOIN operations, including FULL OUTER JOIN
+#[derive(Debug)]
+pub struct RightJoinMetadata {
+    // ephemeral index of the keys of the rows of the right table that match a row of the tables on its left
+    pub matched_cursor_id: CursorID,
+    // name of the ephemeral index, needed to make its records
+    pub matched_index_name: String,
+    // integer register that numbers the rows of a right table that is a subquery, the number being the key of the row
+    pub reg_row_number: Option<usize>,
+    // integer register that holds the return address of the subroutine joining a row of the right table with the tables after it
+    pub reg_subroutine_return: usize,
+    // label for the start of that subroutine
+    pub label_subroutine: BranchOffset,
+    // label for the instruction that returns from that subroutine
+    pub label_subroutine_return: BranchOffset,
+    // label for the loop over the rows of the right table that match no row of the tables on its left
+    pub label_unmatched_rows: BranchOffset,
+}
+
+// Metadata for the loops the search of a table runs within (for IN lists and multi-index ORs only)
+#[derive(Debug, Default)]
+pub struct SearchLoopMetadata {
+    // loops over the values of the `x IN (...)` lists of the seek key, outermost first
+    pub in_loops: Vec<InLoopMetadata>,
+    // ephemeral index of the rowids collected by a multi-index OR, which the loop of the table iterates
+    pub rowset_cursor_id: Option<CursorID>,
+}
+
+// Metadata for a loop over the distinct values of an `x IN (...)` list that a seek is repeated for
+#[derive(Debug)]
+pub struct InLoopMetadata {
+    // ephemeral index of the values of the list
+    pub cursor_id: CursorID,
+    // label for the instruction that reads the current value of the list into the seek key
+    pub label_start: BranchOffset,
+    // label for the instruction that advances to the next value of the list
+    pub label_next: BranchOffset,
+    // whether the values are visited from the largest to the smallest
+    pub descending: bool,
+}
+
 /// Jump labels for each loop in the query's main execution loop
 #[derive(Debug, Clone, Copy)]
 pub struct LoopLabels {
@@ -82,10 +126,14 @@ pub fn init_distinct(program: &mut ProgramBuilder, plan: &SelectPlan) -> Distinc
                 pos_in_table: i,
                 collation: None, // FIXME: this should be determined based on the result column expression!
                 default: None, // FIXME: this should be determined based on the result column expression!
+                expr: None,
             })
             .collect(),
         unique: false,
         has_rowid: false,
+        on_conflict: None,
+        where_clause: None,
+        pk_suffix_len: 0,
     });
     let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
     let ctx = DistinctCtx {
@@ -116,6 +164,10 @@ pub fn init_loop(
         t_ctx.meta_left_joins.len() == tables.joined_tables().len(),
         "meta_left_joins length does not match tables length"
     );
+    assert!(
+        t_ctx.meta_right_joins.len() == tables.joined_tables().len(),
+        "meta_right_joins length does not match tables length"
+    );
 
     let cdc_table = program.capture_data_changes_mode().table();
     if cdc_table.is_some()
@@ -125,6 +177,14 @@ pub fn init_loop(
         )
     {
         assert!(tables.joined_tables().len() == 1);
+        if tables.joined_tables()[0]
+            .btree()
+            .is_some_and(|btree| !btree.has_rowid)
+        {
+            crate::bail_parse_error!(
+                "capturing data changes of WITHOUT ROWID tables is not supported"
+            );
+        }
         let cdc_table_name = cdc_table.unwrap();
         if tables.joined_tables()[0].table.get_name() != cdc_table_name {
             let Some(cdc_table) = t_ctx.resolver.schema.get_table(cdc_table_name) else {
@@ -138,6 +198,7 @@ pub fn init_loop(
                 cursor_id: cdc_cursor_id,
                 root_page: cdc_btree.root_page.into(),
                 name: cdc_btree.name.clone(),
+                db: 0,
             });
             t_ctx.cdc_cursor_id = Some(cdc_cursor_id);
         }
@@ -165,9 +226,13 @@ pub fn init_loop(
                 pos_in_table: 0,
                 collation: None, // FIXME: this should be inferred from the expression
                 default: None,   // FIXME: this should be inferred from the expression
+                expr: None,
             }],
             has_rowid: false,
+            on_conflict: None,
             unique: false,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
         let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
         if group_by.is_none() {
@@ -197,6 +262,10 @@ pub fn init_loop(
                 };
                 t_ctx.meta_left_joins[table_index] = Some(lj_metadata);
             }
+            if join_info.right {
+                t_ctx.meta_right_joins[table_index] =
+                    Some(init_right_join(program, table, table_index));
+            }
         }
         let (table_cursor_id, index_cursor_id) = table.open_cursors(program, mode)?;
         match &table.op {
@@ -207,12 +276,14 @@ pub fn init_loop(
                         program.emit_insn(Insn::OpenRead {
                             cursor_id,
                             root_page,
+                            db: table.database_id,
                         });
                     }
                     if let Some(index_cursor_id) = index_cursor_id {
                         program.emit_insn(Insn::OpenRead {
                             cursor_id: index_cursor_id,
                             root_page: index.as_ref().unwrap().root_page,
+                            db: table.database_id,
                         });
                     }
                 }
@@ -223,16 +294,19 @@ pub fn init_loop(
                             .expect("table cursor is always opened in OperationMode::DELETE"),
                         root_page: root_page.into(),
                         name: btree.name.clone(),
+                        db: table.database_id,
                     });
                     if let Some(index_cursor_id) = index_cursor_id {
                         program.emit_insn(Insn::OpenWrite {
                             cursor_id: index_cursor_id,
                             root_page: index.as_ref().unwrap().root_page.into(),
                             name: index.as_ref().unwrap().name.clone(),
+                            db: table.database_id,
                         });
                     }
                     // For delete, we need to open all the other indexes too for writing
-                    if let Some(indexes) = t_ctx.resolver.schema.indexes.get(&btree.name) {
+                    let schema = t_ctx.resolver.schema.database_schema(table.database_id);
+                    if let Some(indexes) = schema.indexes.get(&btree.name) {
                         for index in indexes {
                             if table
                                 .op
@@ -249,6 +323,7 @@ pub fn init_loop(
                                 cursor_id,
                                 root_page: index.root_page.into(),
                                 name: index.name.clone(),
+                                db: table.database_id,
                             });
                         }
                     }
@@ -260,12 +335,14 @@ pub fn init_loop(
                             .expect("table cursor is always opened in OperationMode::UPDATE"),
                         root_page: root_page.into(),
                         name: btree.name.clone(),
+                        db: table.database_id,
                     });
                     if let Some(index_cursor_id) = index_cursor_id {
                         program.emit_insn(Insn::OpenWrite {
                             cursor_id: index_cursor_id,
                             root_page: index.as_ref().unwrap().root_page.into(),
                             name: index.as_ref().unwrap().name.clone(),
+                            db: table.database_id,
                         });
                     }
                 }
@@ -283,6 +360,7 @@ pub fn init_loop(
                             program.emit_insn(Insn::OpenRead {
                                 cursor_id: table_cursor_id,
                                 root_page: table.table.get_root_page(),
+                                db: table.database_id,
                             });
                         }
                     }
@@ -295,14 +373,14 @@ pub fn init_loop(
                             cursor_id: table_cursor_id,
                             root_page: table.table.get_root_page().into(),
                             name: table.table.get_name().to_string(),
+                            db: table.database_id,
                         });
 
                         // For DELETE, we need to open all the indexes for writing
                         // UPDATE opens these in emit_program_for_update() separately
                         if mode == OperationMode::DELETE {
-                            if let Some(indexes) =
-                                t_ctx.resolver.schema.indexes.get(table.table.get_name())
-                            {
+                            let schema = t_ctx.resolver.schema.database_schema(table.database_id);
+                            if let Some(indexes) = schema.indexes.get(table.table.get_name()) {
                                 for index in indexes {
                                     if table
                                         .op
@@ -319,6 +397,7 @@ pub fn init_loop(
                                         cursor_id,
                                         root_page: index.root_page.into(),
                                         name: index.name.clone(),
+                                        db: table.database_id,
                                     });
                                 }
                             }
@@ -341,6 +420,7 @@ pub fn init_loop(
                                     cursor_id: index_cursor_id
                                         .expect("index cursor is always opened in Seek with index"),
                                     root_page: index.root_page,
+                                    db: table.database_id,
                                 });
                             }
                             OperationMode::UPDATE | OperationMode::DELETE => {
@@ -349,6 +429,44 @@ pub fn init_loop(
                                         .expect("index cursor is always opened in Seek with index"),
                                     root_page: index.root_page.into(),
                                     name: index.name.clone(),
+                                    db: table.database_id,
+                                });
+                            }
+                            _ => {
+                                unimplemented!()
+                            }
+                        }
+                    }
+                }
+
+                if let Search::MultiIndexOr { branches } = search {
+                    for index in branches.iter().filter_map(|branch| match branch {
+                        Search::Seek {
+                            index: Some(index), ..
+                        } => Some(index),
+                        _ => None,
+                    }) {
+                        // Several branches may search the same index, and DELETE opened all of them already.
+                        let key = CursorKey::index(table.internal_id, index.clone());
+                        if program.resolve_cursor_id_safe(&key).is_some() {
+                            continue;
+                        }
+                        let cursor_id = program
+                            .alloc_cursor_id_keyed(key, CursorType::BTreeIndex(index.clone()));
+                        match mode {
+                            OperationMode::SELECT => {
+                                program.emit_insn(Insn::OpenRead {
+                                    cursor_id,
+                                    root_page: index.root_page,
+                                    db: table.database_id,
+                                });
+                            }
+                            OperationMode::UPDATE | OperationMode::DELETE => {
+                                program.emit_insn(Insn::OpenWrite {
+                                    cursor_id,
+                                    root_page: index.root_page.into(),
+                                    name: index.name.clone(),
+                                    db: table.database_id,
                                 });
                             }
                             _ => {
@@ -361,15 +479,36 @@ pub fn init_loop(
         }
     }
 
+    // Any order of the joined tables tells apart the terms that don't reference any of them.
+    let join_order = tables
+        .joined_tables()
+        .iter()
+        .enumerate()
+        .map(|(i, t)| JoinOrderMember {
+            table_id: t.internal_id,
+            original_idx: i,
+            is_outer: t.join_info.as_ref().is_some_and(|j| j.outer),
+        })
+        .collect::<Vec<_>>();
+    // The conditions evaluated before the loop that still apply with a RIGHT JOIN come from ON clauses
+    // on the left of the first one, so only the rows of its right table that match nothing are left.
+    let jump_target_when_false = t_ctx
+        .meta_right_joins
+        .iter()
+        .flatten()
+        .next()
+        .map_or(t_ctx.label_main_loop_end.unwrap(), |rj_meta| {
+            rj_meta.label_unmatched_rows
+        });
     for cond in where_clause
         .iter()
-        .filter(|c| c.should_eval_before_loop(&[JoinOrderMember::default()]))
+        .filter(|c| c.should_eval_before_loop(&join_order))
     {
         let jump_target = program.allocate_label();
         let meta = ConditionMetadata {
             jump_if_condition_is_true: false,
             jump_target_when_true: jump_target,
-            jump_target_when_false: t_ctx.label_main_loop_end.unwrap(),
+            jump_target_when_false,
         };
         translate_condition_expr(program, tables, &cond.expr, meta, &t_ctx.resolver)?;
         program.preassign_label_to_next_insn(jump_target);
@@ -378,6 +517,76 @@ pub fn init_loop(
     Ok(())
 }
 
+/// Opens the ephemeral index keeping track of the rows of the right table of a RIGHT JOIN
+/// that match a row of the tables on its left.
+fn init_right_join(
+    program: &mut ProgramBuilder,
+    table: &JoinedTable,
+    table_index: usize,
+) -> RightJoinMetadata {
+    let index_name = format!("right_join_matched_{table_index}");
+    let index = Arc::new(Index {
+        name: index_name.clone(),
+        table_name: String::new(),
+        ephemeral: true,
+        root_page: 0,
+        columns: vec![IndexColumn {
+            name: "key".to_string(),
+            order: SortOrder::Asc,
+            pos_in_table: 0,
+            collation: None,
+            default: None,
+            expr: None,
+        }],
+        unique: false,
+        has_rowid: false,
+        on_conflict: None,
+        where_clause: None,
+        pk_suffix_len: 0,
+    });
+    let matched_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: matched_cursor_id,
+        is_table: false,
+    });
+    RightJoinMetadata {
+        matched_cursor_id,
+        matched_index_name: index_name,
+        // The rows of a subquery have no rowid, but they are produced in the same order every time.
+        reg_row_number: matches!(table.table, Table::FromClauseSubquery(_))
+            .then(|| program.alloc_register()),
+        reg_subroutine_return: program.alloc_register(),
+        label_subroutine: program.allocate_label(),
+        label_subroutine_return: program.allocate_label(),
+        label_unmatched_rows: program.allocate_label(),
+    }
+}
+
+/// Positions the table cursor of `table` on the row the entry under `index_cursor_id` points to.
+/// Rows of tables with a rowid are only looked up once a column of the table is read; rows of
+/// WITHOUT ROWID tables are looked up by the PRIMARY KEY stored in the index entry.
+fn emit_seek_from_index(
+    program: &mut ProgramBuilder,
+    table: &JoinedTable,
+    index_cursor_id: CursorID,
+    table_cursor_id: CursorID,
+    not_found: BranchOffset,
+) {
+    match (table.btree(), table.op.index()) {
+        (Some(btree), Some(index)) if !btree.has_rowid => {
+            let key_reg = emit_index_entry_row_key(program, &btree, index, index_cursor_id);
+            emit_seek_row_key(program, &btree, table_cursor_id, key_reg, not_found);
+        }
+        _ => {
+            // Don't do a btree table seek until it's actually necessary to read from the table.
+            program.emit_insn(Insn::DeferredSeek {
+                index_cursor_id,
+                table_cursor_id,
+            });
+        }
+    }
+}
+
 /// Set up the main query execution loop
 /// For example in the case of a nested table scan, this means emitting the Rewind instruction
 /// for all tables involved, outermost first.
@@ -413,8 +622,27 @@ pub fn open_loop(
                 });
             }
         }
+        if let Some(reg_row_number) = t_ctx.meta_right_joins[joined_table_index]
+            .as_ref()
+            .and_then(|rj_meta| rj_meta.reg_row_number)
+        {
+            program.emit_insn(Insn::Integer {
+                value: 0,
+                dest: reg_row_number,
+            });
+        }
 
         let (table_cursor_id, index_cursor_id) = table.resolve_cursors(program)?;
+        // The rows of a temp table were read by the plan that filled it, which explained them.
+        // A virtual table is explained when its constraints are known.
+        // The searches of the branches of a multi-index OR are explained within it.
+        let explain_pushed = matches!(table.op, Operation::Search(Search::MultiIndexOr { .. }));
+        if temp_cursor_id.is_none() && !matches!(table.table, Table::Virtual(_)) {
+            program.emit_explain(
+                explain_pushed,
+                explain_table_loop(table, table_cursor_id.is_none()),
+            );
+        }
 
         match &table.op {
             Operation::Scan { iter_dir, .. } => {
@@ -511,6 +739,16 @@ pub fn open_loop(
                                 }
                             }
 
+                            program.emit_explain(
+                                false,
+                                format!(
+                                    "SCAN {} VIRTUAL TABLE INDEX {}:{}",
+                                    table.identifier,
+                                    index_info.idx_num,
+                                    index_info.idx_str.as_deref().unwrap_or_default()
+                                ),
+                            );
+
                             // If best_index provided an idx_str, translate it.
                             let maybe_idx_str = if let Some(idx_str) = index_info.idx_str {
                                 let reg = program.alloc_register();
@@ -542,9 +780,16 @@ pub fn open_loop(
                         });
                         program.preassign_label_to_next_insn(loop_start);
                     }
+                    Table::FromClauseSubquery(from_clause_subquery)
+                        if from_clause_subquery.is_recursive_reference =>
+                    {
+                        // The single row of a recursive reference is already in its result registers,
+                        // so the loop body runs exactly once.
+                        program.preassign_label_to_next_insn(loop_start);
+                    }
                     Table::FromClauseSubquery(from_clause_subquery) => {
                         let (yield_reg, coroutine_implementation_start) =
-                            match &from_clause_subquery.plan.query_destination {
+                            match from_clause_subquery.query_destination() {
                                 QueryDestination::CoroutineYield {
                                     yield_reg,
                                     coroutine_implementation_start,
@@ -571,32 +816,73 @@ pub fn open_loop(
 
                 if let Some(table_cursor_id) = table_cursor_id {
                     if let Some(index_cursor_id) = index_cursor_id {
-                        program.emit_insn(Insn::DeferredSeek {
+                        emit_seek_from_index(
+                            program,
+                            table,
                             index_cursor_id,
                             table_cursor_id,
-                        });
+                            next,
+                        );
                     }
                 }
-
-                for cond in predicates
-                    .iter()
-                    .filter(|cond| cond.should_eval_at_loop(join_index, join_order))
-                {
-                    let jump_target_when_true = program.allocate_label();
-                    let condition_metadata = ConditionMetadata {
-                        jump_if_condition_is_true: false,
-                        jump_target_when_true,
-                        jump_target_when_false: next,
-                    };
-                    translate_condition_expr(
+            }
+            Operation::Search(Search::MultiIndexOr { branches }) => {
+                let table_cursor_id =
+                    table_cursor_id.expect("Search::MultiIndexOr requires a table cursor");
+                let rowset_cursor_id = emit_multi_index_or(
+                    program,
+                    t_ctx,
+                    table_references,
+                    table,
+                    branches,
+                    table_cursor_id,
+                )?;
+                program.pop_explain();
+                program.emit_insn(Insn::Rewind {
+                    cursor_id: rowset_cursor_id,
+                    pc_if_empty: loop_end,
+                });
+                program.preassign_label_to_next_insn(loop_start);
+                let rowid_reg = program.alloc_register();
+                program.emit_column(rowset_cursor_id, 0, rowid_reg);
+                program.emit_insn(Insn::SeekRowid {
+                    cursor_id: table_cursor_id,
+                    src_reg: rowid_reg,
+                    target_pc: next,
+                });
+                t_ctx.meta_search_loops[joined_table_index].rowset_cursor_id =
+                    Some(rowset_cursor_id);
+            }
+            Operation::Search(Search::HashJoin { index, keys }) => {
+                let hash_cursor_id =
+                    index_cursor_id.expect("Search::HashJoin requires a hash table cursor");
+                emit_hash_join_build(
+                    program,
+                    &t_ctx.resolver,
+                    index,
+                    keys,
+                    table,
+                    table_cursor_id.expect("Search::HashJoin requires a table cursor"),
+                    hash_cursor_id,
+                )?;
+                let key_reg = program.alloc_registers(keys.len());
+                for (i, key) in keys.iter().enumerate() {
+                    translate_expr(
                         program,
-                        table_references,
-                        &cond.expr,
-                        condition_metadata,
+                        Some(table_references),
+                        &key.expr,
+                        key_reg + i,
                         &t_ctx.resolver,
                     )?;
-                    program.preassign_label_to_next_insn(jump_target_when_true);
                 }
+                emit_hash_join_key_affinity(program, keys, key_reg);
+                program.emit_insn(Insn::HashProbe {
+                    cursor_id: hash_cursor_id,
+                    key_reg,
+                    num_keys: keys.len(),
+                    pc_if_empty: loop_end,
+                });
+                program.preassign_label_to_next_insn(loop_start);
             }
             Operation::Search(search) => {
                 assert!(
@@ -627,19 +913,15 @@ pub fn open_loop(
                     } = search
                     {
                         if index.ephemeral {
-                            let table_has_rowid = if let Table::BTree(btree) = &table.table {
-                                btree.has_rowid
-                            } else {
-                                false
-                            };
                             Some(emit_autoindex(
                                 program,
+                                &t_ctx.resolver,
                                 index,
+                                &table.table,
                                 table_cursor_id
                                     .expect("an ephemeral index must have a source table cursor"),
                                 index_cursor_id
                                     .expect("an ephemeral index must have an index cursor"),
-                                table_has_rowid,
                             )?)
                         } else {
                             index_cursor_id
@@ -648,18 +930,35 @@ pub fn open_loop(
                         index_cursor_id
                     };
 
-                    let is_index = index_cursor_id.is_some();
+                    // The b-tree of a WITHOUT ROWID table is an index keyed by the PRIMARY KEY
+                    let is_index = index_cursor_id.is_some()
+                        || table.btree().is_some_and(|btree| !btree.has_rowid);
                     let seek_cursor_id = temp_cursor_id.unwrap_or_else(|| {
                         index_cursor_id.unwrap_or_else(|| {
                             table_cursor_id
                                 .expect("Either ephemeral or index or table cursor must be opened")
                         })
                     });
-                    let Search::Seek { seek_def, .. } = search else {
+                    let Search::Seek { index, seek_def } = search else {
                         unreachable!("Rowid equality point lookup should have been handled above");
                     };
 
                     let start_reg = program.alloc_registers(seek_def.key.len());
+                    let in_loops = emit_in_loops(
+                        program,
+                        table_references,
+                        t_ctx,
+                        table,
+                        index.as_ref(),
+                        seek_def,
+                        start_reg,
+                        loop_end,
+                    )?;
+                    // Once the seek finds no more rows, it is repeated for the next value of the innermost IN list.
+                    let seek_end = in_loops
+                        .last()
+                        .map_or(loop_end, |in_loop| in_loop.label_next);
+                    t_ctx.meta_search_loops[joined_table_index].in_loops = in_loops;
                     emit_seek(
                         program,
                         table_references,
@@ -667,7 +966,7 @@ pub fn open_loop(
                         t_ctx,
                         seek_cursor_id,
                         start_reg,
-                        loop_end,
+                        seek_end,
                         is_index,
                     )?;
                     emit_seek_termination(
@@ -678,41 +977,75 @@ pub fn open_loop(
                         seek_cursor_id,
                         start_reg,
                         loop_start,
-                        loop_end,
+                        seek_end,
                         is_index,
                     )?;
 
                     if let Some(index_cursor_id) = index_cursor_id {
                         if let Some(table_cursor_id) = table_cursor_id {
-                            // Don't do a btree table seek until it's actually necessary to read from the table.
-                            program.emit_insn(Insn::DeferredSeek {
+                            emit_seek_from_index(
+                                program,
+                                table,
                                 index_cursor_id,
                                 table_cursor_id,
-                            });
+                                next,
+                            );
                         }
                     }
                 }
+            }
+        }
 
-                for cond in predicates
-                    .iter()
-                    .filter(|cond| cond.should_eval_at_loop(join_index, join_order))
-                {
-                    let jump_target_when_true = program.allocate_label();
-                    let condition_metadata = ConditionMetadata {
-                        jump_if_condition_is_true: false,
-                        jump_target_when_true,
-                        jump_target_when_false: next,
-                    };
-                    translate_condition_expr(
+        let rj_meta = t_ctx.meta_right_joins[joined_table_index].as_ref();
+        if let Some(reg_row_number) = rj_meta.and_then(|rj_meta| rj_meta.reg_row_number) {
+            emit_increment(program, reg_row_number);
+        }
+
+        // The conditions of the ON clause of an OUTER JOIN decide whether the row matches.
+        emit_loop_conditions(
+            program,
+            t_ctx,
+            table_references,
+            predicates.iter().filter(|cond| {
+                cond.from_outer_join == Some(table.internal_id)
+                    && cond.should_eval_at_loop(join_index, join_order)
+            }),
+            next,
+        )?;
+
+        // Remember that the row of the right table of a RIGHT JOIN has a match.
+        if let Some(rj_meta) = rj_meta {
+            let key_reg = match rj_meta.reg_row_number {
+                Some(reg_row_number) => reg_row_number,
+                None => {
+                    let rowid_reg = program.alloc_register();
+                    translate_expr(
                         program,
-                        table_references,
-                        &cond.expr,
-                        condition_metadata,
+                        Some(table_references),
+                        &ast::Expr::RowId {
+                            database: None,
+                            table: table.internal_id,
+                        },
+                        rowid_reg,
                         &t_ctx.resolver,
                     )?;
-                    program.preassign_label_to_next_insn(jump_target_when_true);
+                    rowid_reg
                 }
-            }
+            };
+            let record_reg = program.alloc_register();
+            program.emit_insn(Insn::MakeRecord {
+                start_reg: key_reg,
+                count: 1,
+                dest_reg: record_reg,
+                index_name: Some(rj_meta.matched_index_name.clone()),
+            });
+            program.emit_insn(Insn::IdxInsert {
+                cursor_id: rj_meta.matched_cursor_id,
+                record_reg,
+                unpacked_start: None,
+                unpacked_count: None,
+                flags: IdxInsertFlags::new().no_op_duplicate(),
+            });
         }
 
         // Set the match flag to true if this is a LEFT JOIN.
@@ -729,22 +1062,90 @@ pub fn open_loop(
                 });
             }
         }
+
+        // The rest of the join is a subroutine for the right table of a RIGHT JOIN, which is also
+        // called for each of its rows that match no row of the tables on its left, after the loop.
+        let jump_target_when_false = match rj_meta {
+            Some(rj_meta) => {
+                program.emit_insn(Insn::Gosub {
+                    target_pc: rj_meta.label_subroutine,
+                    return_reg: rj_meta.reg_subroutine_return,
+                });
+                program.emit_insn(Insn::Goto { target_pc: next });
+                program.preassign_label_to_next_insn(rj_meta.label_subroutine);
+                rj_meta.label_subroutine_return
+            }
+            None => next,
+        };
+
+        // The other conditions apply to the rows once the table is joined, NULL-extended or not.
+        emit_loop_conditions(
+            program,
+            t_ctx,
+            table_references,
+            predicates.iter().filter(|cond| {
+                cond.from_outer_join != Some(table.internal_id)
+                    && cond.should_eval_at_loop(join_index, join_order)
+            }),
+            jump_target_when_false,
+        )?;
     }
 
     Ok(())
 }
 
+/// Emits the conditions evaluated in a loop of the join, which jump to `jump_target_when_false`
+/// when they are not satisfied.
+fn emit_loop_conditions<'a>(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    table_references: &TableReferences,
+    conditions: impl Iterator<Item = &'a WhereTerm>,
+    jump_target_when_false: BranchOffset,
+) -> Result<()> {
+    for cond in conditions {
+        let jump_target_when_true = program.allocate_label();
+        let condition_metadata = ConditionMetadata {
+            jump_if_condition_is_true: false,
+            jump_target_when_true,
+            jump_target_when_false,
+        };
+        translate_condition_expr(
+            program,
+            table_references,
+            &cond.expr,
+            condition_metadata,
+            &t_ctx.resolver,
+        )?;
+        program.preassign_label_to_next_insn(jump_target_when_true);
+    }
+    Ok(())
+}
+
+/// Adds 1 to the integer in register `reg`.
+fn emit_increment(program: &mut ProgramBuilder, reg: usize) {
+    let one_reg = program.alloc_register();
+    program.emit_int(1, one_reg);
+    program.emit_insn(Insn::Add {
+        lhs: reg,
+        rhs: one_reg,
+        dest: reg,
+    });
+}
+
 /// SQLite (and so Limbo) processes joins as a nested loop.
 /// The loop may emit rows to various destinations depending on the query:
 /// - a GROUP BY sorter (grouping is done by sorting based on the GROUP BY keys and aggregating while the GROUP BY keys match)
 /// - a GROUP BY phase with no sorting (when the rows are already in the order required by the GROUP BY keys)
 /// - an ORDER BY sorter (when there is no GROUP BY, but there is an ORDER BY)
 /// - an AggStep (the columns are collected for aggregation, which is finished later)
+/// - a window sorter (the window functions are computed after the main loop)
 /// - a QueryResult (there is none of the above, so the loop either emits a ResultRow, or if it's a subquery, yields to the parent query)
 enum LoopEmitTarget {
     GroupBy,
     OrderBySorter,
     AggStep,
+    Window,
     QueryResult,
 }
 
@@ -765,6 +1166,11 @@ pub fn emit_loop(
     if !plan.aggregates.is_empty() {
         return emit_loop_source(program, t_ctx, plan, LoopEmitTarget::AggStep);
     }
+    // if we have window functions, we emit a record into the sorter of the first window.
+    // the ORDER BY sorter, if any, is filled after the window functions are computed.
+    if plan.window.is_some() {
+        return emit_loop_source(program, t_ctx, plan, LoopEmitTarget::Window);
+    }
     // if we DONT have a group by, but we have an order by, we emit a record into the order by sorter.
     if plan.order_by.is_some() {
         return emit_loop_source(program, t_ctx, plan, LoopEmitTarget::OrderBySorter);
@@ -920,12 +1326,11 @@ fn emit_loop_source(
 
             let col_start = t_ctx.reg_result_cols_start.unwrap();
 
-            // Process only non-aggregate columns
-            let non_agg_columns = plan
-                .result_columns
-                .iter()
-                .enumerate()
-                .filter(|(_, rc)| !rc.contains_aggregates);
+            // Process only non-aggregate columns that read the current row,
+            // the others are computed after the loop.
+            let non_agg_columns = plan.result_columns.iter().enumerate().filter(|(_, rc)| {
+                !rc.contains_aggregates && nonagg_column_reads_row(&rc.expr, &plan.table_references)
+            });
 
             for (i, rc) in non_agg_columns {
                 let reg = col_start + i;
@@ -946,15 +1351,22 @@ fn emit_loop_source(
 
             Ok(())
         }
+        LoopEmitTarget::Window => window_sorter_insert(program, t_ctx, plan),
         LoopEmitTarget::QueryResult => {
             assert!(
                 plan.aggregates.is_empty(),
                 "We should not get here with aggregates"
             );
-            let offset_jump_to = t_ctx
-                .labels_main_loop
-                .first()
-                .map(|l| l.next)
+            // A row skipped by the OFFSET continues with the next row of the innermost loop.
+            let offset_jump_to = plan
+                .join_order
+                .last()
+                .map(
+                    |join| match t_ctx.meta_right_joins[join.original_idx].as_ref() {
+                        Some(rj_meta) => rj_meta.label_subroutine_return,
+                        None => t_ctx.labels_main_loop[join.original_idx].next,
+                    },
+                )
                 .or(t_ctx.label_main_loop_end);
             emit_select_result(
                 program,
@@ -1006,6 +1418,15 @@ pub fn close_loop(
 
         let (table_cursor_id, index_cursor_id) = table.resolve_cursors(program)?;
 
+        // The join of a row of the right table of a RIGHT JOIN with the tables after it is done.
+        if let Some(rj_meta) = t_ctx.meta_right_joins[table_index].as_ref() {
+            program.resolve_label(rj_meta.label_subroutine_return, program.offset());
+            program.emit_insn(Insn::Return {
+                return_reg: rj_meta.reg_subroutine_return,
+                can_fallthrough: false,
+            });
+        }
+
         match &table.op {
             Operation::Scan { iter_dir, .. } => {
                 program.resolve_label(loop_labels.next, program.offset());
@@ -1037,6 +1458,8 @@ pub fn close_loop(
                             pc_if_next: loop_labels.loop_start,
                         });
                     }
+                    Table::FromClauseSubquery(from_clause_subquery)
+                        if from_clause_subquery.is_recursive_reference => {}
                     Table::FromClauseSubquery(_) => {
                         // A subquery has no cursor to call Next on, so it just emits a Goto
                         // to the Yield instruction, which in turn jumps back to the main loop of the subquery,
@@ -1060,21 +1483,30 @@ pub fn close_loop(
                             .expect("Either ephemeral or index or table cursor must be opened")
                     })
                 });
+                let search_loops = &t_ctx.meta_search_loops[table_index];
                 // Rowid equality point lookups are handled with a SeekRowid instruction which does not loop, so there is no need to emit a Next instruction.
-                if !matches!(search, Search::RowidEq { .. }) {
-                    let iter_dir = match search {
-                        Search::Seek { seek_def, .. } => seek_def.iter_dir,
-                        Search::RowidEq { .. } => unreachable!(),
-                    };
-
-                    if iter_dir == IterationDirection::Backwards {
-                        program.emit_insn(Insn::Prev {
+                match search {
+                    Search::RowidEq { .. } => {}
+                    Search::Seek { seek_def, .. } => {
+                        emit_next(
+                            program,
+                            iteration_cursor_id,
+                            seek_def.iter_dir == IterationDirection::Backwards,
+                            loop_labels.loop_start,
+                        );
+                        emit_in_loops_next(program, &search_loops.in_loops);
+                    }
+                    Search::HashJoin { .. } => {
+                        program.emit_insn(Insn::HashNext {
                             cursor_id: iteration_cursor_id,
-                            pc_if_prev: loop_labels.loop_start,
+                            pc_if_next: loop_labels.loop_start,
                         });
-                    } else {
+                    }
+                    Search::MultiIndexOr { .. } => {
                         program.emit_insn(Insn::Next {
-                            cursor_id: iteration_cursor_id,
+                            cursor_id: search_loops
+                                .rowset_cursor_id
+                                .expect("a multi-index OR has a rowset"),
                             pc_if_next: loop_labels.loop_start,
                         });
                     }
@@ -1105,14 +1537,7 @@ pub fn close_loop(
                 // First we set the right table cursor's "pseudo null bit" on, which means any Insn::Column will return NULL.
                 // This needs to be set for both the table and the index cursor, if present,
                 // since even if the iteration cursor is the index cursor, it might fetch values from the table cursor.
-                [table_cursor_id, index_cursor_id]
-                    .iter()
-                    .filter_map(|maybe_cursor_id| maybe_cursor_id.as_ref())
-                    .for_each(|cursor_id| {
-                        program.emit_insn(Insn::NullRow {
-                            cursor_id: *cursor_id,
-                        });
-                    });
+                emit_null_row(program, table, table_cursor_id, index_cursor_id);
                 // Then we jump to setting the left join match flag to 1 again,
                 // but this time the right table cursor will set everything to null.
                 // This leads to emitting a row with cols from the left + nulls from the right,
@@ -1126,9 +1551,291 @@ pub fn close_loop(
             }
         }
     }
+
+    // Once the loop is done, the rows of the right tables of RIGHT JOINs that matched nothing
+    // are joined with NULLs for the tables on their left.
+    for (join_index, join) in join_order.iter().enumerate() {
+        if t_ctx.meta_right_joins[join.original_idx].is_some() {
+            emit_unmatched_rows(program, t_ctx, tables, join_order, join_index)?;
+        }
+    }
     Ok(())
 }
 
+/// Makes the columns of `table` read as NULL, for a row of an OUTER JOIN that has no match in it.
+fn emit_null_row(
+    program: &mut ProgramBuilder,
+    table: &JoinedTable,
+    table_cursor_id: Option<CursorID>,
+    index_cursor_id: Option<CursorID>,
+) {
+    // The columns of a subquery are read from the registers its rows are yielded to.
+    if let Table::FromClauseSubquery(from_clause_subquery) = &table.table {
+        if let Some(start_reg) = from_clause_subquery.result_columns_start_reg {
+            if !from_clause_subquery.columns.is_empty() {
+                program.emit_insn(Insn::Null {
+                    dest: start_reg,
+                    dest_end: Some(start_reg + from_clause_subquery.columns.len() - 1),
+                });
+            }
+        }
+        return;
+    }
+    // This needs to be set for both the table and the index cursor, if present,
+    // since even if the iteration cursor is the index cursor, it might fetch values from the table cursor.
+    [table_cursor_id, index_cursor_id]
+        .iter()
+        .filter_map(|maybe_cursor_id| maybe_cursor_id.as_ref())
+        .for_each(|cursor_id| {
+            program.emit_insn(Insn::NullRow {
+                cursor_id: *cursor_id,
+            });
+        });
+}
+
+/// Scans the right table of a RIGHT JOIN, at `join_index` in the join order, for the rows that
+/// match no row of the tables on its left, and calls the subroutine joining them with the tables
+/// after it while the tables on its left are NULL.
+fn emit_unmatched_rows(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    tables: &TableReferences,
+    join_order: &[JoinOrderMember],
+    join_index: usize,
+) -> Result<()> {
+    let table = &tables.joined_tables()[join_order[join_index].original_idx];
+    let rj_meta = t_ctx.meta_right_joins[join_order[join_index].original_idx]
+        .as_ref()
+        .expect("right join metadata must exist");
+    let loop_start = program.allocate_label();
+    let next = program.allocate_label();
+    let loop_end = program.allocate_label();
+    program.preassign_label_to_next_insn(rj_meta.label_unmatched_rows);
+    program.emit_explain(true, format!("RIGHT-JOIN {}", table.identifier));
+    program.emit_explain(false, format!("SCAN {}", table.identifier));
+
+    let (table_cursor_id, index_cursor_id) = table.resolve_cursors(program)?;
+    let key_reg = match &table.table {
+        Table::BTree(_) => {
+            // Every row is read, so the index of a search is scanned from the start.
+            let iteration_cursor_id = index_cursor_id.unwrap_or_else(|| {
+                table_cursor_id.expect("Either index or table cursor must be opened")
+            });
+            program.emit_insn(Insn::Rewind {
+                cursor_id: iteration_cursor_id,
+                pc_if_empty: loop_end,
+            });
+            program.preassign_label_to_next_insn(loop_start);
+            if let (Some(table_cursor_id), Some(index_cursor_id)) =
+                (table_cursor_id, index_cursor_id)
+            {
+                emit_seek_from_index(program, table, index_cursor_id, table_cursor_id, next);
+            }
+            let rowid_reg = program.alloc_register();
+            translate_expr(
+                program,
+                Some(tables),
+                &ast::Expr::RowId {
+                    database: None,
+                    table: table.internal_id,
+                },
+                rowid_reg,
+                &t_ctx.resolver,
+            )?;
+            rowid_reg
+        }
+        Table::FromClauseSubquery(from_clause_subquery) => {
+            let (yield_reg, coroutine_implementation_start) =
+                match from_clause_subquery.query_destination() {
+                    QueryDestination::CoroutineYield {
+                        yield_reg,
+                        coroutine_implementation_start,
+                    } => (*yield_reg, *coroutine_implementation_start),
+                    _ => unreachable!("Subquery table with non-subquery query type"),
+                };
+            let reg_row_number = rj_meta
+                .reg_row_number
+                .expect("the rows of a subquery are numbered");
+            program.emit_insn(Insn::InitCoroutine {
+                yield_reg,
+                jump_on_definition: BranchOffset::Offset(0),
+                start_offset: coroutine_implementation_start,
+            });
+            program.emit_insn(Insn::Integer {
+                value: 0,
+                dest: reg_row_number,
+            });
+            program.preassign_label_to_next_insn(loop_start);
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: loop_end,
+            });
+            emit_increment(program, reg_row_number);
+            reg_row_number
+        }
+        Table::Virtual(_) => unreachable!("RIGHT JOIN with a virtual table"),
+    };
+    program.emit_insn(Insn::Found {
+        cursor_id: rj_meta.matched_cursor_id,
+        target_pc: next,
+        record_reg: key_reg,
+        num_regs: 1,
+    });
+
+    for join in join_order[..join_index].iter() {
+        let left_table = &tables.joined_tables()[join.original_idx];
+        let (table_cursor_id, index_cursor_id) = left_table.resolve_cursors(program)?;
+        emit_null_row(program, left_table, table_cursor_id, index_cursor_id);
+    }
+    program.emit_insn(Insn::Gosub {
+        target_pc: rj_meta.label_subroutine,
+        return_reg: rj_meta.reg_subroutine_return,
+    });
+
+    program.preassign_label_to_next_insn(next);
+    match &table.table {
+        Table::BTree(_) => {
+            program.emit_insn(Insn::Next {
+                cursor_id: index_cursor_id.unwrap_or_else(|| {
+                    table_cursor_id.expect("Either index or table cursor must be opened")
+                }),
+                pc_if_next: loop_start,
+            });
+        }
+        _ => {
+            program.emit_insn(Insn::Goto {
+                target_pc: loop_start,
+            });
+        }
+    }
+    program.preassign_label_to_next_insn(loop_end);
+    program.pop_explain();
+    Ok(())
+}
+
+/// Describes how the loop of a table reads its rows, for the EXPLAIN QUERY PLAN output,
+/// e.g. `SCAN t` or `SEARCH t USING COVERING INDEX t_ab (a=? AND b>?)`.
+pub(crate) fn explain_table_loop(table: &JoinedTable, index_only: bool) -> String {
+    let mut detail = match &table.op {
+        Operation::Scan { index, .. } => match index {
+            Some(index) if !is_primary_key(table, index) => format!(
+                "SCAN {} USING {}",
+                table.identifier,
+                describe_index(table, index, index_only)
+            ),
+            _ => format!("SCAN {}", table.identifier),
+        },
+        // The lookups of the branches are explained as its children
+        Operation::Search(Search::MultiIndexOr { .. }) => "MULTI-INDEX OR".to_string(),
+        Operation::Search(search) => explain_search(table, search, index_only),
+    };
+    if table
+        .join_info
+        .as_ref()
+        .is_some_and(|join_info| join_info.outer)
+    {
+        detail.push_str(" LEFT-JOIN");
+    }
+    detail
+}
+
+/// Whether `index` is the PRIMARY KEY of a WITHOUT ROWID table, i.e. the b-tree of the table itself.
+fn is_primary_key(table: &JoinedTable, index: &Index) -> bool {
+    table
+        .btree()
+        .is_some_and(|btree| !btree.has_rowid && btree.root_page == index.root_page)
+}
+
+fn describe_index(table: &JoinedTable, index: &Index, index_only: bool) -> String {
+    if index.ephemeral {
+        "AUTOMATIC COVERING INDEX".to_string()
+    } else if is_primary_key(table, index) {
+        "PRIMARY KEY".to_string()
+    } else if index_only {
+        format!("COVERING INDEX {}", index.name)
+    } else {
+        format!("INDEX {}", index.name)
+    }
+}
+
+/// Describes a [Search::RowidEq], [Search::Seek] or [Search::HashJoin] of a table, see [explain_table_loop].
+fn explain_search(table: &JoinedTable, search: &Search, index_only: bool) -> String {
+    match search {
+        Search::RowidEq { .. } => format!(
+            "SEARCH {} USING INTEGER PRIMARY KEY (rowid=?)",
+            table.identifier
+        ),
+        Search::Seek { index, seek_def } => {
+            let (access, constraints) = match index {
+                Some(index) => (
+                    describe_index(table, index, index_only),
+                    explain_seek_constraints(seek_def, |i| match &index.columns[i].expr {
+                        Some(_) => "<expr>",
+                        None => &index.columns[i].name,
+                    }),
+                ),
+                None => match table.btree().filter(|btree| !btree.has_rowid) {
+                    Some(btree) => (
+                        "PRIMARY KEY".to_string(),
+                        explain_seek_constraints(seek_def, |i| {
+                            btree.primary_key_columns[i].0.as_str()
+                        }),
+                    ),
+                    None => (
+                        "INTEGER PRIMARY KEY".to_string(),
+                        explain_seek_constraints(seek_def, |_| "rowid"),
+                    ),
+                },
+            };
+            format!("SEARCH {} USING {access} {constraints}", table.identifier)
+        }
+        Search::HashJoin { index, keys } => {
+            let constraints = index.columns[..keys.len()]
+                .iter()
+                .map(|column| format!("{}=?", column.name));
+            format!(
+                "SEARCH {} USING HASH JOIN ({})",
+                table.identifier,
+                constraints.collect::<Vec<_>>().join(" AND ")
+            )
+        }
+        Search::MultiIndexOr { .. } => unreachable!("a branch of a multi-index OR is not one"),
+    }
+}
+
+/// Describes the columns a seek constrains, e.g. `(a=? AND b>?)`: all of them are equalities,
+/// except possibly the last one, which can be bounded from below or above.
+fn explain_seek_constraints<'a>(
+    seek_def: &SeekDef,
+    column_name: impl Fn(usize) -> &'a str,
+) -> String {
+    let key_len = seek_def.key.len();
+    let seek_len = seek_def.seek.as_ref().map_or(0, |seek| seek.len);
+    let termination_len = seek_def
+        .termination
+        .as_ref()
+        .map_or(0, |termination| termination.len);
+    let constraints = (0..key_len).map(|i| {
+        let op = if i + 1 < key_len || (seek_len == key_len && termination_len == key_len) {
+            "="
+        } else {
+            // The bound of the last column is where the iteration starts if the seek key has
+            // the column, and where it ends otherwise. Which of them is the lower bound depends
+            // on the direction of the iteration and on the order of the column in the index.
+            let bound_is_start = seek_len == key_len;
+            let forwards = seek_def.iter_dir == IterationDirection::Forwards;
+            let ascending = seek_def.key[i].1 == SortOrder::Asc;
+            if (bound_is_start == forwards) == ascending {
+                ">"
+            } else {
+                "<"
+            }
+        };
+        format!("{}{op}?", column_name(i))
+    });
+    format!("({})", constraints.collect::<Vec<_>>().join(" AND "))
+}
+
 /// Emits instructions for an index seek. See e.g. [crate::translate::plan::SeekDef]
 /// for more details about the seek definition.
 ///
@@ -1181,6 +1888,10 @@ fn emit_seek(
             }
         } else {
             let expr = &seek_def.key[i].0;
+            // The value of an IN list is read into the register by its loop
+            if as_in_list_components(expr).is_some() {
+                continue;
+            }
             translate_expr_no_constant_opt(
                 program,
                 Some(tables),
@@ -1297,7 +2008,8 @@ fn emit_seek_termination(
             });
         // if the seek key is shorter than the termination key, we need to translate the remaining suffix of the termination key.
         // if not, we just reuse what was emitted for the seek.
-        } else if seek_len < termination.len {
+        } else if seek_len < termination.len && as_in_list_components(&seek_def.key[i].0).is_none()
+        {
             translate_expr_no_constant_opt(
                 program,
                 Some(tables),
@@ -1398,17 +2110,336 @@ fn emit_seek_termination(
     Ok(())
 }
 
+/// Advances `cursor_id` to the next row, or to the previous one if `backwards`, jumping to `loop_start` unless there is none.
+fn emit_next(
+    program: &mut ProgramBuilder,
+    cursor_id: CursorID,
+    backwards: bool,
+    loop_start: BranchOffset,
+) {
+    if backwards {
+        program.emit_insn(Insn::Prev {
+            cursor_id,
+            pc_if_prev: loop_start,
+        });
+    } else {
+        program.emit_insn(Insn::Next {
+            cursor_id,
+            pc_if_next: loop_start,
+        });
+    }
+}
+
+/// Opens a loop over the distinct values of each `x IN (...)` list in the key of `seek_def`, outermost first.
+/// Each loop reads the current value of its list into the register of the seek key starting at `start_reg`,
+/// so that the seek is repeated for every value. The values are visited in the order of the index,
+/// which keeps the rows in that order too. The loops end at `loop_end` and are closed by [emit_in_loops_next].
+#[allow(clippy::too_many_arguments)]
+fn emit_in_loops(
+    program: &mut ProgramBuilder,
+    tables: &TableReferences,
+    t_ctx: &TranslateCtx,
+    table: &JoinedTable,
+    index: Option<&Arc<Index>>,
+    seek_def: &SeekDef,
+    start_reg: usize,
+    loop_end: BranchOffset,
+) -> Result<Vec<InLoopMetadata>> {
+    let mut in_loops = vec![];
+    for (i, (expr, sort_order)) in seek_def.key.iter().enumerate() {
+        let Some((lhs, values)) = as_in_list_components(expr) else {
+            continue;
+        };
+        let list_index_name = format!("in_list_{}", start_reg + i);
+        let list_index = Arc::new(Index {
+            name: list_index_name.clone(),
+            table_name: String::new(),
+            ephemeral: true,
+            root_page: 0,
+            columns: vec![IndexColumn {
+                name: lhs.to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                // Values that are equal for the index are looked up once
+                collation: index.and_then(|index| index.columns[i].collation),
+                default: None,
+                expr: None,
+            }],
+            has_rowid: false,
+            on_conflict: None,
+            unique: false,
+            where_clause: None,
+            pk_suffix_len: 0,
+        });
+        let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(list_index));
+        // The values are compared with the column as they would be by `x = value`
+        let affinity = match lhs {
+            ast::Expr::Column { column, .. } => table.columns()[*column].affinity(),
+            ast::Expr::RowId { .. } => Affinity::Integer,
+            _ => Affinity::Blob,
+        };
+
+        // A list of constant values is the same for every row of the outer loops, so it is only built once.
+        let label_list_built = program.allocate_label();
+        if values
+            .iter()
+            .all(|value| value.is_constant(&t_ctx.resolver))
+        {
+            program.emit_insn(Insn::Once {
+                target_pc_when_reentered: label_list_built,
+            });
+        }
+        program.emit_insn(Insn::OpenEphemeral {
+            cursor_id,
+            is_table: false,
+        });
+        let value_reg = program.alloc_register();
+        let record_reg = program.alloc_register();
+        for value in values {
+            let label_skip = program.allocate_label();
+            translate_expr_no_constant_opt(
+                program,
+                Some(tables),
+                value,
+                value_reg,
+                &t_ctx.resolver,
+                NoConstantOptReason::RegisterReuse,
+            )?;
+            // A NULL value matches no row
+            program.emit_insn(Insn::IsNull {
+                reg: value_reg,
+                target_pc: label_skip,
+            });
+            if affinity != Affinity::Blob {
+                program.emit_insn(Insn::Affinity {
+                    start_reg: value_reg,
+                    count: NonZeroUsize::new(1).unwrap(),
+                    affinities: affinity.aff_mask().to_string(),
+                });
+            }
+            program.emit_insn(Insn::MakeRecord {
+                start_reg: value_reg,
+                count: 1,
+                dest_reg: record_reg,
+                index_name: Some(list_index_name.clone()),
+            });
+            program.emit_insn(Insn::IdxInsert {
+                cursor_id,
+                record_reg,
+                unpacked_start: None,
+                unpacked_count: None,
+                flags: IdxInsertFlags::new().no_op_duplicate(),
+            });
+            program.preassign_label_to_next_insn(label_skip);
+        }
+        program.preassign_label_to_next_insn(label_list_built);
+
+        let descending = (seek_def.iter_dir == IterationDirection::Backwards)
+            != (*sort_order == SortOrder::Desc);
+        // Inner lists are only visited for a value of the outer ones, so an empty list ends all of them.
+        if descending {
+            program.emit_insn(Insn::Last {
+                cursor_id,
+                pc_if_empty: loop_end,
+            });
+        } else {
+            program.emit_insn(Insn::Rewind {
+                cursor_id,
+                pc_if_empty: loop_end,
+            });
+        }
+        let label_start = program.allocate_label();
+        program.preassign_label_to_next_insn(label_start);
+        program.emit_column(cursor_id, 0, start_reg + i);
+        in_loops.push(InLoopMetadata {
+            cursor_id,
+            label_start,
+            label_next: program.allocate_label(),
+            descending,
+        });
+    }
+    Ok(in_loops)
+}
+
+/// Closes the loops opened by [emit_in_loops], innermost first.
+fn emit_in_loops_next(program: &mut ProgramBuilder, in_loops: &[InLoopMetadata]) {
+    for in_loop in in_loops.iter().rev() {
+        program.preassign_label_to_next_insn(in_loop.label_next);
+        emit_next(
+            program,
+            in_loop.cursor_id,
+            in_loop.descending,
+            in_loop.label_start,
+        );
+    }
+}
+
+/// Collects into an ephemeral index the rowids of the rows each branch of a multi-index OR finds,
+/// which drops the rows found by several branches, and returns its cursor. The loop of the table
+/// then looks up the rows by those rowids, in ascending order.
+fn emit_multi_index_or(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx,
+    tables: &TableReferences,
+    table: &JoinedTable,
+    branches: &[Search],
+    table_cursor_id: CursorID,
+) -> Result<CursorID> {
+    let rowset_index_name = format!("multi_index_or_{}", table.internal_id);
+    let rowset_index = Arc::new(Index {
+        name: rowset_index_name.clone(),
+        table_name: String::new(),
+        ephemeral: true,
+        root_page: 0,
+        columns: vec![IndexColumn {
+            name: "rowid".to_string(),
+            order: SortOrder::Asc,
+            pos_in_table: 0,
+            collation: None,
+            default: None,
+            expr: None,
+        }],
+        has_rowid: false,
+        on_conflict: None,
+        unique: false,
+        where_clause: None,
+        pk_suffix_len: 0,
+    });
+    let rowset_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(rowset_index));
+    // The rows the branches find may depend on the outer loops, so the rowset is built anew every time.
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: rowset_cursor_id,
+        is_table: false,
+    });
+    let rowid_reg = program.alloc_register();
+    let record_reg = program.alloc_register();
+    for (i, branch) in branches.iter().enumerate() {
+        program.emit_explain(true, format!("INDEX {}", i + 1));
+        program.emit_explain(false, explain_search(table, branch, false));
+        program.pop_explain();
+
+        let label_branch_end = program.allocate_label();
+        let label_branch_start = program.allocate_label();
+        let in_loops = match branch {
+            Search::RowidEq { cmp_expr } => {
+                let src_reg = program.alloc_register();
+                translate_expr(program, Some(tables), cmp_expr, src_reg, &t_ctx.resolver)?;
+                program.emit_insn(Insn::SeekRowid {
+                    cursor_id: table_cursor_id,
+                    src_reg,
+                    target_pc: label_branch_end,
+                });
+                program.emit_insn(Insn::RowId {
+                    cursor_id: table_cursor_id,
+                    dest: rowid_reg,
+                });
+                vec![]
+            }
+            Search::Seek { index, seek_def } => {
+                let seek_cursor_id = match index {
+                    Some(index) => program
+                        .resolve_cursor_id(&CursorKey::index(table.internal_id, index.clone())),
+                    None => table_cursor_id,
+                };
+                let start_reg = program.alloc_registers(seek_def.key.len());
+                let in_loops = emit_in_loops(
+                    program,
+                    tables,
+                    t_ctx,
+                    table,
+                    index.as_ref(),
+                    seek_def,
+                    start_reg,
+                    label_branch_end,
+                )?;
+                let seek_end = in_loops
+                    .last()
+                    .map_or(label_branch_end, |in_loop| in_loop.label_next);
+                emit_seek(
+                    program,
+                    tables,
+                    seek_def,
+                    t_ctx,
+                    seek_cursor_id,
+                    start_reg,
+                    seek_end,
+                    index.is_some(),
+                )?;
+                emit_seek_termination(
+                    program,
+                    tables,
+                    seek_def,
+                    t_ctx,
+                    seek_cursor_id,
+                    start_reg,
+                    label_branch_start,
+                    seek_end,
+                    index.is_some(),
+                )?;
+                if index.is_some() {
+                    program.emit_insn(Insn::IdxRowId {
+                        cursor_id: seek_cursor_id,
+                        dest: rowid_reg,
+                    });
+                } else {
+                    program.emit_insn(Insn::RowId {
+                        cursor_id: seek_cursor_id,
+                        dest: rowid_reg,
+                    });
+                }
+                in_loops
+            }
+            Search::MultiIndexOr { .. } | Search::HashJoin { .. } => {
+                unreachable!("a branch of a multi-index OR only searches the table or its indexes")
+            }
+        };
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: rowid_reg,
+            count: 1,
+            dest_reg: record_reg,
+            index_name: Some(rowset_index_name.clone()),
+        });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id: rowset_cursor_id,
+            record_reg,
+            unpacked_start: None,
+            unpacked_count: None,
+            flags: IdxInsertFlags::new().no_op_duplicate(),
+        });
+        if let Search::Seek { index, seek_def } = branch {
+            let seek_cursor_id = match index {
+                Some(index) => {
+                    program.resolve_cursor_id(&CursorKey::index(table.internal_id, index.clone()))
+                }
+                None => table_cursor_id,
+            };
+            emit_next(
+                program,
+                seek_cursor_id,
+                seek_def.iter_dir == IterationDirection::Backwards,
+                label_branch_start,
+            );
+            emit_in_loops_next(program, &in_loops);
+        }
+        program.preassign_label_to_next_insn(label_branch_end);
+    }
+    Ok(rowset_cursor_id)
+}
+
 /// Open an ephemeral index cursor and build an automatic index on a table.
 /// This is used as a last-resort to avoid a nested full table scan
 /// Returns the cursor id of the ephemeral index cursor.
 fn emit_autoindex(
     program: &mut ProgramBuilder,
+    resolver: &Resolver,
     index: &Arc<Index>,
+    table: &Table,
     table_cursor_id: CursorID,
     index_cursor_id: CursorID,
-    table_has_rowid: bool,
 ) -> Result<CursorID> {
     assert!(index.ephemeral, "Index {} is not ephemeral", index.name);
+    let btree = table.btree();
+    let table_has_rowid = btree.as_ref().is_some_and(|btree| btree.has_rowid);
     let label_ephemeral_build_end = program.allocate_label();
     // Since this typically happens in an inner loop, we only build it once.
     program.emit_insn(Insn::Once {
@@ -1421,7 +2452,7 @@ fn emit_autoindex(
     let label_ephemeral_build_loop_start = program.allocate_label();
     program.emit_insn(Insn::Rewind {
         cursor_id: table_cursor_id,
-        pc_if_empty: label_ephemeral_build_loop_start,
+        pc_if_empty: label_ephemeral_build_end,
     });
     program.preassign_label_to_next_insn(label_ephemeral_build_loop_start);
     // Emit all columns from source table that are needed in the ephemeral index.
@@ -1430,7 +2461,17 @@ fn emit_autoindex(
     let ephemeral_cols_start_reg = program.alloc_registers(num_regs_to_reserve);
     for (i, col) in index.columns.iter().enumerate() {
         let reg = ephemeral_cols_start_reg + i;
-        program.emit_column(table_cursor_id, col.pos_in_table, reg);
+        match &btree {
+            Some(btree) => emit_table_column(
+                program,
+                resolver,
+                btree,
+                table_cursor_id,
+                col.pos_in_table,
+                reg,
+            )?,
+            None => program.emit_column(table_cursor_id, col.pos_in_table, reg),
+        }
     }
     if table_has_rowid {
         program.emit_insn(Insn::RowId {
@@ -1459,3 +2500,91 @@ fn emit_autoindex(
     program.preassign_label_to_next_insn(label_ephemeral_build_end);
     Ok(index_cursor_id)
 }
+
+/// Build the hash table of a hash join from the rows of the table on its build side, once.
+/// Each row holds the key, converted with the affinity of its comparison, followed by the
+/// columns of `index` as they are in the table and by the rowid.
+fn emit_hash_join_build(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    index: &Arc<Index>,
+    keys: &[HashJoinKey],
+    table: &JoinedTable,
+    table_cursor_id: CursorID,
+    hash_cursor_id: CursorID,
+) -> Result<()> {
+    let btree = table
+        .btree()
+        .expect("the build side of a hash join is a b-tree table");
+    let label_build_end = program.allocate_label();
+    // The rows of the table are the same on every iteration of the outer loops.
+    program.emit_insn(Insn::Once {
+        target_pc_when_reentered: label_build_end,
+    });
+    program.emit_insn(Insn::HashOpen {
+        cursor_id: hash_cursor_id,
+        key_len: keys.len(),
+        collations: keys.iter().map(|key| key.collation).collect(),
+        memory_budget: program.optimizer_options().hash_join_memory_budget,
+    });
+    let label_build_loop_start = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: table_cursor_id,
+        pc_if_empty: label_build_end,
+    });
+    program.preassign_label_to_next_insn(label_build_loop_start);
+    let num_regs = keys.len() + index.columns.len() + 1;
+    let key_reg = program.alloc_registers(num_regs);
+    let columns_start_reg = key_reg + keys.len();
+    for (i, col) in index.columns.iter().enumerate() {
+        emit_table_column(
+            program,
+            resolver,
+            &btree,
+            table_cursor_id,
+            col.pos_in_table,
+            columns_start_reg + i,
+        )?;
+    }
+    // The key columns are the first columns of the index.
+    program.emit_insn(Insn::Copy {
+        src_reg: columns_start_reg,
+        dst_reg: key_reg,
+        extra_amount: keys.len() - 1,
+    });
+    emit_hash_join_key_affinity(program, keys, key_reg);
+    program.emit_insn(Insn::RowId {
+        cursor_id: table_cursor_id,
+        dest: columns_start_reg + index.columns.len(),
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: key_reg,
+        count: num_regs,
+        dest_reg: record_reg,
+        index_name: Some(index.name.clone()),
+    });
+    program.emit_insn(Insn::HashInsert {
+        cursor_id: hash_cursor_id,
+        record_reg,
+    });
+    program.emit_insn(Insn::Next {
+        cursor_id: table_cursor_id,
+        pc_if_next: label_build_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_build_end);
+    Ok(())
+}
+
+/// Convert the key of a hash join in the registers starting at `key_reg` with the affinities of
+/// the comparisons of its columns, so that the keys that compare equal are equal in the hash table.
+fn emit_hash_join_key_affinity(program: &mut ProgramBuilder, keys: &[HashJoinKey], key_reg: usize) {
+    if keys.iter().all(|key| key.affinity == Affinity::Blob) {
+        return;
+    }
+    program.emit_insn(Insn::Affinity {
+        start_reg: key_reg,
+        count: NonZeroUsize::new(keys.len()).expect("a hash join has a key"),
+        affinities: keys.iter().map(|key| key.affinity.aff_mask()).collect(),
+    });
+}
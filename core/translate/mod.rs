@@ -9,12 +9,16 @@
 
 pub(crate) mod aggregation;
 pub(crate) mod alter;
+pub(crate) mod analyze;
+pub(crate) mod attach;
 pub(crate) mod collate;
 mod compound_select;
 pub(crate) mod delete;
 pub(crate) mod display;
 pub(crate) mod emitter;
 pub(crate) mod expr;
+pub(crate) mod foreign_key;
+pub(crate) mod generated;
 pub(crate) mod group_by;
 pub(crate) mod index;
 pub(crate) mod insert;
@@ -31,16 +35,24 @@ pub(crate) mod schema;
 pub(crate) mod select;
 pub(crate) mod subquery;
 pub(crate) mod transaction;
+pub(crate) mod trigger;
 pub(crate) mod update;
+pub(crate) mod upsert;
+pub(crate) mod vacuum;
 mod values;
+pub(crate) mod view;
+pub(crate) mod window;
 
 use crate::schema::Schema;
 use crate::storage::pager::Pager;
 use crate::translate::delete::translate_delete;
+use crate::translate::optimizer::OptimizerOptions;
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts, QueryMode};
 use crate::vdbe::Program;
 use crate::{bail_parse_error, Connection, Result, SymbolTable};
 use alter::translate_alter_table;
+use analyze::translate_analyze;
+use attach::{translate_attach, translate_detach};
 use index::{translate_create_index, translate_drop_index};
 use insert::translate_insert;
 use rollback::translate_rollback;
@@ -49,9 +61,14 @@ use select::translate_select;
 use std::rc::Rc;
 use std::sync::Arc;
 use tracing::{instrument, Level};
-use transaction::{translate_tx_begin, translate_tx_commit};
+use transaction::{
+    translate_release, translate_savepoint, translate_tx_begin, translate_tx_commit,
+};
+use trigger::{translate_create_trigger, translate_drop_trigger};
 use turso_sqlite3_parser::ast::{self, Delete, Insert};
 use update::translate_update;
+use vacuum::translate_vacuum;
+use view::{translate_create_view, translate_drop_view};
 
 #[instrument(skip_all, level = Level::INFO)]
 #[allow(clippy::too_many_arguments)]
@@ -62,9 +79,9 @@ pub fn translate(
     connection: Arc<Connection>,
     syms: &SymbolTable,
     query_mode: QueryMode,
-    _input: &str, // TODO: going to be used for CREATE VIEW
+    input: &str,
 ) -> Result<Program> {
-    tracing::trace!("querying {}", _input);
+    tracing::trace!("querying {}", input);
     let change_cnt_on = matches!(
         stmt,
         ast::Stmt::CreateIndex { .. }
@@ -76,6 +93,13 @@ pub fn translate(
     let mut program = ProgramBuilder::new(
         query_mode,
         connection.get_capture_data_changes().clone(),
+        connection.get_recursive_triggers(),
+        OptimizerOptions {
+            automatic_index: connection.get_automatic_index(),
+            hash_join_memory_budget: connection.get_cache_size_in_bytes(),
+        },
+        connection.get_ignore_check_constraints(),
+        connection.get_foreign_keys(),
         // These options will be extended whithin each translate program
         ProgramBuilderOpts {
             num_cursors: 1,
@@ -96,7 +120,7 @@ pub fn translate(
             connection.clone(),
             program,
         )?,
-        stmt => translate_inner(schema, stmt, syms, program)?,
+        stmt => translate_inner(schema, stmt, syms, input, program)?,
     };
 
     // TODO: bring epilogue here when I can sort out what instructions correspond to a Write or a Read transaction
@@ -104,6 +128,37 @@ pub fn translate(
     Ok(program.build(connection, change_cnt_on))
 }
 
+/// Schema changes in attached databases are only supported for CREATE TABLE, and in the temp
+/// database for tables and triggers.
+fn ensure_schema_change_supported(schema: &Schema, stmt: &ast::Stmt) -> Result<()> {
+    let (name, supported_in_temp) = match stmt {
+        ast::Stmt::AlterTable(alter) => (&alter.0, false),
+        ast::Stmt::CreateIndex { idx_name, .. } => (idx_name.as_ref(), false),
+        ast::Stmt::DropIndex { idx_name, .. } => (idx_name, false),
+        ast::Stmt::CreateTrigger(create_trigger) => (&create_trigger.trigger_name, true),
+        ast::Stmt::CreateView { view_name, .. } | ast::Stmt::DropView { view_name, .. } => {
+            (view_name, false)
+        }
+        ast::Stmt::CreateVirtualTable(vtab) => (&vtab.tbl_name, false),
+        ast::Stmt::DropTable { tbl_name, .. } => (tbl_name, true),
+        ast::Stmt::DropTrigger { trigger_name, .. } => (trigger_name, true),
+        _ => return Ok(()),
+    };
+    if let Some(db_name) = &name.db_name {
+        match schema.database_index(&db_name.0)? {
+            0 => {}
+            1 if supported_in_temp => {}
+            1 => bail_parse_error!(
+                "schema changes in the temp database are only supported for tables and triggers"
+            ),
+            _ => bail_parse_error!(
+                "schema changes in attached databases are only supported for CREATE TABLE"
+            ),
+        }
+    }
+    Ok(())
+}
+
 // TODO: for now leaving the return value as a Program. But ideally to support nested parsing of arbitraty
 // statements, we would have to return a program builder instead
 /// Translate SQL statement into bytecode program.
@@ -111,12 +166,16 @@ pub fn translate_inner(
     schema: &Schema,
     stmt: ast::Stmt,
     syms: &SymbolTable,
+    input: &str,
     program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
+    ensure_schema_change_supported(schema, &stmt)?;
     let program = match stmt {
         ast::Stmt::AlterTable(alter) => translate_alter_table(*alter, syms, schema, program)?,
-        ast::Stmt::Analyze(_) => bail_parse_error!("ANALYZE not supported yet"),
-        ast::Stmt::Attach { .. } => bail_parse_error!("ATTACH not supported yet"),
+        ast::Stmt::Analyze(name) => translate_analyze(name.as_ref(), schema, program)?,
+        ast::Stmt::Attach { expr, db_name, key } => {
+            translate_attach(&expr, &db_name, key.as_deref(), schema, syms, program)?
+        }
         ast::Stmt::Begin(tx_type, tx_name) => translate_tx_begin(tx_type, tx_name, program)?,
         ast::Stmt::Commit(tx_name) => translate_tx_commit(tx_name, program)?,
         ast::Stmt::CreateIndex {
@@ -125,13 +184,15 @@ pub fn translate_inner(
             idx_name,
             tbl_name,
             columns,
-            ..
+            where_clause,
         } => translate_create_index(
             (unique, if_not_exists),
             &idx_name.name.0,
             &tbl_name.0,
             &columns,
+            where_clause.as_deref(),
             schema,
+            syms,
             program,
         )?,
         ast::Stmt::CreateTable {
@@ -139,9 +200,35 @@ pub fn translate_inner(
             if_not_exists,
             tbl_name,
             body,
-        } => translate_create_table(tbl_name, temporary, *body, if_not_exists, schema, program)?,
-        ast::Stmt::CreateTrigger { .. } => bail_parse_error!("CREATE TRIGGER not supported yet"),
-        ast::Stmt::CreateView { .. } => bail_parse_error!("CREATE VIEW not supported yet"),
+        } => translate_create_table(
+            tbl_name,
+            temporary,
+            *body,
+            if_not_exists,
+            input,
+            schema,
+            syms,
+            program,
+        )?,
+        ast::Stmt::CreateTrigger(create_trigger) => {
+            translate_create_trigger(*create_trigger, schema, program)?
+        }
+        ast::Stmt::CreateView {
+            temporary,
+            if_not_exists,
+            view_name,
+            columns,
+            select,
+        } => translate_create_view(
+            schema,
+            view_name,
+            columns,
+            *select,
+            temporary,
+            if_not_exists,
+            syms,
+            program,
+        )?,
         ast::Stmt::CreateVirtualTable(vtab) => {
             translate_create_virtual_table(*vtab, schema, syms, program)?
         }
@@ -149,12 +236,21 @@ pub fn translate_inner(
             let Delete {
                 tbl_name,
                 where_clause,
+                returning,
                 limit,
                 ..
             } = *delete;
-            translate_delete(schema, &tbl_name, where_clause, limit, syms, program)?
+            translate_delete(
+                schema,
+                &tbl_name,
+                where_clause,
+                limit,
+                returning,
+                syms,
+                program,
+            )?
         }
-        ast::Stmt::Detach(_) => bail_parse_error!("DETACH not supported yet"),
+        ast::Stmt::Detach(db_name) => translate_detach(&db_name, schema, syms, program)?,
         ast::Stmt::DropIndex {
             if_exists,
             idx_name,
@@ -162,19 +258,25 @@ pub fn translate_inner(
         ast::Stmt::DropTable {
             if_exists,
             tbl_name,
-        } => translate_drop_table(tbl_name, if_exists, schema, program)?,
-        ast::Stmt::DropTrigger { .. } => bail_parse_error!("DROP TRIGGER not supported yet"),
-        ast::Stmt::DropView { .. } => bail_parse_error!("DROP VIEW not supported yet"),
+        } => translate_drop_table(tbl_name, if_exists, schema, syms, program)?,
+        ast::Stmt::DropTrigger {
+            if_exists,
+            trigger_name,
+        } => translate_drop_trigger(&trigger_name, if_exists, schema, program)?,
+        ast::Stmt::DropView {
+            if_exists,
+            view_name,
+        } => translate_drop_view(schema, &view_name, if_exists, program)?,
         ast::Stmt::Pragma(..) => {
             bail_parse_error!("PRAGMA statement cannot be evaluated in a nested context")
         }
         ast::Stmt::Reindex { .. } => bail_parse_error!("REINDEX not supported yet"),
-        ast::Stmt::Release(_) => bail_parse_error!("RELEASE not supported yet"),
+        ast::Stmt::Release(name) => translate_release(name, program)?,
         ast::Stmt::Rollback {
             tx_name,
             savepoint_name,
         } => translate_rollback(schema, syms, program, tx_name, savepoint_name)?,
-        ast::Stmt::Savepoint(_) => bail_parse_error!("SAVEPOINT not supported yet"),
+        ast::Stmt::Savepoint(name) => translate_savepoint(name, program)?,
         ast::Stmt::Select(select) => {
             translate_select(
                 schema,
@@ -186,7 +288,9 @@ pub fn translate_inner(
             .program
         }
         ast::Stmt::Update(mut update) => translate_update(schema, &mut update, syms, program)?,
-        ast::Stmt::Vacuum(_, _) => bail_parse_error!("VACUUM not supported yet"),
+        ast::Stmt::Vacuum(name, into) => {
+            translate_vacuum(name.as_ref(), into.as_deref(), schema, syms, program)?
+        }
         ast::Stmt::Insert(insert) => {
             let Insert {
                 with,
@@ -9,8 +9,14 @@ use crate::{
 };
 
 use super::{
-    constraints::{usable_constraints_for_join_order, ConstraintRef, TableConstraints},
-    cost::{estimate_cost_for_scan_or_seek, Cost, IndexInfo},
+    constraints::{
+        usable_constraints_for_join_order, ConstraintRef, MultiIndexOrCandidate, TableConstraints,
+    },
+    cost::{
+        estimate_cost_for_automatic_index, estimate_cost_for_hash_join,
+        estimate_cost_for_multi_index_or, estimate_cost_for_scan_or_seek, Cost, IndexInfo,
+    },
+    ephemeral_index_build,
     order::OrderTarget,
 };
 
@@ -29,23 +35,64 @@ pub struct AccessMethod<'a> {
     /// An empty list of constraint refs means a scan (full table or index);
     /// a non-empty list means a search.
     pub constraint_refs: &'a [ConstraintRef],
+    /// The lookups of a multi-index OR, if the table is accessed with one instead.
+    pub multi_index_or: Option<MultiIndexOrAccess<'a>>,
+    /// Whether the rows are looked up in the hash table of a hash join, built from the table with
+    /// the constraints as its key, instead of being searched in the table or in an index.
+    pub hash_join: bool,
+}
+
+#[derive(Debug, Clone)]
+/// Represents the lookups of the rows matching an OR term, one per operand, see [MultiIndexOrCandidate].
+pub struct MultiIndexOrAccess<'a> {
+    pub candidate: &'a MultiIndexOrCandidate,
+    /// The search of each branch, using the constraints of the branch.
+    pub branches: Vec<AccessMethod<'a>>,
 }
 
 impl AccessMethod<'_> {
     pub fn is_scan(&self) -> bool {
-        self.constraint_refs.is_empty()
+        self.constraint_refs.is_empty() && self.multi_index_or.is_none()
     }
 
-    pub fn new_table_scan(input_cardinality: f64, iter_dir: IterationDirection) -> Self {
+    pub fn new_table_scan(
+        input_cardinality: f64,
+        row_count: f64,
+        iter_dir: IterationDirection,
+    ) -> Self {
         Self {
-            cost: estimate_cost_for_scan_or_seek(None, &[], &[], input_cardinality),
+            cost: estimate_cost_for_scan_or_seek(
+                None,
+                &[],
+                &[],
+                input_cardinality,
+                row_count,
+                None,
+            ),
             iter_dir,
             index: None,
             constraint_refs: &[],
+            multi_index_or: None,
+            hash_join: false,
         }
     }
 }
 
+fn index_info(rhs_table: &JoinedTable, index: Option<&Arc<Index>>) -> IndexInfo {
+    match index {
+        Some(index) => IndexInfo {
+            unique: index.unique,
+            covering: rhs_table.index_is_covering(index),
+            column_count: index.columns.len(),
+        },
+        None => IndexInfo {
+            unique: true, // rowids are always unique
+            covering: false,
+            column_count: 1,
+        },
+    }
+}
+
 /// Return the best [AccessMethod] for a given join order.
 pub fn find_best_access_method_for_join_order<'a>(
     rhs_table: &JoinedTable,
@@ -55,24 +102,16 @@ pub fn find_best_access_method_for_join_order<'a>(
     input_cardinality: f64,
 ) -> Result<AccessMethod<'a>> {
     let table_no = join_order.last().unwrap().table_id;
-    let mut best_access_method =
-        AccessMethod::new_table_scan(input_cardinality, IterationDirection::Forwards);
+    let mut best_access_method = AccessMethod::new_table_scan(
+        input_cardinality,
+        rhs_constraints.row_count,
+        IterationDirection::Forwards,
+    );
     let rowid_column_idx = rhs_table.columns().iter().position(|c| c.is_rowid_alias);
 
     // Estimate cost for each candidate index (including the rowid index) and replace best_access_method if the cost is lower.
     for candidate in rhs_constraints.candidates.iter() {
-        let index_info = match candidate.index.as_ref() {
-            Some(index) => IndexInfo {
-                unique: index.unique,
-                covering: rhs_table.index_is_covering(index),
-                column_count: index.columns.len(),
-            },
-            None => IndexInfo {
-                unique: true, // rowids are always unique
-                covering: false,
-                column_count: 1,
-            },
-        };
+        let index_info = index_info(rhs_table, candidate.index.as_ref());
         let usable_constraint_refs = usable_constraints_for_join_order(
             &rhs_constraints.constraints,
             &candidate.refs,
@@ -83,6 +122,8 @@ pub fn find_best_access_method_for_join_order<'a>(
             &rhs_constraints.constraints,
             usable_constraint_refs,
             input_cardinality,
+            rhs_constraints.row_count,
+            candidate.stats.as_deref(),
         );
 
         // All other things being equal, prefer an access method that satisfies the order target.
@@ -140,9 +181,145 @@ pub fn find_best_access_method_for_join_order<'a>(
                 index: candidate.index.clone(),
                 iter_dir,
                 constraint_refs: usable_constraint_refs,
+                multi_index_or: None,
+                hash_join: false,
             };
         }
     }
 
+    // The lookups of a multi-index OR are only worth it when no index or rowid seek can use the
+    // constraints of the table on their own.
+    if best_access_method.constraint_refs.is_empty() {
+        for candidate in rhs_constraints.multi_index_or_candidates.iter() {
+            let Some(branches) = candidate
+                .branches
+                .iter()
+                .map(|branch| {
+                    find_best_search_for_join_order(
+                        rhs_table,
+                        &branch.constraints,
+                        join_order,
+                        input_cardinality,
+                    )
+                })
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let cost = estimate_cost_for_multi_index_or(branches.iter().map(|branch| branch.cost));
+            if cost < best_access_method.cost {
+                best_access_method = AccessMethod {
+                    cost,
+                    index: None,
+                    iter_dir: IterationDirection::Forwards,
+                    constraint_refs: &[],
+                    multi_index_or: Some(MultiIndexOrAccess {
+                        candidate,
+                        branches,
+                    }),
+                    hash_join: false,
+                };
+            }
+        }
+    }
+
+    // An automatic index or a hash table is only worth building for a table that is looped over
+    // more than once, so never for the first table of the join order. They compete on cost with
+    // the searches above: a range seek still reads a good part of the table for each outer row.
+    if join_order.len() > 1 {
+        let usable_constraint_refs = usable_constraints_for_join_order(
+            &rhs_constraints.constraints,
+            &rhs_constraints.hash_join_key_refs,
+            join_order,
+        );
+        if !usable_constraint_refs.is_empty() {
+            let cost = estimate_cost_for_hash_join(
+                &rhs_constraints.constraints,
+                usable_constraint_refs,
+                input_cardinality,
+                rhs_constraints.row_count,
+                rhs_constraints.hash_join_memory_budget,
+            );
+            if cost < best_access_method.cost {
+                // The rows matching a key are found in the order of their rowids.
+                best_access_method = AccessMethod {
+                    cost,
+                    index: None,
+                    iter_dir: IterationDirection::Forwards,
+                    constraint_refs: usable_constraint_refs,
+                    multi_index_or: None,
+                    hash_join: true,
+                };
+            }
+        }
+        let usable_constraint_refs = usable_constraints_for_join_order(
+            &rhs_constraints.constraints,
+            &rhs_constraints.automatic_index_refs,
+            join_order,
+        );
+        if !usable_constraint_refs.is_empty() {
+            let cost = estimate_cost_for_automatic_index(
+                &rhs_constraints.constraints,
+                usable_constraint_refs,
+                input_cardinality,
+                rhs_constraints.row_count,
+            );
+            if cost < best_access_method.cost {
+                best_access_method = AccessMethod {
+                    cost,
+                    index: Some(Arc::new(ephemeral_index_build(
+                        rhs_table,
+                        &rhs_constraints.constraints,
+                        usable_constraint_refs,
+                    ))),
+                    iter_dir: IterationDirection::Forwards,
+                    constraint_refs: usable_constraint_refs,
+                    multi_index_or: None,
+                    hash_join: false,
+                };
+            }
+        }
+    }
+
     Ok(best_access_method)
 }
+
+/// Return the cheapest search of a table for a given join order, or None if no constraint can be
+/// used for a search.
+fn find_best_search_for_join_order<'a>(
+    rhs_table: &JoinedTable,
+    rhs_constraints: &'a TableConstraints,
+    join_order: &[JoinOrderMember],
+    input_cardinality: f64,
+) -> Option<AccessMethod<'a>> {
+    let mut best_search: Option<AccessMethod<'a>> = None;
+    for candidate in rhs_constraints.candidates.iter() {
+        let usable_constraint_refs = usable_constraints_for_join_order(
+            &rhs_constraints.constraints,
+            &candidate.refs,
+            join_order,
+        );
+        if usable_constraint_refs.is_empty() {
+            continue;
+        }
+        let cost = estimate_cost_for_scan_or_seek(
+            Some(index_info(rhs_table, candidate.index.as_ref())),
+            &rhs_constraints.constraints,
+            usable_constraint_refs,
+            input_cardinality,
+            rhs_constraints.row_count,
+            candidate.stats.as_deref(),
+        );
+        if best_search.as_ref().is_none_or(|best| cost < best.cost) {
+            best_search = Some(AccessMethod {
+                cost,
+                iter_dir: IterationDirection::Forwards,
+                index: candidate.index.clone(),
+                constraint_refs: usable_constraint_refs,
+                multi_index_or: None,
+                hash_join: false,
+            });
+        }
+    }
+    best_search
+}
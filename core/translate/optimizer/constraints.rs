@@ -1,17 +1,19 @@
-use std::{cmp::Ordering, collections::HashMap, sync::Arc};
+use std::{cell::Cell, cmp::Ordering, collections::HashMap, sync::Arc};
 
 use crate::{
-    schema::{Column, Index},
+    schema::{Column, Index, TableStats, EXPR_INDEX_SENTINEL},
     translate::{
-        expr::as_binary_components,
-        plan::{JoinOrderMember, TableReferences, WhereTerm},
-        planner::{table_mask_from_expr, TableMask},
+        collate::CollationSeq,
+        expr::{as_binary_components, comparison_collation, walk_expr_mut},
+        plan::{JoinOrderMember, JoinedTable, TableReferences, WhereTerm},
+        planner::{break_predicate_at_and_boundaries, table_mask_from_expr, TableMask},
     },
+    util::{exprs_are_equivalent, normalize_ident},
     Result,
 };
-use turso_sqlite3_parser::ast::{self, SortOrder, TableInternalId};
+use turso_sqlite3_parser::ast::{self, SortOrder};
 
-use super::cost::ESTIMATED_HARDCODED_ROWS_PER_TABLE;
+use super::{cost::ESTIMATED_HARDCODED_ROWS_PER_TABLE, OptimizerOptions};
 
 /// Represents a single condition derived from a `WHERE` clause term
 /// that constrains a specific column of a table.
@@ -38,7 +40,8 @@ pub struct Constraint {
     pub where_clause_pos: (usize, BinaryExprSide),
     /// The comparison operator (e.g., `=`, `>`, `<`) used in the constraint.
     pub operator: ast::Operator,
-    /// The zero-based index of the constrained column within the table's schema.
+    /// The zero-based index of the constrained column within the table's schema,
+    /// or [EXPR_INDEX_SENTINEL] if the constraint is on an indexed expression.
     pub table_col_pos: usize,
     /// A bitmask representing the set of tables that appear on the *constraining* side
     /// of the comparison expression. For example, in SELECT * FROM t1,t2,t3 WHERE t1.x = t2.x + t3.x,
@@ -57,10 +60,14 @@ pub enum BinaryExprSide {
 }
 
 impl Constraint {
-    /// Get the constraining expression, e.g. '2+3' from 't.x = 2+3'
+    /// Get the constraining expression, e.g. '2+3' from 't.x = 2+3'.
+    /// For an IN list, this is the whole `t.x IN (...)` term, see [crate::translate::plan::SeekDef::key].
     pub fn get_constraining_expr(&self, where_clause: &[WhereTerm]) -> ast::Expr {
         let (idx, side) = self.where_clause_pos;
         let where_term = &where_clause[idx];
+        if as_in_list_components(&where_term.expr).is_some() {
+            return where_term.expr.clone();
+        }
         let Ok(Some((lhs, _, rhs))) = as_binary_components(&where_term.expr) else {
             panic!("Expected a valid binary expression");
         };
@@ -70,6 +77,23 @@ impl Constraint {
             rhs.clone()
         }
     }
+
+    /// Get the constrained expression, e.g. 'lower(t.x)' from 'lower(t.x) = 2+3'
+    pub fn get_constrained_expr(&self, where_clause: &[WhereTerm]) -> ast::Expr {
+        let (idx, side) = self.where_clause_pos;
+        let where_term = &where_clause[idx];
+        if let Some((lhs, _)) = as_in_list_components(&where_term.expr) {
+            return lhs.clone();
+        }
+        let Ok(Some((lhs, _, rhs))) = as_binary_components(&where_term.expr) else {
+            panic!("Expected a valid binary expression");
+        };
+        if side == BinaryExprSide::Lhs {
+            rhs.clone()
+        } else {
+            lhs.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -128,17 +152,50 @@ pub struct ConstraintUseCandidate {
     pub index: Option<Arc<Index>>,
     /// References to the constraints that may be used as an access path for the index.
     pub refs: Vec<ConstraintRef>,
+    /// The statistics of the index gathered by ANALYZE, if any. See [TableStats::indexes].
+    pub stats: Option<Vec<u64>>,
 }
 
 #[derive(Debug)]
 /// A collection of [Constraint]s and their potential [ConstraintUseCandidate]s for a given table.
 pub struct TableConstraints {
-    /// The internal ID of the [TableReference] that these constraints are for.
-    pub table_id: TableInternalId,
     /// The constraints for the table, i.e. any [WhereTerm]s that reference columns from this table.
     pub constraints: Vec<Constraint>,
     /// Candidates for indexes that may use the constraints to perform a lookup.
     pub candidates: Vec<ConstraintUseCandidate>,
+    /// References to the equality constraints that may be the key of an automatic index, i.e. an
+    /// ephemeral index built on the table when it is joined, one per column, in the order of the
+    /// columns of the index. Empty if no automatic index can be built on the table.
+    pub automatic_index_refs: Vec<ConstraintRef>,
+    /// References to the equality constraints that may be the key of the hash table of a hash
+    /// join on the table, like [Self::automatic_index_refs] but without IN lists.
+    pub hash_join_key_refs: Vec<ConstraintRef>,
+    /// The memory the hash table of a hash join on the table may use, see
+    /// [OptimizerOptions::hash_join_memory_budget].
+    pub hash_join_memory_budget: usize,
+    /// The OR terms whose operands may each be looked up with an index, see [MultiIndexOrCandidate].
+    pub multi_index_or_candidates: Vec<MultiIndexOrCandidate>,
+    /// The estimated number of rows of the table, measured by ANALYZE if the table was analyzed.
+    pub row_count: f64,
+}
+
+#[derive(Debug)]
+/// An OR term of the `WHERE` clause whose operands may each be looked up with an index of a table,
+/// e.g. `a = 1 OR b = 2` with an index on `a` and an index on `b`. The rows matching the term are
+/// the union of the rows found by the lookups.
+pub struct MultiIndexOrCandidate {
+    /// One branch per operand of the OR term.
+    pub branches: Vec<MultiIndexOrBranch>,
+}
+
+#[derive(Debug)]
+/// An operand of a [MultiIndexOrCandidate].
+pub struct MultiIndexOrBranch {
+    /// The terms of the operand, split at AND boundaries. They are the `WHERE` clause that the
+    /// constraints of the branch refer to.
+    pub where_clause: Vec<WhereTerm>,
+    /// The constraints of the table in the operand, and the index candidates that may use them.
+    pub constraints: TableConstraints,
 }
 
 /// In lieu of statistics, we estimate that an equality filter will reduce the output set to 1% of its size.
@@ -148,14 +205,21 @@ const SELECTIVITY_RANGE: f64 = 0.4;
 /// In lieu of statistics, we estimate that other filters will reduce the output set to 90% of its size.
 const SELECTIVITY_OTHER: f64 = 0.9;
 
-const SELECTIVITY_UNIQUE_EQUALITY: f64 = 1.0 / ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64;
-
 /// Estimate the selectivity of a constraint based on the operator and the column type.
-fn estimate_selectivity(column: &Column, op: ast::Operator) -> f64 {
+/// `column` is None for a constraint on an indexed expression. `rows_per_value` is the average
+/// number of rows sharing a value of the column, if ANALYZE measured it.
+fn estimate_selectivity(
+    column: Option<&Column>,
+    op: ast::Operator,
+    row_count: f64,
+    rows_per_value: Option<u64>,
+) -> f64 {
     match op {
         ast::Operator::Equals => {
-            if column.is_rowid_alias || column.primary_key {
-                SELECTIVITY_UNIQUE_EQUALITY
+            if column.is_some_and(|column| column.is_rowid_alias || column.primary_key) {
+                1.0 / row_count
+            } else if let Some(rows_per_value) = rows_per_value {
+                (rows_per_value as f64 / row_count).min(1.0)
             } else {
                 SELECTIVITY_EQ
             }
@@ -168,206 +232,731 @@ fn estimate_selectivity(column: &Column, op: ast::Operator) -> f64 {
     }
 }
 
+/// Indexes of `table_reference` that may be used for constraints.
+/// `available_indexes` belong to the main database, so tables of attached databases are
+/// always scanned. A partial index is only usable if the `WHERE` clause of the query implies
+/// its own, since the rows it leaves out would otherwise be missed.
+fn table_indexes(
+    available_indexes: &HashMap<String, Vec<Arc<Index>>>,
+    table_references: &TableReferences,
+    table_reference: &JoinedTable,
+    where_clause: &[WhereTerm],
+) -> Result<Vec<Arc<Index>>> {
+    // The PRIMARY KEY of a WITHOUT ROWID table is the key of the table b-tree itself
+    let clustered_index = table_reference
+        .btree()
+        .and_then(|table| table.clustered_index());
+    if table_reference.database_id != 0 {
+        return Ok(clustered_index.into_iter().collect());
+    }
+    let Some(indexes) = available_indexes.get(table_reference.table.get_name()) else {
+        return Ok(clustered_index.into_iter().collect());
+    };
+    let mut usable = Vec::with_capacity(indexes.len() + 1);
+    usable.extend(clustered_index);
+    for index in indexes {
+        if let Some(index_where_clause) = &index.where_clause {
+            let index_where_clause = bind_index_expr(index_where_clause, table_reference)?;
+            if !where_clause_implies(
+                where_clause,
+                table_references,
+                table_reference,
+                index_where_clause,
+            )? {
+                continue;
+            }
+        }
+        usable.push(index.clone());
+    }
+    Ok(usable)
+}
+
+/// Binds the column references of an indexed expression or partial index `WHERE` clause to
+/// `table_reference`, so that it can be compared with the terms of the query.
+fn bind_index_expr(expr: &ast::Expr, table_reference: &JoinedTable) -> Result<ast::Expr> {
+    let mut expr = expr.clone();
+    walk_expr_mut(&mut expr, &mut |expr: &mut ast::Expr| -> Result<()> {
+        if let ast::Expr::Id(name) = expr {
+            let name = normalize_ident(&name.0);
+            if let Some((column, col)) = table_reference
+                .columns()
+                .iter()
+                .enumerate()
+                .find(|(_, col)| col.name.as_ref() == Some(&name))
+            {
+                *expr = ast::Expr::Column {
+                    database: None,
+                    table: table_reference.internal_id,
+                    column,
+                    is_rowid_alias: col.is_rowid_alias,
+                };
+            }
+        }
+        Ok(())
+    })?;
+    Ok(expr)
+}
+
+/// Whether `term` can be applied to the rows of `table_reference` as they are read, e.g. to look them up.
+fn term_applies_to_table(
+    term: &WhereTerm,
+    table_references: &TableReferences,
+    table_reference: &JoinedTable,
+) -> bool {
+    // Constraints originating from an OUTER JOIN must always be evaluated in that join's RHS table's loop,
+    // regardless of which tables the constraint references.
+    if let Some(outer_join_tbl) = term.from_outer_join {
+        if outer_join_tbl != table_reference.internal_id {
+            return false;
+        }
+    }
+    // Constraints that come after a RIGHT JOIN must not discard the rows of the tables up to its RHS table,
+    // which are NULL-extended or emitted on their own when they don't match.
+    let Some(right_join_tbl) = term.after_right_join else {
+        return true;
+    };
+    let position = |internal_id| {
+        table_references
+            .joined_tables()
+            .iter()
+            .position(|t| t.internal_id == internal_id)
+    };
+    position(table_reference.internal_id) > position(right_join_tbl)
+}
+
+/// Whether every row of `table_reference` satisfying `where_clause` also satisfies `predicate`.
+/// Only simple cases are recognized: each AND-term of `predicate` must either appear as a
+/// term of `where_clause`, or be an `x IS NOT NULL` implied by a comparison of `x`.
+fn where_clause_implies(
+    where_clause: &[WhereTerm],
+    table_references: &TableReferences,
+    table_reference: &JoinedTable,
+    predicate: ast::Expr,
+) -> Result<bool> {
+    let (is_outer, is_right) = table_reference
+        .join_info
+        .as_ref()
+        .map_or((false, false), |join_info| {
+            (join_info.outer, join_info.right)
+        });
+    // All the rows of the right-hand-side table of a RIGHT JOIN are read to find those that
+    // matched no row, so no term restricts them.
+    if is_right {
+        return Ok(false);
+    }
+    // The terms in the WHERE clause of a query are evaluated after the rows of an outer joined
+    // table are NULL-extended, so only the terms of its ON clause restrict the table itself.
+    let terms = where_clause
+        .iter()
+        .filter(|term| {
+            if is_outer {
+                term.from_outer_join == Some(table_reference.internal_id)
+            } else {
+                term.from_outer_join.is_none()
+            }
+        })
+        .filter(|term| term_applies_to_table(term, table_references, table_reference))
+        .collect::<Vec<_>>();
+    let mut predicates = Vec::new();
+    break_predicate_at_and_boundaries(predicate, &mut predicates);
+    for predicate in predicates {
+        let not_null_operand = match &predicate {
+            ast::Expr::NotNull(expr) => Some(expr.as_ref()),
+            ast::Expr::Binary(lhs, ast::Operator::IsNot, rhs)
+                if matches!(rhs.as_ref(), ast::Expr::Literal(ast::Literal::Null)) =>
+            {
+                Some(lhs.as_ref())
+            }
+            _ => None,
+        };
+        let mut implied = false;
+        for term in terms.iter() {
+            if exprs_are_equivalent(&term.expr, &predicate) {
+                implied = true;
+                break;
+            }
+            let Some(operand) = not_null_operand else {
+                continue;
+            };
+            if let Some((lhs, _, rhs)) = as_binary_components(&term.expr)? {
+                if exprs_are_equivalent(lhs, operand) || exprs_are_equivalent(rhs, operand) {
+                    implied = true;
+                    break;
+                }
+            }
+        }
+        if !implied {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
 /// Precompute all potentially usable [Constraints] from a WHERE clause.
 /// The resulting list of [TableConstraints] is then used to evaluate the best access methods for various join orders.
 pub fn constraints_from_where_clause(
     where_clause: &[WhereTerm],
     table_references: &TableReferences,
     available_indexes: &HashMap<String, Vec<Arc<Index>>>,
+    available_stats: &HashMap<String, TableStats>,
 ) -> Result<Vec<TableConstraints>> {
     let mut constraints = Vec::new();
 
     // For each table, collect all the Constraints and all potential index candidates that may use them.
     for table_reference in table_references.joined_tables() {
-        let rowid_alias_column = table_reference
-            .columns()
+        let mut cs = table_constraints(
+            where_clause,
+            table_references,
+            table_reference,
+            available_indexes,
+            available_stats,
+        )?;
+        cs.multi_index_or_candidates = multi_index_or_candidates(
+            where_clause,
+            table_references,
+            table_reference,
+            available_indexes,
+            available_stats,
+        )?;
+        constraints.push(cs);
+    }
+
+    Ok(constraints)
+}
+
+/// Collect the [Constraint]s of `table_reference` in a WHERE clause, and the index candidates that may use them.
+fn table_constraints(
+    where_clause: &[WhereTerm],
+    table_references: &TableReferences,
+    table_reference: &JoinedTable,
+    available_indexes: &HashMap<String, Vec<Arc<Index>>>,
+    available_stats: &HashMap<String, TableStats>,
+) -> Result<TableConstraints> {
+    let rowid_alias_column = table_reference
+        .columns()
+        .iter()
+        .position(|c| c.is_rowid_alias);
+
+    let indexes = table_indexes(
+        available_indexes,
+        table_references,
+        table_reference,
+        where_clause,
+    )?;
+    // The indexed expressions of the usable indexes, bound to the table.
+    let mut index_exprs = Vec::new();
+    for index in indexes.iter() {
+        for (index_col_pos, index_column) in index.columns.iter().enumerate() {
+            if let Some(expr) = &index_column.expr {
+                index_exprs.push((
+                    index.clone(),
+                    index_col_pos,
+                    bind_index_expr(expr, table_reference)?,
+                ));
+            }
+        }
+    }
+    let is_indexed_expr = |expr: &ast::Expr| {
+        index_exprs
             .iter()
-            .position(|c| c.is_rowid_alias);
-
-        let mut cs = TableConstraints {
-            table_id: table_reference.internal_id,
-            constraints: Vec::new(),
-            candidates: available_indexes
-                .get(table_reference.table.get_name())
-                .map_or(Vec::new(), |indexes| {
-                    indexes
-                        .iter()
-                        .map(|index| ConstraintUseCandidate {
-                            index: Some(index.clone()),
-                            refs: Vec::new(),
-                        })
-                        .collect()
-                }),
-        };
-        // Add a candidate for the rowid index, which is always available when the table has a rowid alias.
-        cs.candidates.push(ConstraintUseCandidate {
-            index: None,
-            refs: Vec::new(),
+            .any(|(_, _, index_expr)| exprs_are_equivalent(index_expr, expr))
+    };
+    let indexed_expr_position = |index: &Arc<Index>, expr: &ast::Expr| {
+        index_exprs
+            .iter()
+            .find(|(expr_index, _, index_expr)| {
+                Arc::ptr_eq(index, expr_index) && exprs_are_equivalent(index_expr, expr)
+            })
+            .map(|(_, index_col_pos, _)| *index_col_pos)
+    };
+
+    // Like indexes, statistics are only used for the tables of the main database.
+    let table = table_reference.btree();
+    let stats = table.as_ref().and_then(|table| {
+        available_stats
+            .get(&normalize_ident(&table.name))
+            .filter(|_| table_reference.database_id == 0)
+    });
+    let index_stats = |index: &Index| {
+        stats
+            .zip(table.as_ref())
+            .and_then(|(stats, table)| stats.index(table, index))
+    };
+    let row_count = stats
+        .and_then(|stats| stats.estimated_row_count())
+        .map_or(ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64, |row_count| {
+            row_count.max(1) as f64
         });
+    // The average number of rows sharing a value of a column, from an analyzed index the
+    // column leads. A partial index only measures the rows it covers.
+    let rows_per_value = |table_col_pos: usize| {
+        indexes
+            .iter()
+            .filter(|index| {
+                index.where_clause.is_none() && index.columns[0].pos_in_table == table_col_pos
+            })
+            .find_map(|index| index_stats(index).and_then(|stats| stats.get(1).copied()))
+    };
 
-        for (i, term) in where_clause.iter().enumerate() {
-            let Some((lhs, operator, rhs)) = as_binary_components(&term.expr)? else {
-                continue;
-            };
+    let mut cs = TableConstraints {
+        constraints: Vec::new(),
+        candidates: indexes
+            .iter()
+            .map(|index| ConstraintUseCandidate {
+                index: Some(index.clone()),
+                refs: Vec::new(),
+                stats: index_stats(index).map(<[u64]>::to_vec),
+            })
+            .collect(),
+        automatic_index_refs: Vec::new(),
+        hash_join_key_refs: Vec::new(),
+        hash_join_memory_budget: OptimizerOptions::default().hash_join_memory_budget,
+        multi_index_or_candidates: Vec::new(),
+        row_count,
+    };
+    // Add a candidate for the rowid index, which is always available when the table has a rowid alias.
+    cs.candidates.push(ConstraintUseCandidate {
+        index: None,
+        refs: Vec::new(),
+        stats: None,
+    });
 
-            // Constraints originating from a LEFT JOIN must always be evaluated in that join's RHS table's loop,
-            // regardless of which tables the constraint references.
-            if let Some(outer_join_tbl) = term.from_outer_join {
-                if outer_join_tbl != table_reference.internal_id {
-                    continue;
-                }
+    for (i, term) in where_clause.iter().enumerate() {
+        if !term_applies_to_table(term, table_references, table_reference) {
+            continue;
+        }
+
+        // An IN list is an equality that is looked up once per value of the list.
+        if let Some((lhs, values)) = as_in_list_components(&term.expr) {
+            let mut lhs_mask = TableMask::new();
+            for value in values {
+                lhs_mask |= table_mask_from_expr(value, table_references)?;
             }
+            let constrained_column = match lhs {
+                ast::Expr::Column { table, column, .. }
+                    if *table == table_reference.internal_id =>
+                {
+                    Some(*column)
+                }
+                ast::Expr::RowId { table, .. } if *table == table_reference.internal_id => {
+                    rowid_alias_column
+                }
+                _ => None,
+            };
+            let (table_col_pos, selectivity) = match constrained_column {
+                Some(column) => (
+                    column,
+                    estimate_selectivity(
+                        Some(&table_reference.table.columns()[column]),
+                        ast::Operator::Equals,
+                        row_count,
+                        rows_per_value(column),
+                    ),
+                ),
+                None if is_indexed_expr(lhs) => (
+                    EXPR_INDEX_SENTINEL,
+                    estimate_selectivity(None, ast::Operator::Equals, row_count, None),
+                ),
+                None => continue,
+            };
+            cs.constraints.push(Constraint {
+                where_clause_pos: (i, BinaryExprSide::Rhs),
+                operator: ast::Operator::Equals,
+                table_col_pos,
+                lhs_mask,
+                selectivity: (selectivity * values.len() as f64).min(1.0),
+            });
+            continue;
+        }
+
+        let Some((lhs, operator, rhs)) = as_binary_components(&term.expr)? else {
+            continue;
+        };
 
-            // If either the LHS or RHS of the constraint is a column from the table, add the constraint.
-            match lhs {
-                ast::Expr::Column { table, column, .. } => {
-                    if *table == table_reference.internal_id {
-                        let table_column = &table_reference.table.columns()[*column];
-                        cs.constraints.push(Constraint {
-                            where_clause_pos: (i, BinaryExprSide::Rhs),
+        // If either the LHS or RHS of the constraint is a column from the table, add the constraint.
+        match lhs {
+            ast::Expr::Column { table, column, .. } => {
+                if *table == table_reference.internal_id {
+                    let table_column = &table_reference.table.columns()[*column];
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Rhs),
+                        operator,
+                        table_col_pos: *column,
+                        lhs_mask: table_mask_from_expr(rhs, table_references)?,
+                        selectivity: estimate_selectivity(
+                            Some(table_column),
                             operator,
-                            table_col_pos: *column,
-                            lhs_mask: table_mask_from_expr(rhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
-                        });
-                    }
+                            row_count,
+                            rows_per_value(*column),
+                        ),
+                    });
                 }
-                ast::Expr::RowId { table, .. } => {
-                    // A rowid alias column must exist for the 'rowid' keyword to be considered a valid reference.
-                    // This should be a parse error at an earlier stage of the query compilation, but nevertheless,
-                    // we check it here.
-                    if *table == table_reference.internal_id && rowid_alias_column.is_some() {
-                        let table_column =
-                            &table_reference.table.columns()[rowid_alias_column.unwrap()];
-                        cs.constraints.push(Constraint {
-                            where_clause_pos: (i, BinaryExprSide::Rhs),
+            }
+            ast::Expr::RowId { table, .. } => {
+                // A rowid alias column must exist for the 'rowid' keyword to be considered a valid reference.
+                // This should be a parse error at an earlier stage of the query compilation, but nevertheless,
+                // we check it here.
+                if *table == table_reference.internal_id && rowid_alias_column.is_some() {
+                    let table_column =
+                        &table_reference.table.columns()[rowid_alias_column.unwrap()];
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Rhs),
+                        operator,
+                        table_col_pos: rowid_alias_column.unwrap(),
+                        lhs_mask: table_mask_from_expr(rhs, table_references)?,
+                        selectivity: estimate_selectivity(
+                            Some(table_column),
                             operator,
-                            table_col_pos: rowid_alias_column.unwrap(),
-                            lhs_mask: table_mask_from_expr(rhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
-                        });
-                    }
+                            row_count,
+                            None,
+                        ),
+                    });
                 }
-                _ => {}
-            };
-            match rhs {
-                ast::Expr::Column { table, column, .. } => {
-                    if *table == table_reference.internal_id {
-                        let table_column = &table_reference.table.columns()[*column];
-                        cs.constraints.push(Constraint {
-                            where_clause_pos: (i, BinaryExprSide::Lhs),
-                            operator: opposite_cmp_op(operator),
-                            table_col_pos: *column,
-                            lhs_mask: table_mask_from_expr(lhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
-                        });
-                    }
+            }
+            lhs if is_indexed_expr(lhs) => {
+                cs.constraints.push(Constraint {
+                    where_clause_pos: (i, BinaryExprSide::Rhs),
+                    operator,
+                    table_col_pos: EXPR_INDEX_SENTINEL,
+                    lhs_mask: table_mask_from_expr(rhs, table_references)?,
+                    selectivity: estimate_selectivity(None, operator, row_count, None),
+                });
+            }
+            _ => {}
+        };
+        match rhs {
+            ast::Expr::Column { table, column, .. } => {
+                if *table == table_reference.internal_id {
+                    let table_column = &table_reference.table.columns()[*column];
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Lhs),
+                        operator: opposite_cmp_op(operator),
+                        table_col_pos: *column,
+                        lhs_mask: table_mask_from_expr(lhs, table_references)?,
+                        selectivity: estimate_selectivity(
+                            Some(table_column),
+                            operator,
+                            row_count,
+                            rows_per_value(*column),
+                        ),
+                    });
                 }
-                ast::Expr::RowId { table, .. } => {
-                    if *table == table_reference.internal_id && rowid_alias_column.is_some() {
-                        let table_column =
-                            &table_reference.table.columns()[rowid_alias_column.unwrap()];
-                        cs.constraints.push(Constraint {
-                            where_clause_pos: (i, BinaryExprSide::Lhs),
-                            operator: opposite_cmp_op(operator),
-                            table_col_pos: rowid_alias_column.unwrap(),
-                            lhs_mask: table_mask_from_expr(lhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
-                        });
-                    }
+            }
+            ast::Expr::RowId { table, .. } => {
+                if *table == table_reference.internal_id && rowid_alias_column.is_some() {
+                    let table_column =
+                        &table_reference.table.columns()[rowid_alias_column.unwrap()];
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Lhs),
+                        operator: opposite_cmp_op(operator),
+                        table_col_pos: rowid_alias_column.unwrap(),
+                        lhs_mask: table_mask_from_expr(lhs, table_references)?,
+                        selectivity: estimate_selectivity(
+                            Some(table_column),
+                            operator,
+                            row_count,
+                            None,
+                        ),
+                    });
                 }
-                _ => {}
-            };
-        }
-        // sort equalities first so that index keys will be properly constructed.
-        // see e.g.: https://www.solarwinds.com/blog/the-left-prefix-index-rule
-        cs.constraints.sort_by(|a, b| {
-            if a.operator == ast::Operator::Equals {
-                Ordering::Less
-            } else if b.operator == ast::Operator::Equals {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
             }
-        });
+            rhs if is_indexed_expr(rhs) => {
+                cs.constraints.push(Constraint {
+                    where_clause_pos: (i, BinaryExprSide::Lhs),
+                    operator: opposite_cmp_op(operator),
+                    table_col_pos: EXPR_INDEX_SENTINEL,
+                    lhs_mask: table_mask_from_expr(lhs, table_references)?,
+                    selectivity: estimate_selectivity(None, operator, row_count, None),
+                });
+            }
+            _ => {}
+        };
+    }
+    // sort equalities first so that index keys will be properly constructed.
+    // see e.g.: https://www.solarwinds.com/blog/the-left-prefix-index-rule
+    cs.constraints.sort_by(|a, b| {
+        if a.operator == ast::Operator::Equals {
+            Ordering::Less
+        } else if b.operator == ast::Operator::Equals {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
 
-        // For each constraint we found, add a reference to it for each index that may be able to use it.
-        for (i, constraint) in cs.constraints.iter().enumerate() {
-            if rowid_alias_column == Some(constraint.table_col_pos) {
-                let rowid_candidate = cs
+    // For each constraint we found, add a reference to it for each index that may be able to use it.
+    for (i, constraint) in cs.constraints.iter().enumerate() {
+        if rowid_alias_column == Some(constraint.table_col_pos) {
+            let rowid_candidate = cs
+                .candidates
+                .iter_mut()
+                .find_map(|candidate| {
+                    if candidate.index.is_none() {
+                        Some(candidate)
+                    } else {
+                        None
+                    }
+                })
+                .unwrap();
+            rowid_candidate.refs.push(ConstraintRef {
+                constraint_vec_pos: i,
+                index_col_pos: 0,
+                sort_order: SortOrder::Asc,
+            });
+        }
+        let constrained_expr = (constraint.table_col_pos == EXPR_INDEX_SENTINEL)
+            .then(|| constraint.get_constrained_expr(where_clause));
+        for index in indexes.iter() {
+            let position_in_index = match &constrained_expr {
+                Some(constrained_expr) => indexed_expr_position(index, constrained_expr),
+                None => index.column_table_pos_to_index_pos(constraint.table_col_pos),
+            };
+            // The keys of an index are ordered by the collation of its columns, which only finds
+            // the rows of the comparisons that use the same collation.
+            let position_in_index = position_in_index.filter(|&position_in_index| {
+                constraint_collation(constraint, where_clause, table_references)
+                    == Some(
+                        index.columns[position_in_index]
+                            .collation
+                            .unwrap_or_default(),
+                    )
+            });
+            if let Some(position_in_index) = position_in_index {
+                let index_candidate = cs
                     .candidates
                     .iter_mut()
                     .find_map(|candidate| {
-                        if candidate.index.is_none() {
+                        if candidate
+                            .index
+                            .as_ref()
+                            .is_some_and(|i| Arc::ptr_eq(index, i))
+                        {
                             Some(candidate)
                         } else {
                             None
                         }
                     })
                     .unwrap();
-                rowid_candidate.refs.push(ConstraintRef {
+                index_candidate.refs.push(ConstraintRef {
                     constraint_vec_pos: i,
-                    index_col_pos: 0,
-                    sort_order: SortOrder::Asc,
+                    index_col_pos: position_in_index,
+                    sort_order: index.columns[position_in_index].order,
                 });
             }
-            for index in available_indexes
-                .get(table_reference.table.get_name())
-                .unwrap_or(&Vec::new())
-            {
-                if let Some(position_in_index) =
-                    index.column_table_pos_to_index_pos(constraint.table_col_pos)
-                {
-                    let index_candidate = cs
-                        .candidates
-                        .iter_mut()
-                        .find_map(|candidate| {
-                            if candidate
-                                .index
-                                .as_ref()
-                                .is_some_and(|i| Arc::ptr_eq(index, i))
-                            {
-                                Some(candidate)
-                            } else {
-                                None
-                            }
-                        })
-                        .unwrap();
-                    index_candidate.refs.push(ConstraintRef {
-                        constraint_vec_pos: i,
-                        index_col_pos: position_in_index,
-                        sort_order: index.columns[position_in_index].order,
-                    });
-                }
-            }
         }
+    }
+
+    for candidate in cs.candidates.iter_mut() {
+        // Sort by index_col_pos, ascending -- index columns must be consumed in contiguous order.
+        candidate.refs.sort_by_key(|cref| cref.index_col_pos);
+        // Deduplicate by position, keeping first occurrence (which will be equality if one exists, since the constraints vec is sorted that way)
+        candidate.refs.dedup_by_key(|cref| cref.index_col_pos);
+        // Truncate at first gap in positions -- again, index columns must be consumed in contiguous order.
+        let contiguous_len = candidate
+            .refs
+            .iter()
+            .enumerate()
+            .take_while(|(i, cref)| cref.index_col_pos == *i)
+            .count();
+        candidate.refs.truncate(contiguous_len);
+
+        // Truncate after the first inequality, since the left-prefix rule of indexes requires that all constraints but the last one must be equalities;
+        // again see: https://www.solarwinds.com/blog/the-left-prefix-index-rule
+        if let Some(first_inequality) = candidate.refs.iter().position(|cref| {
+            cs.constraints[cref.constraint_vec_pos].operator != ast::Operator::Equals
+        }) {
+            candidate.refs.truncate(first_inequality + 1);
+        }
+    }
+    // An automatic index and the hash table of a hash join point to the rows of the table by
+    // rowid. They are not built for the right-hand-side table of a RIGHT JOIN, whose rows that
+    // matched nothing are read from the table after the join loop, nor on a rowid alias, which
+    // is the key of the table itself.
+    let supports_join_key = table_reference.btree().is_some_and(|table| table.has_rowid)
+        && !table_reference
+            .join_info
+            .as_ref()
+            .is_some_and(|join_info| join_info.right);
+    if supports_join_key {
+        // The keys of an automatic index are ordered by the collation of their column, which only
+        // finds the rows of the comparisons that use the same collation.
+        cs.automatic_index_refs =
+            join_key_refs(&cs.constraints, rowid_alias_column, |constraint| {
+                compares_with_column_collation(
+                    constraint,
+                    where_clause,
+                    table_references,
+                    table_reference,
+                )
+            });
+        // The values of an IN list are looked up one after another, which only an index can do.
+        cs.hash_join_key_refs = join_key_refs(&cs.constraints, rowid_alias_column, |constraint| {
+            as_in_list_components(&where_clause[constraint.where_clause_pos.0].expr).is_none()
+        });
+    }
+    Ok(cs)
+}
+
+/// Collect the equality constraints accepted by `usable` that may be the key of an ephemeral
+/// structure built on a joined table, one per column, in the order of the columns of the key.
+fn join_key_refs(
+    constraints: &[Constraint],
+    rowid_alias_column: Option<usize>,
+    usable: impl Fn(&Constraint) -> bool,
+) -> Vec<ConstraintRef> {
+    let mut refs: Vec<ConstraintRef> = Vec::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        if constraint.operator != ast::Operator::Equals
+            || constraint.table_col_pos == EXPR_INDEX_SENTINEL
+            || rowid_alias_column == Some(constraint.table_col_pos)
+            || !usable(constraint)
+            || refs.iter().any(|cref| {
+                constraints[cref.constraint_vec_pos].table_col_pos == constraint.table_col_pos
+            })
+        {
+            continue;
+        }
+        refs.push(ConstraintRef {
+            constraint_vec_pos: i,
+            index_col_pos: 0,
+            sort_order: SortOrder::Asc,
+        });
+    }
+    // Columns compared with constants lead, since they are usable in any join order.
+    refs.sort_by_key(|cref| !constraints[cref.constraint_vec_pos].lhs_mask.is_empty());
+    for (i, cref) in refs.iter_mut().enumerate() {
+        cref.index_col_pos = i;
+    }
+    refs
+}
+
+/// Whether the comparison of `constraint` uses the collation of its column of `table_reference`,
+/// e.g. not `t.a = u.b` if `u.b` is the column of `table_reference` and has a collation other
+/// than the one of `t.a`.
+fn compares_with_column_collation(
+    constraint: &Constraint,
+    where_clause: &[WhereTerm],
+    table_references: &TableReferences,
+    table_reference: &JoinedTable,
+) -> bool {
+    let Some(column) = table_reference.columns().get(constraint.table_col_pos) else {
+        return false;
+    };
+    constraint_collation(constraint, where_clause, table_references)
+        == Some(column.collation.unwrap_or_default())
+}
+
+/// The collation the comparison of `constraint` uses, if it can be determined.
+fn constraint_collation(
+    constraint: &Constraint,
+    where_clause: &[WhereTerm],
+    table_references: &TableReferences,
+) -> Option<CollationSeq> {
+    let expr = &where_clause[constraint.where_clause_pos.0].expr;
+    let collation = match as_in_list_components(expr) {
+        Some((lhs, _)) => comparison_collation(lhs, lhs, table_references),
+        None => match as_binary_components(expr) {
+            Ok(Some((lhs, _, rhs))) => comparison_collation(lhs, rhs, table_references),
+            _ => return None,
+        },
+    };
+    collation.ok()
+}
 
-        for candidate in cs.candidates.iter_mut() {
-            // Sort by index_col_pos, ascending -- index columns must be consumed in contiguous order.
-            candidate.refs.sort_by_key(|cref| cref.index_col_pos);
-            // Deduplicate by position, keeping first occurrence (which will be equality if one exists, since the constraints vec is sorted that way)
-            candidate.refs.dedup_by_key(|cref| cref.index_col_pos);
-            // Truncate at first gap in positions -- again, index columns must be consumed in contiguous order.
-            let contiguous_len = candidate
-                .refs
+/// Find the OR terms of a WHERE clause whose operands can each be looked up with an index of
+/// `table_reference`, see [MultiIndexOrCandidate].
+fn multi_index_or_candidates(
+    where_clause: &[WhereTerm],
+    table_references: &TableReferences,
+    table_reference: &JoinedTable,
+    available_indexes: &HashMap<String, Vec<Arc<Index>>>,
+    available_stats: &HashMap<String, TableStats>,
+) -> Result<Vec<MultiIndexOrCandidate>> {
+    // The rowids found by the lookups are read back from the table.
+    if !table_reference.btree().is_some_and(|table| table.has_rowid) {
+        return Ok(Vec::new());
+    }
+    let mut candidates = Vec::new();
+    for term in where_clause.iter() {
+        if !matches!(term.expr, ast::Expr::Binary(_, ast::Operator::Or, _))
+            || !term_applies_to_table(term, table_references, table_reference)
+        {
+            continue;
+        }
+        let mut operands = Vec::new();
+        break_predicate_at_or_boundaries(term.expr.clone(), &mut operands);
+        let operand_count = operands.len();
+        let mut branches = Vec::with_capacity(operand_count);
+        for operand in operands {
+            let mut exprs = Vec::new();
+            break_predicate_at_and_boundaries(operand, &mut exprs);
+            let branch_where_clause = exprs
+                .into_iter()
+                .map(|expr| WhereTerm {
+                    expr,
+                    from_outer_join: term.from_outer_join,
+                    after_right_join: term.after_right_join,
+                    consumed: Cell::new(false),
+                })
+                .collect::<Vec<_>>();
+            let mut constraints = table_constraints(
+                &branch_where_clause,
+                table_references,
+                table_reference,
+                available_indexes,
+                available_stats,
+            )?;
+            if constraints
+                .candidates
                 .iter()
-                .enumerate()
-                .take_while(|(i, cref)| cref.index_col_pos == *i)
-                .count();
-            candidate.refs.truncate(contiguous_len);
-
-            // Truncate after the first inequality, since the left-prefix rule of indexes requires that all constraints but the last one must be equalities;
-            // again see: https://www.solarwinds.com/blog/the-left-prefix-index-rule
-            if let Some(first_inequality) = candidate.refs.iter().position(|cref| {
-                cs.constraints[cref.constraint_vec_pos].operator != ast::Operator::Equals
-            }) {
-                candidate.refs.truncate(first_inequality + 1);
+                .all(|candidate| candidate.refs.is_empty())
+            {
+                break;
             }
+            constraints.automatic_index_refs.clear();
+            constraints.hash_join_key_refs.clear();
+            branches.push(MultiIndexOrBranch {
+                where_clause: branch_where_clause,
+                constraints,
+            });
+        }
+        if branches.len() == operand_count {
+            candidates.push(MultiIndexOrCandidate { branches });
         }
-        constraints.push(cs);
     }
+    Ok(candidates)
+}
 
-    Ok(constraints)
+/// Break an OR predicate into its operands, e.g. `a = 1 OR (b = 2 OR c = 3)` into `a = 1`, `b = 2`
+/// and `c = 3`. The parentheses around an operand are removed.
+pub(crate) fn break_predicate_at_or_boundaries(
+    predicate: ast::Expr,
+    out_predicates: &mut Vec<ast::Expr>,
+) {
+    match predicate {
+        ast::Expr::Binary(left, ast::Operator::Or, right) => {
+            break_predicate_at_or_boundaries(*left, out_predicates);
+            break_predicate_at_or_boundaries(*right, out_predicates);
+        }
+        ast::Expr::Parenthesized(mut exprs) if exprs.len() == 1 => {
+            break_predicate_at_or_boundaries(exprs.pop().unwrap(), out_predicates);
+        }
+        _ => {
+            out_predicates.push(predicate);
+        }
+    }
+}
+
+/// The constrained expression and the values of an `x IN (...)` term with a non-empty list.
+pub(crate) fn as_in_list_components(expr: &ast::Expr) -> Option<(&ast::Expr, &[ast::Expr])> {
+    match expr {
+        ast::Expr::InList {
+            lhs,
+            not: false,
+            rhs: Some(values),
+        } if !values.is_empty() => Some((lhs.as_ref(), values.as_slice())),
+        _ => None,
+    }
 }
 
 /// Find which [Constraint]s are usable for a given join order.
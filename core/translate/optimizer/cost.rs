@@ -1,3 +1,7 @@
+use turso_sqlite3_parser::ast;
+
+use crate::storage::sqlite3_ondisk::DEFAULT_PAGE_SIZE;
+
 use super::constraints::{Constraint, ConstraintRef};
 
 /// A simple newtype wrapper over a f64 that represents the cost of an operation.
@@ -40,33 +44,105 @@ pub fn estimate_page_io_cost(rowcount: f64) -> Cost {
 ///
 /// This is a very simple model that estimates the number of pages read
 /// based on the number of rows read, ignoring any CPU costs.
+/// `row_count` is the estimated number of rows of the table, and `index_stats` the statistics
+/// of the index gathered by ANALYZE, if any.
 pub fn estimate_cost_for_scan_or_seek(
     index_info: Option<IndexInfo>,
     constraints: &[Constraint],
     usable_constraint_refs: &[ConstraintRef],
     input_cardinality: f64,
+    row_count: f64,
+    index_stats: Option<&[u64]>,
 ) -> Cost {
     let Some(index_info) = index_info else {
-        return estimate_page_io_cost(
-            input_cardinality * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64,
-        );
+        return estimate_page_io_cost(input_cardinality * row_count);
     };
 
-    let selectivity_multiplier: f64 = usable_constraint_refs
-        .iter()
-        .map(|cref| {
-            let constraint = &constraints[cref.constraint_vec_pos];
-            constraint.selectivity
-        })
-        .product();
+    let selectivity_multiplier = |refs: &[ConstraintRef]| -> f64 {
+        refs.iter()
+            .map(|cref| {
+                let constraint = &constraints[cref.constraint_vec_pos];
+                constraint.selectivity
+            })
+            .product()
+    };
+
+    // With statistics, the rows read for equalities on the leading columns of the index are
+    // the average number of rows sharing the values of those columns.
+    let rows = match index_stats {
+        Some(stats) => {
+            let equalities = usable_constraint_refs
+                .iter()
+                .take_while(|cref| {
+                    constraints[cref.constraint_vec_pos].operator == ast::Operator::Equals
+                })
+                .count()
+                .min(stats.len() - 1);
+            stats[equalities] as f64 * selectivity_multiplier(&usable_constraint_refs[equalities..])
+        }
+        None => selectivity_multiplier(usable_constraint_refs) * row_count,
+    };
 
     // little cheeky bonus for covering indexes
     let covering_multiplier = if index_info.covering { 0.9 } else { 1.0 };
 
-    estimate_page_io_cost(
-        selectivity_multiplier
-            * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64
-            * input_cardinality
-            * covering_multiplier,
-    )
+    estimate_page_io_cost(rows * input_cardinality * covering_multiplier)
+}
+
+/// Estimate the cost of building an automatic index on a table and seeking it once for each of
+/// the `input_cardinality` rows of the tables joined before it. The index is built once, by
+/// reading all the rows of the table and writing them to the index. The index holds every column
+/// of the table the query uses, so it gets no bonus for being covering.
+pub fn estimate_cost_for_automatic_index(
+    constraints: &[Constraint],
+    usable_constraint_refs: &[ConstraintRef],
+    input_cardinality: f64,
+    row_count: f64,
+) -> Cost {
+    let build_cost = Cost(2.0 * *estimate_page_io_cost(row_count));
+    let seek_cost = estimate_cost_for_scan_or_seek(
+        Some(IndexInfo {
+            unique: false,
+            column_count: usable_constraint_refs.len(),
+            covering: false,
+        }),
+        constraints,
+        usable_constraint_refs,
+        input_cardinality,
+        row_count,
+        None,
+    );
+    build_cost + seek_cost
+}
+
+/// Estimate the cost of a hash join on a table, probed once for each of the `input_cardinality`
+/// rows of the tables joined before it. The hash table is built once, by reading all the rows of
+/// the table. The rows that do not fit in `memory_budget` bytes are written to a temporary file,
+/// and each match among them is read back from it with a random read.
+pub fn estimate_cost_for_hash_join(
+    constraints: &[Constraint],
+    usable_constraint_refs: &[ConstraintRef],
+    input_cardinality: f64,
+    row_count: f64,
+    memory_budget: usize,
+) -> Cost {
+    let build_cost = estimate_page_io_cost(row_count);
+    let estimated_row_size = DEFAULT_PAGE_SIZE as f64 / ESTIMATED_HARDCODED_ROWS_PER_PAGE as f64;
+    let spilled_fraction = (1.0 - memory_budget as f64 / (row_count * estimated_row_size)).max(0.0);
+    let spill_cost = estimate_page_io_cost(row_count * spilled_fraction);
+    let matches = usable_constraint_refs
+        .iter()
+        .map(|cref| constraints[cref.constraint_vec_pos].selectivity)
+        .product::<f64>()
+        * row_count
+        * input_cardinality;
+    let probe_cost = estimate_page_io_cost(matches) + Cost(matches * spilled_fraction);
+    build_cost + spill_cost + probe_cost
+}
+
+/// Estimate the cost of a multi-index OR, from the costs of the seeks of its branches. The rows
+/// the branches find are then looked up in the table by rowid, which costs about as much again.
+pub fn estimate_cost_for_multi_index_or(branch_costs: impl Iterator<Item = Cost>) -> Cost {
+    let seek_cost = branch_costs.fold(Cost(0.0), |total, cost| total + cost);
+    seek_cost + seek_cost
 }
@@ -14,7 +14,6 @@ use crate::{
 use super::{
     access_method::{find_best_access_method_for_join_order, AccessMethod},
     constraints::TableConstraints,
-    cost::ESTIMATED_HARDCODED_ROWS_PER_TABLE,
     order::OrderTarget,
 };
 
@@ -94,10 +93,9 @@ pub fn join_lhs_and_rhs<'a>(
     // Produce a number of rows estimated to be returned when this table is filtered by the WHERE clause.
     // If this table is the rightmost table in the join order, we multiply by the input cardinality,
     // which is the output cardinality of the previous tables.
-    let output_cardinality = (input_cardinality as f64
-        * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64
-        * output_cardinality_multiplier)
-        .ceil() as usize;
+    let output_cardinality =
+        (input_cardinality as f64 * rhs_constraints.row_count * output_cardinality_multiplier)
+            .ceil() as usize;
 
     Ok(Some(JoinN {
         data: best_access_methods,
@@ -213,21 +211,25 @@ pub fn compute_best_join_order<'a>(
     // As mentioned, inner joins are commutative. Outer joins are NOT.
     // Example:
     // "a LEFT JOIN b" can NOT be reordered as "b LEFT JOIN a".
+    // The right-hand-side table of a RIGHT JOIN is also kept after all the tables on its left,
+    // and before all the tables on its right, since the rows that none of the tables on its left
+    // match are joined with the tables on its right once the others have been scanned.
+    // Example:
+    // "a RIGHT JOIN b JOIN c" can NOT be reordered as "c JOIN a RIGHT JOIN b" or "a JOIN c RIGHT JOIN b".
     // If there are outer joins in the plan, ensure correct ordering.
     let left_join_illegal_map = {
-        let left_join_count = joined_tables
-            .iter()
-            .filter(|t| t.join_info.as_ref().is_some_and(|j| j.outer))
-            .count();
+        let is_outer = |t: &JoinedTable| t.join_info.as_ref().is_some_and(|j| j.outer || j.right);
+        let is_right = |t: &JoinedTable| t.join_info.as_ref().is_some_and(|j| j.right);
+        let left_join_count = joined_tables.iter().filter(|t| is_outer(t)).count();
         if left_join_count == 0 {
             None
         } else {
             // map from rhs table index to lhs table index
             let mut left_join_illegal_map: HashMap<usize, TableMask> =
                 HashMap::with_capacity(left_join_count);
-            for (i, _) in joined_tables.iter().enumerate() {
+            for (i, table) in joined_tables.iter().enumerate() {
                 for (j, joined_table) in joined_tables.iter().enumerate().skip(i + 1) {
-                    if joined_table.join_info.as_ref().is_some_and(|j| j.outer) {
+                    if is_outer(joined_table) || is_right(table) {
                         // bitwise OR the masks
                         if let Some(illegal_lhs) = left_join_illegal_map.get_mut(&i) {
                             illegal_lhs.add_table(j);
@@ -526,9 +528,13 @@ mod tests {
         let where_clause = vec![];
 
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -555,9 +561,13 @@ mod tests {
         let where_clause = vec![];
 
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // SELECT * from test_table
         // expecting best_best_plan() not to do any work due to empty where clause.
@@ -595,9 +605,13 @@ mod tests {
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
         let available_indexes = HashMap::new();
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // SELECT * FROM test_table WHERE id = 42
         // expecting a RowidEq access method because id is a rowid alias.
@@ -654,17 +668,25 @@ mod tests {
                 pos_in_table: 0,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: true,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
         available_indexes.insert("test_table".to_string(), vec![index]);
 
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
         // SELECT * FROM test_table WHERE id = 42
         // expecting an IndexScan access method because id is a primary key with an index
         let result = compute_best_join_order(
@@ -702,6 +724,7 @@ mod tests {
                 t2.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -722,11 +745,15 @@ mod tests {
                 pos_in_table: 0,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: true,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
         available_indexes.insert("table1".to_string(), vec![index1]);
 
@@ -740,9 +767,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -805,6 +836,7 @@ mod tests {
                 table_customers.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -813,6 +845,7 @@ mod tests {
                 table_order_items.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -838,11 +871,15 @@ mod tests {
                         pos_in_table: 0,
                         collation: None,
                         default: None,
+                        expr: None,
                     }],
                     unique: true,
                     ephemeral: false,
                     root_page: 1,
                     has_rowid: true,
+                    on_conflict: None,
+                    where_clause: None,
+                    pk_suffix_len: 0,
                 });
                 available_indexes.insert(table_name.to_string(), vec![index]);
             });
@@ -855,11 +892,15 @@ mod tests {
                 pos_in_table: 1,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: false,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
         let order_id_idx = Arc::new(Index {
             name: "order_items_order_id_idx".to_string(),
@@ -870,11 +911,15 @@ mod tests {
                 pos_in_table: 1,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: false,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
 
         available_indexes
@@ -912,9 +957,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -989,6 +1038,7 @@ mod tests {
                 t2.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -997,6 +1047,7 @@ mod tests {
                 t3.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -1021,9 +1072,13 @@ mod tests {
         let table_references = TableReferences::new(joined_tables, vec![]);
         let available_indexes = HashMap::new();
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1034,18 +1089,21 @@ mod tests {
         .unwrap()
         .unwrap();
 
-        // Verify that t2 is chosen first due to its equality filter
-        assert_eq!(best_plan.table_numbers().next().unwrap(), 1);
-        // Verify table scan is used since there are no indexes
+        // Verify that t1 is chosen first, and that t2 is looked up through an automatic index
+        // on its equality filter instead of being scanned once per row of t1
+        assert_eq!(best_plan.table_numbers().collect::<Vec<_>>(), vec![0, 1, 2]);
         let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
         assert!(access_method.is_scan());
         assert!(access_method.iter_dir == IterationDirection::Forwards);
         assert!(access_method.index.is_none());
-        // Verify that t1 is chosen next due to its inequality filter
         let access_method = &access_methods_arena.borrow()[best_plan.data[1].1];
-        assert!(access_method.is_scan());
+        assert!(!access_method.is_scan());
         assert!(access_method.iter_dir == IterationDirection::Forwards);
-        assert!(access_method.index.is_none());
+        assert!(access_method.index.as_ref().unwrap().ephemeral);
+        assert!(access_method.constraint_refs.len() == 1);
+        let constraint =
+            &table_constraints[1].constraints[access_method.constraint_refs[0].constraint_vec_pos];
+        assert!(constraint.lhs_mask.is_empty());
         // Verify that t3 is chosen last due to no filters
         let access_method = &access_methods_arena.borrow()[best_plan.data[2].1];
         assert!(access_method.is_scan());
@@ -1092,6 +1150,7 @@ mod tests {
                     t.clone(),
                     Some(JoinInfo {
                         outer: false,
+                        right: false,
                         using: None,
                     }),
                     table_id_counter.next(),
@@ -1101,6 +1160,7 @@ mod tests {
                 fact_table.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
                     using: None,
                 }),
                 table_id_counter.next(),
@@ -1124,9 +1184,13 @@ mod tests {
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
         let available_indexes = HashMap::new();
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -1208,9 +1272,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // Run the optimizer
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
@@ -1282,6 +1350,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "y".to_string(),
@@ -1289,12 +1358,16 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             unique: false,
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
 
         let mut available_indexes = HashMap::new();
@@ -1310,6 +1383,7 @@ mod tests {
             identifier: "t1".to_string(),
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
+            database_id: 0,
         });
 
         // Create where clause that only references second column
@@ -1325,14 +1399,19 @@ mod tests {
                 Box::new(Expr::Literal(ast::Literal::Numeric(5.to_string()))),
             ),
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1368,6 +1447,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c2".to_string(),
@@ -1375,6 +1455,7 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c3".to_string(),
@@ -1382,12 +1463,16 @@ mod tests {
                     pos_in_table: 2,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             unique: false,
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
         available_indexes.insert("t1".to_string(), vec![index]);
 
@@ -1401,6 +1486,7 @@ mod tests {
             identifier: "t1".to_string(),
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
+            database_id: 0,
         });
 
         // Create where clause that references first and third columns
@@ -1417,6 +1503,7 @@ mod tests {
                     Box::new(Expr::Literal(ast::Literal::Numeric(5.to_string()))),
                 ),
                 from_outer_join: None,
+                after_right_join: None,
                 consumed: Cell::new(false),
             },
             WhereTerm {
@@ -1431,15 +1518,20 @@ mod tests {
                     Box::new(Expr::Literal(ast::Literal::Numeric(7.to_string()))),
                 ),
                 from_outer_join: None,
+                after_right_join: None,
                 consumed: Cell::new(false),
             },
         ];
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1484,6 +1576,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c2".to_string(),
@@ -1491,6 +1584,7 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c3".to_string(),
@@ -1498,12 +1592,16 @@ mod tests {
                     pos_in_table: 2,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            on_conflict: None,
             unique: false,
+            where_clause: None,
+            pk_suffix_len: 0,
         });
         available_indexes.insert("t1".to_string(), vec![index]);
 
@@ -1517,6 +1615,7 @@ mod tests {
             identifier: "t1".to_string(),
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
+            database_id: 0,
         });
 
         // Create where clause: c1 = 5 AND c2 > 10 AND c3 = 7
@@ -1533,6 +1632,7 @@ mod tests {
                     Box::new(Expr::Literal(ast::Literal::Numeric(5.to_string()))),
                 ),
                 from_outer_join: None,
+                after_right_join: None,
                 consumed: Cell::new(false),
             },
             WhereTerm {
@@ -1547,6 +1647,7 @@ mod tests {
                     Box::new(Expr::Literal(ast::Literal::Numeric(10.to_string()))),
                 ),
                 from_outer_join: None,
+                after_right_join: None,
                 consumed: Cell::new(false),
             },
             WhereTerm {
@@ -1561,15 +1662,20 @@ mod tests {
                     Box::new(Expr::Literal(ast::Literal::Numeric(7.to_string()))),
                 ),
                 from_outer_join: None,
+                after_right_join: None,
                 consumed: Cell::new(false),
             },
         ];
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1611,6 +1717,9 @@ mod tests {
             unique: false,
             collation: None,
             hidden: false,
+            notnull_conflict: None,
+            unique_conflict: None,
+            generated: None,
         }
     }
     fn _create_column_of_type(name: &str, ty: Type) -> Column {
@@ -1642,10 +1751,13 @@ mod tests {
             root_page: 1, // Page number doesn't matter for tests
             name: name.to_string(),
             primary_key_columns: vec![],
+            primary_key_conflict: None,
             columns,
             has_rowid: true,
             is_strict: false,
             unique_sets: None,
+            check_constraints: vec![],
+            foreign_keys: vec![],
         })
     }
 
@@ -1666,6 +1778,7 @@ mod tests {
             internal_id,
             join_info,
             col_used_mask: ColumnUsedMask::default(),
+            database_id: 0,
         }
     }
 
@@ -1684,6 +1797,7 @@ mod tests {
         WhereTerm {
             expr: Expr::Binary(Box::new(lhs), op, Box::new(rhs)),
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }
     }
@@ -43,6 +43,7 @@ pub(crate) fn lift_common_subexpressions_from_binary_or_terms(
         }
         let term_expr_owned = where_clause[i].expr.clone(); // Own the expression for flattening
         let term_from_outer_join = where_clause[i].from_outer_join; // This needs to be remembered for the new WhereTerms
+        let term_after_right_join = where_clause[i].after_right_join;
 
         // e.g. a OR b OR c becomes effectively OR [a,b,c].
         let or_operands = flatten_or_expr_owned(term_expr_owned)?;
@@ -127,6 +128,7 @@ pub(crate) fn lift_common_subexpressions_from_binary_or_terms(
             where_clause.push(WhereTerm {
                 expr: common_expr_to_add,
                 from_outer_join: term_from_outer_join,
+                after_right_join: term_after_right_join,
                 consumed: Cell::new(false),
             });
         }
@@ -258,6 +260,7 @@ mod tests {
         let mut where_clause = vec![WhereTerm {
             expr: or_expr,
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
@@ -357,6 +360,7 @@ mod tests {
         let mut where_clause = vec![WhereTerm {
             expr: or_expr,
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
@@ -424,6 +428,7 @@ mod tests {
         let mut where_clause = vec![WhereTerm {
             expr: or_expr.clone(),
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
@@ -491,6 +496,7 @@ mod tests {
         let mut where_clause = vec![WhereTerm {
             expr: or_expr,
             from_outer_join: Some(TableInternalId::default()), // Set from_outer_join
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
@@ -543,6 +549,7 @@ mod tests {
         let mut where_clause = vec![WhereTerm {
             expr: single_expr.clone(),
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
@@ -596,6 +603,7 @@ mod tests {
         let mut where_clause = vec![WhereTerm {
             expr: or_expr,
             from_outer_join: None,
+            after_right_join: None,
             consumed: Cell::new(false),
         }];
 
@@ -1,30 +1,38 @@
 use std::{cell::RefCell, cmp::Ordering, collections::HashMap, sync::Arc};
 
 use constraints::{
-    constraints_from_where_clause, usable_constraints_for_join_order, Constraint, ConstraintRef,
+    as_in_list_components, break_predicate_at_or_boundaries, constraints_from_where_clause,
+    Constraint, ConstraintRef,
 };
 use cost::Cost;
 use join::{compute_best_join_order, BestJoinOrderResult};
 use lift_common_subexpressions::lift_common_subexpressions_from_binary_or_terms;
 use order::{compute_order_target, plan_satisfies_order_target, EliminatesSortBy};
 use turso_sqlite3_parser::{
-    ast::{self, Expr, SortOrder},
+    ast::{self, Expr, SortOrder, TableInternalId},
     to_sql_string::ToSqlString as _,
 };
 
 use crate::{
     parameters::PARAM_PREFIX,
-    schema::{Index, IndexColumn, Schema, Table},
-    translate::{expr::walk_expr_mut, plan::TerminationKey},
+    schema::{Index, IndexColumn, Schema, Table, TableStats},
+    storage::sqlite3_ondisk::DEFAULT_CACHE_SIZE,
+    translate::{
+        expr::{as_binary_components, comparison_affinity, comparison_collation, walk_expr_mut},
+        plan::TerminationKey,
+        planner::{last_right_join, table_mask_from_expr},
+    },
     types::SeekOp,
+    util::exprs_are_equivalent,
     Result,
 };
 
 use super::{
     emitter::Resolver,
     plan::{
-        DeletePlan, GroupBy, IterationDirection, JoinOrderMember, JoinedTable, Operation, Plan,
-        Search, SeekDef, SeekKey, SelectPlan, TableReferences, UpdatePlan, WhereTerm,
+        DeletePlan, GroupBy, HashJoinKey, IterationDirection, JoinOrderMember, JoinedTable,
+        Operation, Plan, Search, SeekDef, SeekKey, SelectPlan, TableReferences, UpdatePlan,
+        WhereTerm, WindowFunctionKind,
     },
 };
 
@@ -35,18 +43,38 @@ pub(crate) mod join;
 pub(crate) mod lift_common_subexpressions;
 pub(crate) mod order;
 
+/// The settings of the connection that the optimizer takes into account.
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizerOptions {
+    /// Whether joined tables may be looked up in automatic indexes, which are built on them by
+    /// the query (`PRAGMA automatic_index`).
+    pub automatic_index: bool,
+    /// The memory the hash table of a hash join may use before spilling to a temporary file, in
+    /// bytes. It is the memory of the page cache (`PRAGMA cache_size`).
+    pub hash_join_memory_budget: usize,
+}
+
+impl Default for OptimizerOptions {
+    fn default() -> Self {
+        Self {
+            automatic_index: true,
+            hash_join_memory_budget: DEFAULT_CACHE_SIZE.unsigned_abs() as usize * 1024,
+        }
+    }
+}
+
 #[tracing::instrument(skip_all, level = tracing::Level::DEBUG)]
-pub fn optimize_plan(plan: &mut Plan, schema: &Schema) -> Result<()> {
+pub fn optimize_plan(plan: &mut Plan, schema: &Schema, options: OptimizerOptions) -> Result<()> {
     match plan {
-        Plan::Select(plan) => optimize_select_plan(plan, schema)?,
+        Plan::Select(plan) => optimize_select_plan(plan, schema, options)?,
         Plan::Delete(plan) => optimize_delete_plan(plan, schema)?,
-        Plan::Update(plan) => optimize_update_plan(plan, schema)?,
+        Plan::Update(plan) => optimize_update_plan(plan, schema, options)?,
         Plan::CompoundSelect {
             left, right_most, ..
         } => {
-            optimize_select_plan(right_most, schema)?;
+            optimize_select_plan(right_most, schema, options)?;
             for (plan, _) in left {
-                optimize_select_plan(plan, schema)?;
+                optimize_select_plan(plan, schema, options)?;
             }
         }
     }
@@ -60,23 +88,36 @@ pub fn optimize_plan(plan: &mut Plan, schema: &Schema) -> Result<()> {
  * TODO: these could probably be done in less passes,
  * but having them separate makes them easier to understand
  */
-pub fn optimize_select_plan(plan: &mut SelectPlan, schema: &Schema) -> Result<()> {
-    optimize_subqueries(plan, schema)?;
+pub fn optimize_select_plan(
+    plan: &mut SelectPlan,
+    schema: &Schema,
+    options: OptimizerOptions,
+) -> Result<()> {
+    optimize_subqueries(plan, schema, options)?;
     rewrite_exprs_select(plan)?;
+    let last_right_join = last_right_join(plan.table_references.joined_tables());
     if let ConstantConditionEliminationResult::ImpossibleCondition =
-        eliminate_constant_conditions(&mut plan.where_clause)?
+        eliminate_constant_conditions(&mut plan.where_clause, last_right_join)?
     {
         plan.contains_constant_false_condition = true;
         return Ok(());
     }
 
+    // Window functions reorder the rows, so the join order cannot satisfy the ORDER BY of the query.
+    let mut no_order_by = None;
+    let order_by = if plan.window.is_some() {
+        &mut no_order_by
+    } else {
+        &mut plan.order_by
+    };
     let best_join_order = optimize_table_access(
-        schema,
         &mut plan.table_references,
         &schema.indexes,
+        &schema.stats,
         &mut plan.where_clause,
-        &mut plan.order_by,
+        order_by,
         &mut plan.group_by,
+        options,
     )?;
 
     if let Some(best_join_order) = best_join_order {
@@ -89,7 +130,7 @@ pub fn optimize_select_plan(plan: &mut SelectPlan, schema: &Schema) -> Result<()
 fn optimize_delete_plan(plan: &mut DeletePlan, _schema: &Schema) -> Result<()> {
     rewrite_exprs_delete(plan)?;
     if let ConstantConditionEliminationResult::ImpossibleCondition =
-        eliminate_constant_conditions(&mut plan.where_clause)?
+        eliminate_constant_conditions(&mut plan.where_clause, None)?
     {
         plan.contains_constant_false_condition = true;
         return Ok(());
@@ -108,29 +149,48 @@ fn optimize_delete_plan(plan: &mut DeletePlan, _schema: &Schema) -> Result<()> {
     Ok(())
 }
 
-fn optimize_update_plan(plan: &mut UpdatePlan, schema: &Schema) -> Result<()> {
+fn optimize_update_plan(
+    plan: &mut UpdatePlan,
+    schema: &Schema,
+    options: OptimizerOptions,
+) -> Result<()> {
     rewrite_exprs_update(plan)?;
     if let ConstantConditionEliminationResult::ImpossibleCondition =
-        eliminate_constant_conditions(&mut plan.where_clause)?
+        eliminate_constant_conditions(&mut plan.where_clause, None)?
     {
         plan.contains_constant_false_condition = true;
         return Ok(());
     }
+    // The rows collected by the ephemeral plan are looked up by their keys, so there is no
+    // access method to choose.
+    if plan.ephemeral_plan.is_some() {
+        return Ok(());
+    }
     let _ = optimize_table_access(
-        schema,
         &mut plan.table_references,
         &schema.indexes,
+        &schema.stats,
         &mut plan.where_clause,
         &mut plan.order_by,
         &mut None,
+        options,
     )?;
     Ok(())
 }
 
-fn optimize_subqueries(plan: &mut SelectPlan, schema: &Schema) -> Result<()> {
+fn optimize_subqueries(
+    plan: &mut SelectPlan,
+    schema: &Schema,
+    options: OptimizerOptions,
+) -> Result<()> {
     for table in plan.table_references.joined_tables_mut() {
         if let Table::FromClauseSubquery(from_clause_subquery) = &mut table.table {
-            optimize_select_plan(&mut from_clause_subquery.plan, schema)?;
+            for plan in from_clause_subquery.selects_mut() {
+                optimize_select_plan(plan, schema, options)?;
+            }
+            if let Some(recursive) = &mut from_clause_subquery.recursive {
+                optimize_select_plan(&mut recursive.plan, schema, options)?;
+            }
         }
     }
 
@@ -148,18 +208,40 @@ fn optimize_subqueries(plan: &mut SelectPlan, schema: &Schema) -> Result<()> {
 /// - Removes sorting operations if the selected join order and access methods satisfy the [crate::translate::optimizer::order::OrderTarget].
 ///
 /// Returns the join order if it was optimized, or None if the default join order was considered best.
+/// Automatic indexes are only considered with [OptimizerOptions::automatic_index], and hash joins
+/// are costed with the [OptimizerOptions::hash_join_memory_budget].
 fn optimize_table_access(
-    schema: &Schema,
     table_references: &mut TableReferences,
     available_indexes: &HashMap<String, Vec<Arc<Index>>>,
+    available_stats: &HashMap<String, TableStats>,
     where_clause: &mut [WhereTerm],
     order_by: &mut Option<Vec<(ast::Expr, SortOrder)>>,
     group_by: &mut Option<GroupBy>,
+    options: OptimizerOptions,
 ) -> Result<Option<Vec<JoinOrderMember>>> {
     let access_methods_arena = RefCell::new(Vec::new());
-    let maybe_order_target = compute_order_target(order_by, group_by.as_mut());
-    let constraints_per_table =
-        constraints_from_where_clause(where_clause, table_references, available_indexes)?;
+    // The rows of the right-hand-side table of a RIGHT JOIN that match no row are emitted after all
+    // the others, so no join order returns the rows in order.
+    let has_right_join = table_references.joined_tables().iter().any(|t| {
+        t.join_info
+            .as_ref()
+            .is_some_and(|join_info| join_info.right)
+    });
+    let maybe_order_target =
+        compute_order_target(order_by, group_by.as_mut()).filter(|_| !has_right_join);
+    rewrite_or_of_equalities_as_in_list(where_clause, table_references)?;
+    let mut constraints_per_table = constraints_from_where_clause(
+        where_clause,
+        table_references,
+        available_indexes,
+        available_stats,
+    )?;
+    for table_constraints in constraints_per_table.iter_mut() {
+        if !options.automatic_index {
+            table_constraints.automatic_index_refs.clear();
+        }
+        table_constraints.hash_join_memory_budget = options.hash_join_memory_budget;
+    }
     let Some(best_join_order_result) = compute_best_join_order(
         table_references.joined_tables_mut(),
         maybe_order_target.as_ref(),
@@ -175,7 +257,7 @@ fn optimize_table_access(
         best_ordered_plan,
     } = best_join_order_result;
 
-    let joined_tables = table_references.joined_tables_mut();
+    let joined_tables = table_references.joined_tables();
 
     // See if best_ordered_plan is better than the overall best_plan if we add a sorting penalty
     // to the unordered plan's cost.
@@ -238,79 +320,47 @@ fn optimize_table_access(
     // Mutate the Operations in `joined_tables` to use the selected access methods.
     for (i, join_order_member) in best_join_order.iter().enumerate() {
         let table_idx = join_order_member.original_idx;
-        let access_method = &access_methods_arena.borrow()[best_access_methods[i]];
-        if access_method.is_scan() {
-            let try_to_build_ephemeral_index = if schema.indexes_enabled() {
-                let is_leftmost_table = i == 0;
-                let uses_index = access_method.index.is_some();
-                let source_table_does_not_support_search = matches!(
-                    &joined_tables[table_idx].table,
-                    Table::FromClauseSubquery(_) | Table::Virtual(_)
-                );
-                !is_leftmost_table && !uses_index && !source_table_does_not_support_search
-            } else {
-                false
-            };
-
-            if !try_to_build_ephemeral_index {
-                joined_tables[table_idx].op = Operation::Scan {
-                    iter_dir: access_method.iter_dir,
-                    index: access_method.index.clone(),
-                };
-                continue;
-            }
-            // This branch means we have a full table scan for a non-outermost table.
-            // Try to construct an ephemeral index since it's going to be better than a scan.
-            let table_constraints = constraints_per_table
+        let table = &table_references.joined_tables()[table_idx];
+        let mut access_method = access_methods_arena.borrow()[best_access_methods[i]].clone();
+        // The b-tree of a WITHOUT ROWID table is its PRIMARY KEY index, which is accessed through
+        // the table cursor.
+        if access_method.index.as_ref().is_some_and(|index| {
+            table
+                .btree()
+                .is_some_and(|table| !table.has_rowid && table.root_page == index.root_page)
+        }) {
+            access_method.index = None;
+        }
+        let op = if let Some(multi_index_or) = &access_method.multi_index_or {
+            // The OR term is not consumed: the lookups find the rows matching any operand
+            // through some of its terms only, so the whole term is still evaluated in the loop.
+            let branches = multi_index_or
+                .branches
                 .iter()
-                .find(|c| c.table_id == join_order_member.table_id);
-            let Some(table_constraints) = table_constraints else {
-                joined_tables[table_idx].op = Operation::Scan {
-                    iter_dir: access_method.iter_dir,
-                    index: access_method.index.clone(),
-                };
-                continue;
-            };
-            let temp_constraint_refs = (0..table_constraints.constraints.len())
-                .map(|i| ConstraintRef {
-                    constraint_vec_pos: i,
-                    index_col_pos: table_constraints.constraints[i].table_col_pos,
-                    sort_order: SortOrder::Asc,
+                .zip(multi_index_or.candidate.branches.iter())
+                .map(|(branch_access_method, branch)| {
+                    search_from_constraints(
+                        table,
+                        branch_access_method.index.clone(),
+                        &branch.constraints.constraints,
+                        branch_access_method.constraint_refs,
+                        branch_access_method.iter_dir,
+                        &branch.where_clause,
+                    )
                 })
-                .collect::<Vec<_>>();
-            let usable_constraint_refs = usable_constraints_for_join_order(
-                &table_constraints.constraints,
-                &temp_constraint_refs,
-                &best_join_order[..=i],
-            );
-            if usable_constraint_refs.is_empty() {
-                joined_tables[table_idx].op = Operation::Scan {
-                    iter_dir: access_method.iter_dir,
-                    index: access_method.index.clone(),
-                };
-                continue;
+                .collect::<Result<Vec<_>>>()?;
+            Operation::Search(Search::MultiIndexOr { branches })
+        } else if access_method.is_scan() {
+            Operation::Scan {
+                iter_dir: access_method.iter_dir,
+                index: access_method.index.clone(),
             }
-            let ephemeral_index = ephemeral_index_build(
-                &joined_tables[table_idx],
-                &table_constraints.constraints,
-                usable_constraint_refs,
-            );
-            let ephemeral_index = Arc::new(ephemeral_index);
-            joined_tables[table_idx].op = Operation::Search(Search::Seek {
-                index: Some(ephemeral_index),
-                seek_def: build_seek_def_from_constraints(
-                    &table_constraints.constraints,
-                    usable_constraint_refs,
-                    access_method.iter_dir,
-                    where_clause,
-                )?,
-            });
         } else {
+            let constraints = &constraints_per_table[table_idx].constraints;
             let constraint_refs = access_method.constraint_refs;
             assert!(!constraint_refs.is_empty());
             for cref in constraint_refs.iter() {
-                let constraint =
-                    &constraints_per_table[table_idx].constraints[cref.constraint_vec_pos];
+                let constraint = &constraints[cref.constraint_vec_pos];
                 assert!(
                     !where_clause[constraint.where_clause_pos.0].consumed.get(),
                     "trying to consume a where clause term twice: {:?}",
@@ -320,42 +370,149 @@ fn optimize_table_access(
                     .consumed
                     .set(true);
             }
-            if let Some(index) = &access_method.index {
-                joined_tables[table_idx].op = Operation::Search(Search::Seek {
-                    index: Some(index.clone()),
-                    seek_def: build_seek_def_from_constraints(
-                        &constraints_per_table[table_idx].constraints,
-                        constraint_refs,
-                        access_method.iter_dir,
-                        where_clause,
-                    )?,
-                });
-                continue;
+            if access_method.hash_join {
+                let keys = constraint_refs
+                    .iter()
+                    .map(|cref| {
+                        let constraint = &constraints[cref.constraint_vec_pos];
+                        let term = &where_clause[constraint.where_clause_pos.0];
+                        let Some((lhs, _, rhs)) = as_binary_components(&term.expr)? else {
+                            panic!("hash join key is not a binary comparison: {term:?}");
+                        };
+                        Ok(HashJoinKey {
+                            expr: constraint.get_constraining_expr(where_clause),
+                            affinity: comparison_affinity(lhs, rhs, Some(table_references)),
+                            collation: comparison_collation(lhs, rhs, table_references)?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let index = Arc::new(ephemeral_index_build(table, constraints, constraint_refs));
+                Operation::Search(Search::HashJoin { index, keys })
+            } else {
+                Operation::Search(search_from_constraints(
+                    table,
+                    access_method.index.clone(),
+                    constraints,
+                    constraint_refs,
+                    access_method.iter_dir,
+                    where_clause,
+                )?)
+            }
+        };
+        table_references.joined_tables_mut()[table_idx].op = op;
+    }
+
+    Ok(Some(best_join_order))
+}
+
+/// Rewrite each OR of equalities between the same column and other values into an IN list,
+/// e.g. `t.a = 1 OR 2 = t.a OR t.a = u.b` into `t.a IN (1, 2, u.b)`, which a single index can look up.
+fn rewrite_or_of_equalities_as_in_list(
+    where_clause: &mut [WhereTerm],
+    table_references: &TableReferences,
+) -> Result<()> {
+    for term in where_clause.iter_mut() {
+        if !matches!(term.expr, Expr::Binary(_, ast::Operator::Or, _)) {
+            continue;
+        }
+        let mut operands = Vec::new();
+        break_predicate_at_or_boundaries(term.expr.clone(), &mut operands);
+        let mut equalities = Vec::with_capacity(operands.len());
+        for operand in operands.iter() {
+            match as_binary_components(operand)? {
+                Some((lhs, ast::Operator::Equals, rhs)) => equalities.push((lhs, rhs)),
+                _ => break,
             }
-            assert!(
-                constraint_refs.len() == 1,
-                "expected exactly one constraint for rowid seek, got {constraint_refs:?}"
-            );
-            let constraint = &constraints_per_table[table_idx].constraints
-                [constraint_refs[0].constraint_vec_pos];
-            joined_tables[table_idx].op = match constraint.operator {
-                ast::Operator::Equals => Operation::Search(Search::RowidEq {
-                    cmp_expr: constraint.get_constraining_expr(where_clause),
-                }),
-                _ => Operation::Search(Search::Seek {
-                    index: None,
-                    seek_def: build_seek_def_from_constraints(
-                        &constraints_per_table[table_idx].constraints,
-                        constraint_refs,
-                        access_method.iter_dir,
-                        where_clause,
-                    )?,
-                }),
+        }
+        if equalities.len() != operands.len() {
+            continue;
+        }
+        // The value compared with the column in an equality, if the column is on either side of it.
+        // A value of the same table cannot be the key of a lookup in it.
+        let value_of = |column: &Expr, (lhs, rhs): (&Expr, &Expr)| -> Result<Option<Expr>> {
+            let (Expr::Column { table, .. } | Expr::RowId { table, .. }) = column else {
+                return Ok(None);
+            };
+            let Some(table_no) = table_references
+                .joined_tables()
+                .iter()
+                .position(|t| t.internal_id == *table)
+            else {
+                return Ok(None);
             };
+            for (side, value) in [(lhs, rhs), (rhs, lhs)] {
+                if exprs_are_equivalent(side, column)
+                    && !matches!(value, Expr::Collate(..))
+                    && !table_mask_from_expr(value, table_references)?.contains_table(table_no)
+                {
+                    return Ok(Some(value.clone()));
+                }
+            }
+            Ok(None)
+        };
+        let (first_lhs, first_rhs) = equalities[0];
+        for column in [first_lhs, first_rhs] {
+            let mut values = Vec::with_capacity(equalities.len());
+            for equality in equalities.iter() {
+                match value_of(column, *equality)? {
+                    Some(value) => values.push(value),
+                    None => break,
+                }
+            }
+            if values.len() == equalities.len() {
+                term.expr = Expr::InList {
+                    lhs: Box::new(column.clone()),
+                    not: false,
+                    rhs: Some(values),
+                };
+                break;
+            }
         }
     }
+    Ok(())
+}
 
-    Ok(Some(best_join_order))
+/// Build the [Search] of `table` with `index` (None for the rowid) that uses the given constraints.
+fn search_from_constraints(
+    table: &JoinedTable,
+    index: Option<Arc<Index>>,
+    constraints: &[Constraint],
+    constraint_refs: &[ConstraintRef],
+    iter_dir: IterationDirection,
+    where_clause: &[WhereTerm],
+) -> Result<Search> {
+    if index.is_some() || table.btree().is_some_and(|table| !table.has_rowid) {
+        return Ok(Search::Seek {
+            index,
+            seek_def: build_seek_def_from_constraints(
+                constraints,
+                constraint_refs,
+                iter_dir,
+                where_clause,
+            )?,
+        });
+    }
+    assert!(
+        constraint_refs.len() == 1,
+        "expected exactly one constraint for rowid seek, got {constraint_refs:?}"
+    );
+    let constraint = &constraints[constraint_refs[0].constraint_vec_pos];
+    // A rowid equality with an IN list is a seek repeated for each value of the list.
+    let cmp_expr = constraint.get_constraining_expr(where_clause);
+    Ok(match constraint.operator {
+        ast::Operator::Equals if as_in_list_components(&cmp_expr).is_none() => {
+            Search::RowidEq { cmp_expr }
+        }
+        _ => Search::Seek {
+            index: None,
+            seek_def: build_seek_def_from_constraints(
+                constraints,
+                constraint_refs,
+                iter_dir,
+                where_clause,
+            )?,
+        },
+    })
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -367,8 +524,10 @@ enum ConstantConditionEliminationResult {
 /// Removes predicates that are always true.
 /// Returns a ConstantEliminationResult indicating whether any predicates are always false.
 /// This is used to determine whether the query can be aborted early.
+/// `last_right_join` is the right-hand-side table of the last RIGHT or FULL OUTER JOIN of the query, if any.
 fn eliminate_constant_conditions(
     where_clause: &mut [WhereTerm],
+    last_right_join: Option<TableInternalId>,
 ) -> Result<ConstantConditionEliminationResult> {
     let mut i = 0;
     while i < where_clause.len() {
@@ -379,8 +538,10 @@ fn eliminate_constant_conditions(
             i += 1;
         } else if predicate.expr.is_always_false()? {
             // any false predicate in a list of conjuncts (AND-ed predicates) will make the whole list false,
-            // except an outer join condition, because that just results in NULLs, not skipping the whole loop
-            if predicate.from_outer_join.is_some() {
+            // except an outer join condition, because that just results in NULLs, not skipping the whole loop,
+            // and a join condition on the left of a RIGHT JOIN, whose right-hand-side rows are still emitted
+            if predicate.from_outer_join.is_some() || predicate.after_right_join != last_right_join
+            {
                 i += 1;
                 continue;
             }
@@ -404,6 +565,35 @@ fn rewrite_exprs_select(plan: &mut SelectPlan) -> Result<()> {
     for agg in plan.aggregates.iter_mut() {
         rewrite_expr(&mut agg.original_expr, &mut param_count)?;
     }
+    if let Some(window_plan) = &mut plan.window {
+        // The window plan holds copies of expressions of the result columns and ORDER BY,
+        // so parameters inside them must not be counted again.
+        let mut param_count = param_count;
+        for column in window_plan.columns.iter_mut() {
+            rewrite_expr(column, &mut param_count)?;
+        }
+        for window in window_plan.windows.iter_mut() {
+            for expr in window.partition_by.iter_mut() {
+                rewrite_expr(expr, &mut param_count)?;
+            }
+            for (expr, _) in window.order_by.iter_mut() {
+                rewrite_expr(expr, &mut param_count)?;
+            }
+            for func in window.functions.iter_mut() {
+                rewrite_expr(&mut func.original_expr, &mut param_count)?;
+                for arg in func.args.iter_mut() {
+                    rewrite_expr(arg, &mut param_count)?;
+                }
+                if let WindowFunctionKind::Aggregate(agg) = &mut func.func {
+                    agg.args.clone_from(&func.args);
+                    agg.original_expr.clone_from(&func.original_expr);
+                }
+                if let Some(filter) = &mut func.filter {
+                    rewrite_expr(filter, &mut param_count)?;
+                }
+            }
+        }
+    }
     lift_common_subexpressions_from_binary_or_terms(&mut plan.where_clause)?;
     for cond in plan.where_clause.iter_mut() {
         rewrite_expr(&mut cond.expr, &mut param_count)?;
@@ -427,6 +617,9 @@ fn rewrite_exprs_delete(plan: &mut DeletePlan) -> Result<()> {
     for cond in plan.where_clause.iter_mut() {
         rewrite_expr(&mut cond.expr, &mut param_idx)?;
     }
+    for rc in plan.result_columns.iter_mut() {
+        rewrite_expr(&mut rc.expr, &mut param_idx)?;
+    }
     Ok(())
 }
 
@@ -554,7 +747,9 @@ impl Optimizable for ast::Expr {
                 panic!("Do not call is_nonnull before Qualified has been rewritten as Column")
             }
             Expr::Raise(..) => false,
+            Expr::Register(..) => false,
             Expr::Subquery(..) => false,
+            Expr::SubqueryResult { expr, .. } => expr.is_nonnull(tables),
             Expr::Unary(_, expr) => expr.is_nonnull(tables),
             Expr::Variable(..) => false,
         }
@@ -633,8 +828,11 @@ impl Optimizable for ast::Expr {
             Expr::Qualified(_, _) => {
                 panic!("Qualified should have been rewritten as Column")
             }
-            Expr::Raise(_, expr) => expr.as_ref().is_none_or(|expr| expr.is_constant(resolver)),
+            // RAISE() has side effects and must be evaluated where it appears
+            Expr::Raise(..) => false,
+            Expr::Register(_) => false,
             Expr::Subquery(_) => false,
+            Expr::SubqueryResult { expr, .. } => expr.is_constant(resolver),
             Expr::Unary(_, expr) => expr.is_constant(resolver),
             Expr::Variable(_) => false,
         }
@@ -773,6 +971,7 @@ fn ephemeral_index_build(
             pos_in_table: i,
             collation: c.collation,
             default: c.default.clone(),
+            expr: None,
         })
         // only include columns that are used in the query
         .filter(|c| table_reference.column_is_used(c.pos_in_table))
@@ -809,6 +1008,9 @@ fn ephemeral_index_build(
             .table
             .btree()
             .is_some_and(|btree| btree.has_rowid),
+        on_conflict: None,
+        where_clause: None,
+        pk_suffix_len: 0,
     };
 
     ephemeral_index
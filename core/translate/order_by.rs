@@ -49,19 +49,7 @@ pub fn init_order_by(
      */
     let collations = order_by
         .iter()
-        .map(|(expr, _)| match expr {
-            ast::Expr::Collate(_, collation_name) => CollationSeq::new(collation_name).map(Some),
-            ast::Expr::Column { table, column, .. } => {
-                let table = referenced_tables.find_table_by_internal_id(*table).unwrap();
-
-                let Some(table_column) = table.get_column_at(*column) else {
-                    crate::bail_parse_error!("column index out of bounds");
-                };
-
-                Ok(table_column.collation)
-            }
-            _ => Ok(Some(CollationSeq::default())),
-        })
+        .map(|(expr, _)| sort_key_collation(expr, referenced_tables))
         .collect::<Result<Vec<_>>>()?;
     program.emit_insn(Insn::SorterOpen {
         cursor_id: sort_cursor,
@@ -72,6 +60,26 @@ pub fn init_order_by(
     Ok(())
 }
 
+/// Returns the collating sequence used for sorting by the given expression.
+pub fn sort_key_collation(
+    expr: &ast::Expr,
+    referenced_tables: &TableReferences,
+) -> Result<Option<CollationSeq>> {
+    match expr {
+        ast::Expr::Collate(_, collation_name) => CollationSeq::new(collation_name).map(Some),
+        ast::Expr::Column { table, column, .. } => {
+            let table = referenced_tables.find_table_by_internal_id(*table).unwrap();
+
+            let Some(table_column) = table.get_column_at(*column) else {
+                crate::bail_parse_error!("column index out of bounds");
+            };
+
+            Ok(table_column.collation)
+        }
+        _ => Ok(Some(CollationSeq::default())),
+    }
+}
+
 /// Emits the bytecode for outputting rows from an ORDER BY sorter.
 /// This is called when the main query execution loop has finished processing,
 /// and we can now emit rows from the ORDER BY sorter.
@@ -1,10 +1,15 @@
-use std::{cell::Cell, cmp::Ordering, rc::Rc, sync::Arc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    rc::Rc,
+    sync::Arc,
+};
 use turso_ext::{ConstraintInfo, ConstraintOp};
 use turso_sqlite3_parser::ast::{self, SortOrder};
 
 use crate::{
-    function::AggFunc,
-    schema::{BTreeTable, Column, FromClauseSubquery, Index, Table},
+    function::{AggFunc, WindowFunc},
+    schema::{Affinity, BTreeTable, Column, FromClauseSubquery, Index, Table},
     vdbe::{
         builder::{CursorKey, CursorType, ProgramBuilder},
         insn::{IdxInsertFlags, Insn},
@@ -16,7 +21,7 @@ use crate::{schema::Type, types::SeekOp, util::can_pushdown_predicate};
 
 use turso_sqlite3_parser::ast::TableInternalId;
 
-use super::{emitter::OperationMode, planner::determine_where_to_eval_term};
+use super::{collate::CollationSeq, emitter::OperationMode, planner::determine_where_to_eval_term};
 
 #[derive(Debug, Clone)]
 pub struct ResultSetColumn {
@@ -80,6 +85,12 @@ pub struct WhereTerm {
     /// regardless of which tables it references.
     /// We also cannot e.g. short circuit the entire query in the optimizer if the condition is statically false.
     pub from_outer_join: Option<TableInternalId>,
+    /// The internal ID of the right-hand-side table of the last RIGHT or FULL OUTER JOIN that precedes
+    /// the clause this condition comes from, if any.
+    /// The rows of the tables on the left of such a join are replaced with NULLs for the rows of its right-hand-side
+    /// table that match none of them, so the condition can only be evaluated once the rows of that table are matched,
+    /// and it cannot be used to look up the rows of that table or of any table on its left.
+    pub after_right_join: Option<TableInternalId>,
     /// Whether the condition has been consumed by the optimizer in some way, and it should not be evaluated
     /// in the normal place where WHERE terms are evaluated.
     /// A term may have been consumed e.g. if:
@@ -295,7 +306,8 @@ pub enum Plan {
         right_most: SelectPlan,
         limit: Option<isize>,
         offset: Option<isize>,
-        order_by: Option<Vec<(ast::Expr, SortOrder)>>,
+        /// The ORDER BY terms, as the index of the result column to sort by, the sort order and the collation.
+        order_by: Option<Vec<(usize, SortOrder, Option<CollationSeq>)>>,
     },
     Delete(DeletePlan),
     Update(UpdatePlan),
@@ -414,6 +426,54 @@ impl DistinctCtx {
     }
 }
 
+/// The recursive part of a recursive CTE, i.e. the SELECT after the last `UNION [ALL]` of
+/// `WITH RECURSIVE cte AS (initial UNION [ALL] recursive)`. It is run for every row the CTE
+/// produces, which it reads through a [FromClauseSubquery] with `is_recursive_reference` set,
+/// and its rows are added to those of the CTE.
+#[derive(Debug, Clone)]
+pub struct RecursiveCte {
+    /// The query plan of the recursive part.
+    pub plan: SelectPlan,
+    /// Whether duplicate rows are kept (UNION ALL) or discarded (UNION).
+    pub union_all: bool,
+    /// The LIMIT of the CTE, which stops the recursion once reached.
+    pub limit: Option<isize>,
+    /// The OFFSET of the CTE; the skipped rows are still used by the recursive part.
+    pub offset: Option<isize>,
+    /// The ORDER BY terms of the CTE, as the index of the result column to sort by, the sort
+    /// order and the collation. They determine which row the recursive part is run for next.
+    pub order_by: Option<Vec<(usize, SortOrder, Option<CollationSeq>)>>,
+    /// The coroutine which yields the rows of the CTE, set during bytecode emission.
+    pub query_destination: QueryDestination,
+}
+
+/// A common table expression of a WITH clause. Every reference to it is planned from this
+/// definition into a [FromClauseSubquery] of its own, like a view.
+#[derive(Debug)]
+pub struct CteDefinition {
+    pub name: String,
+    /// The column names of `WITH name(columns) AS (...)`.
+    pub columns: Option<Vec<ast::IndexedColumn>>,
+    pub select: ast::Select,
+    /// Whether the WITH clause is a WITH RECURSIVE clause.
+    pub recursive: bool,
+    /// The tables the CTE can refer to, i.e. the CTEs before it in the WITH clause and the
+    /// outer query references of the query the WITH clause belongs to.
+    pub outer_query_refs: Vec<OuterQueryReference>,
+}
+
+/// The parts of a compound SELECT in a FROM clause subquery other than its rightmost SELECT,
+/// which is the plan of the [FromClauseSubquery]. Together they form a [Plan::CompoundSelect].
+#[derive(Debug, Clone)]
+pub struct CompoundSubquery {
+    /// The SELECTs before the rightmost one, each with the operator that follows it.
+    pub left: Vec<(SelectPlan, ast::CompoundOperator)>,
+    pub limit: Option<isize>,
+    pub offset: Option<isize>,
+    /// The ORDER BY terms, as the index of the result column to sort by, the sort order and the collation.
+    pub order_by: Option<Vec<(usize, SortOrder, Option<CollationSeq>)>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SelectPlan {
     pub table_references: TableReferences,
@@ -430,6 +490,8 @@ pub struct SelectPlan {
     pub order_by: Option<Vec<(ast::Expr, SortOrder)>>,
     /// all the aggregates collected from the result columns, order by, and (TODO) having clauses
     pub aggregates: Vec<Aggregate>,
+    /// the window functions collected from the result columns and order by
+    pub window: Option<WindowPlan>,
     /// limit clause
     pub limit: Option<isize>,
     /// offset clause
@@ -534,6 +596,8 @@ pub struct UpdatePlan {
     // whether the WHERE clause is always false
     pub contains_constant_false_condition: bool,
     pub indexes_to_update: Vec<Arc<Index>>,
+    // conflict resolution algorithm of the statement, from UPDATE OR <algorithm>
+    pub or_conflict: Option<ast::ResolveType>,
     // If the table's rowid alias is used, gather all the target rowids into an ephemeral table, and then use that table as the single JoinedTable for the actual UPDATE loop.
     pub ephemeral_plan: Option<SelectPlan>,
 }
@@ -545,11 +609,67 @@ pub enum IterationDirection {
 }
 
 pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn>) {
+    let first_star_column = out_columns.len();
     for table in tables.iter() {
         let maybe_using_cols = table
             .join_info
             .as_ref()
             .and_then(|join_info| join_info.using.as_ref());
+        if let Some(using_cols) =
+            maybe_using_cols.filter(|_| table.join_info.as_ref().unwrap().right)
+        {
+            // The rows of a RIGHT JOIN table that match no rows on the left have NULLs in the left table's
+            // USING columns, so the deduplicated column takes its value from whichever side is not NULL.
+            for using_col in using_cols.iter() {
+                let Some(right_col_idx) = table.columns().iter().position(|col| {
+                    col.name
+                        .as_ref()
+                        .is_some_and(|name| name.eq_ignore_ascii_case(&using_col.0))
+                }) else {
+                    continue;
+                };
+                let right_col = &table.columns()[right_col_idx];
+                let left_col = out_columns[first_star_column..].iter_mut().find(|out_col| {
+                    let ast::Expr::Column {
+                        table: left_table_id,
+                        column,
+                        ..
+                    } = &out_col.expr
+                    else {
+                        return false;
+                    };
+                    tables
+                        .iter()
+                        .find(|t| t.internal_id == *left_table_id)
+                        .and_then(|t| t.columns().get(*column))
+                        .and_then(|col| col.name.as_ref())
+                        .is_some_and(|name| name.eq_ignore_ascii_case(&using_col.0))
+                });
+                if let Some(left_col) = left_col {
+                    let name = right_col.name.clone();
+                    let left_expr = std::mem::replace(
+                        &mut left_col.expr,
+                        ast::Expr::Literal(ast::Literal::Null),
+                    );
+                    left_col.alias = left_col.alias.take().or(name);
+                    left_col.expr = ast::Expr::FunctionCall {
+                        name: ast::Id("coalesce".to_string()),
+                        distinctness: None,
+                        args: Some(vec![
+                            left_expr,
+                            ast::Expr::Column {
+                                database: None,
+                                table: table.internal_id,
+                                column: right_col_idx,
+                                is_rowid_alias: right_col.is_rowid_alias,
+                            },
+                        ]),
+                        order_by: None,
+                        filter_over: None,
+                    };
+                }
+            }
+        }
         out_columns.extend(
             table
                 .columns()
@@ -586,8 +706,11 @@ pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn
 /// Join information for a table reference.
 #[derive(Debug, Clone)]
 pub struct JoinInfo {
-    /// Whether this is an OUTER JOIN.
+    /// Whether this is a LEFT or FULL OUTER JOIN, i.e. the table is NULL-extended for the rows on its left that it doesn't match.
     pub outer: bool,
+    /// Whether this is a RIGHT or FULL OUTER JOIN, i.e. the tables on its left are NULL-extended for the rows of this table
+    /// that they don't match.
+    pub right: bool,
     /// The USING clause for the join, if any. NATURAL JOIN is transformed into USING (col1, col2, ...).
     pub using: Option<ast::DistinctNames>,
 }
@@ -601,7 +724,7 @@ pub struct JoinInfo {
 /// - all have [Operation::Scan]
 /// - identifiers are `t`, `p`, `sub`
 /// - `t` and `p` are [Table::BTree] while `sub` is [Table::FromClauseSubquery]
-/// - join_info is None for the first table reference, and Some(JoinInfo { outer: false, using: None }) for the second and third table references
+/// - join_info is None for the first table reference, and Some(JoinInfo { outer: false, right: false, using: None }) for the second and third table references
 #[derive(Debug, Clone)]
 pub struct JoinedTable {
     /// The operation that this table reference performs.
@@ -612,6 +735,9 @@ pub struct JoinedTable {
     pub identifier: String,
     /// Internal ID of the table reference, used in e.g. [Expr::Column] to refer to this table.
     pub internal_id: TableInternalId,
+    /// Index of the database the table belongs to: 0 for `main`, 1 for `temp`, 2 and up for
+    /// attached databases.
+    pub database_id: usize,
     /// The join info for this table reference, if it is the right side of a join (which all except the first table reference have)
     pub join_info: Option<JoinInfo>,
     /// Bitmask of columns that are referenced in the query.
@@ -633,6 +759,9 @@ pub struct OuterQueryReference {
     /// i.e., if the subquery depends on tables T and U,
     /// then both T and U need to be in scope for the subquery to be evaluated.
     pub col_used_mask: ColumnUsedMask,
+    /// Whether the rowid of the table is referenced in the query, which is not tracked by
+    /// [OuterQueryReference::col_used_mask] unless the table has a rowid alias column.
+    pub rowid_used: bool,
 }
 
 impl OuterQueryReference {
@@ -649,7 +778,7 @@ impl OuterQueryReference {
     /// Whether the OuterQueryReference is used by the current query scope.
     /// This is used primarily to determine at what loop depth a subquery should be evaluated.
     pub fn is_used(&self) -> bool {
-        !self.col_used_mask.is_empty()
+        !self.col_used_mask.is_empty() || self.rowid_used
     }
 }
 
@@ -673,6 +802,17 @@ pub struct TableReferences {
     joined_tables: Vec<JoinedTable>,
     /// Tables from outer scopes that are referenced in this query scope.
     outer_query_refs: Vec<OuterQueryReference>,
+    /// Plans of the subqueries in the expressions of this query scope.
+    expr_subqueries: Vec<ExprSubquery>,
+}
+
+/// The plan of a subquery in an expression, which the expression references as an
+/// [ast::Expr::SubqueryResult]. The plan is taken out of the [TableReferences] when the
+/// expression is translated, which only has an immutable reference to them.
+#[derive(Debug, Clone)]
+struct ExprSubquery {
+    subquery_id: TableInternalId,
+    plan: RefCell<Option<SelectPlan>>,
 }
 
 impl TableReferences {
@@ -683,6 +823,7 @@ impl TableReferences {
         Self {
             joined_tables,
             outer_query_refs,
+            expr_subqueries: vec![],
         }
     }
 
@@ -816,6 +957,72 @@ impl TableReferences {
         }
     }
 
+    /// Marks the rowid of a table as used. This only matters for outer query references,
+    /// since the rowid is always available from both the table and its indexes.
+    pub fn mark_rowid_used(&mut self, internal_id: TableInternalId) {
+        if let Some(outer_query_ref) = self.find_outer_query_ref_by_internal_id_mut(internal_id) {
+            outer_query_ref.rowid_used = true;
+        }
+    }
+
+    /// Marks the columns of `other`'s outer query references that `other` uses as used in this
+    /// scope too, so that e.g. a covering index is not chosen for a table whose columns are
+    /// read by a subquery.
+    pub fn mark_outer_query_refs_used(&mut self, other: &TableReferences) {
+        for outer_query_ref in other.outer_query_refs().iter().filter(|t| t.is_used()) {
+            let id = outer_query_ref.internal_id;
+            for column_index in 0..outer_query_ref.columns().len() {
+                if !outer_query_ref.col_used_mask.get(column_index) {
+                    continue;
+                }
+                if let Some(joined_table) = self.find_joined_table_by_internal_id_mut(id) {
+                    joined_table.mark_column_used(column_index);
+                } else if let Some(outer) = self.find_outer_query_ref_by_internal_id_mut(id) {
+                    outer.mark_column_used(column_index);
+                }
+            }
+            if outer_query_ref.rowid_used {
+                self.mark_rowid_used(id);
+            }
+        }
+    }
+
+    /// Adds the plan of a subquery in an expression of this query scope, see [ExprSubquery].
+    pub fn add_expr_subquery(&mut self, subquery_id: TableInternalId, plan: SelectPlan) {
+        self.expr_subqueries.push(ExprSubquery {
+            subquery_id,
+            plan: RefCell::new(Some(plan)),
+        });
+    }
+
+    /// Takes the plan of a subquery in an expression of this query scope. Returns None if it
+    /// was already taken because the expression is translated more than once.
+    pub fn take_expr_subquery(&self, subquery_id: TableInternalId) -> Option<SelectPlan> {
+        self.expr_subqueries
+            .iter()
+            .find(|s| s.subquery_id == subquery_id)
+            .and_then(|s| s.plan.borrow_mut().take())
+    }
+
+    /// Updates the tables of the outer query references of this query scope and of its FROM
+    /// clause subqueries to the tables of `outer`, the enclosing query scope. Those may have
+    /// changed since this query was planned, e.g. the result registers of a FROM clause
+    /// subquery are only known once it has been emitted.
+    pub fn update_outer_query_refs(&mut self, outer: &TableReferences) {
+        for outer_query_ref in self.outer_query_refs.iter_mut() {
+            if let Some(table) = outer.find_table_by_internal_id(outer_query_ref.internal_id) {
+                outer_query_ref.table = table.clone();
+            }
+        }
+        for joined_table in self.joined_tables.iter_mut() {
+            if let Table::FromClauseSubquery(subquery) = &mut joined_table.table {
+                for plan in subquery.selects_mut() {
+                    plan.table_references.update_outer_query_refs(outer);
+                }
+            }
+        }
+    }
+
     pub fn contains_table(&self, table: &Table) -> bool {
         self.joined_tables.iter().any(|t| t.table == *table)
             || self.outer_query_refs.iter().any(|t| t.table == *table)
@@ -824,6 +1031,7 @@ impl TableReferences {
     pub fn extend(&mut self, other: TableReferences) {
         self.joined_tables.extend(other.joined_tables);
         self.outer_query_refs.extend(other.outer_query_refs);
+        self.expr_subqueries.extend(other.expr_subqueries);
     }
 }
 
@@ -877,8 +1085,9 @@ impl Operation {
     pub fn index(&self) -> Option<&Arc<Index>> {
         match self {
             Operation::Scan { index, .. } => index.as_ref(),
-            Operation::Search(Search::RowidEq { .. }) => None,
+            Operation::Search(Search::RowidEq { .. } | Search::MultiIndexOr { .. }) => None,
             Operation::Search(Search::Seek { index, .. }) => index.as_ref(),
+            Operation::Search(Search::HashJoin { index, .. }) => Some(index),
         }
     }
 }
@@ -905,28 +1114,64 @@ impl JoinedTable {
         join_info: Option<JoinInfo>,
         internal_id: TableInternalId,
     ) -> Self {
-        let columns = plan
-            .result_columns
-            .iter()
-            .map(|rc| Column {
-                name: rc.name(&plan.table_references).map(String::from),
-                ty: Type::Blob, // FIXME: infer proper type
-                ty_str: "BLOB".to_string(),
-                is_rowid_alias: false,
-                primary_key: false,
-                notnull: false,
-                default: None,
-                unique: false,
-                collation: None, // FIXME: infer collation from subquery
-                hidden: false,
-            })
-            .collect();
+        let columns = subquery_columns(&plan);
+        Self::from_subquery(identifier, plan, columns, None, join_info, internal_id)
+    }
 
+    /// Creates a new TableReference for a subquery that may be a compound SELECT.
+    /// The columns of a compound SELECT are those of its leftmost SELECT.
+    pub fn new_subquery_from_plan(
+        identifier: String,
+        plan: Plan,
+        join_info: Option<JoinInfo>,
+        internal_id: TableInternalId,
+    ) -> Self {
+        match plan {
+            Plan::Select(plan) => Self::new_subquery(identifier, plan, join_info, internal_id),
+            Plan::CompoundSelect {
+                left,
+                right_most,
+                limit,
+                offset,
+                order_by,
+            } => {
+                let columns = subquery_columns(&left[0].0);
+                let compound = CompoundSubquery {
+                    left,
+                    limit,
+                    offset,
+                    order_by,
+                };
+                Self::from_subquery(
+                    identifier,
+                    right_most,
+                    columns,
+                    Some(Box::new(compound)),
+                    join_info,
+                    internal_id,
+                )
+            }
+            Plan::Delete(_) | Plan::Update(_) => unreachable!("a subquery is a SELECT"),
+        }
+    }
+
+    fn from_subquery(
+        identifier: String,
+        plan: SelectPlan,
+        columns: Vec<Column>,
+        compound: Option<Box<CompoundSubquery>>,
+        join_info: Option<JoinInfo>,
+        internal_id: TableInternalId,
+    ) -> Self {
         let table = Table::FromClauseSubquery(FromClauseSubquery {
             name: identifier.clone(),
             plan: Box::new(plan),
             columns,
             result_columns_start_reg: None,
+            recursive: None,
+            compound,
+            is_recursive_reference: false,
+            cte: None,
         });
         Self {
             op: Operation::Scan {
@@ -938,6 +1183,7 @@ impl JoinedTable {
             internal_id,
             join_info,
             col_used_mask: ColumnUsedMask::default(),
+            database_id: 0,
         }
     }
 
@@ -975,9 +1221,15 @@ impl JoinedTable {
                     ))
                 };
                 let index_cursor_id = index.map(|index| {
+                    let cursor_type = match self.op {
+                        Operation::Search(Search::HashJoin { .. }) => {
+                            CursorType::HashTable(index.clone())
+                        }
+                        _ => CursorType::BTreeIndex(index.clone()),
+                    };
                     program.alloc_cursor_id_keyed(
                         CursorKey::index(self.internal_id, index.clone()),
-                        CursorType::BTreeIndex(index.clone()),
+                        cursor_type,
                     )
                 });
                 Ok((table_cursor_id, index_cursor_id))
@@ -1012,11 +1264,15 @@ impl JoinedTable {
         let Table::BTree(btree) = &self.table else {
             return false;
         };
+        // The b-tree of a WITHOUT ROWID table holds the whole row under its PRIMARY KEY
+        if !btree.has_rowid && index.root_page == btree.root_page {
+            return true;
+        }
         if self.col_used_mask.is_empty() {
             return false;
         }
         let mut index_cols_mask = ColumnUsedMask::default();
-        for col in index.columns.iter() {
+        for col in index.columns.iter().filter(|col| col.expr.is_none()) {
             index_cols_mask.set(col.pos_in_table);
         }
 
@@ -1042,6 +1298,10 @@ impl JoinedTable {
     /// Returns true if the index selected for use with this [TableReference] is a covering index,
     /// meaning that it contains all the columns that are referenced in the query.
     pub fn utilizes_covering_index(&self) -> bool {
+        // The columns of a table joined with a hash join are always read from its hash table.
+        if matches!(self.op, Operation::Search(Search::HashJoin { .. })) {
+            return true;
+        }
         let Some(index) = self.op.index() else {
             return false;
         };
@@ -1053,6 +1313,39 @@ impl JoinedTable {
     }
 }
 
+/// The columns of a FROM clause subquery, named after the result columns of its SELECT.
+fn subquery_columns(plan: &SelectPlan) -> Vec<Column> {
+    plan.result_columns
+        .iter()
+        .map(|rc| {
+            // A result column that is a plain column reference keeps the declared type
+            // and collation of the column it refers to.
+            let source_column = match &rc.expr {
+                ast::Expr::Column { table, column, .. } => plan
+                    .table_references
+                    .find_table_by_internal_id(*table)
+                    .and_then(|table| table.columns().get(*column)),
+                _ => None,
+            };
+            Column {
+                name: rc.name(&plan.table_references).map(String::from),
+                ty: source_column.map_or(Type::Blob, |c| c.ty),
+                ty_str: source_column.map_or_else(String::new, |c| c.ty_str.clone()),
+                is_rowid_alias: false,
+                primary_key: false,
+                notnull: false,
+                default: None,
+                unique: false,
+                collation: source_column.and_then(|c| c.collation),
+                hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
+            }
+        })
+        .collect()
+}
+
 /// A definition of a rowid/index search.
 ///
 /// [SeekKey] is the condition that is used to seek to a specific row in a table/index.
@@ -1065,6 +1358,9 @@ pub struct SeekDef {
     /// - SELECT * FROM t WHERE x = 1 AND y >= 30
     ///
     /// The key is [(1, ASC), (30, DESC)]
+    ///
+    /// An equality with an `x IN (...)` list is keyed by the [ast::Expr::InList] itself: the seek
+    /// is repeated for each distinct value of the list.
     pub key: Vec<(ast::Expr, SortOrder)>,
     /// The condition to use when seeking. See [SeekKey] for more details.
     pub seek: Option<SeekKey>,
@@ -1119,6 +1415,29 @@ pub enum Search {
         index: Option<Arc<Index>>,
         seek_def: SeekDef,
     },
+    /// A search for the rows matching any of the operands of an OR term, e.g. `a = 1 OR b = 2`.
+    /// Each branch is a [Search::RowidEq] or [Search::Seek] for one operand. The rowids they find
+    /// are collected into an ephemeral index, which removes duplicates, and the rows are then
+    /// looked up by rowid in the table.
+    MultiIndexOr { branches: Vec<Search> },
+    /// A hash join: the rows of the table are inserted once into a hash table keyed by the columns
+    /// of some equalities, which is then probed with the values compared with those columns.
+    /// The rows hold the columns of `index`, whose first columns are the key columns, and the rowid.
+    HashJoin {
+        index: Arc<Index>,
+        keys: Vec<HashJoinKey>,
+    },
+}
+
+/// A column of the key of a [Search::HashJoin], in the order of the columns of its index.
+#[derive(Clone, Debug)]
+pub struct HashJoinKey {
+    /// The expression compared with the column, whose value is probed for.
+    pub expr: ast::Expr,
+    /// The affinity of the comparison, applied to both the column and the expression.
+    pub affinity: Affinity,
+    /// The collating sequence of the comparison.
+    pub collation: CollationSeq,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -1134,3 +1453,84 @@ impl Aggregate {
         self.distinctness.is_distinct()
     }
 }
+
+/// The window functions of a SELECT, grouped by the window they are computed over.
+#[derive(Debug, Clone)]
+pub struct WindowPlan {
+    /// The windows are computed one after another, each one sorting the rows by its own
+    /// PARTITION BY and ORDER BY. The rows are output in the order of the last window.
+    pub windows: Vec<Window>,
+    /// Columns that the result columns and the ORDER BY of the query reference outside of window functions.
+    /// They are carried through the window sorters, because the table cursors are no longer positioned
+    /// on the row when the window functions are computed.
+    pub columns: Vec<ast::Expr>,
+}
+
+impl WindowPlan {
+    pub fn functions(&self) -> impl Iterator<Item = &WindowFunction> {
+        self.windows
+            .iter()
+            .flat_map(|window| window.functions.iter())
+    }
+}
+
+/// A window, i.e. a PARTITION BY and ORDER BY shared by one or more window functions.
+/// Each window function may have its own frame.
+#[derive(Debug, Clone)]
+pub struct Window {
+    /// PARTITION BY expressions of the window.
+    pub partition_by: Vec<ast::Expr>,
+    /// ORDER BY expressions of the window. Rows with equal ORDER BY values are peers.
+    pub order_by: Vec<(ast::Expr, SortOrder)>,
+    /// The window functions computed over the window.
+    pub functions: Vec<WindowFunction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowFunction {
+    pub func: WindowFunctionKind,
+    pub args: Vec<ast::Expr>,
+    /// FILTER clause of an aggregate window function.
+    pub filter: Option<ast::Expr>,
+    pub frame: WindowFrame,
+    pub original_expr: ast::Expr,
+}
+
+#[derive(Debug, Clone)]
+pub enum WindowFunctionKind {
+    /// A built-in window function, e.g. row_number().
+    Builtin(WindowFunc),
+    /// An aggregate function used as a window function, e.g. sum(x) OVER (ORDER BY y).
+    Aggregate(Aggregate),
+}
+
+impl WindowFunctionKind {
+    /// Whether the result of the function depends on the frame of the window.
+    pub fn uses_frame(&self) -> bool {
+        match self {
+            WindowFunctionKind::Builtin(func) => func.uses_frame(),
+            WindowFunctionKind::Aggregate(_) => true,
+        }
+    }
+}
+
+/// The frame of a window function, with the defaults of an omitted frame specification filled in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowFrame {
+    pub mode: ast::FrameMode,
+    pub start: ast::FrameBound,
+    pub end: ast::FrameBound,
+    pub exclude: ast::FrameExclude,
+}
+
+impl Default for WindowFrame {
+    /// RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW
+    fn default() -> Self {
+        Self {
+            mode: ast::FrameMode::Range,
+            start: ast::FrameBound::UnboundedPreceding,
+            end: ast::FrameBound::CurrentRow,
+            exclude: ast::FrameExclude::NoOthers,
+        }
+    }
+}
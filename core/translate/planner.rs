@@ -1,19 +1,21 @@
-use std::cell::Cell;
+use std::{cell::Cell, rc::Rc};
 
 use super::{
     expr::walk_expr,
     plan::{
-        Aggregate, ColumnUsedMask, Distinctness, EvalAt, IterationDirection, JoinInfo,
-        JoinOrderMember, JoinedTable, Operation, OuterQueryReference, Plan, QueryDestination,
-        ResultSetColumn, TableReferences, WhereTerm,
+        select_star, Aggregate, ColumnUsedMask, CteDefinition, Distinctness, EvalAt,
+        IterationDirection, JoinInfo, JoinOrderMember, JoinedTable, Operation, OuterQueryReference,
+        Plan, QueryDestination, RecursiveCte, ResultSetColumn, SelectPlan, TableReferences,
+        WhereTerm, Window, WindowFrame, WindowFunction, WindowFunctionKind, WindowPlan,
     },
-    select::prepare_select_plan,
+    select::{prepare_select_plan, resolve_compound_order_by},
+    view::view_as_subquery,
     SymbolTable,
 };
 use crate::translate::expr::WalkControl;
 use crate::{
-    function::Func,
-    schema::{Schema, Table},
+    function::{AggFunc, Func, WindowFunc},
+    schema::{FromClauseSubquery, Schema, Table},
     translate::expr::walk_expr_mut,
     util::{exprs_are_equivalent, normalize_ident},
     vdbe::{builder::TableRefIdCounter, BranchOffset},
@@ -21,19 +23,19 @@ use crate::{
 };
 use turso_sqlite3_parser::ast::Literal::Null;
 use turso_sqlite3_parser::ast::{
-    self, As, Expr, FromClause, JoinType, Limit, Materialized, QualifiedName, TableInternalId,
-    UnaryOperator, With,
+    self, As, Expr, FromClause, IndexedColumn, JoinType, Limit, QualifiedName, ResultColumn,
+    TableInternalId, UnaryOperator, With,
 };
 
 pub const ROWID: &str = "rowid";
 
-pub fn resolve_aggregates(
-    schema: &Schema,
-    top_level_expr: &Expr,
-    aggs: &mut Vec<Aggregate>,
-) -> Result<bool> {
+pub fn resolve_aggregates(top_level_expr: &Expr, aggs: &mut Vec<Aggregate>) -> Result<bool> {
     let mut contains_aggregates = false;
     walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        // Aggregates used as window functions are computed over their window, see [resolve_window_functions]
+        if window_function_over_clause(expr).is_some() {
+            return Ok(WalkControl::SkipChildren);
+        }
         if aggs
             .iter()
             .any(|a| exprs_are_equivalent(&a.original_expr, expr))
@@ -56,11 +58,6 @@ pub fn resolve_aggregates(
                 match Func::resolve_function(&name.0, args_count) {
                     Ok(Func::Agg(f)) => {
                         let distinctness = Distinctness::from_ast(distinctness.as_ref());
-                        if !schema.indexes_enabled() && distinctness.is_distinct() {
-                            crate::bail_parse_error!(
-                                "SELECT with DISTINCT is not allowed without indexes enabled"
-                            );
-                        }
                         let num_args = args.as_ref().map_or(0, |args| args.len());
                         if distinctness.is_distinct() && num_args != 1 {
                             crate::bail_parse_error!(
@@ -78,7 +75,7 @@ pub fn resolve_aggregates(
                     _ => {
                         if let Some(args) = args {
                             for arg in args.iter() {
-                                contains_aggregates |= resolve_aggregates(schema, arg, aggs)?;
+                                contains_aggregates |= resolve_aggregates(arg, aggs)?;
                             }
                         }
                     }
@@ -104,10 +101,318 @@ pub fn resolve_aggregates(
     Ok(contains_aggregates)
 }
 
+/// Returns the OVER clause of an expression if it is a window function call.
+pub fn window_function_over_clause(expr: &Expr) -> Option<&ast::Over> {
+    match expr {
+        Expr::FunctionCall {
+            filter_over: Some(filter_over),
+            ..
+        }
+        | Expr::FunctionCallStar {
+            filter_over: Some(filter_over),
+            ..
+        } => filter_over.over_clause.as_deref(),
+        _ => None,
+    }
+}
+
+/// Collects the window functions of a result column or ORDER BY expression into `window_plan`.
+/// Window functions are grouped by their PARTITION BY and ORDER BY into [Window]s.
+pub fn resolve_window_functions(
+    top_level_expr: &Expr,
+    window_defs: &[ast::WindowDef],
+    window_plan: &mut Option<WindowPlan>,
+) -> Result<()> {
+    walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        let (name, distinctness, args, filter_over) = match expr {
+            Expr::FunctionCall {
+                name,
+                distinctness,
+                args,
+                filter_over,
+                ..
+            } => (
+                name,
+                distinctness.as_ref(),
+                args.as_deref().unwrap_or_default(),
+                filter_over,
+            ),
+            Expr::FunctionCallStar { name, filter_over } => (name, None, &[][..], filter_over),
+            _ => return Ok(WalkControl::Continue),
+        };
+        let Some(over) = window_function_over_clause(expr) else {
+            if matches!(expr, Expr::FunctionCall { .. })
+                && WindowFunc::resolve_function(&name.0, args.len())?.is_some()
+            {
+                crate::bail_parse_error!("misuse of window function {}()", name.0);
+            }
+            return Ok(WalkControl::Continue);
+        };
+        let window = resolve_window(over, window_defs)?;
+        let filter = filter_over
+            .as_ref()
+            .and_then(|filter_over| filter_over.filter_clause.as_deref());
+        for nested_expr in args
+            .iter()
+            .chain(filter)
+            .chain(window.partition_by.iter().flatten())
+            .chain(window.order_by.iter().flatten().map(|col| &col.expr))
+        {
+            ensure_no_window_functions(nested_expr)?;
+        }
+        if distinctness.is_some() {
+            crate::bail_parse_error!("DISTINCT is not supported for window functions");
+        }
+
+        let func = match WindowFunc::resolve_function(&name.0, args.len())? {
+            Some(func) => {
+                if filter.is_some() {
+                    crate::bail_parse_error!(
+                        "FILTER clause may only be used with aggregate window functions"
+                    );
+                }
+                WindowFunctionKind::Builtin(func)
+            }
+            None => match Func::resolve_function(&name.0, args.len())? {
+                Func::Agg(func) => {
+                    // count(*) and count() count rows, like COUNT(1)
+                    let args = if args.is_empty() && matches!(func, AggFunc::Count0) {
+                        vec![Expr::Literal(ast::Literal::Numeric("1".to_string()))]
+                    } else {
+                        args.to_vec()
+                    };
+                    WindowFunctionKind::Aggregate(Aggregate {
+                        func,
+                        args,
+                        original_expr: expr.clone(),
+                        distinctness: Distinctness::NonDistinct,
+                    })
+                }
+                _ => crate::bail_parse_error!("{}() may not be used as a window function", name.0),
+            },
+        };
+        let args = match &func {
+            WindowFunctionKind::Aggregate(agg) => agg.args.clone(),
+            WindowFunctionKind::Builtin(_) => args.to_vec(),
+        };
+
+        let order_by: Vec<(Expr, ast::SortOrder)> = window
+            .order_by
+            .unwrap_or_default()
+            .into_iter()
+            .map(|col| (col.expr, col.order.unwrap_or(ast::SortOrder::Asc)))
+            .collect();
+        let frame = resolve_window_frame(window.frame_clause, order_by.len())?;
+        let partition_by = window.partition_by.unwrap_or_default();
+
+        let window_plan = window_plan.get_or_insert_with(|| WindowPlan {
+            windows: vec![],
+            columns: vec![],
+        });
+        let same_exprs = |a: &[Expr], b: &[Expr]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| exprs_are_equivalent(a, b))
+        };
+        let existing_window = window_plan.windows.iter_mut().find(|w| {
+            same_exprs(&w.partition_by, &partition_by)
+                && w.order_by.len() == order_by.len()
+                && w.order_by
+                    .iter()
+                    .zip(&order_by)
+                    .all(|((a, a_order), (b, b_order))| {
+                        a_order == b_order && exprs_are_equivalent(a, b)
+                    })
+        });
+        let window = match existing_window {
+            Some(window) => window,
+            None => {
+                window_plan.windows.push(Window {
+                    partition_by,
+                    order_by,
+                    functions: vec![],
+                });
+                window_plan.windows.last_mut().unwrap()
+            }
+        };
+        if !window
+            .functions
+            .iter()
+            .any(|f| exprs_are_equivalent(&f.original_expr, expr))
+        {
+            window.functions.push(WindowFunction {
+                func,
+                args,
+                filter: filter.cloned(),
+                frame,
+                original_expr: expr.clone(),
+            });
+        }
+        Ok(WalkControl::SkipChildren)
+    })?;
+    Ok(())
+}
+
+/// Collects the columns that an expression references outside of window functions, see [WindowPlan::columns].
+pub fn resolve_window_columns(top_level_expr: &Expr, window_plan: &mut WindowPlan) -> Result<()> {
+    walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        match expr {
+            _ if window_function_over_clause(expr).is_some() => {
+                return Ok(WalkControl::SkipChildren);
+            }
+            Expr::Column { .. } | Expr::RowId { .. } => {
+                if !window_plan
+                    .columns
+                    .iter()
+                    .any(|column| exprs_are_equivalent(column, expr))
+                {
+                    window_plan.columns.push(expr.clone());
+                }
+            }
+            _ => {}
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(())
+}
+
+fn ensure_no_window_functions(top_level_expr: &Expr) -> Result<()> {
+    walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        if window_function_over_clause(expr).is_some() {
+            let (Expr::FunctionCall { name, .. } | Expr::FunctionCallStar { name, .. }) = expr
+            else {
+                unreachable!();
+            };
+            crate::bail_parse_error!("misuse of window function {}()", name.0);
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(())
+}
+
+/// Rejects the aggregate and window functions of an expression that is evaluated once per row
+/// outside of a SELECT, such as a RETURNING column.
+pub(crate) fn ensure_no_aggregates(top_level_expr: &Expr) -> Result<()> {
+    ensure_no_window_functions(top_level_expr)?;
+    walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        match expr {
+            Expr::FunctionCall { name, args, .. } => {
+                let args_count = args.as_ref().map_or(0, |args| args.len());
+                if WindowFunc::resolve_function(&name.0, args_count)?.is_some() {
+                    crate::bail_parse_error!("misuse of window function {}()", name.0);
+                }
+                if let Ok(Func::Agg(_)) = Func::resolve_function(&name.0, args_count) {
+                    crate::bail_parse_error!("misuse of aggregate function {}()", name.0);
+                }
+            }
+            Expr::FunctionCallStar { name, .. } => {
+                if let Ok(Func::Agg(_)) = Func::resolve_function(&name.0, 0) {
+                    crate::bail_parse_error!("misuse of aggregate function {}()", name.0);
+                }
+            }
+            _ => {}
+        }
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(())
+}
+
+/// Resolves the window of an OVER clause, which may name a window of the WINDOW clause,
+/// or extend one of them, e.g. `OVER (w ORDER BY x)`.
+fn resolve_window(over: &ast::Over, window_defs: &[ast::WindowDef]) -> Result<ast::Window> {
+    match over {
+        ast::Over::Name(name) => resolve_named_window(name, window_defs),
+        ast::Over::Window(window) => extend_window(window, window_defs),
+    }
+}
+
+fn resolve_named_window(name: &ast::Name, window_defs: &[ast::WindowDef]) -> Result<ast::Window> {
+    let name_normalized = normalize_ident(&name.0);
+    // A window definition can only extend the windows defined before it
+    let Some(idx) = window_defs
+        .iter()
+        .position(|def| normalize_ident(&def.name.0) == name_normalized)
+    else {
+        crate::bail_parse_error!("no such window: {}", name.0);
+    };
+    extend_window(&window_defs[idx].window, &window_defs[..idx])
+}
+
+fn extend_window(window: &ast::Window, window_defs: &[ast::WindowDef]) -> Result<ast::Window> {
+    let Some(base_name) = &window.base else {
+        return Ok(window.clone());
+    };
+    let base = resolve_named_window(base_name, window_defs)?;
+    if window.partition_by.is_some() {
+        crate::bail_parse_error!(
+            "cannot override PARTITION clause of window: {}",
+            base_name.0
+        );
+    }
+    if window.order_by.is_some() && base.order_by.is_some() {
+        crate::bail_parse_error!("cannot override ORDER BY clause of window: {}", base_name.0);
+    }
+    if base.frame_clause.is_some() {
+        crate::bail_parse_error!(
+            "cannot override frame specification of window: {}",
+            base_name.0
+        );
+    }
+    Ok(ast::Window {
+        base: None,
+        partition_by: base.partition_by,
+        order_by: window.order_by.clone().or(base.order_by),
+        frame_clause: window.frame_clause.clone(),
+    })
+}
+
+fn resolve_window_frame(
+    frame_clause: Option<ast::FrameClause>,
+    order_by_len: usize,
+) -> Result<WindowFrame> {
+    let Some(frame_clause) = frame_clause else {
+        return Ok(WindowFrame::default());
+    };
+    let frame = WindowFrame {
+        mode: frame_clause.mode,
+        start: frame_clause.start,
+        end: frame_clause.end.unwrap_or(ast::FrameBound::CurrentRow),
+        exclude: frame_clause.exclude.unwrap_or(ast::FrameExclude::NoOthers),
+    };
+    // The frame cannot end before it starts, e.g. BETWEEN CURRENT ROW AND 1 PRECEDING
+    let bound_rank = |bound: &ast::FrameBound| match bound {
+        ast::FrameBound::UnboundedPreceding => 0,
+        ast::FrameBound::Preceding(_) => 1,
+        ast::FrameBound::CurrentRow => 2,
+        ast::FrameBound::Following(_) => 3,
+        ast::FrameBound::UnboundedFollowing => 4,
+    };
+    if matches!(frame.start, ast::FrameBound::UnboundedFollowing)
+        || matches!(frame.end, ast::FrameBound::UnboundedPreceding)
+        || bound_rank(&frame.start) > bound_rank(&frame.end)
+    {
+        crate::bail_parse_error!("unsupported frame specification");
+    }
+    let has_offset = |bound: &ast::FrameBound| {
+        matches!(
+            bound,
+            ast::FrameBound::Preceding(_) | ast::FrameBound::Following(_)
+        )
+    };
+    if frame.mode == ast::FrameMode::Range
+        && (has_offset(&frame.start) || has_offset(&frame.end))
+        && order_by_len != 1
+    {
+        crate::bail_parse_error!(
+            "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression"
+        );
+    }
+    Ok(frame)
+}
+
 pub fn bind_column_references(
     top_level_expr: &mut Expr,
     referenced_tables: &mut TableReferences,
     result_columns: Option<&[ResultSetColumn]>,
+    schema: &Schema,
 ) -> Result<()> {
     walk_expr_mut(top_level_expr, &mut |expr: &mut Expr| -> Result<()> {
         match expr {
@@ -119,7 +424,12 @@ pub fn bind_column_references(
                 }
                 let normalized_id = normalize_ident(id.0.as_str());
 
-                if !referenced_tables.joined_tables().is_empty() {
+                // WITHOUT ROWID tables have no rowid to refer to
+                if referenced_tables
+                    .joined_tables()
+                    .first()
+                    .is_some_and(|table| table.btree().is_none_or(|table| table.has_rowid))
+                {
                     if let Some(row_id_expr) = parse_row_id(
                         &normalized_id,
                         referenced_tables.joined_tables()[0].internal_id,
@@ -202,18 +512,51 @@ pub fn bind_column_references(
                 }
                 crate::bail_parse_error!("Column {} not found", id.0);
             }
-            Expr::Qualified(tbl, id) => {
-                let normalized_table_name = normalize_ident(tbl.0.as_str());
-                let matching_tbl = referenced_tables
-                    .find_table_and_internal_id_by_identifier(&normalized_table_name);
-                if matching_tbl.is_none() {
-                    crate::bail_parse_error!("no such table: {}", normalized_table_name);
-                }
-                let (tbl_id, tbl) = matching_tbl.unwrap();
-                let normalized_id = normalize_ident(id.0.as_str());
+            Expr::Qualified(..) | Expr::DoublyQualified(..) => {
+                let (tbl_id, tbl, normalized_id) = match expr {
+                    Expr::Qualified(tbl, id) => {
+                        let normalized_table_name = normalize_ident(tbl.0.as_str());
+                        let matching_tbl = referenced_tables
+                            .find_table_and_internal_id_by_identifier(&normalized_table_name);
+                        if matching_tbl.is_none() {
+                            crate::bail_parse_error!("no such table: {}", normalized_table_name);
+                        }
+                        let (tbl_id, tbl) = matching_tbl.unwrap();
+                        (tbl_id, tbl, normalize_ident(id.0.as_str()))
+                    }
+                    Expr::DoublyQualified(db_name, tbl, id) => {
+                        // schema.table.column refers to a table of the FROM clause that is not aliased
+                        let database_id = schema.database_index(&db_name.0)?;
+                        let normalized_table_name = normalize_ident(tbl.0.as_str());
+                        let Some(joined_table) =
+                            referenced_tables.joined_tables().iter().find(|t| {
+                                t.database_id == database_id
+                                    && t.identifier == normalized_table_name
+                                    && t.table.get_name().eq_ignore_ascii_case(&t.identifier)
+                            })
+                        else {
+                            crate::bail_parse_error!(
+                                "no such column: {}.{}.{}",
+                                db_name.0,
+                                tbl.0,
+                                id.0
+                            );
+                        };
+                        (
+                            joined_table.internal_id,
+                            &joined_table.table,
+                            normalize_ident(id.0.as_str()),
+                        )
+                    }
+                    _ => unreachable!(),
+                };
 
-                if let Some(row_id_expr) = parse_row_id(&normalized_id, tbl_id, || false)? {
+                let has_rowid = tbl.btree().is_none_or(|table| table.has_rowid);
+                if let Some(row_id_expr) =
+                    parse_row_id(&normalized_id, tbl_id, || false)?.filter(|_| has_rowid)
+                {
                     *expr = row_id_expr;
+                    referenced_tables.mark_rowid_used(tbl_id);
 
                     return Ok(());
                 }
@@ -245,13 +588,14 @@ fn parse_from_clause_table(
     table: ast::SelectTable,
     table_references: &mut TableReferences,
     out_where_clause: &mut Vec<WhereTerm>,
-    ctes: &mut Vec<JoinedTable>,
+    ctes: &[JoinedTable],
     syms: &SymbolTable,
     table_ref_counter: &mut TableRefIdCounter,
 ) -> Result<()> {
     match table {
         ast::SelectTable::Table(qualified_name, maybe_alias, _) => parse_table(
             schema,
+            syms,
             table_references,
             ctes,
             table_ref_counter,
@@ -261,7 +605,7 @@ fn parse_from_clause_table(
             None,
         ),
         ast::SelectTable::Select(subselect, maybe_alias) => {
-            let Plan::Select(subplan) = prepare_select_plan(
+            let subplan = prepare_select_plan(
                 schema,
                 *subselect,
                 syms,
@@ -271,10 +615,7 @@ fn parse_from_clause_table(
                     yield_reg: usize::MAX, // will be set later in bytecode emission
                     coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
                 },
-            )?
-            else {
-                crate::bail_parse_error!("Only non-compound SELECT queries are currently supported in FROM clause subqueries");
-            };
+            )?;
             let cur_table_index = table_references.joined_tables().len();
             let identifier = maybe_alias
                 .map(|a| match a {
@@ -282,16 +623,23 @@ fn parse_from_clause_table(
                     ast::As::Elided(id) => id.0.clone(),
                 })
                 .unwrap_or(format!("subquery_{cur_table_index}"));
-            table_references.add_joined_table(JoinedTable::new_subquery(
+            let subquery = JoinedTable::new_subquery_from_plan(
                 identifier,
                 subplan,
                 None,
                 table_ref_counter.next(),
-            ));
+            );
+            if let Table::FromClauseSubquery(from_clause_subquery) = &subquery.table {
+                for plan in from_clause_subquery.selects() {
+                    table_references.mark_outer_query_refs_used(&plan.table_references);
+                }
+            }
+            table_references.add_joined_table(subquery);
             Ok(())
         }
         ast::SelectTable::TableCall(qualified_name, maybe_args, maybe_alias) => parse_table(
             schema,
+            syms,
             table_references,
             ctes,
             table_ref_counter,
@@ -307,8 +655,9 @@ fn parse_from_clause_table(
 #[allow(clippy::too_many_arguments)]
 fn parse_table(
     schema: &Schema,
+    syms: &SymbolTable,
     table_references: &mut TableReferences,
-    ctes: &mut Vec<JoinedTable>,
+    ctes: &[JoinedTable],
     table_ref_counter: &mut TableRefIdCounter,
     out_where_clause: &mut Vec<WhereTerm>,
     qualified_name: QualifiedName,
@@ -316,19 +665,34 @@ fn parse_table(
     maybe_args: Option<Vec<Expr>>,
 ) -> Result<()> {
     let normalized_qualified_name = normalize_ident(qualified_name.name.0.as_str());
+    let cte_identifier = || {
+        maybe_alias
+            .as_ref()
+            .map(|a| match a {
+                ast::As::As(id) => id.0.clone(),
+                ast::As::Elided(id) => id.0.clone(),
+            })
+            .unwrap_or_else(|| normalized_qualified_name.clone())
+    };
     // Check if the FROM clause table is referring to a CTE in the current scope.
-    if let Some(cte_idx) = ctes
+    // Every reference to a CTE is planned from its definition, so it can be referenced multiple times.
+    if let Some(cte_table) = ctes
         .iter()
-        .position(|cte| cte.identifier == normalized_qualified_name)
+        .find(|cte| qualified_name.db_name.is_none() && cte.identifier == normalized_qualified_name)
     {
-        // TODO: what if the CTE is referenced multiple times?
-        let cte_table = ctes.remove(cte_idx);
+        let Table::FromClauseSubquery(FromClauseSubquery { cte: Some(cte), .. }) = &cte_table.table
+        else {
+            unreachable!("CTE must be planned from its definition");
+        };
+        let cte_table = plan_cte(schema, syms, cte, cte_identifier(), table_ref_counter)?;
         table_references.add_joined_table(cte_table);
         return Ok(());
     };
 
-    // Check if our top level schema has this table.
-    if let Some(table) = schema.get_table(&normalized_qualified_name) {
+    // Check if the database the name refers to has this table.
+    let database_id = schema.locate_database(&qualified_name)?;
+    let database_schema = schema.database_schema(database_id);
+    if let Some(table) = database_schema.get_table(&normalized_qualified_name) {
         let alias = maybe_alias
             .map(|a| match a {
                 ast::As::As(id) => id,
@@ -363,37 +727,66 @@ fn parse_table(
             internal_id,
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
+            database_id,
         });
         return Ok(());
     };
 
+    // Views are expanded into FROM clause subqueries.
+    if let Some(view) = database_schema.get_view(&normalized_qualified_name) {
+        let identifier = maybe_alias
+            .map(|a| match a {
+                ast::As::As(id) => id.0,
+                ast::As::Elided(id) => id.0,
+            })
+            .unwrap_or(normalized_qualified_name);
+        let view_table = view_as_subquery(schema, &view, identifier, syms, table_ref_counter)?;
+        table_references.add_joined_table(view_table);
+        return Ok(());
+    }
+
     // CTEs are transformed into FROM clause subqueries.
-    // If we find a CTE with this name in our outer query references,
-    // we can use it as a joined table, but we must clone it since it's not MATERIALIZED.
+    // If we find a CTE with this name in our outer query references, we plan it from its
+    // definition as a joined table. The recursive reference of a recursive CTE to itself has no
+    // definition, its single row is provided by the CTE, so it is cloned.
     //
     // For other types of tables in the outer query references, we do not add them as joined tables,
     // because the query can simply _reference_ them in e.g. the SELECT columns or the WHERE clause,
     // but it's not part of the join order.
-    if let Some(outer_ref) =
-        table_references.find_outer_query_ref_by_identifier(&normalized_qualified_name)
+    if let Some(outer_ref) = table_references
+        .find_outer_query_ref_by_identifier(&normalized_qualified_name)
+        .filter(|_| qualified_name.db_name.is_none())
     {
-        if matches!(outer_ref.table, Table::FromClauseSubquery(_)) {
+        if let Table::FromClauseSubquery(subquery) = &outer_ref.table {
+            if let Some(cte) = subquery.cte.clone() {
+                let cte_table = plan_cte(schema, syms, &cte, cte_identifier(), table_ref_counter)?;
+                table_references.add_joined_table(cte_table);
+                return Ok(());
+            }
             table_references.add_joined_table(JoinedTable {
                 op: Operation::Scan {
                     iter_dir: IterationDirection::Forwards,
                     index: None,
                 },
                 table: outer_ref.table.clone(),
-                identifier: outer_ref.identifier.clone(),
+                identifier: cte_identifier(),
                 internal_id: table_ref_counter.next(),
                 join_info: None,
                 col_used_mask: ColumnUsedMask::default(),
+                database_id: 0,
             });
             return Ok(());
         }
     }
 
-    crate::bail_parse_error!("no such table: {}", normalized_qualified_name);
+    match qualified_name.db_name {
+        Some(db_name) => crate::bail_parse_error!(
+            "no such table: {}.{}",
+            normalize_ident(&db_name.0),
+            normalized_qualified_name
+        ),
+        None => crate::bail_parse_error!("no such table: {}", normalized_qualified_name),
+    }
 }
 
 fn transform_args_into_where_terms(
@@ -433,6 +826,7 @@ fn transform_args_into_where_terms(
             out_where_clause.push(WhereTerm {
                 expr,
                 from_outer_join: None,
+                after_right_join: None,
                 consumed: Cell::new(false),
             });
         }
@@ -478,25 +872,24 @@ pub fn parse_from(
     let mut ctes_as_subqueries = vec![];
 
     if let Some(with) = with {
-        if with.recursive {
-            crate::bail_parse_error!("Recursive CTEs are not yet supported");
-        }
         for cte in with.ctes {
-            if cte.materialized == Materialized::Yes {
-                crate::bail_parse_error!("Materialized CTEs are not yet supported");
-            }
-            if cte.columns.is_some() {
-                crate::bail_parse_error!("CTE columns are not yet supported");
-            }
+            // MATERIALIZED and NOT MATERIALIZED are only hints for the query planner. They are
+            // ignored: every reference to a CTE is planned and evaluated as a subquery of its own.
+            let ast::CommonTableExpr {
+                tbl_name,
+                columns,
+                materialized: _,
+                select,
+            } = cte;
 
             // Check if normalized name conflicts with catalog tables or other CTEs
             // TODO: sqlite actually allows overriding a catalog table with a CTE.
             // We should carry over the 'Scope' struct to all of our identifier resolution.
-            let cte_name_normalized = normalize_ident(&cte.tbl_name.0);
+            let cte_name_normalized = normalize_ident(&tbl_name.0);
             if schema.get_table(&cte_name_normalized).is_some() {
                 crate::bail_parse_error!(
                     "CTE name {} conflicts with catalog table name",
-                    cte.tbl_name.0
+                    tbl_name.0
                 );
             }
             if table_references
@@ -506,7 +899,7 @@ pub fn parse_from(
             {
                 crate::bail_parse_error!(
                     "CTE name {} conflicts with WITH table name {}",
-                    cte.tbl_name.0,
+                    tbl_name.0,
                     cte_name_normalized
                 );
             }
@@ -518,30 +911,22 @@ pub fn parse_from(
                     internal_id: t.internal_id,
                     table: t.table.clone(),
                     col_used_mask: ColumnUsedMask::default(),
+                    rowid_used: false,
                 }
             }));
 
             // CTE can refer to other CTEs that came before it, plus any schema tables or tables in the outer scope.
-            let cte_plan = prepare_select_plan(
-                schema,
-                *cte.select,
-                syms,
-                &outer_query_refs_for_cte,
-                table_ref_counter,
-                QueryDestination::CoroutineYield {
-                    yield_reg: usize::MAX, // will be set later in bytecode emission
-                    coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
-                },
-            )?;
-            let Plan::Select(cte_plan) = cte_plan else {
-                crate::bail_parse_error!("Only SELECT queries are currently supported in CTEs");
-            };
-            ctes_as_subqueries.push(JoinedTable::new_subquery(
-                cte_name_normalized,
-                cte_plan,
-                None,
-                table_ref_counter.next(),
-            ));
+            let cte = Rc::new(CteDefinition {
+                name: cte_name_normalized,
+                columns,
+                select: *select,
+                recursive: with.recursive,
+                outer_query_refs: outer_query_refs_for_cte,
+            });
+            // The CTE is planned even if it is not referenced, to report its errors and to give
+            // the CTEs after it its columns.
+            let cte_table = plan_cte(schema, syms, &cte, cte.name.clone(), table_ref_counter)?;
+            ctes_as_subqueries.push(cte_table);
         }
     }
 
@@ -553,7 +938,7 @@ pub fn parse_from(
         select_owned,
         table_references,
         out_where_clause,
-        &mut ctes_as_subqueries,
+        &ctes_as_subqueries,
         syms,
         table_ref_counter,
     )?;
@@ -563,7 +948,7 @@ pub fn parse_from(
             schema,
             join,
             syms,
-            &mut ctes_as_subqueries,
+            &ctes_as_subqueries,
             out_where_clause,
             table_references,
             table_ref_counter,
@@ -573,22 +958,277 @@ pub fn parse_from(
     Ok(())
 }
 
+/// Plans a reference to a CTE as a FROM clause subquery named `identifier`.
+fn plan_cte(
+    schema: &Schema,
+    syms: &SymbolTable,
+    cte: &Rc<CteDefinition>,
+    identifier: String,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<JoinedTable> {
+    let recursive_cte = if cte.recursive {
+        parse_recursive_cte(
+            schema,
+            &cte.select,
+            &cte.name,
+            cte.columns.as_deref(),
+            syms,
+            &cte.outer_query_refs,
+            table_ref_counter,
+        )?
+    } else {
+        None
+    };
+    let mut cte_table = match recursive_cte {
+        Some(cte_table) => cte_table,
+        None => {
+            let cte_plan = prepare_select_plan(
+                schema,
+                cte.select.clone(),
+                syms,
+                &cte.outer_query_refs,
+                table_ref_counter,
+                QueryDestination::CoroutineYield {
+                    yield_reg: usize::MAX, // will be set later in bytecode emission
+                    coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+                },
+            )?;
+            let mut cte_table = JoinedTable::new_subquery_from_plan(
+                cte.name.clone(),
+                cte_plan,
+                None,
+                table_ref_counter.next(),
+            );
+            rename_cte_columns(&mut cte_table, cte.columns.as_deref())?;
+            cte_table
+        }
+    };
+    let Table::FromClauseSubquery(subquery) = &mut cte_table.table else {
+        unreachable!("CTE must be a FROM clause subquery");
+    };
+    subquery.name = identifier.clone();
+    subquery.cte = Some(cte.clone());
+    cte_table.identifier = identifier;
+    Ok(cte_table)
+}
+
+/// Renames the columns of a CTE to the names in its column list, if it has one.
+fn rename_cte_columns(cte_table: &mut JoinedTable, names: Option<&[IndexedColumn]>) -> Result<()> {
+    let (Some(names), Table::FromClauseSubquery(subquery)) = (names, &mut cte_table.table) else {
+        return Ok(());
+    };
+    if names.len() != subquery.columns.len() {
+        crate::bail_parse_error!(
+            "table {} has {} values for {} columns",
+            cte_table.identifier,
+            subquery.columns.len(),
+            names.len()
+        );
+    }
+    for (column, name) in subquery.columns.iter_mut().zip(names) {
+        column.name = Some(normalize_ident(&name.col_name.0));
+    }
+    Ok(())
+}
+
+/// Counts the tables named `name` that are directly listed in the FROM clause of `select`.
+fn count_references_in_from(select: &ast::OneSelect, name: &str) -> usize {
+    let ast::OneSelect::Select(inner) = select else {
+        return 0;
+    };
+    let Some(from) = inner.from.as_ref() else {
+        return 0;
+    };
+    from.select
+        .iter()
+        .map(|table| table.as_ref())
+        .chain(from.joins.iter().flatten().map(|join| &join.table))
+        .filter(|table| {
+            matches!(table, ast::SelectTable::Table(qualified_name, _, _)
+                if qualified_name.db_name.is_none()
+                    && normalize_ident(&qualified_name.name.0) == name)
+        })
+        .count()
+}
+
+/// Plans a CTE of a WITH RECURSIVE clause. A CTE is recursive if its query is
+/// `initial UNION [ALL] recursive`, where the recursive SELECT refers to the CTE itself.
+/// Returns None if the CTE is not recursive, in which case it is planned like any other CTE.
+fn parse_recursive_cte(
+    schema: &Schema,
+    select: &ast::Select,
+    cte_name: &str,
+    column_names: Option<&[IndexedColumn]>,
+    syms: &SymbolTable,
+    outer_query_refs: &[OuterQueryReference],
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<Option<JoinedTable>> {
+    let Some((recursive_select, initial_compounds)) = select
+        .body
+        .compounds
+        .as_ref()
+        .and_then(|compounds| compounds.split_last())
+    else {
+        return Ok(None);
+    };
+    let union_all = match recursive_select.operator {
+        ast::CompoundOperator::UnionAll => true,
+        ast::CompoundOperator::Union => false,
+        _ => return Ok(None),
+    };
+    let coroutine_destination = || QueryDestination::CoroutineYield {
+        yield_reg: usize::MAX, // will be set later in bytecode emission
+        coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+    };
+
+    // The initial part determines the columns of the CTE, which the recursive part needs.
+    let initial_select = ast::Select {
+        with: select.with.clone(),
+        body: ast::SelectBody {
+            select: select.body.select.clone(),
+            compounds: (!initial_compounds.is_empty()).then(|| initial_compounds.to_vec()),
+        },
+        order_by: None,
+        limit: None,
+    };
+    let Plan::Select(initial_plan) = prepare_select_plan(
+        schema,
+        initial_select,
+        syms,
+        outer_query_refs,
+        table_ref_counter,
+        coroutine_destination(),
+    )?
+    else {
+        crate::bail_parse_error!("Compound SELECT queries are not supported yet in recursive CTEs");
+    };
+    let mut cte_table = JoinedTable::new_subquery(
+        cte_name.to_string(),
+        initial_plan,
+        None,
+        table_ref_counter.next(),
+    );
+    rename_cte_columns(&mut cte_table, column_names)?;
+
+    // The recursive part sees the CTE as an outer query reference to itself.
+    let Table::FromClauseSubquery(cte_subquery) = &mut cte_table.table else {
+        unreachable!("CTE must be a FROM clause subquery");
+    };
+    let mut recursive_reference = cte_subquery.clone();
+    recursive_reference.is_recursive_reference = true;
+    let mut outer_query_refs_for_recursive_part = outer_query_refs.to_vec();
+    outer_query_refs_for_recursive_part.push(OuterQueryReference {
+        identifier: cte_name.to_string(),
+        internal_id: table_ref_counter.next(),
+        table: Table::FromClauseSubquery(recursive_reference),
+        col_used_mask: ColumnUsedMask::default(),
+        rowid_used: false,
+    });
+    // Checked before planning, since planning a join of two references fails on ambiguous columns.
+    if count_references_in_from(&recursive_select.select, cte_name) > 1 {
+        crate::bail_parse_error!("multiple references to recursive table: {}", cte_name);
+    }
+    let recursive_select = ast::Select {
+        with: None,
+        body: ast::SelectBody {
+            select: recursive_select.select.clone(),
+            compounds: None,
+        },
+        order_by: None,
+        limit: None,
+    };
+    let Plan::Select(mut recursive_plan) = prepare_select_plan(
+        schema,
+        recursive_select,
+        syms,
+        &outer_query_refs_for_recursive_part,
+        table_ref_counter,
+        coroutine_destination(),
+    )?
+    else {
+        unreachable!("a SELECT without compounds is planned as a single SELECT");
+    };
+
+    let mut num_recursive_references = 0;
+    for table in recursive_plan.joined_tables() {
+        let Table::FromClauseSubquery(subquery) = &table.table else {
+            continue;
+        };
+        if subquery.is_recursive_reference {
+            num_recursive_references += 1;
+        } else if subquery.selects().any(contains_recursive_reference) {
+            crate::bail_parse_error!("circular reference: {}", cte_name);
+        }
+    }
+    match num_recursive_references {
+        0 => return Ok(None),
+        1 => {}
+        _ => crate::bail_parse_error!("multiple references to recursive table: {}", cte_name),
+    }
+    if recursive_plan.result_columns.len() != cte_subquery.columns.len() {
+        crate::bail_parse_error!(
+            "SELECTs to the left and right of {} do not have the same number of result columns",
+            if union_all { "UNION ALL" } else { "UNION" }
+        );
+    }
+    // The ORDER BY sorts the queue of rows that the recursive part has not been run for yet.
+    let order_by = select
+        .order_by
+        .clone()
+        .map(|order_by| {
+            resolve_compound_order_by(
+                schema,
+                order_by,
+                &mut [&mut cte_subquery.plan, &mut recursive_plan],
+            )
+        })
+        .transpose()?;
+    let (limit, offset) = select
+        .limit
+        .as_ref()
+        .map_or(Ok((None, None)), |limit| parse_limit(limit))?;
+    cte_subquery.recursive = Some(Box::new(RecursiveCte {
+        plan: recursive_plan,
+        union_all,
+        limit,
+        offset,
+        order_by,
+        query_destination: coroutine_destination(),
+    }));
+    Ok(Some(cte_table))
+}
+
+/// Returns true if the plan, or a FROM clause subquery in it, reads from a recursive reference.
+fn contains_recursive_reference(plan: &SelectPlan) -> bool {
+    plan.joined_tables().iter().any(|table| match &table.table {
+        Table::FromClauseSubquery(subquery) => {
+            subquery.is_recursive_reference || subquery.selects().any(contains_recursive_reference)
+        }
+        _ => false,
+    })
+}
+
 pub fn parse_where(
     where_clause: Option<Expr>,
     table_references: &mut TableReferences,
     result_columns: Option<&[ResultSetColumn]>,
     out_where_clause: &mut Vec<WhereTerm>,
+    schema: &Schema,
 ) -> Result<()> {
     if let Some(where_expr) = where_clause {
+        ensure_no_window_functions(&where_expr)?;
         let mut predicates = vec![];
         break_predicate_at_and_boundaries(where_expr, &mut predicates);
         for expr in predicates.iter_mut() {
-            bind_column_references(expr, table_references, result_columns)?;
+            bind_column_references(expr, table_references, result_columns, schema)?;
         }
+        // The WHERE clause applies to the rows of the complete join.
+        let after_right_join = last_right_join(table_references.joined_tables());
         for expr in predicates {
             out_where_clause.push(WhereTerm {
                 expr,
                 from_outer_join: None,
+                after_right_join,
                 consumed: Cell::new(false),
             });
         }
@@ -598,6 +1238,47 @@ pub fn parse_where(
     }
 }
 
+/// Binds the RETURNING clause of an INSERT, UPDATE or DELETE to the table it modifies, the
+/// single table of `table_references`.
+pub fn parse_returning(
+    returning: Option<Vec<ResultColumn>>,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+) -> Result<Vec<ResultSetColumn>> {
+    let mut result_columns = vec![];
+    for result_column in returning.into_iter().flatten() {
+        match result_column {
+            ResultColumn::Star => {
+                select_star(table_references.joined_tables(), &mut result_columns);
+            }
+            ResultColumn::TableStar(name) => {
+                let name = normalize_ident(&name.0);
+                if !table_references
+                    .joined_tables()
+                    .iter()
+                    .any(|table| normalize_ident(&table.identifier) == name)
+                {
+                    crate::bail_parse_error!("no such table: {}", name);
+                }
+                select_star(table_references.joined_tables(), &mut result_columns);
+            }
+            ResultColumn::Expr(mut expr, alias) => {
+                ensure_no_aggregates(&expr)?;
+                bind_column_references(&mut expr, table_references, None, schema)?;
+                result_columns.push(ResultSetColumn {
+                    expr,
+                    alias: alias.map(|alias| match alias {
+                        As::As(alias) => alias.0,
+                        As::Elided(alias) => alias.0,
+                    }),
+                    contains_aggregates: false,
+                });
+            }
+        }
+    }
+    Ok(result_columns)
+}
+
 /**
   Returns the earliest point at which a WHERE term can be evaluated.
   For expressions referencing tables, this is the innermost loop that contains a row for each
@@ -618,7 +1299,26 @@ pub fn determine_where_to_eval_term(
         ));
     }
 
-    determine_where_to_eval_expr(&term.expr, join_order)
+    let eval_at = determine_where_to_eval_expr(&term.expr, join_order)?;
+    let Some(table_id) = term.after_right_join else {
+        return Ok(eval_at);
+    };
+    // The rows of the tables on the left of a RIGHT JOIN may still be NULL-extended
+    // until the loop of its right-hand-side table.
+    let right_join_idx = join_order
+        .iter()
+        .position(|t| t.table_id == table_id)
+        .unwrap_or(usize::MAX);
+    Ok(eval_at.max(EvalAt::Loop(right_join_idx)))
+}
+
+/// The internal ID of the right-hand-side table of the last RIGHT or FULL OUTER JOIN among `tables`, if any.
+pub fn last_right_join(tables: &[JoinedTable]) -> Option<TableInternalId> {
+    tables
+        .iter()
+        .rev()
+        .find(|table| table.join_info.as_ref().is_some_and(|j| j.right))
+        .map(|table| table.internal_id)
 }
 
 /// A bitmask representing a set of tables in a query plan.
@@ -742,6 +1442,16 @@ pub fn table_mask_from_expr(
                     crate::bail_parse_error!("table not found in joined_tables");
                 }
             }
+            // The tables that a subquery references are not tracked here.
+            // Assume it may be correlated with any table in this query.
+            Expr::Exists(_)
+            | Expr::Subquery(_)
+            | Expr::InSelect { .. }
+            | Expr::SubqueryResult { .. } => {
+                for table_idx in 0..table_references.joined_tables().len() {
+                    mask.add_table(table_idx);
+                }
+            }
             _ => {}
         }
         Ok(WalkControl::Continue)
@@ -758,11 +1468,20 @@ pub fn determine_where_to_eval_expr(
     walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
         match expr {
             Expr::Column { table, .. } | Expr::RowId { table, .. } => {
-                let join_idx = join_order
-                    .iter()
-                    .position(|t| t.table_id == *table)
-                    .unwrap_or(usize::MAX);
-                eval_at = eval_at.max(EvalAt::Loop(join_idx));
+                // A table that is not part of the join is a table of an outer query,
+                // whose row doesn't change while the loops of this query run.
+                if let Some(join_idx) = join_order.iter().position(|t| t.table_id == *table) {
+                    eval_at = eval_at.max(EvalAt::Loop(join_idx));
+                }
+            }
+            // See table_mask_from_expr(): a subquery may reference any table in the join.
+            Expr::Exists(_)
+            | Expr::Subquery(_)
+            | Expr::InSelect { .. }
+            | Expr::SubqueryResult { .. } => {
+                if let Some(last_idx) = join_order.len().checked_sub(1) {
+                    eval_at = eval_at.max(EvalAt::Loop(last_idx));
+                }
             }
             _ => {}
         }
@@ -776,7 +1495,7 @@ fn parse_join(
     schema: &Schema,
     join: ast::JoinedSelectTable,
     syms: &SymbolTable,
-    ctes: &mut Vec<JoinedTable>,
+    ctes: &[JoinedTable],
     out_where_clause: &mut Vec<WhereTerm>,
     table_references: &mut TableReferences,
     table_ref_counter: &mut TableRefIdCounter,
@@ -797,15 +1516,45 @@ fn parse_join(
         table_ref_counter,
     )?;
 
-    let (outer, natural) = match join_operator {
+    let (outer, right, natural) = match join_operator {
         ast::JoinOperator::TypedJoin(Some(join_type)) => {
-            let is_outer = join_type.contains(JoinType::OUTER);
+            let is_outer = join_type.contains(JoinType::LEFT);
+            let is_right = join_type.contains(JoinType::RIGHT);
             let is_natural = join_type.contains(JoinType::NATURAL);
-            (is_outer, is_natural)
+            (is_outer, is_right, is_natural)
         }
-        _ => (false, false),
+        _ => (false, false, false),
     };
 
+    let last_idx = table_references.joined_tables().len() - 1;
+    if right {
+        // The rows of the right-hand-side table that match no row are found by scanning it again,
+        // and told apart by their rowid, or by their position for a subquery.
+        let rightmost_table = &table_references.joined_tables()[last_idx];
+        match &rightmost_table.table {
+            Table::BTree(btree) if !btree.has_rowid => {
+                crate::bail_parse_error!(
+                    "RIGHT and FULL OUTER JOIN are not supported on WITHOUT ROWID tables"
+                );
+            }
+            Table::Virtual(_) => {
+                crate::bail_parse_error!(
+                    "RIGHT and FULL OUTER JOIN are not supported on virtual tables"
+                );
+            }
+            Table::FromClauseSubquery(subquery) if subquery.is_recursive_reference => {
+                crate::bail_parse_error!(
+                    "recursive reference may not appear on the right side of a RIGHT or FULL OUTER JOIN: {}",
+                    subquery.name
+                );
+            }
+            _ => {}
+        }
+    }
+    // The conditions of the ON clause are evaluated while joining the tables on its left,
+    // which are only NULL-extended by the RIGHT JOINs that come after it.
+    let after_right_join = last_right_join(&table_references.joined_tables()[..last_idx]);
+
     let mut using = None;
 
     if natural && constraint.is_some() {
@@ -863,16 +1612,17 @@ fn parse_join(
                 let mut preds = vec![];
                 break_predicate_at_and_boundaries(expr, &mut preds);
                 for predicate in preds.iter_mut() {
-                    bind_column_references(predicate, table_references, None)?;
+                    bind_column_references(predicate, table_references, None, schema)?;
                 }
                 for pred in preds {
                     out_where_clause.push(WhereTerm {
                         expr: pred,
-                        from_outer_join: if outer {
+                        from_outer_join: if outer || right {
                             Some(table_references.joined_tables().last().unwrap().internal_id)
                         } else {
                             None
                         },
+                        after_right_join,
                         consumed: Cell::new(false),
                     });
                 }
@@ -949,11 +1699,12 @@ fn parse_join(
                     right_table.mark_column_used(right_col_idx);
                     out_where_clause.push(WhereTerm {
                         expr,
-                        from_outer_join: if outer {
+                        from_outer_join: if outer || right {
                             Some(right_table.internal_id)
                         } else {
                             None
                         },
+                        after_right_join,
                         consumed: Cell::new(false),
                     });
                 }
@@ -963,12 +1714,15 @@ fn parse_join(
     }
 
     assert!(table_references.joined_tables().len() >= 2);
-    let last_idx = table_references.joined_tables().len() - 1;
     let rightmost_table = table_references
         .joined_tables_mut()
         .get_mut(last_idx)
         .unwrap();
-    rightmost_table.join_info = Some(JoinInfo { outer, using });
+    rightmost_table.join_info = Some(JoinInfo {
+        outer,
+        right,
+        using,
+    });
 
     Ok(())
 }
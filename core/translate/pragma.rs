@@ -12,13 +12,15 @@ use crate::storage::pager::AutoVacuumMode;
 use crate::storage::sqlite3_ondisk::MIN_PAGE_CACHE_SIZE;
 use crate::storage::wal::CheckpointMode;
 use crate::translate::schema::translate_create_table;
-use crate::util::{normalize_ident, parse_signed_number, parse_string};
+use crate::translate::view::view_as_subquery;
+use crate::util::{normalize_ident, parse_pragma_bool, parse_signed_number, parse_string};
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
 use crate::vdbe::insn::{Cookie, Insn};
 use crate::{bail_parse_error, storage, CaptureDataChangesMode, LimboError, Value};
 use std::str::FromStr;
 use strum::IntoEnumIterator;
 
+use super::foreign_key::{emit_foreign_key_check, ref_act_name};
 use super::integrity_check::translate_integrity_check;
 use crate::storage::header_accessor;
 use crate::storage::pager::Pager;
@@ -62,7 +64,10 @@ pub fn translate_pragma(
     let (mut program, mode) = match body {
         None => query_pragma(pragma, schema, None, pager, connection, program)?,
         Some(ast::PragmaBody::Equals(value) | ast::PragmaBody::Call(value)) => match pragma {
-            PragmaName::TableInfo => {
+            PragmaName::TableInfo
+            | PragmaName::TableXinfo
+            | PragmaName::ForeignKeyList
+            | PragmaName::ForeignKeyCheck => {
                 query_pragma(pragma, schema, Some(value), pager, connection, program)?
             }
             _ => update_pragma(pragma, schema, value, pager, connection, program)?,
@@ -108,6 +113,14 @@ fn update_pragma(
             connection,
             program,
         ),
+        PragmaName::DatabaseList => query_pragma(
+            PragmaName::DatabaseList,
+            schema,
+            None,
+            pager,
+            connection,
+            program,
+        ),
         PragmaName::PageCount => query_pragma(
             PragmaName::PageCount,
             schema,
@@ -132,11 +145,30 @@ fn update_pragma(
             });
             Ok((program, TransactionMode::Write))
         }
+        PragmaName::RecursiveTriggers => {
+            connection.set_recursive_triggers(parse_pragma_bool(&value)?);
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::AutomaticIndex => {
+            connection.set_automatic_index(parse_pragma_bool(&value)?);
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::IgnoreCheckConstraints => {
+            connection.set_ignore_check_constraints(parse_pragma_bool(&value)?);
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeys => {
+            connection.set_foreign_keys(parse_pragma_bool(&value)?);
+            Ok((program, TransactionMode::None))
+        }
         PragmaName::SchemaVersion => {
             // TODO: Implement updating schema_version
             todo!("updating schema_version not yet implemented")
         }
-        PragmaName::TableInfo => {
+        PragmaName::TableInfo
+        | PragmaName::TableXinfo
+        | PragmaName::ForeignKeyList
+        | PragmaName::ForeignKeyCheck => {
             // because we need control over the write parameter for the transaction,
             // this should be unreachable. We have to force-call query_pragma before
             // getting here
@@ -197,6 +229,7 @@ fn update_pragma(
             program.emit_insn(Insn::Halt {
                 err_code: 0,
                 description: "Early halt because auto vacuum mode is not enabled".to_string(),
+                on_error: ast::ResolveType::Abort,
             });
             program.resolve_label(set_cookie_label, program.offset());
             program.emit_insn(Insn::SetCookie {
@@ -225,7 +258,9 @@ fn update_pragma(
                     )
                     .unwrap(),
                     true,
+                    "",
                     schema,
+                    &connection.syms.borrow(),
                     program,
                 )?;
             }
@@ -295,50 +330,84 @@ fn query_pragma(
             program.add_pragma_result_column(pragma.to_string());
             Ok((program, TransactionMode::Read))
         }
-        PragmaName::TableInfo => {
-            let table = match value {
+        PragmaName::TableInfo | PragmaName::TableXinfo => {
+            // table_xinfo also reports the hidden and generated columns
+            let xinfo = pragma == PragmaName::TableXinfo;
+            let columns = match value {
                 Some(ast::Expr::Name(name)) => {
                     let tbl = normalize_ident(&name.0);
-                    schema.get_table(&tbl)
+                    let database_id =
+                        schema.locate_database(&QualifiedName::single(name.clone()))?;
+                    let database_schema = schema.database_schema(database_id);
+                    if let Some(table) = database_schema.get_table(&tbl) {
+                        table.columns().to_vec()
+                    } else if let Some(view) = database_schema.get_view(&tbl) {
+                        let view_table = view_as_subquery(
+                            schema,
+                            &view,
+                            tbl,
+                            &connection.syms.borrow(),
+                            &mut program.table_reference_counter,
+                        )?;
+                        view_table.columns().to_vec()
+                    } else {
+                        vec![]
+                    }
                 }
-                _ => None,
+                _ => vec![],
             };
 
             let base_reg = register;
-            program.alloc_registers(5);
-            if let Some(table) = table {
-                // According to the SQLite documentation: "The 'cid' column should not be taken to
-                // mean more than 'rank within the current result set'."
-                // Therefore, we enumerate only after filtering out hidden columns.
-                for (i, column) in table.columns().iter().filter(|col| !col.hidden).enumerate() {
-                    // cid
-                    program.emit_int(i as i64, base_reg);
-                    // name
-                    program.emit_string8(column.name.clone().unwrap_or_default(), base_reg + 1);
-
-                    // type
-                    program.emit_string8(column.ty_str.clone(), base_reg + 2);
-
-                    // notnull
-                    program.emit_bool(column.notnull, base_reg + 3);
-
-                    // dflt_value
-                    match &column.default {
-                        None => {
-                            program.emit_null(base_reg + 4, None);
-                        }
-                        Some(expr) => {
-                            program.emit_string8(expr.to_string(), base_reg + 4);
-                        }
+            program.alloc_registers(6);
+            // According to the SQLite documentation: "The 'cid' column should not be taken to
+            // mean more than 'rank within the current result set'."
+            // Therefore, we enumerate only after filtering out hidden columns.
+            for (i, column) in columns
+                .iter()
+                .filter(|col| xinfo || !(col.hidden || col.is_generated()))
+                .enumerate()
+            {
+                // cid
+                program.emit_int(i as i64, base_reg);
+                // name
+                program.emit_string8(column.name.clone().unwrap_or_default(), base_reg + 1);
+
+                // type
+                program.emit_string8(column.ty_str.clone(), base_reg + 2);
+
+                // notnull
+                program.emit_bool(column.notnull, base_reg + 3);
+
+                // dflt_value
+                match &column.default {
+                    None => {
+                        program.emit_null(base_reg + 4, None);
                     }
+                    Some(expr) => {
+                        program.emit_string8(expr.to_string(), base_reg + 4);
+                    }
+                }
 
-                    // pk
-                    program.emit_bool(column.primary_key, base_reg + 5);
-
+                // pk
+                program.emit_bool(column.primary_key, base_reg + 5);
+
+                if xinfo {
+                    // 1 for a hidden column, 2 for a VIRTUAL and 3 for a STORED generated column
+                    let hidden = match &column.generated {
+                        Some(generated) if generated.stored => 3,
+                        Some(_) => 2,
+                        None => column.hidden as i64,
+                    };
+                    program.emit_int(hidden, base_reg + 6);
+                    program.emit_result_row(base_reg, 7);
+                } else {
                     program.emit_result_row(base_reg, 6);
                 }
             }
-            let col_names = ["cid", "name", "type", "notnull", "dflt_value", "pk"];
+            let mut col_names = vec!["cid", "name", "type", "notnull", "dflt_value", "pk"];
+            if xinfo {
+                col_names.push("hidden");
+            }
             for name in col_names {
                 program.add_pragma_result_column(name.into());
             }
@@ -354,6 +423,93 @@ fn query_pragma(
             program.emit_result_row(register, 1);
             Ok((program, TransactionMode::Read))
         }
+        PragmaName::RecursiveTriggers => {
+            program.emit_bool(connection.get_recursive_triggers(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::AutomaticIndex => {
+            program.emit_bool(connection.get_automatic_index(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::IgnoreCheckConstraints => {
+            program.emit_bool(connection.get_ignore_check_constraints(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeys => {
+            program.emit_bool(connection.get_foreign_keys(), register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::DatabaseList => {
+            let base_reg = register;
+            program.alloc_registers(2);
+            for (seq, name, file) in connection.database_list() {
+                program.emit_int(seq as i64, base_reg);
+                program.emit_string8(name, base_reg + 1);
+                program.emit_string8(file, base_reg + 2);
+                program.emit_result_row(base_reg, 3);
+            }
+            let pragma = pragma_for(&pragma);
+            for name in pragma.columns {
+                program.add_pragma_result_column(name.to_string());
+            }
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeyList => {
+            let foreign_keys = match value {
+                Some(ast::Expr::Name(name)) => schema
+                    .get_table(&name.0)
+                    .and_then(|table| table.btree())
+                    .map(|table| table.foreign_keys.clone())
+                    .unwrap_or_default(),
+                _ => vec![],
+            };
+
+            let base_reg = register;
+            program.alloc_registers(7);
+            // Like SQLite, the last declared foreign key has id 0
+            for (id, fk) in foreign_keys.iter().rev().enumerate() {
+                for (seq, from) in fk.child_columns.iter().enumerate() {
+                    program.emit_int(id as i64, base_reg);
+                    program.emit_int(seq as i64, base_reg + 1);
+                    program.emit_string8(fk.parent_table.clone(), base_reg + 2);
+                    program.emit_string8(from.clone(), base_reg + 3);
+                    // to: NULL when the parent key is the PRIMARY KEY
+                    match fk.parent_columns.get(seq) {
+                        Some(to) => program.emit_string8(to.clone(), base_reg + 4),
+                        None => program.emit_null(base_reg + 4, None),
+                    }
+                    program.emit_string8(ref_act_name(fk.on_update).to_string(), base_reg + 5);
+                    program.emit_string8(ref_act_name(fk.on_delete).to_string(), base_reg + 6);
+                    program.emit_string8("NONE".to_string(), base_reg + 7);
+                    program.emit_result_row(base_reg, 8);
+                }
+            }
+            let pragma = pragma_for(&pragma);
+            for name in pragma.columns {
+                program.add_pragma_result_column(name.to_string());
+            }
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeyCheck => {
+            let table_name = match &value {
+                Some(ast::Expr::Name(name)) => Some(name.0.as_str()),
+                _ => None,
+            };
+            emit_foreign_key_check(&mut program, schema, 0, table_name)?;
+            let pragma = pragma_for(&pragma);
+            for name in pragma.columns {
+                program.add_pragma_result_column(name.to_string());
+            }
+            Ok((program, TransactionMode::Read))
+        }
         PragmaName::SchemaVersion => {
             program.emit_insn(Insn::ReadCookie {
                 db: 0,
@@ -391,7 +547,7 @@ fn query_pragma(
             Ok((program, TransactionMode::None))
         }
         PragmaName::IntegrityCheck => {
-            translate_integrity_check(schema, &mut program)?;
+            translate_integrity_check(schema, &connection.syms.borrow(), &mut program)?;
             Ok((program, TransactionMode::Read))
         }
         PragmaName::UnstableCaptureDataChangesConn => {
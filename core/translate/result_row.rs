@@ -9,7 +9,7 @@ use crate::{
 
 use super::{
     emitter::{LimitCtx, Resolver},
-    expr::translate_expr,
+    expr::{translate_expr, translate_expr_no_constant_opt, NoConstantOptReason},
     plan::{Distinctness, QueryDestination, SelectPlan},
 };
 
@@ -45,13 +45,29 @@ pub fn emit_select_result(
             || reg_nonagg_emit_once_flag.is_none()
     }) {
         let reg = start_reg + i;
-        translate_expr(
-            program,
-            Some(&plan.table_references),
-            &rc.expr,
-            reg,
-            resolver,
-        )?;
+        // The registers of the rows yielded to a coroutine may be shared by all the SELECTs of a
+        // compound SELECT, so their values can't be computed once at the start of the program.
+        if matches!(
+            plan.query_destination,
+            QueryDestination::CoroutineYield { .. }
+        ) {
+            translate_expr_no_constant_opt(
+                program,
+                Some(&plan.table_references),
+                &rc.expr,
+                reg,
+                resolver,
+                NoConstantOptReason::RegisterReuse,
+            )?;
+        } else {
+            translate_expr(
+                program,
+                Some(&plan.table_references),
+                &rc.expr,
+                reg,
+                resolver,
+            )?;
+        }
     }
 
     // Handle SELECT DISTINCT deduplication
@@ -128,7 +144,7 @@ pub fn emit_result_row_and_limit(
                 cursor: *table_cursor_id,
                 key_reg: result_columns_start_reg + (plan.result_columns.len() - 1), // Rowid reg is the last register
                 record_reg,
-                flag: InsertFlags(0),
+                flag: InsertFlags::new().ephemeral(true),
                 table_name: table.name.clone(),
             });
         }
@@ -4,43 +4,70 @@ use std::rc::Rc;
 
 use crate::ast;
 use crate::ext::VTabImpl;
+use crate::schema::Affinity;
 use crate::schema::BTreeTable;
 use crate::schema::Column;
 use crate::schema::Schema;
 use crate::schema::Table;
 use crate::schema::Type;
 use crate::storage::pager::CreateBTreeFlags;
+use crate::translate::analyze::{emit_drop_stat1_rows, Stat1Rows};
+use crate::translate::collate::CollationSeq;
+use crate::translate::expr::get_expr_affinity;
+use crate::translate::foreign_key::emit_drop_table_foreign_keys;
+use crate::translate::generated::check_generated_columns;
+use crate::translate::insert::check_check_constraints;
+use crate::translate::plan::{Plan, QueryDestination, TableReferences};
+use crate::translate::select::{prepare_select_plan, translate_select};
 use crate::translate::ProgramBuilder;
 use crate::translate::ProgramBuilderOpts;
 use crate::util::normalize_ident;
 use crate::util::PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX;
-use crate::vdbe::builder::CursorType;
+use crate::vdbe::builder::{CursorType, TableRefIdCounter};
 use crate::vdbe::insn::Cookie;
-use crate::vdbe::insn::{CmpInsFlags, InsertFlags, Insn};
+use crate::vdbe::insn::{CmpInsFlags, InsertFlags, Insn, RegisterOrLiteral};
+use crate::vdbe::BranchOffset;
 use crate::LimboError;
 use crate::SymbolTable;
 use crate::{bail_parse_error, Result};
 
 use turso_ext::VTabKind;
 use turso_sqlite3_parser::ast::{fmt::ToTokens, CreateVirtualTable};
+use turso_sqlite3_parser::dialect::{keyword_token, TokenType};
+use turso_sqlite3_parser::lexer::{sql::Tokenizer, Scanner};
 
+#[allow(clippy::too_many_arguments)]
 pub fn translate_create_table(
     tbl_name: ast::QualifiedName,
     temporary: bool,
     body: ast::CreateTableBody,
     if_not_exists: bool,
+    input: &str,
     schema: &Schema,
+    syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
-    if temporary {
-        bail_parse_error!("TEMPORARY table not supported yet");
-    }
     let opts = ProgramBuilderOpts {
         num_cursors: 1,
         approx_num_insns: 30,
         approx_num_labels: 1,
     };
     program.extend(&opts);
+    // Temporary tables live in the temp database of the connection
+    let database_id = match &tbl_name.db_name {
+        Some(db_name) => {
+            let database_id = schema.database_index(&db_name.0)?;
+            if temporary && database_id != 1 {
+                bail_parse_error!("temporary table name must be unqualified");
+            }
+            database_id
+        }
+        None if temporary => 1,
+        None => 0,
+    };
+    // The SELECT of CREATE TABLE ... AS SELECT can read from any database
+    let select_schema = schema;
+    let schema = schema.database_schema(database_id);
     let normalized_tbl_name = normalize_ident(&tbl_name.name.0);
     if schema.get_table(&normalized_tbl_name).is_some() {
         if if_not_exists {
@@ -50,8 +77,66 @@ pub fn translate_create_table(
         }
         bail_parse_error!("Table {} already exists", normalized_tbl_name);
     }
+    if schema.get_view(&normalized_tbl_name).is_some() {
+        bail_parse_error!("view {} already exists", normalized_tbl_name);
+    }
 
-    let sql = create_table_body_to_str(&tbl_name, &body);
+    // The table of CREATE TABLE ... AS SELECT is created with the columns of the SELECT, and then
+    // filled with its rows
+    let (body, select) = match body {
+        ast::CreateTableBody::AsSelect(select) => {
+            let columns = columns_from_select(
+                select_schema,
+                &select,
+                input,
+                syms,
+                &mut program.table_reference_counter,
+            )?;
+            let body = ast::CreateTableBody::columns_and_constraints_from_definition(
+                columns,
+                None,
+                ast::TableOptions::NONE,
+            )
+            .unwrap();
+            (body, Some(select))
+        }
+        body => (body, None),
+    };
+
+    // Like SQLite, the statement is stored as it was written, unless its columns come from a SELECT
+    let sql = match select {
+        None => create_table_sql(input),
+        Some(_) => None,
+    }
+    .unwrap_or_else(|| create_table_body_to_str(&tbl_name, &body));
+
+    if let ast::CreateTableBody::ColumnsAndConstraints {
+        columns,
+        constraints,
+        ..
+    } = &body
+    {
+        let has_generated_columns = columns.values().any(|column| {
+            column
+                .constraints
+                .iter()
+                .any(|c| matches!(c.constraint, ast::ColumnConstraint::Generated { .. }))
+        });
+        let has_check_constraints = columns.values().any(|column| {
+            column
+                .constraints
+                .iter()
+                .any(|c| matches!(c.constraint, ast::ColumnConstraint::Check(_)))
+        }) || constraints
+            .iter()
+            .flatten()
+            .any(|c| matches!(c.constraint, ast::TableConstraint::Check(_)));
+        if has_generated_columns || has_check_constraints {
+            let table = BTreeTable::from_sql(&sql, 0)?;
+            check_generated_columns(&table)?;
+            check_check_constraints(&table)?;
+        }
+    }
 
     let parse_schema_label = program.allocate_label();
     // TODO: ReadCookie
@@ -59,12 +144,21 @@ pub fn translate_create_table(
     // TODO: SetCookie
     // TODO: SetCookie
 
-    // Create the table B-tree
+    // Create the table B-tree, an index B-tree keyed by the PRIMARY KEY for WITHOUT ROWID tables
+    let has_rowid = !matches!(
+        &body,
+        ast::CreateTableBody::ColumnsAndConstraints { options, .. }
+            if options.contains(ast::TableOptions::WITHOUT_ROWID)
+    );
     let table_root_reg = program.alloc_register();
     program.emit_insn(Insn::CreateBtree {
-        db: 0,
+        db: database_id,
         root: table_root_reg,
-        flags: CreateBTreeFlags::new_table(),
+        flags: if has_rowid {
+            CreateBTreeFlags::new_table()
+        } else {
+            CreateBTreeFlags::new_index()
+        },
     });
 
     // Create an automatic index B-tree if needed
@@ -93,24 +187,34 @@ pub fn translate_create_table(
 
     let index_regs = check_automatic_pk_index_required(&body, &mut program, &tbl_name.name.0)?;
     if let Some(index_regs) = index_regs.as_ref() {
-        if !schema.indexes_enabled() {
-            bail_parse_error!("Constraints UNIQUE and PRIMARY KEY (unless INTEGER PRIMARY KEY) on table are not supported without indexes");
-        }
         for index_reg in index_regs.clone() {
             program.emit_insn(Insn::CreateBtree {
-                db: 0,
+                db: database_id,
                 root: index_reg,
                 flags: CreateBTreeFlags::new_index(),
             });
         }
     }
 
+    if let Some(select) = select {
+        program = emit_rows_of_select(
+            program,
+            select_schema,
+            *select,
+            syms,
+            Rc::new(BTreeTable::from_sql(&sql, 0)?),
+            database_id,
+            table_root_reg,
+        )?;
+    }
+
     let table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
     let sqlite_schema_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: 1usize.into(),
         name: tbl_name.name.0.clone(),
+        db: database_id,
     });
 
     // Add the table entry to sqlite_schema
@@ -126,12 +230,14 @@ pub fn translate_create_table(
 
     // If we need an automatic index, add its entry to sqlite_schema
     if let Some(index_regs) = index_regs {
+        // The PRIMARY KEY of a WITHOUT ROWID table takes the first automatic index name
+        let first_index_number = if has_rowid { 1 } else { 2 };
         for (idx, index_reg) in index_regs.into_iter().enumerate() {
             let index_name = format!(
                 "{}{}_{}",
                 PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
                 tbl_name.name.0,
-                idx + 1
+                idx + first_index_number
             );
             emit_schema_entry(
                 &mut program,
@@ -148,7 +254,7 @@ pub fn translate_create_table(
     program.resolve_label(parse_schema_label, program.offset());
     // TODO: SetCookie
     program.emit_insn(Insn::SetCookie {
-        db: 0,
+        db: database_id,
         cookie: Cookie::SchemaVersion,
         value: schema.schema_version as i32 + 1,
         p5: 0,
@@ -157,7 +263,7 @@ pub fn translate_create_table(
     let parse_schema_where_clause =
         format!("tbl_name = '{}' AND type != 'trigger'", normalized_tbl_name);
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: database_id,
         where_clause: Some(parse_schema_where_clause),
     });
 
@@ -171,6 +277,8 @@ pub fn translate_create_table(
 pub enum SchemaEntryType {
     Table,
     Index,
+    Trigger,
+    View,
 }
 
 impl SchemaEntryType {
@@ -178,6 +286,8 @@ impl SchemaEntryType {
         match self {
             SchemaEntryType::Table => "table",
             SchemaEntryType::Index => "index",
+            SchemaEntryType::Trigger => "trigger",
+            SchemaEntryType::View => "view",
         }
     }
 }
@@ -241,6 +351,73 @@ pub fn emit_schema_entry(
     });
 }
 
+/// Emits the deletion of the row of the `entry_type` entry named `name` from the schema table
+/// of the database at index `database_id`, whose schema is `schema`.
+pub fn emit_delete_schema_entry(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    entry_type: SchemaEntryType,
+    name: &str,
+) {
+    let name_reg = program.emit_string8_new_reg(name.to_string());
+    let type_reg = program.emit_string8_new_reg(entry_type.as_str().to_string());
+    let row_id_reg = program.alloc_register();
+
+    let sqlite_schema = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+    let sqlite_schema_cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(sqlite_schema.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: sqlite_schema_cursor_id,
+        root_page: RegisterOrLiteral::Literal(sqlite_schema.root_page),
+        name: sqlite_schema.name.clone(),
+        db: database_id,
+    });
+
+    let loop_start_label = program.allocate_label();
+    let loop_end_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: sqlite_schema_cursor_id,
+        pc_if_empty: loop_end_label,
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+
+    let dest_reg = program.alloc_register();
+    let next_label = program.allocate_label();
+    // skip if sqlite_schema.name != name
+    program.emit_column(sqlite_schema_cursor_id, 1, dest_reg);
+    program.emit_insn(Insn::Ne {
+        lhs: name_reg,
+        rhs: dest_reg,
+        target_pc: next_label,
+        flags: CmpInsFlags::default(),
+        collation: program.curr_collation(),
+    });
+    // skip if sqlite_schema.type != entry_type
+    program.emit_column(sqlite_schema_cursor_id, 0, dest_reg);
+    program.emit_insn(Insn::Ne {
+        lhs: type_reg,
+        rhs: dest_reg,
+        target_pc: next_label,
+        flags: CmpInsFlags::default(),
+        collation: program.curr_collation(),
+    });
+    program.emit_insn(Insn::RowId {
+        cursor_id: sqlite_schema_cursor_id,
+        dest: row_id_reg,
+    });
+    program.emit_insn(Insn::Delete {
+        cursor_id: sqlite_schema_cursor_id,
+    });
+
+    program.preassign_label_to_next_insn(next_label);
+    program.emit_insn(Insn::Next {
+        cursor_id: sqlite_schema_cursor_id,
+        pc_if_next: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(loop_end_label);
+}
+
 #[derive(Debug)]
 struct PrimaryKeyColumnInfo<'a> {
     name: &'a String,
@@ -337,13 +514,9 @@ fn check_automatic_pk_index_required(
                         }
                     } else if let ast::TableConstraint::Unique {
                         columns: unique_columns,
-                        conflict_clause,
+                        ..
                     } = &constraint.constraint
                     {
-                        if conflict_clause.is_some() {
-                            unimplemented!("ON CONFLICT not implemented");
-                        }
-
                         let col_names = unique_columns
                             .iter()
                             .map(|column| match &column.expr {
@@ -387,9 +560,35 @@ fn check_automatic_pk_index_required(
                 }
             }
 
-            // Check if table has rowid
-            if options.contains(ast::TableOptions::WITHOUT_ROWID) {
-                bail_parse_error!("WITHOUT ROWID tables are not supported yet");
+            // A WITHOUT ROWID table is stored in the b-tree of its PRIMARY KEY
+            let without_rowid = options.contains(ast::TableOptions::WITHOUT_ROWID);
+            if without_rowid {
+                if primary_key_definition.is_none() {
+                    bail_parse_error!("PRIMARY KEY missing on table {}", tbl_name);
+                }
+                let column_autoincrement = columns.values().any(|col_def| {
+                    col_def.constraints.iter().any(|c| {
+                        matches!(
+                            c.constraint,
+                            ast::ColumnConstraint::PrimaryKey {
+                                auto_increment: true,
+                                ..
+                            }
+                        )
+                    })
+                });
+                let table_autoincrement = constraints.iter().flatten().any(|c| {
+                    matches!(
+                        c.constraint,
+                        ast::TableConstraint::PrimaryKey {
+                            auto_increment: true,
+                            ..
+                        }
+                    )
+                });
+                if column_autoincrement || table_autoincrement {
+                    bail_parse_error!("AUTOINCREMENT not allowed on WITHOUT ROWID tables");
+                }
             }
 
             unique_sets.dedup();
@@ -419,8 +618,13 @@ fn check_automatic_pk_index_required(
                 false
             };
             let mut total_indices = unique_sets.len();
-            // if pk needs and index, but we already found out we primary key is unique, we only need a single index since constraint pk == unique
-            if auto_index_pk && !pk_is_unique {
+            if without_rowid {
+                // the PRIMARY KEY is the table b-tree, and so are UNIQUE constraints on the same columns
+                if pk_is_unique {
+                    total_indices -= 1;
+                }
+            } else if auto_index_pk && !pk_is_unique {
+                // if pk needs and index, but we already found out we primary key is unique, we only need a single index since constraint pk == unique
                 total_indices += 1;
             }
 
@@ -432,11 +636,357 @@ fn check_automatic_pk_index_required(
             }
         }
         ast::CreateTableBody::AsSelect(_) => {
-            bail_parse_error!("CREATE TABLE AS SELECT not supported yet")
+            unreachable!("CREATE TABLE AS SELECT is created with the columns of its SELECT")
+        }
+    }
+}
+
+/// Derives the column definitions of CREATE TABLE ... AS SELECT from the result columns of the
+/// (leftmost) SELECT, like SQLite: the names of the result columns, made unique, with the
+/// declared type of their affinity. In a compound SELECT, a column that the other SELECTs can fill
+/// with values of another type has no affinity.
+fn columns_from_select(
+    schema: &Schema,
+    select: &ast::Select,
+    sql: &str,
+    syms: &SymbolTable,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<Vec<ast::ColumnDefinition>> {
+    // The plan does not keep the text of the result columns that are expressions, which name them
+    let mut select = select.clone();
+    if let ast::OneSelect::Select(inner) = select.body.select.as_mut() {
+        let texts = result_column_texts(sql);
+        let texts = (texts.len() == inner.columns.len()).then_some(texts);
+        for (i, column) in inner.columns.iter_mut().enumerate() {
+            if let ast::ResultColumn::Expr(expr, alias @ None) = column {
+                if !matches!(
+                    expr,
+                    ast::Expr::Id(_) | ast::Expr::Qualified(..) | ast::Expr::DoublyQualified(..)
+                ) {
+                    let text = match &texts {
+                        Some(texts) => texts[i].clone(),
+                        None => expr
+                            .format()
+                            .map_err(|e| LimboError::InternalError(e.to_string()))?,
+                    };
+                    *alias = Some(ast::As::As(ast::Name(text)));
+                }
+            }
+        }
+    }
+    let (plan, others) = match prepare_select_plan(
+        schema,
+        select,
+        syms,
+        &[],
+        table_ref_counter,
+        QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // only planned to derive the columns
+            coroutine_implementation_start: BranchOffset::Placeholder,
+        },
+    )? {
+        Plan::Select(plan) => (plan, vec![]),
+        Plan::CompoundSelect {
+            left, right_most, ..
+        } => {
+            let mut plans = left.into_iter().map(|(plan, _)| plan).collect::<Vec<_>>();
+            plans.push(right_most);
+            let plan = plans.remove(0);
+            (plan, plans)
+        }
+        _ => unreachable!("SELECT is not planned as a SELECT"),
+    };
+
+    let mut columns: Vec<ast::ColumnDefinition> = Vec::with_capacity(plan.result_columns.len());
+    for (i, result_column) in plan.result_columns.iter().enumerate() {
+        let base_name = result_column
+            .name(&plan.table_references)
+            .map(normalize_ident)
+            .unwrap_or_else(|| format!("column{}", i + 1));
+        let mut name = base_name.clone();
+        let mut suffix = 0;
+        while columns
+            .iter()
+            .any(|column| normalize_ident(&column.col_name.0) == name)
+        {
+            suffix += 1;
+            name = format!("{base_name}:{suffix}");
+        }
+        let mut affinity = match &result_column.expr {
+            ast::Expr::RowId { .. } => Affinity::Integer,
+            expr => get_expr_affinity(expr, Some(&plan.table_references)),
+        };
+        let other_data_types = others.iter().fold(0, |data_types, other| {
+            data_types | expr_data_types(&other.result_columns[i].expr, &other.table_references)
+        });
+        if affinity == Affinity::Text && other_data_types & 0x01 != 0
+            || affinity.is_numeric() && other_data_types & 0x02 != 0
+        {
+            affinity = Affinity::Blob;
+        }
+        let type_name = match affinity {
+            Affinity::Integer => Some("INT"),
+            Affinity::Text => Some("TEXT"),
+            Affinity::Numeric => Some("NUM"),
+            Affinity::Real => Some("REAL"),
+            Affinity::Blob => None,
+        };
+        columns.push(ast::ColumnDefinition {
+            col_name: ast::Name(quote_identifier(&name)),
+            col_type: type_name.map(|name| ast::Type {
+                name: name.to_string(),
+                size: None,
+            }),
+            constraints: vec![],
+        });
+    }
+    Ok(columns)
+}
+
+/// Splits the result columns of the leftmost SELECT of the `sql` of CREATE TABLE ... AS SELECT
+/// into their source text, which names the expressions without an alias like in SQLite.
+/// Returns no columns if the statement cannot be tokenized.
+fn result_column_texts(sql: &str) -> Vec<String> {
+    let input = sql.as_bytes();
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut texts = Vec::new();
+    let mut depth = 0usize;
+    // Where the text of the current result column starts, once the SELECT is found
+    let mut start = None;
+    loop {
+        let Ok((token_start, token, token_end)) = scanner.scan(input) else {
+            return Vec::new();
+        };
+        let token_type = token.map(|(_, token_type)| token_type);
+        let Some(column_start) = start else {
+            match token_type {
+                None => return Vec::new(),
+                Some(TokenType::TK_LP) => depth += 1,
+                Some(TokenType::TK_RP) => depth = depth.saturating_sub(1),
+                Some(TokenType::TK_SELECT) if depth == 0 => start = Some(token_end),
+                _ => {}
+            }
+            continue;
+        };
+        match token_type {
+            Some(TokenType::TK_DISTINCT | TokenType::TK_ALL) if texts.is_empty() && depth == 0 => {
+                start = Some(token_end);
+                continue;
+            }
+            Some(TokenType::TK_LP) => depth += 1,
+            Some(TokenType::TK_RP) if depth > 0 => depth -= 1,
+            Some(TokenType::TK_COMMA) if depth == 0 => {
+                texts.push(
+                    String::from_utf8_lossy(&input[column_start..token_start])
+                        .trim()
+                        .to_string(),
+                );
+                start = Some(token_end);
+            }
+            None
+            | Some(
+                TokenType::TK_FROM
+                | TokenType::TK_WHERE
+                | TokenType::TK_GROUP
+                | TokenType::TK_HAVING
+                | TokenType::TK_WINDOW
+                | TokenType::TK_ORDER
+                | TokenType::TK_LIMIT
+                | TokenType::TK_UNION
+                | TokenType::TK_EXCEPT
+                | TokenType::TK_INTERSECT
+                | TokenType::TK_SEMI
+                | TokenType::TK_RP,
+            ) if depth == 0 => {
+                let end = if token_type.is_some() {
+                    token_start
+                } else {
+                    input.len()
+                };
+                texts.push(
+                    String::from_utf8_lossy(&input[column_start..end])
+                        .trim()
+                        .to_string(),
+                );
+                return texts;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the text SQLite stores in sqlite_schema for the CREATE TABLE statement `input`:
+/// `CREATE TABLE` followed by its source text from the table name on, without `TEMP`,
+/// `IF NOT EXISTS` or the schema name. Returns `None` if `input` is not such a statement.
+fn create_table_sql(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut next_token = || match scanner.scan(bytes) {
+        Ok((start, Some((_, token_type)), end)) => Some((start, token_type, end)),
+        _ => None,
+    };
+    if next_token()?.1 != TokenType::TK_CREATE {
+        return None;
+    }
+    let mut token = next_token()?;
+    if token.1 == TokenType::TK_TEMP {
+        token = next_token()?;
+    }
+    if token.1 != TokenType::TK_TABLE {
+        return None;
+    }
+    let mut name = next_token()?;
+    if name.1 == TokenType::TK_IF {
+        // IF NOT EXISTS
+        next_token()?;
+        next_token()?;
+        name = next_token()?;
+    }
+    let mut token = next_token();
+    if token.is_some_and(|(_, token_type, _)| token_type == TokenType::TK_DOT) {
+        name = next_token()?;
+        token = next_token();
+    }
+    let mut end = name.2;
+    while let Some((_, token_type, token_end)) = token {
+        if token_type == TokenType::TK_SEMI {
+            break;
+        }
+        end = token_end;
+        token = next_token();
+    }
+    Some(format!("CREATE TABLE {}", &input[name.0..end]))
+}
+
+/// Returns the types of the values `expr` can evaluate to, like SQLite's sqlite3ExprDataType():
+/// 0x01 for numbers, 0x02 for text and 0x04 for blobs.
+fn expr_data_types(expr: &ast::Expr, tables: &TableReferences) -> u8 {
+    match expr {
+        ast::Expr::Collate(expr, _) | ast::Expr::Unary(ast::UnaryOperator::Positive, expr) => {
+            expr_data_types(expr, tables)
+        }
+        ast::Expr::Literal(ast::Literal::Null) => 0,
+        ast::Expr::Literal(ast::Literal::String(_)) => 0x02,
+        ast::Expr::Literal(ast::Literal::Blob(_)) => 0x04,
+        ast::Expr::Binary(_, ast::Operator::Concat, _) => 0x06,
+        ast::Expr::Literal(
+            ast::Literal::CurrentDate | ast::Literal::CurrentTime | ast::Literal::CurrentTimestamp,
+        )
+        | ast::Expr::Variable(_)
+        | ast::Expr::FunctionCall { .. }
+        | ast::Expr::FunctionCallStar { .. } => 0x07,
+        ast::Expr::Column { .. } | ast::Expr::Cast { .. } | ast::Expr::Subquery(_) => {
+            match get_expr_affinity(expr, Some(tables)) {
+                affinity if affinity.is_numeric() => 0x05,
+                Affinity::Text => 0x06,
+                _ => 0x07,
+            }
         }
+        ast::Expr::Case {
+            when_then_pairs,
+            else_expr,
+            ..
+        } => when_then_pairs
+            .iter()
+            .map(|(_, then)| then)
+            .chain(else_expr.as_deref())
+            .fold(0, |data_types, expr| {
+                data_types | expr_data_types(expr, tables)
+            }),
+        _ => 0x01,
+    }
+}
+
+/// Double-quotes `name` unless it is a plain identifier that is not a keyword.
+fn quote_identifier(name: &str) -> String {
+    let is_plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && keyword_token(name.as_bytes()).is_none();
+    if is_plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
     }
 }
 
+/// Fills the table of CREATE TABLE ... AS SELECT, whose root page is in `root_reg`, with the rows
+/// of its SELECT, yielded by a coroutine like for INSERT INTO ... SELECT.
+fn emit_rows_of_select(
+    mut program: ProgramBuilder,
+    schema: &Schema,
+    select: ast::Select,
+    syms: &SymbolTable,
+    table: Rc<BTreeTable>,
+    database_id: usize,
+    root_reg: usize,
+) -> Result<ProgramBuilder> {
+    let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id,
+        root_page: RegisterOrLiteral::Register(root_reg),
+        name: table.name.clone(),
+        db: database_id,
+    });
+
+    let yield_reg = program.alloc_register();
+    let jump_on_definition_label = program.allocate_label();
+    let start_offset_label = program.allocate_label();
+    let loop_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: jump_on_definition_label,
+        start_offset: start_offset_label,
+    });
+    program.preassign_label_to_next_insn(start_offset_label);
+    let query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start: loop_end_label,
+    };
+    program.incr_nesting();
+    let result = translate_select(schema, select, syms, program, query_destination)?;
+    let mut program = result.program;
+    program.decr_nesting();
+    // The rows go to the table, the statement itself returns none
+    program.result_columns.clear();
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(jump_on_definition_label);
+
+    let loop_start_label = program.allocate_label();
+    program.preassign_label_to_next_insn(loop_start_label);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: loop_end_label,
+    });
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: yield_reg + 1,
+        count: result.num_result_cols,
+        dest_reg: record_reg,
+        index_name: None,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: cursor_id,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new(),
+        table_name: table.name.clone(),
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(loop_end_label);
+    Ok(program)
+}
+
 #[derive(Debug)]
 enum PrimaryKeyDefinitionType<'a> {
     Simple {
@@ -459,14 +1009,6 @@ fn create_table_body_to_str(tbl_name: &ast::QualifiedName, body: &ast::CreateTab
         )
         .as_str(),
     );
-    match body {
-        ast::CreateTableBody::ColumnsAndConstraints {
-            columns: _,
-            constraints: _,
-            options: _,
-        } => {}
-        ast::CreateTableBody::AsSelect(_select) => todo!("as select not yet supported"),
-    }
     sql
 }
 
@@ -586,6 +1128,7 @@ pub fn translate_create_virtual_table(
         cursor_id: sqlite_schema_cursor_id,
         root_page: 1usize.into(),
         name: table_name.clone(),
+        db: 0,
     });
 
     let sql = create_vtable_body_to_str(&vtab, vtab_module.clone());
@@ -607,7 +1150,7 @@ pub fn translate_create_virtual_table(
     });
     let parse_schema_where_clause = format!("tbl_name = '{table_name}' AND type != 'trigger'");
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: 0,
         where_clause: Some(parse_schema_where_clause),
     });
 
@@ -620,21 +1163,28 @@ pub fn translate_drop_table(
     tbl_name: ast::QualifiedName,
     if_exists: bool,
     schema: &Schema,
+    syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
-    if !schema.indexes_enabled() && schema.table_has_indexes(&tbl_name.name.to_string()) {
-        bail_parse_error!(
-            "DROP TABLE with indexes on the table is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
-    }
     let opts = ProgramBuilderOpts {
         num_cursors: 3,
         approx_num_insns: 40,
         approx_num_labels: 4,
     };
     program.extend(&opts);
+    let database_id = schema.locate_database(&tbl_name)?;
+    if database_id > 1 {
+        bail_parse_error!(
+            "schema changes in attached databases are only supported for CREATE TABLE"
+        );
+    }
+    let top_level_schema = schema;
+    let schema = schema.database_schema(database_id);
     let table = schema.get_table(tbl_name.name.0.as_str());
     if table.is_none() {
+        if schema.get_view(tbl_name.name.0.as_str()).is_some() {
+            bail_parse_error!("use DROP VIEW to delete view {}", tbl_name.name.0.as_str());
+        }
         if if_exists {
             program.epilogue(crate::translate::emitter::TransactionMode::Write);
 
@@ -645,14 +1195,22 @@ pub fn translate_drop_table(
 
     let table = table.unwrap(); // safe since we just checked for None
 
+    if let Some(btree_table) = table.btree() {
+        emit_drop_table_foreign_keys(
+            &mut program,
+            top_level_schema,
+            syms,
+            database_id,
+            &btree_table,
+        )?;
+    }
+
     let null_reg = program.alloc_register(); //  r1
     program.emit_null(null_reg, None);
     let table_name_and_root_page_register = program.alloc_register(); //  r2, this register is special because it's first used to track table name and then moved root page
-    let table_reg = program.emit_string8_new_reg(tbl_name.name.0.clone()); //  r3
+    let table_reg = program.emit_string8_new_reg(table.get_name().to_string()); //  r3
     program.mark_last_insn_constant();
-    let table_type = program.emit_string8_new_reg("trigger".to_string()); //  r4
-    program.mark_last_insn_constant();
-    let row_id_reg = program.alloc_register(); //  r5
+    let row_id_reg = program.alloc_register(); //  r4
 
     let schema_table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
     let sqlite_schema_cursor_id_0 = program.alloc_cursor_id(
@@ -663,9 +1221,10 @@ pub fn translate_drop_table(
         cursor_id: sqlite_schema_cursor_id_0,
         root_page: 1usize.into(),
         name: SQLITE_TABLEID.to_string(),
+        db: database_id,
     });
 
-    //  1. Remove all entries from the schema table related to the table we are dropping, except for triggers
+    //  1. Remove all entries from the schema table related to the table we are dropping, including its triggers
     //  loop to beginning of schema table
     let end_metadata_label = program.allocate_label();
     let metadata_loop = program.allocate_label();
@@ -682,24 +1241,13 @@ pub fn translate_drop_table(
         table_name_and_root_page_register,
     );
     let next_label = program.allocate_label();
+    // Names are case insensitive, SQLite stores them as they were declared
     program.emit_insn(Insn::Ne {
         lhs: table_name_and_root_page_register,
         rhs: table_reg,
         target_pc: next_label,
         flags: CmpInsFlags::default(),
-        collation: program.curr_collation(),
-    });
-    program.emit_column(
-        sqlite_schema_cursor_id_0,
-        0,
-        table_name_and_root_page_register,
-    );
-    program.emit_insn(Insn::Eq {
-        lhs: table_name_and_root_page_register,
-        rhs: table_type,
-        target_pc: next_label,
-        flags: CmpInsFlags::default(),
-        collation: program.curr_collation(),
+        collation: Some(CollationSeq::NoCase),
     });
     program.emit_insn(Insn::RowId {
         cursor_id: sqlite_schema_cursor_id_0,
@@ -717,13 +1265,20 @@ pub fn translate_drop_table(
     program.preassign_label_to_next_insn(end_metadata_label);
     //  end of loop on schema table
 
+    emit_drop_stat1_rows(
+        &mut program,
+        schema,
+        database_id,
+        &Stat1Rows::Table(table.get_name().to_string()),
+    );
+
     //  2. Destroy the indices within a loop
     let indices = schema.get_indices(&tbl_name.name.0);
     for index in indices {
         program.emit_insn(Insn::Destroy {
             root: index.root_page,
             former_root_reg: 0, //  no autovacuum (https://www.sqlite.org/opcode.html#Destroy)
-            is_temp: 0,
+            is_temp: database_id,
         });
 
         //  3. TODO: Open an ephemeral table, and read over triggers from schema table into ephemeral table
@@ -739,7 +1294,7 @@ pub fn translate_drop_table(
             program.emit_insn(Insn::Destroy {
                 root: table.root_page,
                 former_root_reg: table_name_and_root_page_register,
-                is_temp: 0,
+                is_temp: database_id,
             });
         }
         Table::Virtual(vtab) => {
@@ -776,6 +1331,7 @@ pub fn translate_drop_table(
             name: "ephemeral_scratch".to_string(),
             has_rowid: true,
             primary_key_columns: vec![],
+            primary_key_conflict: None,
             columns: vec![Column {
                 name: Some("rowid".to_string()),
                 ty: Type::Integer,
@@ -787,9 +1343,14 @@ pub fn translate_drop_table(
                 unique: false,
                 collation: None,
                 hidden: false,
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             }],
             is_strict: false,
             unique_sets: None,
+            check_constraints: vec![],
+            foreign_keys: vec![],
         });
         //  cursor id 2
         let ephemeral_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(simple_table_rc));
@@ -806,6 +1367,7 @@ pub fn translate_drop_table(
         program.emit_insn(Insn::OpenRead {
             cursor_id: sqlite_schema_cursor_id_1,
             root_page: 1usize,
+            db: database_id,
         });
 
         let schema_column_0_register = program.alloc_register();
@@ -863,6 +1425,7 @@ pub fn translate_drop_table(
             cursor_id: sqlite_schema_cursor_id_1,
             root_page: 1usize.into(),
             name: SQLITE_TABLEID.to_string(),
+            db: database_id,
         });
 
         //  Loop to copy over row id's from the ephemeral table and then re-insert into the schema table with the correct root page
@@ -925,7 +1488,7 @@ pub fn translate_drop_table(
 
     //  Drop the in-memory structures for the table
     program.emit_insn(Insn::DropTable {
-        db: 0,
+        db: database_id,
         _p2: 0,
         _p3: 0,
         table_name: tbl_name.name.0,
@@ -1,19 +1,26 @@
 use super::emitter::{emit_program, TranslateCtx};
+use super::expr::{walk_expr, walk_expr_mut, WalkControl};
+use super::main_loop::explain_table_loop;
 use super::plan::{
-    select_star, Distinctness, JoinOrderMember, Operation, OuterQueryReference, QueryDestination,
-    Search, TableReferences,
+    select_star, Distinctness, JoinOrderMember, JoinedTable, Operation, OuterQueryReference,
+    QueryDestination, Search, TableReferences,
 };
-use crate::function::{AggFunc, ExtFunc, Func};
+use super::subquery::bind_expr_subqueries;
+use crate::function::{AggFunc, ExtFunc, Func, WindowFunc};
 use crate::schema::Table;
+use crate::translate::collate::CollationSeq;
 use crate::translate::optimizer::optimize_plan;
+use crate::translate::order_by::sort_key_collation;
 use crate::translate::plan::{Aggregate, GroupBy, Plan, ResultSetColumn, SelectPlan};
 use crate::translate::planner::{
     bind_column_references, break_predicate_at_and_boundaries, parse_from, parse_limit,
-    parse_where, resolve_aggregates,
+    parse_where, resolve_aggregates, resolve_window_columns, resolve_window_functions,
+    window_function_over_clause,
 };
-use crate::util::normalize_ident;
+use crate::util::{exprs_are_equivalent, normalize_ident};
 use crate::vdbe::builder::{ProgramBuilderOpts, TableRefIdCounter};
 use crate::vdbe::insn::Insn;
+use crate::vdbe::BranchOffset;
 use crate::SymbolTable;
 use crate::{schema::Schema, vdbe::builder::ProgramBuilder, Result};
 use turso_sqlite3_parser::ast::{self, CompoundSelect, SortOrder};
@@ -39,7 +46,7 @@ pub fn translate_select(
         &mut program.table_reference_counter,
         query_destination,
     )?;
-    optimize_plan(&mut select_plan, schema)?;
+    optimize_plan(&mut select_plan, schema, program.optimizer_options())?;
     let num_result_cols;
     let opts = match &select_plan {
         Plan::Select(select) => {
@@ -110,18 +117,20 @@ pub fn prepare_select_plan(
             )?))
         }
         Some(compounds) => {
+            // The CTEs of the WITH clause can be referenced by every SELECT of the compound.
             let mut last = prepare_one_select_plan(
                 schema,
                 *select.body.select,
                 None,
                 None,
-                None,
+                select.with.clone(),
                 syms,
                 outer_query_refs,
                 table_ref_counter,
                 query_destination.clone(),
             )?;
 
+            let select_with = select.with.take();
             let mut left = Vec::with_capacity(compounds.len());
             for CompoundSelect { select, operator } in compounds {
                 left.push((last, operator));
@@ -130,7 +139,7 @@ pub fn prepare_select_plan(
                     *select,
                     None,
                     None,
-                    None,
+                    select_with.clone(),
                     syms,
                     outer_query_refs,
                     table_ref_counter,
@@ -146,30 +155,108 @@ pub fn prepare_select_plan(
                 }
             }
             let (limit, offset) = select.limit.map_or(Ok((None, None)), |l| parse_limit(&l))?;
-
-            // FIXME: handle OFFSET for compound selects
-            if offset.is_some_and(|o| o > 0) {
-                crate::bail_parse_error!("OFFSET is not supported for compound SELECTs yet");
-            }
-            // FIXME: handle ORDER BY for compound selects
-            if select.order_by.is_some() {
-                crate::bail_parse_error!("ORDER BY is not supported for compound SELECTs yet");
-            }
-            // FIXME: handle WITH for compound selects
-            if select.with.is_some() {
-                crate::bail_parse_error!("WITH is not supported for compound SELECTs yet");
-            }
+            let order_by = select
+                .order_by
+                .map(|order_by| {
+                    let mut selects = left
+                        .iter_mut()
+                        .map(|(plan, _)| plan)
+                        .chain(std::iter::once(&mut last))
+                        .collect::<Vec<_>>();
+                    resolve_compound_order_by(schema, order_by, &mut selects)
+                })
+                .transpose()?;
             Ok(Plan::CompoundSelect {
                 left,
                 right_most: last,
                 limit,
                 offset,
-                order_by: None,
+                order_by,
             })
         }
     }
 }
 
+/// Resolves the terms of the ORDER BY clause of a compound SELECT to the result columns they sort by.
+/// A term is either a column number, or an expression matching a result column of one of the SELECTs,
+/// looked up from the leftmost SELECT to the rightmost one.
+pub(crate) fn resolve_compound_order_by(
+    schema: &Schema,
+    order_by: Vec<ast::SortedColumn>,
+    selects: &mut [&mut SelectPlan],
+) -> Result<Vec<(usize, SortOrder, Option<CollationSeq>)>> {
+    let num_result_columns = selects[0].result_columns.len();
+    let mut key = Vec::with_capacity(order_by.len());
+    for (i, term) in order_by.into_iter().enumerate() {
+        let (expr, collation) = match term.expr {
+            ast::Expr::Collate(expr, collation) => (*expr, Some(CollationSeq::new(&collation)?)),
+            expr => (expr, None),
+        };
+        let column_idx = if let ast::Expr::Literal(ast::Literal::Numeric(num)) = &expr {
+            match num.parse::<usize>() {
+                Ok(column_number) if (1..=num_result_columns).contains(&column_number) => {
+                    column_number - 1
+                }
+                _ => crate::bail_parse_error!(
+                    "{} ORDER BY term out of range - should be between 1 and {}",
+                    ordinal(i + 1),
+                    num_result_columns
+                ),
+            }
+        } else {
+            let column_idx = selects.iter_mut().find_map(|plan| {
+                let mut expr = expr.clone();
+                bind_column_references(
+                    &mut expr,
+                    &mut plan.table_references,
+                    Some(&plan.result_columns),
+                    schema,
+                )
+                .ok()?;
+                plan.result_columns
+                    .iter()
+                    .position(|column| exprs_are_equivalent(&column.expr, &expr))
+            });
+            let Some(column_idx) = column_idx else {
+                crate::bail_parse_error!(
+                    "{} ORDER BY term does not match any column in the result set",
+                    ordinal(i + 1)
+                );
+            };
+            column_idx
+        };
+        // Without a COLLATE operator, the collation of the column of the leftmost SELECT is used.
+        let collation = match collation {
+            Some(collation) => Some(collation),
+            None => {
+                let leftmost = &selects[0];
+                sort_key_collation(
+                    &leftmost.result_columns[column_idx].expr,
+                    &leftmost.table_references,
+                )?
+            }
+        };
+        key.push((
+            column_idx,
+            term.order.unwrap_or(ast::SortOrder::Asc),
+            collation,
+        ));
+    }
+    Ok(key)
+}
+
+/// Formats a 1-based position as an English ordinal, e.g. 1st, 2nd or 11th.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
 #[allow(clippy::too_many_arguments)]
 fn prepare_one_select_plan(
     schema: &Schema,
@@ -189,14 +276,9 @@ fn prepare_one_select_plan(
                 from,
                 where_clause,
                 group_by,
+                window_clause,
                 distinctness,
-                ..
             } = *select_inner;
-            if !schema.indexes_enabled() && distinctness.is_some() {
-                crate::bail_parse_error!(
-                    "SELECT with DISTINCT is not allowed without indexes enabled"
-                );
-            }
             let col_count = columns.len();
             if col_count == 0 {
                 crate::bail_parse_error!("SELECT without columns is not allowed");
@@ -266,6 +348,7 @@ fn prepare_one_select_plan(
                 group_by: None,
                 order_by: None,
                 aggregates: vec![],
+                window: None,
                 limit: None,
                 offset: None,
                 contains_constant_false_condition: false,
@@ -274,6 +357,35 @@ fn prepare_one_select_plan(
                 values: vec![],
             };
 
+            // Bind the columns referenced by the windows of the WINDOW clause
+            let mut window_defs = window_clause.unwrap_or_default();
+            for window_def in window_defs.iter_mut() {
+                let window = &mut window_def.window;
+                let frame_bounds = window.frame_clause.iter_mut().flat_map(|frame_clause| {
+                    std::iter::once(&mut frame_clause.start).chain(frame_clause.end.as_mut())
+                });
+                let exprs = window
+                    .partition_by
+                    .iter_mut()
+                    .flatten()
+                    .chain(
+                        window
+                            .order_by
+                            .iter_mut()
+                            .flatten()
+                            .map(|col| &mut col.expr),
+                    )
+                    .chain(frame_bounds.filter_map(|bound| match bound {
+                        ast::FrameBound::Preceding(expr) | ast::FrameBound::Following(expr) => {
+                            Some(expr.as_mut())
+                        }
+                        _ => None,
+                    }));
+                for expr in exprs {
+                    bind_column_references(expr, &mut plan.table_references, None, schema)?;
+                }
+            }
+
             let mut aggregate_expressions = Vec::new();
             for column in columns.iter_mut() {
                 match column {
@@ -328,27 +440,31 @@ fn prepare_one_select_plan(
                             expr,
                             &mut plan.table_references,
                             Some(&plan.result_columns),
+                            schema,
+                        )?;
+                        // Before the aggregates are resolved, so that their arguments refer to
+                        // the same subquery plans
+                        bind_expr_subqueries(
+                            expr,
+                            &mut plan.table_references,
+                            schema,
+                            syms,
+                            table_ref_counter,
                         )?;
                         match expr {
                             ast::Expr::FunctionCall {
                                 name,
                                 distinctness,
                                 args,
-                                filter_over: _,
+                                filter_over,
                                 order_by: _,
-                            } => {
+                            } if !is_window_function_call(filter_over) => {
                                 let args_count = if let Some(args) = &args {
                                     args.len()
                                 } else {
                                     0
                                 };
                                 let distinctness = Distinctness::from_ast(distinctness.as_ref());
-
-                                if !schema.indexes_enabled() && distinctness.is_distinct() {
-                                    crate::bail_parse_error!(
-                                       "SELECT with DISTINCT is not allowed without indexes enabled"
-                                   );
-                                }
                                 if distinctness.is_distinct() && args_count != 1 {
                                     crate::bail_parse_error!("DISTINCT aggregate functions must have exactly one argument");
                                 }
@@ -385,11 +501,8 @@ fn prepare_one_select_plan(
                                         });
                                     }
                                     Ok(_) => {
-                                        let contains_aggregates = resolve_aggregates(
-                                            schema,
-                                            expr,
-                                            &mut aggregate_expressions,
-                                        )?;
+                                        let contains_aggregates =
+                                            resolve_aggregates(expr, &mut aggregate_expressions)?;
                                         plan.result_columns.push(ResultSetColumn {
                                             alias: maybe_alias.as_ref().map(|alias| match alias {
                                                 ast::As::Elided(alias) => alias.0.clone(),
@@ -404,7 +517,6 @@ fn prepare_one_select_plan(
                                         {
                                             if let ExtFunc::Scalar(_) = f.as_ref().func {
                                                 let contains_aggregates = resolve_aggregates(
-                                                    schema,
                                                     expr,
                                                     &mut aggregate_expressions,
                                                 )?;
@@ -442,56 +554,64 @@ fn prepare_one_select_plan(
                                                 });
                                             }
                                             continue; // Continue with the normal flow instead of returning
+                                        } else if WindowFunc::resolve_function(&name.0, args_count)?
+                                            .is_some()
+                                        {
+                                            crate::bail_parse_error!(
+                                                "misuse of window function {}()",
+                                                name.0
+                                            );
                                         } else {
                                             return Err(e);
                                         }
                                     }
                                 }
                             }
-                            ast::Expr::FunctionCallStar {
-                                name,
-                                filter_over: _,
-                            } => match Func::resolve_function(&name.0, 0) {
-                                Ok(Func::Agg(f)) => {
-                                    let agg = Aggregate {
-                                        func: f,
-                                        args: vec![ast::Expr::Literal(ast::Literal::Numeric(
-                                            "1".to_string(),
-                                        ))],
-                                        original_expr: expr.clone(),
-                                        distinctness: Distinctness::NonDistinct,
-                                    };
-                                    aggregate_expressions.push(agg.clone());
-                                    plan.result_columns.push(ResultSetColumn {
-                                        alias: maybe_alias.as_ref().map(|alias| match alias {
-                                            ast::As::Elided(alias) => alias.0.clone(),
-                                            ast::As::As(alias) => alias.0.clone(),
-                                        }),
-                                        expr: expr.clone(),
-                                        contains_aggregates: true,
-                                    });
-                                }
-                                Ok(_) => {
-                                    crate::bail_parse_error!(
-                                        "Invalid aggregate function: {}",
-                                        name.0
-                                    );
-                                }
-                                Err(e) => match e {
-                                    crate::LimboError::ParseError(e) => {
-                                        crate::bail_parse_error!("{}", e);
+                            ast::Expr::FunctionCallStar { name, filter_over }
+                                if !is_window_function_call(filter_over) =>
+                            {
+                                match Func::resolve_function(&name.0, 0) {
+                                    Ok(Func::Agg(f)) => {
+                                        let agg = Aggregate {
+                                            func: f,
+                                            args: vec![ast::Expr::Literal(ast::Literal::Numeric(
+                                                "1".to_string(),
+                                            ))],
+                                            original_expr: expr.clone(),
+                                            distinctness: Distinctness::NonDistinct,
+                                        };
+                                        aggregate_expressions.push(agg.clone());
+                                        plan.result_columns.push(ResultSetColumn {
+                                            alias: maybe_alias.as_ref().map(|alias| match alias {
+                                                ast::As::Elided(alias) => alias.0.clone(),
+                                                ast::As::As(alias) => alias.0.clone(),
+                                            }),
+                                            expr: expr.clone(),
+                                            contains_aggregates: true,
+                                        });
                                     }
-                                    _ => {
+                                    Ok(_) => {
                                         crate::bail_parse_error!(
                                             "Invalid aggregate function: {}",
                                             name.0
                                         );
                                     }
-                                },
-                            },
+                                    Err(e) => match e {
+                                        crate::LimboError::ParseError(e) => {
+                                            crate::bail_parse_error!("{}", e);
+                                        }
+                                        _ => {
+                                            crate::bail_parse_error!(
+                                                "Invalid aggregate function: {}",
+                                                name.0
+                                            );
+                                        }
+                                    },
+                                }
+                            }
                             expr => {
                                 let contains_aggregates =
-                                    resolve_aggregates(schema, expr, &mut aggregate_expressions)?;
+                                    resolve_aggregates(expr, &mut aggregate_expressions)?;
                                 plan.result_columns.push(ResultSetColumn {
                                     alias: maybe_alias.as_ref().map(|alias| match alias {
                                         ast::As::Elided(alias) => alias.0.clone(),
@@ -512,6 +632,7 @@ fn prepare_one_select_plan(
                 &mut plan.table_references,
                 Some(&plan.result_columns),
                 &mut plan.where_clause,
+                schema,
             )?;
 
             if let Some(mut group_by) = group_by {
@@ -521,6 +642,7 @@ fn prepare_one_select_plan(
                         expr,
                         &mut plan.table_references,
                         Some(&plan.result_columns),
+                        schema,
                     )?;
                 }
 
@@ -535,9 +657,10 @@ fn prepare_one_select_plan(
                                 expr,
                                 &mut plan.table_references,
                                 Some(&plan.result_columns),
+                                schema,
                             )?;
                             let contains_aggregates =
-                                resolve_aggregates(schema, expr, &mut aggregate_expressions)?;
+                                resolve_aggregates(expr, &mut aggregate_expressions)?;
                             if !contains_aggregates {
                                 // TODO: sqlite allows HAVING clauses with non aggregate expressions like
                                 // HAVING id = 5. We should support this too eventually (I guess).
@@ -571,17 +694,71 @@ fn prepare_one_select_plan(
                         &mut o.expr,
                         &mut plan.table_references,
                         Some(&plan.result_columns),
+                        schema,
                     )?;
-                    resolve_aggregates(schema, &o.expr, &mut plan.aggregates)?;
+                    resolve_aggregates(&o.expr, &mut plan.aggregates)?;
 
                     key.push((o.expr, o.order.unwrap_or(ast::SortOrder::Asc)));
                 }
                 plan.order_by = Some(key);
             }
 
+            // Collect the window functions from the result columns and ORDER BY
+            let mut window_plan = None;
+            for expr in window_source_exprs(&plan) {
+                resolve_window_functions(expr, &window_defs, &mut window_plan)?;
+            }
+            if window_plan.is_some() && is_aggregate_query(&plan, &window_defs) {
+                // The windows are computed over the rows produced by the aggregation
+                push_aggregation_into_subquery(
+                    &mut plan,
+                    &mut window_defs,
+                    schema,
+                    syms,
+                    table_ref_counter,
+                )?;
+                window_plan = None;
+                for expr in window_source_exprs(&plan) {
+                    resolve_window_functions(expr, &window_defs, &mut window_plan)?;
+                }
+            }
+            plan.window = window_plan;
+            if let Some(window_plan) = &mut plan.window {
+                let window_exprs = plan
+                    .result_columns
+                    .iter()
+                    .map(|rc| &rc.expr)
+                    .chain(plan.order_by.iter().flatten().map(|(expr, _)| expr));
+                for expr in window_exprs {
+                    resolve_window_columns(expr, window_plan)?;
+                }
+            }
+
             // Parse the LIMIT/OFFSET clause
             (plan.limit, plan.offset) = limit.map_or(Ok((None, None)), parse_limit)?;
 
+            // Plan the subqueries in the other expressions
+            let exprs = plan
+                .where_clause
+                .iter_mut()
+                .map(|term| &mut term.expr)
+                .chain(plan.group_by.iter_mut().flat_map(|group_by| {
+                    group_by
+                        .exprs
+                        .iter_mut()
+                        .chain(group_by.having.iter_mut().flatten())
+                }))
+                .chain(plan.order_by.iter_mut().flatten().map(|(expr, _)| expr));
+            for expr in exprs {
+                bind_expr_subqueries(
+                    expr,
+                    &mut plan.table_references,
+                    schema,
+                    syms,
+                    table_ref_counter,
+                )?;
+            }
+
             // Return the unoptimized query plan
             Ok(plan)
         }
@@ -604,6 +781,7 @@ fn prepare_one_select_plan(
                 group_by: None,
                 order_by: None,
                 aggregates: vec![],
+                window: None,
                 limit: None,
                 offset: None,
                 contains_constant_false_condition: false,
@@ -617,6 +795,221 @@ fn prepare_one_select_plan(
     }
 }
 
+/// The expressions of a SELECT that window functions can appear in: the result columns and the ORDER BY.
+fn window_source_exprs(plan: &SelectPlan) -> impl Iterator<Item = &ast::Expr> {
+    plan.result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(plan.order_by.iter().flatten().map(|(expr, _)| expr))
+}
+
+/// Whether a SELECT aggregates its rows, including with aggregate functions that only appear
+/// in the arguments or windows of window functions, e.g. rank() OVER (ORDER BY sum(b)).
+fn is_aggregate_query(plan: &SelectPlan, window_defs: &[ast::WindowDef]) -> bool {
+    if plan.group_by.is_some() || !plan.aggregates.is_empty() {
+        return true;
+    }
+    let window_def_exprs = window_defs.iter().flat_map(|def| {
+        let window = &def.window;
+        window
+            .partition_by
+            .iter()
+            .flatten()
+            .chain(window.order_by.iter().flatten().map(|col| &col.expr))
+    });
+    let mut found = false;
+    for expr in window_source_exprs(plan).chain(window_def_exprs) {
+        let _ = walk_expr(expr, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+            if is_aggregate_function_call(expr) {
+                found = true;
+                return Ok(WalkControl::SkipChildren);
+            }
+            Ok(WalkControl::Continue)
+        });
+    }
+    found
+}
+
+/// Whether an expression is a call of an aggregate function that is not used as a window function.
+fn is_aggregate_function_call(expr: &ast::Expr) -> bool {
+    if window_function_over_clause(expr).is_some() {
+        return false;
+    }
+    match expr {
+        ast::Expr::FunctionCall { name, args, .. } => matches!(
+            Func::resolve_function(&name.0, args.as_ref().map_or(0, |args| args.len())),
+            Ok(Func::Agg(_))
+        ),
+        ast::Expr::FunctionCallStar { name, .. } => {
+            matches!(Func::resolve_function(&name.0, 0), Ok(Func::Agg(_)))
+        }
+        _ => false,
+    }
+}
+
+/// Moves the FROM clause, WHERE clause, GROUP BY and aggregate functions of a SELECT with window functions
+/// into a FROM clause subquery, so that the windows are computed over the rows of the aggregation. E.g.
+/// `SELECT a, rank() OVER (ORDER BY sum(b)) FROM t GROUP BY a` is computed as
+/// `SELECT c0, rank() OVER (ORDER BY c1) FROM (SELECT a AS c0, sum(b) AS c1 FROM t GROUP BY a)`.
+///
+/// The columns and aggregate functions that the result columns, the ORDER BY and the windows reference
+/// become the result columns of the subquery, and the references are replaced with its columns.
+fn push_aggregation_into_subquery(
+    plan: &mut SelectPlan,
+    window_defs: &mut [ast::WindowDef],
+    schema: &Schema,
+    syms: &SymbolTable,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<()> {
+    // The names of the result columns would otherwise be derived from the rewritten expressions
+    for rc in plan.result_columns.iter_mut() {
+        if rc.alias.is_none() {
+            let name = rc
+                .name(&plan.table_references)
+                .map_or_else(|| rc.expr.to_string(), String::from);
+            rc.alias = Some(name);
+        }
+        rc.contains_aggregates = false;
+    }
+
+    let subquery_id = table_ref_counter.next();
+    let mut subquery_columns: Vec<ResultSetColumn> = vec![];
+    let mut aggregates = std::mem::take(&mut plan.aggregates);
+    {
+        let table_references = &plan.table_references;
+        // The replaced expressions are not walked into, as the walk visits an expression before its children
+        let mut rewrite = |expr: &mut ast::Expr| -> Result<()> {
+            let is_aggregate = is_aggregate_function_call(expr);
+            let is_column = match expr {
+                ast::Expr::Column { table, .. } | ast::Expr::RowId { table, .. } => {
+                    table_references
+                        .find_joined_table_by_internal_id(*table)
+                        .is_some()
+                }
+                _ => false,
+            };
+            if !is_aggregate && !is_column {
+                return Ok(());
+            }
+            if is_aggregate {
+                resolve_aggregates(expr, &mut aggregates)?;
+            }
+            let column = match subquery_columns
+                .iter()
+                .position(|rc| exprs_are_equivalent(&rc.expr, expr))
+            {
+                Some(column) => column,
+                None => {
+                    subquery_columns.push(ResultSetColumn {
+                        expr: expr.clone(),
+                        alias: None,
+                        contains_aggregates: is_aggregate,
+                    });
+                    subquery_columns.len() - 1
+                }
+            };
+            *expr = ast::Expr::Column {
+                database: None,
+                table: subquery_id,
+                column,
+                is_rowid_alias: false,
+            };
+            Ok(())
+        };
+        for rc in plan.result_columns.iter_mut() {
+            walk_expr_mut(&mut rc.expr, &mut rewrite)?;
+        }
+        for (expr, _) in plan.order_by.iter_mut().flatten() {
+            walk_expr_mut(expr, &mut rewrite)?;
+        }
+        for window_def in window_defs.iter_mut() {
+            let window = &mut window_def.window;
+            for expr in window.partition_by.iter_mut().flatten() {
+                walk_expr_mut(expr, &mut rewrite)?;
+            }
+            for col in window.order_by.iter_mut().flatten() {
+                walk_expr_mut(&mut col.expr, &mut rewrite)?;
+            }
+        }
+    }
+    if subquery_columns.is_empty() {
+        // The subquery only produces the groups, e.g. for SELECT row_number() OVER () FROM t GROUP BY a
+        subquery_columns.push(ResultSetColumn {
+            expr: ast::Expr::Literal(ast::Literal::Null),
+            alias: None,
+            contains_aggregates: false,
+        });
+    }
+
+    let outer_query_refs = plan.table_references.outer_query_refs().to_vec();
+    let mut subquery = SelectPlan {
+        join_order: std::mem::take(&mut plan.join_order),
+        table_references: std::mem::replace(
+            &mut plan.table_references,
+            TableReferences::new(vec![], outer_query_refs),
+        ),
+        result_columns: subquery_columns,
+        where_clause: std::mem::take(&mut plan.where_clause),
+        group_by: plan.group_by.take(),
+        order_by: None,
+        aggregates,
+        window: None,
+        limit: None,
+        offset: None,
+        contains_constant_false_condition: false,
+        query_destination: QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // will be set later in bytecode emission
+            coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+        },
+        distinctness: Distinctness::NonDistinct,
+        values: vec![],
+    };
+    // The expressions of the subquery are not visited by the caller anymore
+    let exprs = subquery
+        .result_columns
+        .iter_mut()
+        .map(|rc| &mut rc.expr)
+        .chain(subquery.where_clause.iter_mut().map(|term| &mut term.expr))
+        .chain(subquery.group_by.iter_mut().flat_map(|group_by| {
+            group_by
+                .exprs
+                .iter_mut()
+                .chain(group_by.having.iter_mut().flatten())
+        }));
+    for expr in exprs {
+        bind_expr_subqueries(
+            expr,
+            &mut subquery.table_references,
+            schema,
+            syms,
+            table_ref_counter,
+        )?;
+    }
+
+    plan.table_references
+        .mark_outer_query_refs_used(&subquery.table_references);
+    plan.table_references
+        .add_joined_table(JoinedTable::new_subquery(
+            "subquery_0".to_string(),
+            subquery,
+            None,
+            subquery_id,
+        ));
+    plan.join_order = vec![JoinOrderMember {
+        table_id: subquery_id,
+        original_idx: 0,
+        is_outer: false,
+    }];
+    Ok(())
+}
+
+/// Whether the FILTER/OVER clause of a function call makes it a window function call.
+fn is_window_function_call(filter_over: &Option<ast::FunctionTail>) -> bool {
+    filter_over
+        .as_ref()
+        .is_some_and(|filter_over| filter_over.over_clause.is_some())
+}
+
 /// Replaces a column number in an ORDER BY or GROUP BY expression with a copy of the column expression.
 /// For example, in SELECT u.first_name, count(1) FROM users u GROUP BY 1 ORDER BY 2,
 /// the column number 1 is replaced with u.first_name and the column number 2 is replaced with count(1).
@@ -651,17 +1044,26 @@ fn count_plan_required_cursors(plan: &SelectPlan) -> usize {
             Operation::Search(search) => match search {
                 Search::RowidEq { .. } => 1,
                 Search::Seek { index, .. } => 1 + index.is_some() as usize,
+                // The table, the ephemeral index of the rowids, and an index per branch
+                Search::MultiIndexOr { branches } => 2 + branches.len(),
+                // The table the hash table is built from, and the hash table
+                Search::HashJoin { .. } => 2,
             }
         } + if let Table::FromClauseSubquery(from_clause_subquery) = &t.table {
-            count_plan_required_cursors(&from_clause_subquery.plan)
+            from_clause_subquery
+                .selects()
+                .map(count_plan_required_cursors)
+                .sum()
         } else {
             0
         })
         .sum();
     let num_sorter_cursors = plan.group_by.is_some() as usize + plan.order_by.is_some() as usize;
     let num_pseudo_cursors = plan.group_by.is_some() as usize + plan.order_by.is_some() as usize;
+    // Each window has a sorter, a pseudo cursor to read it, and an ephemeral index for the current partition
+    let num_window_cursors = plan.window.as_ref().map_or(0, |w| w.windows.len() * 3);
 
-    num_table_cursors + num_sorter_cursors + num_pseudo_cursors
+    num_table_cursors + num_sorter_cursors + num_pseudo_cursors + num_window_cursors
 }
 
 fn estimate_num_instructions(select: &SelectPlan) -> usize {
@@ -672,7 +1074,10 @@ fn estimate_num_instructions(select: &SelectPlan) -> usize {
             Operation::Scan { .. } => 10,
             Operation::Search(_) => 15,
         } + if let Table::FromClauseSubquery(from_clause_subquery) = &t.table {
-            10 + estimate_num_instructions(&from_clause_subquery.plan)
+            10 + from_clause_subquery
+                .selects()
+                .map(estimate_num_instructions)
+                .sum::<usize>()
         } else {
             0
         })
@@ -695,7 +1100,10 @@ fn estimate_num_labels(select: &SelectPlan) -> usize {
             Operation::Scan { .. } => 3,
             Operation::Search(_) => 3,
         } + if let Table::FromClauseSubquery(from_clause_subquery) = &t.table {
-            3 + estimate_num_labels(&from_clause_subquery.plan)
+            3 + from_clause_subquery
+                .selects()
+                .map(estimate_num_labels)
+                .sum::<usize>()
         } else {
             0
         })
@@ -714,11 +1122,9 @@ pub fn emit_simple_count(
     _t_ctx: &mut TranslateCtx,
     plan: &SelectPlan,
 ) -> Result<()> {
-    let cursors = plan
-        .joined_tables()
-        .first()
-        .unwrap()
-        .resolve_cursors(program)?;
+    let table = plan.joined_tables().first().unwrap();
+    let cursors = table.resolve_cursors(program)?;
+    program.emit_explain(false, explain_table_loop(table, cursors.0.is_none()));
 
     let cursor_id = {
         match cursors {
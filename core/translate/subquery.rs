@@ -1,13 +1,28 @@
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, Expr, SortOrder};
+
 use crate::{
-    schema::Table,
-    vdbe::{builder::ProgramBuilder, insn::Insn},
-    Result,
+    schema::{FromClauseSubquery, Index, IndexColumn, Schema, Table},
+    vdbe::{
+        builder::{CursorType, ProgramBuilder, TableRefIdCounter},
+        insn::{CmpInsFlags, IdxInsertFlags, Insn},
+        BranchOffset,
+    },
+    Result, SymbolTable,
 };
 
 use super::{
+    compound_select::emit_compound_subquery,
     emitter::{emit_query, Resolver, TranslateCtx},
-    main_loop::LoopLabels,
-    plan::{QueryDestination, SelectPlan, TableReferences},
+    expr::{translate_expr, walk_expr_mut},
+    main_loop::{init_distinct, LoopLabels},
+    optimizer::optimize_select_plan,
+    plan::{
+        ColumnUsedMask, DistinctCtx, OuterQueryReference, Plan, QueryDestination, SelectPlan,
+        TableReferences,
+    },
+    select::prepare_select_plan,
 };
 
 /// Emit the subqueries contained in the FROM clause.
@@ -19,9 +34,20 @@ pub fn emit_subqueries(
 ) -> Result<()> {
     for table_reference in tables.joined_tables_mut() {
         if let Table::FromClauseSubquery(from_clause_subquery) = &mut table_reference.table {
+            // The row of a recursive reference is provided by the recursive CTE it refers to.
+            if from_clause_subquery.is_recursive_reference {
+                continue;
+            }
+            program.emit_explain(true, format!("CO-ROUTINE {}", table_reference.identifier));
             // Emit the subquery and get the start register of the result columns.
-            let result_columns_start =
-                emit_subquery(program, &mut from_clause_subquery.plan, t_ctx)?;
+            let result_columns_start = if from_clause_subquery.recursive.is_some() {
+                emit_recursive_cte(program, from_clause_subquery, &t_ctx.resolver)?
+            } else if from_clause_subquery.compound.is_some() {
+                emit_compound_subquery(program, from_clause_subquery, &t_ctx.resolver)?
+            } else {
+                emit_subquery(program, &mut from_clause_subquery.plan, &t_ctx.resolver)?
+            };
+            program.pop_explain();
             // Set the start register of the subquery's result columns.
             // This is done so that translate_expr() can read the result columns of the subquery,
             // as if it were reading from a regular table.
@@ -47,7 +73,7 @@ pub fn emit_subqueries(
 pub fn emit_subquery(
     program: &mut ProgramBuilder,
     plan: &mut SelectPlan,
-    t_ctx: &mut TranslateCtx,
+    resolver: &Resolver,
 ) -> Result<usize> {
     let yield_reg = program.alloc_register();
     let coroutine_implementation_start_offset = program.allocate_label();
@@ -71,7 +97,12 @@ pub fn emit_subquery(
         label_main_loop_end: None,
         meta_group_by: None,
         meta_left_joins: (0..plan.joined_tables().len()).map(|_| None).collect(),
+        meta_right_joins: (0..plan.joined_tables().len()).map(|_| None).collect(),
+        meta_search_loops: (0..plan.joined_tables().len())
+            .map(|_| Default::default())
+            .collect(),
         meta_sort: None,
+        meta_window: None,
         reg_agg_start: None,
         reg_nonagg_emit_once_flag: None,
         reg_result_cols_start: None,
@@ -80,7 +111,7 @@ pub fn emit_subquery(
         limit_ctx: None,
         reg_offset: None,
         reg_limit_offset_sum: None,
-        resolver: Resolver::new(t_ctx.resolver.schema, t_ctx.resolver.symbol_table),
+        resolver: Resolver::new(resolver.schema, resolver.symbol_table),
         non_aggregate_expressions: Vec::new(),
         cdc_cursor_id: None,
     };
@@ -97,3 +128,731 @@ pub fn emit_subquery(
     program.preassign_label_to_next_insn(subquery_body_end_label);
     Ok(result_column_start_reg)
 }
+
+/// Emits a recursive CTE as a coroutine and returns the start register of its result columns,
+/// see [RecursiveCte]. The rows that the recursive part has not been run for yet are kept in a
+/// queue, an ephemeral index whose key is the ORDER BY terms of the CTE followed by a sequence
+/// number, so that they are processed in the ORDER BY order and otherwise in the order in which
+/// they were produced:
+///
+/// 1. The rows of the initial part are added to the queue.
+/// 2. The first row of the queue is removed from it and yielded to the parent query. Then the
+///    recursive part is run with that row as the row of its recursive reference, and its rows are
+///    added to the queue. This is repeated until the queue is empty or the LIMIT is reached.
+///
+/// With UNION, a row is only added to the queue if it was not produced before.
+pub fn emit_recursive_cte(
+    program: &mut ProgramBuilder,
+    subquery: &mut FromClauseSubquery,
+    resolver: &Resolver,
+) -> Result<usize> {
+    let FromClauseSubquery {
+        plan: initial_plan,
+        columns,
+        recursive,
+        ..
+    } = subquery;
+    let recursive = recursive
+        .as_mut()
+        .expect("emit_recursive_cte called on non-recursive subquery");
+    let num_columns = columns.len();
+    let order_by = recursive.order_by.as_deref().unwrap_or_default();
+
+    let yield_reg = program.alloc_register();
+    let coroutine_implementation_start = program.allocate_label();
+    recursive.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    };
+    let subquery_body_end_label = program.allocate_label();
+    let label_end = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: subquery_body_end_label,
+        start_offset: coroutine_implementation_start,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+
+    let queue_index = Arc::new(Index {
+        name: format!("recursive_queue_{}", program.offset().as_offset_int()),
+        table_name: String::new(),
+        ephemeral: true,
+        root_page: 0,
+        columns: order_by
+            .iter()
+            .map(|(idx, order, collation)| {
+                let name = columns[*idx].name.as_deref().unwrap_or_default();
+                (name, *order, *collation)
+            })
+            .chain(std::iter::once(("sequence", SortOrder::Asc, None)))
+            .chain(
+                columns
+                    .iter()
+                    .map(|c| (c.name.as_deref().unwrap_or_default(), SortOrder::Asc, None)),
+            )
+            .enumerate()
+            .map(|(i, (name, order, collation))| IndexColumn {
+                name: name.to_string(),
+                order,
+                pos_in_table: i,
+                collation,
+                default: None,
+                expr: None,
+            })
+            .collect(),
+        unique: false,
+        has_rowid: false,
+        on_conflict: None,
+        where_clause: None,
+        pk_suffix_len: 0,
+    });
+    let queue_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(queue_index.clone()));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: queue_cursor_id,
+        is_table: false,
+    });
+    let distinct_ctx = if recursive.union_all {
+        None
+    } else {
+        Some(init_distinct(program, initial_plan))
+    };
+    let queue = RecursiveCteQueue {
+        cursor_id: queue_cursor_id,
+        index_name: queue_index.name.clone(),
+        order_by: order_by.iter().map(|(idx, _, _)| *idx).collect(),
+        num_columns,
+        last_sequence_reg: program.alloc_register(),
+        one_reg: program.alloc_register(),
+        distinct_ctx,
+    };
+    program.emit_insn(Insn::Integer {
+        value: 0,
+        dest: queue.last_sequence_reg,
+    });
+    program.emit_insn(Insn::Integer {
+        value: 1,
+        dest: queue.one_reg,
+    });
+    // A negative LIMIT or OFFSET is the same as none at all
+    let limit_reg = recursive.limit.filter(|limit| *limit >= 0).map(|limit| {
+        let reg = program.alloc_register();
+        program.emit_insn(Insn::Integer {
+            value: limit as i64,
+            dest: reg,
+        });
+        program.emit_insn(Insn::IfNot {
+            reg,
+            target_pc: label_end,
+            jump_if_null: false,
+        });
+        reg
+    });
+    let offset_reg = recursive.offset.filter(|offset| *offset > 0).map(|offset| {
+        let reg = program.alloc_register();
+        program.emit_insn(Insn::Integer {
+            value: offset as i64,
+            dest: reg,
+        });
+        reg
+    });
+
+    program.emit_explain(true, "SETUP".to_string());
+    let initial_start_reg = emit_subquery(program, initial_plan, resolver)?;
+    queue.emit_enqueue(program, initial_plan, initial_start_reg);
+    program.pop_explain();
+
+    // Take the first row out of the queue
+    let num_queue_columns = queue.num_key_columns() + num_columns;
+    let queue_row_reg = program.alloc_registers(num_queue_columns);
+    let result_start_reg = queue_row_reg + queue.num_key_columns();
+    let label_next_row = program.allocate_label();
+    program.preassign_label_to_next_insn(label_next_row);
+    program.emit_insn(Insn::Rewind {
+        cursor_id: queue.cursor_id,
+        pc_if_empty: label_end,
+    });
+    for i in 0..num_queue_columns {
+        program.emit_column(queue.cursor_id, i, queue_row_reg + i);
+    }
+    program.emit_insn(Insn::IdxDelete {
+        start_reg: queue_row_reg,
+        num_regs: num_queue_columns,
+        cursor_id: queue.cursor_id,
+        raise_error_if_no_matching_entry: false,
+    });
+    let label_skip_row = program.allocate_label();
+    if let Some(offset_reg) = offset_reg {
+        program.emit_insn(Insn::IfPos {
+            reg: offset_reg,
+            target_pc: label_skip_row,
+            decrement_by: 1,
+        });
+    }
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: BranchOffset::Offset(0),
+    });
+    if let Some(limit_reg) = limit_reg {
+        program.emit_insn(Insn::DecrJumpZero {
+            reg: limit_reg,
+            target_pc: label_end,
+        });
+    }
+    program.preassign_label_to_next_insn(label_skip_row);
+
+    // Run the recursive part for the row
+    for table in recursive.plan.table_references.joined_tables_mut() {
+        if let Table::FromClauseSubquery(reference) = &mut table.table {
+            if reference.is_recursive_reference {
+                reference.result_columns_start_reg = Some(result_start_reg);
+            }
+        }
+    }
+    program.emit_explain(true, "RECURSIVE STEP".to_string());
+    let recursive_start_reg = emit_subquery(program, &mut recursive.plan, resolver)?;
+    queue.emit_enqueue(program, &recursive.plan, recursive_start_reg);
+    program.pop_explain();
+    program.emit_insn(Insn::Goto {
+        target_pc: label_next_row,
+    });
+
+    program.preassign_label_to_next_insn(label_end);
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(subquery_body_end_label);
+    Ok(result_start_reg)
+}
+
+/// The queue of rows of a recursive CTE, see [emit_recursive_cte].
+struct RecursiveCteQueue {
+    cursor_id: usize,
+    index_name: String,
+    /// The result columns the queue is sorted by before the sequence number.
+    order_by: Vec<usize>,
+    num_columns: usize,
+    /// The sequence number of the row that was added to the queue last.
+    last_sequence_reg: usize,
+    /// Holds the constant 1, to increment the sequence number.
+    one_reg: usize,
+    /// Deduplicates the rows of the CTE if its parts are combined by UNION.
+    distinct_ctx: Option<DistinctCtx>,
+}
+
+impl RecursiveCteQueue {
+    /// The number of columns that precede the columns of a row in the queue.
+    fn num_key_columns(&self) -> usize {
+        self.order_by.len() + 1
+    }
+
+    /// Adds the rows yielded by the coroutine of a part of the CTE to the queue.
+    fn emit_enqueue(&self, program: &mut ProgramBuilder, part: &SelectPlan, start_reg: usize) {
+        let yield_reg = coroutine_yield_reg(part);
+        let label_loop = program.allocate_label();
+        let label_done = program.allocate_label();
+        program.preassign_label_to_next_insn(label_loop);
+        program.emit_insn(Insn::Yield {
+            yield_reg,
+            end_offset: label_done,
+        });
+        if let Some(distinct_ctx) = &self.distinct_ctx {
+            // A row that was produced before is skipped
+            let distinct_ctx = DistinctCtx {
+                label_on_conflict: label_loop,
+                ..distinct_ctx.clone()
+            };
+            distinct_ctx.emit_deduplication_insns(program, self.num_columns, start_reg);
+        }
+        program.emit_insn(Insn::Add {
+            lhs: self.last_sequence_reg,
+            rhs: self.one_reg,
+            dest: self.last_sequence_reg,
+        });
+        let num_key_columns = self.num_key_columns();
+        let key_start_reg = program.alloc_registers(num_key_columns + self.num_columns);
+        for (i, column_idx) in self.order_by.iter().enumerate() {
+            program.emit_insn(Insn::Copy {
+                src_reg: start_reg + column_idx,
+                dst_reg: key_start_reg + i,
+                extra_amount: 0,
+            });
+        }
+        program.emit_insn(Insn::Copy {
+            src_reg: self.last_sequence_reg,
+            dst_reg: key_start_reg + num_key_columns - 1,
+            extra_amount: 0,
+        });
+        program.emit_insn(Insn::Copy {
+            src_reg: start_reg,
+            dst_reg: key_start_reg + num_key_columns,
+            extra_amount: self.num_columns - 1,
+        });
+        let record_reg = program.alloc_register();
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: key_start_reg,
+            count: num_key_columns + self.num_columns,
+            dest_reg: record_reg,
+            index_name: Some(self.index_name.clone()),
+        });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id: self.cursor_id,
+            record_reg,
+            unpacked_start: Some(key_start_reg),
+            unpacked_count: Some((num_key_columns + self.num_columns) as u16),
+            flags: IdxInsertFlags::new(),
+        });
+        program.emit_insn(Insn::Goto {
+            target_pc: label_loop,
+        });
+        program.preassign_label_to_next_insn(label_done);
+    }
+}
+
+/// Plans a subquery that appears in an expression, i.e. `EXISTS (SELECT ...)`, a scalar
+/// `(SELECT ...)` or `x IN (SELECT ...)`. The tables of the enclosing query are visible to the
+/// subquery as outer query references, which makes it a correlated subquery if it uses them.
+/// A compound SELECT is planned as `SELECT * FROM (<compound select>)`.
+pub fn prepare_expr_subquery(
+    schema: &Schema,
+    mut select: ast::Select,
+    syms: &SymbolTable,
+    table_references: Option<&TableReferences>,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<SelectPlan> {
+    let outer_query_refs = table_references.map_or_else(Vec::new, |table_references| {
+        table_references
+            .joined_tables()
+            .iter()
+            .map(|t| OuterQueryReference {
+                identifier: t.identifier.clone(),
+                internal_id: t.internal_id,
+                table: t.table.clone(),
+                col_used_mask: ColumnUsedMask::default(),
+                rowid_used: false,
+            })
+            .chain(
+                table_references
+                    .outer_query_refs()
+                    .iter()
+                    .map(|t| OuterQueryReference {
+                        col_used_mask: ColumnUsedMask::default(),
+                        rowid_used: false,
+                        ..t.clone()
+                    }),
+            )
+            .collect()
+    });
+    if select.body.compounds.is_some() {
+        select = ast::Select {
+            with: None,
+            body: ast::SelectBody {
+                select: Box::new(ast::OneSelect::Select(Box::new(ast::SelectInner {
+                    distinctness: None,
+                    columns: vec![ast::ResultColumn::Star],
+                    from: Some(ast::FromClause::single(ast::SelectTable::Select(
+                        Box::new(select),
+                        None,
+                    ))),
+                    where_clause: None,
+                    group_by: None,
+                    window_clause: None,
+                }))),
+                compounds: None,
+            },
+            order_by: None,
+            limit: None,
+        };
+    }
+    let Plan::Select(plan) = prepare_select_plan(
+        schema,
+        select,
+        syms,
+        &outer_query_refs,
+        table_ref_counter,
+        QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // will be set later in bytecode emission
+            coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+        },
+    )?
+    else {
+        unreachable!("compound SELECTs are planned as FROM clause subqueries");
+    };
+    Ok(plan)
+}
+
+/// Plans the subqueries of an expression of the enclosing query, and rewrites them into
+/// [Expr::SubqueryResult]s that refer to their plans, which are stored in `table_references`
+/// until the expression is translated, see [emit_expr_subquery]. The columns of the enclosing
+/// query that a subquery references are marked as used, so that e.g. a covering index is only
+/// chosen if it contains them.
+pub fn bind_expr_subqueries(
+    expr: &mut Expr,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<()> {
+    walk_expr_mut(expr, &mut |e| -> Result<()> {
+        let (Expr::Exists(select) | Expr::Subquery(select) | Expr::InSelect { rhs: select, .. }) =
+            e
+        else {
+            return Ok(());
+        };
+        let plan = prepare_expr_subquery(
+            schema,
+            *select.clone(),
+            syms,
+            Some(table_references),
+            table_ref_counter,
+        )?;
+        table_references.mark_outer_query_refs_used(&plan.table_references);
+        let subquery_id = table_ref_counter.next();
+        table_references.add_expr_subquery(subquery_id, plan);
+        *e = Expr::SubqueryResult {
+            subquery_id,
+            expr: Box::new(e.clone()),
+        };
+        Ok(())
+    })
+}
+
+/// Emits a subquery that appears in an expression and stores its value in `target_register`:
+/// - `EXISTS (SELECT ...)` is 1 if the subquery returns any row and 0 otherwise.
+/// - `(SELECT ...)` is the value of the first row, or NULL if the subquery returns no rows.
+/// - `x IN (SELECT ...)` is 1 if x equals any of the rows, NULL if x is NULL or no row equals x
+///   but one of them is NULL, and 0 otherwise. `NOT IN` negates it.
+///
+/// The subquery runs as a coroutine. An uncorrelated subquery only runs once, guarded by an
+/// [Insn::Once]: its value is cached in a register, and for `IN` its rows are stored in an
+/// ephemeral index that is probed for x. A correlated subquery runs again every time the
+/// expression is evaluated, reading the columns of the outer query from its cursors.
+pub fn emit_expr_subquery(
+    program: &mut ProgramBuilder,
+    referenced_tables: Option<&TableReferences>,
+    expr: &Expr,
+    target_register: usize,
+    resolver: &Resolver,
+) -> Result<usize> {
+    // The plan of a subquery that was planned with its enclosing query is only taken once,
+    // the subquery is planned again if the expression is translated more than once.
+    let (expr, planned) = match expr {
+        Expr::SubqueryResult { subquery_id, expr } => (
+            expr.as_ref(),
+            referenced_tables.and_then(|tables| tables.take_expr_subquery(*subquery_id)),
+        ),
+        expr => (expr, None),
+    };
+    let select = match expr {
+        Expr::Exists(select) | Expr::Subquery(select) | Expr::InSelect { rhs: select, .. } => {
+            select
+        }
+        _ => unreachable!("emit_expr_subquery called on non-subquery expression"),
+    };
+    let mut plan = match planned {
+        Some(mut plan) => {
+            if let Some(referenced_tables) = referenced_tables {
+                plan.table_references
+                    .update_outer_query_refs(referenced_tables);
+            }
+            plan
+        }
+        None => prepare_expr_subquery(
+            resolver.schema,
+            *select.clone(),
+            resolver.symbol_table,
+            referenced_tables,
+            &mut program.table_reference_counter,
+        )?,
+    };
+    optimize_select_plan(&mut plan, resolver.schema, program.optimizer_options())?;
+    if !matches!(expr, Expr::Exists(_)) && plan.result_columns.len() != 1 {
+        crate::bail_parse_error!(
+            "sub-select returns {} columns - expected 1",
+            plan.result_columns.len()
+        );
+    }
+    let correlated = plan
+        .table_references
+        .outer_query_refs()
+        .iter()
+        .any(|outer_query_ref| outer_query_ref.is_used());
+    program.emit_explain_subquery(correlated, matches!(expr, Expr::InSelect { .. }));
+
+    if let Expr::InSelect { lhs, not, .. } = expr {
+        emit_in_subquery(
+            program,
+            referenced_tables,
+            lhs,
+            *not,
+            &mut plan,
+            correlated,
+            target_register,
+            resolver,
+        )?;
+        program.pop_explain();
+        return Ok(target_register);
+    }
+
+    // An uncorrelated subquery computes its value once into a register of its own
+    let result_reg = if correlated {
+        target_register
+    } else {
+        program.alloc_register()
+    };
+    let label_done = program.allocate_label();
+    if !correlated {
+        program.emit_insn(Insn::Once {
+            target_pc_when_reentered: label_done,
+        });
+    }
+    let result_start_reg = emit_subquery(program, &mut plan, resolver)?;
+    let yield_reg = coroutine_yield_reg(&plan);
+    if let Expr::Exists(_) = expr {
+        program.emit_insn(Insn::Integer {
+            value: 0,
+            dest: result_reg,
+        });
+        program.emit_insn(Insn::Yield {
+            yield_reg,
+            end_offset: label_done,
+        });
+        program.emit_insn(Insn::Integer {
+            value: 1,
+            dest: result_reg,
+        });
+    } else {
+        program.emit_insn(Insn::Null {
+            dest: result_reg,
+            dest_end: None,
+        });
+        program.emit_insn(Insn::Yield {
+            yield_reg,
+            end_offset: label_done,
+        });
+        program.emit_insn(Insn::Copy {
+            src_reg: result_start_reg,
+            dst_reg: result_reg,
+            extra_amount: 0,
+        });
+    }
+    program.preassign_label_to_next_insn(label_done);
+    if !correlated {
+        program.emit_insn(Insn::Copy {
+            src_reg: result_reg,
+            dst_reg: target_register,
+            extra_amount: 0,
+        });
+    }
+    program.pop_explain();
+    Ok(target_register)
+}
+
+/// Emits `lhs [NOT] IN (SELECT ...)`, see [emit_expr_subquery].
+#[allow(clippy::too_many_arguments)]
+fn emit_in_subquery(
+    program: &mut ProgramBuilder,
+    referenced_tables: Option<&TableReferences>,
+    lhs: &Expr,
+    not: bool,
+    plan: &mut SelectPlan,
+    correlated: bool,
+    target_register: usize,
+    resolver: &Resolver,
+) -> Result<()> {
+    let label_found = program.allocate_label();
+    let label_lhs_null = program.allocate_label();
+    let label_result_null = program.allocate_label();
+    let label_false = program.allocate_label();
+    let label_done = program.allocate_label();
+    // Whether the subquery returned any row, and whether any of them was NULL
+    let nonempty_reg = program.alloc_register();
+    let has_null_reg = program.alloc_register();
+    let lhs_reg = program.alloc_register();
+
+    if !correlated {
+        // Store the rows of the subquery in an ephemeral index once, then probe it for lhs
+        let index = Arc::new(Index {
+            name: format!("in_subquery_{}", program.offset().as_offset_int()),
+            table_name: String::new(),
+            ephemeral: true,
+            root_page: 0,
+            columns: vec![IndexColumn {
+                name: plan.result_columns[0].expr.to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                collation: None, // FIXME: this should be the collation of the subquery column
+                default: None,
+                expr: None,
+            }],
+            unique: false,
+            has_rowid: false,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
+        });
+        let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+        let label_built = program.allocate_label();
+        program.emit_insn(Insn::Once {
+            target_pc_when_reentered: label_built,
+        });
+        program.emit_insn(Insn::OpenEphemeral {
+            cursor_id,
+            is_table: false,
+        });
+        let (result_reg, label_loop) = emit_in_subquery_scan_start(
+            program,
+            plan,
+            nonempty_reg,
+            has_null_reg,
+            label_built,
+            resolver,
+        )?;
+        let record_reg = program.alloc_register();
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: result_reg,
+            count: 1,
+            dest_reg: record_reg,
+            index_name: Some(index.name.clone()),
+        });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id,
+            record_reg,
+            unpacked_start: Some(result_reg),
+            unpacked_count: Some(1),
+            flags: IdxInsertFlags::new().no_op_duplicate(),
+        });
+        program.emit_insn(Insn::Goto {
+            target_pc: label_loop,
+        });
+        program.preassign_label_to_next_insn(label_built);
+
+        translate_expr(program, referenced_tables, lhs, lhs_reg, resolver)?;
+        program.emit_insn(Insn::IsNull {
+            reg: lhs_reg,
+            target_pc: label_lhs_null,
+        });
+        program.emit_insn(Insn::Found {
+            cursor_id,
+            target_pc: label_found,
+            record_reg: lhs_reg,
+            num_regs: 1,
+        });
+    } else {
+        // Run the subquery and compare each of its rows with lhs
+        translate_expr(program, referenced_tables, lhs, lhs_reg, resolver)?;
+        let collation = program.curr_collation();
+        let label_scanned = program.allocate_label();
+        let (result_reg, label_loop) = emit_in_subquery_scan_start(
+            program,
+            plan,
+            nonempty_reg,
+            has_null_reg,
+            label_scanned,
+            resolver,
+        )?;
+        program.emit_insn(Insn::Eq {
+            lhs: lhs_reg,
+            rhs: result_reg,
+            target_pc: label_found,
+            flags: CmpInsFlags::default(),
+            collation,
+        });
+        program.emit_insn(Insn::Goto {
+            target_pc: label_loop,
+        });
+        program.preassign_label_to_next_insn(label_scanned);
+        program.emit_insn(Insn::IsNull {
+            reg: lhs_reg,
+            target_pc: label_lhs_null,
+        });
+    }
+
+    // No row equals lhs: the result is NULL if one of the rows was NULL
+    program.emit_insn(Insn::If {
+        reg: has_null_reg,
+        target_pc: label_result_null,
+        jump_if_null: false,
+    });
+    program.preassign_label_to_next_insn(label_false);
+    program.emit_insn(Insn::Integer {
+        value: not as i64,
+        dest: target_register,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+    program.preassign_label_to_next_insn(label_found);
+    program.emit_insn(Insn::Integer {
+        value: !not as i64,
+        dest: target_register,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+    // A NULL lhs is not in an empty set, and unknown otherwise
+    program.preassign_label_to_next_insn(label_lhs_null);
+    program.emit_insn(Insn::IfNot {
+        reg: nonempty_reg,
+        target_pc: label_false,
+        jump_if_null: false,
+    });
+    program.preassign_label_to_next_insn(label_result_null);
+    program.emit_insn(Insn::Null {
+        dest: target_register,
+        dest_end: None,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Emits the subquery of an `IN` as a coroutine and the start of the loop over its rows,
+/// which records whether there are any rows and whether any of them is NULL. NULL rows go back
+/// to the start of the loop. Returns the register of the current row and the loop start label.
+fn emit_in_subquery_scan_start(
+    program: &mut ProgramBuilder,
+    plan: &mut SelectPlan,
+    nonempty_reg: usize,
+    has_null_reg: usize,
+    label_loop_end: BranchOffset,
+    resolver: &Resolver,
+) -> Result<(usize, BranchOffset)> {
+    program.emit_insn(Insn::Integer {
+        value: 0,
+        dest: nonempty_reg,
+    });
+    program.emit_insn(Insn::Integer {
+        value: 0,
+        dest: has_null_reg,
+    });
+    let result_reg = emit_subquery(program, plan, resolver)?;
+    let yield_reg = coroutine_yield_reg(plan);
+    let label_loop = program.allocate_label();
+    let label_not_null = program.allocate_label();
+    program.preassign_label_to_next_insn(label_loop);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: label_loop_end,
+    });
+    program.emit_insn(Insn::Integer {
+        value: 1,
+        dest: nonempty_reg,
+    });
+    program.emit_insn(Insn::NotNull {
+        reg: result_reg,
+        target_pc: label_not_null,
+    });
+    program.emit_insn(Insn::Integer {
+        value: 1,
+        dest: has_null_reg,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_loop,
+    });
+    program.preassign_label_to_next_insn(label_not_null);
+    Ok((result_reg, label_loop))
+}
+
+fn coroutine_yield_reg(plan: &SelectPlan) -> usize {
+    match plan.query_destination {
+        QueryDestination::CoroutineYield { yield_reg, .. } => yield_reg,
+        _ => unreachable!("subquery must yield to its parent query"),
+    }
+}
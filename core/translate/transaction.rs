@@ -1,5 +1,6 @@
 use crate::translate::{ProgramBuilder, ProgramBuilderOpts};
-use crate::vdbe::insn::Insn;
+use crate::util::normalize_ident;
+use crate::vdbe::insn::{Insn, SavepointOp};
 use crate::Result;
 use turso_sqlite3_parser::ast::{Name, TransactionType};
 
@@ -22,8 +23,8 @@ pub fn translate_tx_begin(
             });
         }
         TransactionType::Immediate | TransactionType::Exclusive => {
-            program.emit_insn(Insn::Transaction { write: true });
-            // TODO: Emit transaction instruction on temporary tables when we support them.
+            program.emit_insn(Insn::Transaction { db: 0, write: true });
+            // The temp database is private to the connection, there is no lock to take on it
             program.emit_insn(Insn::AutoCommit {
                 auto_commit: false,
                 rollback: false,
@@ -43,6 +44,7 @@ pub fn translate_tx_commit(
         approx_num_insns: 0,
         approx_num_labels: 0,
     });
+    program.emit_insn(Insn::FkCheck { release: None });
     program.emit_insn(Insn::AutoCommit {
         auto_commit: true,
         rollback: false,
@@ -50,3 +52,34 @@ pub fn translate_tx_commit(
     program.epilogue(super::emitter::TransactionMode::None);
     Ok(program)
 }
+
+pub fn translate_savepoint(name: Name, mut program: ProgramBuilder) -> Result<ProgramBuilder> {
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 0,
+        approx_num_insns: 0,
+        approx_num_labels: 0,
+    });
+    program.emit_insn(Insn::Savepoint {
+        op: SavepointOp::Begin,
+        name: normalize_ident(&name.0),
+    });
+    program.epilogue(super::emitter::TransactionMode::None);
+    Ok(program)
+}
+
+pub fn translate_release(name: Name, mut program: ProgramBuilder) -> Result<ProgramBuilder> {
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 0,
+        approx_num_insns: 0,
+        approx_num_labels: 0,
+    });
+    program.emit_insn(Insn::FkCheck {
+        release: Some(normalize_ident(&name.0)),
+    });
+    program.emit_insn(Insn::Savepoint {
+        op: SavepointOp::Release,
+        name: normalize_ident(&name.0),
+    });
+    program.epilogue(super::emitter::TransactionMode::None);
+    Ok(program)
+}
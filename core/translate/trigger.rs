@@ -0,0 +1,1026 @@
+//! VDBE bytecode generation for triggers.
+//!
+//! Like SQLite, the body of a trigger is translated once per statement into a sub-program,
+//! which [Insn::Program] runs in a frame of its own each time the trigger fires, so triggers can
+//! fire recursively as deep as the runtime limit allows. The rows the trigger fires for are
+//! passed as the arguments of the sub-program, and `NEW.x` and `OLD.x` references in the body
+//! are rewritten into [Expr::Register]s pointing at them.
+//! More info: https://www.sqlite.org/lang_createtrigger.html
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, fmt::ToTokens, Expr, Name, QualifiedName};
+
+use crate::schema::{BTreeTable, Schema, Table, Trigger, View};
+use crate::translate::delete::translate_delete;
+use crate::translate::emitter::{Resolver, TransactionMode};
+use crate::translate::expr::{translate_condition_expr, walk_expr_mut, ConditionMetadata};
+use crate::translate::insert::translate_insert;
+use crate::translate::optimizer::{rewrite_expr, OptimizerOptions};
+use crate::translate::plan::{QueryDestination, TableReferences};
+use crate::translate::schema::{
+    emit_delete_schema_entry, emit_schema_entry, SchemaEntryType, SQLITE_TABLEID,
+};
+use crate::translate::select::translate_select;
+use crate::translate::update::translate_update;
+use crate::translate::view::view_as_subquery;
+use crate::util::normalize_ident;
+use crate::vdbe::builder::{
+    CursorType, ProgramBuilder, ProgramBuilderOpts, QueryMode, SubProgramKey,
+};
+use crate::vdbe::insn::{Cookie, InsertFlags, Insn};
+use crate::vdbe::BranchOffset;
+use crate::{bail_parse_error, CaptureDataChangesMode, LimboError, Result, SymbolTable};
+
+/// Names that refer to the rowid of `NEW` / `OLD` when the table has no column with that name.
+pub(crate) const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
+
+pub fn translate_create_trigger(
+    create_trigger: ast::CreateTrigger,
+    schema: &Schema,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let opts = ProgramBuilderOpts {
+        num_cursors: 1,
+        approx_num_insns: 20,
+        approx_num_labels: 1,
+    };
+    program.extend(&opts);
+
+    let trigger_name = normalize_ident(&create_trigger.trigger_name.name.0);
+    let tbl_name = normalize_ident(&create_trigger.tbl_name.name.0);
+    // Temporary triggers and the triggers on temporary tables live in the temp database, where
+    // they can be on the tables of any database
+    let table_database_id =
+        schema.locate_database(&QualifiedName::single(Name(tbl_name.clone())))?;
+    let database_id = match &create_trigger.trigger_name.db_name {
+        Some(_) if create_trigger.temporary => {
+            bail_parse_error!("temporary trigger may not have qualified name")
+        }
+        Some(db_name) => schema.database_index(&db_name.0)?,
+        None if create_trigger.temporary || table_database_id == 1 => 1,
+        None => 0,
+    };
+    let table_schema = if database_id == 1 {
+        schema.database_schema(table_database_id)
+    } else {
+        schema.database_schema(database_id)
+    };
+    let schema = schema.database_schema(database_id);
+    if schema.get_trigger(&trigger_name).is_some() {
+        if create_trigger.if_not_exists {
+            program.epilogue(TransactionMode::None);
+            return Ok(program);
+        }
+        bail_parse_error!("trigger {} already exists", trigger_name);
+    }
+    match table_schema.get_table(&tbl_name) {
+        Some(table) => {
+            if tbl_name.starts_with("sqlite_") {
+                bail_parse_error!("cannot create trigger on system table");
+            }
+            match table.as_ref() {
+                Table::BTree(_) => {
+                    if create_trigger.time == Some(ast::TriggerTime::InsteadOf) {
+                        bail_parse_error!(
+                            "cannot create INSTEAD OF trigger on table: {}",
+                            tbl_name
+                        );
+                    }
+                }
+                Table::Virtual(_) => bail_parse_error!("cannot create triggers on virtual tables"),
+                Table::FromClauseSubquery(_) => {
+                    unreachable!("FromClauseSubquery is never stored in the schema")
+                }
+            }
+        }
+        // Views only have INSTEAD OF triggers, which run instead of the writes to the view
+        None if table_schema.get_view(&tbl_name).is_some() => match create_trigger.time {
+            Some(ast::TriggerTime::InsteadOf) => {}
+            Some(ast::TriggerTime::After) => {
+                bail_parse_error!("cannot create AFTER trigger on view: {}", tbl_name)
+            }
+            Some(ast::TriggerTime::Before) | None => {
+                bail_parse_error!("cannot create BEFORE trigger on view: {}", tbl_name)
+            }
+        },
+        None => bail_parse_error!("no such table: {}", tbl_name),
+    }
+
+    // The schema table of the database the trigger is stored in tells it apart from the others
+    let mut create_trigger = create_trigger;
+    create_trigger.if_not_exists = false;
+    create_trigger.temporary = false;
+    create_trigger.trigger_name.db_name = None;
+    let sql = ast::Stmt::CreateTrigger(Box::new(create_trigger))
+        .format()
+        .map_err(|e| LimboError::InternalError(e.to_string()))?;
+
+    let sqlite_schema = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+    let sqlite_schema_cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(sqlite_schema.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: sqlite_schema_cursor_id,
+        root_page: 1usize.into(),
+        name: SQLITE_TABLEID.to_string(),
+        db: database_id,
+    });
+
+    emit_schema_entry(
+        &mut program,
+        sqlite_schema_cursor_id,
+        SchemaEntryType::Trigger,
+        &trigger_name,
+        &tbl_name,
+        0, // triggers have no b-tree, rootpage=0
+        Some(sql),
+    );
+
+    program.emit_insn(Insn::SetCookie {
+        db: database_id,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+    let parse_schema_where_clause = format!("name = '{trigger_name}' AND type = 'trigger'");
+    program.emit_insn(Insn::ParseSchema {
+        db: database_id,
+        where_clause: Some(parse_schema_where_clause),
+    });
+
+    program.epilogue(TransactionMode::Write);
+
+    Ok(program)
+}
+
+pub fn translate_drop_trigger(
+    trigger_name: &QualifiedName,
+    if_exists: bool,
+    schema: &Schema,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let opts = ProgramBuilderOpts {
+        num_cursors: 1,
+        approx_num_insns: 20,
+        approx_num_labels: 3,
+    };
+    program.extend(&opts);
+
+    let name = normalize_ident(&trigger_name.name.0);
+    // Unqualified names are searched in the temp database first
+    let database_id = match &trigger_name.db_name {
+        Some(db_name) => schema.database_index(&db_name.0)?,
+        None if schema.database_schema(1).get_trigger(&name).is_some() => 1,
+        None => 0,
+    };
+    let schema = schema.database_schema(database_id);
+    let trigger_name = name;
+    if schema.get_trigger(&trigger_name).is_none() {
+        if if_exists {
+            program.epilogue(TransactionMode::None);
+            return Ok(program);
+        }
+        bail_parse_error!("no such trigger: {}", trigger_name);
+    }
+
+    emit_delete_schema_entry(
+        &mut program,
+        schema,
+        database_id,
+        SchemaEntryType::Trigger,
+        &trigger_name,
+    );
+
+    program.emit_insn(Insn::SetCookie {
+        db: database_id,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+
+    // Remove the trigger from the Schema
+    program.emit_insn(Insn::DropTrigger {
+        db: database_id,
+        trigger_name,
+    });
+
+    program.epilogue(TransactionMode::Write);
+
+    Ok(program)
+}
+
+/// Registers holding a row that a trigger body can refer to as `NEW` or `OLD`.
+#[derive(Debug, Clone, Copy)]
+pub struct TriggerRowRegisters {
+    /// Register holding the rowid of the row.
+    pub rowid_reg: usize,
+    /// First of the registers holding the columns of the row, in table column order.
+    pub columns_start_reg: usize,
+}
+
+/// Returns the triggers on `table_name` of the database at index `database_id` that fire on
+/// `event` at `time`, in firing order: temporary triggers first, then the triggers of the
+/// database, each most recently created first (like SQLite).
+/// `updated_columns` are the names of the columns assigned by an UPDATE.
+pub fn triggers_to_fire(
+    schema: &Schema,
+    database_id: usize,
+    table_name: &str,
+    event: &ast::TriggerEvent,
+    time: ast::TriggerTime,
+    updated_columns: &[String],
+) -> Vec<Arc<Trigger>> {
+    // Temporary triggers can be created on the tables of any database, they are on the table
+    // their table name resolves to
+    let temp_triggers = match schema.temp.get() {
+        Some(temp) if database_id != 1 => {
+            let unqualified = QualifiedName::single(Name(table_name.to_string()));
+            if schema.locate_database(&unqualified).ok() == Some(database_id) {
+                temp.get_triggers(table_name)
+            } else {
+                &[]
+            }
+        }
+        _ => &[],
+    };
+    temp_triggers
+        .iter()
+        .rev()
+        .chain(
+            schema
+                .database_schema(database_id)
+                .get_triggers(table_name)
+                .iter()
+                .rev(),
+        )
+        .filter(|trigger| trigger.time == time && trigger.fires_on(event, updated_columns))
+        .cloned()
+        .collect()
+}
+
+/// Emits the firing of `triggers` for the current row of `table`.
+/// `ignore_label` is where `RAISE(IGNORE)` jumps to, i.e. the end of the processing of the
+/// current row by the statement that fired the triggers.
+#[allow(clippy::too_many_arguments)]
+pub fn emit_triggers(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    triggers: &[Arc<Trigger>],
+    table: &BTreeTable,
+    new: Option<TriggerRowRegisters>,
+    old: Option<TriggerRowRegisters>,
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    if triggers.is_empty() {
+        return Ok(());
+    }
+    // The arguments of the trigger programs: NEW then OLD, each as the rowid and the columns
+    let row_len = table.columns.len() + 1;
+    let args_reg = program.alloc_registers(2 * row_len);
+    for (row, start_reg) in [(new, args_reg), (old, args_reg + row_len)] {
+        match row {
+            Some(row) => {
+                program.emit_insn(Insn::Copy {
+                    src_reg: row.rowid_reg,
+                    dst_reg: start_reg,
+                    extra_amount: 0,
+                });
+                program.emit_insn(Insn::Copy {
+                    src_reg: row.columns_start_reg,
+                    dst_reg: start_reg + 1,
+                    extra_amount: table.columns.len() - 1,
+                });
+            }
+            None => program.emit_null(start_reg, Some(start_reg + row_len - 1)),
+        }
+    }
+    for trigger in triggers {
+        let sub_program = trigger_program(program, schema, syms, trigger, table)?;
+        program.emit_insn(Insn::Program {
+            sub_program,
+            args_reg,
+            num_args: 2 * row_len,
+            ignore_target: ignore_label,
+            // Unless recursive triggers are enabled, a trigger does not fire from within its body
+            skip_if_active: !program.recursive_triggers(),
+            description: format!("-- TRIGGER {}", trigger.name),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the index of the sub-program of `trigger`, a trigger on `table`, translating it if
+/// the statement does not have it yet.
+fn trigger_program(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    trigger: &Arc<Trigger>,
+    table: &BTreeTable,
+) -> Result<usize> {
+    let key = SubProgramKey::Trigger(trigger.clone());
+    if let Some(index) = program.sub_program(&key) {
+        return Ok(index);
+    }
+    let (index, mut sub_program) = program.begin_sub_program(key, true);
+    let row_len = table.columns.len() + 1;
+    let args_reg = sub_program.alloc_registers(2 * row_len);
+    let new = TriggerRowRegisters {
+        rowid_reg: args_reg,
+        columns_start_reg: args_reg + 1,
+    };
+    let old = TriggerRowRegisters {
+        rowid_reg: args_reg + row_len,
+        columns_start_reg: args_reg + row_len + 1,
+    };
+    let row_refs = match trigger.event {
+        ast::TriggerEvent::Insert => TriggerRowRefs {
+            table,
+            new: Some(new),
+            old: None,
+        },
+        ast::TriggerEvent::Delete => TriggerRowRefs {
+            table,
+            new: None,
+            old: Some(old),
+        },
+        ast::TriggerEvent::Update | ast::TriggerEvent::UpdateOf(_) => TriggerRowRefs {
+            table,
+            new: Some(new),
+            old: Some(old),
+        },
+    };
+    emit_trigger_body(&mut sub_program, schema, syms, trigger, &row_refs)?;
+    program.end_sub_program(index, sub_program);
+    Ok(index)
+}
+
+/// An INSERT, UPDATE or DELETE on a view, see [translate_view_write].
+pub enum ViewWrite {
+    Insert {
+        columns: Option<ast::DistinctNames>,
+        body: ast::InsertBody,
+    },
+    Update {
+        sets: Vec<ast::Set>,
+        where_clause: Option<Box<Expr>>,
+    },
+    Delete {
+        where_clause: Option<Box<Expr>>,
+    },
+}
+
+/// Translates `write` on `view`, a view of the database at index `database_id`: the INSTEAD OF
+/// triggers of the view run for each row it would write, with the row as `NEW` and `OLD`.
+/// Like SQLite, the rows are collected in an ephemeral table before the triggers run, as they
+/// usually write to the tables the view selects from.
+pub fn translate_view_write(
+    schema: &Schema,
+    syms: &SymbolTable,
+    database_id: usize,
+    view: &View,
+    write: ViewWrite,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let (event, updated_columns) = match &write {
+        ViewWrite::Insert { .. } => (ast::TriggerEvent::Insert, vec![]),
+        ViewWrite::Update { sets, .. } => (
+            ast::TriggerEvent::Update,
+            sets.iter()
+                .flat_map(|set| set.col_names.iter())
+                .map(|name| normalize_ident(&name.0))
+                .collect(),
+        ),
+        ViewWrite::Delete { .. } => (ast::TriggerEvent::Delete, vec![]),
+    };
+    let triggers = triggers_to_fire(
+        schema,
+        database_id,
+        &view.name,
+        &event,
+        ast::TriggerTime::InsteadOf,
+        &updated_columns,
+    );
+    if triggers.is_empty() {
+        bail_parse_error!("cannot modify {} because it is a view", view.name);
+    }
+
+    // The rows the triggers refer to have the columns of the view
+    let subquery = view_as_subquery(
+        schema,
+        view,
+        view.name.clone(),
+        syms,
+        &mut program.table_reference_counter,
+    )?;
+    let Table::FromClauseSubquery(subquery) = subquery.table else {
+        unreachable!("a view is planned as a FROM clause subquery");
+    };
+    let table = BTreeTable {
+        root_page: 0,
+        name: view.name.clone(),
+        primary_key_columns: vec![],
+        primary_key_conflict: None,
+        columns: subquery.columns,
+        has_rowid: true,
+        is_strict: false,
+        unique_sets: None,
+        check_constraints: vec![],
+        foreign_keys: vec![],
+    };
+    let column_names = table
+        .columns
+        .iter()
+        .map(|column| column.name.clone().expect("Column name must be present"))
+        .collect::<Vec<_>>();
+    let num_columns = column_names.len();
+
+    // The query selecting the rows, and where the columns of NEW and OLD are in its result
+    let view_name = QualifiedName::fullname(
+        Name(schema.database_name(database_id).to_string()),
+        Name(view.name.clone()),
+    );
+    let select_from_view = |columns: Vec<Expr>, where_clause: Option<Box<Expr>>| ast::Select {
+        with: None,
+        body: ast::SelectBody {
+            select: Box::new(ast::OneSelect::Select(Box::new(ast::SelectInner {
+                distinctness: None,
+                columns: columns
+                    .into_iter()
+                    .map(|expr| ast::ResultColumn::Expr(expr, None))
+                    .collect(),
+                from: Some(ast::FromClause::single(ast::SelectTable::Table(
+                    view_name.clone(),
+                    None,
+                    None,
+                ))),
+                where_clause: where_clause.map(|expr| *expr),
+                group_by: None,
+                window_clause: None,
+            }))),
+            compounds: None,
+        },
+        order_by: None,
+        limit: None,
+    };
+    let column_exprs = || {
+        column_names
+            .iter()
+            .map(|name| Expr::Id(ast::Id(name.clone())))
+            .collect::<Vec<_>>()
+    };
+    // Whether the columns of an INSERT are listed, to check the number of values inserted
+    let insert_columns_listed = match &write {
+        ViewWrite::Insert { columns, .. } => Some(columns.is_some()),
+        _ => None,
+    };
+    let (select, new_columns, old_columns) = match write {
+        ViewWrite::Insert { columns, body } => {
+            let select = match body {
+                ast::InsertBody::Select(_, Some(_)) => {
+                    bail_parse_error!("cannot UPSERT a view");
+                }
+                ast::InsertBody::Select(select, None) => *select,
+                ast::InsertBody::DefaultValues => ast::Select {
+                    with: None,
+                    body: ast::SelectBody {
+                        select: Box::new(ast::OneSelect::Values(vec![vec![
+                            Expr::Literal(
+                                ast::Literal::Null
+                            );
+                            num_columns
+                        ]])),
+                        compounds: None,
+                    },
+                    order_by: None,
+                    limit: None,
+                },
+            };
+            let new_columns = match columns {
+                None => (0..num_columns).map(Some).collect::<Vec<_>>(),
+                Some(columns) => {
+                    let columns = columns
+                        .iter()
+                        .map(|name| {
+                            let name = normalize_ident(&name.0);
+                            if !column_names.contains(&name) {
+                                bail_parse_error!(
+                                    "table {} has no column named {}",
+                                    view.name,
+                                    name
+                                );
+                            }
+                            Ok(name)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    column_names
+                        .iter()
+                        .map(|name| columns.iter().position(|column| column == name))
+                        .collect()
+                }
+            };
+            (select, new_columns, None)
+        }
+        ViewWrite::Update { sets, where_clause } => {
+            let mut new_values = column_exprs();
+            for set in sets {
+                let values = match set.expr {
+                    expr if set.col_names.len() == 1 => vec![expr],
+                    Expr::Parenthesized(exprs) => exprs,
+                    expr => vec![expr],
+                };
+                if values.len() != set.col_names.len() {
+                    bail_parse_error!(
+                        "{} columns assigned {} values",
+                        set.col_names.len(),
+                        values.len()
+                    );
+                }
+                for (name, value) in set.col_names.iter().zip(values) {
+                    let name = normalize_ident(&name.0);
+                    let Some(idx) = column_names.iter().position(|column| *column == name) else {
+                        bail_parse_error!("no such column: {}", name);
+                    };
+                    new_values[idx] = value;
+                }
+            }
+            let mut columns = column_exprs();
+            columns.extend(new_values);
+            (
+                select_from_view(columns, where_clause),
+                (num_columns..2 * num_columns).map(Some).collect(),
+                Some(0..num_columns),
+            )
+        }
+        ViewWrite::Delete { where_clause } => (
+            select_from_view(column_exprs(), where_clause),
+            vec![],
+            Some(0..num_columns),
+        ),
+    };
+
+    // Collect the rows
+    let yield_reg = program.alloc_register();
+    let jump_on_definition_label = program.allocate_label();
+    let start_offset_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: jump_on_definition_label,
+        start_offset: start_offset_label,
+    });
+    program.preassign_label_to_next_insn(start_offset_label);
+    let query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start: start_offset_label,
+    };
+    program.incr_nesting();
+    let result = translate_select(schema, select, syms, program, query_destination)?;
+    program = result.program;
+    program.decr_nesting();
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(jump_on_definition_label);
+
+    if let Some(columns_listed) = insert_columns_listed {
+        let num_values = result.num_result_cols;
+        let num_targets = new_columns.iter().flatten().count();
+        if num_values != num_targets {
+            if columns_listed {
+                bail_parse_error!("{} values for {} columns", num_values, num_targets);
+            }
+            bail_parse_error!(
+                "table {} has {} columns but {} values were supplied",
+                view.name,
+                num_columns,
+                num_values
+            );
+        }
+    }
+
+    let rows_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(Rc::new(table.clone())));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: rows_cursor_id,
+        is_table: true,
+    });
+    let collect_loop_label = program.allocate_label();
+    let collect_done_label = program.allocate_label();
+    program.preassign_label_to_next_insn(collect_loop_label);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: collect_done_label,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: yield_reg + 1,
+        count: result.num_result_cols,
+        dest_reg: record_reg,
+        index_name: None,
+    });
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: rows_cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: rows_cursor_id,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new().ephemeral(true),
+        table_name: String::new(),
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: collect_loop_label,
+    });
+    program.preassign_label_to_next_insn(collect_done_label);
+
+    // Run the triggers for each row. Views have no rowid, NEW.rowid and OLD.rowid are NULL.
+    let loop_start_label = program.allocate_label();
+    let loop_end_label = program.allocate_label();
+    let row_done_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: rows_cursor_id,
+        pc_if_empty: loop_end_label,
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+    let emit_row = |program: &mut ProgramBuilder, columns: &[Option<usize>]| {
+        let rowid_reg = program.alloc_registers(num_columns + 1);
+        program.emit_null(rowid_reg, None);
+        for (i, column) in columns.iter().enumerate() {
+            match column {
+                // The rows are wider than the view when they hold both OLD and NEW
+                Some(column) => program.emit_insn(Insn::Column {
+                    cursor_id: rows_cursor_id,
+                    column: *column,
+                    dest: rowid_reg + 1 + i,
+                    default: None,
+                }),
+                None => program.emit_null(rowid_reg + 1 + i, None),
+            }
+        }
+        TriggerRowRegisters {
+            rowid_reg,
+            columns_start_reg: rowid_reg + 1,
+        }
+    };
+    let new = (!new_columns.is_empty()).then(|| emit_row(&mut program, &new_columns));
+    let old =
+        old_columns.map(|columns| emit_row(&mut program, &columns.map(Some).collect::<Vec<_>>()));
+    emit_triggers(
+        &mut program,
+        schema,
+        syms,
+        &triggers,
+        &table,
+        new,
+        old,
+        row_done_label,
+    )?;
+    program.preassign_label_to_next_insn(row_done_label);
+    program.emit_insn(Insn::Next {
+        cursor_id: rows_cursor_id,
+        pc_if_next: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(loop_end_label);
+
+    program.epilogue(TransactionMode::Write);
+    Ok(program)
+}
+
+fn emit_trigger_body(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    trigger: &Trigger,
+    row_refs: &TriggerRowRefs,
+) -> Result<()> {
+    let trigger_end_label = program.allocate_label();
+    if let Some(when_clause) = &trigger.when_clause {
+        let mut when_clause = when_clause.clone();
+        rewrite_expr(&mut when_clause, &mut 1)?;
+        row_refs.rewrite_expr(&mut when_clause)?;
+        let when_true_label = program.allocate_label();
+        translate_condition_expr(
+            program,
+            &TableReferences::new(vec![], vec![]),
+            &when_clause,
+            ConditionMetadata {
+                jump_if_condition_is_true: false,
+                jump_target_when_true: when_true_label,
+                jump_target_when_false: trigger_end_label,
+            },
+            &Resolver::new(schema, syms),
+        )?;
+        program.preassign_label_to_next_insn(when_true_label);
+    }
+
+    for command in &trigger.commands {
+        let mut command = command.clone();
+        row_refs.rewrite_command(&mut command)?;
+        emit_trigger_command(program, schema, syms, command)?;
+    }
+
+    program.preassign_label_to_next_insn(trigger_end_label);
+    Ok(())
+}
+
+fn emit_trigger_command(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    command: ast::TriggerCmd,
+) -> Result<()> {
+    match command {
+        ast::TriggerCmd::Insert(insert) => {
+            let ast::TriggerCmdInsert {
+                or_conflict,
+                tbl_name,
+                col_names,
+                select,
+                upsert,
+                returning,
+            } = *insert;
+            if returning.is_some() {
+                bail_parse_error!("cannot use RETURNING in a trigger");
+            }
+            translate_nested(program, |program| {
+                translate_insert(
+                    schema,
+                    None,
+                    or_conflict,
+                    QualifiedName::single(tbl_name),
+                    col_names,
+                    ast::InsertBody::Select(select, upsert),
+                    None,
+                    syms,
+                    program,
+                )
+            })
+        }
+        ast::TriggerCmd::Update(update) => {
+            let ast::TriggerCmdUpdate {
+                or_conflict,
+                tbl_name,
+                sets,
+                from,
+                where_clause,
+            } = *update;
+            let mut update = ast::Update {
+                with: None,
+                or_conflict,
+                tbl_name: QualifiedName::single(tbl_name),
+                indexed: None,
+                sets,
+                from,
+                where_clause: where_clause.map(Box::new),
+                returning: None,
+                order_by: None,
+                limit: None,
+            };
+            translate_nested(program, |program| {
+                translate_update(schema, &mut update, syms, program)
+            })
+        }
+        ast::TriggerCmd::Delete(delete) => {
+            let ast::TriggerCmdDelete {
+                tbl_name,
+                where_clause,
+            } = *delete;
+            translate_nested(program, |program| {
+                translate_delete(
+                    schema,
+                    &QualifiedName::single(tbl_name),
+                    where_clause.map(Box::new),
+                    None,
+                    None,
+                    syms,
+                    program,
+                )
+            })
+        }
+        ast::TriggerCmd::Select(select) => {
+            // A SELECT in a trigger body only runs for its side effects (e.g. RAISE()),
+            // so run it as a coroutine and discard the rows it yields.
+            let yield_reg = program.alloc_register();
+            let jump_on_definition_label = program.allocate_label();
+            let start_offset_label = program.allocate_label();
+            program.emit_insn(Insn::InitCoroutine {
+                yield_reg,
+                jump_on_definition: jump_on_definition_label,
+                start_offset: start_offset_label,
+            });
+            program.preassign_label_to_next_insn(start_offset_label);
+
+            let query_destination = QueryDestination::CoroutineYield {
+                yield_reg,
+                coroutine_implementation_start: start_offset_label,
+            };
+            translate_nested(program, |program| {
+                Ok(translate_select(schema, *select, syms, program, query_destination)?.program)
+            })?;
+
+            program.emit_insn(Insn::EndCoroutine { yield_reg });
+            program.preassign_label_to_next_insn(jump_on_definition_label);
+
+            let loop_start_label = program.allocate_label();
+            let loop_end_label = program.allocate_label();
+            program.preassign_label_to_next_insn(loop_start_label);
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: loop_end_label,
+            });
+            program.emit_insn(Insn::Goto {
+                target_pc: loop_start_label,
+            });
+            program.preassign_label_to_next_insn(loop_end_label);
+            Ok(())
+        }
+    }
+}
+
+/// Runs one of the `translate_*` functions, which take the [ProgramBuilder] by value,
+/// as a nested statement of the program we hold a mutable reference to.
+pub(crate) fn translate_nested(
+    program: &mut ProgramBuilder,
+    translate: impl FnOnce(ProgramBuilder) -> Result<ProgramBuilder>,
+) -> Result<()> {
+    let placeholder = ProgramBuilder::new(
+        QueryMode::Normal,
+        CaptureDataChangesMode::Off,
+        false,
+        OptimizerOptions::default(),
+        false,
+        false,
+        ProgramBuilderOpts {
+            num_cursors: 0,
+            approx_num_insns: 0,
+            approx_num_labels: 0,
+        },
+    );
+    let mut outer = std::mem::replace(program, placeholder);
+    // Nested statements overwrite the result columns with their own
+    let result_columns = std::mem::take(&mut outer.result_columns);
+    outer.incr_nesting();
+    let mut nested = translate(outer)?;
+    nested.decr_nesting();
+    nested.result_columns = result_columns;
+    *program = nested;
+    Ok(())
+}
+
+/// Resolves `NEW.x` / `OLD.x` references in a trigger body to the registers holding the row.
+struct TriggerRowRefs<'a> {
+    table: &'a BTreeTable,
+    new: Option<TriggerRowRegisters>,
+    old: Option<TriggerRowRegisters>,
+}
+
+impl TriggerRowRefs<'_> {
+    /// Returns the register `qualifier.column` refers to, or `None` if `qualifier` is
+    /// neither `NEW` nor `OLD`.
+    fn resolve(&self, qualifier: &Name, column: &Name) -> Result<Option<usize>> {
+        let row = match normalize_ident(&qualifier.0).as_str() {
+            "new" => self.new,
+            "old" => self.old,
+            _ => return Ok(None),
+        };
+        let Some(row) = row else {
+            bail_parse_error!("no such column: {}.{}", qualifier.0, column.0);
+        };
+        let column_name = normalize_ident(&column.0);
+        match self.table.get_column(&column_name) {
+            Some((_, col)) if col.is_rowid_alias => Ok(Some(row.rowid_reg)),
+            Some((idx, _)) => Ok(Some(row.columns_start_reg + idx)),
+            None if ROWID_NAMES.contains(&column_name.as_str()) => Ok(Some(row.rowid_reg)),
+            None => bail_parse_error!("no such column: {}.{}", qualifier.0, column.0),
+        }
+    }
+
+    fn rewrite_expr(&self, expr: &mut Expr) -> Result<()> {
+        walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+            match expr {
+                Expr::Qualified(qualifier, column) => {
+                    if let Some(reg) = self.resolve(qualifier, column)? {
+                        *expr = Expr::Register(reg);
+                    }
+                }
+                Expr::Exists(select) | Expr::Subquery(select) => self.rewrite_select(select)?,
+                Expr::InSelect { rhs, .. } => self.rewrite_select(rhs)?,
+                _ => {}
+            }
+            Ok(())
+        })
+    }
+
+    fn rewrite_command(&self, command: &mut ast::TriggerCmd) -> Result<()> {
+        match command {
+            ast::TriggerCmd::Update(update) => {
+                for set in update.sets.iter_mut() {
+                    self.rewrite_expr(&mut set.expr)?;
+                }
+                if let Some(from) = &mut update.from {
+                    self.rewrite_from(from)?;
+                }
+                if let Some(where_clause) = &mut update.where_clause {
+                    self.rewrite_expr(where_clause)?;
+                }
+            }
+            ast::TriggerCmd::Insert(insert) => self.rewrite_select(&mut insert.select)?,
+            ast::TriggerCmd::Delete(delete) => {
+                if let Some(where_clause) = &mut delete.where_clause {
+                    self.rewrite_expr(where_clause)?;
+                }
+            }
+            ast::TriggerCmd::Select(select) => self.rewrite_select(select)?,
+        }
+        Ok(())
+    }
+
+    fn rewrite_select(&self, select: &mut ast::Select) -> Result<()> {
+        if let Some(with) = &mut select.with {
+            for cte in with.ctes.iter_mut() {
+                self.rewrite_select(&mut cte.select)?;
+            }
+        }
+        self.rewrite_one_select(&mut select.body.select)?;
+        if let Some(compounds) = &mut select.body.compounds {
+            for compound in compounds.iter_mut() {
+                self.rewrite_one_select(&mut compound.select)?;
+            }
+        }
+        if let Some(order_by) = &mut select.order_by {
+            for sorted_column in order_by.iter_mut() {
+                self.rewrite_expr(&mut sorted_column.expr)?;
+            }
+        }
+        if let Some(limit) = &mut select.limit {
+            self.rewrite_expr(&mut limit.expr)?;
+            if let Some(offset) = &mut limit.offset {
+                self.rewrite_expr(offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rewrite_one_select(&self, one_select: &mut ast::OneSelect) -> Result<()> {
+        match one_select {
+            ast::OneSelect::Select(select) => {
+                for column in select.columns.iter_mut() {
+                    if let ast::ResultColumn::Expr(expr, ..) = column {
+                        self.rewrite_expr(expr)?;
+                    }
+                }
+                if let Some(from) = &mut select.from {
+                    self.rewrite_from(from)?;
+                }
+                if let Some(where_clause) = &mut select.where_clause {
+                    self.rewrite_expr(where_clause)?;
+                }
+                if let Some(group_by) = &mut select.group_by {
+                    for expr in group_by.exprs.iter_mut() {
+                        self.rewrite_expr(expr)?;
+                    }
+                    if let Some(having) = &mut group_by.having {
+                        self.rewrite_expr(having)?;
+                    }
+                }
+            }
+            ast::OneSelect::Values(rows) => {
+                for expr in rows.iter_mut().flat_map(|row| row.iter_mut()) {
+                    self.rewrite_expr(expr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rewrite_from(&self, from: &mut ast::FromClause) -> Result<()> {
+        if let Some(select_table) = &mut from.select {
+            self.rewrite_select_table(select_table)?;
+        }
+        if let Some(joins) = &mut from.joins {
+            for join in joins.iter_mut() {
+                self.rewrite_select_table(&mut join.table)?;
+                if let Some(ast::JoinConstraint::On(expr)) = &mut join.constraint {
+                    self.rewrite_expr(expr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rewrite_select_table(&self, select_table: &mut ast::SelectTable) -> Result<()> {
+        match select_table {
+            ast::SelectTable::Table(..) => {}
+            ast::SelectTable::TableCall(_, args, _) => {
+                for arg in args.iter_mut().flatten() {
+                    self.rewrite_expr(arg)?;
+                }
+            }
+            ast::SelectTable::Select(select, _) => self.rewrite_select(select)?,
+            ast::SelectTable::Sub(from, _) => self.rewrite_from(from)?,
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use std::rc::Rc;
+use std::sync::Arc;
 
-use crate::schema::{BTreeTable, Column, Type};
+use crate::schema::{BTreeTable, Column, Index, Type};
 use crate::translate::optimizer::optimize_select_plan;
 use crate::translate::plan::{Operation, QueryDestination, Search, SelectPlan};
 use crate::vdbe::builder::CursorType;
@@ -11,16 +12,19 @@ use crate::{
     vdbe::builder::{ProgramBuilder, ProgramBuilderOpts},
     SymbolTable,
 };
-use turso_sqlite3_parser::ast::{self, Expr, ResultColumn, SortOrder, Update};
+use turso_sqlite3_parser::ast::{Expr, ResolveType, SortOrder, TableInternalId, Update};
 
 use super::emitter::emit_program;
+use super::generated::columns_changed_by_update;
+use super::index::index_uses_columns;
 use super::optimizer::optimize_plan;
 use super::plan::{
     ColumnUsedMask, IterationDirection, JoinedTable, Plan, ResultSetColumn, TableReferences,
     UpdatePlan,
 };
 use super::planner::bind_column_references;
-use super::planner::{parse_limit, parse_where};
+use super::planner::{parse_limit, parse_returning, parse_where};
+use super::trigger::{translate_view_write, ViewWrite};
 /*
 * Update is simple. By default we scan the table, and for each row, we check the WHERE
 * clause. If it evaluates to true, we build the new record with the updated value and insert.
@@ -56,8 +60,29 @@ pub fn translate_update(
     syms: &SymbolTable,
     mut program: ProgramBuilder,
 ) -> crate::Result<ProgramBuilder> {
+    let database_id = schema.locate_database(&body.tbl_name)?;
+    let database_schema = schema.database_schema(database_id);
+    let table_name = body.tbl_name.name.0.as_str();
+    if database_schema.get_table(table_name).is_none() {
+        if let Some(view) = database_schema.get_view(table_name) {
+            if body.with.is_some() {
+                bail_parse_error!("WITH clause is not supported");
+            }
+            if body.returning.is_some() {
+                bail_parse_error!("RETURNING is not supported for views");
+            }
+            if body.from.is_some() || body.order_by.is_some() || body.limit.is_some() {
+                bail_parse_error!("UPDATE of a view does not support FROM, ORDER BY or LIMIT");
+            }
+            let write = ViewWrite::Update {
+                sets: std::mem::take(&mut body.sets),
+                where_clause: body.where_clause.take(),
+            };
+            return translate_view_write(schema, syms, database_id, &view, write, program);
+        }
+    }
     let mut plan = prepare_update_plan(&mut program, schema, body)?;
-    optimize_plan(&mut plan, schema)?;
+    optimize_plan(&mut plan, schema, program.optimizer_options())?;
     // TODO: freestyling these numbers
     let opts = ProgramBuilderOpts {
         num_cursors: 1,
@@ -77,7 +102,7 @@ pub fn translate_update_with_after(
     after: impl FnOnce(&mut ProgramBuilder),
 ) -> crate::Result<ProgramBuilder> {
     let mut plan = prepare_update_plan(&mut program, schema, body)?;
-    optimize_plan(&mut plan, schema)?;
+    optimize_plan(&mut plan, schema, program.optimizer_options())?;
     // TODO: freestyling these numbers
     let opts = ProgramBuilderOpts {
         num_cursors: 1,
@@ -97,19 +122,14 @@ pub fn prepare_update_plan(
     if body.with.is_some() {
         bail_parse_error!("WITH clause is not supported");
     }
-    if body.or_conflict.is_some() {
-        bail_parse_error!("ON CONFLICT clause is not supported");
-    }
     let table_name = &body.tbl_name.name;
-    if schema.table_has_indexes(&table_name.to_string()) && !schema.indexes_enabled() {
-        // Let's disable altering a table with indices altogether instead of checking column by
-        // column to be extra safe.
-        bail_parse_error!(
-            "UPDATE table disabled for table with indexes is disabled by default. Run with `--experimental-indexes` to enable this feature."
-        );
-    }
-    let table = match schema.get_table(table_name.0.as_str()) {
+    let database_id = schema.locate_database(&body.tbl_name)?;
+    let database_schema = schema.database_schema(database_id);
+    let table = match database_schema.get_table(table_name.0.as_str()) {
         Some(table) => table,
+        None if database_schema.get_view(table_name.0.as_str()).is_some() => {
+            bail_parse_error!("cannot modify {} because it is a view", table_name)
+        }
         None => bail_parse_error!("Parse error: no such table: {}", table_name),
     };
     let iter_dir = body
@@ -139,6 +159,7 @@ pub fn prepare_update_plan(
         },
         join_info: None,
         col_used_mask: ColumnUsedMask::default(),
+        database_id,
     }];
     let mut table_references = TableReferences::new(joined_tables, vec![]);
     let set_clauses = body
@@ -162,33 +183,23 @@ pub fn prepare_update_plan(
                         ident, table_name.0
                     ))
                 })?;
+            let column = &table.columns()[col_index];
+            if column.is_generated() {
+                bail_parse_error!(
+                    "cannot UPDATE generated column \"{}\"",
+                    column.name.as_deref().unwrap_or_default()
+                );
+            }
 
-            let _ = bind_column_references(&mut set.expr, &mut table_references, None);
+            let _ = bind_column_references(&mut set.expr, &mut table_references, None, schema);
             Ok((col_index, set.expr.clone()))
         })
         .collect::<Result<Vec<(usize, Expr)>, crate::LimboError>>()?;
 
-    let mut result_columns = vec![];
-    if let Some(returning) = &mut body.returning {
-        for rc in returning.iter_mut() {
-            if let ResultColumn::Expr(expr, alias) = rc {
-                bind_column_references(expr, &mut table_references, None)?;
-                result_columns.push(ResultSetColumn {
-                    expr: expr.clone(),
-                    alias: alias.as_ref().and_then(|a| {
-                        if let ast::As::As(name) = a {
-                            Some(name.to_string())
-                        } else {
-                            None
-                        }
-                    }),
-                    contains_aggregates: false,
-                });
-            } else {
-                bail_parse_error!("Only expressions are allowed in RETURNING clause");
-            }
-        }
+    if body.returning.is_some() && table.virtual_table().is_some() {
+        bail_parse_error!("RETURNING is not supported for virtual tables");
     }
+    let result_columns = parse_returning(body.returning.take(), &mut table_references, schema)?;
     let order_by = body.order_by.as_ref().map(|order| {
         order
             .iter()
@@ -205,100 +216,137 @@ pub fn prepare_update_plan(
     let rowid_alias_used = set_clauses.iter().fold(false, |accum, (idx, _)| {
         accum || columns[*idx].is_rowid_alias
     });
+    // Changing the PRIMARY KEY of a WITHOUT ROWID table moves the row within the b-tree being
+    // scanned, so the keys of the rows to update are collected first as well.
+    let clustered_index = table.btree().and_then(|table| table.clustered_index());
+    let primary_key_used =
+        clustered_index.is_some() && set_clauses.iter().any(|(idx, _)| columns[*idx].primary_key);
 
-    let (ephemeral_plan, mut where_clause) = if rowid_alias_used {
-        let mut where_clause = vec![];
-        let internal_id = program.table_reference_counter.next();
+    // Check what indexes will need to be updated by checking set_clauses and see
+    // if a column is contained in an index.
+    let updated_columns = set_clauses.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
+    let updated_columns = match table.btree() {
+        Some(btree_table) => columns_changed_by_update(&btree_table, &updated_columns),
+        None => updated_columns,
+    };
+    let indexes = database_schema.get_indices(&table_name.0);
+    // Updating an indexed column moves its entries within the index, which the loop may be
+    // scanning, so the rows to update are collected first in that case too.
+    let indexed_column_used = table.btree().is_some_and(|btree_table| {
+        indexes
+            .iter()
+            .any(|index| index_uses_columns(&btree_table, index, &updated_columns))
+    });
 
-        let joined_tables = vec![JoinedTable {
-            table: match table.as_ref() {
-                Table::Virtual(vtab) => Table::Virtual(vtab.clone()),
-                Table::BTree(btree_table) => Table::BTree(btree_table.clone()),
-                _ => unreachable!(),
-            },
-            identifier: table_name.0.clone(),
-            internal_id,
-            op: Operation::Scan {
-                iter_dir,
-                index: None,
-            },
-            join_info: None,
-            col_used_mask: ColumnUsedMask::default(),
-        }];
-        let mut table_references = TableReferences::new(joined_tables, vec![]);
+    let (ephemeral_plan, mut where_clause) =
+        if rowid_alias_used || primary_key_used || indexed_column_used {
+            let mut where_clause = vec![];
+            let internal_id = program.table_reference_counter.next();
 
-        // Parse the WHERE clause
-        parse_where(
-            body.where_clause.as_ref().map(|w| *w.clone()),
-            &mut table_references,
-            Some(&result_columns),
-            &mut where_clause,
-        )?;
+            let joined_tables = vec![JoinedTable {
+                table: match table.as_ref() {
+                    Table::Virtual(vtab) => Table::Virtual(vtab.clone()),
+                    Table::BTree(btree_table) => Table::BTree(btree_table.clone()),
+                    _ => unreachable!(),
+                },
+                identifier: table_name.0.clone(),
+                internal_id,
+                op: Operation::Scan {
+                    iter_dir,
+                    index: None,
+                },
+                join_info: None,
+                col_used_mask: ColumnUsedMask::default(),
+                database_id,
+            }];
+            let mut table_references = TableReferences::new(joined_tables, vec![]);
 
-        let table = Rc::new(BTreeTable {
-            root_page: 0, // Not relevant for ephemeral table definition
-            name: "ephemeral_scratch".to_string(),
-            has_rowid: true,
-            primary_key_columns: vec![],
-            columns: vec![Column {
-                name: Some("rowid".to_string()),
-                ty: Type::Integer,
-                ty_str: "INTEGER".to_string(),
-                primary_key: true,
-                is_rowid_alias: false,
-                notnull: true,
-                default: None,
-                unique: false,
-                collation: None,
-                hidden: false,
-            }],
-            is_strict: false,
-            unique_sets: None,
-        });
+            // Parse the WHERE clause
+            parse_where(
+                body.where_clause.as_ref().map(|w| *w.clone()),
+                &mut table_references,
+                Some(&result_columns),
+                &mut where_clause,
+                schema,
+            )?;
 
-        let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+            let (ephemeral_result_columns, query_destination) = match clustered_index {
+                Some(clustered_index) => {
+                    let index = Arc::new(Index {
+                        name: "ephemeral_scratch".to_string(),
+                        table_name: String::new(),
+                        ephemeral: true,
+                        root_page: 0,
+                        columns: clustered_index.columns.clone(),
+                        unique: false,
+                        has_rowid: false,
+                        on_conflict: None,
+                        where_clause: None,
+                        pk_suffix_len: 0,
+                    });
+                    let temp_cursor_id =
+                        program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+                    let ephemeral_result_columns = index
+                        .columns
+                        .iter()
+                        .map(|column| {
+                            table_references.mark_column_used(internal_id, column.pos_in_table);
+                            ResultSetColumn {
+                                expr: Expr::Column {
+                                    database: None,
+                                    table: internal_id,
+                                    column: column.pos_in_table,
+                                    is_rowid_alias: false,
+                                },
+                                alias: None,
+                                contains_aggregates: false,
+                            }
+                        })
+                        .collect();
+                    (
+                        ephemeral_result_columns,
+                        QueryDestination::EphemeralIndex {
+                            cursor_id: temp_cursor_id,
+                            index,
+                            is_delete: false,
+                        },
+                    )
+                }
+                _ => ephemeral_rowid_destination(program, internal_id),
+            };
 
-        let mut ephemeral_plan = SelectPlan {
-            table_references,
-            result_columns: vec![ResultSetColumn {
-                expr: Expr::RowId {
-                    database: None,
-                    table: internal_id,
-                },
-                alias: None,
-                contains_aggregates: false,
-            }],
-            where_clause,       // original WHERE terms from the UPDATE clause
-            group_by: None,     // N/A
-            order_by: None,     // N/A
-            aggregates: vec![], // N/A
-            limit: None,        // N/A
-            query_destination: QueryDestination::EphemeralTable {
-                cursor_id: temp_cursor_id,
-                table,
-            },
-            join_order: vec![],
-            offset: None,
-            contains_constant_false_condition: false,
-            distinctness: super::plan::Distinctness::NonDistinct,
-            values: vec![],
-        };
+            let mut ephemeral_plan = SelectPlan {
+                table_references,
+                result_columns: ephemeral_result_columns,
+                where_clause,       // original WHERE terms from the UPDATE clause
+                group_by: None,     // N/A
+                order_by: None,     // N/A
+                aggregates: vec![], // N/A
+                window: None,       // N/A
+                limit: None,        // N/A
+                query_destination,
+                join_order: vec![],
+                offset: None,
+                contains_constant_false_condition: false,
+                distinctness: super::plan::Distinctness::NonDistinct,
+                values: vec![],
+            };
 
-        optimize_select_plan(&mut ephemeral_plan, schema)?;
-        let table = ephemeral_plan
-            .table_references
-            .joined_tables()
-            .first()
-            .unwrap();
-        // We do not need to emit an ephemeral plan if we are not going to loop over the table values
-        if matches!(table.op, Operation::Search(Search::RowidEq { .. })) {
-            (None, vec![])
+            optimize_select_plan(&mut ephemeral_plan, schema, program.optimizer_options())?;
+            let table = ephemeral_plan
+                .table_references
+                .joined_tables()
+                .first()
+                .unwrap();
+            // We do not need to emit an ephemeral plan if we are not going to loop over the table values
+            if matches!(table.op, Operation::Search(Search::RowidEq { .. })) {
+                (None, vec![])
+            } else {
+                (Some(ephemeral_plan), vec![])
+            }
         } else {
-            (Some(ephemeral_plan), vec![])
-        }
-    } else {
-        (None, vec![])
-    };
+            (None, vec![])
+        };
 
     if ephemeral_plan.is_none() {
         // Parse the WHERE clause
@@ -307,6 +355,7 @@ pub fn prepare_update_plan(
             &mut table_references,
             Some(&result_columns),
             &mut where_clause,
+            schema,
         )?;
     };
 
@@ -317,17 +366,25 @@ pub fn prepare_update_plan(
         .map(|l| parse_limit(l))
         .unwrap_or(Ok((None, None)))?;
 
-    // Check what indexes will need to be updated by checking set_clauses and see
-    // if a column is contained in an index.
-    let indexes = schema.get_indices(&table_name.0);
+    // Resolving a conflict with REPLACE deletes the conflicting row from every index, so all of
+    // them are needed then.
+    let may_replace = body.or_conflict == Some(ResolveType::Replace)
+        || (body.or_conflict.is_none()
+            && (table
+                .btree()
+                .is_some_and(|t| t.primary_key_conflict == Some(ResolveType::Replace))
+                || indexes
+                    .iter()
+                    .any(|index| index.on_conflict == Some(ResolveType::Replace))));
+    // Every index entry ends with the rowid of its row, so changing the rowid moves all of them.
     let indexes_to_update = indexes
         .iter()
         .filter(|index| {
-            index.columns.iter().any(|index_column| {
-                set_clauses
-                    .iter()
-                    .any(|(set_index_column, _)| index_column.pos_in_table == *set_index_column)
-            })
+            may_replace
+                || rowid_alias_used
+                || table.btree().is_some_and(|btree_table| {
+                    index_uses_columns(&btree_table, index, &updated_columns)
+                })
         })
         .cloned()
         .collect();
@@ -342,6 +399,57 @@ pub fn prepare_update_plan(
         offset,
         contains_constant_false_condition: false,
         indexes_to_update,
+        or_conflict: body.or_conflict,
         ephemeral_plan,
     }))
 }
+
+/// The destination of the rowids of the rows an UPDATE changes, when they are collected before
+/// updating any of them: a result column with the rowid, stored in an ephemeral table.
+fn ephemeral_rowid_destination(
+    program: &mut ProgramBuilder,
+    internal_id: TableInternalId,
+) -> (Vec<ResultSetColumn>, QueryDestination) {
+    let table = Rc::new(BTreeTable {
+        root_page: 0, // Not relevant for ephemeral table definition
+        name: "ephemeral_scratch".to_string(),
+        has_rowid: true,
+        primary_key_columns: vec![],
+        primary_key_conflict: None,
+        columns: vec![Column {
+            name: Some("rowid".to_string()),
+            ty: Type::Integer,
+            ty_str: "INTEGER".to_string(),
+            primary_key: true,
+            is_rowid_alias: false,
+            notnull: true,
+            default: None,
+            unique: false,
+            collation: None,
+            hidden: false,
+            notnull_conflict: None,
+            unique_conflict: None,
+            generated: None,
+        }],
+        is_strict: false,
+        unique_sets: None,
+        check_constraints: vec![],
+        foreign_keys: vec![],
+    });
+
+    let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    (
+        vec![ResultSetColumn {
+            expr: Expr::RowId {
+                database: None,
+                table: internal_id,
+            },
+            alias: None,
+            contains_aggregates: false,
+        }],
+        QueryDestination::EphemeralTable {
+            cursor_id: temp_cursor_id,
+            table,
+        },
+    )
+}
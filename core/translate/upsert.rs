@@ -0,0 +1,694 @@
+//! UPSERT: the `ON CONFLICT` clauses of an INSERT statement.
+//!
+//! An upsert clause turns the uniqueness constraint violation of an INSERT into either a no-op
+//! (`DO NOTHING`) or an UPDATE of the existing row that caused the conflict (`DO UPDATE`).
+//!
+//! More info: https://www.sqlite.org/lang_upsert.html
+
+use std::rc::Rc;
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, Expr};
+
+use crate::error::{SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY};
+use crate::schema::{BTreeTable, Index, IndexColumn, Table};
+use crate::translate::emitter::{
+    emit_cdc_full_record, emit_cdc_insns, emit_cdc_patch_record, emit_index_entry_row_key,
+    emit_returning_row, emit_row_key, emit_row_keys_eq, emit_seek_row_key, OperationMode, Resolver,
+};
+use crate::translate::expr::{
+    translate_condition_expr, translate_expr_no_constant_opt, walk_expr_mut, ConditionMetadata,
+    NoConstantOptReason,
+};
+use crate::translate::foreign_key::emit_foreign_key_checks;
+use crate::translate::generated::{
+    columns_changed_by_update, emit_generated_columns, emit_table_record,
+};
+use crate::translate::index::{
+    emit_index_key, emit_partial_index_check, unique_index_violation, IndexKeySource,
+};
+use crate::translate::insert::emit_check_constraints;
+use crate::translate::optimizer::rewrite_expr;
+use crate::translate::plan::{ResultSetColumn, TableReferences};
+use crate::translate::trigger::{
+    emit_triggers, triggers_to_fire, TriggerRowRegisters, ROWID_NAMES,
+};
+use crate::util::{exprs_are_equivalent, normalize_ident};
+use crate::vdbe::builder::ProgramBuilder;
+use crate::vdbe::insn::{CmpInsFlags, IdxInsertFlags, InsertFlags, Insn};
+use crate::vdbe::{BranchOffset, CursorID};
+use crate::{bail_parse_error, Result};
+
+/// The uniqueness constraint an upsert clause applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpsertTarget {
+    /// The INTEGER PRIMARY KEY, i.e. the rowid.
+    Rowid,
+    /// The UNIQUE or PRIMARY KEY constraint backed by the index with this name.
+    Index(String),
+    /// No conflict target: any uniqueness constraint. Only allowed for the last clause.
+    Any,
+}
+
+/// An `ON CONFLICT` clause of an INSERT, with its conflict target resolved against the table.
+#[derive(Debug, Clone)]
+pub struct Upsert {
+    pub target: UpsertTarget,
+    pub do_clause: ast::UpsertDo,
+}
+
+/// Resolves the chain of `ON CONFLICT` clauses of an INSERT into `table`, in order.
+pub fn resolve_upserts(
+    upsert: Option<ast::Upsert>,
+    table: &BTreeTable,
+    indexes: &[Arc<Index>],
+) -> Result<Vec<Upsert>> {
+    let mut upserts = Vec::new();
+    let mut next = upsert.map(Box::new);
+    while let Some(upsert) = next {
+        let ast::Upsert {
+            index,
+            do_clause,
+            next: rest,
+        } = *upsert;
+        let target = match index {
+            Some(index) => resolve_target(&index, table, indexes)?,
+            None => UpsertTarget::Any,
+        };
+        upserts.push(Upsert {
+            target,
+            do_clause: *do_clause,
+        });
+        next = rest;
+    }
+    Ok(upserts)
+}
+
+/// A term of an `ON CONFLICT` target: a table column or an indexed expression.
+enum TargetTerm<'a> {
+    Column(usize),
+    Expr(&'a Expr),
+}
+
+impl TargetTerm<'_> {
+    fn matches(&self, column: &IndexColumn) -> bool {
+        match (self, &column.expr) {
+            (TargetTerm::Column(pos), None) => *pos == column.pos_in_table,
+            (TargetTerm::Expr(expr), Some(indexed)) => exprs_are_equivalent(expr, indexed),
+            _ => false,
+        }
+    }
+}
+
+fn resolve_target(
+    index: &ast::UpsertIndex,
+    table: &BTreeTable,
+    indexes: &[Arc<Index>],
+) -> Result<UpsertTarget> {
+    let mut terms: Vec<TargetTerm> = Vec::with_capacity(index.targets.len());
+    for target in &index.targets {
+        let expr = match &target.expr {
+            Expr::Collate(expr, _) => expr.as_ref(),
+            expr => expr,
+        };
+        let term = match expr {
+            Expr::Id(ast::Id(name)) | Expr::Name(ast::Name(name)) => {
+                let name = normalize_ident(name);
+                let Some((pos, _)) = table.get_column(&name) else {
+                    bail_parse_error!("no such column: {}", name);
+                };
+                TargetTerm::Column(pos)
+            }
+            Expr::Parenthesized(exprs) if exprs.len() == 1 => TargetTerm::Expr(&exprs[0]),
+            expr => TargetTerm::Expr(expr),
+        };
+        let duplicate = terms.iter().any(|existing| match (existing, &term) {
+            (TargetTerm::Column(a), TargetTerm::Column(b)) => a == b,
+            (TargetTerm::Expr(a), TargetTerm::Expr(b)) => exprs_are_equivalent(a, b),
+            _ => false,
+        });
+        if !duplicate {
+            terms.push(term);
+        }
+    }
+    if let [TargetTerm::Column(pos)] = terms.as_slice() {
+        if table.columns[*pos].is_rowid_alias {
+            return Ok(UpsertTarget::Rowid);
+        }
+    }
+    // The PRIMARY KEY of a WITHOUT ROWID table is enforced by the table b-tree itself
+    let clustered_index = table.clustered_index();
+    // A partial index is only a valid target if the target repeats its WHERE clause
+    let index = clustered_index.iter().chain(indexes).find(|candidate| {
+        let where_matches = match (&candidate.where_clause, &index.where_clause) {
+            (None, _) => true,
+            (Some(indexed), Some(target)) => exprs_are_equivalent(target, indexed),
+            (Some(_), None) => false,
+        };
+        candidate.unique
+            && where_matches
+            && candidate.key_columns().len() == terms.len()
+            && candidate
+                .key_columns()
+                .iter()
+                .all(|column| terms.iter().any(|term| term.matches(column)))
+    });
+    match index {
+        Some(index) => Ok(UpsertTarget::Index(index.name.clone())),
+        None => bail_parse_error!(
+            "ON CONFLICT clause does not match any PRIMARY KEY or UNIQUE constraint"
+        ),
+    }
+}
+
+/// Returns the first upsert clause that applies to a conflict on the rowid.
+pub fn upsert_for_rowid(upserts: &[Upsert]) -> Option<&Upsert> {
+    upserts
+        .iter()
+        .find(|upsert| matches!(upsert.target, UpsertTarget::Rowid | UpsertTarget::Any))
+}
+
+/// Returns the first upsert clause that applies to a conflict on the index named `index_name`.
+pub fn upsert_for_index<'a>(upserts: &'a [Upsert], index_name: &str) -> Option<&'a Upsert> {
+    upserts.iter().find(|upsert| match &upsert.target {
+        UpsertTarget::Index(name) => name == index_name,
+        UpsertTarget::Any => true,
+        UpsertTarget::Rowid => false,
+    })
+}
+
+/// Rewrites the expressions of `upserts` like the rest of the statement, numbering anonymous
+/// parameters from `param_idx`.
+pub fn rewrite_upserts(upserts: &mut [Upsert], param_idx: &mut usize) -> Result<()> {
+    for upsert in upserts.iter_mut() {
+        if let ast::UpsertDo::Set { sets, where_clause } = &mut upsert.do_clause {
+            for set in sets.iter_mut() {
+                rewrite_expr(&mut set.expr, param_idx)?;
+            }
+            if let Some(where_clause) = where_clause {
+                rewrite_expr(where_clause, param_idx)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where the `DO UPDATE` of an upsert writes to.
+pub struct UpsertTable<'a> {
+    pub table: &'a Rc<BTreeTable>,
+    pub database_id: usize,
+    pub cursor_id: CursorID,
+    /// All the indexes of the table, with their open cursors.
+    pub indexes: &'a [(Arc<Index>, CursorID)],
+    pub cdc_cursor_id: Option<CursorID>,
+    /// The RETURNING clause of the INSERT, also evaluated for rows updated by the upsert.
+    pub returning: &'a [ResultSetColumn],
+}
+
+/// Emits the `DO UPDATE` of an upsert for the existing row with the key in `conflict_key_reg`:
+/// its rowid, or the PRIMARY KEY of a WITHOUT ROWID table. `excluded` holds the row that failed
+/// to be inserted.
+/// Jumps to `row_done_label` when done, or when the `WHERE` clause of the upsert is false.
+#[allow(clippy::too_many_arguments)]
+pub fn emit_upsert_update(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    target: &UpsertTable,
+    sets: &[ast::Set],
+    where_clause: Option<&Expr>,
+    conflict_key_reg: usize,
+    excluded: TriggerRowRegisters,
+    row_done_label: BranchOffset,
+) -> Result<()> {
+    let table = target.table;
+    let cursor_id = target.cursor_id;
+    let num_cols = table.columns.len();
+
+    // Load the existing row
+    let old_rowid_reg = program.alloc_register();
+    if table.has_rowid {
+        program.emit_insn(Insn::Copy {
+            src_reg: conflict_key_reg,
+            dst_reg: old_rowid_reg,
+            extra_amount: 0,
+        });
+    } else {
+        program.emit_null(old_rowid_reg, None);
+    }
+    emit_seek_row_key(program, table, cursor_id, conflict_key_reg, row_done_label);
+    let old_columns_start_reg = program.alloc_registers(num_cols);
+    for idx in 0..num_cols {
+        program.emit_column(cursor_id, idx, old_columns_start_reg + idx);
+    }
+    let old_row = TriggerRowRegisters {
+        rowid_reg: old_rowid_reg,
+        columns_start_reg: old_columns_start_reg,
+    };
+    let old_key_reg = emit_row_key(program, table, old_row);
+    emit_generated_columns(program, resolver, table, old_row, true)?;
+    let row_refs = UpsertRowRefs {
+        table,
+        existing: old_row,
+        excluded,
+    };
+
+    if let Some(where_clause) = where_clause {
+        let mut where_clause = where_clause.clone();
+        row_refs.rewrite_expr(&mut where_clause)?;
+        let update_label = program.allocate_label();
+        translate_condition_expr(
+            program,
+            &TableReferences::new(vec![], vec![]),
+            &where_clause,
+            ConditionMetadata {
+                jump_if_condition_is_true: false,
+                jump_target_when_true: update_label,
+                jump_target_when_false: row_done_label,
+            },
+            resolver,
+        )?;
+        program.preassign_label_to_next_insn(update_label);
+    }
+
+    // The updated row starts as a copy of the existing one
+    let new_rowid_reg = program.alloc_register();
+    let new_columns_start_reg = program.alloc_registers(num_cols);
+    program.emit_insn(Insn::Copy {
+        src_reg: old_rowid_reg,
+        dst_reg: new_rowid_reg,
+        extra_amount: 0,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: old_columns_start_reg,
+        dst_reg: new_columns_start_reg,
+        extra_amount: num_cols - 1,
+    });
+    let mut updated_columns = Vec::with_capacity(sets.len());
+    let mut updated_column_indexes = Vec::with_capacity(sets.len());
+    let mut rowid_updated = false;
+    for set in sets {
+        if set.col_names.len() != 1 {
+            bail_parse_error!(
+                "multi-column assignments in ON CONFLICT DO UPDATE are not supported"
+            );
+        }
+        let name = normalize_ident(&set.col_names[0].0);
+        let Some((idx, column)) = table.get_column(&name) else {
+            bail_parse_error!("no such column: {}", name);
+        };
+        if column.is_generated() {
+            bail_parse_error!(
+                "cannot UPDATE generated column \"{}\"",
+                column.name.as_deref().unwrap_or_default()
+            );
+        }
+        let mut expr = set.expr.clone();
+        row_refs.rewrite_expr(&mut expr)?;
+        // The register holds a copy of the existing value, so a constant must not be hoisted
+        let target_reg = if column.is_rowid_alias {
+            new_rowid_reg
+        } else {
+            new_columns_start_reg + idx
+        };
+        translate_expr_no_constant_opt(
+            program,
+            None,
+            &expr,
+            target_reg,
+            resolver,
+            NoConstantOptReason::RegisterReuse,
+        )?;
+        if column.is_rowid_alias {
+            program.emit_insn(Insn::MustBeInt { reg: new_rowid_reg });
+            rowid_updated = true;
+        }
+        updated_columns.push(name);
+        updated_column_indexes.push(idx);
+    }
+    let new_row = TriggerRowRegisters {
+        rowid_reg: new_rowid_reg,
+        columns_start_reg: new_columns_start_reg,
+    };
+    emit_generated_columns(program, resolver, table, new_row, false)?;
+    let updated_column_indexes = columns_changed_by_update(table, &updated_column_indexes);
+
+    let before_triggers = triggers_to_fire(
+        resolver.schema,
+        target.database_id,
+        &table.name,
+        &ast::TriggerEvent::Update,
+        ast::TriggerTime::Before,
+        &updated_columns,
+    );
+    let after_triggers = triggers_to_fire(
+        resolver.schema,
+        target.database_id,
+        &table.name,
+        &ast::TriggerEvent::Update,
+        ast::TriggerTime::After,
+        &updated_columns,
+    );
+    if !before_triggers.is_empty() {
+        emit_triggers(
+            program,
+            resolver.schema,
+            resolver.symbol_table,
+            &before_triggers,
+            table,
+            Some(new_row),
+            Some(old_row),
+            row_done_label,
+        )?;
+        // The trigger body may have moved the cursor or deleted the row itself
+        emit_seek_row_key(program, table, cursor_id, old_key_reg, row_done_label);
+    }
+
+    // The constraint checks of the update itself use ABORT
+    for (idx, column) in table.columns.iter().enumerate() {
+        if !column.notnull || column.is_rowid_alias {
+            continue;
+        }
+        program.emit_insn(Insn::HaltIfNull {
+            target_reg: new_columns_start_reg + idx,
+            err_code: SQLITE_CONSTRAINT_NOTNULL,
+            description: format!(
+                "{}.{}",
+                table.name,
+                column.name.as_ref().expect("Column name must be present")
+            ),
+        });
+    }
+    emit_check_constraints(
+        program,
+        resolver,
+        table,
+        new_row,
+        Some(&updated_column_indexes),
+        ast::ResolveType::Abort,
+        row_done_label,
+    )?;
+    let primary_key_updated = !table.has_rowid
+        && updated_column_indexes
+            .iter()
+            .any(|idx| table.columns[*idx].primary_key);
+    if rowid_updated {
+        let rowid_ok_label = program.allocate_label();
+        program.emit_insn(Insn::Eq {
+            lhs: new_rowid_reg,
+            rhs: old_rowid_reg,
+            target_pc: rowid_ok_label,
+            flags: CmpInsFlags::default(),
+            collation: program.curr_collation(),
+        });
+        program.emit_insn(Insn::NotExists {
+            cursor: cursor_id,
+            rowid_reg: new_rowid_reg,
+            target_pc: rowid_ok_label,
+        });
+        let rowid_column = table
+            .columns
+            .iter()
+            .find(|column| column.is_rowid_alias)
+            .and_then(|column| column.name.as_deref())
+            .unwrap_or("rowid");
+        program.emit_insn(Insn::Halt {
+            err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+            description: format!("{}.{}", table.name, rowid_column),
+            on_error: ast::ResolveType::Abort,
+        });
+        program.preassign_label_to_next_insn(rowid_ok_label);
+    }
+    if primary_key_updated {
+        let clustered_index = table
+            .clustered_index()
+            .expect("a WITHOUT ROWID table has a PRIMARY KEY");
+        let key_ok_label = program.allocate_label();
+        let new_key_reg = emit_row_key(program, table, new_row);
+        emit_row_keys_eq(program, table, new_key_reg, old_key_reg, key_ok_label);
+        program.emit_insn(Insn::NoConflict {
+            cursor_id,
+            target_pc: key_ok_label,
+            record_reg: new_key_reg,
+            num_regs: clustered_index.columns.len(),
+        });
+        program.emit_insn(Insn::Halt {
+            err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+            description: unique_index_violation(&table.name, &clustered_index),
+            on_error: ast::ResolveType::Abort,
+        });
+        program.preassign_label_to_next_insn(key_ok_label);
+    }
+    let mut index_keys = Vec::with_capacity(target.indexes.len());
+    for (index, idx_cursor_id) in target.indexes {
+        let num_regs = index.columns.len() + index.has_rowid as usize;
+        let key_start_reg = program.alloc_registers(num_regs);
+        emit_index_key(
+            program,
+            resolver,
+            table,
+            index,
+            IndexKeySource::Registers(new_row),
+            key_start_reg,
+        )?;
+        if index.unique {
+            let constraint_ok_label = program.allocate_label();
+            emit_partial_index_check(
+                program,
+                resolver,
+                table,
+                index,
+                IndexKeySource::Registers(new_row),
+                constraint_ok_label,
+            )?;
+            program.emit_insn(Insn::NoConflict {
+                cursor_id: *idx_cursor_id,
+                target_pc: constraint_ok_label,
+                record_reg: key_start_reg,
+                num_regs: index.key_columns().len(),
+            });
+            let conflict_key_reg = emit_index_entry_row_key(program, table, index, *idx_cursor_id);
+            emit_row_keys_eq(
+                program,
+                table,
+                old_key_reg,
+                conflict_key_reg,
+                constraint_ok_label,
+            );
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                description: unique_index_violation(&table.name, index),
+                on_error: ast::ResolveType::Abort,
+            });
+            program.preassign_label_to_next_insn(constraint_ok_label);
+        }
+        index_keys.push((index, *idx_cursor_id, key_start_reg, num_regs));
+    }
+    if rowid_updated || primary_key_updated {
+        // Checking the new key moved the cursor
+        emit_seek_row_key(program, table, cursor_id, old_key_reg, row_done_label);
+    }
+
+    // Replace the index entries of the existing row
+    for (index, idx_cursor_id, key_start_reg, num_regs) in index_keys {
+        let old_key_label = program.allocate_label();
+        emit_partial_index_check(
+            program,
+            resolver,
+            table,
+            index,
+            IndexKeySource::Registers(old_row),
+            old_key_label,
+        )?;
+        let old_key_start_reg = program.alloc_registers(num_regs);
+        emit_index_key(
+            program,
+            resolver,
+            table,
+            index,
+            IndexKeySource::Registers(old_row),
+            old_key_start_reg,
+        )?;
+        program.emit_insn(Insn::IdxDelete {
+            start_reg: old_key_start_reg,
+            num_regs,
+            cursor_id: idx_cursor_id,
+            raise_error_if_no_matching_entry: true,
+        });
+        program.preassign_label_to_next_insn(old_key_label);
+        let new_key_label = program.allocate_label();
+        emit_partial_index_check(
+            program,
+            resolver,
+            table,
+            index,
+            IndexKeySource::Registers(new_row),
+            new_key_label,
+        )?;
+        let record_reg = program.alloc_register();
+        program.emit_insn(Insn::MakeRecord {
+            start_reg: key_start_reg,
+            count: num_regs,
+            dest_reg: record_reg,
+            index_name: Some(index.name.clone()),
+        });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id: idx_cursor_id,
+            record_reg,
+            unpacked_start: Some(key_start_reg),
+            unpacked_count: Some(num_regs as u16),
+            flags: IdxInsertFlags::new(),
+        });
+        program.preassign_label_to_next_insn(new_key_label);
+    }
+
+    // The rowid alias column is always stored as NULL
+    if let Some(idx) = table
+        .columns
+        .iter()
+        .position(|column| column.is_rowid_alias)
+    {
+        program.emit_null(new_columns_start_reg + idx, None);
+    }
+    let record_reg = program.alloc_register();
+    emit_table_record(program, table, new_columns_start_reg, record_reg);
+    let cdc_table = Table::BTree(table.clone());
+    let cdc_before_reg =
+        match target.cdc_cursor_id {
+            Some(_) if program.capture_data_changes_mode().has_before() => Some(
+                emit_cdc_full_record(program, &cdc_table, cursor_id, old_rowid_reg),
+            ),
+            _ => None,
+        };
+    if rowid_updated {
+        let insert_label = program.allocate_label();
+        program.emit_insn(Insn::Eq {
+            lhs: new_rowid_reg,
+            rhs: old_rowid_reg,
+            target_pc: insert_label,
+            flags: CmpInsFlags::default(),
+            collation: program.curr_collation(),
+        });
+        program.emit_insn(Insn::Delete { cursor_id });
+        program.emit_insn(Insn::NotExists {
+            cursor: cursor_id,
+            rowid_reg: new_rowid_reg,
+            target_pc: insert_label,
+        });
+        program.preassign_label_to_next_insn(insert_label);
+    }
+    if table.has_rowid {
+        program.emit_insn(Insn::Insert {
+            cursor: cursor_id,
+            key_reg: new_rowid_reg,
+            record_reg,
+            flag: InsertFlags::new().update(true),
+            table_name: table.name.clone(),
+        });
+    } else {
+        // Like UPDATE, the row is deleted first as its key may be in an interior cell
+        program.emit_insn(Insn::Delete { cursor_id });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id,
+            record_reg,
+            unpacked_start: None,
+            unpacked_count: None,
+            flags: IdxInsertFlags::new(),
+        });
+    }
+    if let Some(cdc_cursor_id) = target.cdc_cursor_id {
+        let cdc_after_reg = if program.capture_data_changes_mode().has_after() {
+            Some(emit_cdc_patch_record(
+                program,
+                &cdc_table,
+                new_columns_start_reg,
+                record_reg,
+                new_rowid_reg,
+            ))
+        } else {
+            None
+        };
+        emit_cdc_insns(
+            program,
+            resolver,
+            OperationMode::UPDATE,
+            cdc_cursor_id,
+            old_rowid_reg,
+            cdc_before_reg,
+            cdc_after_reg,
+            &table.name,
+        )?;
+    }
+
+    emit_foreign_key_checks(
+        program,
+        resolver,
+        target.database_id,
+        table,
+        Some(new_row),
+        Some(old_row),
+        Some(&updated_column_indexes),
+        row_done_label,
+    )?;
+
+    emit_triggers(
+        program,
+        resolver.schema,
+        resolver.symbol_table,
+        &after_triggers,
+        table,
+        Some(new_row),
+        Some(old_row),
+        row_done_label,
+    )?;
+    emit_returning_row(program, resolver, target.returning, new_row)?;
+    program.emit_insn(Insn::Goto {
+        target_pc: row_done_label,
+    });
+    Ok(())
+}
+
+/// Resolves the column references of a `DO UPDATE` clause: `excluded.x` refers to the row that
+/// failed to be inserted, and unqualified or table-qualified columns to the existing row.
+struct UpsertRowRefs<'a> {
+    table: &'a BTreeTable,
+    existing: TriggerRowRegisters,
+    excluded: TriggerRowRegisters,
+}
+
+impl UpsertRowRefs<'_> {
+    fn resolve(&self, row: TriggerRowRegisters, column: &str) -> Option<usize> {
+        let column = normalize_ident(column);
+        match self.table.get_column(&column) {
+            Some((_, col)) if col.is_rowid_alias => Some(row.rowid_reg),
+            Some((idx, _)) => Some(row.columns_start_reg + idx),
+            None if self.table.has_rowid && ROWID_NAMES.contains(&column.as_str()) => {
+                Some(row.rowid_reg)
+            }
+            None => None,
+        }
+    }
+
+    fn rewrite_expr(&self, expr: &mut Expr) -> Result<()> {
+        walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+            let reg = match expr {
+                Expr::Id(column) => self.resolve(self.existing, &column.0),
+                Expr::Qualified(qualifier, column) => {
+                    let row = match normalize_ident(&qualifier.0).as_str() {
+                        "excluded" => self.excluded,
+                        name if name == self.table.name => self.existing,
+                        _ => bail_parse_error!("no such column: {}.{}", qualifier.0, column.0),
+                    };
+                    match self.resolve(row, &column.0) {
+                        Some(reg) => Some(reg),
+                        None => bail_parse_error!("no such column: {}.{}", qualifier.0, column.0),
+                    }
+                }
+                _ => None,
+            };
+            if let Some(reg) = reg {
+                *expr = Expr::Register(reg);
+            }
+            Ok(())
+        })
+    }
+}
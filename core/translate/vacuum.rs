@@ -0,0 +1,46 @@
+//! VDBE bytecode generation for VACUUM and VACUUM INTO.
+//!
+//! More info: https://www.sqlite.org/lang_vacuum.html
+
+use turso_sqlite3_parser::ast;
+
+use crate::schema::Schema;
+use crate::translate::emitter::{Resolver, TransactionMode};
+use crate::translate::expr::translate_expr;
+use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
+use crate::vdbe::insn::Insn;
+use crate::{bail_parse_error, Result, SymbolTable};
+
+pub fn translate_vacuum(
+    schema_name: Option<&ast::Name>,
+    into: Option<&ast::Expr>,
+    schema: &Schema,
+    syms: &SymbolTable,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let db = match schema_name {
+        Some(name) => schema.database_index(&name.0)?,
+        None => 0,
+    };
+    if db != 0 {
+        bail_parse_error!("VACUUM of attached databases is not supported yet");
+    }
+    program.extend(&ProgramBuilderOpts {
+        num_cursors: 0,
+        approx_num_insns: 3,
+        approx_num_labels: 0,
+    });
+    let into = match into {
+        Some(expr) => {
+            let resolver = Resolver::new(schema, syms);
+            let reg = program.alloc_register();
+            translate_expr(&mut program, None, expr, reg, &resolver)?;
+            Some(reg)
+        }
+        None => None,
+    };
+    program.emit_insn(Insn::Vacuum { db, into });
+    // The connection runs the transactions of the rebuild itself.
+    program.epilogue(TransactionMode::None);
+    Ok(program)
+}
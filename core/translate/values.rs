@@ -1,28 +1,42 @@
 use crate::translate::emitter::Resolver;
 use crate::translate::expr::{translate_expr_no_constant_opt, NoConstantOptReason};
 use crate::translate::plan::{QueryDestination, SelectPlan};
+use crate::translate::result_row::emit_result_row_and_limit;
 use crate::vdbe::builder::ProgramBuilder;
 use crate::vdbe::insn::Insn;
 use crate::vdbe::BranchOffset;
 use crate::Result;
 
+/// Emits the rows of a VALUES clause. The rows yielded to a coroutine are written to the registers
+/// starting at `reg_result_cols_start` if it is given, e.g. when they are shared by the SELECTs of a
+/// compound SELECT.
 pub fn emit_values(
     program: &mut ProgramBuilder,
     plan: &SelectPlan,
     resolver: &Resolver,
+    reg_result_cols_start: Option<usize>,
 ) -> Result<usize> {
+    program.emit_explain(
+        false,
+        match plan.values.len() {
+            1 => "SCAN CONSTANT ROW".to_string(),
+            n => format!("SCAN {n}-ROW VALUES CLAUSE"),
+        },
+    );
     if plan.values.len() == 1 {
-        let start_reg = emit_values_when_single_row(program, plan, resolver)?;
+        let start_reg =
+            emit_values_when_single_row(program, plan, resolver, reg_result_cols_start)?;
         return Ok(start_reg);
     }
 
     let reg_result_cols_start = match plan.query_destination {
         QueryDestination::ResultRows => emit_toplevel_values(program, plan, resolver)?,
         QueryDestination::CoroutineYield { yield_reg, .. } => {
-            emit_values_in_subquery(program, plan, resolver, yield_reg)?
+            emit_values_in_subquery(program, plan, resolver, yield_reg, reg_result_cols_start)?
+        }
+        QueryDestination::EphemeralIndex { .. } | QueryDestination::EphemeralTable { .. } => {
+            emit_values_to_ephemeral(program, plan, resolver)?
         }
-        QueryDestination::EphemeralIndex { .. } => unreachable!(),
-        QueryDestination::EphemeralTable { .. } => unreachable!(),
     };
     Ok(reg_result_cols_start)
 }
@@ -31,10 +45,15 @@ fn emit_values_when_single_row(
     program: &mut ProgramBuilder,
     plan: &SelectPlan,
     resolver: &Resolver,
+    reg_result_cols_start: Option<usize>,
 ) -> Result<usize> {
     let first_row = &plan.values[0];
     let row_len = first_row.len();
-    let start_reg = program.alloc_registers(row_len);
+    let start_reg = match plan.query_destination {
+        QueryDestination::CoroutineYield { .. } => reg_result_cols_start,
+        _ => None,
+    }
+    .unwrap_or_else(|| program.alloc_registers(row_len));
     for (i, v) in first_row.iter().enumerate() {
         translate_expr_no_constant_opt(
             program,
@@ -45,22 +64,7 @@ fn emit_values_when_single_row(
             NoConstantOptReason::RegisterReuse,
         )?;
     }
-    match plan.query_destination {
-        QueryDestination::ResultRows => {
-            program.emit_insn(Insn::ResultRow {
-                start_reg,
-                count: row_len,
-            });
-        }
-        QueryDestination::CoroutineYield { yield_reg, .. } => {
-            program.emit_insn(Insn::Yield {
-                yield_reg,
-                end_offset: BranchOffset::Offset(0),
-            });
-        }
-        QueryDestination::EphemeralIndex { .. } => unreachable!(),
-        QueryDestination::EphemeralTable { .. } => unreachable!(),
-    }
+    emit_result_row_and_limit(program, plan, start_reg, None, None)?;
     Ok(start_reg)
 }
 
@@ -79,7 +83,7 @@ fn emit_toplevel_values(
     });
     program.preassign_label_to_next_insn(start_offset_label);
 
-    let start_reg = emit_values_in_subquery(program, plan, resolver, yield_reg)?;
+    let start_reg = emit_values_in_subquery(program, plan, resolver, yield_reg, None)?;
 
     program.emit_insn(Insn::EndCoroutine { yield_reg });
     program.preassign_label_to_next_insn(definition_label);
@@ -123,9 +127,10 @@ fn emit_values_in_subquery(
     plan: &SelectPlan,
     resolver: &Resolver,
     yield_reg: usize,
+    reg_result_cols_start: Option<usize>,
 ) -> Result<usize> {
     let row_len = plan.values[0].len();
-    let start_reg = program.alloc_registers(row_len);
+    let start_reg = reg_result_cols_start.unwrap_or_else(|| program.alloc_registers(row_len));
     for value in &plan.values {
         for (i, v) in value.iter().enumerate() {
             translate_expr_no_constant_opt(
@@ -145,3 +150,29 @@ fn emit_values_in_subquery(
 
     Ok(start_reg)
 }
+
+/// Emits the rows of a VALUES clause into an ephemeral index or table, e.g. for deduplicating
+/// the rows of a UNION.
+fn emit_values_to_ephemeral(
+    program: &mut ProgramBuilder,
+    plan: &SelectPlan,
+    resolver: &Resolver,
+) -> Result<usize> {
+    let row_len = plan.values[0].len();
+    let start_reg = program.alloc_registers(row_len);
+    for value in &plan.values {
+        for (i, v) in value.iter().enumerate() {
+            translate_expr_no_constant_opt(
+                program,
+                None,
+                v,
+                start_reg + i,
+                resolver,
+                NoConstantOptReason::RegisterReuse,
+            )?;
+        }
+        emit_result_row_and_limit(program, plan, start_reg, None, None)?;
+    }
+
+    Ok(start_reg)
+}
@@ -0,0 +1,215 @@
+//! VDBE bytecode generation for views.
+//!
+//! Views are stored in sqlite_schema and in the [Schema]. When a view is referenced in a
+//! FROM clause, its SELECT is planned as a FROM clause subquery.
+//! More info: https://www.sqlite.org/lang_createview.html
+
+use turso_sqlite3_parser::ast::{self, fmt::ToTokens, IndexedColumn, QualifiedName};
+
+use crate::schema::{Schema, Table, View};
+use crate::translate::emitter::TransactionMode;
+use crate::translate::plan::{JoinedTable, QueryDestination};
+use crate::translate::schema::{
+    emit_delete_schema_entry, emit_schema_entry, SchemaEntryType, SQLITE_TABLEID,
+};
+use crate::translate::select::prepare_select_plan;
+use crate::util::normalize_ident;
+use crate::vdbe::builder::{CursorType, ProgramBuilder, ProgramBuilderOpts, TableRefIdCounter};
+use crate::vdbe::insn::{Cookie, Insn};
+use crate::vdbe::BranchOffset;
+use crate::{bail_parse_error, LimboError, Result, SymbolTable};
+
+#[allow(clippy::too_many_arguments)]
+pub fn translate_create_view(
+    schema: &Schema,
+    view_name: QualifiedName,
+    columns: Option<Vec<IndexedColumn>>,
+    select: ast::Select,
+    temporary: bool,
+    if_not_exists: bool,
+    syms: &SymbolTable,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    if temporary {
+        bail_parse_error!("TEMPORARY views are not supported yet");
+    }
+    let opts = ProgramBuilderOpts {
+        num_cursors: 1,
+        approx_num_insns: 20,
+        approx_num_labels: 1,
+    };
+    program.extend(&opts);
+
+    let normalized_view_name = normalize_ident(&view_name.name.0);
+    if schema.get_view(&normalized_view_name).is_some() {
+        if if_not_exists {
+            program.epilogue(TransactionMode::None);
+            return Ok(program);
+        }
+        bail_parse_error!("view {} already exists", normalized_view_name);
+    }
+    if schema.get_table(&normalized_view_name).is_some() {
+        bail_parse_error!("table {} already exists", normalized_view_name);
+    }
+    if normalized_view_name.starts_with("sqlite_") {
+        bail_parse_error!(
+            "object name reserved for internal use: {}",
+            normalized_view_name
+        );
+    }
+
+    let sql = ast::Stmt::CreateView {
+        temporary,
+        if_not_exists: false,
+        view_name,
+        columns,
+        select: Box::new(select),
+    }
+    .format()
+    .map_err(|e| LimboError::InternalError(e.to_string()))?;
+
+    // Make sure the view can be planned before storing it
+    let view = View::from_sql(&sql)?;
+    view_as_subquery(
+        schema,
+        &view,
+        normalized_view_name.clone(),
+        syms,
+        &mut program.table_reference_counter,
+    )?;
+
+    let sqlite_schema = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+    let sqlite_schema_cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(sqlite_schema.clone()));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: sqlite_schema_cursor_id,
+        root_page: 1usize.into(),
+        name: SQLITE_TABLEID.to_string(),
+        db: 0,
+    });
+
+    emit_schema_entry(
+        &mut program,
+        sqlite_schema_cursor_id,
+        SchemaEntryType::View,
+        &normalized_view_name,
+        &normalized_view_name,
+        0, // views have no b-tree, rootpage=0
+        Some(sql),
+    );
+
+    program.emit_insn(Insn::SetCookie {
+        db: 0,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+    let parse_schema_where_clause = format!("name = '{normalized_view_name}' AND type = 'view'");
+    program.emit_insn(Insn::ParseSchema {
+        db: 0,
+        where_clause: Some(parse_schema_where_clause),
+    });
+
+    program.epilogue(TransactionMode::Write);
+
+    Ok(program)
+}
+
+pub fn translate_drop_view(
+    schema: &Schema,
+    view_name: &QualifiedName,
+    if_exists: bool,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let opts = ProgramBuilderOpts {
+        num_cursors: 1,
+        approx_num_insns: 20,
+        approx_num_labels: 3,
+    };
+    program.extend(&opts);
+
+    let view_name = normalize_ident(&view_name.name.0);
+    if schema.get_view(&view_name).is_none() {
+        if schema.get_table(&view_name).is_some() {
+            bail_parse_error!("use DROP TABLE to delete table {}", view_name);
+        }
+        if if_exists {
+            program.epilogue(TransactionMode::None);
+            return Ok(program);
+        }
+        bail_parse_error!("no such view: {}", view_name);
+    }
+
+    emit_delete_schema_entry(&mut program, schema, 0, SchemaEntryType::View, &view_name);
+    // The INSTEAD OF triggers of the view are dropped with it
+    let triggers = schema.get_triggers(&view_name).to_vec();
+    for trigger in &triggers {
+        emit_delete_schema_entry(
+            &mut program,
+            schema,
+            0,
+            SchemaEntryType::Trigger,
+            &trigger.name,
+        );
+    }
+
+    program.emit_insn(Insn::SetCookie {
+        db: 0,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+
+    // Remove the view and its triggers from the Schema
+    for trigger in triggers {
+        program.emit_insn(Insn::DropTrigger {
+            db: 0,
+            trigger_name: trigger.name.clone(),
+        });
+    }
+    program.emit_insn(Insn::DropView { db: 0, view_name });
+
+    program.epilogue(TransactionMode::Write);
+
+    Ok(program)
+}
+
+/// Plans the SELECT of `view` as a FROM clause subquery named `identifier`.
+/// The columns of the subquery are renamed if the view was declared with a column list.
+pub fn view_as_subquery(
+    schema: &Schema,
+    view: &View,
+    identifier: String,
+    syms: &SymbolTable,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<JoinedTable> {
+    let plan = prepare_select_plan(
+        schema,
+        view.select.clone(),
+        syms,
+        &[],
+        table_ref_counter,
+        QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // will be set later in bytecode emission
+            coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+        },
+    )?;
+    let mut joined_table =
+        JoinedTable::new_subquery_from_plan(identifier, plan, None, table_ref_counter.next());
+    if let (Some(names), Table::FromClauseSubquery(subquery)) =
+        (&view.columns, &mut joined_table.table)
+    {
+        if names.len() != subquery.columns.len() {
+            bail_parse_error!(
+                "expected {} columns for '{}' but got {}",
+                names.len(),
+                view.name,
+                subquery.columns.len()
+            );
+        }
+        for (column, name) in subquery.columns.iter_mut().zip(names) {
+            column.name = Some(name.clone());
+        }
+    }
+    Ok(joined_table)
+}
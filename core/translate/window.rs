@@ -0,0 +1,1627 @@
+use std::sync::Arc;
+
+use turso_sqlite3_parser::ast::{self, SortOrder};
+
+use crate::{
+    error::SQLITE_ERROR,
+    function::{AggFunc, WindowFunc},
+    schema::{Index, IndexColumn, PseudoCursorType},
+    vdbe::{
+        builder::{CursorType, ProgramBuilder},
+        insn::{CmpInsFlags, IdxInsertFlags, Insn},
+        BranchOffset, CursorID,
+    },
+    Result,
+};
+
+use super::{
+    emitter::{Resolver, TranslateCtx},
+    expr::translate_expr,
+    group_by::{translate_aggregation_step_groupby, GroupByAggArgumentSource},
+    order_by::{order_by_sorter_insert, sort_key_collation, sorter_insert},
+    plan::{
+        Distinctness, SelectPlan, TableReferences, WindowFrame, WindowFunction, WindowFunctionKind,
+        WindowPlan,
+    },
+    result_row::emit_select_result,
+};
+
+/// Column of the partition buffer holding the position of the row in its partition.
+const POSITION_COLUMN: usize = 0;
+/// Column of the partition buffer holding the peer group number of the row, starting from 1.
+const PEER_GROUP_COLUMN: usize = 1;
+/// Column of the partition buffer where the sorter record of the row starts.
+const SORTER_RECORD_COLUMN: usize = 2;
+
+// Metadata for computing window functions
+#[derive(Debug)]
+pub struct WindowMetadata {
+    /// Sorter of each window, ordering the rows by the PARTITION BY and ORDER BY of the window.
+    pub sorter_cursors: Vec<CursorID>,
+    /// Ephemeral index of each window that buffers the rows of the current partition, keyed by their position.
+    pub partition_cursors: Vec<(CursorID, Arc<Index>)>,
+    /// First register of the record the main loop inserts into the sorter of the first window.
+    pub reg_sorter_record_start: usize,
+    /// Register for the records inserted into the window sorters.
+    pub reg_sorter_record: usize,
+}
+
+/// The values the main loop evaluates for computing the window functions.
+///
+/// The sorter record of a window consists of the PARTITION BY and ORDER BY values of the window,
+/// followed by these values, followed by the results of the window functions of the previous windows.
+struct WindowValues<'a> {
+    exprs: Vec<&'a ast::Expr>,
+    /// Offset of the PARTITION BY and ORDER BY values of each window.
+    key_offsets: Vec<usize>,
+    /// Offset of the arguments of each window function, in the order of [WindowPlan::functions].
+    /// The FILTER expression of an aggregate follows its arguments.
+    arg_offsets: Vec<usize>,
+}
+
+impl<'a> WindowValues<'a> {
+    fn new(window_plan: &'a WindowPlan) -> Self {
+        let mut exprs: Vec<&ast::Expr> = window_plan.columns.iter().collect();
+        let mut key_offsets = Vec::with_capacity(window_plan.windows.len());
+        for window in window_plan.windows.iter() {
+            key_offsets.push(exprs.len());
+            exprs.extend(window.partition_by.iter());
+            exprs.extend(window.order_by.iter().map(|(expr, _)| expr));
+        }
+        let mut arg_offsets = Vec::new();
+        for func in window_plan.functions() {
+            arg_offsets.push(exprs.len());
+            exprs.extend(func.args.iter());
+            exprs.extend(func.filter.iter());
+        }
+        Self {
+            exprs,
+            key_offsets,
+            arg_offsets,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.exprs.len()
+    }
+}
+
+/// Number of PARTITION BY and ORDER BY values of the window at `window_idx`.
+fn window_key_len(window_plan: &WindowPlan, window_idx: usize) -> usize {
+    let window = &window_plan.windows[window_idx];
+    window.partition_by.len() + window.order_by.len()
+}
+
+/// Number of window functions computed before the window at `window_idx`.
+fn window_first_function(window_plan: &WindowPlan, window_idx: usize) -> usize {
+    window_plan.windows[..window_idx]
+        .iter()
+        .map(|window| window.functions.len())
+        .sum()
+}
+
+/// Number of columns of the sorter record of the window at `window_idx`.
+fn window_sorter_column_count(
+    window_plan: &WindowPlan,
+    values: &WindowValues,
+    window_idx: usize,
+) -> usize {
+    window_key_len(window_plan, window_idx)
+        + values.len()
+        + window_first_function(window_plan, window_idx)
+}
+
+/// Initialize resources needed for computing window functions
+pub fn init_window(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx,
+    window_plan: &WindowPlan,
+    referenced_tables: &TableReferences,
+) -> Result<()> {
+    let values = WindowValues::new(window_plan);
+    let mut sorter_cursors = Vec::with_capacity(window_plan.windows.len());
+    let mut partition_cursors = Vec::with_capacity(window_plan.windows.len());
+    for (window_idx, window) in window_plan.windows.iter().enumerate() {
+        // Partitions are sorted in ascending order, the rows within a partition by the ORDER BY of the window.
+        let sort_keys = window
+            .partition_by
+            .iter()
+            .map(|expr| (expr, SortOrder::Asc))
+            .chain(window.order_by.iter().map(|(expr, order)| (expr, *order)))
+            .collect::<Vec<_>>();
+        let collations = sort_keys
+            .iter()
+            .map(|(expr, _)| sort_key_collation(expr, referenced_tables))
+            .collect::<Result<Vec<_>>>()?;
+        let sorter_cursor = program.alloc_cursor_id(CursorType::Sorter);
+        program.emit_insn(Insn::SorterOpen {
+            cursor_id: sorter_cursor,
+            columns: sort_keys.len(),
+            order: sort_keys.iter().map(|(_, order)| *order).collect(),
+            collations,
+        });
+        sorter_cursors.push(sorter_cursor);
+
+        let column_count =
+            SORTER_RECORD_COLUMN + window_sorter_column_count(window_plan, &values, window_idx);
+        let partition_index = Arc::new(Index {
+            name: format!("window_partition_{}", program.offset().as_offset_int()),
+            table_name: String::new(),
+            ephemeral: true,
+            root_page: 0,
+            columns: (0..column_count)
+                .map(|i| IndexColumn {
+                    name: match i {
+                        POSITION_COLUMN => "position".to_string(),
+                        PEER_GROUP_COLUMN => "peer_group".to_string(),
+                        _ => format!("c{}", i - SORTER_RECORD_COLUMN),
+                    },
+                    order: SortOrder::Asc,
+                    pos_in_table: i,
+                    collation: None,
+                    default: None,
+                    expr: None,
+                })
+                .collect(),
+            unique: false,
+            has_rowid: false,
+            on_conflict: None,
+            where_clause: None,
+            pk_suffix_len: 0,
+        });
+        let partition_cursor =
+            program.alloc_cursor_id(CursorType::BTreeIndex(partition_index.clone()));
+        program.emit_insn(Insn::OpenEphemeral {
+            cursor_id: partition_cursor,
+            is_table: false,
+        });
+        partition_cursors.push((partition_cursor, partition_index));
+    }
+
+    t_ctx.meta_window = Some(WindowMetadata {
+        sorter_cursors,
+        partition_cursors,
+        reg_sorter_record_start: program.alloc_registers(window_sorter_column_count(
+            window_plan,
+            &values,
+            0,
+        )),
+        reg_sorter_record: program.alloc_register(),
+    });
+    Ok(())
+}
+
+/// Emits the bytecode for inserting the current row of the main loop into the sorter of the first window.
+pub fn window_sorter_insert(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+) -> Result<()> {
+    let window_plan = plan.window.as_ref().expect("window plan must exist");
+    let values = WindowValues::new(window_plan);
+    let WindowMetadata {
+        sorter_cursors,
+        reg_sorter_record_start,
+        reg_sorter_record,
+        ..
+    } = t_ctx
+        .meta_window
+        .as_ref()
+        .expect("window metadata must exist");
+
+    let key_len = window_key_len(window_plan, 0);
+    let reg_values_start = reg_sorter_record_start + key_len;
+    for (i, expr) in values.exprs.iter().enumerate() {
+        translate_expr(
+            program,
+            Some(&plan.table_references),
+            expr,
+            reg_values_start + i,
+            &t_ctx.resolver,
+        )?;
+    }
+    // The sort keys of the first window are among the values, copy them to the front of the record.
+    if key_len > 0 {
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_values_start + values.key_offsets[0],
+            dst_reg: *reg_sorter_record_start,
+            extra_amount: key_len - 1,
+        });
+    }
+    sorter_insert(
+        program,
+        *reg_sorter_record_start,
+        key_len + values.len(),
+        sorter_cursors[0],
+        *reg_sorter_record,
+    );
+    Ok(())
+}
+
+/// Cursor and registers used while computing the window functions of a partition.
+struct Partition {
+    /// Ephemeral index holding the rows of the partition.
+    cursor_id: CursorID,
+    /// Number of rows in the partition.
+    reg_row_count: usize,
+    /// Position of the current row.
+    reg_position: usize,
+    /// Position of the first peer of the current row.
+    reg_peer_start: usize,
+    /// First register of the current row, as stored in the partition buffer.
+    reg_row: usize,
+    reg_zero: usize,
+    reg_one: usize,
+    /// Register holding 1.0, used for converting integers to reals.
+    reg_real_one: usize,
+}
+
+impl Partition {
+    fn reg_peer_group(&self) -> usize {
+        self.reg_row + PEER_GROUP_COLUMN
+    }
+
+    /// Positions the partition cursor on the row at the position in `reg_position`.
+    fn emit_seek(
+        &self,
+        program: &mut ProgramBuilder,
+        reg_position: usize,
+        target_pc: BranchOffset,
+    ) {
+        program.emit_insn(Insn::SeekGE {
+            is_index: true,
+            cursor_id: self.cursor_id,
+            start_reg: reg_position,
+            num_regs: 1,
+            target_pc,
+            eq_only: false,
+        });
+    }
+
+    fn emit_increment(&self, program: &mut ProgramBuilder, reg: usize) {
+        program.emit_insn(Insn::Add {
+            lhs: reg,
+            rhs: self.reg_one,
+            dest: reg,
+        });
+    }
+
+    /// Moves the position in `reg_pointer` forward over the rows of the partition until
+    /// the end of the partition, or until `emit_stop_check` jumps to the label it is given
+    /// for the value of `column` of the row at the position.
+    fn emit_advance(
+        &self,
+        program: &mut ProgramBuilder,
+        reg_pointer: usize,
+        column: usize,
+        emit_stop_check: impl FnOnce(&mut ProgramBuilder, usize, BranchOffset),
+    ) {
+        let label_loop = program.allocate_label();
+        let label_stop = program.allocate_label();
+        let reg_value = program.alloc_register();
+        program.preassign_label_to_next_insn(label_loop);
+        program.emit_insn(Insn::Ge {
+            lhs: reg_pointer,
+            rhs: self.reg_row_count,
+            target_pc: label_stop,
+            flags: CmpInsFlags::default(),
+            collation: None,
+        });
+        self.emit_seek(program, reg_pointer, label_stop);
+        program.emit_column(self.cursor_id, column, reg_value);
+        emit_stop_check(program, reg_value, label_stop);
+        self.emit_increment(program, reg_pointer);
+        program.emit_insn(Insn::Goto {
+            target_pc: label_loop,
+        });
+        program.preassign_label_to_next_insn(label_stop);
+    }
+
+    /// Moves the position in `reg_pointer` forward to the first row of the peer group in `reg_group`
+    /// (`is_start`), or past the last row of that group.
+    fn emit_advance_to_peer_group(
+        &self,
+        program: &mut ProgramBuilder,
+        reg_pointer: usize,
+        reg_group: usize,
+        is_start: bool,
+    ) {
+        self.emit_advance(
+            program,
+            reg_pointer,
+            PEER_GROUP_COLUMN,
+            |program, reg_value, label_stop| {
+                let (lhs, rhs) = (reg_value, reg_group);
+                let flags = CmpInsFlags::default();
+                program.emit_insn(if is_start {
+                    Insn::Ge {
+                        lhs,
+                        rhs,
+                        target_pc: label_stop,
+                        flags,
+                        collation: None,
+                    }
+                } else {
+                    Insn::Gt {
+                        lhs,
+                        rhs,
+                        target_pc: label_stop,
+                        flags,
+                        collation: None,
+                    }
+                });
+            },
+        );
+    }
+
+    /// Emits a loop over the rows of a frame, from the position in `reg_cursor` up to the position in `reg_end`,
+    /// skipping the rows excluded by the EXCLUDE clause of the frame.
+    /// `emit_body` is called with the labels of the next row and of the end of the loop.
+    fn emit_frame_loop(
+        &self,
+        program: &mut ProgramBuilder,
+        exclude: &ast::FrameExclude,
+        reg_cursor: usize,
+        reg_end: usize,
+        emit_body: impl FnOnce(&mut ProgramBuilder, BranchOffset, BranchOffset) -> Result<()>,
+    ) -> Result<()> {
+        let label_loop = program.allocate_label();
+        let label_next = program.allocate_label();
+        let label_done = program.allocate_label();
+        program.preassign_label_to_next_insn(label_loop);
+        program.emit_insn(Insn::Ge {
+            lhs: reg_cursor,
+            rhs: reg_end,
+            target_pc: label_done,
+            flags: CmpInsFlags::default(),
+            collation: None,
+        });
+        self.emit_seek(program, reg_cursor, label_done);
+        match exclude {
+            ast::FrameExclude::NoOthers => {}
+            ast::FrameExclude::CurrentRow => {
+                program.emit_insn(Insn::Eq {
+                    lhs: reg_cursor,
+                    rhs: self.reg_position,
+                    target_pc: label_next,
+                    flags: CmpInsFlags::default(),
+                    collation: None,
+                });
+            }
+            ast::FrameExclude::Group | ast::FrameExclude::Ties => {
+                let reg_group = program.alloc_register();
+                program.emit_column(self.cursor_id, PEER_GROUP_COLUMN, reg_group);
+                if matches!(exclude, ast::FrameExclude::Group) {
+                    program.emit_insn(Insn::Eq {
+                        lhs: reg_group,
+                        rhs: self.reg_peer_group(),
+                        target_pc: label_next,
+                        flags: CmpInsFlags::default(),
+                        collation: None,
+                    });
+                } else {
+                    // The current row itself stays in the frame, only its peers are excluded.
+                    let label_keep = program.allocate_label();
+                    program.emit_insn(Insn::Ne {
+                        lhs: reg_group,
+                        rhs: self.reg_peer_group(),
+                        target_pc: label_keep,
+                        flags: CmpInsFlags::default(),
+                        collation: None,
+                    });
+                    program.emit_insn(Insn::Ne {
+                        lhs: reg_cursor,
+                        rhs: self.reg_position,
+                        target_pc: label_next,
+                        flags: CmpInsFlags::default(),
+                        collation: None,
+                    });
+                    program.preassign_label_to_next_insn(label_keep);
+                }
+            }
+        }
+        emit_body(program, label_next, label_done)?;
+        program.preassign_label_to_next_insn(label_next);
+        self.emit_increment(program, reg_cursor);
+        program.emit_insn(Insn::Goto {
+            target_pc: label_loop,
+        });
+        program.preassign_label_to_next_insn(label_done);
+        Ok(())
+    }
+}
+
+/// Registers of the frame of a window function.
+struct FrameRegisters {
+    /// Position of the first row of the frame.
+    reg_start: usize,
+    /// Position after the last row of the frame.
+    reg_end: usize,
+    /// Value of the `<expr> PRECEDING` or `<expr> FOLLOWING` starting bound.
+    reg_start_offset: Option<usize>,
+    /// Value of the `<expr> PRECEDING` or `<expr> FOLLOWING` ending bound.
+    reg_end_offset: Option<usize>,
+}
+
+/// Registers used for computing a window function over the rows of a partition.
+struct WindowFunctionRegisters {
+    frame: Option<FrameRegisters>,
+    /// For aggregates over frames that only grow: the accumulator, and the position of the next row to aggregate.
+    incremental: Option<(usize, usize)>,
+    /// For cume_dist(): position after the last peer of the current row.
+    reg_peer_end: Option<usize>,
+}
+
+/// Emits the bytecode for computing the window functions, after the main loop has inserted the rows
+/// into the sorter of the first window.
+///
+/// The windows are computed one after another. For each window, the rows are read from its sorter,
+/// and the rows of each partition are buffered in an ephemeral index. When a partition is complete,
+/// its rows are visited in order and the window functions are computed for each row. The rows are then
+/// inserted, along with the results, into the sorter of the next window, or for the last window,
+/// emitted as the result of the query (or inserted into the ORDER BY sorter).
+pub fn emit_window<'a>(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx<'a>,
+    plan: &'a SelectPlan,
+) -> Result<()> {
+    let window_plan = plan.window.as_ref().expect("window plan must exist");
+    let values = WindowValues::new(window_plan);
+    for window_idx in 0..window_plan.windows.len() {
+        emit_window_stage(program, t_ctx, plan, &values, window_idx)?;
+    }
+    Ok(())
+}
+
+/// Emits the bytecode for computing the window functions of a single window.
+fn emit_window_stage<'a>(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx<'a>,
+    plan: &'a SelectPlan,
+    values: &WindowValues,
+    window_idx: usize,
+) -> Result<()> {
+    let window_plan = plan.window.as_ref().expect("window plan must exist");
+    let window = &window_plan.windows[window_idx];
+    let is_last_window = window_idx + 1 == window_plan.windows.len();
+    let (sorter_cursor, partition_cursor, partition_index_name) = {
+        let meta = t_ctx
+            .meta_window
+            .as_ref()
+            .expect("window metadata must exist");
+        let (cursor_id, index) = &meta.partition_cursors[window_idx];
+        (
+            meta.sorter_cursors[window_idx],
+            *cursor_id,
+            index.name.clone(),
+        )
+    };
+
+    let num_partition_keys = window.partition_by.len();
+    let num_order_keys = window.order_by.len();
+    let key_len = num_partition_keys + num_order_keys;
+    let first_function = window_first_function(window_plan, window_idx);
+    let sorter_column_count = window_sorter_column_count(window_plan, values, window_idx);
+    let row_column_count = SORTER_RECORD_COLUMN + sorter_column_count;
+    // Column of the partition buffer where the values evaluated in the main loop start
+    let values_column = SORTER_RECORD_COLUMN + key_len;
+    // Column of the partition buffer where the results of the previous windows start
+    let results_column = values_column + values.len();
+
+    let label_stage_end = program.allocate_label();
+    let label_flush = program.allocate_label();
+    let reg_flush_return = program.alloc_register();
+
+    let reg_zero = program.alloc_register();
+    program.emit_int(0, reg_zero);
+    let reg_one = program.alloc_register();
+    program.emit_int(1, reg_one);
+    let reg_real_one = program.alloc_register();
+    program.emit_insn(Insn::Real {
+        value: 1.0,
+        dest: reg_real_one,
+    });
+    let reg_row_count = program.alloc_register();
+    program.emit_int(0, reg_row_count);
+    let reg_last_peer_group = program.alloc_register();
+    // The sort keys of the previous row, for detecting partition and peer group boundaries
+    let reg_prev_keys = program.alloc_registers(key_len.max(1));
+    // The row being buffered: its position and peer group, followed by the sorter record
+    let reg_buffer_row = program.alloc_registers(row_column_count);
+    let reg_buffer_record = program.alloc_register();
+    // The frame offsets are constant, they are evaluated and validated once.
+    let mut frame_offsets = Vec::with_capacity(window.functions.len());
+    for func in window.functions.iter() {
+        if !func.func.uses_frame() {
+            frame_offsets.push((None, None));
+            continue;
+        }
+        let frame = &func.frame;
+        frame_offsets.push((
+            emit_frame_offset(
+                program,
+                &frame.start,
+                &frame.mode,
+                true,
+                reg_zero,
+                &t_ctx.resolver,
+            )?,
+            emit_frame_offset(
+                program,
+                &frame.end,
+                &frame.mode,
+                false,
+                reg_zero,
+                &t_ctx.resolver,
+            )?,
+        ));
+    }
+
+    let reg_sorter_data = program.alloc_register();
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: sorter_column_count,
+    }));
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: sorter_column_count,
+    });
+    let label_sort_loop_start = program.allocate_label();
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sorter_cursor,
+        pc_if_empty: label_stage_end,
+    });
+    program.preassign_label_to_next_insn(label_sort_loop_start);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sorter_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+    let reg_keys = reg_buffer_row + SORTER_RECORD_COLUMN;
+    for i in 0..sorter_column_count {
+        program.emit_column(pseudo_cursor, i, reg_keys + i);
+    }
+
+    // A row with different PARTITION BY values completes the partition being buffered.
+    if num_partition_keys > 0 {
+        let label_new_partition = program.allocate_label();
+        let label_same_partition = program.allocate_label();
+        program.emit_insn(Insn::IfNot {
+            reg: reg_row_count,
+            target_pc: label_same_partition,
+            jump_if_null: false,
+        });
+        program.emit_insn(Insn::Compare {
+            start_reg_a: reg_prev_keys,
+            start_reg_b: reg_keys,
+            count: num_partition_keys,
+            collation: program.curr_collation(),
+        });
+        program.emit_insn(Insn::Jump {
+            target_pc_lt: label_new_partition,
+            target_pc_eq: label_same_partition,
+            target_pc_gt: label_new_partition,
+        });
+        program.preassign_label_to_next_insn(label_new_partition);
+        program.emit_insn(Insn::Gosub {
+            target_pc: label_flush,
+            return_reg: reg_flush_return,
+        });
+        program.preassign_label_to_next_insn(label_same_partition);
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_keys,
+            dst_reg: reg_prev_keys,
+            extra_amount: num_partition_keys - 1,
+        });
+    }
+
+    // A row with different ORDER BY values than the previous row starts a new peer group.
+    if num_order_keys > 0 {
+        let label_first_row = program.allocate_label();
+        let label_new_group = program.allocate_label();
+        let label_same_group = program.allocate_label();
+        program.emit_insn(Insn::IfNot {
+            reg: reg_row_count,
+            target_pc: label_first_row,
+            jump_if_null: false,
+        });
+        program.emit_insn(Insn::Compare {
+            start_reg_a: reg_prev_keys + num_partition_keys,
+            start_reg_b: reg_keys + num_partition_keys,
+            count: num_order_keys,
+            collation: program.curr_collation(),
+        });
+        program.emit_insn(Insn::Jump {
+            target_pc_lt: label_new_group,
+            target_pc_eq: label_same_group,
+            target_pc_gt: label_new_group,
+        });
+        program.preassign_label_to_next_insn(label_new_group);
+        program.emit_insn(Insn::Add {
+            lhs: reg_last_peer_group,
+            rhs: reg_one,
+            dest: reg_last_peer_group,
+        });
+        program.emit_insn(Insn::Goto {
+            target_pc: label_same_group,
+        });
+        program.preassign_label_to_next_insn(label_first_row);
+        program.emit_int(1, reg_last_peer_group);
+        program.preassign_label_to_next_insn(label_same_group);
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_keys + num_partition_keys,
+            dst_reg: reg_prev_keys + num_partition_keys,
+            extra_amount: num_order_keys - 1,
+        });
+    } else {
+        // Without ORDER BY, all rows of the partition are peers.
+        program.emit_int(1, reg_last_peer_group);
+    }
+
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_row_count,
+        dst_reg: reg_buffer_row + POSITION_COLUMN,
+        extra_amount: 0,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: reg_last_peer_group,
+        dst_reg: reg_buffer_row + PEER_GROUP_COLUMN,
+        extra_amount: 0,
+    });
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: reg_buffer_row,
+        count: row_column_count,
+        dest_reg: reg_buffer_record,
+        index_name: Some(partition_index_name),
+    });
+    program.emit_insn(Insn::IdxInsert {
+        cursor_id: partition_cursor,
+        record_reg: reg_buffer_record,
+        unpacked_start: None,
+        unpacked_count: None,
+        flags: IdxInsertFlags::new(),
+    });
+    program.emit_insn(Insn::Add {
+        lhs: reg_row_count,
+        rhs: reg_one,
+        dest: reg_row_count,
+    });
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sorter_cursor,
+        pc_if_next: label_sort_loop_start,
+    });
+    // The last partition is complete once the sorter is exhausted.
+    program.emit_insn(Insn::Gosub {
+        target_pc: label_flush,
+        return_reg: reg_flush_return,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_stage_end,
+    });
+
+    // Subroutine computing the window functions for the rows of the buffered partition
+    let label_flush_done = program.allocate_label();
+    program.preassign_label_to_next_insn(label_flush);
+    program.emit_insn(Insn::IfNot {
+        reg: reg_row_count,
+        target_pc: label_flush_done,
+        jump_if_null: false,
+    });
+    let partition = Partition {
+        cursor_id: partition_cursor,
+        reg_row_count,
+        reg_position: program.alloc_register(),
+        reg_peer_start: program.alloc_register(),
+        reg_row: program.alloc_registers(row_column_count),
+        reg_zero,
+        reg_one,
+        reg_real_one,
+    };
+    let reg_prev_peer_group = program.alloc_register();
+    program.emit_int(0, partition.reg_position);
+    program.emit_int(0, partition.reg_peer_start);
+    program.emit_int(0, reg_prev_peer_group);
+
+    // Reset the state of the window functions for the new partition
+    let mut function_regs = Vec::with_capacity(window.functions.len());
+    for (func, (reg_start_offset, reg_end_offset)) in window.functions.iter().zip(frame_offsets) {
+        let frame = func.func.uses_frame().then(|| FrameRegisters {
+            reg_start: program.alloc_register(),
+            reg_end: program.alloc_register(),
+            reg_start_offset,
+            reg_end_offset,
+        });
+        if let Some(frame) = &frame {
+            program.emit_int(0, frame.reg_start);
+            program.emit_int(0, frame.reg_end);
+        }
+        let incremental = match &func.func {
+            WindowFunctionKind::Aggregate(agg)
+                if func.frame.start == ast::FrameBound::UnboundedPreceding
+                    && func.frame.exclude == ast::FrameExclude::NoOthers
+                    && !matches!(agg.func, AggFunc::External(_)) =>
+            {
+                let reg_acc = program.alloc_register();
+                let reg_next = program.alloc_register();
+                program.emit_null(reg_acc, None);
+                program.emit_int(0, reg_next);
+                Some((reg_acc, reg_next))
+            }
+            _ => None,
+        };
+        let reg_peer_end = match &func.func {
+            WindowFunctionKind::Builtin(WindowFunc::CumeDist) => {
+                let reg = program.alloc_register();
+                program.emit_int(0, reg);
+                Some(reg)
+            }
+            _ => None,
+        };
+        function_regs.push(WindowFunctionRegisters {
+            frame,
+            incremental,
+            reg_peer_end,
+        });
+    }
+
+    let label_row_loop = program.allocate_label();
+    let label_next_row = program.allocate_label();
+    let label_rows_done = program.allocate_label();
+    program.preassign_label_to_next_insn(label_row_loop);
+    program.emit_insn(Insn::Ge {
+        lhs: partition.reg_position,
+        rhs: reg_row_count,
+        target_pc: label_rows_done,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    partition.emit_seek(program, partition.reg_position, label_rows_done);
+    for i in 0..row_column_count {
+        program.emit_column(partition_cursor, i, partition.reg_row + i);
+    }
+    let label_same_peer_group = program.allocate_label();
+    program.emit_insn(Insn::Eq {
+        lhs: partition.reg_peer_group(),
+        rhs: reg_prev_peer_group,
+        target_pc: label_same_peer_group,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: partition.reg_position,
+        dst_reg: partition.reg_peer_start,
+        extra_amount: 0,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: partition.reg_peer_group(),
+        dst_reg: reg_prev_peer_group,
+        extra_amount: 0,
+    });
+    program.preassign_label_to_next_insn(label_same_peer_group);
+
+    let reg_results = program.alloc_registers(window.functions.len());
+    let order = window
+        .order_by
+        .first()
+        .map(|(_, order)| (SORTER_RECORD_COLUMN + num_partition_keys, *order));
+    for (i, (func, regs)) in window
+        .functions
+        .iter()
+        .zip(function_regs.iter())
+        .enumerate()
+    {
+        emit_window_function(
+            program,
+            &partition,
+            func,
+            regs,
+            values_column + values.arg_offsets[first_function + i],
+            order,
+            reg_results + i,
+            &plan.table_references,
+            &t_ctx.resolver,
+        )?;
+    }
+
+    if is_last_window {
+        // The result columns and the ORDER BY of the query read the columns and the
+        // window function results from the current row of the partition.
+        for (i, expr) in window_plan.columns.iter().enumerate() {
+            t_ctx
+                .resolver
+                .expr_to_reg_cache
+                .push((expr, partition.reg_row + values_column + i));
+        }
+        for (i, func) in window_plan.functions().enumerate() {
+            let reg = if i < first_function {
+                partition.reg_row + results_column + i
+            } else {
+                reg_results + i - first_function
+            };
+            t_ctx
+                .resolver
+                .expr_to_reg_cache
+                .push((&func.original_expr, reg));
+        }
+        t_ctx.resolver.enable_expr_to_reg_cache();
+
+        if plan.order_by.is_some() {
+            order_by_sorter_insert(
+                program,
+                &t_ctx.resolver,
+                t_ctx
+                    .meta_sort
+                    .as_ref()
+                    .expect("sort metadata must exist for ORDER BY"),
+                &mut t_ctx.result_column_indexes_in_orderby_sorter,
+                plan,
+            )?;
+        } else {
+            emit_select_result(
+                program,
+                &t_ctx.resolver,
+                plan,
+                Some(label_stage_end),
+                Some(label_next_row),
+                None,
+                t_ctx.reg_offset,
+                t_ctx.reg_result_cols_start.unwrap(),
+                t_ctx.limit_ctx,
+            )?;
+        }
+        if let Distinctness::Distinct { ctx } = &plan.distinctness {
+            let distinct_ctx = ctx.as_ref().expect("distinct context must exist");
+            program.preassign_label_to_next_insn(distinct_ctx.label_on_conflict);
+        }
+    } else {
+        // The next window sorts by its own keys, followed by the values and all results so far.
+        let next_key_len = window_key_len(window_plan, window_idx + 1);
+        let carried_count = values.len() + first_function;
+        let record_len = next_key_len + carried_count + window.functions.len();
+        let reg_record_start = program.alloc_registers(record_len);
+        if next_key_len > 0 {
+            program.emit_insn(Insn::Copy {
+                src_reg: partition.reg_row + values_column + values.key_offsets[window_idx + 1],
+                dst_reg: reg_record_start,
+                extra_amount: next_key_len - 1,
+            });
+        }
+        if carried_count > 0 {
+            program.emit_insn(Insn::Copy {
+                src_reg: partition.reg_row + values_column,
+                dst_reg: reg_record_start + next_key_len,
+                extra_amount: carried_count - 1,
+            });
+        }
+        program.emit_insn(Insn::Copy {
+            src_reg: reg_results,
+            dst_reg: reg_record_start + next_key_len + carried_count,
+            extra_amount: window.functions.len() - 1,
+        });
+        let meta = t_ctx
+            .meta_window
+            .as_ref()
+            .expect("window metadata must exist");
+        sorter_insert(
+            program,
+            reg_record_start,
+            record_len,
+            meta.sorter_cursors[window_idx + 1],
+            meta.reg_sorter_record,
+        );
+    }
+
+    program.preassign_label_to_next_insn(label_next_row);
+    partition.emit_increment(program, partition.reg_position);
+    program.emit_insn(Insn::Goto {
+        target_pc: label_row_loop,
+    });
+    program.preassign_label_to_next_insn(label_rows_done);
+    // Reopening the ephemeral index clears it for the next partition.
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: partition_cursor,
+        is_table: false,
+    });
+    program.emit_int(0, reg_row_count);
+    program.preassign_label_to_next_insn(label_flush_done);
+    program.emit_insn(Insn::Return {
+        return_reg: reg_flush_return,
+        can_fallthrough: false,
+    });
+
+    program.preassign_label_to_next_insn(label_stage_end);
+    Ok(())
+}
+
+/// Emits a runtime error.
+fn emit_window_error(program: &mut ProgramBuilder, description: String) {
+    program.emit_insn(Insn::Halt {
+        err_code: SQLITE_ERROR,
+        description,
+        on_error: ast::ResolveType::Abort,
+    });
+}
+
+/// Evaluates the offset of a `<expr> PRECEDING` or `<expr> FOLLOWING` frame bound,
+/// failing when it is NULL or negative.
+fn emit_frame_offset(
+    program: &mut ProgramBuilder,
+    bound: &ast::FrameBound,
+    mode: &ast::FrameMode,
+    is_start: bool,
+    reg_zero: usize,
+    resolver: &Resolver,
+) -> Result<Option<usize>> {
+    let (ast::FrameBound::Preceding(expr) | ast::FrameBound::Following(expr)) = bound else {
+        return Ok(None);
+    };
+    let reg_offset = program.alloc_register();
+    translate_expr(program, None, expr, reg_offset, resolver)?;
+    let label_invalid = program.allocate_label();
+    let label_valid = program.allocate_label();
+    program.emit_insn(Insn::IsNull {
+        reg: reg_offset,
+        target_pc: label_invalid,
+    });
+    program.emit_insn(Insn::Ge {
+        lhs: reg_offset,
+        rhs: reg_zero,
+        target_pc: label_valid,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.preassign_label_to_next_insn(label_invalid);
+    emit_window_error(
+        program,
+        format!(
+            "frame {} offset must be a non-negative {}",
+            if is_start { "starting" } else { "ending" },
+            if matches!(mode, ast::FrameMode::Range) {
+                "number"
+            } else {
+                "integer"
+            }
+        ),
+    );
+    program.preassign_label_to_next_insn(label_valid);
+    Ok(Some(reg_offset))
+}
+
+/// Fails unless the value in `reg` is a positive number.
+fn emit_positive_check(
+    program: &mut ProgramBuilder,
+    partition: &Partition,
+    reg: usize,
+    description: &str,
+) {
+    let label_invalid = program.allocate_label();
+    let label_valid = program.allocate_label();
+    program.emit_insn(Insn::IsNull {
+        reg,
+        target_pc: label_invalid,
+    });
+    program.emit_insn(Insn::Gt {
+        lhs: reg,
+        rhs: partition.reg_zero,
+        target_pc: label_valid,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.preassign_label_to_next_insn(label_invalid);
+    emit_window_error(program, description.to_string());
+    program.preassign_label_to_next_insn(label_valid);
+}
+
+/// Moves the frame bound in `reg_pointer` to its position for the current row.
+/// `order` is the column of the first ORDER BY value of the window in the partition buffer and its direction.
+fn emit_frame_bound(
+    program: &mut ProgramBuilder,
+    partition: &Partition,
+    frame: &WindowFrame,
+    is_start: bool,
+    reg_pointer: usize,
+    reg_offset: Option<usize>,
+    order: Option<(usize, SortOrder)>,
+) {
+    let bound = if is_start { &frame.start } else { &frame.end };
+    match bound {
+        // The start of the frame stays at the start of the partition.
+        ast::FrameBound::UnboundedPreceding => {}
+        ast::FrameBound::UnboundedFollowing => {
+            program.emit_insn(Insn::Copy {
+                src_reg: partition.reg_row_count,
+                dst_reg: reg_pointer,
+                extra_amount: 0,
+            });
+        }
+        ast::FrameBound::CurrentRow => match frame.mode {
+            ast::FrameMode::Rows => {
+                if is_start {
+                    program.emit_insn(Insn::Copy {
+                        src_reg: partition.reg_position,
+                        dst_reg: reg_pointer,
+                        extra_amount: 0,
+                    });
+                } else {
+                    program.emit_insn(Insn::Add {
+                        lhs: partition.reg_position,
+                        rhs: partition.reg_one,
+                        dest: reg_pointer,
+                    });
+                }
+            }
+            ast::FrameMode::Groups | ast::FrameMode::Range => {
+                partition.emit_advance_to_peer_group(
+                    program,
+                    reg_pointer,
+                    partition.reg_peer_group(),
+                    is_start,
+                );
+            }
+        },
+        ast::FrameBound::Preceding(_) | ast::FrameBound::Following(_) => {
+            let preceding = matches!(bound, ast::FrameBound::Preceding(_));
+            let reg_offset = reg_offset.expect("frame offset must be evaluated");
+            let emit_offset_arithmetic = |program: &mut ProgramBuilder, lhs, dest, subtract| {
+                program.emit_insn(if subtract {
+                    Insn::Subtract {
+                        lhs,
+                        rhs: reg_offset,
+                        dest,
+                    }
+                } else {
+                    Insn::Add {
+                        lhs,
+                        rhs: reg_offset,
+                        dest,
+                    }
+                });
+            };
+            match frame.mode {
+                ast::FrameMode::Rows => {
+                    emit_offset_arithmetic(program, partition.reg_position, reg_pointer, preceding);
+                    let label_in_partition = program.allocate_label();
+                    if is_start {
+                        program.emit_insn(Insn::Ge {
+                            lhs: reg_pointer,
+                            rhs: partition.reg_zero,
+                            target_pc: label_in_partition,
+                            flags: CmpInsFlags::default(),
+                            collation: None,
+                        });
+                        program.emit_int(0, reg_pointer);
+                    } else {
+                        partition.emit_increment(program, reg_pointer);
+                        program.emit_insn(Insn::Le {
+                            lhs: reg_pointer,
+                            rhs: partition.reg_row_count,
+                            target_pc: label_in_partition,
+                            flags: CmpInsFlags::default(),
+                            collation: None,
+                        });
+                        program.emit_insn(Insn::Copy {
+                            src_reg: partition.reg_row_count,
+                            dst_reg: reg_pointer,
+                            extra_amount: 0,
+                        });
+                    }
+                    program.preassign_label_to_next_insn(label_in_partition);
+                }
+                ast::FrameMode::Groups => {
+                    let reg_group = program.alloc_register();
+                    emit_offset_arithmetic(
+                        program,
+                        partition.reg_peer_group(),
+                        reg_group,
+                        preceding,
+                    );
+                    partition.emit_advance_to_peer_group(program, reg_pointer, reg_group, is_start);
+                }
+                ast::FrameMode::Range => {
+                    let (column, direction) =
+                        order.expect("RANGE with offset requires an ORDER BY expression");
+                    let reg_value = partition.reg_row + column;
+                    let label_null = program.allocate_label();
+                    let label_done = program.allocate_label();
+                    program.emit_insn(Insn::IsNull {
+                        reg: reg_value,
+                        target_pc: label_null,
+                    });
+                    let reg_bound = program.alloc_register();
+                    emit_offset_arithmetic(
+                        program,
+                        reg_value,
+                        reg_bound,
+                        preceding != (direction == SortOrder::Desc),
+                    );
+                    partition.emit_advance(
+                        program,
+                        reg_pointer,
+                        column,
+                        |program, reg_value, label_stop| {
+                            let flags = CmpInsFlags::default();
+                            let (lhs, rhs, target_pc) = (reg_value, reg_bound, label_stop);
+                            // NULLs sort first, so they come before any ascending bound and after any descending one.
+                            match direction {
+                                SortOrder::Asc => {
+                                    let label_advance = program.allocate_label();
+                                    program.emit_insn(Insn::IsNull {
+                                        reg: reg_value,
+                                        target_pc: label_advance,
+                                    });
+                                    program.emit_insn(if is_start {
+                                        Insn::Ge {
+                                            lhs,
+                                            rhs,
+                                            target_pc,
+                                            flags,
+                                            collation: None,
+                                        }
+                                    } else {
+                                        Insn::Gt {
+                                            lhs,
+                                            rhs,
+                                            target_pc,
+                                            flags,
+                                            collation: None,
+                                        }
+                                    });
+                                    program.preassign_label_to_next_insn(label_advance);
+                                }
+                                SortOrder::Desc => {
+                                    program.emit_insn(Insn::IsNull {
+                                        reg: reg_value,
+                                        target_pc: label_stop,
+                                    });
+                                    program.emit_insn(if is_start {
+                                        Insn::Le {
+                                            lhs,
+                                            rhs,
+                                            target_pc,
+                                            flags,
+                                            collation: None,
+                                        }
+                                    } else {
+                                        Insn::Lt {
+                                            lhs,
+                                            rhs,
+                                            target_pc,
+                                            flags,
+                                            collation: None,
+                                        }
+                                    });
+                                }
+                            }
+                        },
+                    );
+                    program.emit_insn(Insn::Goto {
+                        target_pc: label_done,
+                    });
+                    // The frame of a row with a NULL ORDER BY value consists of its peers.
+                    program.preassign_label_to_next_insn(label_null);
+                    partition.emit_advance_to_peer_group(
+                        program,
+                        reg_pointer,
+                        partition.reg_peer_group(),
+                        is_start,
+                    );
+                    program.preassign_label_to_next_insn(label_done);
+                }
+            }
+        }
+    }
+}
+
+/// Emits the bytecode computing a window function for the current row of the partition into `reg_result`.
+/// `args_column` is the column of the partition buffer where the arguments of the function start.
+#[allow(clippy::too_many_arguments)]
+fn emit_window_function(
+    program: &mut ProgramBuilder,
+    partition: &Partition,
+    func: &WindowFunction,
+    regs: &WindowFunctionRegisters,
+    args_column: usize,
+    order: Option<(usize, SortOrder)>,
+    reg_result: usize,
+    referenced_tables: &TableReferences,
+    resolver: &Resolver,
+) -> Result<()> {
+    let reg_arg = |i: usize| partition.reg_row + args_column + i;
+    if let Some(frame) = &regs.frame {
+        emit_frame_bound(
+            program,
+            partition,
+            &func.frame,
+            true,
+            frame.reg_start,
+            frame.reg_start_offset,
+            order,
+        );
+        emit_frame_bound(
+            program,
+            partition,
+            &func.frame,
+            false,
+            frame.reg_end,
+            frame.reg_end_offset,
+            order,
+        );
+    }
+
+    match &func.func {
+        WindowFunctionKind::Builtin(WindowFunc::RowNumber) => {
+            program.emit_insn(Insn::Add {
+                lhs: partition.reg_position,
+                rhs: partition.reg_one,
+                dest: reg_result,
+            });
+        }
+        WindowFunctionKind::Builtin(WindowFunc::Rank) => {
+            program.emit_insn(Insn::Add {
+                lhs: partition.reg_peer_start,
+                rhs: partition.reg_one,
+                dest: reg_result,
+            });
+        }
+        WindowFunctionKind::Builtin(WindowFunc::DenseRank) => {
+            program.emit_insn(Insn::Copy {
+                src_reg: partition.reg_peer_group(),
+                dst_reg: reg_result,
+                extra_amount: 0,
+            });
+        }
+        WindowFunctionKind::Builtin(WindowFunc::PercentRank) => {
+            // (rank - 1) / (rows - 1), or 0.0 for a partition of a single row
+            let label_done = program.allocate_label();
+            program.emit_insn(Insn::Real {
+                value: 0.0,
+                dest: reg_result,
+            });
+            program.emit_insn(Insn::Le {
+                lhs: partition.reg_row_count,
+                rhs: partition.reg_one,
+                target_pc: label_done,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            let reg_denominator = program.alloc_register();
+            program.emit_insn(Insn::Subtract {
+                lhs: partition.reg_row_count,
+                rhs: partition.reg_one,
+                dest: reg_denominator,
+            });
+            program.emit_insn(Insn::Multiply {
+                lhs: partition.reg_peer_start,
+                rhs: partition.reg_real_one,
+                dest: reg_result,
+            });
+            program.emit_insn(Insn::Divide {
+                lhs: reg_result,
+                rhs: reg_denominator,
+                dest: reg_result,
+            });
+            program.preassign_label_to_next_insn(label_done);
+        }
+        WindowFunctionKind::Builtin(WindowFunc::CumeDist) => {
+            // rows up to the last peer of the current row / rows
+            let reg_peer_end = regs.reg_peer_end.expect("cume_dist() state must exist");
+            partition.emit_advance_to_peer_group(
+                program,
+                reg_peer_end,
+                partition.reg_peer_group(),
+                false,
+            );
+            program.emit_insn(Insn::Multiply {
+                lhs: reg_peer_end,
+                rhs: partition.reg_real_one,
+                dest: reg_result,
+            });
+            program.emit_insn(Insn::Divide {
+                lhs: reg_result,
+                rhs: partition.reg_row_count,
+                dest: reg_result,
+            });
+        }
+        WindowFunctionKind::Builtin(WindowFunc::Ntile) => {
+            // The rows are divided into N buckets, the first rows % N buckets have one row more than the others.
+            let reg_buckets = reg_arg(0);
+            emit_positive_check(
+                program,
+                partition,
+                reg_buckets,
+                "argument of ntile must be a positive integer",
+            );
+            let reg_size = program.alloc_register();
+            let reg_large = program.alloc_register();
+            let reg_large_size = program.alloc_register();
+            let reg_boundary = program.alloc_register();
+            let reg_tmp = program.alloc_register();
+            program.emit_insn(Insn::Divide {
+                lhs: partition.reg_row_count,
+                rhs: reg_buckets,
+                dest: reg_size,
+            });
+            program.emit_insn(Insn::Remainder {
+                lhs: partition.reg_row_count,
+                rhs: reg_buckets,
+                dest: reg_large,
+            });
+            program.emit_insn(Insn::Add {
+                lhs: reg_size,
+                rhs: partition.reg_one,
+                dest: reg_large_size,
+            });
+            program.emit_insn(Insn::Multiply {
+                lhs: reg_large,
+                rhs: reg_large_size,
+                dest: reg_boundary,
+            });
+            let label_in_large_bucket = program.allocate_label();
+            let label_done = program.allocate_label();
+            program.emit_insn(Insn::Lt {
+                lhs: partition.reg_position,
+                rhs: reg_boundary,
+                target_pc: label_in_large_bucket,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            program.emit_insn(Insn::Subtract {
+                lhs: partition.reg_position,
+                rhs: reg_boundary,
+                dest: reg_tmp,
+            });
+            program.emit_insn(Insn::Divide {
+                lhs: reg_tmp,
+                rhs: reg_size,
+                dest: reg_tmp,
+            });
+            program.emit_insn(Insn::Add {
+                lhs: reg_tmp,
+                rhs: reg_large,
+                dest: reg_tmp,
+            });
+            program.emit_insn(Insn::Add {
+                lhs: reg_tmp,
+                rhs: partition.reg_one,
+                dest: reg_result,
+            });
+            program.emit_insn(Insn::Goto {
+                target_pc: label_done,
+            });
+            program.preassign_label_to_next_insn(label_in_large_bucket);
+            program.emit_insn(Insn::Divide {
+                lhs: partition.reg_position,
+                rhs: reg_large_size,
+                dest: reg_tmp,
+            });
+            program.emit_insn(Insn::Add {
+                lhs: reg_tmp,
+                rhs: partition.reg_one,
+                dest: reg_result,
+            });
+            program.preassign_label_to_next_insn(label_done);
+        }
+        WindowFunctionKind::Builtin(func_kind @ (WindowFunc::Lag | WindowFunc::Lead)) => {
+            let reg_offset = if func.args.len() > 1 {
+                reg_arg(1)
+            } else {
+                partition.reg_one
+            };
+            let reg_target = program.alloc_register();
+            program.emit_insn(if matches!(func_kind, WindowFunc::Lag) {
+                Insn::Subtract {
+                    lhs: partition.reg_position,
+                    rhs: reg_offset,
+                    dest: reg_target,
+                }
+            } else {
+                Insn::Add {
+                    lhs: partition.reg_position,
+                    rhs: reg_offset,
+                    dest: reg_target,
+                }
+            });
+            let label_default = program.allocate_label();
+            let label_done = program.allocate_label();
+            program.emit_insn(Insn::IsNull {
+                reg: reg_target,
+                target_pc: label_default,
+            });
+            program.emit_insn(Insn::Lt {
+                lhs: reg_target,
+                rhs: partition.reg_zero,
+                target_pc: label_default,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            program.emit_insn(Insn::Ge {
+                lhs: reg_target,
+                rhs: partition.reg_row_count,
+                target_pc: label_default,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            partition.emit_seek(program, reg_target, label_default);
+            program.emit_column(partition.cursor_id, args_column, reg_result);
+            program.emit_insn(Insn::Goto {
+                target_pc: label_done,
+            });
+            program.preassign_label_to_next_insn(label_default);
+            if func.args.len() > 2 {
+                program.emit_insn(Insn::Copy {
+                    src_reg: reg_arg(2),
+                    dst_reg: reg_result,
+                    extra_amount: 0,
+                });
+            } else {
+                program.emit_null(reg_result, None);
+            }
+            program.preassign_label_to_next_insn(label_done);
+        }
+        WindowFunctionKind::Builtin(
+            func_kind @ (WindowFunc::FirstValue | WindowFunc::LastValue | WindowFunc::NthValue),
+        ) => {
+            let frame = regs.frame.as_ref().expect("frame must exist");
+            let reg_nth = if matches!(func_kind, WindowFunc::NthValue) {
+                emit_positive_check(
+                    program,
+                    partition,
+                    reg_arg(1),
+                    "second argument to nth_value must be a positive integer",
+                );
+                Some(reg_arg(1))
+            } else {
+                None
+            };
+            program.emit_null(reg_result, None);
+            if func.frame.exclude == ast::FrameExclude::NoOthers {
+                // Read the row at the wanted position of the frame directly.
+                let label_done = program.allocate_label();
+                let reg_target = program.alloc_register();
+                match func_kind {
+                    WindowFunc::FirstValue => {
+                        program.emit_insn(Insn::Copy {
+                            src_reg: frame.reg_start,
+                            dst_reg: reg_target,
+                            extra_amount: 0,
+                        });
+                    }
+                    WindowFunc::LastValue => {
+                        program.emit_insn(Insn::Subtract {
+                            lhs: frame.reg_end,
+                            rhs: partition.reg_one,
+                            dest: reg_target,
+                        });
+                        program.emit_insn(Insn::Lt {
+                            lhs: reg_target,
+                            rhs: frame.reg_start,
+                            target_pc: label_done,
+                            flags: CmpInsFlags::default(),
+                            collation: None,
+                        });
+                    }
+                    _ => {
+                        program.emit_insn(Insn::Add {
+                            lhs: frame.reg_start,
+                            rhs: reg_nth.unwrap(),
+                            dest: reg_target,
+                        });
+                        program.emit_insn(Insn::Subtract {
+                            lhs: reg_target,
+                            rhs: partition.reg_one,
+                            dest: reg_target,
+                        });
+                    }
+                }
+                program.emit_insn(Insn::Ge {
+                    lhs: reg_target,
+                    rhs: frame.reg_end,
+                    target_pc: label_done,
+                    flags: CmpInsFlags::default(),
+                    collation: None,
+                });
+                partition.emit_seek(program, reg_target, label_done);
+                program.emit_column(partition.cursor_id, args_column, reg_result);
+                program.preassign_label_to_next_insn(label_done);
+            } else {
+                let reg_cursor = program.alloc_register();
+                let reg_count = program.alloc_register();
+                program.emit_insn(Insn::Copy {
+                    src_reg: frame.reg_start,
+                    dst_reg: reg_cursor,
+                    extra_amount: 0,
+                });
+                program.emit_int(0, reg_count);
+                partition.emit_frame_loop(
+                    program,
+                    &func.frame.exclude,
+                    reg_cursor,
+                    frame.reg_end,
+                    |program, label_next, label_done| {
+                        if let Some(reg_nth) = reg_nth {
+                            partition.emit_increment(program, reg_count);
+                            program.emit_insn(Insn::Lt {
+                                lhs: reg_count,
+                                rhs: reg_nth,
+                                target_pc: label_next,
+                                flags: CmpInsFlags::default(),
+                                collation: None,
+                            });
+                        }
+                        program.emit_column(partition.cursor_id, args_column, reg_result);
+                        if !matches!(func_kind, WindowFunc::LastValue) {
+                            program.emit_insn(Insn::Goto {
+                                target_pc: label_done,
+                            });
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+        }
+        WindowFunctionKind::Aggregate(agg) => {
+            let frame = regs.frame.as_ref().expect("frame must exist");
+            // A frame starting at the start of the partition only grows, so the rows entering it are
+            // added to a running accumulator. Other frames are aggregated from scratch for each row.
+            let (reg_acc, reg_cursor) = match regs.incremental {
+                Some(state) => state,
+                None => {
+                    let reg_cursor = program.alloc_register();
+                    program.emit_insn(Insn::Copy {
+                        src_reg: frame.reg_start,
+                        dst_reg: reg_cursor,
+                        extra_amount: 0,
+                    });
+                    program.emit_null(reg_result, None);
+                    (reg_result, reg_cursor)
+                }
+            };
+            let reg_args = program.alloc_registers(agg.args.len().max(1));
+            partition.emit_frame_loop(
+                program,
+                &func.frame.exclude,
+                reg_cursor,
+                frame.reg_end,
+                |program, label_next, _| {
+                    if func.filter.is_some() {
+                        let reg_filter = program.alloc_register();
+                        program.emit_column(
+                            partition.cursor_id,
+                            args_column + func.args.len(),
+                            reg_filter,
+                        );
+                        program.emit_insn(Insn::IfNot {
+                            reg: reg_filter,
+                            target_pc: label_next,
+                            jump_if_null: true,
+                        });
+                    }
+                    for i in 0..agg.args.len() {
+                        program.emit_column(partition.cursor_id, args_column + i, reg_args + i);
+                    }
+                    translate_aggregation_step_groupby(
+                        program,
+                        referenced_tables,
+                        GroupByAggArgumentSource::new_from_registers(reg_args, agg),
+                        reg_acc,
+                        resolver,
+                    )?;
+                    Ok(())
+                },
+            )?;
+            if regs.incremental.is_some() {
+                // AggFinal replaces the accumulator with the result, so finalize a copy of it.
+                program.emit_insn(Insn::Copy {
+                    src_reg: reg_acc,
+                    dst_reg: reg_result,
+                    extra_amount: 0,
+                });
+            }
+            program.emit_insn(Insn::AggFinal {
+                register: reg_result,
+                func: agg.func.clone(),
+            });
+        }
+    }
+    Ok(())
+}
@@ -11,6 +11,7 @@ use crate::storage::btree::BTreeCursor;
 use crate::storage::sqlite3_ondisk::{read_integer, read_value, read_varint, write_varint};
 use crate::translate::collate::CollationSeq;
 use crate::translate::plan::IterationDirection;
+use crate::vdbe::hash_table::HashTable;
 use crate::vdbe::sorter::Sorter;
 use crate::vdbe::Register;
 use crate::vtab::VirtualTableCursor;
@@ -1486,6 +1487,8 @@ pub struct IndexKeyInfo {
     /// Indicates whether the index includes a row ID column.
     pub has_rowid: bool,
     /// The total number of columns in the index, including the row ID column if present.
+    /// These make up the key of an entry: the b-tree of a WITHOUT ROWID table stores the rest
+    /// of the row after its PRIMARY KEY columns.
     pub num_cols: usize,
 }
 
@@ -2263,6 +2266,7 @@ pub enum Cursor {
     Pseudo(PseudoCursor),
     Sorter(Sorter),
     Virtual(VirtualTableCursor),
+    HashTable(Box<HashTable>),
 }
 
 impl Cursor {
@@ -2278,6 +2282,10 @@ impl Cursor {
         Self::Sorter(cursor)
     }
 
+    pub fn new_hash_table(cursor: HashTable) -> Self {
+        Self::HashTable(Box::new(cursor))
+    }
+
     pub fn as_btree_mut(&mut self) -> &mut BTreeCursor {
         match self {
             Self::BTree(cursor) => cursor,
@@ -2305,6 +2313,13 @@ impl Cursor {
             _ => panic!("Cursor is not a virtual cursor"),
         }
     }
+
+    pub fn as_hash_table_mut(&mut self) -> &mut HashTable {
+        match self {
+            Self::HashTable(cursor) => cursor,
+            _ => panic!("Cursor is not a hash table cursor"),
+        }
+    }
 }
 
 #[derive(Debug)]
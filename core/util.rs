@@ -25,6 +25,11 @@ impl RoundToPrecision for f64 {
 const QUOTE_PAIRS: &[(char, char)] = &[('"', '"'), ('[', ']'), ('`', '`')];
 
 pub fn normalize_ident(identifier: &str) -> String {
+    dequote(identifier).to_lowercase()
+}
+
+/// Removes the quotes around a quoted identifier, keeping its case.
+pub fn dequote(identifier: &str) -> &str {
     let quote_pair = QUOTE_PAIRS
         .iter()
         .find(|&(start, end)| identifier.starts_with(*start) && identifier.ends_with(*end));
@@ -34,7 +39,6 @@ pub fn normalize_ident(identifier: &str) -> String {
     } else {
         identifier
     }
-    .to_lowercase()
 }
 
 pub const PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX: &str = "sqlite_autoindex_";
@@ -66,7 +70,7 @@ pub fn parse_schema_rows(
                 StepResult::Row => {
                     let row = rows.row().unwrap();
                     let ty = row.get::<&str>(0)?;
-                    if !["table", "index"].contains(&ty) {
+                    if !["table", "index", "trigger", "view"].contains(&ty) {
                         continue;
                     }
                     match ty {
@@ -123,6 +127,16 @@ pub fn parse_schema_rows(
                                 }
                             }
                         }
+                        "trigger" => {
+                            let sql: &str = row.get::<&str>(4)?;
+                            let trigger = schema::Trigger::from_sql(sql)?;
+                            schema.add_trigger(Arc::new(trigger));
+                        }
+                        "view" => {
+                            let sql: &str = row.get::<&str>(4)?;
+                            let view = schema::View::from_sql(sql)?;
+                            schema.add_view(Arc::new(view));
+                        }
                         _ => continue,
                     }
                 }
@@ -137,33 +151,51 @@ pub fn parse_schema_rows(
             }
         }
         for unparsed_sql_from_index in from_sql_indexes {
-            if !schema.indexes_enabled() {
-                schema.table_set_has_index(&unparsed_sql_from_index.table_name);
-            } else {
-                let table = schema
-                    .get_btree_table(&unparsed_sql_from_index.table_name)
-                    .unwrap();
-                let index = schema::Index::from_sql(
-                    &unparsed_sql_from_index.sql,
-                    unparsed_sql_from_index.root_page,
-                    table.as_ref(),
-                )?;
+            let table = schema
+                .get_btree_table(&unparsed_sql_from_index.table_name)
+                .unwrap();
+            let index = schema::Index::from_sql(
+                &unparsed_sql_from_index.sql,
+                unparsed_sql_from_index.root_page,
+                table.as_ref(),
+            )?;
+            schema.add_index(Arc::new(index));
+        }
+        for automatic_index in automatic_indices {
+            let table = schema.get_btree_table(&automatic_index.0).unwrap();
+            let ret_index = schema::Index::automatic_from_primary_key_and_unique(
+                table.as_ref(),
+                automatic_index.1,
+            )?;
+            for index in ret_index {
                 schema.add_index(Arc::new(index));
             }
         }
-        for automatic_index in automatic_indices {
-            if !schema.indexes_enabled() {
-                schema.table_set_has_index(&automatic_index.0);
-            } else {
-                let table = schema.get_btree_table(&automatic_index.0).unwrap();
-                let ret_index = schema::Index::automatic_from_primary_key_and_unique(
-                    table.as_ref(),
-                    automatic_index.1,
-                )?;
-                for index in ret_index {
-                    schema.add_index(Arc::new(index));
-                }
+    }
+    Ok(())
+}
+
+/// Replace the statistics of `schema` with the rows of `sqlite_stat1` returned by `rows`.
+pub fn parse_stat1_rows(
+    mut rows: Statement,
+    schema: &mut Schema,
+    mv_tx_id: Option<u64>,
+) -> Result<()> {
+    rows.set_mv_tx_id(mv_tx_id);
+    schema.stats.clear();
+    loop {
+        match rows.step()? {
+            StepResult::Row => {
+                let row = rows.row().unwrap();
+                let (Ok(tbl), Ok(stat)) = (row.get::<&str>(0), row.get::<&str>(2)) else {
+                    continue;
+                };
+                schema.add_stat1_row(tbl, row.get::<&str>(1).ok(), stat);
+            }
+            StepResult::IO => {
+                rows.run_once()?;
             }
+            StepResult::Interrupt | StepResult::Done | StepResult::Busy => break,
         }
     }
     Ok(())
@@ -410,13 +442,14 @@ pub fn exprs_are_equivalent(expr1: &Expr, expr2: &Expr) -> bool {
                             filter_clause: fc2,
                             over_clause: oc2,
                         }),
-                    ) => match ((fc1, fc2), (oc1, oc2)) {
-                        ((Some(fc1), Some(fc2)), (Some(oc1), Some(oc2))) => {
-                            exprs_are_equivalent(fc1, fc2) && oc1 == oc2
-                        }
-                        ((Some(fc1), Some(fc2)), _) => exprs_are_equivalent(fc1, fc2),
-                        _ => false,
-                    },
+                    ) => {
+                        let filters_equivalent = match (fc1, fc2) {
+                            (Some(fc1), Some(fc2)) => exprs_are_equivalent(fc1, fc2),
+                            (None, None) => true,
+                            _ => false,
+                        };
+                        filters_equivalent && oc1 == oc2
+                    }
                     _ => false,
                 }
         }
@@ -432,6 +465,7 @@ pub fn exprs_are_equivalent(expr1: &Expr, expr2: &Expr) -> bool {
         (Expr::Variable(var), Expr::Variable(var2)) if var.is_empty() && var2.is_empty() => false,
         // Named variables can be compared by their name
         (Expr::Variable(val), Expr::Variable(val2)) => val == val2,
+        (Expr::Register(reg1), Expr::Register(reg2)) => reg1 == reg2,
         (Expr::Parenthesized(exprs1), Expr::Parenthesized(exprs2)) => {
             exprs1.len() == exprs2.len()
                 && exprs1
@@ -476,6 +510,9 @@ pub fn exprs_are_equivalent(expr1: &Expr, expr2: &Expr) -> bool {
                     })
                     .unwrap_or(false)
         }
+        // A subquery is the same expression whether or not it was planned already
+        (Expr::SubqueryResult { expr: expr1, .. }, expr2)
+        | (expr2, Expr::SubqueryResult { expr: expr1, .. }) => exprs_are_equivalent(expr1, expr2),
         // fall back to naive equality check
         _ => expr1 == expr2,
     }
@@ -573,6 +610,9 @@ pub fn columns_from_create_table_body(body: &ast::CreateTableBody) -> crate::Res
                     .as_ref()
                     .map(|data_type| data_type.name.as_str().contains("HIDDEN"))
                     .unwrap_or(false),
+                notnull_conflict: None,
+                unique_conflict: None,
+                generated: None,
             }
         })
         .collect::<Vec<_>>())
@@ -1051,7 +1091,6 @@ pub fn parse_string(expr: &Expr) -> Result<String> {
     }
 }
 
-#[allow(unused)]
 pub fn parse_pragma_bool(expr: &Expr) -> Result<bool> {
     const TRUE_VALUES: &[&str] = &["yes", "true", "on"];
     const FALSE_VALUES: &[&str] = &["no", "false", "off"];
@@ -1059,8 +1098,8 @@ pub fn parse_pragma_bool(expr: &Expr) -> Result<bool> {
         if let Value::Integer(x @ (0 | 1)) = number {
             return Ok(x != 0);
         }
-    } else if let Expr::Name(name) = expr {
-        let ident = normalize_ident(&name.0);
+    } else if let Expr::Name(ast::Name(name)) | Expr::Literal(Literal::Keyword(name)) = expr {
+        let ident = normalize_ident(name);
         if TRUE_VALUES.contains(&ident.as_str()) {
             return Ok(true);
         }
@@ -2039,6 +2078,7 @@ pub mod tests {
         assert!(parse_pragma_bool(&Expr::Name(Name("true".into()))).unwrap(),);
         assert!(parse_pragma_bool(&Expr::Name(Name("on".into()))).unwrap(),);
         assert!(parse_pragma_bool(&Expr::Name(Name("yes".into()))).unwrap(),);
+        assert!(parse_pragma_bool(&Expr::Literal(Literal::Keyword("ON".into()))).unwrap(),);
 
         assert!(!parse_pragma_bool(&Expr::Literal(Literal::Numeric("0".into()))).unwrap(),);
         assert!(!parse_pragma_bool(&Expr::Name(Name("false".into()))).unwrap(),);
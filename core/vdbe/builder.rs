@@ -6,10 +6,11 @@ use turso_sqlite3_parser::ast::{self, TableInternalId};
 use crate::{
     numeric::Numeric,
     parameters::Parameters,
-    schema::{BTreeTable, Index, PseudoCursorType, Table},
+    schema::{BTreeTable, Index, PseudoCursorType, Table, Trigger},
     translate::{
         collate::CollationSeq,
         emitter::TransactionMode,
+        optimizer::OptimizerOptions,
         plan::{ResultSetColumn, TableReferences},
     },
     CaptureDataChangesMode, Connection, Value, VirtualTable,
@@ -111,6 +112,78 @@ pub struct ProgramBuilder {
     init_label: BranchOffset,
     start_offset: BranchOffset,
     capture_data_changes_mode: CaptureDataChangesMode,
+    /// Whether triggers may fire recursively (`PRAGMA recursive_triggers`)
+    recursive_triggers: bool,
+    /// The settings of the connection that the optimizer takes into account
+    optimizer_options: OptimizerOptions,
+    /// Whether CHECK constraints are not enforced (`PRAGMA ignore_check_constraints`)
+    ignore_check_constraints: bool,
+    /// Whether foreign key constraints are enforced (`PRAGMA foreign_keys`)
+    foreign_keys: bool,
+    /// Whether this is the sub-program of a trigger, where `RAISE()` may be used
+    is_trigger_program: bool,
+    /// Whether the triggers of the rows written next do not fire, see [Self::set_disable_triggers]
+    disable_triggers: bool,
+    /// The sub-programs of the statement, see [Self::begin_sub_program]. `None` while the
+    /// sub-program is being translated.
+    sub_programs: Vec<(SubProgramKey, Option<ProgramBuilder>)>,
+    /// Temp and attached databases accessed by the program, and whether they are written to
+    other_databases: Vec<(usize, bool)>,
+    query_mode: QueryMode,
+    /// The EXPLAIN QUERY PLAN rows that the rows emitted next are nested in, innermost last
+    explain_parents: Vec<usize>,
+    /// Whether the statement may fail after writing, see [Self::skip_statement_journal]
+    needs_statement_journal: bool,
+    /// The number of subqueries in expressions that were explained so far
+    explained_subqueries: usize,
+}
+
+/// What a sub-program of a statement was translated for. Each is translated once per statement,
+/// however many times it is run, including recursively from within itself.
+#[derive(Debug, Clone)]
+pub enum SubProgramKey {
+    /// The body of a trigger.
+    Trigger(Arc<Trigger>),
+    /// The `ON DELETE` action of a foreign key of `child_table`, or its `ON UPDATE` action with
+    /// `update`.
+    ForeignKeyAction {
+        database_id: usize,
+        child_table: String,
+        parent_table: String,
+        child_columns: Vec<usize>,
+        update: bool,
+    },
+}
+
+impl PartialEq for SubProgramKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SubProgramKey::Trigger(a), SubProgramKey::Trigger(b)) => Arc::ptr_eq(a, b),
+            (
+                SubProgramKey::ForeignKeyAction {
+                    database_id,
+                    child_table,
+                    parent_table,
+                    child_columns,
+                    update,
+                },
+                SubProgramKey::ForeignKeyAction {
+                    database_id: other_database_id,
+                    child_table: other_child_table,
+                    parent_table: other_parent_table,
+                    child_columns: other_child_columns,
+                    update: other_update,
+                },
+            ) => {
+                database_id == other_database_id
+                    && child_table == other_child_table
+                    && parent_table == other_parent_table
+                    && child_columns == other_child_columns
+                    && update == other_update
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -120,6 +193,8 @@ pub enum CursorType {
     Pseudo(PseudoCursorType),
     Sorter,
     VirtualTable(Rc<VirtualTable>),
+    /// The hash table of a hash join, whose rows hold the columns of the index.
+    HashTable(Arc<Index>),
 }
 
 impl CursorType {
@@ -132,12 +207,14 @@ impl CursorType {
 pub enum QueryMode {
     Normal,
     Explain,
+    ExplainQueryPlan,
 }
 
 impl From<ast::Cmd> for QueryMode {
     fn from(stmt: ast::Cmd) -> Self {
         match stmt {
-            ast::Cmd::ExplainQueryPlan(_) | ast::Cmd::Explain(_) => QueryMode::Explain,
+            ast::Cmd::Explain(_) => QueryMode::Explain,
+            ast::Cmd::ExplainQueryPlan(_) => QueryMode::ExplainQueryPlan,
             _ => QueryMode::Normal,
         }
     }
@@ -153,6 +230,10 @@ impl ProgramBuilder {
     pub fn new(
         query_mode: QueryMode,
         capture_data_changes_mode: CaptureDataChangesMode,
+        recursive_triggers: bool,
+        optimizer_options: OptimizerOptions,
+        ignore_check_constraints: bool,
+        foreign_keys: bool,
         opts: ProgramBuilderOpts,
     ) -> Self {
         Self {
@@ -178,6 +259,18 @@ impl ProgramBuilder {
             init_label: BranchOffset::Placeholder,
             start_offset: BranchOffset::Placeholder,
             capture_data_changes_mode,
+            recursive_triggers,
+            optimizer_options,
+            ignore_check_constraints,
+            foreign_keys,
+            is_trigger_program: false,
+            disable_triggers: false,
+            sub_programs: Vec::new(),
+            other_databases: Vec::new(),
+            query_mode,
+            explain_parents: Vec::new(),
+            explained_subqueries: 0,
+            needs_statement_journal: true,
         }
     }
 
@@ -185,6 +278,146 @@ impl ProgramBuilder {
         &self.capture_data_changes_mode
     }
 
+    pub fn recursive_triggers(&self) -> bool {
+        self.recursive_triggers
+    }
+
+    pub fn optimizer_options(&self) -> OptimizerOptions {
+        self.optimizer_options
+    }
+
+    pub fn ignore_check_constraints(&self) -> bool {
+        self.ignore_check_constraints
+    }
+
+    pub fn foreign_keys(&self) -> bool {
+        self.foreign_keys
+    }
+
+    /// Whether this is the sub-program of a trigger, where `RAISE()` may be used.
+    pub fn is_trigger_program(&self) -> bool {
+        self.is_trigger_program
+    }
+
+    /// Whether the triggers of the rows written next do not fire.
+    pub fn disable_triggers(&self) -> bool {
+        self.disable_triggers
+    }
+
+    /// Keeps the triggers from firing for the rows written next, like the implicit DELETE of a
+    /// table being dropped. It does not apply to sub-programs, e.g. foreign key actions.
+    pub fn set_disable_triggers(&mut self, disable_triggers: bool) {
+        self.disable_triggers = disable_triggers;
+    }
+
+    /// Marks the statement as failing only before it writes anything, like an INSERT of a single
+    /// row without triggers. In an explicit transaction, it then does not need the statement
+    /// journal that undoes the changes of a failing statement.
+    pub fn skip_statement_journal(&mut self) {
+        self.needs_statement_journal = false;
+    }
+
+    /// Returns the index of the sub-program translated for `key`, if there is one. It may still
+    /// be being translated, when a trigger fires recursively from within its own body.
+    pub fn sub_program(&self, key: &SubProgramKey) -> Option<usize> {
+        self.sub_programs
+            .iter()
+            .position(|(other_key, _)| other_key == key)
+    }
+
+    /// Starts the translation of the sub-program for `key`, run by [Insn::Program].
+    /// Returns its index and the builder to translate it with, which must be handed back to
+    /// [Self::end_sub_program]. The arguments of the sub-program are in its first registers,
+    /// starting at register 1.
+    pub fn begin_sub_program(
+        &mut self,
+        key: SubProgramKey,
+        is_trigger_program: bool,
+    ) -> (usize, ProgramBuilder) {
+        let index = self.sub_programs.len();
+        self.sub_programs.push((key, None));
+        let mut sub_program = ProgramBuilder::new(
+            QueryMode::Normal,
+            self.capture_data_changes_mode.clone(),
+            self.recursive_triggers,
+            self.optimizer_options,
+            self.ignore_check_constraints,
+            self.foreign_keys,
+            ProgramBuilderOpts {
+                num_cursors: 1,
+                approx_num_insns: 32,
+                approx_num_labels: 4,
+            },
+        );
+        sub_program.is_trigger_program = is_trigger_program;
+        // The sub-programs it runs are the ones of the statement
+        sub_program.sub_programs = std::mem::take(&mut self.sub_programs);
+        sub_program.prologue();
+        (index, sub_program)
+    }
+
+    /// Ends the translation of the sub-program started with [Self::begin_sub_program].
+    pub fn end_sub_program(&mut self, index: usize, mut sub_program: ProgramBuilder) {
+        // The statement opens the transactions of the databases the sub-program accesses
+        for (db, write) in std::mem::take(&mut sub_program.other_databases) {
+            self.use_other_database(db, write);
+        }
+        sub_program.epilogue(TransactionMode::None);
+        self.sub_programs = std::mem::take(&mut sub_program.sub_programs);
+        self.sub_programs[index].1 = Some(sub_program);
+    }
+
+    pub fn query_mode(&self) -> QueryMode {
+        self.query_mode
+    }
+
+    /// Whether the statement being translated is an EXPLAIN QUERY PLAN.
+    fn explains_query_plan(&self) -> bool {
+        self.query_mode == QueryMode::ExplainQueryPlan
+    }
+
+    /// Emits a row of the EXPLAIN QUERY PLAN output, see [Insn::Explain], nested in the row of
+    /// the last [Self::emit_explain] call with `push` that was not popped yet. With `push`, the
+    /// rows emitted next are nested in this one until [Self::pop_explain] is called.
+    /// Nothing is emitted unless the statement is an EXPLAIN QUERY PLAN.
+    pub fn emit_explain(&mut self, push: bool, detail: String) {
+        if !self.explains_query_plan() {
+            return;
+        }
+        let id = self.insns.len();
+        self.emit_insn(Insn::Explain {
+            id,
+            parent_id: self.explain_parents.last().copied(),
+            detail,
+        });
+        if push {
+            self.explain_parents.push(id);
+        }
+    }
+
+    /// Emits the EXPLAIN QUERY PLAN row of a subquery in an expression, numbered in the order in
+    /// which they are translated, that the rows of the subquery are nested in.
+    pub fn emit_explain_subquery(&mut self, correlated: bool, list: bool) {
+        if !self.explains_query_plan() {
+            return;
+        }
+        self.explained_subqueries += 1;
+        let detail = format!(
+            "{}{} SUBQUERY {}",
+            if correlated { "CORRELATED " } else { "" },
+            if list { "LIST" } else { "SCALAR" },
+            self.explained_subqueries
+        );
+        self.emit_explain(true, detail);
+    }
+
+    /// Ends the nesting of EXPLAIN QUERY PLAN rows in the last row emitted with `push`.
+    pub fn pop_explain(&mut self) {
+        if self.explains_query_plan() {
+            self.explain_parents.pop();
+        }
+    }
+
     pub fn extend(&mut self, opts: &ProgramBuilderOpts) {
         self.insns.reserve(opts.approx_num_insns);
         self.cursor_ref.reserve(opts.num_cursors);
@@ -303,12 +536,37 @@ impl ProgramBuilder {
 
     #[instrument(skip(self), level = Level::INFO)]
     pub fn emit_insn(&mut self, insn: Insn) {
+        // Accessing the temp database or an attached database requires a transaction on it
+        match &insn {
+            Insn::OpenRead { db, .. } | Insn::ReadCookie { db, .. } if *db > 0 => {
+                self.use_other_database(*db, false)
+            }
+            Insn::OpenWrite { db, .. }
+            | Insn::SetCookie { db, .. }
+            | Insn::CreateBtree { db, .. }
+                if *db > 0 =>
+            {
+                self.use_other_database(*db, true)
+            }
+            _ => {}
+        }
         let function = insn.to_function();
         // This seemingly empty trace here is needed so that a function span is emmited with it
         tracing::trace!("");
         self.insns.push((insn, function, self.insns.len()));
     }
 
+    fn use_other_database(&mut self, db: usize, write: bool) {
+        match self
+            .other_databases
+            .iter_mut()
+            .find(|(other_db, _)| *other_db == db)
+        {
+            Some((_, other_write)) => *other_write |= write,
+            None => self.other_databases.push((db, write)),
+        }
+    }
+
     pub fn close_cursors(&mut self, cursors: &[CursorID]) {
         for cursor in cursors {
             self.emit_insn(Insn::Close { cursor_id: *cursor });
@@ -352,6 +610,7 @@ impl ProgramBuilder {
             } else {
                 String::new()
             },
+            on_error: ast::ResolveType::Abort,
         });
     }
 
@@ -363,6 +622,7 @@ impl ProgramBuilder {
         self.emit_insn(Insn::Halt {
             err_code,
             description,
+            on_error: ast::ResolveType::Abort,
         });
     }
 
@@ -571,6 +831,9 @@ impl ProgramBuilder {
                 } => {
                     resolve(target_pc, "IfNot");
                 }
+                Insn::FkIfZero { target_pc, .. } => {
+                    resolve(target_pc, "FkIfZero");
+                }
                 Insn::Rewind { pc_if_empty, .. } => {
                     resolve(pc_if_empty, "Rewind");
                 }
@@ -595,6 +858,12 @@ impl ProgramBuilder {
                 Insn::SorterSort { pc_if_empty, .. } => {
                     resolve(pc_if_empty, "SorterSort");
                 }
+                Insn::HashProbe { pc_if_empty, .. } => {
+                    resolve(pc_if_empty, "HashProbe");
+                }
+                Insn::HashNext { pc_if_next, .. } => {
+                    resolve(pc_if_next, "HashNext");
+                }
                 Insn::NotNull {
                     reg: _reg,
                     target_pc,
@@ -640,6 +909,9 @@ impl ProgramBuilder {
                 Insn::SeekRowid { target_pc, .. } => {
                     resolve(target_pc, "SeekRowid");
                 }
+                Insn::Program { ignore_target, .. } => {
+                    resolve(ignore_target, "Program");
+                }
                 Insn::Gosub { target_pc, .. } => {
                     resolve(target_pc, "Gosub");
                 }
@@ -768,10 +1040,16 @@ impl ProgramBuilder {
             self.preassign_label_to_next_insn(self.init_label);
 
             match txn_mode {
-                TransactionMode::Read => self.emit_insn(Insn::Transaction { write: false }),
-                TransactionMode::Write => self.emit_insn(Insn::Transaction { write: true }),
+                TransactionMode::Read => self.emit_insn(Insn::Transaction {
+                    db: 0,
+                    write: false,
+                }),
+                TransactionMode::Write => self.emit_insn(Insn::Transaction { db: 0, write: true }),
                 TransactionMode::None => {}
             }
+            for (db, write) in std::mem::take(&mut self.other_databases) {
+                self.emit_insn(Insn::Transaction { db, write });
+            }
 
             self.emit_constant_insns();
             self.emit_insn(Insn::Goto {
@@ -857,6 +1135,20 @@ impl ProgramBuilder {
             })
         };
 
+        // VIRTUAL generated columns are not stored in the table record, so the record
+        // position of a column is shifted by the virtual columns before it.
+        let column = match cursor_type {
+            CursorType::BTreeTable(btree) if btree.columns[column].is_virtual() => {
+                self.emit_insn(Insn::Null {
+                    dest: out,
+                    dest_end: None,
+                });
+                return;
+            }
+            CursorType::BTreeTable(btree) => btree.storage_column_index(column),
+            _ => column,
+        };
+
         self.emit_insn(Insn::Column {
             cursor_id,
             column,
@@ -869,6 +1161,34 @@ impl ProgramBuilder {
         self.resolve_labels();
 
         self.parameters.list.dedup();
+        if self.query_mode == QueryMode::ExplainQueryPlan {
+            // The program is not run, its Explain instructions are returned as rows instead
+            self.result_columns.clear();
+            for name in ["id", "parent", "notused", "detail"] {
+                self.add_pragma_result_column(name.to_string());
+            }
+            self.next_free_register = self.next_free_register.max(4);
+        }
+        let sub_programs = self
+            .sub_programs
+            .into_iter()
+            .map(|(_, sub_program)| {
+                sub_program
+                    .expect("sub-program was translated")
+                    .build(connection.clone(), false)
+            })
+            .collect();
+        let changes_schema = self.insns.iter().any(|(insn, ..)| {
+            matches!(
+                insn,
+                Insn::ParseSchema { .. }
+                    | Insn::DropTable { .. }
+                    | Insn::DropIndex { .. }
+                    | Insn::DropTrigger { .. }
+                    | Insn::DropView { .. }
+                    | Insn::SetCookie { .. }
+            )
+        });
         Program {
             max_registers: self.next_free_register,
             insns: self
@@ -884,6 +1204,10 @@ impl ProgramBuilder {
             change_cnt_on,
             result_columns: self.result_columns,
             table_references: self.table_references,
+            query_mode: self.query_mode,
+            needs_statement_journal: self.needs_statement_journal,
+            changes_schema,
+            sub_programs,
         }
     }
 }
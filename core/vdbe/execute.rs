@@ -9,14 +9,18 @@ use crate::storage::sqlite3_ondisk::read_varint;
 use crate::storage::wal::DummyWAL;
 use crate::storage::{self, header_accessor};
 use crate::translate::collate::CollationSeq;
+use crate::translate::expr::walk_expr_mut;
 use crate::types::{
     compare_immutable, compare_records_generic, ImmutableRecord, SeekResult, Text, TextSubtype,
 };
 use crate::util::normalize_ident;
+use crate::vdbe::hash_table::HashTable;
 use crate::vdbe::registers_to_ref_values;
 use crate::{
     error::{
-        LimboError, SQLITE_CONSTRAINT, SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY,
+        LimboError, SQLITE_CONSTRAINT, SQLITE_CONSTRAINT_CHECK, SQLITE_CONSTRAINT_FOREIGNKEY,
+        SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY, SQLITE_CONSTRAINT_TRIGGER,
+        SQLITE_ERROR,
     },
     ext::ExtValue,
     function::{AggFunc, ExtFunc, MathFunc, MathFuncArity, ScalarFunc, VectorFunc},
@@ -45,11 +49,11 @@ use crate::{
     types::{AggContext, Cursor, ExternalAggState, IOResult, SeekKey, SeekOp, Value, ValueType},
     util::{
         cast_real_to_integer, cast_text_to_integer, cast_text_to_numeric, cast_text_to_real,
-        checked_cast_text_to_numeric, parse_schema_rows, RoundToPrecision,
+        checked_cast_text_to_numeric, parse_schema_rows, parse_stat1_rows, RoundToPrecision,
     },
     vdbe::{
         builder::CursorType,
-        insn::{IdxInsertFlags, Insn},
+        insn::{IdxInsertFlags, InsertFlags, Insn, SavepointOp},
     },
     vector::{vector32, vector64, vector_distance_cos, vector_distance_l2, vector_extract},
 };
@@ -216,12 +220,45 @@ pub fn op_drop_index(
     let Insn::DropIndex { index, db: _ } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    let mut schema = program.connection.schema.borrow_mut();
+    let mut schema = program.connection.schema_mut();
     schema.remove_index(index);
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_drop_trigger(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::DropTrigger { db, trigger_name } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let (conn, _) = database_handles(program, pager, *db)?;
+    let mut schema = conn.schema_mut();
+    schema.remove_trigger(trigger_name);
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_drop_view(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::DropView { db: _, view_name } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let mut schema = program.connection.schema_mut();
+    schema.remove_view(view_name);
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_remainder(
     program: &Program,
     state: &mut ProgramState,
@@ -365,11 +402,16 @@ pub fn op_null_row(
     let Insn::NullRow { cursor_id } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    {
+    if let CursorType::HashTable(_) = program.cursor_ref[*cursor_id].1 {
+        let mut cursor = state.get_cursor(*cursor_id);
+        cursor.as_hash_table_mut().set_null_flag(true);
+    } else {
         let mut cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "NullRow");
         let cursor = cursor.as_btree_mut();
         cursor.set_null_flag(true);
     }
+    // There is no row to seek to anymore
+    state.deferred_seeks[*cursor_id] = None;
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
 }
@@ -864,6 +906,20 @@ pub fn op_if_not(
     Ok(InsnFunctionStepResult::Step)
 }
 
+/// Returns the connection and pager used to access the database at index `db`.
+fn database_handles(
+    program: &Program,
+    pager: &Rc<Pager>,
+    db: usize,
+) -> Result<(Arc<crate::Connection>, Rc<Pager>)> {
+    if db == 0 {
+        return Ok((program.connection.clone(), pager.clone()));
+    }
+    let conn = program.connection.database_connection(db)?;
+    let pager = conn.pager.borrow().clone();
+    Ok((conn, pager))
+}
+
 pub fn op_open_read(
     program: &Program,
     state: &mut ProgramState,
@@ -874,13 +930,15 @@ pub fn op_open_read(
     let Insn::OpenRead {
         cursor_id,
         root_page,
+        db,
     } = insn
     else {
         unreachable!("unexpected Insn {:?}", insn)
     };
+    let (conn, pager) = database_handles(program, pager, *db)?;
     let (_, cursor_type) = program.cursor_ref.get(*cursor_id).unwrap();
     let mv_cursor = match state.mv_tx_id {
-        Some(tx_id) => {
+        Some(tx_id) if *db == 0 => {
             let table_id = *root_page as u64;
             let mv_store = mv_store.unwrap().clone();
             let mv_cursor = Rc::new(RefCell::new(
@@ -888,7 +946,7 @@ pub fn op_open_read(
             ));
             Some(mv_cursor)
         }
-        None => None,
+        _ => None,
     };
     let mut cursors = state.cursors.borrow_mut();
     let num_columns = match cursor_type {
@@ -898,15 +956,20 @@ pub fn op_open_read(
     };
 
     match cursor_type {
-        CursorType::BTreeTable(_) => {
+        CursorType::BTreeTable(table) if table.has_rowid => {
             let cursor = BTreeCursor::new_table(mv_cursor, pager.clone(), *root_page, num_columns);
             cursors
                 .get_mut(*cursor_id)
                 .unwrap()
                 .replace(Cursor::new_btree(cursor));
         }
-        CursorType::BTreeIndex(index) => {
-            let conn = program.connection.clone();
+        CursorType::BTreeTable(_) | CursorType::BTreeIndex(_) => {
+            // WITHOUT ROWID tables are stored as an index keyed by their PRIMARY KEY
+            let index = match cursor_type {
+                CursorType::BTreeIndex(index) => index.clone(),
+                CursorType::BTreeTable(table) => table.clustered_index().unwrap(),
+                _ => unreachable!(),
+            };
             let schema = conn.schema.borrow();
             let table = schema
                 .get_table(&index.table_name)
@@ -916,11 +979,13 @@ pub fn op_open_read(
                     .columns
                     .iter()
                     .map(|c| {
-                        table
-                            .columns
-                            .get(c.pos_in_table)
-                            .unwrap()
-                            .collation
+                        c.collation
+                            .or_else(|| {
+                                table
+                                    .columns
+                                    .get(c.pos_in_table)
+                                    .and_then(|column| column.collation)
+                            })
                             .unwrap_or_default()
                     })
                     .collect()
@@ -947,6 +1012,9 @@ pub fn op_open_read(
         CursorType::VirtualTable(_) => {
             panic!("OpenRead on virtual table cursor, use Insn:VOpen instead");
         }
+        CursorType::HashTable(_) => {
+            panic!("OpenRead on hash table cursor, use Insn:HashOpen instead");
+        }
     }
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
@@ -1642,6 +1710,13 @@ pub fn op_column(
         CursorType::VirtualTable(_) => {
             panic!("Insn:Column on virtual table cursor, use Insn:VColumn instead");
         }
+        CursorType::HashTable(_) => {
+            let value = {
+                let mut cursor = state.get_cursor(*cursor_id);
+                cursor.as_hash_table_mut().column(*column)?
+            };
+            state.registers[*dest] = Register::Value(value);
+        }
     }
 
     state.pc += 1;
@@ -1769,10 +1844,14 @@ pub fn op_next(
     let is_empty = {
         let mut cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "Next");
         let cursor = cursor.as_btree_mut();
-        cursor.set_null_flag(false);
-        return_if_io!(cursor.next());
-
-        cursor.is_empty()
+        // A cursor that was set to a NULL row might not point to any row, so there is no row after it.
+        if cursor.get_null_flag() {
+            cursor.set_null_flag(false);
+            true
+        } else {
+            return_if_io!(cursor.next());
+            cursor.is_empty()
+        }
     };
     if !is_empty {
         state.pc = pc_if_next.as_offset_int();
@@ -1800,10 +1879,14 @@ pub fn op_prev(
     let is_empty = {
         let mut cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "Prev");
         let cursor = cursor.as_btree_mut();
-        cursor.set_null_flag(false);
-        return_if_io!(cursor.prev());
-
-        cursor.is_empty()
+        // A cursor that was set to a NULL row might not point to any row, so there is no row after it.
+        if cursor.get_null_flag() {
+            cursor.set_null_flag(false);
+            true
+        } else {
+            return_if_io!(cursor.prev());
+            cursor.is_empty()
+        }
     };
     if !is_empty {
         state.pc = pc_if_prev.as_offset_int();
@@ -1820,36 +1903,87 @@ pub fn halt(
     mv_store: Option<&Rc<MvStore>>,
     err_code: usize,
     description: &str,
+    on_error: ast::ResolveType,
 ) -> Result<InsnFunctionStepResult> {
     if err_code > 0 {
-        // invalidate page cache in case of error
-        pager.clear_page_cache();
+        return halt_with_error(
+            program,
+            state,
+            pager,
+            mv_store,
+            halt_error(err_code, description),
+            on_error,
+        );
+    }
+    match program.commit_txn(pager.clone(), state, mv_store, false)? {
+        StepResult::Done => Ok(InsnFunctionStepResult::Done),
+        StepResult::IO => Ok(InsnFunctionStepResult::IO),
+        StepResult::Row => Ok(InsnFunctionStepResult::Row),
+        StepResult::Interrupt => Ok(InsnFunctionStepResult::Interrupt),
+        StepResult::Busy => Ok(InsnFunctionStepResult::Busy),
     }
+}
+
+pub(crate) fn halt_error(err_code: usize, description: &str) -> LimboError {
     match err_code {
-        0 => {}
         SQLITE_CONSTRAINT_PRIMARYKEY => {
-            return Err(LimboError::Constraint(format!(
-                "UNIQUE constraint failed: {description} (19)"
-            )));
+            LimboError::Constraint(format!("UNIQUE constraint failed: {description} (19)"))
         }
         SQLITE_CONSTRAINT_NOTNULL => {
-            return Err(LimboError::Constraint(format!(
-                "NOT NULL constraint failed: {description} (19)"
-            )));
+            LimboError::Constraint(format!("NOT NULL constraint failed: {description} (19)"))
         }
-        _ => {
-            return Err(LimboError::Constraint(format!(
-                "undocumented halt error code {description}"
-            )));
+        SQLITE_CONSTRAINT_CHECK => {
+            LimboError::Constraint(format!("CHECK constraint failed: {description} (19)"))
         }
+        SQLITE_CONSTRAINT_FOREIGNKEY => {
+            LimboError::Constraint("FOREIGN KEY constraint failed (19)".to_string())
+        }
+        SQLITE_CONSTRAINT_TRIGGER => LimboError::Constraint(description.to_string()),
+        SQLITE_ERROR => LimboError::Constraint(description.to_string()),
+        _ => LimboError::Constraint(format!("undocumented halt error code {description}")),
     }
-    match program.commit_txn(pager.clone(), state, mv_store, false)? {
-        StepResult::Done => Ok(InsnFunctionStepResult::Done),
-        StepResult::IO => Ok(InsnFunctionStepResult::IO),
-        StepResult::Row => Ok(InsnFunctionStepResult::Row),
-        StepResult::Interrupt => Ok(InsnFunctionStepResult::Interrupt),
-        StepResult::Busy => Ok(InsnFunctionStepResult::Busy),
+}
+
+/// Ends the statement with `err`. `on_error` is the conflict resolution of the constraint that
+/// failed, and decides how much of the work done so far is undone:
+/// - ABORT: the changes of the statement are rolled back by [Program::step], and so are the
+///   changes of the transaction in autocommit mode.
+/// - ROLLBACK: the changes of the transaction are rolled back, and an explicit transaction is
+///   ended too.
+/// - FAIL: the changes made so far are kept, and committed in autocommit mode.
+pub(crate) fn halt_with_error(
+    program: &Program,
+    state: &mut ProgramState,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+    err: LimboError,
+    on_error: ast::ResolveType,
+) -> Result<InsnFunctionStepResult> {
+    match on_error {
+        ast::ResolveType::Fail => {
+            state.release_statement_journals();
+            match program.commit_txn(pager.clone(), state, mv_store, false) {
+                Ok(StepResult::IO) => return Ok(InsnFunctionStepResult::IO),
+                Ok(_) => {}
+                Err(commit_err) => {
+                    program.rollback_write_txns(pager)?;
+                    return Err(commit_err);
+                }
+            }
+        }
+        ast::ResolveType::Rollback => {
+            program.connection.auto_commit.replace(true);
+            pager.clear_page_cache();
+        }
+        ast::ResolveType::Abort | ast::ResolveType::Ignore | ast::ResolveType::Replace => {
+            // invalidate page cache in case of error, unless the statement journal keeps the
+            // changes of the transaction made before the statement
+            if state.statement_journals.is_empty() {
+                pager.clear_page_cache();
+            }
+        }
     }
+    Err(err)
 }
 
 pub fn op_halt(
@@ -1862,33 +1996,37 @@ pub fn op_halt(
     let Insn::Halt {
         err_code,
         description,
+        on_error,
     } = insn
     else {
         unreachable!("unexpected Insn {:?}", insn)
     };
     if *err_code > 0 {
-        // invalidate page cache in case of error
-        pager.clear_page_cache();
-    }
-    match *err_code {
-        0 => {}
-        SQLITE_CONSTRAINT_PRIMARYKEY => {
-            return Err(LimboError::Constraint(format!(
-                "UNIQUE constraint failed: {description} (19)"
-            )));
-        }
-        SQLITE_CONSTRAINT_NOTNULL => {
-            return Err(LimboError::Constraint(format!(
-                "NOTNULL constraint failed: {description} (19)"
-            )));
-        }
-        _ => {
-            return Err(LimboError::Constraint(format!(
-                "undocumented halt error code {description}"
-            )));
-        }
+        return halt_with_error(
+            program,
+            state,
+            pager,
+            mv_store,
+            halt_error(*err_code, description),
+            *on_error,
+        );
     }
     let auto_commit = program.connection.auto_commit.get();
+    // Immediate foreign key constraints are checked at the end of the statement, deferred ones
+    // at the end of the transaction, which is now in autocommit mode
+    if state.fk_immediate_violations > 0
+        || (auto_commit && program.connection.deferred_fk_violations.get() > 0)
+    {
+        return halt_with_error(
+            program,
+            state,
+            pager,
+            mv_store,
+            halt_error(SQLITE_CONSTRAINT_FOREIGNKEY, ""),
+            ast::ResolveType::Abort,
+        );
+    }
+    state.release_statement_journals();
     tracing::trace!("op_halt(auto_commit={})", auto_commit);
     if auto_commit {
         match program.commit_txn(pager.clone(), state, mv_store, false)? {
@@ -1919,13 +2057,101 @@ pub fn op_halt_if_null(
         unreachable!("unexpected Insn {:?}", insn)
     };
     if state.registers[*target_reg].get_owned_value() == &Value::Null {
-        halt(program, state, pager, mv_store, *err_code, description)
+        halt(
+            program,
+            state,
+            pager,
+            mv_store,
+            *err_code,
+            description,
+            ast::ResolveType::Abort,
+        )
     } else {
         state.pc += 1;
         Ok(InsnFunctionStepResult::Step)
     }
 }
 
+pub fn op_fk_counter(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::FkCounter {
+        deferred,
+        increment_value,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    if *deferred {
+        let violations = &program.connection.deferred_fk_violations;
+        violations.set(violations.get() + increment_value);
+    } else {
+        state.fk_immediate_violations += increment_value;
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_fk_if_zero(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::FkIfZero {
+        deferred,
+        target_pc,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let violations = if *deferred {
+        program.connection.deferred_fk_violations.get()
+    } else {
+        state.fk_immediate_violations
+    };
+    if violations == 0 {
+        state.pc = target_pc.as_offset_int();
+    } else {
+        state.pc += 1;
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_fk_check(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::FkCheck { release } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let conn = &program.connection;
+    let commits = match release {
+        None => true,
+        Some(name) => {
+            let savepoints = conn.savepoints.borrow();
+            savepoints
+                .iter()
+                .rposition(|savepoint| savepoint.name.eq_ignore_ascii_case(name))
+                == Some(0)
+                && savepoints[0].starts_transaction
+        }
+    };
+    if commits && conn.deferred_fk_violations.get() > 0 {
+        return Err(halt_error(SQLITE_CONSTRAINT_FOREIGNKEY, ""));
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_transaction(
     program: &Program,
     state: &mut ProgramState,
@@ -1933,15 +2159,16 @@ pub fn op_transaction(
     pager: &Rc<Pager>,
     mv_store: Option<&Rc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
-    let Insn::Transaction { write } = insn else {
+    let Insn::Transaction { db, write } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    let conn = program.connection.clone();
+    let (conn, pager) = database_handles(program, pager, *db)?;
     if *write && conn._db.open_flags.contains(OpenFlags::ReadOnly) {
         return Err(LimboError::ReadOnly);
     }
 
-    if let Some(mv_store) = &mv_store {
+    // MVCC only covers the main database
+    if let Some(mv_store) = mv_store.filter(|_| *db == 0) {
         if state.mv_tx_id.is_none() {
             let tx_id = mv_store.begin_tx();
             conn.mv_transactions.borrow_mut().push(tx_id);
@@ -1988,6 +2215,16 @@ pub fn op_transaction(
         if updated {
             conn.transaction_state.replace(new_transaction_state);
         }
+        // In an explicit transaction, a failing statement only undoes its own changes
+        if *write && program.needs_statement_journal && !program.connection.auto_commit.get() {
+            // Only the main database has the savepoints of SAVEPOINT statements
+            let depth = if *db == 0 {
+                conn.savepoints.borrow().len()
+            } else {
+                0
+            };
+            state.open_statement_journal(program, &conn, &pager, depth);
+        }
     }
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
@@ -2025,10 +2262,14 @@ pub fn op_auto_commit(
         };
 
     if *auto_commit != conn.auto_commit.get() {
+        // COMMIT and ROLLBACK end all the open savepoints
+        conn.savepoints.borrow_mut().clear();
+        pager.release_savepoint(0);
         if *rollback {
             // TODO(pere): add rollback I/O logic once we implement rollback journal
             pager.rollback(schema_did_change, &conn)?;
             conn.auto_commit.replace(true);
+            conn.deferred_fk_violations.set(0);
         } else {
             conn.auto_commit.replace(*auto_commit);
         }
@@ -2054,6 +2295,139 @@ pub fn op_auto_commit(
     }
 }
 
+pub fn op_savepoint(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::Savepoint { op, name } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    if state.commit_state == CommitState::Committing {
+        return match program.commit_txn(pager.clone(), state, mv_store, false)? {
+            super::StepResult::Done => Ok(InsnFunctionStepResult::Done),
+            super::StepResult::IO => Ok(InsnFunctionStepResult::IO),
+            super::StepResult::Row => Ok(InsnFunctionStepResult::Row),
+            super::StepResult::Interrupt => Ok(InsnFunctionStepResult::Interrupt),
+            super::StepResult::Busy => Ok(InsnFunctionStepResult::Busy),
+        };
+    }
+    if mv_store.is_some() {
+        return Err(LimboError::TxError(
+            "savepoints are not supported with MVCC".to_string(),
+        ));
+    }
+    let conn = program.connection.clone();
+    let find_savepoint = || {
+        conn.savepoints
+            .borrow()
+            .iter()
+            .rposition(|savepoint| savepoint.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| LimboError::TxError(format!("no such savepoint: {name}")))
+    };
+    match op {
+        SavepointOp::Begin => {
+            // A savepoint outside of BEGIN...COMMIT starts a transaction
+            let starts_transaction = conn.auto_commit.get();
+            if starts_transaction {
+                conn.auto_commit.replace(false);
+            }
+            conn.savepoints.borrow_mut().push(crate::Savepoint {
+                name: name.clone(),
+                schema: conn.schema.borrow().clone(),
+                starts_transaction,
+                deferred_fk_violations: conn.deferred_fk_violations.get(),
+            });
+            pager.open_savepoint();
+        }
+        SavepointOp::Release => {
+            let depth = find_savepoint()?;
+            let commits = depth == 0 && conn.savepoints.borrow()[0].starts_transaction;
+            conn.savepoints.borrow_mut().truncate(depth);
+            pager.release_savepoint(depth);
+            if commits {
+                // Releasing the savepoint that started the transaction commits it
+                conn.auto_commit.replace(true);
+                return match program.commit_txn(pager.clone(), state, mv_store, false)? {
+                    super::StepResult::Done => Ok(InsnFunctionStepResult::Done),
+                    super::StepResult::IO => Ok(InsnFunctionStepResult::IO),
+                    super::StepResult::Row => Ok(InsnFunctionStepResult::Row),
+                    super::StepResult::Interrupt => Ok(InsnFunctionStepResult::Interrupt),
+                    super::StepResult::Busy => Ok(InsnFunctionStepResult::Busy),
+                };
+            }
+        }
+        SavepointOp::Rollback => {
+            let depth = find_savepoint()?;
+            pager.rollback_to_savepoint(depth)?;
+            let mut savepoints = conn.savepoints.borrow_mut();
+            savepoints.truncate(depth + 1);
+            *conn.schema_mut() = savepoints[depth].schema.clone();
+            conn.deferred_fk_violations
+                .set(savepoints[depth].deferred_fk_violations);
+        }
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_attach(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::Attach { path, name } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let path = state.registers[*path].get_owned_value().to_string();
+    let name = state.registers[*name].get_owned_value().to_string();
+    program.connection.attach_database(&path, &name)?;
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_detach(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::Detach { name } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let name = state.registers[*name].get_owned_value().to_string();
+    program.connection.detach_database(&name)?;
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_vacuum(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::Vacuum { db, into } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    assert_eq!(*db, 0, "VACUUM is only supported on the main database");
+    match into {
+        Some(into) => {
+            let path = state.registers[*into].get_owned_value().to_string();
+            program.connection.vacuum_into(&path)?;
+        }
+        None => program.connection.vacuum()?,
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_goto(
     program: &Program,
     state: &mut ProgramState,
@@ -2089,6 +2463,18 @@ pub fn op_gosub(
     Ok(InsnFunctionStepResult::Step)
 }
 
+/// [Insn::Program] enters a frame of the statement, which holds its sub-programs and frames, so
+/// it is run by [Program::step] itself.
+pub fn op_program(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    unreachable!("Program is run by Program::step")
+}
+
 pub fn op_return(
     program: &Program,
     state: &mut ProgramState,
@@ -2271,7 +2657,9 @@ pub fn op_row_id(
     }
     let mut cursors = state.cursors.borrow_mut();
     if let Some(Cursor::BTree(btree_cursor)) = cursors.get_mut(*cursor_id).unwrap() {
-        if let Some(ref rowid) = return_if_io!(btree_cursor.rowid()) {
+        if btree_cursor.get_null_flag() {
+            state.registers[*dest] = Register::Value(Value::Null);
+        } else if let Some(ref rowid) = return_if_io!(btree_cursor.rowid()) {
             state.registers[*dest] = Register::Value(Value::Integer(*rowid));
         } else {
             state.registers[*dest] = Register::Value(Value::Null);
@@ -2304,7 +2692,20 @@ pub fn op_idx_row_id(
     };
     let mut cursors = state.cursors.borrow_mut();
     let cursor = cursors.get_mut(*cursor_id).unwrap().as_mut().unwrap();
+    if let Cursor::HashTable(cursor) = cursor {
+        state.registers[*dest] = match cursor.rowid()? {
+            Some(rowid) => Register::Value(Value::Integer(rowid)),
+            None => Register::Value(Value::Null),
+        };
+        state.pc += 1;
+        return Ok(InsnFunctionStepResult::Step);
+    }
     let cursor = cursor.as_btree_mut();
+    if cursor.get_null_flag() {
+        state.registers[*dest] = Register::Value(Value::Null);
+        state.pc += 1;
+        return Ok(InsnFunctionStepResult::Step);
+    }
     let rowid = return_if_io!(cursor.rowid());
     state.registers[*dest] = match rowid {
         Some(rowid) => Register::Value(Value::Integer(rowid)),
@@ -3081,8 +3482,9 @@ pub fn op_agg_step(
             }
             AggFunc::Max => Register::Aggregate(AggContext::Max(None)),
             AggFunc::Min => Register::Aggregate(AggContext::Min(None)),
+            // NULL until a value is appended, which is also the result of no values
             AggFunc::GroupConcat | AggFunc::StringAgg => {
-                Register::Aggregate(AggContext::GroupConcat(Value::build_text("")))
+                Register::Aggregate(AggContext::GroupConcat(Value::Null))
             }
             #[cfg(feature = "json")]
             AggFunc::JsonGroupArray | AggFunc::JsonbGroupArray => {
@@ -3206,7 +3608,9 @@ pub fn op_agg_step(
             let AggContext::GroupConcat(acc) = agg.borrow_mut() else {
                 unreachable!();
             };
-            if acc.to_string().is_empty() {
+            if col == Value::Null {
+                // NULL values are skipped, without a delimiter
+            } else if *acc == Value::Null {
                 *acc = col;
             } else {
                 match delimiter {
@@ -3326,6 +3730,8 @@ pub fn op_agg_final(
                 let value = match acc {
                     Value::Integer(i) => Value::Integer(*i),
                     Value::Float(f) => Value::Float(*f),
+                    // sum() of only NULLs is NULL, total() is always a float
+                    Value::Null if matches!(func, AggFunc::Sum) => Value::Null,
                     _ => Value::Float(0.0),
                 };
                 state.registers[*register] = Register::Value(value);
@@ -3578,6 +3984,126 @@ pub fn op_sorter_next(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_hash_open(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashOpen {
+        cursor_id,
+        key_len,
+        collations,
+        memory_budget,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let cursor = HashTable::new(
+        *key_len,
+        collations.clone(),
+        *memory_budget,
+        pager.io.clone(),
+    );
+    let mut cursors = state.cursors.borrow_mut();
+    cursors
+        .get_mut(*cursor_id)
+        .unwrap()
+        .replace(Cursor::new_hash_table(cursor));
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_hash_insert(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashInsert {
+        cursor_id,
+        record_reg,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    {
+        let mut cursor = state.get_cursor(*cursor_id);
+        let cursor = cursor.as_hash_table_mut();
+        let record = match &state.registers[*record_reg] {
+            Register::Record(record) => record,
+            _ => unreachable!("HashInsert on non-record register"),
+        };
+        cursor.insert(record)?;
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_hash_probe(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashProbe {
+        cursor_id,
+        key_reg,
+        num_keys,
+        pc_if_empty,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    assert!(pc_if_empty.is_offset());
+    let key = ImmutableRecord::from_registers(
+        &state.registers[*key_reg..*key_reg + *num_keys],
+        *num_keys,
+    );
+    let found = {
+        let mut cursor = state.get_cursor(*cursor_id);
+        let cursor = cursor.as_hash_table_mut();
+        return_if_io!(cursor.probe(key))
+    };
+    if found {
+        state.pc += 1;
+    } else {
+        state.pc = pc_if_empty.as_offset_int();
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_hash_next(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::HashNext {
+        cursor_id,
+        pc_if_next,
+    } = insn
+    else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    assert!(pc_if_next.is_offset());
+    let found = {
+        let mut cursor = state.get_cursor(*cursor_id);
+        let cursor = cursor.as_hash_table_mut();
+        return_if_io!(cursor.next())
+    };
+    if found {
+        state.pc = pc_if_next.as_offset_int();
+    } else {
+        state.pc += 1;
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_function(
     program: &Program,
     state: &mut ProgramState,
@@ -4636,21 +5162,27 @@ pub fn op_function(
                                 idx_name,
                                 tbl_name,
                                 mut columns,
-                                where_clause,
+                                mut where_clause,
                             } => {
                                 if table != normalize_ident(&tbl_name.0) {
                                     break 'sql None;
                                 }
 
-                                for column in &mut columns {
-                                    match &mut column.expr {
-                                        ast::Expr::Id(ast::Id(id))
-                                            if normalize_ident(id) == rename_from =>
-                                        {
+                                // The column may be referred to by indexed expressions and the
+                                // WHERE clause of a partial index too
+                                let mut rename_column = |expr: &mut ast::Expr| -> Result<()> {
+                                    if let ast::Expr::Id(ast::Id(id)) = expr {
+                                        if normalize_ident(id) == rename_from {
                                             *id = rename_to.clone();
                                         }
-                                        _ => {}
                                     }
+                                    Ok(())
+                                };
+                                for column in &mut columns {
+                                    walk_expr_mut(&mut column.expr, &mut rename_column)?;
+                                }
+                                if let Some(where_clause) = &mut where_clause {
+                                    walk_expr_mut(where_clause, &mut rename_column)?;
                                 }
 
                                 Some(
@@ -4900,12 +5432,13 @@ pub fn op_insert(
     }
 
     // Only update last_insert_rowid for regular table inserts, not schema modifications
+    // or rows buffered in an ephemeral table
     let root_page = {
         let mut cursor = state.get_cursor(*cursor_id);
         let cursor = cursor.as_btree_mut();
         cursor.root_page()
     };
-    if root_page != 1 {
+    if root_page != 1 && !flag.has(InsertFlags::EPHEMERAL) {
         state.op_insert_state = OpInsertState::UpdateLastRowid;
     } else {
         state.pc += 1;
@@ -5024,13 +5557,15 @@ pub fn op_idx_delete(
                 state.op_idx_delete_state = Some(OpIdxDeleteState::Verifying);
             }
             Some(OpIdxDeleteState::Verifying) => {
-                let rowid = {
+                // The entries of an index of a WITHOUT ROWID table end with the PRIMARY KEY
+                // instead of a rowid, and the seek has already matched all of it.
+                let (rowid, has_rowid) = {
                     let mut cursor = state.get_cursor(*cursor_id);
                     let cursor = cursor.as_btree_mut();
-                    return_if_io!(cursor.rowid())
+                    (return_if_io!(cursor.rowid()), cursor.has_rowid())
                 };
 
-                if rowid.is_none() && *raise_error_if_no_matching_entry {
+                if has_rowid && rowid.is_none() && *raise_error_if_no_matching_entry {
                     return Err(LimboError::Corrupt(format!(
                         "IdxDelete: no matching index entry found for record {:?}",
                         make_record(&state.registers, start_reg, num_regs)
@@ -5097,10 +5632,13 @@ pub fn op_idx_insert(
     match state.op_idx_insert_state {
         OpIdxInsertState::SeekIfUnique => {
             let (_, cursor_type) = program.cursor_ref.get(cursor_id).unwrap();
-            let CursorType::BTreeIndex(index_meta) = cursor_type else {
-                panic!("IdxInsert: not a BTreeIndex cursor");
+            let unique = match cursor_type {
+                CursorType::BTreeIndex(index_meta) => index_meta.unique,
+                // rows of a WITHOUT ROWID table: the PRIMARY KEY is checked before inserting
+                CursorType::BTreeTable(table) if !table.has_rowid => false,
+                _ => panic!("IdxInsert: not a BTreeIndex cursor"),
             };
-            if !index_meta.unique {
+            if !unique {
                 state.op_idx_insert_state = OpIdxInsertState::Insert {
                     moved_before: false,
                 };
@@ -5193,7 +5731,10 @@ pub fn op_idx_insert(
             }
             state.op_idx_insert_state = OpIdxInsertState::SeekIfUnique;
             state.pc += 1;
-            // TODO: flag optimizations, update n_change if OPFLAG_NCHANGE
+            if flags.has(IdxInsertFlags::NCHANGE) {
+                program.n_change.set(program.n_change.get() + 1);
+            }
+            // TODO: flag optimizations
             Ok(InsnFunctionStepResult::Step)
         }
     }
@@ -5543,6 +6084,7 @@ pub fn op_open_write(
     let Insn::OpenWrite {
         cursor_id,
         root_page,
+        db,
         ..
     } = insn
     else {
@@ -5551,6 +6093,7 @@ pub fn op_open_write(
     if program.connection.readonly.get() {
         return Err(LimboError::ReadOnly);
     }
+    let (conn, pager) = database_handles(program, pager, *db)?;
     let root_page = match root_page {
         RegisterOrLiteral::Literal(lit) => *lit as u64,
         RegisterOrLiteral::Register(reg) => match &state.registers[*reg].get_owned_value() {
@@ -5565,11 +6108,13 @@ pub fn op_open_write(
     let (_, cursor_type) = program.cursor_ref.get(*cursor_id).unwrap();
     let mut cursors = state.cursors.borrow_mut();
     let maybe_index = match cursor_type {
-        CursorType::BTreeIndex(index) => Some(index),
+        CursorType::BTreeIndex(index) => Some(index.clone()),
+        // WITHOUT ROWID tables are stored as an index keyed by their PRIMARY KEY
+        CursorType::BTreeTable(table) => table.clustered_index(),
         _ => None,
     };
     let mv_cursor = match state.mv_tx_id {
-        Some(tx_id) => {
+        Some(tx_id) if *db == 0 => {
             let table_id = root_page;
             let mv_store = mv_store.unwrap().clone();
             let mv_cursor = Rc::new(RefCell::new(
@@ -5577,10 +6122,9 @@ pub fn op_open_write(
             ));
             Some(mv_cursor)
         }
-        None => None,
+        _ => None,
     };
     if let Some(index) = maybe_index {
-        let conn = program.connection.clone();
         let schema = conn.schema.borrow();
         let table = schema
             .get_table(&index.table_name)
@@ -5592,11 +6136,13 @@ pub fn op_open_write(
                 .columns
                 .iter()
                 .map(|c| {
-                    table
-                        .columns
-                        .get(c.pos_in_table)
-                        .unwrap()
-                        .collation
+                    c.collation
+                        .or_else(|| {
+                            table
+                                .columns
+                                .get(c.pos_in_table)
+                                .and_then(|column| column.collation)
+                        })
                         .unwrap_or_default()
                 })
                 .collect()
@@ -5666,10 +6212,7 @@ pub fn op_create_btree(
     if program.connection.readonly.get() {
         return Err(LimboError::ReadOnly);
     }
-    if *db > 0 {
-        // TODO: implement temp databases
-        todo!("temp databases not implemented yet");
-    }
+    let (_, pager) = database_handles(program, pager, *db)?;
     // FIXME: handle page cache is full
     let root_page = return_if_io!(pager.btree_create(flags));
     state.registers[*root] = Register::Value(Value::Integer(root_page as i64));
@@ -5692,9 +6235,7 @@ pub fn op_destroy(
     else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    if *is_temp == 1 {
-        todo!("temp databases not implemented yet.");
-    }
+    let (_, pager) = database_handles(program, pager, *is_temp)?;
     // TODO not sure if should be BTreeCursor::new_table or BTreeCursor::new_index here or neither and just pass an emtpy vec
     let mut cursor = BTreeCursor::new(None, pager.clone(), *root, Vec::new(), 0);
     let former_root_page_result = cursor.btree_destroy()?;
@@ -5716,13 +6257,11 @@ pub fn op_drop_table(
     let Insn::DropTable { db, table_name, .. } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    if *db > 0 {
-        todo!("temp databases not implemented yet");
-    }
-    let conn = program.connection.clone();
+    let (conn, _) = database_handles(program, pager, *db)?;
     {
-        let mut schema = conn.schema.borrow_mut();
+        let mut schema = conn.schema_mut();
         schema.remove_indices_for_table(table_name);
+        schema.remove_triggers_for_table(table_name);
         schema.remove_table(table_name);
     }
     state.pc += 1;
@@ -5773,11 +6312,8 @@ pub fn op_page_count(
     let Insn::PageCount { db, dest } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    if *db > 0 {
-        // TODO: implement temp databases
-        todo!("temp databases not implemented yet");
-    }
-    let count = header_accessor::get_database_size(pager)?.into();
+    let (_, pager) = database_handles(program, pager, *db)?;
+    let count = header_accessor::get_database_size(&pager)?.into();
     state.registers[*dest] = Register::Value(Value::Integer(count));
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
@@ -5790,14 +6326,12 @@ pub fn op_parse_schema(
     pager: &Rc<Pager>,
     mv_store: Option<&Rc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
-    let Insn::ParseSchema {
-        db: _,
-        where_clause,
-    } = insn
-    else {
+    let Insn::ParseSchema { db, where_clause } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    let conn = program.connection.clone();
+    let (conn, _) = database_handles(program, pager, *db)?;
+    // MVCC transactions only cover the main database
+    let mv_tx_id = if *db == 0 { state.mv_tx_id } else { None };
     // set auto commit to false in order for parse schema to not commit changes as transaction state is stored in connection,
     // and we use the same connection for nested query.
     let previous_auto_commit = conn.auto_commit.get();
@@ -5810,35 +6344,61 @@ pub fn op_parse_schema(
 
         // TODO: This function below is synchronous, make it async
         {
-            parse_schema_rows(
-                Some(stmt),
-                &mut new_schema,
-                &conn.syms.borrow(),
-                state.mv_tx_id,
-            )?;
+            parse_schema_rows(Some(stmt), &mut new_schema, &conn.syms.borrow(), mv_tx_id)?;
         }
-        conn.schema.replace(new_schema);
+        *conn.schema_mut() = new_schema;
     } else {
         let stmt = conn.prepare("SELECT * FROM sqlite_schema")?;
         let mut new_schema = conn.schema.borrow().clone();
 
         // TODO: This function below is synchronous, make it async
         {
-            parse_schema_rows(
-                Some(stmt),
-                &mut new_schema,
-                &conn.syms.borrow(),
-                state.mv_tx_id,
-            )?;
+            parse_schema_rows(Some(stmt), &mut new_schema, &conn.syms.borrow(), mv_tx_id)?;
         }
 
-        conn.schema.replace(new_schema);
+        *conn.schema_mut() = new_schema;
     }
     conn.auto_commit.set(previous_auto_commit);
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_load_analysis(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Rc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    let Insn::LoadAnalysis { db } = insn else {
+        unreachable!("unexpected Insn {:?}", insn)
+    };
+    let (conn, _) = database_handles(program, pager, *db)?;
+    let mv_tx_id = if *db == 0 { state.mv_tx_id } else { None };
+    // Same as ParseSchema, the nested query must not commit the transaction of the statement.
+    let previous_auto_commit = conn.auto_commit.get();
+    conn.auto_commit.set(false);
+    let result = conn
+        .prepare("SELECT tbl, idx, stat FROM sqlite_stat1")
+        .and_then(|stmt| {
+            let mut new_schema = conn.schema.borrow().clone();
+            parse_stat1_rows(stmt, &mut new_schema, mv_tx_id)?;
+            *conn.schema_mut() = new_schema;
+            Ok(())
+        });
+    conn.auto_commit.set(previous_auto_commit);
+    result?;
+    // The statistics are part of the schema other connections start from once the transaction
+    // commits, and are discarded with it on rollback.
+    if let TransactionState::Write { .. } = conn.transaction_state.get() {
+        conn.transaction_state.set(TransactionState::Write {
+            schema_did_change: true,
+        });
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_read_cookie(
     program: &Program,
     state: &mut ProgramState,
@@ -5849,15 +6409,12 @@ pub fn op_read_cookie(
     let Insn::ReadCookie { db, dest, cookie } = insn else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    if *db > 0 {
-        // TODO: implement temp databases
-        todo!("temp databases not implemented yet");
-    }
+    let (_, pager) = database_handles(program, pager, *db)?;
     let cookie_value = match cookie {
-        Cookie::UserVersion => header_accessor::get_user_version(pager)?.into(),
-        Cookie::SchemaVersion => header_accessor::get_schema_cookie(pager)?.into(),
+        Cookie::UserVersion => header_accessor::get_user_version(&pager)?.into(),
+        Cookie::SchemaVersion => header_accessor::get_schema_cookie(&pager)?.into(),
         Cookie::LargestRootPageNumber => {
-            header_accessor::get_vacuum_mode_largest_root_page(pager)?.into()
+            header_accessor::get_vacuum_mode_largest_root_page(&pager)?.into()
         }
         cookie => todo!("{cookie:?} is not yet implement for ReadCookie"),
     };
@@ -5882,31 +6439,29 @@ pub fn op_set_cookie(
     else {
         unreachable!("unexpected Insn {:?}", insn)
     };
-    if *db > 0 {
-        todo!("temp databases not implemented yet");
-    }
+    let (conn, pager) = database_handles(program, pager, *db)?;
     match cookie {
         Cookie::UserVersion => {
-            header_accessor::set_user_version(pager, *value)?;
+            header_accessor::set_user_version(&pager, *value)?;
         }
         Cookie::LargestRootPageNumber => {
-            header_accessor::set_vacuum_mode_largest_root_page(pager, *value as u32)?;
+            header_accessor::set_vacuum_mode_largest_root_page(&pager, *value as u32)?;
         }
         Cookie::IncrementalVacuum => {
-            header_accessor::set_incremental_vacuum_enabled(pager, *value as u32)?;
+            header_accessor::set_incremental_vacuum_enabled(&pager, *value as u32)?;
         }
         Cookie::SchemaVersion => {
             // we update transaction state to indicate that the schema has changed
-            match program.connection.transaction_state.get() {
+            match conn.transaction_state.get() {
                 TransactionState::Write { schema_did_change } => {
-                    program.connection.transaction_state.set(TransactionState::Write { schema_did_change: true });
+                    conn.transaction_state.set(TransactionState::Write { schema_did_change: true });
                 },
                 TransactionState::Read => unreachable!("invalid transaction state for SetCookie: TransactionState::Read, should be write"),
                 TransactionState::None => unreachable!("invalid transaction state for SetCookie: TransactionState::None, should be write"),
             }
 
-            program.connection.schema.borrow_mut().schema_version = *value as u32;
-            header_accessor::set_schema_cookie(pager, *value as u32)?;
+            conn.schema_mut().schema_version = *value as u32;
+            header_accessor::set_schema_cookie(&pager, *value as u32)?;
         }
         cookie => todo!("{cookie:?} is not yet implement for SetCookie"),
     }
@@ -6203,6 +6758,9 @@ pub fn op_open_ephemeral(
                 CursorType::VirtualTable(_) => {
                     panic!("OpenEphemeral on virtual table cursor, use Insn::VOpen instead");
                 }
+                CursorType::HashTable(_) => {
+                    panic!("OpenEphemeral on hash table cursor");
+                }
             }
 
             state.pc += 1;
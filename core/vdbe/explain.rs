@@ -20,6 +20,7 @@ pub fn insn_to_str(
             CursorType::Pseudo(_) => "pseudo",
             CursorType::VirtualTable(virtual_table) => &virtual_table.name,
             CursorType::Sorter => "sorter",
+            CursorType::HashTable(index) => &index.name,
         }
     };
     let (opcode, p1, p2, p3, p4, p5, comment): (&str, i32, i32, i32, Value, u16, String) =
@@ -357,11 +358,12 @@ pub fn insn_to_str(
             Insn::OpenRead {
                 cursor_id,
                 root_page,
+                db,
             } => (
                 "OpenRead",
                 *cursor_id as i32,
                 *root_page as i32,
-                0,
+                *db as i32,
                 Value::build_text(""),
                 0,
                 {
@@ -539,6 +541,10 @@ pub fn insn_to_str(
                         let name = &index.columns.get(*column).unwrap().name;
                         Some(name)
                     }
+                    CursorType::HashTable(index) => {
+                        let name = &index.columns.get(*column).unwrap().name;
+                        Some(name)
+                    }
                     CursorType::Pseudo(_) => None,
                     CursorType::Sorter => None,
                     CursorType::VirtualTable(v) => v.columns.get(*column).unwrap().name.as_ref(),
@@ -623,10 +629,15 @@ pub fn insn_to_str(
             Insn::Halt {
                 err_code,
                 description,
+                on_error,
             } => (
                 "Halt",
                 *err_code as i32,
-                0,
+                if *err_code > 0 {
+                    on_error.bit_value() as i32
+                } else {
+                    0
+                },
                 0,
                 Value::build_text(description),
                 0,
@@ -645,15 +656,85 @@ pub fn insn_to_str(
                 0,
                 "".to_string(),
             ),
-            Insn::Transaction { write } => (
-                "Transaction",
+            Insn::FkCounter {
+                deferred,
+                increment_value,
+            } => (
+                "FkCounter",
+                *deferred as i32,
+                *increment_value as i32,
+                0,
+                Value::build_text(""),
                 0,
+                format!(
+                    "fk_{}_violations += {increment_value}",
+                    if *deferred { "deferred" } else { "statement" }
+                ),
+            ),
+            Insn::FkIfZero {
+                deferred,
+                target_pc,
+            } => (
+                "FkIfZero",
+                *deferred as i32,
+                target_pc.as_debug_int(),
+                0,
+                Value::build_text(""),
+                0,
+                format!(
+                    "if fk_{}_violations == 0 goto {}",
+                    if *deferred { "deferred" } else { "statement" },
+                    target_pc.as_debug_int()
+                ),
+            ),
+            Insn::FkCheck { release } => (
+                "FkCheck",
+                0,
+                0,
+                0,
+                Value::build_text(release.as_deref().unwrap_or("")),
+                0,
+                "".to_string(),
+            ),
+            Insn::Transaction { db, write } => (
+                "Transaction",
+                *db as i32,
                 *write as i32,
                 0,
                 Value::build_text(""),
                 0,
                 format!("write={write}"),
             ),
+            Insn::Attach { path, name } => (
+                "Attach",
+                *path as i32,
+                *name as i32,
+                0,
+                Value::build_text(""),
+                0,
+                format!("attach r[{path}] as r[{name}]"),
+            ),
+            Insn::Detach { name } => (
+                "Detach",
+                *name as i32,
+                0,
+                0,
+                Value::build_text(""),
+                0,
+                format!("detach r[{name}]"),
+            ),
+            Insn::Vacuum { db, into } => (
+                "Vacuum",
+                *db as i32,
+                into.map_or(0, |into| into as i32),
+                0,
+                Value::build_text(""),
+                0,
+                match into {
+                    Some(into) => format!("vacuum db[{db}] into r[{into}]"),
+                    None => format!("vacuum db[{db}]"),
+                },
+            ),
             Insn::Goto { target_pc } => (
                 "Goto",
                 0,
@@ -675,6 +756,22 @@ pub fn insn_to_str(
                 0,
                 "".to_string(),
             ),
+            Insn::Program {
+                sub_program,
+                args_reg,
+                num_args,
+                ignore_target,
+                skip_if_active,
+                description,
+            } => (
+                "Program",
+                *args_reg as i32,
+                ignore_target.as_debug_int(),
+                *sub_program as i32,
+                Value::build_text(description),
+                *skip_if_active as u16,
+                format!("args=r[{}..{}]", args_reg, args_reg + num_args),
+            ),
             Insn::Return {
                 return_reg,
                 can_fallthrough,
@@ -1026,6 +1123,69 @@ pub fn insn_to_str(
                 0,
                 "".to_string(),
             ),
+            Insn::HashOpen {
+                cursor_id,
+                key_len,
+                collations,
+                memory_budget,
+            } => (
+                "HashOpen",
+                *cursor_id as i32,
+                *key_len as i32,
+                0,
+                Value::build_text(format!(
+                    "k({key_len},{})",
+                    collations
+                        .iter()
+                        .map(|collation| collation.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )),
+                0,
+                format!("memory_budget={memory_budget}"),
+            ),
+            Insn::HashInsert {
+                cursor_id,
+                record_reg,
+            } => (
+                "HashInsert",
+                *cursor_id as i32,
+                *record_reg as i32,
+                0,
+                Value::build_text(""),
+                0,
+                format!("key=r[{record_reg}]"),
+            ),
+            Insn::HashProbe {
+                cursor_id,
+                key_reg,
+                num_keys,
+                pc_if_empty,
+            } => (
+                "HashProbe",
+                *cursor_id as i32,
+                pc_if_empty.as_debug_int(),
+                *key_reg as i32,
+                Value::build_text(""),
+                0,
+                format!(
+                    "key=r[{}..{}]",
+                    key_reg,
+                    key_reg + num_keys.saturating_sub(1)
+                ),
+            ),
+            Insn::HashNext {
+                cursor_id,
+                pc_if_next,
+            } => (
+                "HashNext",
+                *cursor_id as i32,
+                pc_if_next.as_debug_int(),
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
             Insn::Function {
                 constant_mask,
                 start_reg,
@@ -1214,7 +1374,7 @@ pub fn insn_to_str(
                 cursor_id,
                 root_page,
                 name,
-                ..
+                db,
             } => (
                 "OpenWrite",
                 *cursor_id as i32,
@@ -1222,7 +1382,7 @@ pub fn insn_to_str(
                     RegisterOrLiteral::Literal(i) => *i as _,
                     RegisterOrLiteral::Register(i) => *i as _,
                 },
-                0,
+                *db as i32,
                 Value::build_text(""),
                 0,
                 format!("root={root_page}; {name}"),
@@ -1287,6 +1447,24 @@ pub fn insn_to_str(
                 0,
                 format!("DROP INDEX {}", index.name),
             ),
+            Insn::DropTrigger { db, trigger_name } => (
+                "DropTrigger",
+                *db as i32,
+                0,
+                0,
+                Value::build_text(trigger_name),
+                0,
+                format!("DROP TRIGGER {trigger_name}"),
+            ),
+            Insn::DropView { db, view_name } => (
+                "DropView",
+                *db as i32,
+                0,
+                0,
+                Value::build_text(view_name),
+                0,
+                format!("DROP VIEW {view_name}"),
+            ),
             Insn::Close { cursor_id } => (
                 "Close",
                 *cursor_id as i32,
@@ -1326,6 +1504,15 @@ pub fn insn_to_str(
                 0,
                 where_clause.clone().unwrap_or("NULL".to_string()),
             ),
+            Insn::LoadAnalysis { db } => (
+                "LoadAnalysis",
+                *db as i32,
+                0,
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
             Insn::Prev {
                 cursor_id,
                 pc_if_prev,
@@ -1413,6 +1600,19 @@ pub fn insn_to_str(
                 format!("r[{dest}]=(r[{lhs}] || r[{rhs}])"),
             ),
             Insn::Noop => ("Noop", 0, 0, 0, Value::build_text(""), 0, String::new()),
+            Insn::Explain {
+                id,
+                parent_id,
+                detail,
+            } => (
+                "Explain",
+                *id as i32,
+                parent_id.unwrap_or(0) as i32,
+                0,
+                Value::build_text(detail),
+                0,
+                String::new(),
+            ),
             Insn::PageCount { db, dest } => (
                 "Pagecount",
                 *db as i32,
@@ -1457,6 +1657,15 @@ pub fn insn_to_str(
                 0,
                 format!("auto_commit={auto_commit}, rollback={rollback}"),
             ),
+            Insn::Savepoint { op, name } => (
+                "Savepoint",
+                *op as i32,
+                0,
+                0,
+                Value::build_text(name),
+                0,
+                format!("{op:?} {name}"),
+            ),
             Insn::OpenEphemeral {
                 cursor_id,
                 is_table,
@@ -0,0 +1,430 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{
+    io::{Buffer, Completion, CompletionType, File, OpenFlags, ReadCompletion, WriteCompletion},
+    translate::collate::CollationSeq,
+    types::{
+        compare_immutable, IOResult, ImmutableRecord, IndexKeySortOrder, RecordCursor, RefValue,
+        Value,
+    },
+    Result, IO,
+};
+
+/// The rows spilled to the temporary file are written in batches of this many bytes.
+const SPILL_BATCH_SIZE: usize = 64 * 1024;
+
+/// The hash table of a hash join, which holds the rows of the table on the build side of the join
+/// and finds the ones matching the key of a row of the tables on the probe side.
+///
+/// Each row is a record whose first `key_len` values are its key. Keys are compared like the `=`
+/// operator does, with the collating sequence of each key column, so a row with a NULL in its key
+/// matches nothing and is never inserted. Once the rows take more memory than the budget, the rows
+/// inserted next are written to a temporary file instead, and only their position in the file is
+/// kept in memory. Reading them back may then need IO.
+pub struct HashTable {
+    key_len: usize,
+    collations: Vec<CollationSeq>,
+    memory_budget: usize,
+    memory_used: usize,
+    io: Arc<dyn IO>,
+    rows: Vec<StoredRow>,
+    /// The positions in `rows` of the rows with a given hash of their key, in insertion order.
+    buckets: HashMap<u64, Vec<usize>>,
+    spill_file: Option<SpillFile>,
+    probe: Probe,
+    /// The row matching the probed key that the cursor is positioned on, if any.
+    current: Option<ImmutableRecord>,
+    null_flag: bool,
+}
+
+enum StoredRow {
+    InMemory(ImmutableRecord),
+    Spilled { offset: usize, len: usize },
+}
+
+struct SpillFile {
+    file: Arc<dyn File>,
+    path: String,
+    /// The rows that are not written to the file yet, which start at `written_len` in the file.
+    pending: Vec<u8>,
+    written_len: usize,
+    writes: Vec<Arc<Completion>>,
+}
+
+#[derive(Default)]
+struct Probe {
+    key: Option<ImmutableRecord>,
+    hash: u64,
+    /// The position in the bucket of `hash` of the next row to compare with the key.
+    next: usize,
+    /// The read of a spilled row that is compared with the key once it completes.
+    read: Option<Arc<Completion>>,
+}
+
+impl HashTable {
+    pub fn new(
+        key_len: usize,
+        collations: Vec<CollationSeq>,
+        memory_budget: usize,
+        io: Arc<dyn IO>,
+    ) -> Self {
+        Self {
+            key_len,
+            collations,
+            memory_budget,
+            memory_used: 0,
+            io,
+            rows: Vec::new(),
+            buckets: HashMap::new(),
+            spill_file: None,
+            probe: Probe::default(),
+            current: None,
+            null_flag: false,
+        }
+    }
+
+    /// Whether some rows were written to the temporary file.
+    pub fn has_spilled(&self) -> bool {
+        self.spill_file.is_some()
+    }
+
+    pub fn insert(&mut self, record: &ImmutableRecord) -> Result<()> {
+        let values = record.get_values();
+        let key = &values[..self.key_len];
+        if key.iter().any(|value| matches!(value, RefValue::Null)) {
+            return Ok(());
+        }
+        let hash = self.hash_key(key);
+        let payload = record.get_payload();
+        let row = if self.memory_used + payload.len() <= self.memory_budget {
+            self.memory_used += payload.len();
+            StoredRow::InMemory(record.clone())
+        } else {
+            self.spill(payload)?
+        };
+        self.buckets.entry(hash).or_default().push(self.rows.len());
+        self.rows.push(row);
+        Ok(())
+    }
+
+    /// Positions the cursor on the first row whose key equals `key`, and returns whether there is one.
+    pub fn probe(&mut self, key: ImmutableRecord) -> Result<IOResult<bool>> {
+        // The probe is resumed where it stopped if it is waiting for a row to be read back.
+        if self.probe.read.is_none() {
+            self.null_flag = false;
+            self.current = None;
+            let values = key.get_values();
+            self.probe = if values.iter().any(|value| matches!(value, RefValue::Null)) {
+                Probe::default()
+            } else {
+                Probe {
+                    hash: self.hash_key(&values),
+                    key: Some(key),
+                    next: 0,
+                    read: None,
+                }
+            };
+        }
+        self.next_match()
+    }
+
+    /// Positions the cursor on the next row whose key equals the probed key, and returns whether
+    /// there is one.
+    pub fn next(&mut self) -> Result<IOResult<bool>> {
+        self.next_match()
+    }
+
+    /// Returns the value of a column of the row the cursor is positioned on, where the columns
+    /// are the ones following the key.
+    pub fn column(&self, column: usize) -> Result<Value> {
+        match self.current.as_ref() {
+            Some(record) if !self.null_flag => {
+                Ok(record.get_value(self.key_len + column)?.to_owned())
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// Returns the rowid of the row the cursor is positioned on, which is its last column.
+    pub fn rowid(&self) -> Result<Option<i64>> {
+        let mut record_cursor = RecordCursor::new();
+        match self
+            .current
+            .as_ref()
+            .and_then(|record| record.last_value(&mut record_cursor))
+        {
+            Some(value) if !self.null_flag => match value? {
+                RefValue::Integer(rowid) => Ok(Some(rowid)),
+                _ => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    pub fn set_null_flag(&mut self, flag: bool) {
+        self.null_flag = flag;
+    }
+
+    pub fn get_null_flag(&self) -> bool {
+        self.null_flag
+    }
+
+    fn next_match(&mut self) -> Result<IOResult<bool>> {
+        loop {
+            if let Some(read) = self.probe.read.as_ref() {
+                if !read.is_completed() {
+                    return Ok(IOResult::IO);
+                }
+                let record = record_from_payload(read.as_read().buf().as_slice());
+                self.probe.read = None;
+                if self.key_matches(&record) {
+                    self.current = Some(record);
+                    return Ok(IOResult::Done(true));
+                }
+                continue;
+            }
+            let Some(&position) = self.probe.key.as_ref().and_then(|_| {
+                self.buckets
+                    .get(&self.probe.hash)
+                    .and_then(|bucket| bucket.get(self.probe.next))
+            }) else {
+                self.current = None;
+                return Ok(IOResult::Done(false));
+            };
+            let record = match &self.rows[position] {
+                StoredRow::InMemory(record) => record.clone(),
+                StoredRow::Spilled { offset, len } => {
+                    let spill_file = self.spill_file.as_mut().expect("rows were spilled");
+                    if *offset >= spill_file.written_len {
+                        let start = offset - spill_file.written_len;
+                        record_from_payload(&spill_file.pending[start..start + len])
+                    } else {
+                        // A row can only be read back once it is written.
+                        spill_file.writes.retain(|write| !write.is_completed());
+                        if !spill_file.writes.is_empty() {
+                            return Ok(IOResult::IO);
+                        }
+                        self.probe.read = Some(spill_file.read(*offset, *len)?);
+                        self.probe.next += 1;
+                        continue;
+                    }
+                }
+            };
+            self.probe.next += 1;
+            if self.key_matches(&record) {
+                self.current = Some(record);
+                return Ok(IOResult::Done(true));
+            }
+        }
+    }
+
+    fn key_matches(&self, record: &ImmutableRecord) -> bool {
+        let Some(key) = self.probe.key.as_ref() else {
+            return false;
+        };
+        let values = record.get_values();
+        compare_immutable(
+            &values[..self.key_len],
+            &key.get_values(),
+            IndexKeySortOrder::default(),
+            &self.collations,
+        )
+        .is_eq()
+    }
+
+    /// Hashes a key so that keys that compare equal have the same hash: integers and floats with
+    /// the same numeric value hash the same, and strings are hashed as their collating sequence
+    /// compares them.
+    fn hash_key(&self, key: &[RefValue]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (i, value) in key.iter().enumerate() {
+            match value {
+                RefValue::Null => 0u8.hash(&mut hasher),
+                RefValue::Integer(int) => {
+                    1u8.hash(&mut hasher);
+                    int.hash(&mut hasher);
+                }
+                RefValue::Float(float) => {
+                    if float.fract() == 0.0 && *float >= i64::MIN as f64 && *float < i64::MAX as f64
+                    {
+                        1u8.hash(&mut hasher);
+                        (*float as i64).hash(&mut hasher);
+                    } else {
+                        2u8.hash(&mut hasher);
+                        float.to_bits().hash(&mut hasher);
+                    }
+                }
+                RefValue::Text(text) => {
+                    3u8.hash(&mut hasher);
+                    let text = text.as_str();
+                    match self.collations.get(i).copied().unwrap_or_default() {
+                        CollationSeq::Binary => text.hash(&mut hasher),
+                        CollationSeq::NoCase => {
+                            for byte in text.bytes() {
+                                byte.to_ascii_lowercase().hash(&mut hasher);
+                            }
+                        }
+                        CollationSeq::Rtrim => text.trim_end().hash(&mut hasher),
+                    }
+                }
+                RefValue::Blob(blob) => {
+                    4u8.hash(&mut hasher);
+                    blob.to_slice().hash(&mut hasher);
+                }
+            }
+        }
+        hasher.finish()
+    }
+
+    fn spill(&mut self, payload: &[u8]) -> Result<StoredRow> {
+        if self.spill_file.is_none() {
+            let path = std::env::temp_dir()
+                .join(format!(
+                    "turso-hash-join-{:016x}",
+                    self.io.generate_random_number()
+                ))
+                .to_string_lossy()
+                .into_owned();
+            let file = self.io.open_file(&path, OpenFlags::Create, false)?;
+            self.spill_file = Some(SpillFile {
+                file,
+                path,
+                pending: Vec::with_capacity(SPILL_BATCH_SIZE),
+                written_len: 0,
+                writes: Vec::new(),
+            });
+        }
+        let spill_file = self.spill_file.as_mut().unwrap();
+        let offset = spill_file.written_len + spill_file.pending.len();
+        spill_file.pending.extend_from_slice(payload);
+        if spill_file.pending.len() >= SPILL_BATCH_SIZE {
+            spill_file.write_pending()?;
+        }
+        Ok(StoredRow::Spilled {
+            offset,
+            len: payload.len(),
+        })
+    }
+}
+
+impl SpillFile {
+    fn write_pending(&mut self) -> Result<()> {
+        let data = std::mem::replace(&mut self.pending, Vec::with_capacity(SPILL_BATCH_SIZE));
+        let len = data.len();
+        let buffer = Arc::new(RefCell::new(Buffer::new(Pin::new(data), Rc::new(|_| {}))));
+        let completion = Completion::new(CompletionType::Write(WriteCompletion::new(Box::new(
+            |_| {},
+        ))));
+        self.writes
+            .push(self.file.pwrite(self.written_len, buffer, completion)?);
+        self.written_len += len;
+        Ok(())
+    }
+
+    fn read(&self, offset: usize, len: usize) -> Result<Arc<Completion>> {
+        let buffer = Arc::new(RefCell::new(Buffer::allocate(len, Rc::new(|_| {}))));
+        let completion = Completion::new(CompletionType::Read(ReadCompletion::new(
+            buffer,
+            Box::new(|_, _| {}),
+        )));
+        self.file.pread(offset, completion)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        // The IO backend may not keep its files on disk, in which case there is nothing to remove.
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn record_from_payload(payload: &[u8]) -> ImmutableRecord {
+    let mut record = ImmutableRecord::new(payload.len());
+    record.start_serialization(payload);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryIO;
+
+    fn record(values: &[Value]) -> ImmutableRecord {
+        ImmutableRecord::from_values(values, values.len())
+    }
+
+    fn matches(table: &mut HashTable, key: &[Value]) -> Vec<i64> {
+        let mut found = Vec::new();
+        let mut has_row = match table.probe(record(key)).unwrap() {
+            IOResult::Done(has_row) => has_row,
+            IOResult::IO => unreachable!("memory IO completes immediately"),
+        };
+        while has_row {
+            found.push(table.rowid().unwrap().unwrap());
+            has_row = match table.next().unwrap() {
+                IOResult::Done(has_row) => has_row,
+                IOResult::IO => unreachable!("memory IO completes immediately"),
+            };
+        }
+        found
+    }
+
+    #[test]
+    fn test_hash_table_matches_keys_like_equality() {
+        let mut table = HashTable::new(
+            1,
+            vec![CollationSeq::NoCase],
+            usize::MAX,
+            Arc::new(MemoryIO::new()),
+        );
+        for (key, row) in [
+            (Value::build_text("a"), 1),
+            (Value::build_text("A"), 2),
+            (Value::Integer(1), 3),
+            (Value::Null, 4),
+            (Value::Float(1.0), 5),
+        ] {
+            table.insert(&record(&[key, Value::Integer(row)])).unwrap();
+        }
+        assert_eq!(matches(&mut table, &[Value::build_text("a")]), vec![1, 2]);
+        assert_eq!(matches(&mut table, &[Value::Float(1.0)]), vec![3, 5]);
+        assert!(matches(&mut table, &[Value::Null]).is_empty());
+        assert!(matches(&mut table, &[Value::build_text("b")]).is_empty());
+        assert!(!table.has_spilled());
+    }
+
+    #[test]
+    fn test_hash_table_spills_over_memory_budget() {
+        let mut table = HashTable::new(
+            1,
+            vec![CollationSeq::Binary],
+            1024,
+            Arc::new(MemoryIO::new()),
+        );
+        let row_count = 10_000;
+        for i in 0..row_count {
+            let key = Value::Integer(i % 100);
+            table
+                .insert(&record(&[
+                    key,
+                    Value::build_text("x".repeat(20)),
+                    Value::Integer(i),
+                ]))
+                .unwrap();
+        }
+        assert!(table.has_spilled());
+        for key in [0, 42, 99] {
+            let expected = (0..row_count)
+                .filter(|i| i % 100 == key)
+                .collect::<Vec<_>>();
+            assert_eq!(matches(&mut table, &[Value::Integer(key)]), expected);
+        }
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
     Value,
 };
 use turso_macros::Description;
-use turso_sqlite3_parser::ast::SortOrder;
+use turso_sqlite3_parser::ast::{ResolveType, SortOrder};
 
 /// Flags provided to comparison instructions (e.g. Eq, Ne) which determine behavior related to NULL values.
 #[derive(Clone, Copy, Debug, Default)]
@@ -109,6 +109,7 @@ pub struct InsertFlags(pub u8);
 
 impl InsertFlags {
     pub const UPDATE: u8 = 0x01; // Flag indicating this is part of an UPDATE statement
+    pub const EPHEMERAL: u8 = 0x02; // Flag indicating the target is an ephemeral table, so the insert is not counted as a change
 
     pub fn new() -> Self {
         InsertFlags(0)
@@ -126,6 +127,15 @@ impl InsertFlags {
         }
         self
     }
+
+    pub fn ephemeral(mut self, is_ephemeral: bool) -> Self {
+        if is_ephemeral {
+            self.0 |= InsertFlags::EPHEMERAL;
+        } else {
+            self.0 &= !InsertFlags::EPHEMERAL;
+        }
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -333,6 +343,8 @@ pub enum Insn {
     OpenRead {
         cursor_id: CursorID,
         root_page: PageIdx,
+        /// Index of the database the b-tree belongs to.
+        db: usize,
     },
 
     /// Open a cursor for a virtual table.
@@ -451,6 +463,8 @@ pub enum Insn {
     Halt {
         err_code: usize,
         description: String,
+        /// How much of the work of the statement is undone when halting with an error.
+        on_error: ResolveType,
     },
 
     /// Halt the program if P3 is null.
@@ -460,17 +474,64 @@ pub enum Insn {
         err_code: usize,     // p1
     },
 
-    /// Start a transaction.
+    /// Add `increment_value` to the number of foreign key constraint violations: those of the
+    /// transaction if `deferred`, checked at COMMIT, otherwise those of the statement, checked
+    /// when it halts.
+    FkCounter {
+        deferred: bool,       // P1
+        increment_value: i64, // P2
+    },
+
+    /// Jump to `target_pc` if there are no foreign key constraint violations, of the transaction
+    /// if `deferred`, otherwise of the statement.
+    FkIfZero {
+        deferred: bool,          // P1
+        target_pc: BranchOffset, // P2
+    },
+
+    /// Halt with an error, leaving the transaction open, if the deferred foreign key constraints
+    /// of the transaction are violated and the transaction is about to commit: at COMMIT, or when
+    /// releasing the savepoint named `release` if it started the transaction.
+    FkCheck {
+        release: Option<String>,
+    },
+
+    /// Start a transaction on the database at index `db`.
     Transaction {
+        db: usize,
         write: bool,
     },
 
+    /// Attach the database file named by r\[path\] under the schema name in r\[name\].
+    Attach {
+        path: usize,
+        name: usize,
+    },
+
+    /// Detach the database attached under the schema name in r\[name\].
+    Detach {
+        name: usize,
+    },
+
+    /// Rebuild database `db` to reclaim its free pages. If `into` is set, write the rebuilt
+    /// database to the new file named by r\[into\] instead, leaving `db` untouched.
+    Vacuum {
+        db: usize,
+        into: Option<usize>,
+    },
+
     /// Set database auto-commit mode and potentially rollback.
     AutoCommit {
         auto_commit: bool,
         rollback: bool,
     },
 
+    /// Open, release or roll back to a savepoint.
+    Savepoint {
+        op: SavepointOp,
+        name: String,
+    },
+
     /// Branch to the given PC.
     Goto {
         target_pc: BranchOffset,
@@ -482,6 +543,23 @@ pub enum Insn {
         return_reg: usize,
     },
 
+    /// Runs the sub-program of a trigger or of a foreign key action, see
+    /// [crate::vdbe::builder::ProgramBuilder::begin_sub_program], in a new frame with its own
+    /// registers and cursors. The `num_args` registers starting at `args_reg` are copied to the
+    /// registers of the frame starting at register 1.
+    /// If the sub-program halts with `RAISE(IGNORE)`, jumps to `ignore_target` once it returns.
+    /// With `skip_if_active`, nothing is run if the sub-program is already running in an outer
+    /// frame, i.e. triggers do not fire recursively.
+    Program {
+        sub_program: usize,
+        args_reg: usize,
+        num_args: usize,
+        ignore_target: BranchOffset,
+        skip_if_active: bool,
+        /// What the sub-program is for, shown by EXPLAIN.
+        description: String,
+    },
+
     /// Returns to the program counter stored in register 'return_reg'.
     /// If can_fallthrough is true, fall through to the next instruction
     /// if return_reg does not contain an integer value. Otherwise raise an error.
@@ -795,6 +873,8 @@ pub enum Insn {
         cursor_id: CursorID,
         root_page: RegisterOrLiteral<PageIdx>,
         name: String,
+        /// Index of the database the b-tree belongs to.
+        db: usize,
     },
 
     Copy {
@@ -841,6 +921,22 @@ pub enum Insn {
         index: Arc<Index>,
     },
 
+    ///  Drop a trigger
+    DropTrigger {
+        ///  The database within which this trigger needs to be dropped (P1).
+        db: usize,
+        //  The name of the trigger being dropped
+        trigger_name: String,
+    },
+
+    ///  Drop a view
+    DropView {
+        ///  The database within which this view needs to be dropped (P1).
+        db: usize,
+        //  The name of the view being dropped
+        view_name: String,
+    },
+
     /// Close a cursor.
     Close {
         cursor_id: CursorID,
@@ -859,6 +955,11 @@ pub enum Insn {
         where_clause: Option<String>,
     },
 
+    /// Read the sqlite_stat1 table of database P1 and load its content into the in-memory schema.
+    LoadAnalysis {
+        db: usize,
+    },
+
     /// Place the result of lhs >> rhs in dest register.
     ShiftRight {
         lhs: usize,
@@ -910,6 +1011,13 @@ pub enum Insn {
     },
     /// Do nothing. Continue downward to the next opcode.
     Noop,
+    /// A row of the EXPLAIN QUERY PLAN output, with id P1 and parent row P2, describing a part
+    /// of the plan of the statement. Does nothing when the program is run.
+    Explain {
+        id: usize,
+        parent_id: Option<usize>,
+        detail: String,
+    },
     /// Write the current number of pages in database P1 to memory cell P2.
     PageCount {
         db: usize,
@@ -938,6 +1046,33 @@ pub enum Insn {
     OpenAutoindex {
         cursor_id: usize,
     },
+    /// Open a new cursor P1 to the hash table of a hash join, whose rows start with a key of P2 columns.
+    /// Rows are written to a temporary file once the hash table uses more than memory_budget bytes.
+    HashOpen {
+        cursor_id: CursorID,
+        key_len: usize,
+        collations: Vec<CollationSeq>,
+        memory_budget: usize,
+    },
+    /// Insert the record in register P2 into the hash table P1. Records with a NULL in their key are skipped.
+    HashInsert {
+        cursor_id: CursorID,
+        record_reg: usize,
+    },
+    /// Position the hash table cursor P1 on the first row whose key equals the P3 registers starting at P2.
+    /// If there is no such row, jump to pc_if_empty.
+    HashProbe {
+        cursor_id: CursorID,
+        key_reg: usize,
+        num_keys: usize,
+        pc_if_empty: BranchOffset,
+    },
+    /// Advance the hash table cursor P1 to the next row whose key equals the probed key, and jump to
+    /// pc_if_next if there is one.
+    HashNext {
+        cursor_id: CursorID,
+        pc_if_next: BranchOffset,
+    },
     /// Fall through to the next instruction on the first invocation, otherwise jump to target_pc
     Once {
         target_pc_when_reentered: BranchOffset,
@@ -1006,6 +1141,8 @@ impl Insn {
             Insn::Multiply { .. } => execute::op_multiply,
             Insn::Divide { .. } => execute::op_divide,
             Insn::DropIndex { .. } => execute::op_drop_index,
+            Insn::DropTrigger { .. } => execute::op_drop_trigger,
+            Insn::DropView { .. } => execute::op_drop_view,
             Insn::Compare { .. } => execute::op_compare,
             Insn::BitAnd { .. } => execute::op_bit_and,
             Insn::BitOr { .. } => execute::op_bit_or,
@@ -1044,10 +1181,18 @@ impl Insn {
             Insn::Prev { .. } => execute::op_prev,
             Insn::Halt { .. } => execute::op_halt,
             Insn::HaltIfNull { .. } => execute::op_halt_if_null,
+            Insn::FkCounter { .. } => execute::op_fk_counter,
+            Insn::FkIfZero { .. } => execute::op_fk_if_zero,
+            Insn::FkCheck { .. } => execute::op_fk_check,
             Insn::Transaction { .. } => execute::op_transaction,
             Insn::AutoCommit { .. } => execute::op_auto_commit,
+            Insn::Attach { .. } => execute::op_attach,
+            Insn::Detach { .. } => execute::op_detach,
+            Insn::Vacuum { .. } => execute::op_vacuum,
+            Insn::Savepoint { .. } => execute::op_savepoint,
             Insn::Goto { .. } => execute::op_goto,
             Insn::Gosub { .. } => execute::op_gosub,
+            Insn::Program { .. } => execute::op_program,
             Insn::Return { .. } => execute::op_return,
             Insn::Integer { .. } => execute::op_integer,
             Insn::Real { .. } => execute::op_real,
@@ -1076,6 +1221,10 @@ impl Insn {
             Insn::SorterSort { .. } => execute::op_sorter_sort,
             Insn::SorterData { .. } => execute::op_sorter_data,
             Insn::SorterNext { .. } => execute::op_sorter_next,
+            Insn::HashOpen { .. } => execute::op_hash_open,
+            Insn::HashInsert { .. } => execute::op_hash_insert,
+            Insn::HashProbe { .. } => execute::op_hash_probe,
+            Insn::HashNext { .. } => execute::op_hash_next,
             Insn::Function { .. } => execute::op_function,
             Insn::InitCoroutine { .. } => execute::op_init_coroutine,
             Insn::EndCoroutine { .. } => execute::op_end_coroutine,
@@ -1099,6 +1248,7 @@ impl Insn {
             Insn::Close { .. } => execute::op_close,
             Insn::IsNull { .. } => execute::op_is_null,
             Insn::ParseSchema { .. } => execute::op_parse_schema,
+            Insn::LoadAnalysis { .. } => execute::op_load_analysis,
             Insn::ShiftRight { .. } => execute::op_shift_right,
             Insn::ShiftLeft { .. } => execute::op_shift_left,
             Insn::Variable { .. } => execute::op_variable,
@@ -1107,7 +1257,7 @@ impl Insn {
             Insn::Concat { .. } => execute::op_concat,
             Insn::And { .. } => execute::op_and,
             Insn::Or { .. } => execute::op_or,
-            Insn::Noop => execute::op_noop,
+            Insn::Noop | Insn::Explain { .. } => execute::op_noop,
             Insn::PageCount { .. } => execute::op_page_count,
             Insn::ReadCookie { .. } => execute::op_read_cookie,
             Insn::SetCookie { .. } => execute::op_set_cookie,
@@ -1122,6 +1272,17 @@ impl Insn {
     }
 }
 
+/// The operation performed by [Insn::Savepoint].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavepointOp {
+    /// `SAVEPOINT name`
+    Begin,
+    /// `RELEASE name`
+    Release,
+    /// `ROLLBACK TO name`
+    Rollback,
+}
+
 // TODO: Add remaining cookies.
 #[derive(Description, Debug, Clone, Copy)]
 pub enum Cookie {
@@ -20,12 +20,13 @@
 pub mod builder;
 pub mod execute;
 pub mod explain;
+pub mod hash_table;
 pub mod insn;
 pub mod likeop;
 pub mod sorter;
 
 use crate::{
-    error::LimboError,
+    error::{LimboError, SQLITE_ERROR},
     function::{AggFunc, FuncCtx},
     storage::sqlite3_ondisk::SmallVec,
     translate::plan::TableReferences,
@@ -44,10 +45,10 @@ use crate::{
 #[cfg(feature = "json")]
 use crate::json::JsonCacheCell;
 use crate::{Connection, MvStore, Result, TransactionState};
-use builder::CursorKey;
+use builder::{CursorKey, QueryMode};
 use execute::{
-    InsnFunction, InsnFunctionStepResult, OpIdxDeleteState, OpIntegrityCheckState,
-    OpOpenEphemeralState,
+    halt_error, halt_with_error, InsnFunction, InsnFunctionStepResult, OpIdxDeleteState,
+    OpIntegrityCheckState, OpOpenEphemeralState,
 };
 
 use regex::Regex;
@@ -59,6 +60,7 @@ use std::{
     sync::Arc,
 };
 use tracing::{instrument, Level};
+use turso_sqlite3_parser::ast;
 
 /// We use labels to indicate that we want to jump to whatever the instruction offset
 /// will be at runtime, because the offset cannot always be determined when the jump
@@ -247,6 +249,8 @@ pub struct ProgramState {
     interrupted: bool,
     parameters: HashMap<NonZero<usize>, Value>,
     commit_state: CommitState,
+    /// Number of violations of immediate foreign key constraints by the statement.
+    fk_immediate_violations: i64,
     #[cfg(feature = "json")]
     json_cache: JsonCacheCell,
     op_idx_delete_state: Option<OpIdxDeleteState>,
@@ -256,6 +260,39 @@ pub struct ProgramState {
     op_idx_insert_state: OpIdxInsertState,
     op_insert_state: OpInsertState,
     seek_state: OpSeekState,
+    /// The frames of the sub-programs that are running, innermost last, see [Insn::Program].
+    frames: Vec<Frame>,
+    /// The statement journals opened on the databases the statement writes to.
+    statement_journals: Vec<StatementJournal>,
+}
+
+/// Maximum number of nested frames of trigger and foreign key action sub-programs, like
+/// SQLite's default `SQLITE_MAX_TRIGGER_DEPTH`.
+pub const MAX_TRIGGER_DEPTH: usize = 1000;
+
+/// A sub-program of the statement running in its own registers and cursors.
+struct Frame {
+    /// Index of the sub-program in [Program::sub_programs].
+    sub_program: usize,
+    state: ProgramState,
+    /// Where the parent frame continues if the sub-program halts with `RAISE(IGNORE)`.
+    ignore_target: InsnReference,
+    /// `last_insert_rowid()` when the frame was entered, restored when it returns like in
+    /// SQLite, where inserts made by a trigger are only visible within the trigger.
+    last_insert_rowid: i64,
+}
+
+/// A savepoint opened on the pager of a database that a statement writes to in an explicit
+/// transaction, so that a failing statement undoes its own changes and the transaction goes on,
+/// see [Insn::Transaction].
+struct StatementJournal {
+    pager: Rc<Pager>,
+    /// Depth of the savepoint in the savepoints of the pager.
+    depth: usize,
+    /// The schema of the database before the statement, for the statements that change it.
+    schema: Option<(Arc<Connection>, crate::schema::Schema)>,
+    /// The deferred foreign key violations of the transaction before the statement.
+    deferred_fk_violations: i64,
 }
 
 impl ProgramState {
@@ -277,6 +314,7 @@ impl ProgramState {
             interrupted: false,
             parameters: HashMap::new(),
             commit_state: CommitState::Ready,
+            fk_immediate_violations: 0,
             #[cfg(feature = "json")]
             json_cache: JsonCacheCell::new(),
             op_idx_delete_state: None,
@@ -286,6 +324,8 @@ impl ProgramState {
             op_idx_insert_state: OpIdxInsertState::SeekIfUnique,
             op_insert_state: OpInsertState::Insert,
             seek_state: OpSeekState::Start,
+            frames: Vec::new(),
+            statement_journals: Vec::new(),
         }
     }
 
@@ -325,10 +365,49 @@ impl ProgramState {
         self.regex_cache.like.clear();
         self.interrupted = false;
         self.parameters.clear();
+        self.fk_immediate_violations = 0;
+        self.frames.clear();
+        self.statement_journals.clear();
         #[cfg(feature = "json")]
         self.json_cache.clear()
     }
 
+    /// Opens a statement journal on `pager`, the pager of the database of `conn`, unless the
+    /// statement already did. `depth` is the number of savepoints opened by the transaction on
+    /// `pager`: a journal left open by a statement that did not run to completion is dropped.
+    pub(crate) fn open_statement_journal(
+        &mut self,
+        program: &Program,
+        conn: &Arc<Connection>,
+        pager: &Rc<Pager>,
+        depth: usize,
+    ) {
+        if self
+            .statement_journals
+            .iter()
+            .any(|journal| Rc::ptr_eq(&journal.pager, pager))
+        {
+            return;
+        }
+        pager.release_savepoint(depth);
+        pager.open_savepoint();
+        self.statement_journals.push(StatementJournal {
+            pager: pager.clone(),
+            depth,
+            schema: program
+                .changes_schema
+                .then(|| (conn.clone(), conn.schema.borrow().clone())),
+            deferred_fk_violations: program.connection.deferred_fk_violations.get(),
+        });
+    }
+
+    /// Releases the statement journals, keeping the changes of the statement.
+    pub(crate) fn release_statement_journals(&mut self) {
+        for journal in self.statement_journals.drain(..) {
+            journal.pager.release_savepoint(journal.depth);
+        }
+    }
+
     pub fn get_cursor(&self, cursor_id: CursorID) -> std::cell::RefMut<Cursor> {
         let cursors = self.cursors.borrow_mut();
         std::cell::RefMut::map(cursors, |c| {
@@ -363,6 +442,7 @@ macro_rules! must_be_btree_cursor {
             CursorType::Pseudo(_) => panic!("{} on pseudo cursor", $insn_name),
             CursorType::Sorter => panic!("{} on sorter cursor", $insn_name),
             CursorType::VirtualTable(_) => panic!("{} on virtual table cursor", $insn_name),
+            CursorType::HashTable(_) => panic!("{} on hash table cursor", $insn_name),
         };
         cursor
     }};
@@ -379,6 +459,15 @@ pub struct Program {
     pub change_cnt_on: bool,
     pub result_columns: Vec<ResultSetColumn>,
     pub table_references: TableReferences,
+    pub query_mode: QueryMode,
+    /// Whether the statement may fail after writing, and so needs a statement journal in an
+    /// explicit transaction.
+    pub needs_statement_journal: bool,
+    /// Whether the statement changes the schema.
+    pub changes_schema: bool,
+    /// The sub-programs of the triggers and foreign key actions the statement runs, see
+    /// [Insn::Program]. Only the program of the statement itself has them.
+    pub sub_programs: Vec<Program>,
 }
 
 impl Program {
@@ -389,13 +478,13 @@ impl Program {
         mv_store: Option<Rc<MvStore>>,
         pager: Rc<Pager>,
     ) -> Result<StepResult> {
+        if self.query_mode == QueryMode::ExplainQueryPlan {
+            return Ok(self.step_explain_query_plan(state));
+        }
         loop {
             if self.connection.closed.get() {
                 // Connection is closed for whatever reason, rollback the transaction.
-                let state = self.connection.transaction_state.get();
-                if let TransactionState::Write { schema_did_change } = state {
-                    pager.rollback(schema_did_change, &self.connection)?
-                }
+                self.rollback_write_txns(&pager)?;
                 return Err(LimboError::InternalError("Connection closed".to_string()));
             }
             if state.is_interrupted() {
@@ -403,13 +492,55 @@ impl Program {
             }
             // invalidate row
             let _ = state.result_row.take();
-            let (insn, insn_function) = &self.insns[state.pc as usize];
-            trace_insn(self, state.pc as InsnReference, insn);
-            let res = insn_function(self, state, insn, &pager, mv_store.as_ref());
-            if res.is_err() {
-                let state = self.connection.transaction_state.get();
-                if let TransactionState::Write { schema_did_change } = state {
-                    pager.rollback(schema_did_change, &self.connection)?
+            let in_frame = !state.frames.is_empty();
+            let program = self.frame_program(state);
+            let pc = self.frame_state(state).pc;
+            let (insn, insn_function) = &program.insns[pc as usize];
+            trace_insn(program, pc as InsnReference, insn);
+            let res = match insn {
+                Insn::Program { .. } => self.enter_frame(state, insn, &pager, mv_store.as_ref()),
+                // A sub-program halting returns to its parent, errors end the whole statement
+                Insn::Halt {
+                    err_code: 0,
+                    on_error,
+                    ..
+                } if in_frame => {
+                    self.leave_frame(state, *on_error == ast::ResolveType::Ignore);
+                    Ok(InsnFunctionStepResult::Step)
+                }
+                Insn::Halt {
+                    err_code,
+                    description,
+                    on_error,
+                } if in_frame => halt_with_error(
+                    self,
+                    state,
+                    &pager,
+                    mv_store.as_ref(),
+                    halt_error(*err_code, description),
+                    *on_error,
+                ),
+                _ => insn_function(
+                    program,
+                    self.frame_state(state),
+                    insn,
+                    &pager,
+                    mv_store.as_ref(),
+                ),
+            };
+            // A constraint failing with FAIL keeps the changes made so far, see `halt_with_error`,
+            // and a COMMIT failing on deferred foreign key violations leaves the transaction open
+            let keep_changes = matches!(
+                insn,
+                Insn::Halt {
+                    on_error: ast::ResolveType::Fail,
+                    ..
+                } | Insn::FkCheck { .. }
+            );
+            if let Err(err) = &res {
+                if !keep_changes && !self.rollback_statement(state, err)? {
+                    state.statement_journals.clear();
+                    self.rollback_write_txns(&pager)?;
                 }
             }
             match res? {
@@ -423,6 +554,173 @@ impl Program {
         }
     }
 
+    /// Returns the program running in the innermost frame.
+    fn frame_program(&self, state: &ProgramState) -> &Program {
+        match state.frames.last() {
+            Some(frame) => &self.sub_programs[frame.sub_program],
+            None => self,
+        }
+    }
+
+    /// Returns the state of the program running in the innermost frame.
+    fn frame_state<'a>(&self, state: &'a mut ProgramState) -> &'a mut ProgramState {
+        if state.frames.is_empty() {
+            return state;
+        }
+        &mut state.frames.last_mut().unwrap().state
+    }
+
+    /// Runs [Insn::Program]: enters a new frame running the sub-program.
+    fn enter_frame(
+        &self,
+        state: &mut ProgramState,
+        insn: &Insn,
+        pager: &Rc<Pager>,
+        mv_store: Option<&Rc<MvStore>>,
+    ) -> Result<InsnFunctionStepResult> {
+        let Insn::Program {
+            sub_program,
+            args_reg,
+            num_args,
+            ignore_target,
+            skip_if_active,
+            ..
+        } = insn
+        else {
+            unreachable!("unexpected Insn {:?}", insn)
+        };
+        if *skip_if_active
+            && state
+                .frames
+                .iter()
+                .any(|frame| frame.sub_program == *sub_program)
+        {
+            self.frame_state(state).pc += 1;
+            return Ok(InsnFunctionStepResult::Step);
+        }
+        if state.frames.len() >= MAX_TRIGGER_DEPTH {
+            return halt_with_error(
+                self,
+                state,
+                pager,
+                mv_store,
+                halt_error(SQLITE_ERROR, "too many levels of trigger recursion"),
+                ast::ResolveType::Abort,
+            );
+        }
+        let program = &self.sub_programs[*sub_program];
+        let mut frame_state = ProgramState::new(program.max_registers, program.cursor_ref.len());
+        let parent_state = self.frame_state(state);
+        frame_state.registers[1..=*num_args]
+            .clone_from_slice(&parent_state.registers[*args_reg..*args_reg + *num_args]);
+        frame_state.mv_tx_id = parent_state.mv_tx_id;
+        // Violations of immediate foreign key constraints are counted for the whole statement
+        frame_state.fk_immediate_violations =
+            std::mem::take(&mut parent_state.fk_immediate_violations);
+        state.frames.push(Frame {
+            sub_program: *sub_program,
+            state: frame_state,
+            ignore_target: ignore_target.as_offset_int(),
+            last_insert_rowid: self.connection.last_insert_rowid(),
+        });
+        Ok(InsnFunctionStepResult::Step)
+    }
+
+    /// Returns from the innermost frame to its parent, which continues after the
+    /// [Insn::Program] that entered it, or at its `ignore_target` if it was `ignored`.
+    fn leave_frame(&self, state: &mut ProgramState, ignored: bool) {
+        let frame = state.frames.pop().expect("a frame is running");
+        self.connection.update_last_rowid(frame.last_insert_rowid);
+        let parent_state = self.frame_state(state);
+        parent_state.fk_immediate_violations = frame.state.fk_immediate_violations;
+        parent_state.pc = if ignored {
+            frame.ignore_target
+        } else {
+            parent_state.pc + 1
+        };
+    }
+
+    /// Returns the next row of the EXPLAIN QUERY PLAN output, i.e. the next [Insn::Explain] of
+    /// the program, as (id, parent, notused, detail). The program itself is not run.
+    fn step_explain_query_plan(&self, state: &mut ProgramState) -> StepResult {
+        let _ = state.result_row.take();
+        while let Some((insn, _)) = self.insns.get(state.pc as usize) {
+            state.pc += 1;
+            let Insn::Explain {
+                id,
+                parent_id,
+                detail,
+            } = insn
+            else {
+                continue;
+            };
+            state.registers[0] = Register::Value(Value::Integer(*id as i64));
+            state.registers[1] = Register::Value(Value::Integer(parent_id.unwrap_or(0) as i64));
+            state.registers[2] = Register::Value(Value::Integer(0));
+            state.registers[3] = Register::Value(Value::build_text(detail));
+            state.result_row = Some(Row {
+                values: &state.registers[0] as *const Register,
+                count: 4,
+            });
+            return StepResult::Row;
+        }
+        StepResult::Done
+    }
+
+    /// Undoes the changes of a statement that failed with `err` in an explicit transaction, which
+    /// goes on. Returns false if the whole transaction must be rolled back instead: when the
+    /// statement has no journal, or the error is not a failure of the statement alone.
+    fn rollback_statement(&self, state: &mut ProgramState, err: &LimboError) -> Result<bool> {
+        let statement_failed = matches!(
+            err,
+            LimboError::Constraint(_)
+                | LimboError::IntegerOverflow
+                | LimboError::ParseError(_)
+                | LimboError::ConversionError(_)
+                | LimboError::InvalidArgument(_)
+                | LimboError::InvalidDate(_)
+                | LimboError::InvalidTime(_)
+                | LimboError::InvalidModifier(_)
+                | LimboError::InvalidFormatter(_)
+                | LimboError::ExtensionError(_)
+        );
+        // A constraint resolved with ROLLBACK ends the transaction
+        if state.statement_journals.is_empty()
+            || !statement_failed
+            || self.connection.auto_commit.get()
+        {
+            return Ok(false);
+        }
+        for journal in state.statement_journals.drain(..).rev() {
+            journal.pager.rollback_to_savepoint(journal.depth)?;
+            journal.pager.release_savepoint(journal.depth);
+            if let Some((conn, schema)) = journal.schema {
+                *conn.schema_mut() = schema;
+            }
+            self.connection
+                .deferred_fk_violations
+                .set(journal.deferred_fk_violations);
+        }
+        Ok(true)
+    }
+
+    /// Rolls back the pending changes of the write transactions of the connection and of its
+    /// temp and attached databases.
+    fn rollback_write_txns(&self, pager: &Rc<Pager>) -> Result<()> {
+        self.connection.deferred_fk_violations.set(0);
+        for conn in self.connection.internal_connections() {
+            if let TransactionState::Write { schema_did_change } = conn.transaction_state.get() {
+                conn.pager.borrow().rollback(schema_did_change, &conn)?;
+            }
+        }
+        if let TransactionState::Write { schema_did_change } =
+            self.connection.transaction_state.get()
+        {
+            pager.rollback(schema_did_change, &self.connection)?;
+        }
+        Ok(())
+    }
+
     #[instrument(skip_all, level = Level::INFO)]
     pub fn commit_txn(
         &self,
@@ -450,20 +748,30 @@ impl Program {
                 auto_commit,
                 program_state.commit_state
             );
-            if program_state.commit_state == CommitState::Committing {
-                let TransactionState::Write { schema_did_change } =
-                    connection.transaction_state.get()
-                else {
-                    unreachable!("invalid state for write commit step")
-                };
-                self.step_end_write_txn(
-                    &pager,
-                    &mut program_state.commit_state,
-                    &connection,
-                    rollback,
-                    schema_did_change,
-                )
-            } else if auto_commit {
+            if program_state.commit_state == CommitState::Committing || auto_commit {
+                // End the transactions of the temp and attached databases first. The ones that
+                // are already done are in the None state when resuming after I/O.
+                for conn in connection.internal_connections() {
+                    let internal_pager = conn.pager.borrow().clone();
+                    match conn.transaction_state.get() {
+                        TransactionState::Write { schema_did_change } => {
+                            if let StepResult::IO = self.step_end_write_txn(
+                                &internal_pager,
+                                &mut program_state.commit_state,
+                                &conn,
+                                rollback,
+                                schema_did_change,
+                            )? {
+                                return Ok(StepResult::IO);
+                            }
+                        }
+                        TransactionState::Read => {
+                            conn.transaction_state.replace(TransactionState::None);
+                            internal_pager.end_read_tx()?;
+                        }
+                        TransactionState::None => {}
+                    }
+                }
                 let current_state = connection.transaction_state.get();
                 tracing::trace!("Auto-commit state: {:?}", current_state);
                 match current_state {
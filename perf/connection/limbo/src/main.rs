@@ -25,7 +25,7 @@ fn main() {
         let start = Instant::now();
         
         // Open connection to database and prepare a statement
-        let db = Database::open_file(io.clone(), &opts.database, false, false).unwrap();
+        let db = Database::open_file(io.clone(), &opts.database, false, true).unwrap();
         let conn = db.connect().unwrap();
         let _stmt = conn.prepare("SELECT name FROM table_0 WHERE id = ?").unwrap();
         
@@ -681,7 +681,7 @@ fn reopen_database(env: &mut SimulatorEnv) {
         env.io.clone(),
         &db_path,
         env.opts.experimental_mvcc,
-        env.opts.experimental_indexes,
+        true,
     ) {
         Ok(db) => db,
         Err(e) => {
@@ -96,7 +96,11 @@ pub struct SimulatorCLI {
     pub latency_probability: usize,
     #[clap(long, help = "Enable experimental MVCC feature")]
     pub experimental_mvcc: bool,
-    #[clap(long, help = "Enable experimental indexing feature")]
+    #[clap(
+        long,
+        hide = true,
+        help = "Deprecated and ignored: indexes are always enabled"
+    )]
     pub experimental_indexes: bool,
 }
 
@@ -123,7 +123,6 @@ impl SimulatorEnv {
             max_time_simulation: cli_opts.maximum_time,
             disable_reopen_database: cli_opts.disable_reopen_database,
             experimental_mvcc: cli_opts.experimental_mvcc,
-            experimental_indexes: cli_opts.experimental_indexes,
         };
 
         let io =
@@ -143,7 +142,7 @@ impl SimulatorEnv {
             io.clone(),
             db_path.to_str().unwrap(),
             opts.experimental_mvcc,
-            opts.experimental_indexes,
+            true,
         ) {
             Ok(db) => db,
             Err(e) => {
@@ -250,5 +249,4 @@ pub(crate) struct SimulatorOpts {
     pub(crate) max_time_simulation: usize,
 
     pub(crate) experimental_mvcc: bool,
-    pub(crate) experimental_indexes: bool,
 }
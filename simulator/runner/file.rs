@@ -209,6 +209,15 @@ impl File for SimulatorFile {
     fn size(&self) -> Result<u64> {
         self.inner.size()
     }
+
+    fn truncate(&self, len: u64) -> Result<()> {
+        if self.fault.get() {
+            return Err(turso_core::LimboError::InternalError(
+                FAULT_ERROR_MSG.into(),
+            ));
+        }
+        self.inner.truncate(len)
+    }
 }
 
 impl Drop for SimulatorFile {
@@ -19,11 +19,11 @@ unsafe impl Send for TempDatabase {}
 
 #[allow(dead_code, clippy::arc_with_non_send_sync)]
 impl TempDatabase {
-    pub fn new_empty(enable_indexes: bool) -> Self {
-        Self::new(&format!("test-{}.db", rng().next_u32()), enable_indexes)
+    pub fn new_empty() -> Self {
+        Self::new(&format!("test-{}.db", rng().next_u32()))
     }
 
-    pub fn new(db_name: &str, enable_indexes: bool) -> Self {
+    pub fn new(db_name: &str) -> Self {
         let mut path = TempDir::new().unwrap().keep();
         path.push(db_name);
         let io: Arc<dyn IO + Send> = Arc::new(turso_core::PlatformIO::new().unwrap());
@@ -32,32 +32,24 @@ impl TempDatabase {
             path.to_str().unwrap(),
             turso_core::OpenFlags::default(),
             false,
-            enable_indexes,
+            true,
         )
         .unwrap();
         Self { path, io, db }
     }
 
-    pub fn new_with_existent(db_path: &Path, enable_indexes: bool) -> Self {
-        Self::new_with_existent_with_flags(
-            db_path,
-            turso_core::OpenFlags::default(),
-            enable_indexes,
-        )
+    pub fn new_with_existent(db_path: &Path) -> Self {
+        Self::new_with_existent_with_flags(db_path, turso_core::OpenFlags::default())
     }
 
-    pub fn new_with_existent_with_flags(
-        db_path: &Path,
-        flags: turso_core::OpenFlags,
-        enable_indexes: bool,
-    ) -> Self {
+    pub fn new_with_existent_with_flags(db_path: &Path, flags: turso_core::OpenFlags) -> Self {
         let io: Arc<dyn IO + Send> = Arc::new(turso_core::PlatformIO::new().unwrap());
         let db = Database::open_file_with_flags(
             io.clone(),
             db_path.to_str().unwrap(),
             flags,
             false,
-            enable_indexes,
+            true,
         )
         .unwrap();
         Self {
@@ -67,7 +59,7 @@ impl TempDatabase {
         }
     }
 
-    pub fn new_with_rusqlite(table_sql: &str, enable_indexes: bool) -> Self {
+    pub fn new_with_rusqlite(table_sql: &str) -> Self {
         let _ = tracing_subscriber::fmt()
             .with_max_level(tracing::Level::TRACE)
             .finish();
@@ -86,7 +78,7 @@ impl TempDatabase {
             path.to_str().unwrap(),
             turso_core::OpenFlags::default(),
             false,
-            enable_indexes,
+            true,
         )
         .unwrap();
 
@@ -101,15 +93,9 @@ impl TempDatabase {
         conn
     }
 
-    pub fn limbo_database(&self, enable_indexes: bool) -> Arc<turso_core::Database> {
+    pub fn limbo_database(&self) -> Arc<turso_core::Database> {
         log::debug!("conneting to limbo");
-        Database::open_file(
-            self.io.clone(),
-            self.path.to_str().unwrap(),
-            false,
-            enable_indexes,
-        )
-        .unwrap()
+        Database::open_file(self.io.clone(), self.path.to_str().unwrap(), false, true).unwrap()
     }
 }
 
@@ -254,7 +240,6 @@ mod tests {
         let _ = env_logger::try_init();
         let tmp_db = TempDatabase::new_with_rusqlite(
             "create table test (foo integer, bar integer, baz integer);",
-            false,
         );
         let conn = tmp_db.connect_limbo();
 
@@ -292,11 +277,8 @@ mod tests {
     fn test_limbo_open_read_only() -> anyhow::Result<()> {
         let path = TempDir::new().unwrap().keep().join("temp_read_only");
         {
-            let db = TempDatabase::new_with_existent_with_flags(
-                &path,
-                turso_core::OpenFlags::default(),
-                false,
-            );
+            let db =
+                TempDatabase::new_with_existent_with_flags(&path, turso_core::OpenFlags::default());
             let conn = db.connect_limbo();
             let ret = limbo_exec_rows(&db, &conn, "CREATE table t(a)");
             assert!(ret.is_empty(), "{ret:?}");
@@ -308,7 +290,6 @@ mod tests {
             let db = TempDatabase::new_with_existent_with_flags(
                 &path,
                 turso_core::OpenFlags::default() | turso_core::OpenFlags::ReadOnly,
-                false,
             );
             let conn = db.connect_limbo();
             let ret = limbo_exec_rows(&db, &conn, "SELECT * from t");
@@ -324,7 +305,7 @@ mod tests {
     fn test_unique_index_ordering() -> anyhow::Result<()> {
         use rand::Rng;
 
-        let db = TempDatabase::new_empty(true);
+        let db = TempDatabase::new_empty();
         let conn = db.connect_limbo();
 
         let _ = limbo_exec_rows(&db, &conn, "CREATE TABLE t(x INTEGER UNIQUE)");
@@ -365,7 +346,7 @@ mod tests {
     #[test]
     fn test_large_unique_blobs() -> anyhow::Result<()> {
         let path = TempDir::new().unwrap().keep().join("temp_read_only");
-        let db = TempDatabase::new_with_existent(&path, true);
+        let db = TempDatabase::new_with_existent(&path);
         let conn = db.connect_limbo();
 
         let _ = limbo_exec_rows(&db, &conn, "CREATE TABLE t(x BLOB UNIQUE)");
@@ -16,7 +16,7 @@ fn replace_column_with_null(rows: Vec<Vec<Value>>, column: usize) -> Vec<Vec<Val
 
 #[test]
 fn test_cdc_simple_id() {
-    let db = TempDatabase::new_empty(false);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("PRAGMA unstable_capture_data_changes_conn('id')")
         .unwrap();
@@ -76,7 +76,7 @@ fn record<const N: usize>(values: [Value; N]) -> Vec<u8> {
 
 #[test]
 fn test_cdc_simple_before() {
-    let db = TempDatabase::new_empty(false);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("PRAGMA unstable_capture_data_changes_conn('before')")
         .unwrap();
@@ -142,7 +142,7 @@ fn test_cdc_simple_before() {
 
 #[test]
 fn test_cdc_simple_after() {
-    let db = TempDatabase::new_empty(false);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("PRAGMA unstable_capture_data_changes_conn('after')")
         .unwrap();
@@ -208,7 +208,7 @@ fn test_cdc_simple_after() {
 
 #[test]
 fn test_cdc_simple_full() {
-    let db = TempDatabase::new_empty(false);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("PRAGMA unstable_capture_data_changes_conn('full')")
         .unwrap();
@@ -274,7 +274,7 @@ fn test_cdc_simple_full() {
 
 #[test]
 fn test_cdc_crud() {
-    let db = TempDatabase::new_empty(false);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("PRAGMA unstable_capture_data_changes_conn('id')")
         .unwrap();
@@ -386,7 +386,7 @@ fn test_cdc_crud() {
 
 #[test]
 fn test_cdc_failed_op() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("PRAGMA unstable_capture_data_changes_conn('id')")
         .unwrap();
@@ -456,7 +456,7 @@ fn test_cdc_failed_op() {
 
 #[test]
 fn test_cdc_uncaptured_connection() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn1 = db.connect_limbo();
     conn1
         .execute("CREATE TABLE t(x INTEGER PRIMARY KEY, y UNIQUE)")
@@ -533,7 +533,7 @@ fn test_cdc_uncaptured_connection() {
 
 #[test]
 fn test_cdc_custom_table() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn1 = db.connect_limbo();
     conn1
         .execute("CREATE TABLE t(x INTEGER PRIMARY KEY, y UNIQUE)")
@@ -580,7 +580,7 @@ fn test_cdc_custom_table() {
 
 #[test]
 fn test_cdc_ignore_changes_in_cdc_table() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn1 = db.connect_limbo();
     conn1
         .execute("CREATE TABLE t(x INTEGER PRIMARY KEY, y UNIQUE)")
@@ -619,7 +619,7 @@ fn test_cdc_ignore_changes_in_cdc_table() {
 
 #[test]
 fn test_cdc_transaction() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn1 = db.connect_limbo();
     conn1
         .execute("CREATE TABLE t(x INTEGER PRIMARY KEY, y UNIQUE)")
@@ -697,7 +697,7 @@ fn test_cdc_transaction() {
 
 #[test]
 fn test_cdc_independent_connections() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn1 = db.connect_limbo();
     let conn2 = db.connect_limbo();
     conn1
@@ -751,7 +751,7 @@ fn test_cdc_independent_connections() {
 
 #[test]
 fn test_cdc_independent_connections_different_cdc_not_ignore() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn1 = db.connect_limbo();
     let conn2 = db.connect_limbo();
     conn1
@@ -837,7 +837,7 @@ fn test_cdc_independent_connections_different_cdc_not_ignore() {
 
 #[test]
 fn test_cdc_table_columns() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     conn.execute("CREATE TABLE t(a INTEGER PRIMARY KEY, b, c UNIQUE)")
         .unwrap();
@@ -853,7 +853,7 @@ fn test_cdc_table_columns() {
 
 #[test]
 fn test_cdc_bin_record() {
-    let db = TempDatabase::new_empty(true);
+    let db = TempDatabase::new_empty();
     let conn = db.connect_limbo();
     let record = record([
         Value::Null,
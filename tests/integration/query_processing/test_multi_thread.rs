@@ -2,7 +2,7 @@ use crate::common::TempDatabase;
 
 #[test]
 fn test_schema_change() {
-    let tmp_db = TempDatabase::new_empty(false);
+    let tmp_db = TempDatabase::new_empty();
     let conn1 = tmp_db.connect_limbo();
     conn1.execute("CREATE TABLE t(x, y, z)").unwrap();
     conn1
@@ -1,5 +1,8 @@
 use crate::common::{self, maybe_setup_tracing};
-use crate::common::{compare_string, do_flush, TempDatabase};
+use crate::common::{
+    compare_string, do_flush, limbo_exec_rows, limbo_exec_rows_error, sqlite_exec_rows,
+    TempDatabase,
+};
 use log::debug;
 use std::io::{Read, Seek, Write};
 use std::sync::Arc;
@@ -24,10 +27,8 @@ macro_rules! change_state {
 #[ignore]
 fn test_simple_overflow_page() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db = TempDatabase::new_with_rusqlite(
-        "CREATE TABLE test (x INTEGER PRIMARY KEY, t TEXT);",
-        false,
-    );
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY, t TEXT);");
     let conn = tmp_db.connect_limbo();
 
     let mut huge_text = String::new();
@@ -88,10 +89,8 @@ fn test_simple_overflow_page() -> anyhow::Result<()> {
 fn test_sequential_overflow_page() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
     maybe_setup_tracing();
-    let tmp_db = TempDatabase::new_with_rusqlite(
-        "CREATE TABLE test (x INTEGER PRIMARY KEY, t TEXT);",
-        false,
-    );
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY, t TEXT);");
     let conn = tmp_db.connect_limbo();
     let iterations = 10_usize;
 
@@ -160,8 +159,7 @@ fn test_sequential_write() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
     maybe_setup_tracing();
 
-    let tmp_db =
-        TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY);", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY);");
     let conn = tmp_db.connect_limbo();
 
     let list_query = "SELECT * FROM test";
@@ -196,7 +194,7 @@ fn test_sequential_write() -> anyhow::Result<()> {
 /// https://github.com/tursodatabase/turso/pull/679
 fn test_regression_multi_row_insert() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x REAL);", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x REAL);");
     let conn = tmp_db.connect_limbo();
 
     let insert_query = "INSERT INTO test VALUES (-2), (-3), (-1)";
@@ -229,7 +227,7 @@ fn test_regression_multi_row_insert() -> anyhow::Result<()> {
 #[test]
 fn test_statement_reset() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db = TempDatabase::new_with_rusqlite("create table test (i integer);", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("create table test (i integer);");
     let conn = tmp_db.connect_limbo();
 
     conn.execute("insert into test values (1)")?;
@@ -276,8 +274,7 @@ fn test_statement_reset() -> anyhow::Result<()> {
 #[ignore]
 fn test_wal_checkpoint() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db =
-        TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY);", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY);");
     // threshold is 1000 by default
     let iterations = 1001_usize;
     let conn = tmp_db.connect_limbo();
@@ -304,8 +301,7 @@ fn test_wal_checkpoint() -> anyhow::Result<()> {
 #[test]
 fn test_wal_restart() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db =
-        TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY);", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x INTEGER PRIMARY KEY);");
     // threshold is 1000 by default
 
     fn insert(i: usize, conn: &Arc<Connection>, tmp_db: &TempDatabase) -> anyhow::Result<()> {
@@ -350,7 +346,7 @@ fn test_wal_restart() -> anyhow::Result<()> {
 #[test]
 fn test_insert_after_big_blob() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE temp (t1 BLOB, t2 INTEGER)", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE temp (t1 BLOB, t2 INTEGER)");
     let conn = tmp_db.connect_limbo();
 
     conn.execute("insert into temp(t1) values (zeroblob (262144))")?;
@@ -366,7 +362,7 @@ fn test_write_delete_with_index() -> anyhow::Result<()> {
 
     maybe_setup_tracing();
 
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x PRIMARY KEY);", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x PRIMARY KEY);");
     let conn = tmp_db.connect_limbo();
 
     let list_query = "SELECT * FROM test";
@@ -420,8 +416,7 @@ fn test_update_with_index() -> anyhow::Result<()> {
 
     maybe_setup_tracing();
 
-    let tmp_db =
-        TempDatabase::new_with_rusqlite("CREATE TABLE test (x REAL PRIMARY KEY, y TEXT);", true);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE test (x REAL PRIMARY KEY, y TEXT);");
     let conn = tmp_db.connect_limbo();
 
     run_query(&tmp_db, &conn, "INSERT INTO test VALUES (1.0, 'foo')")?;
@@ -457,7 +452,7 @@ fn test_delete_with_index() -> anyhow::Result<()> {
 
     maybe_setup_tracing();
 
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x UNIQUE)", true);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x UNIQUE)");
     let conn = tmp_db.connect_limbo();
 
     run_query(&tmp_db, &conn, "INSERT INTO t VALUES (1), (2)")?;
@@ -470,10 +465,64 @@ fn test_delete_with_index() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_insert_delete_with_text_and_blob_indexes() -> anyhow::Result<()> {
+    use rusqlite::types::Value;
+
+    maybe_setup_tracing();
+
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE t(id INTEGER PRIMARY KEY, s TEXT, b BLOB)");
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE INDEX ts ON t(s); CREATE INDEX tb ON t(b); CREATE INDEX tsb ON t(s, b);")?;
+
+    // Keys of very different sizes, some of them spilling to overflow pages, make the balancing
+    // of the index btrees move cells of interior pages around.
+    for batch in 0..5 {
+        conn.execute("BEGIN")?;
+        for i in batch * 200..(batch + 1) * 200 {
+            let s = format!(
+                "k{:06}{}",
+                (i * 7919) % 1000003,
+                "x".repeat(1 + (i * 37) % 900)
+            );
+            let b = format!("{:02x}", (i * 13) % 256).repeat(1 + (i * 53) % 1200);
+            conn.execute(format!("INSERT INTO t(s, b) VALUES ('{s}', X'{b}')"))?;
+        }
+        conn.execute("COMMIT")?;
+    }
+    conn.execute("DELETE FROM t WHERE id % 3 = 0")?;
+    conn.execute("DELETE FROM t WHERE s > 'k5'")?;
+    conn.execute("UPDATE t SET s = s || 'y' WHERE id % 7 = 1")?;
+    conn.execute("DELETE FROM t WHERE b < X'40'")?;
+
+    let expected = vec![vec![Value::Text("ok".to_string())]];
+    assert_eq!(
+        limbo_exec_rows(&tmp_db, &conn, "PRAGMA integrity_check"),
+        expected
+    );
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path)?;
+    assert_eq!(
+        sqlite_exec_rows(&sqlite_conn, "PRAGMA integrity_check"),
+        expected
+    );
+    let query = "SELECT count(*) FROM t WHERE id % 3 = 0 OR s > 'k5' OR b < X'40'";
+    assert_eq!(
+        sqlite_exec_rows(&sqlite_conn, query),
+        vec![vec![Value::Integer(0)]]
+    );
+    let query = "SELECT id, s, b FROM t ORDER BY id";
+    assert_eq!(
+        limbo_exec_rows(&tmp_db, &conn, query),
+        sqlite_exec_rows(&sqlite_conn, query)
+    );
+    Ok(())
+}
+
 #[test]
 fn test_update_regression() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE imaginative_baroja (blithesome_hall BLOB,remarkable_lester INTEGER,generous_balagun TEXT,ample_earth INTEGER,marvelous_khadzhiev BLOB,glowing_parissi TEXT,insightful_ryner BLOB)", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE imaginative_baroja (blithesome_hall BLOB,remarkable_lester INTEGER,generous_balagun TEXT,ample_earth INTEGER,marvelous_khadzhiev BLOB,glowing_parissi TEXT,insightful_ryner BLOB)");
     let conn = tmp_db.connect_limbo();
 
     conn.execute("INSERT INTO imaginative_baroja VALUES (X'617070726F61636861626C655F6F6D6164', 5581285929211692372, 'approachable_podur', -4145754929970306534, X'666F72747569746F75735F7368617270', 'sensible_amesly', X'636F6D70657469746976655F6669746368'), (X'6D6972746866756C5F686F6673746565', -8554670009677647372, 'shimmering_modkraftdk', 4993627046425025026, X'636F6E73696465726174655F63616765', 'breathtaking_boggs', X'616D617A696E675F73696D6F6E65'), (X'7669766163696F75735F7363687761727A', 5860599187854155616, 'sparkling_aurora', 3757552048117668067, X'756E697175655F6769617A', 'lovely_leroy', X'68617264776F726B696E675F6D696C6C6572'), (X'677265676172696F75735F7061657065', -488992130149088413, 'focused_brinker', 4503849242092922100, X'66756E6E795F6A616B736963', 'competitive_communications', X'657863656C6C656E745F7873696C656E74'), (X'7374756E6E696E675F74616E6E656E6261756D', -5634782647279946253, 'fabulous_crute', -3978009805517476564, X'72656C617865645F63617272796F7574', 'spellbinding_erkan', X'66756E6E795F646F626273'), (X'696D6167696E61746976655F746F6C6F6B6F6E6E696B6F7661', 4236471363502323025, 'excellent_wolke', 7606168469334609395, X'736C65656B5F6D6361666565', 'magnificent_riley', X'616D6961626C655F706173736164616B6973'), (X'77696C6C696E675F736872657665', 5048296470820985219, 'ambitious_jeppesen', 6961857167361512834, X'70617469656E745F6272696E6B6572', 'giving_kramm', X'726573706F6E7369626C655F7363686D696474'), (X'73656E7369626C655F6D757865726573', -5519194136843846790, 'frank_ruggero', 4354855935194921345, X'76697669645F63617365', 'focused_lovecruft', X'6D61676E69666963656E745F736B79')")?;
@@ -507,7 +556,7 @@ fn test_update_regression() -> anyhow::Result<()> {
 #[test]
 fn test_multiple_statements() -> anyhow::Result<()> {
     let _ = env_logger::try_init();
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x)", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x)");
     let conn = tmp_db.connect_limbo();
 
     conn.execute("INSERT INTO t values(1); insert into t values(2);")?;
@@ -601,7 +650,7 @@ fn test_write_concurrent_connections() -> anyhow::Result<()> {
 
     maybe_setup_tracing();
 
-    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x)", false);
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(x)");
     let num_connections = 4;
     let num_inserts_per_connection = 100;
     let mut connections = vec![];
@@ -652,7 +701,7 @@ fn test_wal_bad_frame() -> anyhow::Result<()> {
     maybe_setup_tracing();
     let _ = env_logger::try_init();
     let db_path = {
-        let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t1(x)", false);
+        let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t1(x)");
         let db_path = tmp_db.path.clone();
         let conn = tmp_db.connect_limbo();
         conn.execute("BEGIN")?;
@@ -703,7 +752,7 @@ fn test_wal_bad_frame() -> anyhow::Result<()> {
                 db_path.to_str().unwrap(),
                 turso_core::OpenFlags::default(),
                 false,
-                false,
+                true,
             )
             .unwrap();
             let tmp_db = TempDatabase {
@@ -743,20 +792,20 @@ fn test_read_wal_dumb_no_frames() -> anyhow::Result<()> {
     maybe_setup_tracing();
     let _ = env_logger::try_init();
     let db_path = {
-        let tmp_db = TempDatabase::new_empty(false);
+        let tmp_db = TempDatabase::new_empty();
         let conn = tmp_db.connect_limbo();
         conn.close()?;
         tmp_db.path.clone()
     };
     // Second connection must recover from the WAL file. Last checksum should be filled correctly.
     {
-        let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+        let tmp_db = TempDatabase::new_with_existent(&db_path);
         let conn = tmp_db.connect_limbo();
         conn.execute("CREATE TABLE t0(x)")?;
         conn.close()?;
     }
     {
-        let tmp_db = TempDatabase::new_with_existent(&db_path, false);
+        let tmp_db = TempDatabase::new_with_existent(&db_path);
         let conn = tmp_db.connect_limbo();
         conn.execute("INSERT INTO t0(x) VALUES (1)")?;
         conn.close()?;
@@ -765,6 +814,92 @@ fn test_read_wal_dumb_no_frames() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_abort_in_transaction_rolls_back_statement() -> anyhow::Result<()> {
+    use rusqlite::types::Value;
+
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE t(id INTEGER PRIMARY KEY, x UNIQUE)");
+    let conn = tmp_db.connect_limbo();
+
+    conn.execute("BEGIN")?;
+    conn.execute("INSERT INTO t VALUES (1, 'a')")?;
+    // The second row fails the statement, whose first row is rolled back
+    assert!(
+        limbo_exec_rows_error(&tmp_db, &conn, "INSERT INTO t VALUES (2, 'b'), (3, 'a')").is_err()
+    );
+    assert!(limbo_exec_rows_error(
+        &tmp_db,
+        &conn,
+        "UPDATE t SET x = 'c' WHERE id = 1 OR x = 'c'"
+    )
+    .is_ok());
+    assert!(limbo_exec_rows_error(
+        &tmp_db,
+        &conn,
+        "INSERT OR ABORT INTO t VALUES (4, 'd'), (5, 'c')"
+    )
+    .is_err());
+    conn.execute("INSERT INTO t VALUES (6, 'e')")?;
+    conn.execute("COMMIT")?;
+
+    let expected = vec![
+        vec![Value::Integer(1), Value::Text("c".to_string())],
+        vec![Value::Integer(6), Value::Text("e".to_string())],
+    ];
+    assert_eq!(limbo_exec_rows(&tmp_db, &conn, "SELECT * FROM t"), expected);
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path)?;
+    assert_eq!(sqlite_exec_rows(&sqlite_conn, "SELECT * FROM t"), expected);
+    Ok(())
+}
+
+#[test]
+fn test_fail_in_transaction_keeps_previous_rows() -> anyhow::Result<()> {
+    use rusqlite::types::Value;
+
+    let tmp_db =
+        TempDatabase::new_with_rusqlite("CREATE TABLE t(id INTEGER PRIMARY KEY, x UNIQUE)");
+    let conn = tmp_db.connect_limbo();
+
+    conn.execute("BEGIN")?;
+    conn.execute("INSERT INTO t VALUES (1, 'a')")?;
+    // The rows inserted before the failing one are kept, the ones after it are not inserted
+    assert!(limbo_exec_rows_error(
+        &tmp_db,
+        &conn,
+        "INSERT OR FAIL INTO t VALUES (2, 'b'), (3, 'a'), (4, 'c')"
+    )
+    .is_err());
+    conn.execute("INSERT INTO t VALUES (5, 'd')")?;
+    conn.execute("COMMIT")?;
+
+    let expected = vec![
+        vec![Value::Integer(1), Value::Text("a".to_string())],
+        vec![Value::Integer(2), Value::Text("b".to_string())],
+        vec![Value::Integer(5), Value::Text("d".to_string())],
+    ];
+    assert_eq!(limbo_exec_rows(&tmp_db, &conn, "SELECT * FROM t"), expected);
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path)?;
+    assert_eq!(sqlite_exec_rows(&sqlite_conn, "SELECT * FROM t"), expected);
+    Ok(())
+}
+
+#[test]
+fn test_create_table_as_select_in_multiple_statements() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t(a)");
+    let conn = tmp_db.connect_limbo();
+
+    // The expression columns are named from the text of the statement that creates the table
+    conn.execute("SELECT a+1, a FROM t; CREATE TABLE c AS SELECT a*2, a FROM t;")?;
+
+    let mut names = Vec::new();
+    run_query_on_row(&tmp_db, &conn, "PRAGMA table_info(c)", |row| {
+        names.push(row.get::<String>(1).unwrap());
+    })?;
+    assert_eq!(names, ["a*2", "a"]);
+    Ok(())
+}
+
 fn run_query(tmp_db: &TempDatabase, conn: &Arc<Connection>, query: &str) -> anyhow::Result<()> {
     run_query_core(tmp_db, conn, query, None::<fn(&Row)>)
 }
@@ -774,12 +774,14 @@ impl ToTokens for Expr {
                 }
                 s.append(TK_RP, None)
             }
+            Self::Register(_) => Ok(()),
             Self::RowId { .. } => Ok(()),
             Self::Subquery(query) => {
                 s.append(TK_LP, None)?;
                 query.to_tokens(s)?;
                 s.append(TK_RP, None)
             }
+            Self::SubqueryResult { expr, .. } => expr.to_tokens(s),
             Self::Unary(op, sub_expr) => {
                 op.to_tokens(s)?;
                 sub_expr.to_tokens(s)
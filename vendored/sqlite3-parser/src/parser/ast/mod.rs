@@ -472,8 +472,17 @@ pub enum Expr {
     Qualified(Name, Name),
     /// `RAISE` function call
     Raise(ResolveType, Option<Box<Expr>>),
+    /// Value already loaded into a VDBE register (e.g. a trigger's `NEW.x` / `OLD.x`)
+    Register(usize),
     /// Subquery expression
     Subquery(Box<Select>),
+    /// `EXISTS`, `IN` subselect or subquery expression whose subquery was already planned
+    SubqueryResult {
+        /// id of the plan of the subquery
+        subquery_id: TableInternalId,
+        /// the subquery expression
+        expr: Box<Expr>,
+    },
     /// Unary expression
     Unary(UnaryOperator, Box<Expr>),
     /// Parameters
@@ -899,6 +908,15 @@ pub struct FromClause {
     op: Option<JoinOperator>, // FIXME transient
 }
 impl FromClause {
+    /// `FROM` clause of a single table
+    pub fn single(table: SelectTable) -> Self {
+        Self {
+            select: Some(Box::new(table)),
+            joins: None,
+            op: None,
+        }
+    }
+
     pub(crate) fn empty() -> Self {
         Self {
             select: None,
@@ -1429,6 +1447,11 @@ impl ColumnDefinition {
             }
         }
         for constraint in &cd.constraints {
+            if let ColumnConstraint::Generated { typ: Some(typ), .. } = &constraint.constraint {
+                if !typ.0.eq_ignore_ascii_case("VIRTUAL") && !typ.0.eq_ignore_ascii_case("STORED") {
+                    return Err(custom_err!("error in generated column \"{}\"", col_name));
+                }
+            }
             if let ColumnConstraint::ForeignKey {
                 clause:
                     ForeignKeyClause {
@@ -1744,8 +1767,20 @@ pub type PragmaValue = Expr; // TODO
 pub enum PragmaName {
     /// set the autovacuum mode
     AutoVacuum,
+    /// Enable or disable automatic indexes.
+    AutomaticIndex,
     /// `cache_size` pragma
     CacheSize,
+    /// Returns the databases attached to the connection.
+    DatabaseList,
+    /// Returns the rows that violate a foreign key constraint.
+    ForeignKeyCheck,
+    /// Returns the foreign key constraints of a table.
+    ForeignKeyList,
+    /// Enable or disable the enforcement of foreign key constraints.
+    ForeignKeys,
+    /// Enable or disable the enforcement of CHECK constraints.
+    IgnoreCheckConstraints,
     /// Run integrity check on the database file
     IntegrityCheck,
     /// `journal_mode` pragma
@@ -1756,10 +1791,14 @@ pub enum PragmaName {
     PageCount,
     /// Return the page size of the database in bytes.
     PageSize,
+    /// Enable or disable recursive trigger firing.
+    RecursiveTriggers,
     /// Returns schema version of the database file.
     SchemaVersion,
     /// returns information about the columns of a table
     TableInfo,
+    /// returns information about the columns of a table, including hidden and generated ones
+    TableXinfo,
     /// enable capture-changes logic for the connection
     UnstableCaptureDataChangesConn,
     /// Returns the user version of the database file.
@@ -254,11 +254,17 @@ impl ToSqlString for Expr {
                 }
                 ret.push(')');
             }
+            Expr::Register(reg) => {
+                ret.push_str(&format!("r[{reg}]"));
+            }
             Expr::Subquery(select) => {
                 ret.push('(');
                 ret.push_str(&select.to_sql_string(context));
                 ret.push(')');
             }
+            Expr::SubqueryResult { expr, .. } => {
+                ret.push_str(&expr.to_sql_string(context));
+            }
             Expr::Unary(unary_operator, expr) => {
                 ret.push_str(&unary_operator.to_string());
                 ret.push(' ');